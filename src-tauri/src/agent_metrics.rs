@@ -0,0 +1,251 @@
+//! Local-only metrics about agent requests: which intent was detected, whether the local
+//! parser or the LLM handled it, parse and validation failures, whether the user accepted
+//! the proposal, and latency. Kept in a capped file under app data so the diagnostics page
+//! can show which intents fail most. Nothing here is ever uploaded.
+
+use anyhow::{Context, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+use crate::ai_agent::AgentResponse;
+use crate::longterm_storage;
+
+/// Oldest records are dropped past this many
+const MAX_RECORDS: usize = 1000;
+
+/// Serializes read-modify-write of the metrics file
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Handler {
+    Local, // One of the local command parsers (selection, export, drift, dead air)
+    Llm,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Answered,
+    ParseFailure, // The LLM reply wasn't valid JSON
+    Error,        // Any other failure (no API key, network, quota)
+}
+
+/// One agent request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentRequestRecord {
+    pub message_id: String,
+    pub created_at: String, // ISO 8601
+    pub intent: String,
+    pub handler: Handler,
+    pub outcome: Outcome,
+    pub proposed_operations: usize,
+    pub adjusted_operations: usize, // Proposed operations that failed validation and were clamped
+    pub latency_ms: u64,
+    #[serde(default)]
+    pub accepted: Option<bool>, // None until the user accepts or rejects the proposal
+}
+
+/// Aggregates for one intent
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IntentMetrics {
+    pub intent: String,
+    pub requests: usize,
+    pub local: usize,
+    pub llm: usize,
+    pub parse_failures: usize,
+    pub errors: usize,
+    pub validation_failures: usize, // Requests with at least one adjusted operation
+    pub accepted: usize,
+    pub rejected: usize,
+    pub avg_latency_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentMetrics {
+    pub enabled: bool,
+    pub requests: usize,
+    pub intents: Vec<IntentMetrics>, // Most failures first
+}
+
+fn metrics_path() -> Result<PathBuf> {
+    Ok(longterm_storage::get_lts_directory()?.join("agent_metrics.json"))
+}
+
+fn enabled() -> bool {
+    longterm_storage::get_settings().map(|s| !s.agent_metrics_disabled).unwrap_or(true)
+}
+
+fn read_records() -> Result<Vec<AgentRequestRecord>> {
+    let path = metrics_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&data).with_context(|| "Failed to parse agent metrics")
+}
+
+fn write_records(records: &[AgentRequestRecord]) -> Result<()> {
+    let path = metrics_path()?;
+    let data = serde_json::to_string(records).with_context(|| "Failed to serialize agent metrics")?;
+    fs::write(&path, data).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Coarse intent of a chat message, for grouping metrics
+pub fn detect_intent(message: &str) -> &'static str {
+    let lower = message.to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|w| lower.contains(w));
+    if has(&["export", "render"]) {
+        "export"
+    } else if has(&["drift", "out of sync", "lip sync"]) {
+        "sync"
+    } else if has(&["dead air", "filler", " um"]) {
+        "dead_air"
+    } else if has(&["tighten"]) {
+        "tighten"
+    } else if has(&["silence", "silent", "pause"]) {
+        "silence"
+    } else if has(&["split"]) {
+        "split"
+    } else if has(&["cut", "remove", "delete", "trim"]) {
+        "cut"
+    } else if has(&["detect", "find"]) {
+        "detect"
+    } else if has(&["?", "how", "what", "why"]) {
+        "question"
+    } else {
+        "general"
+    }
+}
+
+/// Record a finished agent request. Does nothing when collection is disabled; failures are
+/// logged, never surfaced to the chat
+pub fn record(message_id: &str, message: &str, handler: Handler, started: Instant, result: Result<&AgentResponse, &str>) {
+    if !enabled() {
+        return;
+    }
+    let (outcome, proposed, adjusted) = match result {
+        Ok(response) => {
+            let operations = response.final_edits.iter().chain(&response.needs_review);
+            let adjusted = operations.clone().filter(|op| op.parameters.contains_key("clamped_from")).count();
+            (Outcome::Answered, operations.count(), adjusted)
+        }
+        Err(e) if e.contains("Failed to parse AI response as JSON") => (Outcome::ParseFailure, 0, 0),
+        Err(_) => (Outcome::Error, 0, 0),
+    };
+    let record = AgentRequestRecord {
+        message_id: message_id.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        intent: detect_intent(message).to_string(),
+        handler,
+        outcome,
+        proposed_operations: proposed,
+        adjusted_operations: adjusted,
+        latency_ms: started.elapsed().as_millis() as u64,
+        accepted: None,
+    };
+
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let result = read_records().and_then(|mut records| {
+        records.push(record);
+        let excess = records.len().saturating_sub(MAX_RECORDS);
+        records.drain(..excess);
+        write_records(&records)
+    });
+    if let Err(e) = result {
+        log::warn!("Failed to record agent metrics: {}", e);
+    }
+}
+
+/// Mark whether the user accepted the proposal of `message_id`, or of the newest undecided
+/// request with a proposal when no id is known
+pub fn record_decision(message_id: Option<&str>, accepted: bool) -> Result<()> {
+    if !enabled() {
+        return Ok(());
+    }
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut records = read_records()?;
+    let record = match message_id {
+        Some(id) => records.iter_mut().rev().find(|r| r.message_id == id),
+        None => records.iter_mut().rev().find(|r| r.accepted.is_none() && r.proposed_operations > 0),
+    };
+    if let Some(record) = record {
+        record.accepted = Some(accepted);
+        write_records(&records)?;
+    }
+    Ok(())
+}
+
+/// Aggregate counts per intent over the last `window_secs` seconds (everything kept when None)
+pub fn get_metrics(window_secs: Option<u64>) -> Result<AgentMetrics> {
+    let records = {
+        let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        read_records()?
+    };
+    let since = window_secs.map(|secs| chrono::Utc::now() - chrono::Duration::seconds(secs as i64));
+    let in_window = records.iter().filter(|r| match since {
+        None => true,
+        Some(since) => chrono::DateTime::parse_from_rfc3339(&r.created_at).map(|t| t >= since).unwrap_or(false),
+    });
+
+    let mut by_intent: HashMap<&str, (IntentMetrics, u64)> = HashMap::new();
+    let mut requests = 0;
+    for record in in_window {
+        requests += 1;
+        let (metrics, total_latency) = by_intent.entry(record.intent.as_str()).or_insert_with(|| {
+            (IntentMetrics { intent: record.intent.clone(), ..Default::default() }, 0)
+        });
+        metrics.requests += 1;
+        match record.handler {
+            Handler::Local => metrics.local += 1,
+            Handler::Llm => metrics.llm += 1,
+        }
+        match record.outcome {
+            Outcome::Answered => {}
+            Outcome::ParseFailure => metrics.parse_failures += 1,
+            Outcome::Error => metrics.errors += 1,
+        }
+        if record.adjusted_operations > 0 {
+            metrics.validation_failures += 1;
+        }
+        match record.accepted {
+            Some(true) => metrics.accepted += 1,
+            Some(false) => metrics.rejected += 1,
+            None => {}
+        }
+        *total_latency += record.latency_ms;
+    }
+
+    let failures = |m: &IntentMetrics| m.parse_failures + m.errors + m.validation_failures + m.rejected;
+    let mut intents: Vec<IntentMetrics> = by_intent.into_values().map(|(mut metrics, total_latency)| {
+        metrics.avg_latency_ms = total_latency / metrics.requests as u64;
+        metrics
+    }).collect();
+    intents.sort_by(|a, b| failures(b).cmp(&failures(a)).then(b.requests.cmp(&a.requests)));
+
+    Ok(AgentMetrics { enabled: enabled(), requests, intents })
+}
+
+/// Delete all recorded metrics
+pub fn clear() -> Result<()> {
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = metrics_path()?;
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("Failed to delete {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Turn collection on or off. Turning it off also deletes what was collected
+pub fn set_enabled(enabled: bool) -> Result<()> {
+    let mut settings = longterm_storage::get_settings()?;
+    settings.agent_metrics_disabled = !enabled;
+    longterm_storage::set_settings(settings)?;
+    if !enabled {
+        clear()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::longterm_storage;
+
+/// Used when the user hasn't configured a limit (Gemini free tier is ~10-15 RPM)
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 10;
+/// How often a queued request re-checks for quota
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Pause after a 429 when the response doesn't say how long to wait
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(20);
+/// A request hitting 429 is re-queued this many times before the error is returned
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Who is waiting on the quota. Lower variants are served first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiPriority {
+    Interactive, // Chat and anything the user is actively waiting on
+    Analysis,    // Video analysis uploads
+    Background,  // Chat naming and other jobs nobody is watching
+}
+
+struct Waiter {
+    id: u64,
+    priority: ApiPriority,
+    label: String,
+    queued_at: Instant,
+}
+
+struct Scheduler {
+    granted: VecDeque<Instant>, // Grants in the last minute, oldest first
+    waiting: Vec<Waiter>,
+    blocked_until: Option<Instant>, // Set after a 429; nothing is granted before it
+    next_id: u64,
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler {
+    granted: VecDeque::new(),
+    waiting: Vec::new(),
+    blocked_until: None,
+    next_id: 0,
+});
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedRequest {
+    pub priority: ApiPriority,
+    pub label: String, // e.g. "video analysis: intro.mp4"
+    pub waiting_seconds: f64,
+}
+
+/// Snapshot for the UI, e.g. "2 analyses waiting for quota"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiQueueStatus {
+    pub requests_per_minute: u32,
+    pub used_last_minute: u32,
+    pub waiting: Vec<QueuedRequest>, // In the order they will be served
+    pub blocked_for_seconds: Option<f64>, // Remaining back-off after a 429
+}
+
+fn requests_per_minute() -> u32 {
+    longterm_storage::get_settings()
+        .ok()
+        .and_then(|s| s.gemini_requests_per_minute)
+        .filter(|rpm| *rpm > 0)
+        .unwrap_or(DEFAULT_REQUESTS_PER_MINUTE)
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Scheduler> {
+    SCHEDULER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Scheduler {
+    fn prune(&mut self, now: Instant) {
+        while self.granted.front().is_some_and(|t| now.duration_since(*t) >= WINDOW) {
+            self.granted.pop_front();
+        }
+        if self.blocked_until.is_some_and(|until| until <= now) {
+            self.blocked_until = None;
+        }
+    }
+
+    /// Waiters in service order: priority, then first come first served
+    fn ordered(&self) -> Vec<&Waiter> {
+        let mut waiting: Vec<&Waiter> = self.waiting.iter().collect();
+        waiting.sort_by_key(|w| (w.priority, w.queued_at, w.id));
+        waiting
+    }
+}
+
+/// Removes the waiter if the request future is dropped while queued
+struct QueueTicket(u64);
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        lock().waiting.retain(|w| w.id != self.0);
+    }
+}
+
+/// Wait until a request of `priority` may be sent. Higher-priority requests queued later
+/// still go first, so lower-priority work is delayed rather than eating the chat's quota
+pub async fn acquire(priority: ApiPriority, label: &str) {
+    let ticket = {
+        let mut scheduler = lock();
+        let id = scheduler.next_id;
+        scheduler.next_id += 1;
+        scheduler.waiting.push(Waiter { id, priority, label: label.to_string(), queued_at: Instant::now() });
+        QueueTicket(id)
+    };
+
+    let limit = requests_per_minute() as usize;
+    loop {
+        {
+            let mut scheduler = lock();
+            let now = Instant::now();
+            scheduler.prune(now);
+            let is_next = scheduler.ordered().first().is_some_and(|w| w.id == ticket.0);
+            if is_next && scheduler.blocked_until.is_none() && scheduler.granted.len() < limit {
+                scheduler.granted.push_back(now);
+                // Ticket drop removes the waiter
+                break;
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Record a 429 so every queued request backs off, not just the one that failed
+pub fn report_rate_limited(retry_after: Option<Duration>) {
+    let until = Instant::now() + retry_after.unwrap_or(DEFAULT_BACKOFF);
+    let mut scheduler = lock();
+    if scheduler.blocked_until.map_or(true, |current| current < until) {
+        scheduler.blocked_until = Some(until);
+    }
+}
+
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Send a Gemini-bound request through the scheduler. `build` is called again for each
+/// attempt; 429 responses back off the whole queue and the request is re-queued
+pub async fn send<F>(priority: ApiPriority, label: &str, build: F) -> reqwest::Result<reqwest::Response>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        acquire(priority, label).await;
+        let response = build().send().await?;
+        if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS || attempt >= MAX_RATE_LIMIT_RETRIES {
+            return Ok(response);
+        }
+        attempt += 1;
+        log::warn!("Gemini rate limited ({}), retry {} of {}", label, attempt, MAX_RATE_LIMIT_RETRIES);
+        report_rate_limited(retry_after(&response));
+    }
+}
+
+pub fn get_api_queue_status() -> ApiQueueStatus {
+    let requests_per_minute = requests_per_minute();
+    let mut scheduler = lock();
+    let now = Instant::now();
+    scheduler.prune(now);
+    ApiQueueStatus {
+        requests_per_minute,
+        used_last_minute: scheduler.granted.len() as u32,
+        waiting: scheduler.ordered().into_iter()
+            .map(|w| QueuedRequest {
+                priority: w.priority,
+                label: w.label.clone(),
+                waiting_seconds: now.duration_since(w.queued_at).as_secs_f64(),
+            })
+            .collect(),
+        blocked_for_seconds: scheduler.blocked_until.map(|until| until.duration_since(now).as_secs_f64()),
+    }
+}
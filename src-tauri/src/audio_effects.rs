@@ -0,0 +1,195 @@
+//! Track-level audio effects (compressor, gate, high-pass, notch, de-esser) and the order audio
+//! processing runs in. Every audio chain is built through `audio_chain` so previews, exports
+//! and bakes agree on it:
+//!
+//!   segment gain → segment effects → track effects → track volume → ducking
+//!
+//! A track's compressor and gate should react to the level a segment actually plays at, so
+//! segment processing comes first; volume is the fader after the track's processing, and
+//! ducking rides on top of the final level. Segment effects are all video so far and ducking
+//! isn't implemented, so those stages are empty today. Pan is stereo placement, applied after
+//! the chain by callers.
+
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use crate::project_file::Effect;
+
+/// Effect types a track accepts
+pub const TRACK_EFFECT_TYPES: [&str; 5] = ["acompressor", "agate", "highpass", "anequalizer", "deesser"];
+
+/// Built-in presets `update_track` can apply by name
+pub const PRESET_NAMES: [&str; 2] = ["Podcast voice", "Clean up room noise"];
+
+/// Parameter name, ffmpeg option, allowed range, default, and unit suffix for ffmpeg.
+/// Levels are given in dB; ffmpeg's "dB" suffix converts them to the linear values it expects
+type Param = (&'static str, &'static str, (f64, f64), f64, &'static str);
+
+fn params(effect_type: &str) -> Option<&'static [Param]> {
+  let params: &'static [Param] = match effect_type {
+    "acompressor" => &[
+      ("threshold", "threshold", (-60.0, 0.0), -18.0, "dB"),
+      ("ratio", "ratio", (1.0, 20.0), 3.0, ""),
+      ("attack", "attack", (0.01, 2000.0), 20.0, ""), // ms
+      ("release", "release", (0.01, 9000.0), 250.0, ""), // ms
+      ("makeup", "makeup", (0.0, 24.0), 0.0, "dB"),
+    ],
+    "agate" => &[
+      ("threshold", "threshold", (-80.0, 0.0), -40.0, "dB"),
+      ("ratio", "ratio", (1.0, 9000.0), 2.0, ""),
+      ("attack", "attack", (0.01, 9000.0), 20.0, ""), // ms
+      ("release", "release", (0.01, 9000.0), 250.0, ""), // ms
+      ("range", "range", (-90.0, 0.0), -18.0, "dB"), // How far a closed gate attenuates
+    ],
+    "highpass" => &[("frequency", "f", (20.0, 2000.0), 80.0, "")],
+    // A single band, e.g. a notch on mains hum; see `anequalizer_filter`
+    "anequalizer" => &[
+      ("frequency", "f", (20.0, 20000.0), 60.0, ""),
+      ("width", "w", (1.0, 1000.0), 10.0, ""), // Hz
+      ("gain", "g", (-60.0, 12.0), -30.0, ""), // dB
+    ],
+    "deesser" => &[
+      ("intensity", "i", (0.0, 1.0), 0.0, ""),
+      ("max_reduction", "m", (0.0, 1.0), 0.5, ""),
+      ("frequency", "f", (0.0, 1.0), 0.5, ""), // Share of the Nyquist frequency
+    ],
+    _ => return None,
+  };
+  Some(params)
+}
+
+/// Check that `effect` is a track effect with known, in-range numeric parameters
+pub fn validate(effect: &Effect) -> Result<()> {
+  effect_filter(effect).map(|_| ())
+}
+
+/// ffmpeg filter for one track effect; missing parameters take their defaults
+pub fn effect_filter(effect: &Effect) -> Result<String> {
+  let params = params(&effect.r#type).ok_or_else(|| {
+    anyhow!("'{}' is not a track effect (expected one of {})", effect.r#type, TRACK_EFFECT_TYPES.join(", "))
+  })?;
+  if let Some(unknown) = effect.parameters.keys().find(|k| !params.iter().any(|p| p.0 == k.as_str())) {
+    return Err(anyhow!("effect '{}' has no parameter '{}'", effect.r#type, unknown));
+  }
+
+  let mut options = Vec::new();
+  for (name, option, (min, max), default, unit) in params {
+    let value = match effect.parameters.get(*name) {
+      None => *default,
+      Some(value) => value
+        .as_f64()
+        .ok_or_else(|| anyhow!("effect '{}' parameter '{}' must be a number", effect.r#type, name))?,
+    };
+    if !(*min..=*max).contains(&value) {
+      return Err(anyhow!("effect '{}' parameter '{}' = {} is outside {}..{}", effect.r#type, name, value, min, max));
+    }
+    options.push(format!("{}={}{}", option, value, unit));
+  }
+  if effect.r#type == "anequalizer" {
+    return Ok(anequalizer_filter(&options.join(" ")));
+  }
+  Ok(format!("{}={}", effect.r#type, options.join(":")))
+}
+
+/// anequalizer takes its band per channel ("c0 f=60 w=10 g=-30 t=0|c1 ..."); the band goes
+/// on the first two, which covers mono and stereo sources
+fn anequalizer_filter(band: &str) -> String {
+  format!("anequalizer=c0 {} t=0|c1 {} t=0", band, band)
+}
+
+/// Filters for one segment's audio in mix order (see the module docs). `track_volume` is
+/// None where the volume is applied elsewhere (the player's mixer) or not at all (exports)
+pub fn audio_chain(segment_gain: f64, track_effects: &[Effect], track_volume: Option<u8>) -> Result<Vec<String>> {
+  let mut chain = Vec::new();
+  if segment_gain != 0.0 {
+    chain.push(format!("volume={}dB", segment_gain));
+  }
+  for effect in track_effects {
+    chain.push(effect_filter(effect)?);
+  }
+  if let Some(volume) = track_volume.filter(|v| *v != 100) {
+    chain.push(format!("volume={:.2}", f64::from(volume) / 100.0));
+  }
+  Ok(chain)
+}
+
+fn effect(r#type: &str, parameters: serde_json::Value) -> Effect {
+  let parameters: HashMap<String, serde_json::Value> = serde_json::from_value(parameters).unwrap_or_default();
+  Effect { r#type: r#type.to_string(), parameters }
+}
+
+/// Effects of the built-in preset `name` (case-insensitive)
+pub fn preset(name: &str) -> Option<Vec<Effect>> {
+  match name.trim().to_lowercase().as_str() {
+    // Rumble out, gentle leveling, tamed sibilance
+    "podcast voice" => Some(vec![
+      effect("highpass", json!({ "frequency": 80.0 })),
+      effect("acompressor", json!({ "threshold": -18.0, "ratio": 3.0, "attack": 10.0, "release": 200.0, "makeup": 4.0 })),
+      effect("deesser", json!({ "intensity": 0.4 })),
+    ]),
+    // Cut low hum, then pull the room tone down between phrases
+    "clean up room noise" => Some(vec![
+      effect("highpass", json!({ "frequency": 100.0 })),
+      effect("agate", json!({ "threshold": -45.0, "ratio": 4.0, "attack": 5.0, "release": 250.0, "range": -30.0 })),
+    ]),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn the_chain_runs_gain_then_track_effects_then_volume() {
+    let effects = [effect("highpass", json!({ "frequency": 100.0 })), effect("acompressor", json!({}))];
+    assert_eq!(
+      audio_chain(-3.0, &effects, Some(80)).unwrap(),
+      vec![
+        "volume=-3dB",
+        "highpass=f=100",
+        "acompressor=threshold=-18dB:ratio=3:attack=20:release=250:makeup=0dB",
+        "volume=0.80",
+      ]
+    );
+  }
+
+  #[test]
+  fn neutral_stages_are_left_out() {
+    assert!(audio_chain(0.0, &[], Some(100)).unwrap().is_empty());
+    assert!(audio_chain(0.0, &[], None).unwrap().is_empty());
+    assert_eq!(audio_chain(0.0, &[], Some(50)).unwrap(), vec!["volume=0.50"]);
+  }
+
+  #[test]
+  fn notch_bands_go_on_both_channels() {
+    assert_eq!(
+      effect_filter(&effect("anequalizer", json!({}))).unwrap(),
+      "anequalizer=c0 f=60 w=10 g=-30 t=0|c1 f=60 w=10 g=-30 t=0"
+    );
+  }
+
+  #[test]
+  fn invalid_effects_are_rejected() {
+    assert!(validate(&effect("reverb", json!({})))
+      .unwrap_err()
+      .to_string()
+      .contains("not a track effect"));
+    assert!(validate(&effect("agate", json!({ "knee": 2.0 }))).is_err());
+    assert!(validate(&effect("highpass", json!({ "frequency": 5.0 }))).is_err());
+    assert!(validate(&effect("deesser", json!({ "intensity": "high" }))).is_err());
+    // A bad effect anywhere fails the whole chain rather than being skipped
+    assert!(audio_chain(0.0, &[effect("highpass", json!({})), effect("reverb", json!({}))], None).is_err());
+  }
+
+  #[test]
+  fn built_in_presets_are_valid_and_found_by_any_case() {
+    for name in PRESET_NAMES {
+      let effects = preset(name).unwrap();
+      assert!(!effects.is_empty());
+      assert!(effects.iter().all(|e| validate(e).is_ok()), "{}", name);
+      assert_eq!(preset(&name.to_uppercase()), Some(effects));
+    }
+    assert_eq!(preset("Radio voice"), None);
+  }
+}
@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::ffmpeg::{self, Cut};
+use crate::jobs;
+
+/// --- Public Types ------------------------------------------------------------------
+
+/// Output frame size presets for social platforms.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AudiogramPreset {
+  Square,   // 1080x1080
+  Vertical, // 1080x1920
+}
+
+impl AudiogramPreset {
+  fn size(&self) -> (u32, u32) {
+    match self {
+      AudiogramPreset::Square => (1080, 1080),
+      AudiogramPreset::Vertical => (1080, 1920),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum WaveformStyle {
+  Bars, // Frequency bars (showfreqs)
+  Line, // Centered waveform line (showwaves)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AudiogramStyle {
+  pub preset: AudiogramPreset,
+  pub waveform: WaveformStyle,
+  #[serde(default = "default_wave_color")]
+  pub wave_color: String,       // "#rrggbb"
+  #[serde(default = "default_background_color")]
+  pub background_color: String, // Used when there is no background image
+  #[serde(default)]
+  pub title: Option<String>,
+  #[serde(default = "default_wave_color")]
+  pub title_color: String,
+}
+
+fn default_wave_color() -> String {
+  "#ffffff".to_string()
+}
+
+fn default_background_color() -> String {
+  "#111111".to_string()
+}
+
+const AUDIOGRAM_FPS: u32 = 30;
+
+/// --- Helpers -----------------------------------------------------------------------
+
+/// "#rrggbb" → "0xrrggbb" for ffmpeg color options.
+fn ffmpeg_color(hex: &str) -> Result<String> {
+  let digits = hex.trim().trim_start_matches('#');
+  if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(anyhow!("invalid color '{}', expected #rrggbb", hex));
+  }
+  Ok(format!("0x{}", digits))
+}
+
+/// Parts of `range` that survive `cuts` (both in clip seconds).
+fn kept_in_range(range: Cut, cuts: &[Cut]) -> Vec<Cut> {
+  let (start, end) = range;
+  let relative: Vec<Cut> = cuts
+    .iter()
+    .map(|(s, e)| (s - start, e - start))
+    .collect();
+  let length = end - start;
+  let normalized = ffmpeg::normalize_cuts(relative, length);
+  ffmpeg::to_kept_segments(&normalized, length)
+    .into_iter()
+    .map(|(s, e)| (s + start, e + start))
+    .collect()
+}
+
+/// --- Audiogram ---------------------------------------------------------------------
+
+/// Render an animated waveform video for `range` of `input`'s `audio_stream`th audio stream,
+/// skipping `cuts` inside the range. `cuts` and `range` are in the input's own time.
+pub fn export_audiogram(
+  input: &str,
+  audio_stream: usize,
+  range: Cut,
+  cuts: &[Cut],
+  style: &AudiogramStyle,
+  background_image: Option<&str>,
+  output: &str,
+) -> Result<()> {
+  let probe = ffmpeg::ffprobe(input).context("ffprobe failed")?;
+  if probe.audio_channels == 0 {
+    return Err(anyhow!("{} has no audio stream", input));
+  }
+  let (start, end) = (range.0.max(0.0), range.1.min(probe.duration));
+  if end - start < 0.1 {
+    return Err(anyhow!("audiogram range {:.3}..{:.3} is empty", range.0, range.1));
+  }
+  let kept = kept_in_range((start, end), cuts);
+  if kept.is_empty() {
+    return Err(anyhow!("All content in the range would be cut out (no kept segments)."));
+  }
+
+  let (width, height) = style.preset.size();
+  let wave_height = height / 3;
+  let wave_color = ffmpeg_color(&style.wave_color)?;
+
+  // Audio: trim to kept pieces and join, then split for output and visualization
+  let mut filter = String::new();
+  for (i, (s, e)) in kept.iter().enumerate() {
+    filter.push_str(&format!("[0:a:{}]atrim=start={}:end={},asetpts=PTS-STARTPTS[a{}];", audio_stream, s, e, i));
+  }
+  let labels: String = (0..kept.len()).map(|i| format!("[a{}]", i)).collect();
+  filter.push_str(&format!("{}concat=n={}:v=0:a=1,asplit=2[outa][viz];", labels, kept.len()));
+
+  let visualizer = match style.waveform {
+    WaveformStyle::Bars => format!(
+      "showfreqs=s={}x{}:mode=bar:ascale=log:fscale=log:colors={},fps={}",
+      width, wave_height, wave_color, AUDIOGRAM_FPS
+    ),
+    WaveformStyle::Line => format!(
+      "showwaves=s={}x{}:mode=cline:rate={}:colors={}",
+      width, wave_height, AUDIOGRAM_FPS, wave_color
+    ),
+  };
+  filter.push_str(&format!("[viz]{}[wave];", visualizer));
+
+  filter.push_str(&format!(
+    "[1:v]scale={w}:{h}:force_original_aspect_ratio=increase,crop={w}:{h},setsar=1,fps={fps}[bg];",
+    w = width, h = height, fps = AUDIOGRAM_FPS
+  ));
+  filter.push_str("[bg][wave]overlay=(W-w)/2:(H-h)/2:shortest=1");
+
+  // Held until ffmpeg has run: a long title is read from a temp file
+  let title = match style.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+    Some(title) => {
+      let text = ffmpeg::drawtext_text(title)?;
+      filter.push_str(&format!(
+        ",drawtext={}:fontcolor={}:fontsize={}:x=(w-text_w)/2:y=h*0.12",
+        text.options,
+        ffmpeg_color(&style.title_color)?,
+        width / 16
+      ));
+      Some(text)
+    }
+    None => None,
+  };
+  filter.push_str(",format=yuv420p[outv]");
+
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-i", input]);
+  match background_image {
+    Some(image) => {
+      cmd.args(["-loop", "1", "-i", image]);
+    }
+    None => {
+      let color = ffmpeg_color(&style.background_color)?;
+      cmd.args(["-f", "lavfi", "-i", &format!("color=c={}:s={}x{}", color, width, height)]);
+    }
+  }
+
+  let tmp = ffmpeg::temp_output_path(Path::new(output));
+  cmd.args([
+    "-filter_complex",
+    &filter,
+    "-map",
+    "[outv]",
+    "-map",
+    "[outa]",
+    "-c:v",
+    "libx264",
+    "-preset",
+    "medium",
+    "-crf",
+    "20",
+    "-c:a",
+    "aac",
+    "-b:a",
+    "192k",
+    "-shortest",
+    "-movflags",
+    "+faststart",
+    "-y",
+    tmp.to_string_lossy().as_ref(),
+  ]);
+  // Registered under the export job, so cancelling it kills ffmpeg
+  let result = jobs::output_cancellable(&mut cmd, None).with_context(|| "failed to spawn ffmpeg for audiogram");
+
+  drop(title);
+  let result = result?;
+  if !result.status.success() {
+    let _ = fs::remove_file(&tmp);
+    return Err(anyhow!(
+      "ffmpeg audiogram failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+
+  fs::rename(&tmp, output).with_context(|| "failed to move tmp output into place")?;
+  Ok(())
+}
@@ -0,0 +1,484 @@
+//! Render-and-replace ("bake"): renders a segment with its speed, audio adjustments and
+//! effects into a new file so playback no longer has to apply them live. Single-segment
+//! exports (`export_segment`) go through the same renderer.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::audio_effects;
+use crate::ffmpeg::{self, ExportSettings, Probe};
+use crate::ids;
+use crate::jobs::JobContext;
+use crate::media_hash;
+use crate::media_protocol::{self, MediaScope};
+use crate::project_file::{self, Clip, ClipType, Effect, Segment};
+
+/// --- Public Types ------------------------------------------------------------------
+
+/// Encode settings for baked media. Intermediates, so quality defaults are high.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BakeSettings {
+  #[serde(default = "default_crf")]
+  pub crf: u8,
+  #[serde(default = "default_preset")]
+  pub preset: String, // x264 preset
+  #[serde(default = "default_audio_bitrate")]
+  pub audio_bitrate: String,
+}
+
+fn default_crf() -> u8 {
+  16
+}
+
+fn default_preset() -> String {
+  "medium".to_string()
+}
+
+fn default_audio_bitrate() -> String {
+  "256k".to_string()
+}
+
+impl Default for BakeSettings {
+  fn default() -> Self {
+    Self {
+      crf: default_crf(),
+      preset: default_preset(),
+      audio_bitrate: default_audio_bitrate(),
+    }
+  }
+}
+
+/// Output durations may differ from the segment by at most this much (about a frame at 24fps).
+const DURATION_TOLERANCE: f64 = 0.042;
+
+/// Cut points this close to a keyframe can be stream-copied without a visible jump.
+const KEYFRAME_TOLERANCE: f64 = 0.042;
+
+/// How an exported segment was produced.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentExportMode {
+  StreamCopy, // Unmodified segment cut on keyframes; packets copied as-is
+  Rendered,
+}
+
+/// Codec and muxer arguments for a render, plus filters applied after the segment's own.
+struct Encode {
+  video: bool, // False for audio-only containers
+  video_args: Vec<String>,
+  audio_args: Vec<String>,
+  muxer_args: Vec<String>,
+  video_filter: Option<String>,
+  audio_filter: Option<String>,
+}
+
+impl Encode {
+  fn bake(settings: &BakeSettings) -> Self {
+    Self {
+      video: true,
+      video_args: vec![
+        "-c:v".into(),
+        "libx264".into(),
+        "-preset".into(),
+        settings.preset.clone(),
+        "-crf".into(),
+        settings.crf.to_string(),
+      ],
+      audio_args: vec!["-c:a".into(), "aac".into(), "-b:a".into(), settings.audio_bitrate.clone()],
+      muxer_args: vec!["-movflags".into(), "+faststart".into()],
+      video_filter: None,
+      audio_filter: None,
+    }
+  }
+
+  fn export(settings: &ExportSettings, probe: &Probe) -> Result<Self> {
+    if settings.normalize_loudness.is_some() {
+      return Err(anyhow!("loudness normalization is only available for cut-list and timeline exports"));
+    }
+    Ok(Self {
+      video: settings.container.has_video(),
+      video_args: settings.video_args(),
+      audio_args: settings.audio_args(),
+      muxer_args: settings.muxer_args().iter().map(|a| a.to_string()).collect(),
+      video_filter: ffmpeg::chain_filters(settings.tonemap_filter(probe), settings.scale_filter()),
+      audio_filter: settings.audio_filter()?, // Track effects and pan
+    })
+  }
+}
+
+/// --- Filters -----------------------------------------------------------------------
+
+fn number_param(effect: &Effect, name: &str, default: f64) -> Result<f64> {
+  match effect.parameters.get(name) {
+    None => Ok(default),
+    Some(value) => value
+      .as_f64()
+      .ok_or_else(|| anyhow!("effect '{}' parameter '{}' must be a number", effect.r#type, name)),
+  }
+}
+
+/// ffmpeg video filter for a segment effect.
+pub(crate) fn effect_filter(effect: &Effect) -> Result<String> {
+  let filter = match effect.r#type.as_str() {
+    "blur" => format!("boxblur={}", number_param(effect, "radius", 5.0)?.max(0.0)),
+    "grayscale" => "hue=s=0".to_string(),
+    "brightness" => format!("eq=brightness={}", number_param(effect, "value", 0.0)?.clamp(-1.0, 1.0)),
+    "contrast" => format!("eq=contrast={}", number_param(effect, "value", 1.0)?.clamp(0.0, 4.0)),
+    "saturation" => format!("eq=saturation={}", number_param(effect, "value", 1.0)?.clamp(0.0, 3.0)),
+    "hflip" => "hflip".to_string(),
+    "vflip" => "vflip".to_string(),
+    "lut" => {
+      let file = effect
+        .parameters
+        .get("file")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("effect 'lut' needs a 'file' parameter"))?;
+      ffmpeg::lut3d_filter(file)
+    }
+    other => return Err(anyhow!("effect '{}' cannot be rendered", other)),
+  };
+  Ok(filter)
+}
+
+/// atempo only accepts 0.5..2.0 per instance, so larger changes are chained.
+pub(crate) fn atempo_chain(speed: f64) -> Vec<String> {
+  let mut filters = Vec::new();
+  let mut remaining = speed;
+  while remaining > 2.0 {
+    filters.push("atempo=2.0".to_string());
+    remaining /= 2.0;
+  }
+  while remaining < 0.5 {
+    filters.push("atempo=0.5".to_string());
+    remaining /= 0.5;
+  }
+  if (remaining - 1.0).abs() > 1e-9 {
+    filters.push(format!("atempo={}", remaining));
+  }
+  filters
+}
+
+/// filter_complex rendering `segment` of input 0 (its `audio_stream`th audio stream, None when
+/// it has no audio) to [outv]/[outa], exactly `duration` long. Short streams are padded (last
+/// frame held, silence) so the output never comes up short.
+fn build_bake_filter(
+  segment: &Segment,
+  encode: &Encode,
+  has_video: bool,
+  audio_stream: Option<usize>,
+  duration: f64,
+) -> Result<String> {
+  let mut graph = Vec::new();
+
+  if has_video {
+    let mut video = vec![
+      format!("trim=start={}:end={}", segment.start, segment.end),
+      format!("setpts=(PTS-STARTPTS)/{}", segment.speed),
+    ];
+    for effect in &segment.effects {
+      video.push(effect_filter(effect)?);
+    }
+    video.extend(segment.video_fade_filters(duration));
+    video.extend(encode.video_filter.clone());
+    video.push("tpad=stop_mode=clone:stop=-1".to_string());
+    video.push(format!("trim=duration={}", duration));
+    video.push("format=yuv420p".to_string());
+    graph.push(format!("[0:v]{}[outv]", video.join(",")));
+  }
+
+  let Some(audio_stream) = audio_stream else {
+    return Ok(graph.join(";"));
+  };
+  let mut audio = vec![
+    format!("atrim=start={}:end={}", segment.start, segment.end),
+    "asetpts=PTS-STARTPTS".to_string(),
+  ];
+  audio.extend(atempo_chain(segment.speed));
+  // Segment stage of the mix chain; track stages come from `encode` when exporting
+  audio.extend(audio_effects::audio_chain(segment.gain, &[], None)?);
+  audio.extend(segment.audio_fade_filters(duration));
+  audio.extend(encode.audio_filter.clone());
+  audio.push(format!("apad=whole_dur={}", duration));
+  audio.push(format!("atrim=duration={}", duration));
+  graph.push(format!("[0:a:{}]{}[outa]", audio_stream, audio.join(",")));
+
+  Ok(graph.join(";"))
+}
+
+/// --- Bake --------------------------------------------------------------------------
+
+/// Encode `segment` of `input` with its `audio_stream`th audio stream to `output`, reporting
+/// progress through `job`.
+fn render_segment(
+  input: &str,
+  probe: &Probe,
+  audio_stream: usize,
+  segment: &Segment,
+  encode: &Encode,
+  output: &Path,
+  job: &JobContext,
+) -> Result<()> {
+  let has_video = encode.video && probe.width > 0 && probe.height > 0;
+  let audio_stream = (probe.audio_rate > 0).then_some(audio_stream);
+  if !has_video && audio_stream.is_none() {
+    return Err(anyhow!("{} has no stream the output format can hold", input));
+  }
+  let duration = segment.effective_duration();
+  let filter = build_bake_filter(segment, encode, has_video, audio_stream, duration)?;
+  let tmp = ffmpeg::temp_output_path(output);
+
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1", "-i", input, "-filter_complex", &filter]);
+  if has_video {
+    cmd.args(["-map", "[outv]"]).args(&encode.video_args);
+  }
+  if audio_stream.is_some() {
+    cmd.args(["-map", "[outa]"]).args(&encode.audio_args);
+  }
+  cmd.args(["-t", &duration.to_string()])
+    .args(&encode.muxer_args)
+    .arg("-y")
+    .arg(&tmp);
+
+  let result = ffmpeg::output_with_progress(&mut cmd, duration, &mut |fraction| job.progress(fraction * 0.95, None))
+    .with_context(|| "ffmpeg bake failed to run")?;
+  if !result.status.success() {
+    let _ = fs::remove_file(&tmp);
+    return Err(anyhow!(
+      "ffmpeg bake failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+
+  fs::rename(&tmp, output).with_context(|| "failed to move tmp output into place")?;
+  Ok(())
+}
+
+/// Render a segment with its effects, speed and audio adjustments into the project's media
+/// folder, register the file as a clip derived from the source, and point the segment at it.
+/// The original segment is kept on the baked one so `project_file::unbake_segment` can restore it.
+pub fn bake_segment(track_id: &str, segment_id: &str, settings: &BakeSettings, job: &JobContext) -> Result<Segment> {
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let segment = project.segment(track_id, segment_id)?.clone();
+  if let Some(reason) = segment.validation_error() {
+    return Err(anyhow!("segment {} is invalid: {}", segment_id, reason));
+  }
+  let source = project
+    .clips_map
+    .get(&segment.clip_id)
+    .ok_or_else(|| anyhow!("clip not found: {}", segment.clip_id))?;
+  if source.r#type == ClipType::Image {
+    return Err(anyhow!("image segments cannot be baked"));
+  }
+
+  job.progress(0.0, Some("Rendering segment"));
+  let input = source.path.to_string_lossy().to_string();
+  let probe = ffmpeg::ffprobe(&input).context("ffprobe failed")?;
+  let has_video = probe.width > 0 && probe.height > 0;
+
+  let media_dir = project.media_dir()?;
+  fs::create_dir_all(&media_dir).with_context(|| format!("failed to create {:?}", media_dir))?;
+  let clip_id = ids::new_id(ids::CLIP);
+  let extension = if has_video { "mp4" } else { "m4a" };
+  let output = media_dir.join(format!("baked_{}_{}.{}", segment.id, &clip_id[clip_id.len() - 8..], extension));
+  render_segment(&input, &probe, source.audio_stream(), &segment, &Encode::bake(settings), &output, job)?;
+
+  job.progress(0.95, Some("Verifying output"));
+  let output_str = output.to_string_lossy().to_string();
+  let baked_probe = ffmpeg::ffprobe(&output_str).context("ffprobe of baked output failed")?;
+  let expected = segment.effective_duration();
+  if (baked_probe.duration - expected).abs() > DURATION_TOLERANCE {
+    let _ = fs::remove_file(&output);
+    return Err(anyhow!(
+      "baked output is {:.3}s but the segment plays for {:.3}s",
+      baked_probe.duration,
+      expected
+    ));
+  }
+
+  let clip = Clip {
+    id: clip_id,
+    path: output.clone(),
+    latest_probe: Some(baked_probe),
+    r#type: source.r#type.clone(),
+    transcript: None,
+    derived_from: Some(source.id.clone()),
+    hashes: media_hash::ingest_hashes(&output).ok(),
+    // A baked LUT effect already graded the file; otherwise it previews like its source
+    preview_lut: if segment.effects.iter().any(|e| e.r#type == "lut") { None } else { source.preview_lut.clone() },
+    stream_hashes: None,
+    creation_time: None, // Starts wherever the segment did, not when recording started
+    ready_for_ai: None,
+    transcript_language: None,
+    language_override: None,
+    has_album_art: None,
+    audio_stream_index: None, // The output has only the stream the source played
+  };
+  // The segment is timed by the requested duration, not the probe, so the timeline doesn't shift
+  let baked = project_file::swap_in_baked_segment(track_id, segment_id, &segment, clip, expected).inspect_err(|_| {
+    let _ = fs::remove_file(&output);
+  })?;
+  media_protocol::register(&output, MediaScope::Project);
+  Ok(baked)
+}
+
+/// --- Export ------------------------------------------------------------------------
+
+/// Copy the packets of an unmodified `segment` of `input` with its `audio_stream`th audio stream
+/// to `output` without re-encoding.
+fn copy_segment(input: &str, audio_stream: usize, segment: &Segment, settings: &ExportSettings, output: &Path, job: &JobContext) -> Result<()> {
+  let duration = segment.duration();
+  let tmp = ffmpeg::temp_output_path(output);
+  let audio_map = format!("0:a:{}?", audio_stream);
+
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1", "-ss", &segment.start.to_string(), "-i", input])
+    .args(["-t", &duration.to_string(), "-map", "0:v:0?", "-map", &audio_map, "-c", "copy"])
+    .args(["-avoid_negative_ts", "make_zero"])
+    .args(settings.muxer_args())
+    .arg("-y")
+    .arg(&tmp);
+
+  let result = ffmpeg::output_with_progress(&mut cmd, duration, &mut |fraction| job.progress(fraction, None))
+    .with_context(|| "ffmpeg stream copy failed to run")?;
+  if !result.status.success() {
+    let _ = fs::remove_file(&tmp);
+    return Err(anyhow!(
+      "ffmpeg stream copy failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+
+  fs::rename(&tmp, output).with_context(|| "failed to move tmp output into place")?;
+  Ok(())
+}
+
+/// Whether both ends of `segment` fall on (or within tolerance of) a keyframe of `input`.
+/// Audio-only sources can be cut anywhere.
+fn cuts_on_keyframes(input: &str, probe: &Probe, segment: &Segment) -> bool {
+  if probe.width == 0 || probe.height == 0 {
+    return true;
+  }
+  let start_ok = segment.start <= KEYFRAME_TOLERANCE
+    || ffmpeg::is_near_keyframe(input, segment.start, KEYFRAME_TOLERANCE).unwrap_or(false);
+  let end_ok = segment.end >= probe.duration - KEYFRAME_TOLERANCE
+    || ffmpeg::is_near_keyframe(input, segment.end, KEYFRAME_TOLERANCE).unwrap_or(false);
+  start_ok && end_ok
+}
+
+/// Export a single segment to `output` with its effects, speed and audio adjustments, in the
+/// format given by `settings`. Unmodified segments cut on keyframes are stream-copied when the
+/// settings allow it; anything else (or a copy ffmpeg rejects) is rendered like a bake.
+pub fn export_segment(
+  track_id: &str,
+  segment_id: &str,
+  settings: &ExportSettings,
+  output: &str,
+  job: &JobContext,
+) -> Result<SegmentExportMode> {
+  settings.validate(output)?;
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let segment = project.segment(track_id, segment_id)?.clone();
+  if let Some(reason) = segment.validation_error() {
+    return Err(anyhow!("segment {} is invalid: {}", segment_id, reason));
+  }
+  let source = project
+    .clips_map
+    .get(&segment.clip_id)
+    .ok_or_else(|| anyhow!("clip not found: {}", segment.clip_id))?;
+  if source.r#type == ClipType::Image {
+    return Err(anyhow!("image segments cannot be exported on their own"));
+  }
+
+  let input = source.path.to_string_lossy().to_string();
+  let probe = ffmpeg::ffprobe(&input).context("ffprobe failed")?;
+  let output = Path::new(output);
+  let audio_stream = source.audio_stream();
+  // The clip's stream decides the export, so a clip playing a non-default stream isn't copied
+  let settings = &ExportSettings { audio_stream: Some(audio_stream), ..settings.clone() };
+
+  if segment.is_unmodified() && settings.allows_stream_copy(&probe) && cuts_on_keyframes(&input, &probe, &segment) {
+    job.progress(0.0, Some("Copying segment"));
+    match copy_segment(&input, audio_stream, &segment, settings, output, job) {
+      Ok(()) => return Ok(SegmentExportMode::StreamCopy),
+      Err(e) => log::warn!("Stream copy of segment {} failed, rendering instead: {}", segment.id, e),
+    }
+  }
+
+  job.progress(0.0, Some("Rendering segment"));
+  render_segment(&input, &probe, audio_stream, &segment, &Encode::export(settings, &probe)?, output, job)?;
+  Ok(SegmentExportMode::Rendered)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn segment() -> Segment {
+    serde_json::from_value(serde_json::json!({ "id": "segment_a", "clip_id": "clip_a", "start": 1.0, "end": 3.0 })).unwrap()
+  }
+
+  #[test]
+  fn video_only_source_has_no_audio_chain() {
+    let filter = build_bake_filter(&segment(), &Encode::bake(&BakeSettings::default()), true, None, 2.0).unwrap();
+    assert!(filter.starts_with("[0:v]trim=start=1:end=3,"), "{}", filter);
+    assert!(filter.ends_with("[outv]"), "{}", filter);
+    assert!(!filter.contains("[0:a"), "{}", filter);
+    assert!(!filter.contains("[outa]"), "{}", filter);
+  }
+
+  #[test]
+  fn audio_only_source_has_no_video_chain() {
+    let filter = build_bake_filter(&segment(), &Encode::bake(&BakeSettings::default()), false, Some(0), 2.0).unwrap();
+    assert!(filter.starts_with("[0:a:0]atrim=start=1:end=3,"), "{}", filter);
+    assert!(!filter.contains("[outv]"), "{}", filter);
+  }
+
+  #[test]
+  fn both_chains_use_the_chosen_audio_stream() {
+    let mut segment = segment();
+    segment.speed = 2.0;
+    let filter = build_bake_filter(&segment, &Encode::bake(&BakeSettings::default()), true, Some(2), 1.0).unwrap();
+    let chains: Vec<&str> = filter.split(';').collect();
+    assert_eq!(chains.len(), 2, "{}", filter);
+    assert!(chains[0].starts_with("[0:v]") && chains[0].ends_with("[outv]"), "{}", filter);
+    assert!(chains[1].starts_with("[0:a:2]") && chains[1].ends_with("[outa]"), "{}", filter);
+    assert!(chains[1].contains("atempo=2"), "{}", filter);
+    assert!(chains[1].contains("apad=whole_dur=1,atrim=duration=1"), "{}", filter);
+  }
+
+  #[test]
+  fn audio_fades_use_the_curve_token_of_each_fade_curve() {
+    let mut segment = segment();
+    segment.fade_in = 0.5;
+    segment.fade_out = 0.25;
+    for (curve, token) in [
+      (project_file::FadeCurve::Linear, "tri"),
+      (project_file::FadeCurve::Exponential, "exp"),
+      (project_file::FadeCurve::SCurve, "hsin"),
+    ] {
+      segment.fade_curve = curve;
+      let filter = build_bake_filter(&segment, &Encode::bake(&BakeSettings::default()), false, Some(0), 2.0).unwrap();
+      assert!(filter.contains(&format!("afade=t=in:st=0:d=0.5:curve={},", token)), "{}", filter);
+      assert!(filter.contains(&format!("afade=t=out:st=1.75:d=0.25:curve={},", token)), "{}", filter);
+    }
+  }
+
+  #[test]
+  fn video_fades_go_to_and_from_black() {
+    let mut segment = segment();
+    segment.video_fade_in = 0.5;
+    segment.video_fade_out = 1.0;
+    let filter = build_bake_filter(&segment, &Encode::bake(&BakeSettings::default()), true, None, 2.0).unwrap();
+    assert!(filter.contains("fade=t=in:st=0:d=0.5,fade=t=out:st=1:d=1,"), "{}", filter);
+    assert!(!filter.contains("afade"), "{}", filter);
+  }
+}
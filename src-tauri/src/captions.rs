@@ -0,0 +1,151 @@
+//! Caption files from the project's transcripts. Each clip's transcript is mapped through the
+//! segments that play it onto the timeline, so cues follow the edit. Clips carry a language
+//! (detected, or set by the user); a project that mixes languages gets one file per language,
+//! named like `name.en.srt` and `name.es.srt`. Exported cues are in output time: the accepted
+//! cuts are taken out, as they are from the exported video.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::project_file::{ProjectFile, TrackType};
+
+/// Language part of the file name for cues whose clip has no known language
+const UNKNOWN_LANGUAGE: &str = "und";
+
+/// One caption, in timeline seconds
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    pub language: Option<String>, // The clip's language, see Clip::language
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CaptionFormat {
+    Srt,
+    Vtt,
+}
+
+/// Cues of every transcribed clip on enabled, unmuted video and audio tracks, in timeline
+/// order. A clip on both a video and an audio track (linked A/V) is only captioned once
+pub fn timeline_cues(project: &ProjectFile) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut seen = HashSet::new();
+    let tracks = project.tracks_map.values()
+        .filter(|t| t.enabled && !t.muted && matches!(t.r#type, TrackType::Video | TrackType::Audio));
+    for track in tracks {
+        for (segment, (timeline_start, timeline_end)) in track.segments.iter().zip(track.timeline_spans()) {
+            let Some(clip) = project.clips_map.get(&segment.clip_id) else { continue };
+            let Some(transcript) = &clip.transcript else { continue };
+            let to_timeline = |t: f64| timeline_start + (t - segment.start) / segment.speed;
+            for line in transcript {
+                if line.end <= segment.start || line.start >= segment.end {
+                    continue;
+                }
+                let start = to_timeline(line.start.max(segment.start));
+                let end = to_timeline(line.end.min(segment.end)).min(timeline_end);
+                // Millisecond keys, as the cue times are written
+                let key = (clip.id.clone(), line.id.clone(), (start * 1000.0).round() as i64);
+                if end <= start || line.text.trim().is_empty() || !seen.insert(key) {
+                    continue;
+                }
+                cues.push(Cue {
+                    start,
+                    end,
+                    text: line.text.trim().to_string(),
+                    language: clip.language().map(str::to_string),
+                });
+            }
+        }
+    }
+    cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+    cues
+}
+
+/// `timeline_cues` moved to output time, see ProjectFile::output_remap. Cues entirely inside
+/// accepted cuts are dropped; one a cut runs into is shortened to the part that's kept
+pub fn output_cues(project: &ProjectFile) -> Vec<Cue> {
+    let remap = project.output_remap();
+    timeline_cues(project)
+        .into_iter()
+        .filter_map(|cue| {
+            let start = remap.to_output(cue.start);
+            let end = remap.to_output(cue.end);
+            (end > start).then_some(Cue { start, end, ..cue })
+        })
+        .collect()
+}
+
+/// "hh:mm:ss,mmm" (SRT) or "hh:mm:ss.mmm" (WebVTT)
+fn timestamp(seconds: f64, format: CaptionFormat) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let separator = if format == CaptionFormat::Srt { ',' } else { '.' };
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+fn render(cues: &[&Cue], format: CaptionFormat) -> String {
+    let mut out = String::new();
+    if format == CaptionFormat::Vtt {
+        out.push_str("WEBVTT\n\n");
+    }
+    for (index, cue) in cues.iter().enumerate() {
+        if format == CaptionFormat::Srt {
+            out.push_str(&format!("{}\n", index + 1));
+        }
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            timestamp(cue.start, format),
+            timestamp(cue.end, format),
+            cue.text
+        ));
+    }
+    out
+}
+
+/// `output` with the language inserted before the extension: "talk.srt" -> "talk.es.srt"
+fn language_path(output: &Path, language: &str) -> PathBuf {
+    let stem = output.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = output.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+    output.with_file_name(format!("{}.{}.{}", stem, language, extension))
+}
+
+/// Write the project's captions to `output` (.srt or .vtt). With cues in more than one language
+/// each language goes to its own file next to `output` instead. Returns the files written
+pub fn export_transcript(project: &ProjectFile, output: &Path) -> Result<Vec<PathBuf>> {
+    let format = match output.extension().map(|e| e.to_string_lossy().to_lowercase()).as_deref() {
+        Some("srt") => CaptionFormat::Srt,
+        Some("vtt") => CaptionFormat::Vtt,
+        _ => return Err(anyhow!("captions are written as .srt or .vtt, not {:?}", output)),
+    };
+    let cues = output_cues(project);
+    if cues.is_empty() {
+        return Err(anyhow!("no transcribed clips on the timeline"));
+    }
+
+    let mut by_language: BTreeMap<&str, Vec<&Cue>> = BTreeMap::new();
+    for cue in &cues {
+        by_language.entry(cue.language.as_deref().unwrap_or(UNKNOWN_LANGUAGE)).or_default().push(cue);
+    }
+    let files: Vec<(PathBuf, Vec<&Cue>)> = if by_language.len() == 1 {
+        vec![(output.to_path_buf(), cues.iter().collect())]
+    } else {
+        by_language.into_iter().map(|(language, cues)| (language_path(output, language), cues)).collect()
+    };
+
+    let mut written = Vec::new();
+    for (path, cues) in files {
+        fs::write(&path, render(&cues, format)).with_context(|| format!("failed to write {:?}", path))?;
+        written.push(path);
+    }
+    Ok(written)
+}
@@ -0,0 +1,440 @@
+//! Chapter suggestions for the edited video, e.g. for a YouTube description. Candidate
+//! positions come from the clips' key moments (Gemini analysis), topic shifts in the
+//! transcript and scene changes, and are picked locally; only the titles come from the LLM,
+//! written from the transcript under each chapter. Chapters follow YouTube's rules: the first
+//! starts at 0:00 and each lasts at least `MIN_CHAPTER_SECONDS`. Positions are worked out in
+//! output time (accepted cuts taken out) and proposed as timeline markers for the user to
+//! review; accepted ones reach exports through `ProjectFile::output_markers`.
+
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use crate::api_quota::ApiPriority;
+use crate::captions::{self, Cue};
+use crate::clip_cache;
+use crate::ffmpeg;
+use crate::gemini_client::GeminiClient;
+use crate::ids;
+use crate::intervals::Remap;
+use crate::longterm_storage;
+use crate::project_file::{Marker, ProjectFile, TrackType};
+use crate::video_analysis::VideoAnalysisResult;
+
+/// YouTube's shortest chapter, seconds
+pub const MIN_CHAPTER_SECONDS: f64 = 10.0;
+/// Color of proposed chapter markers
+const CHAPTER_COLOR: &str = "#9b5de5";
+/// Candidates closer than this are one candidate, their scores added up
+const MERGE_SECONDS: f64 = 3.0;
+/// Transcript compared before and after a cue to spot a change of topic
+const TOPIC_WINDOW_SECONDS: f64 = 30.0;
+/// A pause at least this long before a cue makes it a likelier chapter start
+const PAUSE_SECONDS: f64 = 1.0;
+/// Words shorter than this don't count as topic keywords ("the", "and", ...)
+const MIN_KEYWORD_LEN: usize = 4;
+/// Scene score above which a frame counts as a scene change
+const SCENE_THRESHOLD: f64 = 0.4;
+/// Transcript sent to the LLM per chapter, characters
+const TITLE_CONTEXT_CHARS: usize = 800;
+
+/// Weight of each signal in a candidate's score
+const KEY_MOMENT_WEIGHT: f64 = 1.0;
+const TOPIC_WEIGHT: f64 = 1.0;
+const PAUSE_WEIGHT: f64 = 0.2;
+const SCENE_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signal {
+    KeyMoment,
+    Topic,
+    Scene,
+}
+
+/// A possible chapter start, in output seconds
+#[derive(Debug, Clone)]
+struct Candidate {
+    time: f64,
+    score: f64,
+    signal: Signal,
+    title_hint: Option<String>, // A key moment's description
+}
+
+/// Keywords of `text`: lowercased, without punctuation, long enough to carry meaning
+fn keywords(text: &str) -> HashSet<String> {
+    text.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect::<String>())
+        .filter(|word| word.chars().count() >= MIN_KEYWORD_LEN)
+        .collect()
+}
+
+/// Cue starts where the transcript moves on to another topic: the keywords of the next
+/// `TOPIC_WINDOW_SECONDS` share little with those of the last, or the speaker paused
+fn topic_candidates(cues: &[Cue]) -> Vec<Candidate> {
+    let window_keywords = |from: f64, to: f64| -> HashSet<String> {
+        cues.iter().filter(|c| c.end > from && c.start < to).flat_map(|c| keywords(&c.text)).collect()
+    };
+    let mut candidates = Vec::new();
+    for pair in cues.windows(2) {
+        let (previous, cue) = (&pair[0], &pair[1]);
+        let before = window_keywords(cue.start - TOPIC_WINDOW_SECONDS, cue.start);
+        let after = window_keywords(cue.start, cue.start + TOPIC_WINDOW_SECONDS);
+        let mut score = 0.0;
+        if !before.is_empty() && !after.is_empty() {
+            let shared = before.intersection(&after).count() as f64;
+            let overlap = shared / before.union(&after).count() as f64;
+            score += TOPIC_WEIGHT * (1.0 - overlap);
+        }
+        if cue.start - previous.end >= PAUSE_SECONDS {
+            score += PAUSE_WEIGHT;
+        }
+        if score > 0.0 {
+            candidates.push(Candidate { time: cue.start, score, signal: Signal::Topic, title_hint: None });
+        }
+    }
+    candidates
+}
+
+/// Key moments of the clips' saved analyses and their scene changes, mapped through the
+/// segments that play them onto the output. Timeline edits between segments count as scene
+/// changes too
+fn clip_candidates(project: &ProjectFile, remap: &Remap) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut analyses: HashMap<String, Option<VideoAnalysisResult>> = HashMap::new();
+    let mut scenes: HashMap<String, Vec<f64>> = HashMap::new();
+    let tracks = project.tracks_map.values()
+        .filter(|t| t.enabled && matches!(t.r#type, TrackType::Video | TrackType::Audio));
+    for track in tracks {
+        for (segment, (timeline_start, timeline_end)) in track.segments.iter().zip(track.timeline_spans()) {
+            let Some(clip) = project.clips_map.get(&segment.clip_id) else { continue };
+            let to_output = |t: f64| -> Option<f64> {
+                if t < segment.start || t >= segment.end {
+                    return None;
+                }
+                let timeline = timeline_start + (t - segment.start) / segment.speed;
+                (timeline < timeline_end).then(|| remap.to_output_kept(timeline)).flatten()
+            };
+
+            let analysis = analyses.entry(clip.id.clone()).or_insert_with(|| {
+                let path = clip_cache::clip_dir(&clip.id).ok()?.join("analysis.json");
+                serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+            });
+            for moment in analysis.iter().flat_map(|a| &a.key_moments) {
+                let Some(time) = to_output(moment.start) else { continue };
+                candidates.push(Candidate {
+                    time,
+                    score: KEY_MOMENT_WEIGHT * moment.importance.clamp(0.0, 1.0),
+                    signal: Signal::KeyMoment,
+                    title_hint: Some(moment.description.trim().to_string()).filter(|d| !d.is_empty()),
+                });
+            }
+
+            if track.r#type != TrackType::Video {
+                continue;
+            }
+            if let Some(time) = remap.to_output_kept(timeline_start).filter(|t| *t > 0.0) {
+                candidates.push(Candidate { time, score: SCENE_WEIGHT, signal: Signal::Scene, title_hint: None });
+            }
+            let changes = scenes.entry(clip.id.clone()).or_insert_with(|| {
+                ffmpeg::detect_scene_changes(&clip.path.to_string_lossy(), SCENE_THRESHOLD).unwrap_or_else(|e| {
+                    log::warn!("Scene detection failed for {:?}: {}", clip.path, e);
+                    Vec::new()
+                })
+            });
+            candidates.extend(changes.iter().filter_map(|t| to_output(*t)).map(|time| Candidate {
+                time,
+                score: SCENE_WEIGHT,
+                signal: Signal::Scene,
+                title_hint: None,
+            }));
+        }
+    }
+    candidates
+}
+
+/// Join candidates within `MERGE_SECONDS` of each other, adding up their scores. The joined
+/// candidate sits where a sentence starts if one is among them, so chapters don't open
+/// mid-sentence; otherwise at its strongest part
+fn merge_candidates(mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+    candidates.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let mut groups: Vec<Vec<Candidate>> = Vec::new();
+    for candidate in candidates {
+        match groups.last_mut() {
+            Some(group) if candidate.time - group[0].time < MERGE_SECONDS => group.push(candidate),
+            _ => groups.push(vec![candidate]),
+        }
+    }
+    groups
+        .into_iter()
+        .filter_map(|group| {
+            let score = group.iter().map(|c| c.score).sum();
+            let title_hint = group.iter().find_map(|c| c.title_hint.clone());
+            let anchor = group.iter().find(|c| c.signal == Signal::Topic)
+                .or_else(|| group.iter().max_by(|a, b| a.score.total_cmp(&b.score)))?;
+            Some(Candidate { time: anchor.time, score, signal: anchor.signal, title_hint })
+        })
+        .collect()
+}
+
+/// Up to `target_count` chapter starts (output seconds) with their title hints: 0:00, then
+/// the strongest candidates that keep every chapter `MIN_CHAPTER_SECONDS` long
+fn pick_chapters(candidates: Vec<Candidate>, duration: f64, target_count: usize) -> Vec<(f64, Option<String>)> {
+    let mut ranked = merge_candidates(candidates);
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.time.total_cmp(&b.time)));
+    let opening = ranked.iter().find(|c| c.time < MIN_CHAPTER_SECONDS).and_then(|c| c.title_hint.clone());
+    let mut chosen = vec![(0.0, opening)];
+    for candidate in ranked {
+        if chosen.len() >= target_count {
+            break;
+        }
+        let fits = candidate.time >= MIN_CHAPTER_SECONDS
+            && candidate.time <= duration - MIN_CHAPTER_SECONDS
+            && chosen.iter().all(|(time, _)| (candidate.time - time).abs() >= MIN_CHAPTER_SECONDS);
+        if fits {
+            chosen.push((candidate.time, candidate.title_hint));
+        }
+    }
+    chosen.sort_by(|a, b| a.0.total_cmp(&b.0));
+    chosen
+}
+
+/// What's said in output range [start, end), cut to `TITLE_CONTEXT_CHARS`
+fn transcript_between(cues: &[Cue], start: f64, end: f64) -> String {
+    let text = cues.iter().filter(|c| c.end > start && c.start < end).map(|c| c.text.as_str()).collect::<Vec<_>>().join(" ");
+    text.chars().take(TITLE_CONTEXT_CHARS).collect()
+}
+
+/// Title for a chapter the LLM didn't name: its key moment, else its first words
+fn fallback_title(index: usize, hint: Option<&str>, text: &str) -> String {
+    if let Some(hint) = hint {
+        return hint.split_whitespace().take(8).collect::<Vec<_>>().join(" ");
+    }
+    let words: Vec<&str> = text.split_whitespace().take(6).collect();
+    match (words.is_empty(), index) {
+        (true, 0) => "Intro".to_string(),
+        (true, _) => format!("Chapter {}", index + 1),
+        (false, _) => format!("{}…", words.join(" ").trim_end_matches(|c: char| !c.is_alphanumeric())),
+    }
+}
+
+/// Titles for chapters with transcript `texts` from the LLM, None if there is no API key or
+/// the answer can't be used
+async fn llm_titles(texts: &[String], hints: &[Option<String>]) -> Option<Vec<String>> {
+    let api_key = match longterm_storage::get_gemini_api_key() {
+        Ok(Some(key)) => key,
+        _ => return None,
+    };
+    let chapters: Vec<String> = texts
+        .iter()
+        .zip(hints)
+        .enumerate()
+        .map(|(index, (text, hint))| {
+            let hint = hint.as_deref().map(|h| format!(" (key moment: {})", h)).unwrap_or_default();
+            format!("Chapter {}{}: \"{}\"", index + 1, hint, if text.is_empty() { "(no speech)" } else { text })
+        })
+        .collect();
+    let prompt = format!(
+        "Write a title for each chapter of a video, for its YouTube chapter list. Each title is \
+         2 to 6 words, names what the chapter is about, and has no numbering, timestamps or \
+         quotes. Here is what is said in each chapter:\n\n{}\n\n\
+         Answer with only a JSON array of {} strings, one per chapter, in order.",
+        chapters.join("\n"),
+        texts.len()
+    );
+    let client = GeminiClient::new(api_key).with_priority(ApiPriority::Background);
+    let response = match client.generate_content(prompt).await {
+        Ok(response) => response,
+        Err(e) => {
+            log::warn!("Chapter titling failed, using local titles: {}", e);
+            return None;
+        }
+    };
+    let json = &response[response.find('[')?..=response.rfind(']')?];
+    let titles: Vec<String> = serde_json::from_str(json).ok()?;
+    if titles.len() != texts.len() {
+        log::warn!("Chapter titling returned {} titles for {} chapters", titles.len(), texts.len());
+        return None;
+    }
+    Some(titles.into_iter().map(|t| t.trim().trim_matches('"').to_string()).collect())
+}
+
+/// A chapter before it has a title
+pub struct ChapterPosition {
+    pub output_time: f64,
+    title_hint: Option<String>,
+    transcript: String, // What's said in the chapter, for titling
+}
+
+/// A proposed chapter: the marker to add, and where it lands in the output
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChapterProposal {
+    pub marker: Marker, // Timeline time, as markers are kept
+    pub output_time: f64,
+}
+
+/// Chapter starts in output seconds, with title hints and the transcript of each chapter.
+/// Runs scene detection, so call it off the async runtime
+pub fn chapter_positions(project: &ProjectFile, target_count: usize) -> Result<Vec<ChapterPosition>> {
+    if target_count == 0 {
+        return Err(anyhow!("ask for at least one chapter"));
+    }
+    let remap = project.output_remap();
+    if remap.duration <= 0.0 {
+        return Err(anyhow!("the timeline is empty"));
+    }
+    let cues = captions::output_cues(project);
+    let mut candidates = topic_candidates(&cues);
+    candidates.extend(clip_candidates(project, &remap));
+    let chapters = pick_chapters(candidates, remap.duration, target_count);
+    let ends: Vec<f64> = chapters.iter().skip(1).map(|(time, _)| *time).chain([remap.duration]).collect();
+    Ok(chapters
+        .into_iter()
+        .zip(ends)
+        .map(|((start, title_hint), end)| ChapterPosition {
+            output_time: start,
+            title_hint,
+            transcript: transcript_between(&cues, start, end),
+        })
+        .collect())
+}
+
+/// Title `positions` (from `chapter_positions`) and turn them into proposed markers
+pub async fn propose_chapters(project: &ProjectFile, positions: Vec<ChapterPosition>) -> Vec<ChapterProposal> {
+    let texts: Vec<String> = positions.iter().map(|p| p.transcript.clone()).collect();
+    let hints: Vec<Option<String>> = positions.iter().map(|p| p.title_hint.clone()).collect();
+    let titles = llm_titles(&texts, &hints).await;
+    let remap = project.output_remap();
+    positions
+        .into_iter()
+        .enumerate()
+        .map(|(index, position)| {
+            let label = titles.as_ref()
+                .map(|t| t[index].clone())
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| fallback_title(index, position.title_hint.as_deref(), &position.transcript));
+            ChapterProposal {
+                marker: Marker {
+                    id: ids::new_id(ids::MARKER),
+                    time: remap.to_source(position.output_time),
+                    label,
+                    color: Some(CHAPTER_COLOR.to_string()),
+                },
+                output_time: position.output_time,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(time: f64, score: f64, signal: Signal, title_hint: Option<&str>) -> Candidate {
+        Candidate { time, score, signal, title_hint: title_hint.map(str::to_string) }
+    }
+
+    fn cue(start: f64, end: f64, text: &str) -> Cue {
+        Cue { start, end, text: text.to_string(), language: None }
+    }
+
+    fn times(chapters: &[(f64, Option<String>)]) -> Vec<f64> {
+        chapters.iter().map(|(time, _)| *time).collect()
+    }
+
+    #[test]
+    fn the_first_chapter_starts_at_zero() {
+        let chapters = pick_chapters(vec![candidate(30.0, 1.0, Signal::Topic, None)], 120.0, 5);
+        assert_eq!(chapters, vec![(0.0, None), (30.0, None)]);
+
+        // A strong candidate just after the start names the opening chapter instead of starting one
+        let chapters = pick_chapters(vec![candidate(4.0, 1.0, Signal::KeyMoment, Some("Welcome"))], 120.0, 5);
+        assert_eq!(chapters, vec![(0.0, Some("Welcome".to_string()))]);
+
+        // Without candidates there is still the one chapter
+        assert_eq!(times(&pick_chapters(Vec::new(), 120.0, 5)), vec![0.0]);
+    }
+
+    #[test]
+    fn chapters_last_at_least_the_minimum() {
+        let candidates = vec![
+            candidate(5.0, 3.0, Signal::KeyMoment, Some("Welcome")),
+            candidate(12.0, 1.0, Signal::Topic, None),
+            candidate(15.0, 0.9, Signal::Topic, None),
+            candidate(40.0, 0.5, Signal::Scene, None),
+            candidate(45.0, 0.8, Signal::Topic, None),
+            candidate(115.0, 2.0, Signal::KeyMoment, Some("Outro")),
+        ];
+        let chapters = pick_chapters(candidates, 120.0, 10);
+        assert_eq!(chapters, vec![(0.0, Some("Welcome".to_string())), (12.0, None), (45.0, None)]);
+
+        let ends: Vec<f64> = times(&chapters).into_iter().skip(1).chain([120.0]).collect();
+        for (start, end) in times(&chapters).into_iter().zip(ends) {
+            assert!(end - start >= MIN_CHAPTER_SECONDS, "chapter {}..{} is too short", start, end);
+        }
+    }
+
+    #[test]
+    fn chapters_come_in_ascending_order() {
+        // Strongest last, so picking by score visits them back to front
+        let candidates = vec![
+            candidate(20.0, 0.7, Signal::Topic, None),
+            candidate(40.0, 0.8, Signal::Topic, None),
+            candidate(70.0, 0.9, Signal::Topic, None),
+            candidate(100.0, 1.0, Signal::Topic, None),
+        ];
+        let chapters = pick_chapters(candidates.clone(), 130.0, 10);
+        assert_eq!(times(&chapters), vec![0.0, 20.0, 40.0, 70.0, 100.0]);
+
+        // Fewer asked for: the strongest, still in order
+        assert_eq!(times(&pick_chapters(candidates, 130.0, 3)), vec![0.0, 70.0, 100.0]);
+    }
+
+    #[test]
+    fn nearby_candidates_merge_at_the_sentence_start() {
+        let merged = merge_candidates(vec![
+            candidate(21.5, 0.5, Signal::Topic, None),
+            candidate(20.0, 0.8, Signal::Scene, None),
+            candidate(22.0, 0.6, Signal::KeyMoment, Some("Setup")),
+            candidate(30.0, 0.3, Signal::Scene, None),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].time, 21.5);
+        assert!((merged[0].score - 1.9).abs() < 1e-9);
+        assert_eq!(merged[0].title_hint.as_deref(), Some("Setup"));
+        assert_eq!(merged[1].time, 30.0);
+    }
+
+    #[test]
+    fn a_change_of_topic_or_a_pause_is_a_candidate() {
+        let cues = vec![
+            cue(0.0, 5.0, "Cameras sensors lenses apertures"),
+            cue(5.0, 10.0, "Cameras sensors lenses apertures"),
+            cue(12.0, 17.0, "Recipes flour butter ovens"),
+        ];
+        let candidates = topic_candidates(&cues);
+        assert_eq!(candidates.len(), 2);
+        // Same words on both sides, no pause
+        assert!(candidates[0].time == 5.0 && candidates[0].score < TOPIC_WEIGHT);
+        // New words after a two-second pause
+        assert_eq!(candidates[1].time, 12.0);
+        assert!(candidates[1].score > candidates[0].score);
+    }
+
+    #[test]
+    fn fallback_titles() {
+        assert_eq!(fallback_title(3, Some("  The host walks through the whole setup of the studio today "), ""), "The host walks through the whole setup of");
+        assert_eq!(fallback_title(0, None, ""), "Intro");
+        assert_eq!(fallback_title(2, None, "  "), "Chapter 3");
+        assert_eq!(fallback_title(1, None, "So, let's talk about the budget now."), "So, let's talk about the budget…");
+        assert_eq!(fallback_title(1, None, "Short one."), "Short one…");
+    }
+
+    #[test]
+    fn chapter_transcripts_are_what_is_said_in_them() {
+        let cues = vec![cue(0.0, 4.0, "one"), cue(4.0, 8.0, "two"), cue(8.0, 12.0, "three")];
+        assert_eq!(transcript_between(&cues, 3.0, 8.0), "one two");
+        assert_eq!(transcript_between(&cues, 12.0, 20.0), "");
+        let long = vec![cue(0.0, 1.0, &"x".repeat(TITLE_CONTEXT_CHARS * 2))];
+        assert_eq!(transcript_between(&long, 0.0, 1.0).chars().count(), TITLE_CONTEXT_CHARS);
+    }
+}
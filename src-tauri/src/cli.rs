@@ -0,0 +1,177 @@
+//! Command-line mode for support: `gebo --inspect <project>` and `gebo --probe <media>` print
+//! diagnostics and exit without opening a window, and `gebo --run-script <script> <project>`
+//! applies an edit script (see edit_script.rs). Anything else starts the app as usual.
+
+use anyhow::Result;
+use std::path::Path;
+use crate::edit_script;
+use crate::ffmpeg::{self, ExportSettings};
+use crate::project_file::{HashStatus, ProjectFile};
+
+const USAGE: &str =
+  "usage: gebo [--inspect <project file> | --probe <media file> | --run-script <script> <project file> | --help]";
+
+/// Handle CLI arguments (without the program name). Returns the exit code when a CLI command
+/// ran, or None to start the GUI
+pub fn run(args: &[String]) -> Option<i32> {
+  let command = args.first()?.as_str();
+  let code = match (command, args.get(1)) {
+    ("--help" | "-h", _) => {
+      println!("{}", USAGE);
+      0
+    }
+    ("--inspect", Some(path)) => report(inspect(path)),
+    ("--probe", Some(path)) => report(probe(path)),
+    ("--run-script", Some(script)) => match args.get(2) {
+      Some(project) => report(run_script(script, project)),
+      None => {
+        eprintln!("{}", USAGE);
+        2
+      }
+    },
+    ("--inspect" | "--probe" | "--run-script", None) => {
+      eprintln!("{}", USAGE);
+      2
+    }
+    _ => return None,
+  };
+  Some(code)
+}
+
+/// Exit code for a command: 0 on success, 1 on failure (after printing the error)
+fn report(result: Result<bool>) -> i32 {
+  match result {
+    Ok(true) => 0,
+    Ok(false) => 1,
+    Err(e) => {
+      eprintln!("error: {:#}", e);
+      1
+    }
+  }
+}
+
+/// Print a project summary and its validation report. Ok(false) when the project has issues.
+/// The project isn't verified on load: missing clips are what this is for diagnosing
+fn inspect(path: &str) -> Result<bool> {
+  let project = ProjectFile::from_path_unverified(Path::new(path))?;
+  print!("{}", summary(&project, path));
+  Ok(project.validation_report().valid)
+}
+
+fn summary(project: &ProjectFile, path: &str) -> String {
+  let report = project.validation_report();
+  let mut out = String::new();
+  out.push_str(&format!("Project:  {}\n", project.title));
+  out.push_str(&format!("File:     {}\n", path));
+  out.push_str(&format!("Gebo:     {}\n", env!("CARGO_PKG_VERSION")));
+  out.push_str(&format!("Duration: {:.3}s\n", project.timeline_duration()));
+
+  out.push_str(&format!("\nTracks ({}):\n", project.tracks_map.len()));
+  for track in project.ordered_tracks() {
+    let mut flags = Vec::new();
+    if !track.enabled {
+      flags.push("disabled".to_string());
+    }
+    if track.muted {
+      flags.push("muted".to_string());
+    }
+    if track.pan != 0 {
+      flags.push(format!("pan {}", track.pan));
+    }
+    out.push_str(&format!(
+      "  {} {:?} \"{}\": {} segments, {:.3}s, volume {}{}\n",
+      track.order,
+      track.r#type,
+      track.name,
+      track.segments.len(),
+      track.duration(),
+      track.volume,
+      if flags.is_empty() { String::new() } else { format!(" ({})", flags.join(", ")) }
+    ));
+  }
+
+  let missing = report.clips.iter().filter(|c| !c.exists).count();
+  out.push_str(&format!("\nClips ({}, {} missing):\n", report.clips.len(), missing));
+  for clip in &report.clips {
+    let status = match (clip.exists, clip.hash_status) {
+      (false, _) => "MISSING".to_string(),
+      (true, HashStatus::Verified) => "ok, verified".to_string(),
+      (true, status) => format!("ok, {:?}", status).to_lowercase(),
+    };
+    out.push_str(&format!("  {} {} [{}]\n", clip.clip_id, clip.path, status));
+  }
+
+  let warnings = project.export_warnings(&ExportSettings::default());
+  if !warnings.is_empty() {
+    out.push_str(&format!("\nWarnings ({}):\n", warnings.len()));
+    for warning in &warnings {
+      out.push_str(&format!("  - {}\n", warning));
+    }
+  }
+
+  if report.valid {
+    out.push_str("\nNo issues found\n");
+  } else {
+    out.push_str(&format!("\nIssues ({}):\n", report.issues.len()));
+    for issue in &report.issues {
+      out.push_str(&format!("  - {}\n", issue));
+    }
+  }
+  out
+}
+
+/// Print the probe of a media file as JSON
+fn probe(path: &str) -> Result<bool> {
+  let probe = ffmpeg::ffprobe(path)?;
+  println!("{}", serde_json::to_string_pretty(&probe)?);
+  Ok(true)
+}
+
+/// Apply an edit script, printing each step as it starts. Ok(false) when a step failed
+fn run_script(script: &str, project: &str) -> Result<bool> {
+  let report = edit_script::run_edit_script(Path::new(script), Path::new(project), &mut |index, total, step| {
+    println!("[{}/{}] {}", index + 1, total, serde_json::to_string(step).unwrap_or_default());
+  })?;
+  if let Some(error) = &report.error {
+    eprintln!("error: {}", error);
+  }
+  println!("Report written to {}", report.report_path);
+  Ok(report.ok)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  #[test]
+  fn inspecting_a_project_with_a_missing_clip_reports_it() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("missing.gebo");
+    let raw = serde_json::json!({
+      "title": "Missing",
+      "clips_map": {
+        "clip_a": { "id": "clip_a", "path": dir.join("gone.mp4"), "latest_probe": null, "type": "Video" }
+      },
+      "tracks_map": {
+        "track_v": {
+          "id": "track_v", "name": "Video", "type": "Video", "enabled": true, "muted": false, "volume": 100, "order": 0,
+          "segments": [{ "id": "segment_a", "clip_id": "clip_a", "start": 0.0, "end": 1.0 }]
+        }
+      },
+      "markers": [],
+    });
+    fs::write(&path, serde_json::to_string_pretty(&raw).unwrap()).unwrap();
+    let path = path.to_string_lossy().to_string();
+
+    assert!(ProjectFile::from_path(Path::new(&path)).is_err());
+    assert!(!inspect(&path).unwrap());
+    let project = ProjectFile::from_path_unverified(Path::new(&path)).unwrap();
+    let summary = summary(&project, &path);
+    assert!(summary.contains("Clips (1, 1 missing)"), "{}", summary);
+    assert!(summary.contains("[MISSING]"), "{}", summary);
+    assert!(summary.contains("clip clip_a: file"), "{}", summary);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
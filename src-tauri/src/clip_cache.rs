@@ -0,0 +1,300 @@
+//! Caches generated from project clips (preview proxy, waveform peaks, thumbnail) and what each
+//! one depends on. Every artifact gets a `<artifact>.deps.json` sidecar naming the source
+//! streams it was made from and their decoded-content hashes (media_hash::stream_hashes). An
+//! artifact stays valid while those streams are unchanged, so a derivative that only touched
+//! the picture (stabilization keeps the audio) reuses its source's waveform instead of
+//! regenerating it. Waveform peaks live in waveform_cache, the cache the waveform commands
+//! read, with the sidecar beside the entry.
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ffmpeg;
+use crate::jobs::JobContext;
+use crate::longterm_storage;
+use crate::media_hash;
+use crate::project_file::{self, Clip, ClipType, ProjectFile, StreamHashes, StreamKind};
+use crate::thumbnail_cache;
+use crate::waveform_cache;
+
+/// Width of cached clip thumbnails
+const THUMBNAIL_WIDTH: u32 = 320;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    Proxy,
+    Waveform,
+    Thumbnail,
+}
+
+impl ArtifactKind {
+    /// Source streams the artifact of a `clip_type` clip is made from
+    pub fn depends_on(&self, clip_type: &ClipType) -> Vec<StreamKind> {
+        match self {
+            ArtifactKind::Proxy => vec![StreamKind::Video, StreamKind::Audio],
+            ArtifactKind::Waveform => vec![StreamKind::Audio],
+            ArtifactKind::Thumbnail => match clip_type {
+                ClipType::Audio => vec![StreamKind::Audio], // Poster: album art, else the waveform
+                _ => vec![StreamKind::Video],
+            },
+        }
+    }
+}
+
+/// Sidecar of a cached artifact
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtifactDeps {
+    pub source_clip: String, // Clip the artifact was generated from
+    pub streams: HashMap<StreamKind, String>, // Hash of each stream it depends on, at generation
+    pub created_at: String, // ISO 8601
+}
+
+fn deps_path(artifact: &Path) -> PathBuf {
+    let mut path = OsString::from(artifact.as_os_str());
+    path.push(".deps.json");
+    PathBuf::from(path)
+}
+
+/// Dependency record of `artifact`, if one was written
+pub fn read_deps(artifact: &Path) -> Option<ArtifactDeps> {
+    let data = fs::read_to_string(deps_path(artifact)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn write_deps(artifact: &Path, deps: &ArtifactDeps) -> Result<()> {
+    let path = deps_path(artifact);
+    let data = serde_json::to_string_pretty(deps).with_context(|| "Failed to serialize artifact dependencies")?;
+    fs::write(&path, data).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// True while every stream `deps` was made from still has the same hash in `hashes`
+pub fn deps_match(deps: &ArtifactDeps, hashes: &StreamHashes) -> bool {
+    deps.streams.iter().all(|(kind, hash)| hashes.streams.get(kind) == Some(hash))
+}
+
+/// The clip's stream hashes, if they were taken from the file as it is now
+pub fn current_hashes(clip: &Clip) -> Option<&StreamHashes> {
+    let hashes = clip.stream_hashes.as_ref()?;
+    let (size, modified) = media_hash::file_stamp(&clip.path).ok()?;
+    (hashes.size == size && hashes.modified == modified).then_some(hashes)
+}
+
+pub(crate) fn clip_dir(clip_id: &str) -> Result<PathBuf> {
+    Ok(longterm_storage::get_lts_directory()?.join("clip_cache").join(clip_id))
+}
+
+/// Where the `kind` artifact of `clip` is cached
+pub fn artifact_path(clip: &Clip, kind: ArtifactKind) -> Result<PathBuf> {
+    match kind {
+        ArtifactKind::Proxy => ffmpeg::proxy_path(&clip.path.to_string_lossy(), None, clip.audio_stream_index),
+        ArtifactKind::Waveform => waveform_cache::entry_path(&clip.path.to_string_lossy(), clip.audio_stream(), false),
+        ArtifactKind::Thumbnail => Ok(clip_dir(&clip.id)?.join("thumbnail.png")),
+    }
+}
+
+/// The `kind` artifact of the clip `clip` was derived from, when it was made from streams this
+/// clip still shares (same hashes). Needs this clip's stream hashes
+pub fn shared_artifact(project: &ProjectFile, clip: &Clip, kind: ArtifactKind) -> Option<PathBuf> {
+    let hashes = current_hashes(clip)?;
+    let source = project.clips_map.get(clip.derived_from.as_ref()?)?;
+    let path = artifact_path(source, kind).ok().filter(|p| p.is_file())?;
+    let deps = read_deps(&path)?;
+    let depends_on = kind.depends_on(&clip.r#type);
+    let covers = depends_on.iter().all(|k| deps.streams.contains_key(k) == hashes.streams.contains_key(k));
+    (covers && deps_match(&deps, hashes)).then_some(path)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmOutcome {
+    UpToDate,
+    Reused, // Copied from the clip it was derived from
+    Regenerated,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WarmedArtifact {
+    pub clip_id: String,
+    pub kind: ArtifactKind,
+    pub outcome: WarmOutcome,
+    pub error: Option<String>, // Set when failed
+}
+
+/// Write the `kind` artifact of `clip` to `path`
+fn generate(clip: &Clip, kind: ArtifactKind, path: &Path) -> Result<()> {
+    let input = clip.path.to_string_lossy().to_string();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    match kind {
+        ArtifactKind::Proxy => {
+            ffmpeg::make_preview_proxy(&input, None, clip.audio_stream_index, None, &mut |_| {})?;
+        }
+        ArtifactKind::Waveform => {
+            waveform_cache::mixed_peaks(&input, clip.audio_stream())?;
+            // Peaks cached per clip by older versions
+            let legacy = clip_dir(&clip.id)?.join("peaks.json");
+            let _ = fs::remove_file(deps_path(&legacy));
+            let _ = fs::remove_file(legacy);
+        }
+        ArtifactKind::Thumbnail => {
+            let image = match clip.r#type {
+                ClipType::Audio => thumbnail_cache::poster(&input)?,
+                _ => ffmpeg::generate_thumbnails(&input, 1, THUMBNAIL_WIDTH)?.into_iter().next(),
+            };
+            let image = image.ok_or_else(|| anyhow!("no image for {}", input))?;
+            let png = base64::engine::general_purpose::STANDARD.decode(image).context("thumbnail is not valid base64")?;
+            fs::write(path, png).with_context(|| format!("Failed to write {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Bring one artifact up to date: keep it if its streams are unchanged, else copy the source
+/// clip's when that was made from the same streams, else generate it
+fn warm_artifact(project: &ProjectFile, clip: &Clip, kind: ArtifactKind, hashes: &StreamHashes) -> Result<WarmOutcome> {
+    let path = artifact_path(clip, kind)?;
+    let streams: HashMap<StreamKind, String> = kind
+        .depends_on(&clip.r#type)
+        .into_iter()
+        .filter_map(|k| hashes.streams.get(&k).map(|h| (k, h.clone())))
+        .collect();
+    if path.is_file() && read_deps(&path).is_some_and(|deps| deps.streams == streams) {
+        return Ok(WarmOutcome::UpToDate);
+    }
+
+    let (outcome, source_clip) = match shared_artifact(project, clip, kind) {
+        Some(shared) if shared != path => {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+            }
+            match kind {
+                // Entries record the file they were made from, so the copy is stamped with this clip's
+                ArtifactKind::Waveform => waveform_cache::copy_entry(&shared, &path, &clip.path)?,
+                _ => {
+                    fs::copy(&shared, &path).with_context(|| format!("Failed to copy {:?}", shared))?;
+                }
+            }
+            let source = read_deps(&shared).map(|d| d.source_clip).unwrap_or_else(|| clip.id.clone());
+            (WarmOutcome::Reused, source)
+        }
+        _ => {
+            generate(clip, kind, &path)?;
+            (WarmOutcome::Regenerated, clip.id.clone())
+        }
+    };
+    write_deps(&path, &ArtifactDeps { source_clip, streams, created_at: chrono::Utc::now().to_rfc3339() })?;
+    Ok(outcome)
+}
+
+/// `clip` with stream hashes of the file as it is now, hashing (and recording) them if it changed
+fn with_fresh_hashes(clip: &Clip) -> Result<(Clip, StreamHashes)> {
+    let hashes = match current_hashes(clip) {
+        Some(hashes) => hashes.clone(),
+        None => {
+            let hashes = media_hash::stream_hashes(&clip.path)?;
+            project_file::set_clip_stream_hashes(&clip.id, hashes.clone())?;
+            hashes
+        }
+    };
+    // Stream hashes were just taken, so `shared_artifact` can compare against them
+    let mut clip = clip.clone();
+    clip.stream_hashes = Some(hashes.clone());
+    Ok((clip, hashes))
+}
+
+/// Artifacts a clip of `clip_type` can have, for reporting a clip whose streams couldn't be hashed
+fn possible_kinds(clip_type: &ClipType) -> Vec<ArtifactKind> {
+    match clip_type {
+        ClipType::Video => vec![ArtifactKind::Proxy, ArtifactKind::Waveform, ArtifactKind::Thumbnail],
+        _ => vec![ArtifactKind::Waveform, ArtifactKind::Thumbnail],
+    }
+}
+
+/// Artifacts that apply to `clip`: a proxy for video, a waveform when there is audio, and a thumbnail
+fn applicable_kinds(clip: &Clip, hashes: &StreamHashes) -> Vec<ArtifactKind> {
+    let mut kinds = Vec::new();
+    if clip.r#type == ClipType::Video {
+        kinds.push(ArtifactKind::Proxy);
+    }
+    if hashes.streams.contains_key(&StreamKind::Audio) {
+        kinds.push(ArtifactKind::Waveform);
+    }
+    kinds.push(ArtifactKind::Thumbnail);
+    kinds
+}
+
+/// Bring the `kind` artifact of clip `clip_id` up to date, like `warm_clip_caches` does for
+/// every artifact. None when it doesn't apply to the clip (no proxy for audio, say)
+pub fn warm_clip_artifact(clip_id: &str, kind: ArtifactKind) -> Result<Option<WarmOutcome>> {
+    let project = project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+    if clip.r#type == ClipType::Image || !clip.path.is_file() {
+        return Ok(None);
+    }
+    let (clip, hashes) = with_fresh_hashes(clip)?;
+    if !applicable_kinds(&clip, &hashes).contains(&kind) {
+        return Ok(None);
+    }
+    warm_artifact(&project, &clip, kind, &hashes).map(Some)
+}
+
+/// Hash the streams of the given clips (all clips when None) where the files changed since
+/// they were last hashed, then regenerate only the artifacts whose streams changed. One
+/// artifact or clip failing doesn't stop the rest. The job can be paused between clips, and
+/// cancelling it stops a hash in progress
+pub fn warm_clip_caches(clip_ids: Option<&[String]>, job: &JobContext) -> Result<Vec<WarmedArtifact>> {
+    let project = project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    let mut clips: Vec<&Clip> = project.clips_map.values()
+        .filter(|c| clip_ids.map_or(true, |ids| ids.contains(&c.id)))
+        .filter(|c| c.r#type != ClipType::Image && c.path.is_file())
+        .collect();
+    clips.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut results = Vec::new();
+    job.set_pausable();
+    for (i, clip) in clips.iter().enumerate() {
+        if job.checkpoint().is_err() {
+            return Err(anyhow!("cache warm-up cancelled"));
+        }
+        let name = clip.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        job.progress(i as f64 / clips.len() as f64, Some(format!("Checking {}", name).as_str()));
+
+        let (clip, hashes) = match with_fresh_hashes(clip) {
+            Ok(fresh) => fresh,
+            Err(_) if job.is_cancelled() => return Err(anyhow!("cache warm-up cancelled")),
+            Err(e) => {
+                log::warn!("Failed to hash the streams of {}: {}", name, e);
+                let error = format!("failed to hash the streams: {:#}", e);
+                results.extend(possible_kinds(&clip.r#type).into_iter().map(|kind| WarmedArtifact {
+                    clip_id: clip.id.clone(),
+                    kind,
+                    outcome: WarmOutcome::Failed,
+                    error: Some(error.clone()),
+                }));
+                continue;
+            }
+        };
+        for kind in applicable_kinds(&clip, &hashes) {
+            let (outcome, error) = match warm_artifact(&project, &clip, kind, &hashes) {
+                Ok(outcome) => (outcome, None),
+                Err(e) => {
+                    log::warn!("Failed to warm {:?} for {}: {}", kind, name, e);
+                    (WarmOutcome::Failed, Some(e.to_string()))
+                }
+            };
+            results.push(WarmedArtifact { clip_id: clip.id.clone(), kind, outcome, error });
+        }
+    }
+    Ok(results)
+}
@@ -0,0 +1,120 @@
+//! Everything the media panel shows about one clip, gathered from the project and the on-disk
+//! caches in a single call. Nothing is generated here: an artifact that was never made is
+//! reported as missing. One whose source streams changed is stale (see clip_cache.rs); without
+//! a dependency record or stream hashes, one older than its source is.
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::clip_cache::{self, ArtifactKind};
+use crate::ffmpeg::Probe;
+use crate::media_library;
+use crate::project_file::{self, Clip, ClipType, HashStatus, MediaHashes, StreamKind};
+use crate::thumbnail_cache;
+
+/// A generated file derived from the clip's source
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub stale: bool, // A stream it depends on changed (or, unrecorded, the source was modified after it was written)
+    pub depends_on: Vec<StreamKind>,
+    pub source_clip: Option<String>, // Clip it was generated from, when not this one (a derivative reusing it)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClipDetails {
+    pub clip_id: String,
+    pub path: PathBuf,
+    pub r#type: ClipType,
+    pub exists: bool, // The source file is on disk
+    pub probe: Option<Probe>, // Cached probe from the project
+    pub proxy: Option<Artifact>, // Preview proxy, see ffmpeg::make_preview_proxy
+    pub waveform: Option<Artifact>, // Cached peaks (library or clip cache)
+    pub thumbnail: Option<Artifact>, // Library or clip cache thumbnail, or cached poster
+    pub transcript_segments: Option<usize>, // None until the clip is transcribed
+    pub language: Option<String>, // ISO 639 code: the user's override, else the detected one
+    pub language_overridden: bool,
+    pub ready_for_ai: Option<String>,       // When `prepare_clip` last finished for it
+    pub usage_count: usize, // Segments that play the clip
+    pub derived_from: Option<String>, // Source clip of a baked segment
+    pub hashes: Option<MediaHashes>,
+    pub hash_status: HashStatus,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// `path` as the `kind` artifact of `clip`, if it exists
+fn artifact(path: PathBuf, kind: ArtifactKind, clip: &Clip, source_modified: Option<SystemTime>) -> Option<Artifact> {
+    let written = modified(&path)?;
+    let deps = clip_cache::read_deps(&path);
+    let stale = match (&deps, clip_cache::current_hashes(clip)) {
+        (Some(deps), Some(hashes)) => !clip_cache::deps_match(deps, hashes),
+        _ => source_modified.map_or(false, |source| source > written),
+    };
+    Some(Artifact {
+        path,
+        stale,
+        depends_on: kind.depends_on(&clip.r#type),
+        source_clip: deps.map(|d| d.source_clip).filter(|id| *id != clip.id),
+    })
+}
+
+/// Details of `clip_id` in the loaded project. Only reads caches; takes milliseconds for any clip
+pub fn get_clip_details(clip_id: &str) -> Result<ClipDetails> {
+    let project = project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow::anyhow!("no project is currently loaded"))?;
+    let clip = project.clips_map.get(clip_id)
+        .ok_or_else(|| anyhow::anyhow!("clip not found: {}", clip_id))?;
+
+    let exists = clip.verify();
+    let source_modified = modified(&clip.path);
+    let library_item = media_library::library_item_at(&clip.path).unwrap_or_else(|e| {
+        log::warn!("Failed to read the media library: {}", e);
+        None
+    });
+
+    // The clip's own cache, else the one of the clip it was derived from when the streams match
+    let cached = |kind: ArtifactKind| {
+        clip_cache::artifact_path(clip, kind)
+            .ok()
+            .filter(|p| p.is_file())
+            .or_else(|| clip_cache::shared_artifact(&project, clip, kind))
+    };
+    let proxy = match clip.r#type {
+        ClipType::Video => cached(ArtifactKind::Proxy).and_then(|p| artifact(p, ArtifactKind::Proxy, clip, source_modified)),
+        _ => None,
+    };
+    let waveform = library_item.as_ref()
+        .and_then(|item| item.peaks.clone())
+        .or_else(|| cached(ArtifactKind::Waveform))
+        .and_then(|p| artifact(p, ArtifactKind::Waveform, clip, source_modified));
+    let thumbnail = library_item.as_ref()
+        .and_then(|item| item.thumbnail.clone())
+        .or_else(|| cached(ArtifactKind::Thumbnail))
+        .or_else(|| (clip.r#type == ClipType::Audio && exists).then(|| thumbnail_cache::cached_poster(&clip.path)).flatten())
+        .and_then(|p| artifact(p, ArtifactKind::Thumbnail, clip, source_modified));
+
+    Ok(ClipDetails {
+        clip_id: clip.id.clone(),
+        path: clip.path.clone(),
+        r#type: clip.r#type.clone(),
+        exists,
+        probe: clip.latest_probe.clone(),
+        proxy,
+        waveform,
+        thumbnail,
+        transcript_segments: clip.transcript.as_ref().map(|t| t.len()),
+        language: clip.language().map(str::to_string),
+        language_overridden: clip.language_override.is_some(),
+        ready_for_ai: clip.ready_for_ai.clone(),
+        usage_count: project.clip_usage(clip_id),
+        derived_from: clip.derived_from.clone(),
+        hashes: clip.hashes.clone(),
+        hash_status: if exists || clip.hashes.is_none() { clip.hash_status() } else { HashStatus::Missing },
+    })
+}
@@ -0,0 +1,382 @@
+//! "Prepare clip": everything the agent needs from a clip (preview proxy, waveform, thumbnail,
+//! transcript and, if asked, a Gemini analysis) generated by one job in dependency order. The
+//! transcript is made from audio extracted first, and the analysis runs after it so it can
+//! reuse the transcript. Every step change is sent as "clip-prepare-progress"; a clip whose
+//! steps all succeeded is marked ready for AI (`Clip::ready_for_ai`).
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tauri::{AppHandle, Emitter};
+use tokio_util::sync::CancellationToken;
+use crate::clip_cache::{self, ArtifactKind, WarmOutcome};
+use crate::jobs::{self, JobContext};
+use crate::longterm_storage;
+use crate::project_file::{self, Clip, ClipType};
+use crate::transcription::{TranscriptionResult, TranscriptionService};
+use crate::video_analysis::{self, VideoAnalysisResult, VideoAnalysisService};
+
+/// Mono 16 kHz speech audio; keeps an hour of audio well under the transcription upload limit
+const TRANSCRIPT_AUDIO_ARGS: &[&str] = &["-vn", "-ac", "1", "-ar", "16000", "-c:a", "aac", "-b:a", "48k"];
+
+fn enabled() -> bool {
+    true
+}
+
+/// What `prepare_clip` should generate. Everything but the analysis by default
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrepareOptions {
+    #[serde(default = "enabled")]
+    pub proxy: bool,
+    #[serde(default = "enabled")]
+    pub waveform: bool,
+    #[serde(default = "enabled")]
+    pub thumbnails: bool,
+    #[serde(default = "enabled")]
+    pub transcript: bool,
+    #[serde(default)]
+    pub analysis: bool, // Gemini video analysis; costs API quota, so only when asked for
+}
+
+impl Default for PrepareOptions {
+    fn default() -> Self {
+        PrepareOptions { proxy: true, waveform: true, thumbnails: true, transcript: true, analysis: false }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrepareStep {
+    Proxy,
+    Waveform,
+    Thumbnails,
+    Audio, // Audio extracted for the transcript
+    Transcript,
+    Analysis,
+}
+
+impl PrepareStep {
+    /// Step whose output this one needs
+    fn requires(&self) -> Option<PrepareStep> {
+        match self {
+            PrepareStep::Transcript => Some(PrepareStep::Audio),
+            _ => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            PrepareStep::Proxy => "Making preview proxy",
+            PrepareStep::Waveform => "Reading waveform",
+            PrepareStep::Thumbnails => "Making thumbnail",
+            PrepareStep::Audio => "Extracting audio",
+            PrepareStep::Transcript => "Transcribing",
+            PrepareStep::Analysis => "Analyzing video",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    Pending,
+    Running,
+    Done,
+    Skipped, // Doesn't apply to the clip, was already done, or a step it needs didn't finish
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepReport {
+    pub step: PrepareStep,
+    pub state: StepState,
+    pub detail: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Sent as "clip-prepare-progress" whenever a step changes state
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrepareProgress {
+    pub job_id: String,
+    pub clip_id: String,
+    pub steps: Vec<StepReport>, // Every step of the run, in order
+    pub percent: f64,
+    pub done: bool,
+}
+
+/// Result of the "prepare_clip" job
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrepareReport {
+    pub clip_id: String,
+    pub steps: Vec<StepReport>,
+    pub ready_for_ai: bool,
+    pub analysis: Option<VideoAnalysisResult>,
+}
+
+/// Steps for `options` in the order they run. The transcript brings in audio extraction,
+/// unless the clip already has a transcript
+fn planned_steps(clip: &Clip, options: &PrepareOptions) -> Vec<PrepareStep> {
+    let has_transcript = clip.transcript.as_ref().is_some_and(|t| !t.is_empty());
+    [
+        (options.proxy, PrepareStep::Proxy),
+        (options.waveform, PrepareStep::Waveform),
+        (options.thumbnails, PrepareStep::Thumbnails),
+        (options.transcript && !has_transcript, PrepareStep::Audio),
+        (options.transcript, PrepareStep::Transcript),
+        (options.analysis, PrepareStep::Analysis),
+    ]
+    .into_iter()
+    .filter_map(|(wanted, step)| wanted.then_some(step))
+    .collect()
+}
+
+/// API key for transcription and analysis: the stored key the editor uses for both
+fn api_key() -> Result<String> {
+    longterm_storage::get_gemini_api_key()?.ok_or_else(|| anyhow!("no API key configured; set one in the settings"))
+}
+
+fn warm(clip_id: &str, kind: ArtifactKind) -> Result<(StepState, String)> {
+    Ok(match clip_cache::warm_clip_artifact(clip_id, kind)? {
+        None => (StepState::Skipped, "doesn't apply to this clip".to_string()),
+        Some(WarmOutcome::UpToDate) => (StepState::Done, "already up to date".to_string()),
+        Some(WarmOutcome::Reused) => (StepState::Done, "reused from the source clip".to_string()),
+        Some(_) => (StepState::Done, "generated".to_string()),
+    })
+}
+
+/// Extract the clip's audio for transcription into its cache folder
+fn extract_audio(clip: &Clip, output: &Path) -> Result<(StepState, String)> {
+    if clip.latest_probe.as_ref().is_some_and(|p| p.audio_rate == 0) {
+        return Ok((StepState::Skipped, "clip has no audio".to_string()));
+    }
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {:?}", dir))?;
+    }
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error", "-i"]).arg(&clip.path).args(TRANSCRIPT_AUDIO_ARGS).args(["-f", "mp4", "-y"]).arg(output);
+    let result = jobs::output_cancellable(&mut cmd, None).with_context(|| "ffmpeg audio extraction failed to run")?;
+    if !result.status.success() {
+        let _ = fs::remove_file(output);
+        return Err(anyhow!(
+            "ffmpeg audio extraction failed (status {:?}): {}",
+            result.status.code(),
+            String::from_utf8_lossy(&result.stderr)
+        ));
+    }
+    let size = fs::metadata(output).map(|m| m.len()).unwrap_or(0);
+    Ok((StepState::Done, format!("{:.1} MB", size as f64 / 1_048_576.0)))
+}
+
+fn transcribe(clip: &Clip, audio: Option<TempAudio>, cancel: CancellationToken) -> Result<(StepState, String)> {
+    if clip.transcript.as_ref().is_some_and(|t| !t.is_empty()) {
+        return Ok((StepState::Skipped, "already transcribed".to_string()));
+    }
+    let audio = audio.ok_or_else(|| anyhow!("no audio was extracted"))?;
+    let key = api_key()?;
+    let service = TranscriptionService::new().with_cancel(cancel);
+    let result = upload_audio(&service, audio, &key)?;
+    if result.status != "completed" {
+        return Err(anyhow!("transcription {}: {}", result.status, result.error.unwrap_or_default()));
+    }
+    let count = result.segments.len();
+    project_file::set_clip_transcript(&clip.id, result.segments, result.language.as_deref())?;
+    Ok((StepState::Done, format!("{} segments", count)))
+}
+
+/// Gemini analysis of a video clip, saved next to its cached artifacts. A transcript made by
+/// the earlier step replaces the one Gemini guesses
+fn analyze(clip: &Clip, output: &Path, cancel: CancellationToken) -> Result<(StepState, String, Option<VideoAnalysisResult>)> {
+    if clip.r#type != ClipType::Video {
+        return Ok((StepState::Skipped, "only video clips are analyzed".to_string(), None));
+    }
+    let key = api_key()?;
+    let service = VideoAnalysisService::new().with_cancel(cancel);
+    let mut result = tauri::async_runtime::block_on(service.analyze_video_with_gemini(&clip.path.to_string_lossy(), &key))?;
+
+    let transcript = project_file::get_project()
+        .ok()
+        .flatten()
+        .and_then(|p| p.clips_map.get(&clip.id).and_then(|c| c.transcript.clone()));
+    let reused = transcript.is_some();
+    if let Some(transcript) = transcript {
+        result.transcript = Some(transcript.into_iter().map(|s| video_analysis::TranscriptSegment {
+            id: s.id,
+            start: s.start,
+            end: s.end,
+            text: s.text,
+            confidence: s.confidence,
+        }).collect());
+    }
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {:?}", dir))?;
+    }
+    fs::write(output, serde_json::to_string_pretty(&result)?).with_context(|| format!("failed to write {:?}", output))?;
+    let detail = format!("{} key moments{}", result.key_moments.len(), if reused { ", with the clip's transcript" } else { "" });
+    Ok((StepState::Done, detail, Some(result)))
+}
+
+/// The extracted transcript audio, deleted when this is dropped so a cancelled or failed run
+/// doesn't leave it in the clip's cache folder
+struct TempAudio(PathBuf);
+
+impl Drop for TempAudio {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Transcribe `audio` with OpenAI. The audio is deleted once the upload is over, whether it
+/// succeeded, timed out or was cancelled
+fn upload_audio(service: &TranscriptionService, audio: TempAudio, key: &str) -> Result<TranscriptionResult> {
+    tauri::async_runtime::block_on(service.transcribe_with_openai_whisper(&audio.0.to_string_lossy(), key))
+}
+
+fn emit_progress(app: &AppHandle, progress: &PrepareProgress) {
+    if let Err(e) = app.emit("clip-prepare-progress", progress) {
+        log::warn!("Failed to emit clip-prepare-progress: {}", e);
+    }
+}
+
+/// Run the steps `options` ask for on clip `clip_id`. A failed step skips the steps that need
+/// it and the rest still run; the clip is marked ready for AI when none failed. The job can be
+/// paused between steps
+pub fn prepare_clip(app: &AppHandle, clip_id: &str, options: &PrepareOptions, job: &JobContext) -> Result<PrepareReport> {
+    let project = project_file::get_project()
+        .map_err(|e| anyhow!(e))?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?.clone();
+    let steps = planned_steps(&clip, options);
+    if steps.is_empty() {
+        return Err(anyhow!("nothing to prepare; every step is turned off"));
+    }
+
+    let cache_dir = clip_cache::clip_dir(clip_id)?;
+    let audio_path = cache_dir.join("transcript_audio.m4a");
+    let mut audio = Some(TempAudio(audio_path.clone()));
+    let analysis_path: PathBuf = cache_dir.join("analysis.json");
+    let mut reports: Vec<StepReport> = steps
+        .iter()
+        .map(|&step| StepReport { step, state: StepState::Pending, detail: None, error: None })
+        .collect();
+    let progress = |reports: &[StepReport], done: bool| PrepareProgress {
+        job_id: job.id().to_string(),
+        clip_id: clip_id.to_string(),
+        steps: reports.to_vec(),
+        percent: 100.0 * reports.iter().filter(|r| !matches!(r.state, StepState::Pending | StepState::Running)).count() as f64
+            / reports.len() as f64,
+        done,
+    };
+    emit_progress(app, &progress(&reports, false));
+
+    job.set_pausable();
+    let mut analysis = None;
+    for (i, &step) in steps.iter().enumerate() {
+        job.checkpoint().map_err(|_| anyhow!("clip preparation cancelled"))?;
+        let blocked = step
+            .requires()
+            .filter(|&required| reports.iter().any(|r| r.step == required && r.state != StepState::Done));
+        if let Some(blocked) = blocked {
+            reports[i].state = StepState::Skipped;
+            reports[i].detail = Some(format!("needs {}, which didn't finish", format!("{:?}", blocked).to_lowercase()));
+            emit_progress(app, &progress(&reports, false));
+            continue;
+        }
+
+        reports[i].state = StepState::Running;
+        job.progress(i as f64 / steps.len() as f64, Some(step.label()));
+        emit_progress(app, &progress(&reports, false));
+        let result = match step {
+            PrepareStep::Proxy => warm(clip_id, ArtifactKind::Proxy),
+            PrepareStep::Waveform => warm(clip_id, ArtifactKind::Waveform),
+            PrepareStep::Thumbnails => warm(clip_id, ArtifactKind::Thumbnail),
+            PrepareStep::Audio => extract_audio(&clip, &audio_path),
+            PrepareStep::Transcript => transcribe(&clip, audio.take(), job.cancel_token()),
+            PrepareStep::Analysis => analyze(&clip, &analysis_path, job.cancel_token()).map(|(state, detail, result)| {
+                analysis = result;
+                (state, detail)
+            }),
+        };
+        match result {
+            Ok((state, detail)) => {
+                reports[i].state = state;
+                reports[i].detail = Some(detail);
+            }
+            Err(_) if job.is_cancelled() => return Err(anyhow!("clip preparation cancelled")),
+            Err(e) => {
+                log::warn!("Preparing {}: {:?} failed: {:#}", clip_id, step, e);
+                reports[i].state = StepState::Failed;
+                reports[i].error = Some(format!("{:#}", e));
+            }
+        }
+        emit_progress(app, &progress(&reports, false));
+    }
+    drop(audio);
+
+    let ready_for_ai = reports.iter().all(|r| r.state != StepState::Failed);
+    if ready_for_ai {
+        project_file::set_clip_ready_for_ai(clip_id, Some(chrono::Utc::now().to_rfc3339()))?;
+    }
+    emit_progress(app, &progress(&reports, true));
+    Ok(PrepareReport { clip_id: clip_id.to_string(), steps: reports, ready_for_ai, analysis })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::{self, HttpError};
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    /// A small extracted-audio file in a fresh temp dir
+    fn temp_audio() -> TempAudio {
+        let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("transcript_audio.m4a");
+        fs::write(&path, [0u8; 4096]).unwrap();
+        TempAudio(path)
+    }
+
+    fn http_error(error: &anyhow::Error) -> Option<&HttpError> {
+        error.chain().find_map(|cause| cause.downcast_ref::<HttpError>())
+    }
+
+    #[test]
+    fn audio_is_deleted_when_the_upload_times_out() {
+        let (url, stop) = http_client::silent_server("/v1/audio/transcriptions");
+        let audio = temp_audio();
+        let path = audio.0.clone();
+        let client = http_client::client_with_timeouts(Duration::from_secs(2), Duration::from_secs(1));
+        let service = TranscriptionService::new().with_openai_endpoint(&url, client);
+
+        let error = upload_audio(&service, audio, "key").unwrap_err();
+        stop.store(true, Ordering::Relaxed);
+        assert!(matches!(http_error(&error), Some(HttpError::Timeout(_))), "{:#}", error);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn audio_is_deleted_when_the_upload_is_cancelled() {
+        let (url, stop) = http_client::silent_server("/v1/audio/transcriptions");
+        let audio = temp_audio();
+        let path = audio.0.clone();
+        let client = http_client::client_with_timeouts(Duration::from_secs(2), Duration::from_secs(60));
+        let token = CancellationToken::new();
+        let service = TranscriptionService::new().with_openai_endpoint(&url, client).with_cancel(token.clone());
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            token.cancel();
+        });
+
+        let started = Instant::now();
+        let error = upload_audio(&service, audio, "key").unwrap_err();
+        canceller.join().unwrap();
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(http_error(&error), Some(&HttpError::Cancelled), "{:#}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,103 @@
+//! Conform variable-frame-rate media to a constant frame rate. Trimming and concatenating VFR
+//! video makes audio drift further out of sync with every cut; a CFR copy at the project rate
+//! edits cleanly. A remux can't fix the timestamps, so this always re-encodes.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::process::Command;
+use crate::bake::BakeSettings;
+use crate::ffmpeg;
+use crate::ids;
+use crate::jobs::JobContext;
+use crate::media_hash;
+use crate::media_protocol::{self, MediaScope};
+use crate::project_file::{self, Clip, ClipType};
+
+/// The conformed file may differ from the source duration by at most this much.
+const DURATION_TOLERANCE: f64 = 0.1;
+
+/// Re-encode clip `clip_id` at the project frame rate (`fps` filter, `-vsync cfr`) into the
+/// project's media folder and add it as a clip derived from the source. Segments keep playing
+/// the source until they're pointed at the result (`project_file::retarget_clip`).
+pub fn conform_vfr(clip_id: &str, job: &JobContext) -> Result<Clip> {
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let source = project
+    .clips_map
+    .get(clip_id)
+    .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+  if source.r#type != ClipType::Video {
+    return Err(anyhow!("only video clips can be conformed"));
+  }
+
+  let input = source.path.to_string_lossy().to_string();
+  let probe = ffmpeg::ffprobe(&input).context("ffprobe failed")?;
+  if !probe.is_vfr {
+    log::info!("{} already has a constant frame rate; conforming anyway", input);
+  }
+  let fps = project.fps();
+  let settings = BakeSettings::default();
+
+  let media_dir = project.media_dir()?;
+  fs::create_dir_all(&media_dir).with_context(|| format!("failed to create {:?}", media_dir))?;
+  let new_id = ids::new_id(ids::CLIP);
+  let stem = source.path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let output = media_dir.join(format!("conformed_{}_{}.mp4", stem, &new_id[new_id.len() - 8..]));
+  let tmp = ffmpeg::temp_output_path(&output);
+
+  job.progress(0.0, Some(format!("Conforming to {:.3} fps", fps).as_str()));
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1", "-i", &input])
+    .args(["-map", "0:v:0", "-map", &format!("0:a:{}?", source.audio_stream()), "-vf", &format!("fps={}", fps), "-vsync", "cfr"])
+    .args(["-c:v", "libx264", "-preset", &settings.preset, "-crf", &settings.crf.to_string(), "-pix_fmt", "yuv420p"])
+    // Audio is resampled against its own timestamps so gaps in the source don't shift it
+    .args(["-af", "aresample=async=1:first_pts=0", "-c:a", "aac", "-b:a", &settings.audio_bitrate])
+    .args(["-movflags", "+faststart", "-y"])
+    .arg(&tmp);
+  let result = ffmpeg::output_with_progress(&mut cmd, probe.duration, &mut |fraction| job.progress(fraction * 0.95, None))
+    .with_context(|| "ffmpeg conform failed to run")?;
+  if !result.status.success() {
+    let _ = fs::remove_file(&tmp);
+    return Err(anyhow!(
+      "ffmpeg conform failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+  fs::rename(&tmp, &output).with_context(|| "failed to move tmp output into place")?;
+
+  job.progress(0.95, Some("Verifying output"));
+  let conformed_probe = ffmpeg::ffprobe(&output.to_string_lossy()).context("ffprobe of conformed output failed")?;
+  if (conformed_probe.duration - probe.duration).abs() > DURATION_TOLERANCE {
+    let _ = fs::remove_file(&output);
+    return Err(anyhow!(
+      "conformed output is {:.3}s but the source is {:.3}s",
+      conformed_probe.duration,
+      probe.duration
+    ));
+  }
+
+  let clip = Clip {
+    id: new_id,
+    path: output.clone(),
+    latest_probe: Some(conformed_probe),
+    r#type: ClipType::Video,
+    transcript: source.transcript.clone(), // Same timebase, so the transcript still lines up
+    derived_from: Some(source.id.clone()),
+    hashes: media_hash::ingest_hashes(&output).ok(),
+    preview_lut: source.preview_lut.clone(),
+    stream_hashes: None,
+    creation_time: source.creation_time.clone(),
+    ready_for_ai: None,
+    transcript_language: source.transcript_language.clone(),
+    language_override: source.language_override.clone(),
+    has_album_art: None,
+    audio_stream_index: None, // The output has only the stream the source played
+  };
+  let clip = project_file::add_clip(clip).inspect_err(|_| {
+    let _ = fs::remove_file(&output);
+  })?;
+  media_protocol::register(&output, MediaScope::Project);
+  Ok(clip)
+}
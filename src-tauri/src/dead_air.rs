@@ -0,0 +1,91 @@
+//! Dead air: stretches of a recording where the picture is frozen and the audio is silent.
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use crate::ffmpeg::{self, Cut};
+use crate::intervals;
+
+/// Audio below this level counts as silence.
+pub const DEFAULT_NOISE_DB: f64 = -35.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DeadAirSignal {
+  Frozen, // No visual change
+  Silent, // No audio above the noise floor
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum DeadAirMode {
+  #[default]
+  Both,   // Frozen and silent at the same time
+  Either, // Frozen or silent
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadAirRange {
+  pub start: f64,
+  pub end: f64,
+  pub signals: Vec<DeadAirSignal>, // Which detectors cover this range
+}
+
+/// Combine detector ranges into dead-air ranges of at least `min_duration` seconds.
+pub fn combine(frozen: &[Cut], silent: &[Cut], mode: DeadAirMode, min_duration: f64) -> Vec<DeadAirRange> {
+  let ranges = match mode {
+    DeadAirMode::Both => intervals::intersect(frozen, silent),
+    DeadAirMode::Either => intervals::merge(frozen.iter().chain(silent).copied().collect(), 0.0),
+  };
+  ranges
+    .into_iter()
+    .filter(|(s, e)| e - s >= min_duration)
+    .map(|(start, end)| {
+      let mut signals = Vec::new();
+      if intervals::overlaps_any(frozen, (start, end)) {
+        signals.push(DeadAirSignal::Frozen);
+      }
+      if intervals::overlaps_any(silent, (start, end)) {
+        signals.push(DeadAirSignal::Silent);
+      }
+      DeadAirRange { start, end, signals }
+    })
+    .collect()
+}
+
+/// Run the freeze and silence detectors on `input` and combine them.
+/// Audio-only files have no frozen ranges, so `Both` finds nothing for them.
+pub fn detect_dead_air(input: &str, min_duration: f64, mode: DeadAirMode) -> Result<Vec<DeadAirRange>> {
+  let frozen = ffmpeg::detect_freezes(input, min_duration)?;
+  let silent = ffmpeg::detect_silences(input, DEFAULT_NOISE_DB, min_duration)?;
+  Ok(combine(&frozen, &silent, mode, min_duration))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spans(ranges: &[DeadAirRange]) -> Vec<(f64, f64)> {
+    ranges.iter().map(|r| (r.start, r.end)).collect()
+  }
+
+  #[test]
+  fn both_needs_a_frozen_picture_and_silence_at_once() {
+    let frozen = [(0.0, 10.0), (20.0, 30.0)];
+    let silent = [(5.0, 12.0), (25.0, 40.0)];
+    let ranges = combine(&frozen, &silent, DeadAirMode::Both, 5.0);
+    assert_eq!(spans(&ranges), vec![(5.0, 10.0), (25.0, 30.0)]);
+    assert!(ranges.iter().all(|r| r.signals == [DeadAirSignal::Frozen, DeadAirSignal::Silent]));
+    assert!(combine(&frozen, &silent, DeadAirMode::Both, 6.0).is_empty());
+  }
+
+  #[test]
+  fn either_reports_which_detector_fired() {
+    let ranges = combine(&[(0.0, 10.0)], &[(8.0, 12.0), (20.0, 25.0)], DeadAirMode::Either, 1.0);
+    assert_eq!(spans(&ranges), vec![(0.0, 12.0), (20.0, 25.0)]);
+    assert_eq!(ranges[0].signals, [DeadAirSignal::Frozen, DeadAirSignal::Silent]);
+    assert_eq!(ranges[1].signals, [DeadAirSignal::Silent]);
+  }
+
+  #[test]
+  fn audio_only_files_have_no_dead_air_in_both_mode() {
+    assert!(combine(&[], &[(0.0, 30.0)], DeadAirMode::Both, 1.0).is_empty());
+  }
+}
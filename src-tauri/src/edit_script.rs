@@ -0,0 +1,363 @@
+//! Edit scripts: a JSON list of operations applied in order to a project, for repeatable
+//! edits without the GUI (`gebo --run-script <script> <project>` or the run_edit_script
+//! command). Example:
+//!
+//! ```json
+//! { "version": 1, "steps": [
+//!   { "op": "import", "path": "interview.mp4", "as": "cam" },
+//!   { "op": "add_track", "name": "Main", "type": "Video", "as": "main" },
+//!   { "op": "add_segment", "track": "main", "clip": "cam", "start": 0, "end": 90 },
+//!   { "op": "cut_range", "start": 12.5, "end": 20 },
+//!   { "op": "export", "output": "out/interview.mp4" }
+//! ] }
+//! ```
+//!
+//! `as` names what a step creates so later steps can refer to it; ids already in the project
+//! work too. Relative paths are from the script's folder. The first failing step stops the
+//! run, and a report is written next to the script as `<script>.report.json`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ffmpeg::{self, ExportSettings};
+use crate::longterm_storage;
+use crate::media_project;
+use crate::project_file::{self, Effect, ProjectFile, ProjectSettings, TimeRange, TrackType};
+
+/// Script format version this build understands
+pub const SCRIPT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EditScript {
+    pub version: u32,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// Add a media file to the project's clips
+    Import {
+        path: PathBuf,
+        #[serde(default, rename = "as")]
+        alias: Option<String>,
+    },
+    AddTrack {
+        name: String,
+        r#type: TrackType,
+        #[serde(default, rename = "as")]
+        alias: Option<String>,
+    },
+    /// Append a segment of `clip` to `track`; the whole clip unless `start`/`end` are given
+    AddSegment {
+        track: String,
+        clip: String,
+        #[serde(default)]
+        start: Option<f64>,
+        #[serde(default)]
+        end: Option<f64>,
+        #[serde(default, rename = "as")]
+        alias: Option<String>,
+    },
+    /// Cut a range of the timeline, like an accepted agent cut
+    CutRange { start: f64, end: f64 },
+    /// Add `effect` to a segment, replacing an effect of the same type
+    SetEffect { track: String, segment: String, effect: Effect },
+    /// Render the project's timeline with its cuts; see ffmpeg::render_project
+    Export {
+        output: PathBuf,
+        #[serde(default)]
+        settings: Option<ExportSettings>,
+    },
+}
+
+impl Step {
+    fn op(&self) -> &'static str {
+        match self {
+            Step::Import { .. } => "import",
+            Step::AddTrack { .. } => "add_track",
+            Step::AddSegment { .. } => "add_segment",
+            Step::CutRange { .. } => "cut_range",
+            Step::SetEffect { .. } => "set_effect",
+            Step::Export { .. } => "export",
+        }
+    }
+
+    fn alias(&self) -> Option<&str> {
+        match self {
+            Step::Import { alias, .. } | Step::AddTrack { alias, .. } | Step::AddSegment { alias, .. } => alias.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+fn check_range(start: f64, end: f64) -> Result<()> {
+    if !start.is_finite() || !end.is_finite() || start < 0.0 || start >= end {
+        return Err(anyhow!("range {}..{} must be non-negative with start before end", start, end));
+    }
+    Ok(())
+}
+
+impl EditScript {
+    /// Parse a script, rejecting unknown operations and fields
+    pub fn parse(data: &str) -> Result<EditScript> {
+        let script: EditScript = serde_json::from_str(data).context("script does not match the edit script format")?;
+        script.validate()?;
+        Ok(script)
+    }
+
+    /// Checks that don't need the project: version, ranges, paths and unique aliases
+    pub fn validate(&self) -> Result<()> {
+        if self.version != SCRIPT_VERSION {
+            return Err(anyhow!("unsupported script version {} (expected {})", self.version, SCRIPT_VERSION));
+        }
+        if self.steps.is_empty() {
+            return Err(anyhow!("script has no steps"));
+        }
+        let mut aliases = HashSet::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let checked = match step {
+                Step::Import { path, .. } if path.as_os_str().is_empty() => Err(anyhow!("path is empty")),
+                Step::AddTrack { name, .. } if name.trim().is_empty() => Err(anyhow!("name is empty")),
+                Step::AddSegment { start, end, .. } => match (start, end) {
+                    (Some(start), Some(end)) => check_range(*start, *end),
+                    (Some(start), None) if *start < 0.0 => Err(anyhow!("start must not be negative")),
+                    _ => Ok(()),
+                },
+                Step::CutRange { start, end } => check_range(*start, *end),
+                Step::SetEffect { effect, .. } if effect.r#type.trim().is_empty() => Err(anyhow!("effect type is empty")),
+                Step::Export { output, .. } if output.as_os_str().is_empty() => Err(anyhow!("output is empty")),
+                _ => Ok(()),
+            };
+            let checked = checked.and_then(|_| match step.alias() {
+                Some(alias) if !aliases.insert(alias) => Err(anyhow!("\"{}\" is already used by an earlier step", alias)),
+                _ => Ok(()),
+            });
+            checked.map_err(|e| anyhow!("step {} ({}): {}", index, step.op(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of one step, as written to the report
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepReport {
+    pub index: usize,
+    pub op: String,
+    pub ok: bool,
+    pub result: Option<serde_json::Value>, // Ids created, export outcome, ...
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScriptReport {
+    pub script: String,
+    pub project: String,
+    pub ok: bool,
+    pub failed_step: Option<usize>, // Index of the step that stopped the run
+    pub error: Option<String>, // Why the script couldn't run at all (parse/validation), or the failed step's error
+    pub steps: Vec<StepReport>, // Steps that ran; the last one failed when `failed_step` is set
+    pub started_at: String,
+    pub finished_at: String,
+    pub report_path: String,
+}
+
+/// Where the report of `script` is written
+fn report_path(script: &Path) -> PathBuf {
+    let mut name = script.file_name().unwrap_or_default().to_os_string();
+    name.push(".report.json");
+    script.with_file_name(name)
+}
+
+/// Aliases defined so far, resolved to project ids
+#[derive(Default)]
+struct Names {
+    clips: HashMap<String, String>,
+    tracks: HashMap<String, String>,
+    segments: HashMap<String, String>,
+}
+
+fn resolve<'a>(names: &'a HashMap<String, String>, name: &'a str) -> &'a str {
+    names.get(name).map(|id| id.as_str()).unwrap_or(name)
+}
+
+fn current_project() -> Result<ProjectFile> {
+    project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))
+}
+
+/// Open `project_path`, or create an empty project there when the file doesn't exist yet
+fn open_project(project_path: &Path) -> Result<()> {
+    if project_path.is_file() {
+        project_file::load_project(project_path.to_string_lossy().to_string())?;
+        return Ok(());
+    }
+    let title = project_path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    project_file::new_project(ProjectFile {
+        title,
+        version: project_file::PROJECT_VERSION,
+        clips_map: HashMap::new(),
+        tracks_map: HashMap::new(),
+        path: Some(project_path.to_path_buf()),
+        markers: Vec::new(),
+        accepted_cuts: Vec::new(),
+        settings: ProjectSettings::default(),
+        view_state: Default::default(),
+        retention_risks: Vec::new(),
+    })?;
+    Ok(())
+}
+
+/// Apply `step` to `project`, which holds the edits of the steps before. Exports render the
+/// current project, so `project` is committed before one runs
+fn run_step(step: &Step, base: &Path, names: &mut Names, project: &mut ProjectFile) -> Result<serde_json::Value> {
+    let absolute = |path: &Path| if path.is_absolute() { path.to_path_buf() } else { base.join(path) };
+    match step {
+        Step::Import { path, alias } => {
+            let clip = project_file::import_media(&absolute(path))?;
+            let clip_id = match project.clips_at_path(&clip.path).first() {
+                Some(existing) => existing.id.clone(),
+                None => {
+                    let clip_id = clip.id.clone();
+                    project.clips_map.insert(clip_id.clone(), clip);
+                    clip_id
+                }
+            };
+            if let Some(alias) = alias {
+                names.clips.insert(alias.clone(), clip_id.clone());
+            }
+            Ok(serde_json::json!({ "clip_id": clip_id }))
+        }
+        Step::AddTrack { name, r#type, alias } => {
+            let order = project.tracks_map.values().map(|t| t.order + 1).max().unwrap_or(0);
+            let track = media_project::empty_track(name, r#type.clone(), order);
+            let track_id = track.id.clone();
+            project.tracks_map.insert(track_id.clone(), track);
+            if let Some(alias) = alias {
+                names.tracks.insert(alias.clone(), track_id.clone());
+            }
+            Ok(serde_json::json!({ "track_id": track_id }))
+        }
+        Step::AddSegment { track, clip, start, end, alias } => {
+            let clip_id = resolve(&names.clips, clip);
+            let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip))?;
+            let mut segment = media_project::full_segment(clip)?;
+            segment.start = start.unwrap_or(segment.start);
+            segment.end = end.unwrap_or(segment.end);
+            if let Some(reason) = segment.validation_error() {
+                return Err(anyhow!("invalid segment: {}", reason));
+            }
+            let segment_id = segment.id.clone();
+            let track_id = resolve(&names.tracks, track).to_string();
+            project.tracks_map.get_mut(&track_id)
+                .ok_or_else(|| anyhow!("track not found: {}", track))?
+                .segments.push(segment);
+            if let Some(alias) = alias {
+                names.segments.insert(alias.clone(), segment_id.clone());
+            }
+            Ok(serde_json::json!({ "segment_id": segment_id }))
+        }
+        Step::CutRange { start, end } => {
+            project.accepted_cuts.push(TimeRange { start: *start, end: *end });
+            let cuts = project.accepted_cuts.len();
+            Ok(serde_json::json!({ "cuts": cuts }))
+        }
+        Step::SetEffect { track, segment, effect } => {
+            let segment_id = resolve(&names.segments, segment);
+            let segment = project.tracks_map.get_mut(resolve(&names.tracks, track))
+                .ok_or_else(|| anyhow!("track not found: {}", track))?
+                .segments.iter_mut()
+                .find(|s| s.id == segment_id)
+                .ok_or_else(|| anyhow!("segment not found: {}", segment))?;
+            segment.effects.retain(|e| e.r#type != effect.r#type);
+            segment.effects.push(effect.clone());
+            Ok(serde_json::json!({ "effects": effect.r#type }))
+        }
+        Step::Export { output, settings } => {
+            project_file::commit_project(project.clone())?;
+            let mut settings = settings.clone().unwrap_or_default();
+            settings.hdr_mode = project.hdr_mode(settings.hdr_mode);
+            let output = absolute(output);
+            if let Some(dir) = output.parent() {
+                fs::create_dir_all(dir).with_context(|| format!("failed to create {:?}", dir))?;
+            }
+            let output = output.to_string_lossy().to_string();
+            let encoder = ffmpeg::render_project(project, &output, &settings, &mut |_| {})?;
+            let inputs = project.clips_map.values().map(|c| c.path.to_string_lossy().to_string()).collect();
+            if let Err(e) = longterm_storage::add_export_record("script", &output, inputs) {
+                log::warn!("Failed to record export: {}", e);
+            }
+            Ok(serde_json::json!({ "output": output, "encoder": encoder }))
+        }
+    }
+}
+
+/// Run the script at `script_path` against the project at `project_path` (created when
+/// missing). The steps edit a copy of the project, which is committed as one undo step and
+/// saved when every step succeeded (and before each export). The project becomes the current
+/// project. `on_step` is told about each step before it runs. The report is returned and
+/// written next to the script; Err only when the report itself can't be written
+pub fn run_edit_script(
+    script_path: &Path,
+    project_path: &Path,
+    on_step: &mut dyn FnMut(usize, usize, &Step),
+) -> Result<ScriptReport> {
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let mut steps = Vec::new();
+    let mut failed_step = None;
+
+    let prepared = fs::read_to_string(script_path)
+        .with_context(|| format!("failed to read {:?}", script_path))
+        .and_then(|data| EditScript::parse(&data))
+        .and_then(|script| open_project(project_path).map(|_| script));
+    let error = match prepared {
+        Err(e) => Some(format!("{:#}", e)),
+        Ok(script) => {
+            let base = script_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let mut names = Names::default();
+            let mut error = None;
+            let mut project = current_project();
+            for (index, step) in script.steps.iter().enumerate() {
+                on_step(index, script.steps.len(), step);
+                let result = project
+                    .as_mut()
+                    .map_err(|e| anyhow!("{:#}", e))
+                    .and_then(|project| run_step(step, &base, &mut names, project));
+                let (ok, result, step_error) = match result {
+                    Ok(value) => (true, Some(value), None),
+                    Err(e) => (false, None, Some(format!("{:#}", e))),
+                };
+                steps.push(StepReport { index, op: step.op().to_string(), ok, result, error: step_error.clone() });
+                if let Some(e) = step_error {
+                    failed_step = Some(index);
+                    error = Some(format!("step {} ({}): {}", index, step.op(), e));
+                    break;
+                }
+            }
+            if let (None, Ok(project)) = (&error, project) {
+                error = project_file::commit_project(project).err().map(|e| format!("failed to save the project: {:#}", e));
+            }
+            error
+        }
+    };
+
+    let report_path = report_path(script_path);
+    let report = ScriptReport {
+        script: script_path.to_string_lossy().to_string(),
+        project: project_path.to_string_lossy().to_string(),
+        ok: error.is_none(),
+        failed_step,
+        error,
+        steps,
+        started_at,
+        finished_at: chrono::Utc::now().to_rfc3339(),
+        report_path: report_path.to_string_lossy().to_string(),
+    };
+    let data = serde_json::to_string_pretty(&report).context("failed to serialize the script report")?;
+    fs::write(&report_path, data).with_context(|| format!("failed to write {:?}", report_path))?;
+    Ok(report)
+}
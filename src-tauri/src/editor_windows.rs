@@ -0,0 +1,199 @@
+//! Editor windows, one per project. A window's label comes from a hash of its project's path
+//! ("editor-<hash>", or "editor-untitled" for a project that was never saved) and is recorded
+//! in a registry, so opening a project that already has a window focuses it while another
+//! project gets a window of its own. The backend holds one loaded project: focusing an editor
+//! loads its project again (saved projects are on disk after every change), and closing the
+//! last window of the loaded project unloads it.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder, Window, WindowEvent};
+use crate::project_file;
+use crate::project_lock;
+
+/// Prefix of editor window labels, as granted in capabilities/default.json
+const EDITOR_PREFIX: &str = "editor-";
+
+/// Project of each open editor window, by label. Read by project_file, which has no app handle
+static WINDOWS: Mutex<BTreeMap<String, Option<PathBuf>>> = Mutex::new(BTreeMap::new());
+
+/// Label for a new editor window on the project at `project_path`. Paths naming the same file
+/// get the same label
+pub fn label_for(project_path: Option<&Path>) -> String {
+  let Some(path) = project_path else {
+    return format!("{}untitled", EDITOR_PREFIX);
+  };
+  let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+  let digest = Sha256::digest(path.to_string_lossy().as_bytes());
+  let hash: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+  format!("{}{}", EDITOR_PREFIX, hash)
+}
+
+/// Label of the editor window open on the project at `project_path`. Looked up by project, as a
+/// project saved under a new name keeps the window (and label) it had
+fn label_of(project_path: Option<&Path>) -> Option<String> {
+  let key = label_for(project_path);
+  let windows = WINDOWS.lock().unwrap_or_else(|e| e.into_inner());
+  windows.iter().find(|(_, path)| label_for(path.as_deref()) == key).map(|(label, _)| label.clone())
+}
+
+/// Focus the editor of the project at `project_path` if it is open, otherwise open one on the
+/// loaded project, which the caller has just loaded from `project_path`
+pub fn open(app: &AppHandle, project_path: Option<&str>) -> tauri::Result<()> {
+  let project_path = project_path.map(PathBuf::from);
+  if let Some(window) = label_of(project_path.as_deref()).and_then(|label| app.get_webview_window(&label)) {
+    let _ = window.unminimize();
+    window.show()?;
+    return window.set_focus();
+  }
+
+  // A window saved under another name may still carry the label this project hashes to
+  let base = label_for(project_path.as_deref());
+  let label = std::iter::once(base.clone())
+    .chain((2..).map(|n| format!("{}-{}", base, n)))
+    .find(|label| app.get_webview_window(label).is_none())
+    .unwrap_or(base);
+  let title = match &project_path {
+    Some(path) => format!(
+      "Video Editor - {}",
+      path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default()
+    ),
+    None => "Video Editor".to_string(),
+  };
+  WebviewWindowBuilder::new(app, &label, WebviewUrl::App("/editor".into()))
+    .title(title)
+    .fullscreen(false)
+    .build()?;
+  WINDOWS.lock().unwrap_or_else(|e| e.into_inner()).insert(label, project_path);
+  Ok(())
+}
+
+/// Whether an editor window is open on the project at `project_path`
+pub(crate) fn is_open_for(project_path: Option<&Path>) -> bool {
+  label_of(project_path).is_some()
+}
+
+/// Whether any editor window is open
+pub(crate) fn any_open() -> bool {
+  !WINDOWS.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+}
+
+/// Point the editor of the project at `from` at `to`, which it was just saved as
+pub(crate) fn project_moved(from: Option<&Path>, to: &Path) {
+  if let Some(label) = label_of(from) {
+    WINDOWS.lock().unwrap_or_else(|e| e.into_inner()).insert(label, Some(to.to_path_buf()));
+  }
+}
+
+/// Project of the editor window `label`; None for windows that aren't editors
+fn project_of(label: &str) -> Option<Option<PathBuf>> {
+  WINDOWS.lock().unwrap_or_else(|e| e.into_inner()).get(label).cloned()
+}
+
+/// Label of the editor window for the loaded project, if a project is loaded and has one
+fn loaded_label() -> Option<String> {
+  project_file::get_project().ok().flatten().and_then(|project| label_of(project.path.as_deref()))
+}
+
+/// Whether closing the loaded project would lose work. Saved projects are written on every
+/// change, so only projects without a path can be dirty
+pub(crate) fn has_unsaved_changes() -> bool {
+  matches!(project_file::get_project(), Ok(Some(project)) if project.path.is_none())
+}
+
+/// Whether `label` is the editor of the loaded project, so closing it closes the project
+pub(crate) fn holds_current_project(label: &str) -> bool {
+  loaded_label().as_deref() == Some(label)
+}
+
+/// Load the project of the editor `window` when it isn't the loaded one, and tell the window
+/// to refetch it ("project-activated")
+fn activate(window: &Window, project_path: Option<&Path>) {
+  if loaded_label().as_deref() == Some(window.label()) {
+    return;
+  }
+  let Some(path) = project_path else {
+    // Guarded against by project_file::check_editor_free
+    log::warn!("Editor {} lost its unsaved project to another one", window.label());
+    return;
+  };
+  match project_file::load_project(path.to_string_lossy().to_string()) {
+    Ok(project) => {
+      if let Err(e) = window.emit_to(window.label(), "project-activated", &project) {
+        log::warn!("Failed to emit project-activated: {}", e);
+      }
+    }
+    Err(e) => log::warn!("Failed to load {:?} for its editor: {}", path, e),
+  }
+}
+
+/// Window events for editors. Focusing one loads its project. Once destroyed it leaves the
+/// registry and unloads its project if that is the loaded one, or else drops the project's
+/// lock; closing it while that would lose work is held back by `exit_guard`
+pub fn handle_event(window: &Window, event: &WindowEvent) {
+  let Some(project_path) = project_of(window.label()) else {
+    return;
+  };
+  match event {
+    WindowEvent::Focused(true) => activate(window, project_path.as_deref()),
+    WindowEvent::Destroyed => {
+      let loaded = loaded_label().as_deref() == Some(window.label());
+      WINDOWS.lock().unwrap_or_else(|e| e.into_inner()).remove(window.label());
+      if loaded {
+        if let Err(e) = project_file::close_project() {
+          log::warn!("Failed to close project: {}", e);
+        }
+      } else if let Some(path) = &project_path {
+        project_lock::release(path);
+      }
+      if let Some(main_window) = window.app_handle().get_webview_window("main") {
+        let _ = main_window.set_focus();
+      }
+    }
+    _ => {}
+  }
+}
+
+/// Close the editor window without asking again, after the UI has dealt with what it would lose
+pub fn close(window: &Window) -> tauri::Result<()> {
+  window.destroy()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn labels_follow_the_project_file() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.gebo");
+    let b = dir.join("b.gebo");
+    std::fs::write(&a, "{}").unwrap();
+    std::fs::write(&b, "{}").unwrap();
+
+    let label = label_for(Some(&a));
+    assert!(label.starts_with(EDITOR_PREFIX));
+    assert!(label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    assert_eq!(label_for(Some(&dir.join(".").join("a.gebo"))), label);
+    assert_ne!(label_for(Some(&b)), label);
+    assert_eq!(label_for(None), "editor-untitled");
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn a_project_saved_under_a_new_name_keeps_its_window() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    let (old, new) = (dir.join("old.gebo"), dir.join("new.gebo"));
+    let label = label_for(Some(&old));
+    WINDOWS.lock().unwrap().insert(label.clone(), Some(old.clone()));
+
+    project_moved(Some(&old), &new);
+    assert_eq!(label_of(Some(&new)), Some(label.clone()));
+    assert!(!is_open_for(Some(&old)));
+    assert_eq!(project_of(&label), Some(Some(new)));
+    WINDOWS.lock().unwrap().remove(&label);
+  }
+}
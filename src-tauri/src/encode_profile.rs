@@ -0,0 +1,222 @@
+//! Encode profiles: a checksummed JSON file pinning the export preset, the oldest ffmpeg allowed,
+//! the HDR tone-mapping mode and the loudness target, so everyone on a team exports with the same
+//! settings. The imported profile is kept in the app settings. Export validation warns when this
+//! machine can't satisfy it, and exports made with its settings record its id in their sidecar
+//! and history entry.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use crate::ffmpeg::{self, ExportPreset, ExportSettings, HdrMode, LoudnessTarget};
+use crate::ids;
+use crate::longterm_storage;
+
+/// Marks encode profile files; `PROFILE_VERSION` is bumped on incompatible changes.
+const PROFILE_FORMAT: &str = "gebo-encode-profile";
+const PROFILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncodeProfile {
+  #[serde(default)]
+  pub format: String, // Always "gebo-encode-profile"; set by `seal`
+  #[serde(default)]
+  pub version: u32,
+  #[serde(default)]
+  pub id: String, // Generated by `seal` when empty
+  pub name: String,
+  pub preset: ExportPreset,
+  #[serde(default)]
+  pub min_ffmpeg_version: Option<String>, // e.g. "6.1"
+  #[serde(default)]
+  pub hdr_mode: HdrMode,
+  #[serde(default)]
+  pub loudness_target: Option<LoudnessTarget>, // None: exports aren't normalized
+  #[serde(default)]
+  pub checksum: String, // SHA-256 of the profile with an empty checksum, see `seal`
+}
+
+impl EncodeProfile {
+  /// The export settings this profile pins
+  pub fn settings(&self) -> ExportSettings {
+    ExportSettings {
+      hdr_mode: self.hdr_mode,
+      normalize_loudness: self.loudness_target.clone(),
+      ..self.preset.settings.clone()
+    }
+  }
+
+  /// Whether `settings` are the ones this profile pins. Pan, track effects and the audio stream
+  /// come from the project rather than the preset, so they don't count
+  pub fn matches(&self, settings: &ExportSettings) -> bool {
+    let pinned = ExportSettings {
+      pan: settings.pan,
+      track_effects: settings.track_effects.clone(),
+      audio_stream: settings.audio_stream,
+      ..self.settings()
+    };
+    &pinned == settings
+  }
+
+  /// SHA-256 over the profile as JSON with an empty checksum, see `canonical_json`
+  fn compute_checksum(&self) -> Result<String> {
+    let unsealed = EncodeProfile { checksum: String::new(), ..self.clone() };
+    let value = serde_json::to_value(&unsealed).context("failed to serialize encode profile")?;
+    let mut hasher = Sha256::new();
+    hasher.update(canonical_json(&value).as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+  }
+
+  /// Fill in the format, version, a new id when there is none, and the checksum
+  pub fn seal(mut self) -> Result<Self> {
+    self.format = PROFILE_FORMAT.to_string();
+    self.version = PROFILE_VERSION;
+    if self.id.is_empty() {
+      self.id = ids::new_id(ids::PROFILE);
+    }
+    self.checksum = self.compute_checksum()?;
+    Ok(self)
+  }
+
+  /// Err unless this is an intact profile this version can apply
+  fn verify(&self) -> Result<()> {
+    if self.format != PROFILE_FORMAT {
+      return Err(anyhow!("not an encode profile (format {:?})", self.format));
+    }
+    if self.version > PROFILE_VERSION {
+      return Err(anyhow!("encode profile version {} is newer than this app supports ({})", self.version, PROFILE_VERSION));
+    }
+    if self.checksum != self.compute_checksum()? {
+      return Err(anyhow!("encode profile {:?} was modified or damaged: checksum mismatch", self.name));
+    }
+    if let Some(min) = &self.min_ffmpeg_version {
+      release(min).ok_or_else(|| anyhow!("invalid minimum ffmpeg version {:?}", min))?;
+    }
+    self.settings().validate_format()
+  }
+}
+
+/// `value` as JSON with object keys sorted, so the checksum depends neither on the field order
+/// of the file nor on the iteration order of maps (effect parameters)
+fn canonical_json(value: &serde_json::Value) -> String {
+  match value {
+    serde_json::Value::Object(map) => {
+      let mut entries: Vec<(&String, &serde_json::Value)> = map.iter().collect();
+      entries.sort_by(|a, b| a.0.cmp(b.0));
+      let fields: Vec<String> = entries
+        .into_iter()
+        .map(|(key, value)| format!("{}:{}", serde_json::Value::String(key.clone()), canonical_json(value)))
+        .collect();
+      format!("{{{}}}", fields.join(","))
+    }
+    serde_json::Value::Array(items) => format!("[{}]", items.iter().map(canonical_json).collect::<Vec<String>>().join(",")),
+    other => other.to_string(),
+  }
+}
+
+/// Numeric parts of an ffmpeg release ("n6.1.1-3" -> [6, 1, 1]); None for git builds
+fn release(version: &str) -> Option<Vec<u32>> {
+  let version = version.strip_prefix('n').unwrap_or(version);
+  let numeric: String = version.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+  let parts: Vec<u32> = numeric.split('.').map_while(|p| p.parse().ok()).collect();
+  (!parts.is_empty()).then_some(parts)
+}
+
+/// Whether release `version` comes before `min`; missing parts count as 0
+fn older_than(version: &[u32], min: &[u32]) -> bool {
+  let len = version.len().max(min.len());
+  let padded = |parts: &[u32]| (0..len).map(|i| parts.get(i).copied().unwrap_or(0)).collect::<Vec<u32>>();
+  padded(version) < padded(min)
+}
+
+/// The profile in the app settings, if one was imported
+pub fn active_profile() -> Option<EncodeProfile> {
+  longterm_storage::get_settings().ok()?.encode_profile
+}
+
+/// Id of the active profile when `settings` are the ones it pins
+pub fn profile_for(settings: &ExportSettings) -> Option<String> {
+  active_profile().filter(|profile| profile.matches(settings)).map(|profile| profile.id)
+}
+
+/// Read and check the profile at `path` and make it the active one
+pub fn import_encode_profile(path: &Path) -> Result<EncodeProfile> {
+  let data = fs::read_to_string(path).with_context(|| format!("failed to read encode profile {:?}", path))?;
+  let profile: EncodeProfile = serde_json::from_str(&data).with_context(|| "invalid encode profile format")?;
+  profile.verify()?;
+  let mut settings = longterm_storage::get_settings()?;
+  settings.encode_profile = Some(profile.clone());
+  longterm_storage::set_settings(settings)?;
+  Ok(profile)
+}
+
+/// Write `profile` (sealed first), or the active one when None, to `path` for sharing
+pub fn export_encode_profile(path: &Path, profile: Option<EncodeProfile>) -> Result<EncodeProfile> {
+  let profile = match profile {
+    Some(profile) => profile.seal()?,
+    None => active_profile().ok_or_else(|| anyhow!("no encode profile has been imported"))?,
+  };
+  profile.verify()?;
+  let data = serde_json::to_string_pretty(&profile).context("failed to serialize encode profile")?;
+  fs::write(path, data).with_context(|| format!("failed to write encode profile {:?}", path))?;
+  Ok(profile)
+}
+
+/// Stop applying the active profile
+pub fn clear_encode_profile() -> Result<()> {
+  let mut settings = longterm_storage::get_settings()?;
+  settings.encode_profile = None;
+  longterm_storage::set_settings(settings)
+}
+
+/// What keeps this machine from exporting exactly as `profile` asks: an ffmpeg older than its
+/// minimum, or encoders it needs that this ffmpeg can't run
+pub fn environment_warnings(profile: &EncodeProfile) -> Vec<String> {
+  let mut warnings = Vec::new();
+  if let Some(min) = profile.min_ffmpeg_version.as_deref() {
+    match ffmpeg::ffmpeg_version() {
+      None => warnings.push(format!("Encode profile {:?} needs ffmpeg {} or newer, but ffmpeg was not found", profile.name, min)),
+      Some(local) => match (release(&local), release(min)) {
+        (Some(version), Some(min_version)) if older_than(&version, &min_version) => warnings.push(format!(
+          "ffmpeg {} is older than {}, the oldest encode profile {:?} allows",
+          local, min, profile.name
+        )),
+        (None, _) => warnings.push(format!(
+          "ffmpeg {} is a development build; encode profile {:?} needs release {} or newer and this can't be checked",
+          local, profile.name, min
+        )),
+        _ => {}
+      },
+    }
+  }
+
+  let settings = profile.settings();
+  let available = ffmpeg::detect_encoders();
+  for encoder in settings.encoder_names() {
+    let usable = match settings.hw_encoder() {
+      Some(hw) if hw == encoder => ffmpeg::detect_hw_encoders().iter().any(|e| e == hw),
+      _ => available.iter().any(|e| e == encoder),
+    };
+    if !usable {
+      warnings.push(format!("Encoder {} required by encode profile {:?} is not available here", encoder, profile.name));
+    }
+  }
+  warnings
+}
+
+/// Export validation concerns about the active profile: the environment can't satisfy it, or
+/// `settings` aren't the ones it pins
+pub fn export_warnings(settings: &ExportSettings) -> Vec<String> {
+  let Some(profile) = active_profile() else {
+    return Vec::new();
+  };
+  let mut warnings = environment_warnings(&profile);
+  if !profile.matches(settings) {
+    warnings.push(format!(
+      "Export settings differ from encode profile {:?} ({}); the output won't be comparable with the team's",
+      profile.name, profile.preset.name
+    ));
+  }
+  warnings
+}
@@ -0,0 +1,363 @@
+//! Engagement scoring: how much is happening in each N-second window of a clip, from the
+//! speech rate and keyword novelty of its transcript, the variance of its audio level and how
+//! often the picture cuts. Deterministic, so the same clip always gives the same "boring bits".
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use crate::ffmpeg;
+use crate::longterm_storage;
+use crate::media_hash;
+use crate::project_file::{self, Clip};
+use crate::transcription::TranscriptSegment;
+use crate::waveform;
+
+/// Shortest window that can be scored
+pub const MIN_WINDOW_SECONDS: f64 = 1.0;
+/// Scene score above which a frame counts as a cut
+const SCENE_THRESHOLD: f64 = 0.3;
+/// Words shorter than this don't count towards novelty ("the", "and", ...)
+const MIN_KEYWORD_LEN: usize = 4;
+
+/// Weight of each feature in the score. Features a clip doesn't have (no transcript, no
+/// picture) are left out and the rest re-weighted
+const SPEECH_WEIGHT: f64 = 0.3;
+const ENERGY_WEIGHT: f64 = 0.25;
+const SCENE_WEIGHT: f64 = 0.2;
+const NOVELTY_WEIGHT: f64 = 0.25;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EngagementWindow {
+    pub start: f64,
+    pub end: f64,
+    pub score: f32, // 0 (nothing happening) to 1 (the busiest window of the clip)
+    pub speech_rate: Option<f64>, // Words per second; None without a transcript
+    pub energy_variance: Option<f64>, // Standard deviation of the audio peaks (0..1); None without audio
+    pub scene_changes: Option<usize>, // Cuts in the window; None without a picture
+    pub novelty: Option<f64>, // Share of keywords not said earlier in the clip; None without a transcript
+}
+
+impl EngagementWindow {
+    /// Plain-language reasons this window scored low, for the cut rationale
+    pub fn explain(&self) -> String {
+        let mut reasons = Vec::new();
+        match self.speech_rate {
+            Some(rate) if rate < 0.5 => reasons.push("almost nothing is said".to_string()),
+            Some(rate) if rate < 1.5 => reasons.push(format!("slow speech ({:.1} words/s)", rate)),
+            _ => {}
+        }
+        if self.energy_variance.is_some_and(|v| v < 0.05) {
+            reasons.push("flat audio level".to_string());
+        }
+        if self.scene_changes == Some(0) {
+            reasons.push("no change of shot".to_string());
+        }
+        if self.novelty.is_some_and(|n| n < 0.3) && self.speech_rate.is_some_and(|r| r >= 0.5) {
+            reasons.push("mostly repeats earlier points".to_string());
+        }
+        if reasons.is_empty() {
+            reasons.push("less going on than the rest of the clip".to_string());
+        }
+        format!("Engagement {:.2}: {}", self.score, reasons.join(", "))
+    }
+}
+
+/// A transcript word and the time it is said, in clip seconds
+struct TimedWord {
+    time: f64,
+    word: String,
+}
+
+/// Words of `transcript` with their times. Segments without word timings spread their words
+/// evenly over the segment
+fn timed_words(transcript: &[TranscriptSegment]) -> Vec<TimedWord> {
+    let mut words = Vec::new();
+    for segment in transcript {
+        if !segment.words.is_empty() {
+            words.extend(segment.words.iter().map(|w| TimedWord { time: (w.start + w.end) / 2.0, word: w.word.clone() }));
+            continue;
+        }
+        let text: Vec<&str> = segment.text.split_whitespace().collect();
+        let step = (segment.end - segment.start).max(0.0) / text.len().max(1) as f64;
+        words.extend(text.iter().enumerate().map(|(i, word)| TimedWord {
+            time: segment.start + (i as f64 + 0.5) * step,
+            word: word.to_string(),
+        }));
+    }
+    words.sort_by(|a, b| a.time.total_cmp(&b.time));
+    words
+}
+
+/// Lowercased word without punctuation, if it's long enough to carry meaning
+fn keyword(word: &str) -> Option<String> {
+    let word: String = word.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect();
+    (word.chars().count() >= MIN_KEYWORD_LEN).then_some(word)
+}
+
+/// Standard deviation of `peaks` scaled to 0..1
+fn peak_deviation(peaks: &[i16]) -> f64 {
+    if peaks.is_empty() {
+        return 0.0;
+    }
+    let values: Vec<f64> = peaks.iter().map(|p| (*p as f64).abs() / i16::MAX as f64).collect();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+/// `value` relative to the largest in the clip
+fn relative(value: f64, max: f64) -> f64 {
+    if max > 0.0 { (value / max).clamp(0.0, 1.0) } else { 0.0 }
+}
+
+/// Score `duration` seconds of media in `window_secs` windows. `transcript` is None when the
+/// clip wasn't transcribed, `peaks` (one per `waveform::BUCKET_SECONDS`) None without audio and
+/// `scene_changes` None without a picture
+pub fn score_windows(
+    duration: f64,
+    window_secs: f64,
+    transcript: Option<&[TranscriptSegment]>,
+    peaks: Option<&[i16]>,
+    scene_changes: Option<&[f64]>,
+) -> Result<Vec<EngagementWindow>> {
+    if !window_secs.is_finite() || window_secs < MIN_WINDOW_SECONDS {
+        return Err(anyhow!("window must be at least {}s, got {}", MIN_WINDOW_SECONDS, window_secs));
+    }
+    if transcript.is_none() && peaks.is_none() && scene_changes.is_none() {
+        return Err(anyhow!("nothing to score: no transcript, audio or picture"));
+    }
+    let words = transcript.map(timed_words);
+
+    let mut windows = Vec::new();
+    let mut seen = HashSet::new();
+    let mut start = 0.0;
+    while start < duration {
+        let end = (start + window_secs).min(duration);
+        let length = end - start;
+
+        let (speech_rate, novelty) = match &words {
+            Some(words) => {
+                let said: Vec<String> = words.iter()
+                    .filter(|w| w.time >= start && w.time < end)
+                    .filter_map(|w| keyword(&w.word))
+                    .collect();
+                let count = words.iter().filter(|w| w.time >= start && w.time < end).count();
+                let novel = said.iter().filter(|k| !seen.contains(*k)).collect::<HashSet<_>>().len();
+                let novelty = if said.is_empty() { 0.0 } else { novel as f64 / said.len() as f64 };
+                seen.extend(said);
+                (Some(count as f64 / length), Some(novelty))
+            }
+            None => (None, None),
+        };
+        let energy_variance = peaks.map(|peaks| {
+            let from = ((start / waveform::BUCKET_SECONDS) as usize).min(peaks.len());
+            let to = ((end / waveform::BUCKET_SECONDS).ceil() as usize).clamp(from, peaks.len());
+            peak_deviation(&peaks[from..to])
+        });
+        let scene_changes = scene_changes.map(|times| times.iter().filter(|t| **t >= start && **t < end).count());
+
+        windows.push(EngagementWindow { start, end, score: 0.0, speech_rate, energy_variance, scene_changes, novelty });
+        start = end;
+    }
+
+    // Rates are compared against the clip's own busiest window, so a calm interview and a fast
+    // vlog both spread over 0..1
+    let max_of = |f: &dyn Fn(&EngagementWindow) -> Option<f64>| windows.iter().filter_map(f).fold(0.0, f64::max);
+    let max_speech = max_of(&|w| w.speech_rate);
+    let max_energy = max_of(&|w| w.energy_variance);
+    let max_scenes = max_of(&|w| w.scene_changes.map(|n| n as f64 / (w.end - w.start)));
+    for window in &mut windows {
+        let length = window.end - window.start;
+        let features = [
+            (window.speech_rate.map(|r| relative(r, max_speech)), SPEECH_WEIGHT),
+            (window.energy_variance.map(|v| relative(v, max_energy)), ENERGY_WEIGHT),
+            (window.scene_changes.map(|n| relative(n as f64 / length, max_scenes)), SCENE_WEIGHT),
+            (window.novelty, NOVELTY_WEIGHT),
+        ];
+        let weight: f64 = features.iter().filter(|(v, _)| v.is_some()).map(|(_, w)| w).sum();
+        let total: f64 = features.iter().filter_map(|(v, w)| v.map(|v| v * w)).sum();
+        window.score = (total / weight) as f32;
+    }
+    Ok(windows)
+}
+
+/// The lowest-scoring windows covering at least `target_seconds`, in timeline order
+pub fn least_engaging(windows: &[EngagementWindow], target_seconds: f64) -> Vec<EngagementWindow> {
+    let mut ranked: Vec<&EngagementWindow> = windows.iter().collect();
+    ranked.sort_by(|a, b| a.score.total_cmp(&b.score).then(a.start.total_cmp(&b.start)));
+    let mut picked = Vec::new();
+    let mut covered = 0.0;
+    for window in ranked {
+        if covered >= target_seconds {
+            break;
+        }
+        covered += window.end - window.start;
+        picked.push(window.clone());
+    }
+    picked.sort_by(|a, b| a.start.total_cmp(&b.start));
+    picked
+}
+
+/// --- Cache ---------------------------------------------------------------------------
+
+/// Scores as cached, with what they were computed from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedScores {
+    size: u64,
+    modified: u64,
+    transcript_segments: usize, // Transcribing the clip (again) invalidates the scores
+    windows: Vec<EngagementWindow>,
+}
+
+fn cache_path(clip_id: &str, window_secs: f64) -> Result<PathBuf> {
+    let dir = longterm_storage::get_lts_directory()?.join("engagement");
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    Ok(dir.join(format!("{}-{}ms.json", clip_id, (window_secs * 1000.0).round() as u64)))
+}
+
+/// Score every `window_secs` window of `clip`, from scratch
+fn compute(clip: &Clip, window_secs: f64) -> Result<Vec<EngagementWindow>> {
+    let input = clip.path.to_string_lossy().to_string();
+    let probe = ffmpeg::ffprobe(&input).context("ffprobe failed")?;
+    let peaks = match probe.audio_rate > 0 {
+        true => Some(waveform::pcm_peaks(&input, clip.audio_stream_index.unwrap_or(probe.audio_stream))?),
+        false => None,
+    };
+    let scene_changes = match probe.width > 0 && probe.height > 0 {
+        true => Some(ffmpeg::detect_scene_changes(&input, SCENE_THRESHOLD)?),
+        false => None,
+    };
+    score_windows(probe.duration, window_secs, clip.transcript.as_deref(), peaks.as_deref(), scene_changes.as_deref())
+}
+
+/// Engagement of each `window_secs` window of the clip, cached until the file changes or the
+/// clip is transcribed
+pub fn engagement_windows(clip_id: &str, window_secs: f64) -> Result<Vec<EngagementWindow>> {
+    let project = project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+    let (size, modified) = media_hash::file_stamp(&clip.path)?;
+    let transcript_segments = clip.transcript.as_ref().map_or(0, |t| t.len());
+
+    let path = cache_path(clip_id, window_secs)?;
+    let cached = fs::read_to_string(&path).ok().and_then(|data| serde_json::from_str::<CachedScores>(&data).ok());
+    if let Some(cached) = cached {
+        if cached.size == size && cached.modified == modified && cached.transcript_segments == transcript_segments {
+            return Ok(cached.windows);
+        }
+    }
+
+    let windows = compute(clip, window_secs)?;
+    let cached = CachedScores { size, modified, transcript_segments, windows: windows.clone() };
+    if let Err(e) = serde_json::to_string(&cached).map_err(anyhow::Error::from).and_then(|data| Ok(fs::write(&path, data)?)) {
+        log::warn!("Failed to cache engagement scores for {}: {}", clip_id, e);
+    }
+    Ok(windows)
+}
+
+/// `(start, end, score)` of each `window_secs` window of the clip; see `engagement_windows`
+pub fn score_engagement(clip_id: &str, window_secs: f64) -> Result<Vec<(f64, f64, f32)>> {
+    Ok(engagement_windows(clip_id, window_secs)?.into_iter().map(|w| (w.start, w.end, w.score)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> TranscriptSegment {
+        TranscriptSegment { id: format!("{}", start), start, end, text: text.to_string(), confidence: None, words: Vec::new() }
+    }
+
+    /// Peaks for `seconds` of audio, at a steady level except for swings to full scale while
+    /// `lively`. A lively stretch ends on the steady level, so a bucket straddling the window
+    /// boundary doesn't liven up the next window
+    fn peaks(seconds: &[(f64, bool)]) -> Vec<i16> {
+        seconds.iter().flat_map(|(length, lively)| {
+            let count = (length / waveform::BUCKET_SECONDS).round() as usize;
+            (0..count).map(move |i| if *lively && i % 2 == 0 { i16::MAX } else { 1000 })
+        }).collect()
+    }
+
+    #[test]
+    fn windows_cover_the_duration() {
+        let windows = score_windows(25.0, 10.0, None, None, Some(&[])).unwrap();
+        let ranges: Vec<(f64, f64)> = windows.iter().map(|w| (w.start, w.end)).collect();
+        assert_eq!(ranges, [(0.0, 10.0), (10.0, 20.0), (20.0, 25.0)]);
+    }
+
+    #[test]
+    fn busy_window_scores_one_and_dead_window_zero() {
+        let transcript = [segment(0.0, 10.0, "alpha bravo charlie delta echoes foxtrot")];
+        let peaks = peaks(&[(10.0, true), (10.0, false)]);
+        let windows = score_windows(20.0, 10.0, Some(&transcript), Some(&peaks), Some(&[1.0, 3.0, 5.0])).unwrap();
+        assert_eq!(windows[0].score, 1.0);
+        assert_eq!(windows[0].speech_rate, Some(0.6));
+        assert_eq!(windows[0].scene_changes, Some(3));
+        assert_eq!(windows[0].novelty, Some(1.0));
+        assert_eq!(windows[1].score, 0.0);
+        assert_eq!(windows[1].energy_variance, Some(0.0));
+        assert_eq!(
+            windows[1].explain(),
+            "Engagement 0.00: almost nothing is said, flat audio level, no change of shot"
+        );
+    }
+
+    #[test]
+    fn repeated_keywords_have_no_novelty() {
+        let transcript = [segment(0.0, 5.0, "alpha bravo"), segment(5.0, 10.0, "alpha bravo alpha bravo")];
+        let windows = score_windows(10.0, 5.0, Some(&transcript), None, None).unwrap();
+        assert_eq!(windows[0].novelty, Some(1.0));
+        assert_eq!(windows[1].novelty, Some(0.0));
+        assert_eq!(windows[1].speech_rate, Some(0.8));
+        assert!(windows[1].explain().ends_with("slow speech (0.8 words/s), mostly repeats earlier points"));
+        // Short words don't count as keywords
+        let windows = score_windows(5.0, 5.0, Some(&[segment(0.0, 5.0, "the and for")]), None, None).unwrap();
+        assert_eq!(windows[0].novelty, Some(0.0));
+    }
+
+    #[test]
+    fn missing_features_are_left_out_of_the_score() {
+        // Audio only: the score is the window's energy relative to the liveliest window
+        let peaks = peaks(&[(10.0, true), (10.0, false)]);
+        let windows = score_windows(20.0, 10.0, None, Some(&peaks), None).unwrap();
+        assert_eq!((windows[0].score, windows[1].score), (1.0, 0.0));
+        assert_eq!((windows[0].speech_rate, windows[0].scene_changes), (None, None));
+    }
+
+    #[test]
+    fn scoring_is_deterministic() {
+        let transcript = [segment(0.0, 12.0, "some words here, then more words later on")];
+        let peaks = peaks(&[(6.0, true), (6.0, false), (6.0, true)]);
+        let score = || score_windows(18.0, 3.0, Some(&transcript), Some(&peaks), Some(&[2.5, 13.0])).unwrap();
+        assert_eq!(score(), score());
+    }
+
+    #[test]
+    fn rejects_short_windows_and_nothing_to_score() {
+        assert!(score_windows(10.0, 0.5, None, None, Some(&[])).is_err());
+        assert!(score_windows(10.0, f64::NAN, None, None, Some(&[])).is_err());
+        assert!(score_windows(10.0, 5.0, None, None, None).is_err());
+    }
+
+    #[test]
+    fn least_engaging_picks_lowest_scores_up_to_the_target() {
+        let window = |start: f64, score: f32| EngagementWindow {
+            start,
+            end: start + 5.0,
+            score,
+            speech_rate: None,
+            energy_variance: None,
+            scene_changes: None,
+            novelty: None,
+        };
+        let windows = [window(0.0, 0.9), window(5.0, 0.1), window(10.0, 0.5), window(15.0, 0.1), window(20.0, 0.3)];
+        let starts = |target: f64| least_engaging(&windows, target).iter().map(|w| w.start).collect::<Vec<f64>>();
+        assert_eq!(starts(0.0), Vec::<f64>::new());
+        assert_eq!(starts(10.0), [5.0, 15.0]);
+        // The target is reached with whole windows, in timeline order
+        assert_eq!(starts(11.0), [5.0, 15.0, 20.0]);
+        assert_eq!(starts(100.0), [0.0, 5.0, 10.0, 15.0, 20.0]);
+    }
+}
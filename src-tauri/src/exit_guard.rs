@@ -0,0 +1,191 @@
+//! Holds back closing a window or quitting while that would lose something: a project that was
+//! never saved, agent proposals nobody accepted or rejected, jobs that are still running. The
+//! close is prevented and "close-requested" sent with what is in the way; it only happens once
+//! the UI calls `confirm_close`.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Window};
+use crate::ai_agent;
+use crate::editor_windows;
+use crate::jobs;
+
+/// What would be lost, so the confirmation can say exactly that
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitBlockerCode {
+  UnsavedProject,  // The loaded project has never been saved
+  PendingProposal, // Agent edits the user hasn't accepted or rejected
+  ActiveJob,       // Running, paused or queued jobs
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExitBlocker {
+  pub code: ExitBlockerCode,
+  pub message: String,
+  pub ids: Vec<String>, // Message ids of the proposals, ids of the jobs
+}
+
+/// Payload of "close-requested"
+#[derive(Serialize, Debug, Clone)]
+pub struct CloseRequest {
+  pub window: Option<String>, // Label of the window being closed; None when the app is quitting
+  pub blockers: Vec<ExitBlocker>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PendingClose {
+  Window(String),
+  Exit,
+}
+
+/// The close waiting for `confirm_close`
+static PENDING: Mutex<Option<PendingClose>> = Mutex::new(None);
+/// Window `confirm_close` is closing, so its close request isn't held back again
+static CONFIRMED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Everything quitting now would lose
+pub fn exit_blockers() -> Vec<ExitBlocker> {
+  collect(true, true)
+}
+
+/// Blockers for a close that unloads the project (`closes_project`) or ends the app (`exits`)
+fn collect(closes_project: bool, exits: bool) -> Vec<ExitBlocker> {
+  let mut blockers = Vec::new();
+  if closes_project && editor_windows::has_unsaved_changes() {
+    blockers.push(ExitBlocker {
+      code: ExitBlockerCode::UnsavedProject,
+      message: "The project has never been saved".to_string(),
+      ids: Vec::new(),
+    });
+  }
+
+  let proposals = if closes_project { ai_agent::undecided_proposals() } else { Vec::new() };
+  if !proposals.is_empty() {
+    let edits: usize = proposals.iter().map(|(_, edits)| edits).sum();
+    blockers.push(ExitBlocker {
+      code: ExitBlockerCode::PendingProposal,
+      message: format!(
+        "{} agent proposal{} with {} edit{} not accepted or rejected yet",
+        proposals.len(),
+        if proposals.len() == 1 { "" } else { "s" },
+        edits,
+        if edits == 1 { "" } else { "s" }
+      ),
+      ids: proposals.into_iter().map(|(id, _)| id).collect(),
+    });
+  }
+
+  let active: Vec<jobs::Job> = if exits {
+    jobs::list_jobs().into_iter().filter(|job| job.status.is_active()).collect()
+  } else {
+    Vec::new()
+  };
+  if !active.is_empty() {
+    let mut kinds: Vec<&str> = active.iter().map(|job| job.kind.as_str()).collect();
+    kinds.sort_unstable();
+    kinds.dedup();
+    blockers.push(ExitBlocker {
+      code: ExitBlockerCode::ActiveJob,
+      message: format!(
+        "{} job{} still running or waiting ({})",
+        active.len(),
+        if active.len() == 1 { " is" } else { "s are" },
+        kinds.join(", ")
+      ),
+      ids: active.iter().map(|job| job.id.clone()).collect(),
+    });
+  }
+  blockers
+}
+
+/// Blockers for `pending`. Closing the last window ends the app; closing the editor of the
+/// loaded project unloads it; other windows lose nothing
+fn blockers_for(app: &AppHandle, pending: &PendingClose) -> Vec<ExitBlocker> {
+  match pending {
+    PendingClose::Exit => collect(true, true),
+    PendingClose::Window(label) => {
+      let exits = app.webview_windows().keys().all(|l| l == label);
+      collect(exits || editor_windows::holds_current_project(label), exits)
+    }
+  }
+}
+
+/// Send "close-requested" to `label` and remember `pending` for `confirm_close`
+fn request_confirmation(app: &AppHandle, label: &str, pending: PendingClose, blockers: Vec<ExitBlocker>) {
+  let window = match &pending {
+    PendingClose::Window(label) => Some(label.clone()),
+    PendingClose::Exit => None,
+  };
+  *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = Some(pending);
+  if let Err(e) = app.emit_to(label, "close-requested", CloseRequest { window, blockers }) {
+    log::warn!("Failed to emit close-requested: {}", e);
+  }
+}
+
+/// Whether the close request of `window` has to wait for `confirm_close`. Sends
+/// "close-requested" to the window when it does
+pub fn hold_close(window: &Window) -> bool {
+  let label = window.label().to_string();
+  {
+    let mut confirmed = CONFIRMED.lock().unwrap_or_else(|e| e.into_inner());
+    if confirmed.as_deref() == Some(label.as_str()) {
+      *confirmed = None;
+      return false;
+    }
+  }
+  let app = window.app_handle();
+  let pending = PendingClose::Window(label.clone());
+  let blockers = blockers_for(app, &pending);
+  if blockers.is_empty() {
+    return false;
+  }
+  request_confirmation(app, &label, pending, blockers);
+  true
+}
+
+/// Whether a user request to quit has to wait for `confirm_close`. Sends "close-requested" to
+/// the focused window (or the main one) when it does. Once the last window is gone its close was
+/// already confirmed, so that exit goes ahead
+pub fn hold_exit(app: &AppHandle) -> bool {
+  let windows = app.webview_windows();
+  if windows.is_empty() {
+    return false;
+  }
+  let blockers = blockers_for(app, &PendingClose::Exit);
+  if blockers.is_empty() {
+    return false;
+  }
+  let label = windows
+    .iter()
+    .find(|(_, window)| window.is_focused().unwrap_or(false))
+    .map(|(label, _)| label.clone())
+    .unwrap_or_else(|| "main".to_string());
+  request_confirmation(app, &label, PendingClose::Exit, blockers);
+  true
+}
+
+/// Go ahead with the close waiting since the last "close-requested". Without `force` nothing
+/// happens while something is still in the way, and what is gets returned
+pub fn confirm_close(app: &AppHandle, force: bool) -> tauri::Result<Vec<ExitBlocker>> {
+  let Some(pending) = PENDING.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+    return Ok(Vec::new());
+  };
+  if !force {
+    let blockers = blockers_for(app, &pending);
+    if !blockers.is_empty() {
+      *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = Some(pending);
+      return Ok(blockers);
+    }
+  }
+  match pending {
+    PendingClose::Window(label) => {
+      if let Some(window) = app.get_webview_window(&label) {
+        *CONFIRMED.lock().unwrap_or_else(|e| e.into_inner()) = Some(label);
+        window.close()?;
+      }
+    }
+    PendingClose::Exit => app.exit(0),
+  }
+  Ok(Vec::new())
+}
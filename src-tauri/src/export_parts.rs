@@ -0,0 +1,184 @@
+//! Split export: renders the edited timeline as several files, split by a length cap or at
+//! markers, preferring the joins between kept segments so parts don't start mid-shot.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use crate::ffmpeg::{self, Cut};
+use crate::guides;
+use crate::intervals::Remap;
+use crate::jobs::JobContext;
+
+/// --- Public Types ------------------------------------------------------------------
+
+/// What to export; same inputs as `export_cutlist`. Part files are named after `output`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PartsExportSettings {
+  pub input: String,
+  pub output: String, // "talk.mp4" → "talk_part01.mp4", "talk_part02.mp4", ...
+  #[serde(default)]
+  pub ranges_to_cut: Vec<Cut>,
+  #[serde(default)]
+  pub export: ffmpeg::ExportSettings, // Container and codecs for every part
+  #[serde(default)]
+  pub reframe_aspect: Option<String>,
+  #[serde(default)]
+  pub force: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SplitMode {
+  ByDuration(f64), // Maximum part length in seconds
+  AtMarkers,       // Split at the project's markers
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportPart {
+  pub index: usize, // 1-based, matches the file suffix
+  pub path: String,
+  pub start: f64, // Edited-timeline seconds
+  pub end: f64,
+  pub duration: f64,
+  pub outcome: ffmpeg::ExportOutcome,
+}
+
+/// Splits this close to a join are moved onto it.
+const MARKER_SNAP_WINDOW: f64 = 5.0;
+/// Parts shorter than this (seconds) are merged into their neighbor.
+const MIN_PART: f64 = 0.5;
+const MAX_PARTS: usize = 999;
+
+/// --- Split points ------------------------------------------------------------------
+
+/// Greedy length-capped split: each part ends at the last join that fits, or exactly at the
+/// cap when no join falls in the back half of the part (unavoidable mid-segment split).
+fn duration_splits(timeline: &Remap, max_len: f64) -> Vec<f64> {
+  let joins = timeline.joins();
+  let mut splits = Vec::new();
+  let mut start = 0.0;
+  while timeline.duration - start > max_len {
+    let limit = start + max_len;
+    let split = joins
+      .iter()
+      .copied()
+      .rfind(|j| *j > start + max_len / 2.0 && *j <= limit)
+      .unwrap_or(limit);
+    splits.push(split);
+    start = split;
+  }
+  splits
+}
+
+/// Marker splits, moved onto the nearest join when one is within `MARKER_SNAP_WINDOW`.
+fn marker_splits(timeline: &Remap, markers: &[f64]) -> Vec<f64> {
+  let joins = timeline.joins();
+  let mut splits: Vec<f64> = markers
+    .iter()
+    .map(|m| {
+      let t = timeline.to_output(*m);
+      joins
+        .iter()
+        .copied()
+        .filter(|j| (j - t).abs() <= MARKER_SNAP_WINDOW)
+        .min_by(|a, b| (a - t).abs().total_cmp(&(b - t).abs()))
+        .unwrap_or(t)
+    })
+    .filter(|t| *t >= MIN_PART && *t <= timeline.duration - MIN_PART)
+    .collect();
+  splits.sort_by(|a, b| a.total_cmp(b));
+  splits.dedup_by(|b, a| *b - *a < MIN_PART);
+  splits
+}
+
+fn part_path(output: &Path, index: usize, total: usize) -> PathBuf {
+  let parent = output.parent().unwrap_or_else(|| Path::new("."));
+  let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("out");
+  let ext = output.extension().and_then(|s| s.to_str()).unwrap_or("mp4");
+  let width = total.to_string().len().max(2);
+  parent.join(format!("{}_part{:0width$}.{}", stem, index, ext, width = width))
+}
+
+/// --- Export ------------------------------------------------------------------------
+
+/// Export `settings` as consecutive parts. `markers` are in source seconds (the same time
+/// base as `ranges_to_cut`) and are only used with `SplitMode::AtMarkers`.
+/// Job progress spans all parts; the message names the part being rendered.
+/// The job can be paused between parts.
+pub fn export_parts(
+  settings: &PartsExportSettings,
+  split: &SplitMode,
+  markers: &[f64],
+  job: &JobContext,
+) -> Result<Vec<ExportPart>> {
+  settings.export.validate(&settings.output)?;
+  if !ffmpeg::ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+  let reframe_aspect = settings.reframe_aspect.as_deref().map(guides::parse_aspect).transpose()?;
+  let probe = ffmpeg::ffprobe(&settings.input).context("ffprobe failed")?;
+  let normalized = ffmpeg::normalize_cuts(settings.ranges_to_cut.clone(), probe.duration);
+  let timeline = Remap::new(ffmpeg::to_kept_segments(&normalized, probe.duration));
+  if timeline.duration <= 0.0 {
+    return Err(anyhow!("All content would be cut out (no kept segments)."));
+  }
+
+  let splits = match split {
+    SplitMode::ByDuration(max_len) => {
+      if !max_len.is_finite() || *max_len < 1.0 {
+        return Err(anyhow!("part length must be at least 1 second"));
+      }
+      duration_splits(&timeline, *max_len)
+    }
+    SplitMode::AtMarkers => {
+      if markers.is_empty() {
+        return Err(anyhow!("project has no markers to split at"));
+      }
+      marker_splits(&timeline, markers)
+    }
+  };
+  if splits.len() + 1 > MAX_PARTS {
+    return Err(anyhow!("export would produce {} parts (max {})", splits.len() + 1, MAX_PARTS));
+  }
+
+  let bounds: Vec<f64> = std::iter::once(0.0)
+    .chain(splits.iter().copied())
+    .chain(std::iter::once(timeline.duration))
+    .collect();
+  let total = bounds.len() - 1;
+  let output = Path::new(&settings.output);
+  let mut parts = Vec::with_capacity(total);
+  job.set_pausable();
+
+  for (i, window) in bounds.windows(2).enumerate() {
+    job.checkpoint().map_err(|_| anyhow!("export cancelled after {} of {} parts", i, total))?;
+    let (start, end) = (window[0], window[1]);
+    let path = part_path(output, i + 1, total);
+    let path_str = path.to_string_lossy().to_string();
+    job.progress(i as f64 / total as f64, Some(&format!("Exporting part {} of {}", i + 1, total)));
+
+    // Everything outside this part's source ranges is cut
+    let pieces = timeline.source_ranges(start, end, 0.001);
+    let cuts = ffmpeg::to_kept_segments(&pieces, probe.duration);
+    let outcome = ffmpeg::export_with_cuts(
+      &settings.input,
+      &path_str,
+      &cuts,
+      &settings.export,
+      reframe_aspect,
+      settings.force,
+      &mut |fraction| job.progress((i as f64 + fraction) / total as f64, None),
+    )
+    .with_context(|| format!("part {} of {} failed", i + 1, total))?
+    .outcome;
+
+    parts.push(ExportPart {
+      index: i + 1,
+      path: path_str,
+      start,
+      end,
+      duration: end - start,
+      outcome,
+    });
+  }
+  Ok(parts)
+}
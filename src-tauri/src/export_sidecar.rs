@@ -0,0 +1,547 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use crate::edit_script::{self, EditScript, Step};
+use crate::ffmpeg::{ExportSettings, UpscaleDecision};
+use crate::media_hash::{self, SourceHashStatus};
+use crate::project_file::TrackType;
+
+/// Bumped whenever encoder arguments change in a way that changes output for the same request.
+pub const ENCODER_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+enc1");
+
+/// Bytes read from the head and tail of each source when hashing it.
+const SAMPLE_BYTES: u64 = 1024 * 1024;
+/// Blocks of `BLOCK_BYTES` sampled evenly between the head and tail, so edits inside the file show too.
+const MIDDLE_BLOCKS: u64 = 16;
+const BLOCK_BYTES: u64 = 64 * 1024;
+
+/// Marks `.edits.json` files; `EDITS_VERSION` is bumped on incompatible changes.
+const EDITS_FORMAT: &str = "gebo-export-edits";
+const EDITS_VERSION: u32 = 1;
+
+/// --- Public Types ------------------------------------------------------------------
+
+/// Identity of a source file at the time of export.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceFingerprint {
+  pub path: String,
+  pub size: u64,
+  pub content_hash: String, // SHA-256 over size + first/last MiB + sampled middle blocks
+  #[serde(default)]
+  pub modified: Option<i64>, // Modification time (Unix ms); part of the request fingerprint, not of `content_hash`
+}
+
+/// Contents of `<output>.gebo.json`, written next to every finished export.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportSidecar {
+  pub fingerprint: String,
+  pub encoder_version: String,
+  pub created_at: String, // ISO 8601
+  pub output_size: u64,
+  pub sources: Vec<SourceFingerprint>,
+  #[serde(default)]
+  pub media_hashes: Vec<SourceHashStatus>, // Ingest hash status of each source at export time
+  #[serde(default)]
+  pub upscale: Option<UpscaleDecision>, // Set when the settings asked for more than the source had
+  #[serde(default)]
+  pub encode_profile: Option<String>, // Id of the encode profile whose settings the export used
+}
+
+/// Whether an existing output can be reused for a given export request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportStatus {
+  pub output_exists: bool,
+  pub has_sidecar: bool,
+  pub up_to_date: bool,
+  pub created_at: Option<String>,
+}
+
+/// A stretch of the source removed from the export, in source seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RemovedRange {
+  pub start: f64,
+  pub end: f64,
+  pub duration: f64,
+}
+
+/// A stretch of a source kept in the export, and where it starts in the output.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeptRange {
+  pub start: f64,
+  pub end: f64,
+  pub output_start: f64,
+  #[serde(default)]
+  pub source: usize, // Index into `sources`; timeline exports have several
+}
+
+impl KeptRange {
+  /// `ranges` of source `source` played back to back from the start of the output
+  pub fn in_sequence(source: usize, ranges: &[(f64, f64)]) -> Vec<KeptRange> {
+    let mut output_start = 0.0;
+    ranges
+      .iter()
+      .map(|&(start, end)| {
+        let range = KeptRange { start, end, output_start, source };
+        output_start += end - start;
+        range
+      })
+      .collect()
+  }
+}
+
+/// Contents of `<output>.edits.json`: what an export was made from and exactly what it removed,
+/// for people rather than for the up-to-date check. Written when `export_edits_sidecar` is on.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportEdits {
+  pub format: String, // Always "gebo-export-edits"
+  pub version: u32,
+  pub app_version: String,
+  pub encoder_version: String,
+  pub exported_at: String, // ISO 8601
+  pub output: String,
+  pub output_size: u64,
+  pub output_duration: f64,
+  pub fingerprint: String, // Same as the `.gebo.json` sidecar's
+  pub sources: Vec<SourceFingerprint>,
+  #[serde(default)]
+  pub media_hashes: Vec<SourceHashStatus>,
+  pub source_duration: f64,
+  pub removed: Vec<RemovedRange>, // Normalized: sorted, merged and clamped to the source
+  pub removed_seconds: f64,
+  pub kept: Vec<KeptRange>,
+  pub settings: Option<ExportSettings>, // None for lossless exports, which copy the streams
+  pub settings_summary: String, // Everything that went into the fingerprint besides the cuts, in words
+  #[serde(default)]
+  pub audio_only: bool,
+}
+
+/// Whether a source named in an `.edits.json` still matches the file on disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceMatch {
+  Unchanged,
+  Changed, // Same path, different content
+  Missing,
+}
+
+/// An `.edits.json` read back, with the state of its sources and a script that rebuilds the cut list.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoadedExportEdits {
+  pub path: String,
+  pub edits: ExportEdits,
+  pub source_status: Vec<SourceMatch>, // One per source, in order
+  pub script: EditScript, // Run on a new project (run_edit_script) to get the same timeline
+}
+
+/// --- Fingerprinting ----------------------------------------------------------------
+
+/// `output.mp4` → `output.mp4.gebo.json`
+pub fn sidecar_path(output: &Path) -> PathBuf {
+  let mut name = output.as_os_str().to_os_string();
+  name.push(".gebo.json");
+  PathBuf::from(name)
+}
+
+/// Hash a source file without reading all of it: size, the first and last MiB, and
+/// `MIDDLE_BLOCKS` blocks spread evenly between them.
+pub fn fingerprint_source(path: &str) -> Result<SourceFingerprint> {
+  let mut file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+  let metadata = file.metadata()?;
+  let size = metadata.len();
+  let modified = metadata
+    .modified()
+    .ok()
+    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|d| d.as_millis() as i64);
+
+  let mut hasher = Sha256::new();
+  hasher.update(size.to_le_bytes());
+
+  let mut head = Vec::new();
+  (&mut file).take(SAMPLE_BYTES).read_to_end(&mut head)?;
+  hasher.update(&head);
+
+  if size > SAMPLE_BYTES * 2 {
+    for offset in middle_block_offsets(size) {
+      file.seek(SeekFrom::Start(offset))?;
+      let mut block = Vec::new();
+      (&mut file).take(BLOCK_BYTES).read_to_end(&mut block)?;
+      hasher.update(&block);
+    }
+
+    file.seek(SeekFrom::End(-(SAMPLE_BYTES as i64)))?;
+    let mut tail = Vec::new();
+    file.take(SAMPLE_BYTES).read_to_end(&mut tail)?;
+    hasher.update(&tail);
+  }
+
+  Ok(SourceFingerprint {
+    path: path.to_string(),
+    size,
+    content_hash: format!("{:x}", hasher.finalize()),
+    modified,
+  })
+}
+
+/// Starts of the sampled blocks between the head and tail of a `size`-byte file (larger than both).
+fn middle_block_offsets(size: u64) -> Vec<u64> {
+  let middle = size - SAMPLE_BYTES * 2;
+  if middle <= MIDDLE_BLOCKS * BLOCK_BYTES {
+    // Small enough to read the whole middle
+    return (0..middle.div_ceil(BLOCK_BYTES)).map(|i| SAMPLE_BYTES + i * BLOCK_BYTES).collect();
+  }
+  // One block centred in each of `MIDDLE_BLOCKS` equal stretches
+  let step = middle / MIDDLE_BLOCKS;
+  (0..MIDDLE_BLOCKS).map(|i| SAMPLE_BYTES + i * step + (step - BLOCK_BYTES) / 2).collect()
+}
+
+/// Deterministic fingerprint of an export request.
+/// `layout` is the cut list or timeline layout, `settings` the encode settings.
+pub fn fingerprint_request<L: Serialize, S: Serialize>(
+  sources: &[SourceFingerprint],
+  layout: &L,
+  settings: &S,
+) -> Result<String> {
+  let mut hasher = Sha256::new();
+  hasher.update(ENCODER_VERSION.as_bytes());
+  for source in sources {
+    hasher.update(source.content_hash.as_bytes());
+    // Catches same-size edits that miss every sampled block
+    hasher.update(source.modified.unwrap_or_default().to_le_bytes());
+  }
+  hasher.update(serde_json::to_vec(layout).context("failed to serialize export layout")?);
+  hasher.update(serde_json::to_vec(settings).context("failed to serialize export settings")?);
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// --- Sidecar I/O -------------------------------------------------------------------
+
+pub fn read_sidecar(output: &Path) -> Option<ExportSidecar> {
+  let data = fs::read_to_string(sidecar_path(output)).ok()?;
+  serde_json::from_str(&data).ok()
+}
+
+pub fn write_sidecar(
+  output: &Path,
+  fingerprint: &str,
+  sources: Vec<SourceFingerprint>,
+  upscale: Option<UpscaleDecision>,
+  encode_profile: Option<String>,
+) -> Result<()> {
+  let output_size = fs::metadata(output)
+    .with_context(|| format!("export output missing: {}", output.display()))?
+    .len();
+  let media_hashes = sources.iter().map(|s| media_hash::source_hash_status(&s.path)).collect();
+  let sidecar = ExportSidecar {
+    fingerprint: fingerprint.to_string(),
+    encoder_version: ENCODER_VERSION.to_string(),
+    created_at: chrono::Utc::now().to_rfc3339(),
+    output_size,
+    sources,
+    media_hashes,
+    upscale,
+    encode_profile,
+  };
+  let data = serde_json::to_string_pretty(&sidecar).context("failed to serialize export sidecar")?;
+  fs::write(sidecar_path(output), data).context("failed to write export sidecar")?;
+  Ok(())
+}
+
+/// Compare an existing output (and its sidecar) against the fingerprint of a new request.
+/// The output size is checked too so a truncated or replaced file is never reused.
+pub fn export_status(output: &Path, fingerprint: &str) -> ExportStatus {
+  let output_size = fs::metadata(output).ok().map(|m| m.len());
+  let sidecar = read_sidecar(output);
+  let up_to_date = match (&sidecar, output_size) {
+    (Some(sidecar), Some(size)) => sidecar.fingerprint == fingerprint && sidecar.output_size == size,
+    _ => false,
+  };
+
+  ExportStatus {
+    output_exists: output_size.is_some(),
+    has_sidecar: sidecar.is_some(),
+    up_to_date,
+    created_at: sidecar.map(|s| s.created_at),
+  }
+}
+
+/// --- Edit provenance ---------------------------------------------------------------
+
+/// `output.mp4` → `output.mp4.edits.json`
+pub fn edits_path(output: &Path) -> PathBuf {
+  let mut name = output.as_os_str().to_os_string();
+  name.push(".edits.json");
+  PathBuf::from(name)
+}
+
+/// Write `<output>.edits.json` for a finished export. `cuts` are what a single-source export
+/// removed, normalized; `kept` is what the output plays, in order
+#[allow(clippy::too_many_arguments)]
+pub fn write_edits(
+  output: &Path,
+  fingerprint: &str,
+  sources: Vec<SourceFingerprint>,
+  source_duration: f64,
+  cuts: &[(f64, f64)],
+  kept: Vec<KeptRange>,
+  output_duration: f64,
+  settings: Option<&ExportSettings>,
+  settings_summary: String,
+  audio_only: bool,
+) -> Result<()> {
+  let output_size = fs::metadata(output)
+    .with_context(|| format!("export output missing: {}", output.display()))?
+    .len();
+  let removed: Vec<RemovedRange> = cuts.iter().map(|&(start, end)| RemovedRange { start, end, duration: end - start }).collect();
+  let edits = ExportEdits {
+    format: EDITS_FORMAT.to_string(),
+    version: EDITS_VERSION,
+    app_version: env!("CARGO_PKG_VERSION").to_string(),
+    encoder_version: ENCODER_VERSION.to_string(),
+    exported_at: chrono::Utc::now().to_rfc3339(),
+    output: output.to_string_lossy().to_string(),
+    output_size,
+    output_duration,
+    fingerprint: fingerprint.to_string(),
+    media_hashes: sources.iter().map(|s| media_hash::source_hash_status(&s.path)).collect(),
+    sources,
+    source_duration,
+    removed_seconds: removed.iter().map(|r| r.duration).sum(),
+    removed,
+    kept,
+    settings: settings.cloned(),
+    settings_summary,
+    audio_only,
+  };
+  let data = serde_json::to_string_pretty(&edits).context("failed to serialize export edits")?;
+  fs::write(edits_path(output), data).context("failed to write export edits")?;
+  Ok(())
+}
+
+impl ExportEdits {
+  /// Script that imports the source, lays it on one track and makes the same cuts. Cuts go
+  /// last-first so each one's timeline position is still its source position. Timeline exports
+  /// name no cuts, and get every source imported and their kept stretches laid out in order instead
+  pub fn to_edit_script(&self) -> Result<EditScript> {
+    let source = self.sources.first().ok_or_else(|| anyhow!("export edits name no source"))?;
+    if self.sources.len() > 1 || (self.removed.is_empty() && !self.kept.is_empty()) {
+      return self.to_timeline_script();
+    }
+    let mut steps = vec![
+      Step::Import { path: PathBuf::from(&source.path), alias: Some("source".to_string()) },
+      Step::AddTrack {
+        name: "Main".to_string(),
+        r#type: if self.audio_only { TrackType::Audio } else { TrackType::Video },
+        alias: Some("main".to_string()),
+      },
+      Step::AddSegment { track: "main".to_string(), clip: "source".to_string(), start: None, end: None, alias: None },
+    ];
+    steps.extend(self.removed.iter().rev().map(|r| Step::CutRange { start: r.start, end: r.end }));
+    let script = EditScript { version: edit_script::SCRIPT_VERSION, steps };
+    script.validate()?;
+    Ok(script)
+  }
+
+  fn to_timeline_script(&self) -> Result<EditScript> {
+    let mut steps: Vec<Step> = self
+      .sources
+      .iter()
+      .enumerate()
+      .map(|(i, source)| Step::Import { path: PathBuf::from(&source.path), alias: Some(format!("source{}", i)) })
+      .collect();
+    steps.push(Step::AddTrack {
+      name: "Main".to_string(),
+      r#type: if self.audio_only { TrackType::Audio } else { TrackType::Video },
+      alias: Some("main".to_string()),
+    });
+    for kept in &self.kept {
+      if kept.source >= self.sources.len() {
+        return Err(anyhow!("export edits keep a range of source {}, which they don't name", kept.source));
+      }
+      steps.push(Step::AddSegment {
+        track: "main".to_string(),
+        clip: format!("source{}", kept.source),
+        start: Some(kept.start),
+        end: Some(kept.end),
+        alias: None,
+      });
+    }
+    let script = EditScript { version: edit_script::SCRIPT_VERSION, steps };
+    script.validate()?;
+    Ok(script)
+  }
+}
+
+/// Read an `.edits.json`, given either its own path or the export it describes
+pub fn load_export_sidecar(path: &Path) -> Result<LoadedExportEdits> {
+  let path = if path.to_string_lossy().ends_with(".edits.json") { path.to_path_buf() } else { edits_path(path) };
+  let data = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+  let value: serde_json::Value = serde_json::from_str(&data).with_context(|| format!("{} is not JSON", path.display()))?;
+  if value.get("format").and_then(|f| f.as_str()) != Some(EDITS_FORMAT) {
+    return Err(anyhow!("{} is not an export edits file", path.display()));
+  }
+  let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+  if version > EDITS_VERSION as u64 {
+    return Err(anyhow!("{} was written by a newer version (format {})", path.display(), version));
+  }
+  let edits: ExportEdits = serde_json::from_value(value).with_context(|| format!("{} is damaged", path.display()))?;
+
+  let source_status = edits.sources.iter().map(|source| match fingerprint_source(&source.path) {
+    Ok(current) if current.content_hash == source.content_hash => SourceMatch::Unchanged,
+    Ok(_) => SourceMatch::Changed,
+    Err(_) => SourceMatch::Missing,
+  }).collect();
+  let script = edits.to_edit_script()?;
+  Ok(LoadedExportEdits { path: path.to_string_lossy().to_string(), edits, source_status, script })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn source(path: &str) -> SourceFingerprint {
+    SourceFingerprint { path: path.to_string(), size: 1, content_hash: "hash".to_string(), modified: None }
+  }
+
+  fn edits(sources: Vec<SourceFingerprint>, removed: &[(f64, f64)], kept: Vec<KeptRange>) -> ExportEdits {
+    let removed: Vec<RemovedRange> = removed.iter().map(|&(start, end)| RemovedRange { start, end, duration: end - start }).collect();
+    ExportEdits {
+      format: EDITS_FORMAT.to_string(),
+      version: EDITS_VERSION,
+      app_version: "0.0.0".to_string(),
+      encoder_version: ENCODER_VERSION.to_string(),
+      exported_at: "2026-01-01T00:00:00Z".to_string(),
+      output: "out.mp4".to_string(),
+      output_size: 1,
+      output_duration: kept.iter().map(|k| k.end - k.start).sum(),
+      fingerprint: "fingerprint".to_string(),
+      sources,
+      media_hashes: Vec::new(),
+      source_duration: 10.0,
+      removed_seconds: removed.iter().map(|r| r.duration).sum(),
+      removed,
+      kept,
+      settings: None,
+      settings_summary: String::new(),
+      audio_only: false,
+    }
+  }
+
+  fn ops(script: &EditScript) -> Vec<serde_json::Value> {
+    script.steps.iter().map(|step| serde_json::to_value(step).unwrap()).collect()
+  }
+
+  #[test]
+  fn kept_ranges_play_back_to_back() {
+    let kept = KeptRange::in_sequence(2, &[(1.0, 3.0), (5.0, 6.5), (8.0, 9.0)]);
+    let starts: Vec<f64> = kept.iter().map(|k| k.output_start).collect();
+    assert_eq!(starts, vec![0.0, 2.0, 3.5]);
+    assert!(kept.iter().all(|k| k.source == 2));
+  }
+
+  #[test]
+  fn kept_ranges_without_a_source_read_as_the_first() {
+    let kept: KeptRange = serde_json::from_value(json!({ "start": 1.0, "end": 2.0, "output_start": 0.0 })).unwrap();
+    assert_eq!(kept.source, 0);
+  }
+
+  #[test]
+  fn cut_list_exports_replay_their_cuts_last_first() {
+    let edits = edits(vec![source("/media/a.mp4")], &[(1.0, 2.0), (5.0, 6.0)], KeptRange::in_sequence(0, &[(0.0, 1.0), (2.0, 5.0), (6.0, 10.0)]));
+    let steps = ops(&edits.to_edit_script().unwrap());
+    assert_eq!(steps.len(), 5);
+    assert_eq!(steps[2]["op"], "add_segment");
+    assert_eq!(steps[2]["start"], serde_json::Value::Null);
+    assert_eq!(steps[3], json!({ "op": "cut_range", "start": 5.0, "end": 6.0 }));
+    assert_eq!(steps[4], json!({ "op": "cut_range", "start": 1.0, "end": 2.0 }));
+  }
+
+  #[test]
+  fn timeline_exports_lay_out_each_kept_range() {
+    let kept = vec![
+      KeptRange { start: 2.0, end: 4.0, output_start: 0.0, source: 1 },
+      KeptRange { start: 0.0, end: 1.0, output_start: 2.0, source: 0 },
+      KeptRange { start: 6.0, end: 7.0, output_start: 3.0, source: 1 },
+    ];
+    let edits = edits(vec![source("/media/a.mp4"), source("/media/b.mp4")], &[], kept);
+    let steps = ops(&edits.to_edit_script().unwrap());
+    assert_eq!(steps[0]["as"], "source0");
+    assert_eq!(steps[1]["as"], "source1");
+    assert_eq!(steps[2]["op"], "add_track");
+    let segments: Vec<(String, f64, f64)> = steps[3..]
+      .iter()
+      .map(|s| (s["clip"].as_str().unwrap().to_string(), s["start"].as_f64().unwrap(), s["end"].as_f64().unwrap()))
+      .collect();
+    assert_eq!(segments, vec![
+      ("source1".to_string(), 2.0, 4.0),
+      ("source0".to_string(), 0.0, 1.0),
+      ("source1".to_string(), 6.0, 7.0),
+    ]);
+  }
+
+  #[test]
+  fn single_source_timelines_keep_their_order() {
+    // One clip used twice, out of source order: no cuts describe that
+    let kept = vec![
+      KeptRange { start: 5.0, end: 6.0, output_start: 0.0, source: 0 },
+      KeptRange { start: 1.0, end: 2.0, output_start: 1.0, source: 0 },
+    ];
+    let steps = ops(&edits(vec![source("/media/a.mp4")], &[], kept).to_edit_script().unwrap());
+    assert_eq!(steps.len(), 4);
+    assert_eq!(steps[2]["start"], 5.0);
+    assert_eq!(steps[3]["start"], 1.0);
+  }
+
+  #[test]
+  fn kept_ranges_of_unnamed_sources_are_rejected() {
+    let kept = vec![KeptRange { start: 0.0, end: 1.0, output_start: 0.0, source: 3 }];
+    let edits = edits(vec![source("/media/a.mp4"), source("/media/b.mp4")], &[], kept);
+    assert!(edits.to_edit_script().is_err());
+  }
+
+  fn temp_file(bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("gebo-test-{}.bin", uuid::Uuid::new_v4()));
+    fs::write(&path, bytes).unwrap();
+    path
+  }
+
+  #[test]
+  fn middle_blocks_fit_between_the_head_and_tail() {
+    for size in [SAMPLE_BYTES * 2 + 1, SAMPLE_BYTES * 2 + BLOCK_BYTES * MIDDLE_BLOCKS + 1, SAMPLE_BYTES * 10, SAMPLE_BYTES * 4096] {
+      let offsets = middle_block_offsets(size);
+      assert!(!offsets.is_empty());
+      assert!(offsets.len() as u64 <= MIDDLE_BLOCKS, "{} blocks for {} bytes", offsets.len(), size);
+      assert!(offsets.iter().all(|&o| o >= SAMPLE_BYTES && o < size - SAMPLE_BYTES), "{:?}", offsets);
+      assert!(offsets.windows(2).all(|w| w[1] >= w[0] + BLOCK_BYTES), "{:?}", offsets);
+    }
+  }
+
+  #[test]
+  fn same_size_edits_in_the_middle_change_the_content_hash() {
+    let mut bytes = vec![0u8; (SAMPLE_BYTES * 8) as usize];
+    let original = temp_file(&bytes);
+    let middle = (SAMPLE_BYTES * 4) as usize;
+    // Longer than the gap between sampled blocks, shorter than the head or tail
+    bytes[middle..middle + (SAMPLE_BYTES / 2) as usize].fill(1);
+    let edited = temp_file(&bytes);
+
+    let a = fingerprint_source(original.to_str().unwrap()).unwrap();
+    let b = fingerprint_source(edited.to_str().unwrap()).unwrap();
+    assert_eq!(a.size, b.size);
+    assert_ne!(a.content_hash, b.content_hash);
+    let _ = fs::remove_file(&original);
+    let _ = fs::remove_file(&edited);
+  }
+
+  #[test]
+  fn modification_times_change_the_request_fingerprint() {
+    let touched = SourceFingerprint { modified: Some(2), ..source("/media/a.mp4") };
+    let before = fingerprint_request(&[source("/media/a.mp4")], &[(1.0, 2.0)], &()).unwrap();
+    let after = fingerprint_request(&[touched], &[(1.0, 2.0)], &()).unwrap();
+    assert_ne!(before, after);
+  }
+}
@@ -1,9 +1,23 @@
 use anyhow::{anyhow, Context, Result};
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Mutex, OnceLock};
 use base64::Engine;
+use sha2::{Digest, Sha256};
+use crate::audio_effects;
+use crate::encode_profile;
+use crate::export_sidecar::{self, KeptRange};
+use crate::longterm_storage;
+use crate::media_protocol::{self, MediaScope};
+use crate::guides;
+use crate::intervals;
+use crate::jobs;
+use crate::project_file;
+use crate::thumbnail_cache;
+use crate::work_dirs::{self, WorkDir};
 
 /// --- Public Types ------------------------------------------------------------------
 
@@ -18,14 +32,151 @@ pub struct Probe {
   pub v_codec: String,
   pub a_codec: String,
   pub container: String,
+  #[serde(default = "default_sar")]
+  pub sar: f64,      // Sample (pixel) aspect ratio; != 1.0 for anamorphic video
+  #[serde(default)]
+  pub rotation: i32, // Display rotation in degrees, normalized to 0/90/180/270
+  #[serde(default)]
+  pub color_transfer: Option<String>, // e.g. "bt709", "smpte2084" (PQ), "arib-std-b67" (HLG)
+  #[serde(default)]
+  pub color_primaries: Option<String>,
+  #[serde(default)]
+  pub color_space: Option<String>,
+  #[serde(default)]
+  pub is_hdr: bool, // PQ or HLG transfer
+  #[serde(default)]
+  pub avg_fps: f64, // Frames over duration; differs from `fps` (the stream's base rate) for VFR
+  #[serde(default)]
+  pub is_vfr: bool, // Variable frame rate, e.g. screen recordings and phone clips
+  #[serde(default)]
+  pub creation_time: Option<String>, // Recording start from the container or stream tags (ISO 8601), if the camera wrote one
+  #[serde(default)]
+  pub audio_stream: usize, // Audio stream (counting audio streams only) the audio fields describe; see `default_audio_index`
 }
 
+fn default_sar() -> f64 {
+  1.0
+}
+
+/// Base and average frame rates further apart than this share of the base rate mark a VFR stream
+const VFR_TOLERANCE: f64 = 0.01;
+
+/// "num/den" frame rate as ffprobe prints it; None for "0/0" and malformed values
+fn parse_rate(rate: &str) -> Option<f64> {
+  let (num, den) = rate.split_once('/')?;
+  let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+  (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+impl Probe {
+  /// No video to play, only audio (cover art doesn't count). Import makes such files audio clips
+  pub fn is_audio_only(&self) -> bool {
+    self.width == 0 || self.height == 0
+  }
+
+  /// Frame size as displayed: SAR applied, then rotation.
+  pub fn display_size(&self) -> (f64, f64) {
+    let w = self.width as f64 * self.sar;
+    let h = self.height as f64;
+    if self.rotation % 180 == 90 { (h, w) } else { (w, h) }
+  }
+
+  /// Import/export warning for variable-frame-rate video, if this is
+  pub fn vfr_warning(&self, name: &str) -> Option<String> {
+    self.is_vfr.then(|| {
+      format!(
+        "{} has a variable frame rate ({:.2} fps average, {:.2} fps nominal); trims may drift out of sync. Conform it to a constant frame rate first",
+        name, self.avg_fps, self.fps
+      )
+    })
+  }
+
+  /// zscale/tonemap chain mapping this HDR video to SDR BT.709, or None for SDR input.
+  /// Output is yuv420p so it concatenates with SDR clips.
+  pub fn tonemap_filter(&self) -> Option<String> {
+    if !self.is_hdr {
+      return None;
+    }
+    let transfer = self.color_transfer.as_deref().unwrap_or("smpte2084");
+    let primaries = self.color_primaries.as_deref().unwrap_or("bt2020");
+    let matrix = self.color_space.as_deref().unwrap_or("bt2020nc");
+    Some(format!(
+      "zscale=tin={}:pin={}:min={}:t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+       tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+      transfer, primaries, matrix
+    ))
+  }
+}
+
+/// Transfer characteristics (as ffprobe names them) that mark a stream as HDR
+const HDR_TRANSFERS: &[&str] = &["smpte2084", "arib-std-b67"];
+
 /// Cut range (seconds).
 pub type Cut = (f64, f64);
 
 /// --- Probe -------------------------------------------------------------------------
 
-pub fn ffprobe(input: &str) -> Result<Probe> {
+/// One stream of a media file, see `ffprobe_full`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StreamInfo {
+  pub index: u32,
+  pub codec_type: String, // "video", "audio", "subtitle", "data" or "attachment"
+  pub codec: String, // e.g. "h264", "aac", "subrip"; "unknown" when ffprobe can't name it
+  pub language: Option<String>, // Language tag as written in the file, e.g. "eng"
+  pub title: Option<String>, // Track name, e.g. "Commentary"
+  pub channel_layout: Option<String>, // Audio only, e.g. "stereo", "5.1(side)"
+  pub bit_rate: Option<u64>, // Bits per second, when the file records it
+  pub rotation: Option<i32>, // Video only, see `stream_rotation`
+  pub attached_pic: bool, // Cover art rather than a video to play
+  #[serde(default)]
+  pub dispositions: Vec<String>, // Flags set on the stream, e.g. "default", "forced", "comment" (commentary)
+}
+
+/// A chapter of a media file, in seconds
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chapter {
+  pub start: f64,
+  pub end: f64,
+  pub title: Option<String>,
+}
+
+/// Everything ffprobe reports about a file: every stream, the chapters and the container's
+/// tags. `Probe` condenses it to the first video and audio stream
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProbeFull {
+  pub duration: f64,
+  pub container: String,
+  pub bit_rate: Option<u64>,
+  pub streams: Vec<StreamInfo>,
+  pub chapters: Vec<Chapter>,
+  pub tags: BTreeMap<String, String>, // Container tags, e.g. "title", "encoder", "creation_time"
+  #[serde(skip)]
+  json: serde_json::Value, // ffprobe's output, for `probe`
+}
+
+/// A number ffprobe prints as a string, e.g. "128000"
+fn json_number<T: std::str::FromStr>(value: &serde_json::Value) -> Option<T> {
+  value.as_str().and_then(|v| v.parse().ok())
+}
+
+/// Display rotation of video stream `v` in degrees, normalized to 0/90/180/270: the rotate tag
+/// on older files, the display matrix side data on newer ones
+fn stream_rotation(v: &serde_json::Value) -> i32 {
+  let rotation = v["tags"]["rotate"]
+    .as_str()
+    .and_then(|r| r.parse::<f64>().ok())
+    .or_else(|| {
+      v["side_data_list"]
+        .as_array()?
+        .iter()
+        .find_map(|d| d["rotation"].as_f64())
+    })
+    .unwrap_or(0.0);
+  ((rotation.round() as i32 % 360) + 360) % 360
+}
+
+/// Probe every stream, chapter and container tag of `input`
+pub fn ffprobe_full(input: &str) -> Result<ProbeFull> {
   let out = Command::new("ffprobe")
     .args([
       "-v",
@@ -34,6 +185,7 @@ pub fn ffprobe(input: &str) -> Result<Probe> {
       "json",
       "-show_streams",
       "-show_format",
+      "-show_chapters",
       input,
     ])
     .output()
@@ -50,70 +202,198 @@ pub fn ffprobe(input: &str) -> Result<Probe> {
     serde_json::from_slice(&out.stdout).with_context(|| "invalid ffprobe JSON")?;
 
   let fmt = &json["format"];
-  let duration = fmt["duration"]
-    .as_str()
-    .unwrap_or("0")
-    .parse::<f64>()
-    .unwrap_or(0.0);
-  let container = fmt["format_name"]
-    .as_str()
-    .unwrap_or_default()
-    .to_string();
+  let tag = |tags: &serde_json::Value, key: &str| tags[key].as_str().map(|t| t.to_string());
+  let streams = json["streams"]
+    .as_array()
+    .map(|streams| {
+      streams
+        .iter()
+        .map(|s| {
+          let codec_type = s["codec_type"].as_str().unwrap_or("data").to_string();
+          StreamInfo {
+            index: s["index"].as_u64().unwrap_or(0) as u32,
+            codec: s["codec_name"].as_str().unwrap_or("unknown").to_string(),
+            language: tag(&s["tags"], "language").filter(|l| l != "und"),
+            title: tag(&s["tags"], "title"),
+            channel_layout: s["channel_layout"].as_str().map(|l| l.to_string()),
+            bit_rate: json_number(&s["bit_rate"]),
+            rotation: (codec_type == "video").then(|| stream_rotation(s)),
+            attached_pic: s["disposition"]["attached_pic"].as_u64() == Some(1),
+            dispositions: s["disposition"]
+              .as_object()
+              .map(|flags| flags.iter().filter(|(_, set)| set.as_u64() == Some(1)).map(|(flag, _)| flag.clone()).collect())
+              .unwrap_or_default(),
+            codec_type,
+          }
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+  let chapters = json["chapters"]
+    .as_array()
+    .map(|chapters| {
+      chapters
+        .iter()
+        .map(|c| Chapter {
+          start: json_number(&c["start_time"]).unwrap_or(0.0),
+          end: json_number(&c["end_time"]).unwrap_or(0.0),
+          title: tag(&c["tags"], "title"),
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+  let tags = fmt["tags"]
+    .as_object()
+    .map(|tags| {
+      tags
+        .iter()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect()
+    })
+    .unwrap_or_default();
+
+  Ok(ProbeFull {
+    duration: json_number(&fmt["duration"]).unwrap_or(0.0),
+    container: fmt["format_name"].as_str().unwrap_or_default().to_string(),
+    bit_rate: json_number(&fmt["bit_rate"]),
+    streams,
+    chapters,
+    tags,
+    json,
+  })
+}
 
-  let empty_vec = vec![];
-  let streams = json["streams"].as_array().unwrap_or(&empty_vec);
+impl ProbeFull {
+  /// The first video and the default audio stream (see `default_audio_index`), condensed.
+  /// Files with only one of them probe fine (the other's codec is "none"); files with neither
+  /// are an error. Cover art isn't video
+  pub fn probe(&self) -> Result<Probe> {
+    let fmt = &self.json["format"];
+    let empty_vec = vec![];
+    let streams = self.json["streams"].as_array().unwrap_or(&empty_vec);
 
-  // Find video and audio streams (video stream is optional for audio-only files)
-  let v = streams.iter().find(|s| s["codec_type"] == "video");
-  let a = streams
-    .iter()
-    .find(|s| s["codec_type"] == "audio")
-    .ok_or_else(|| anyhow!("no audio stream"))?;
-
-  // Handle video stream (if present)
-  let (width, height, fps, v_codec) = if let Some(v) = v {
-    // fps as num/den
-    let r = v["r_frame_rate"].as_str().unwrap_or("30/1");
-    let mut parts = r.split('/');
-    let num: f64 = parts.next().unwrap_or("30").parse().unwrap_or(30.0);
-    let den: f64 = parts.next().unwrap_or("1").parse().unwrap_or(1.0);
-    let fps = if den > 0.0 { num / den } else { 30.0 };
-    
-    // Get width and height - if they're not present or are 0, treat as audio-only
-    let w = v["width"].as_u64().unwrap_or(0) as u32;
-    let h = v["height"].as_u64().unwrap_or(0) as u32;
-    
-    // If width or height is 0, this is likely an audio file with an embedded image
-    if w == 0 || h == 0 {
-      (0, 0, 0.0, "none".to_string())
-    } else {
-      (
-        w,
-        h,
-        fps,
-        v["codec_name"].as_str().unwrap_or("h264").to_string()
-      )
+    let v = streams
+      .iter()
+      .find(|s| s["codec_type"] == "video" && s["disposition"]["attached_pic"].as_u64() != Some(1));
+    let audio: Vec<&serde_json::Value> = streams.iter().filter(|s| s["codec_type"] == "audio").collect();
+    let audio_stream = default_audio_index(audio.iter().map(|a| a["disposition"]["default"].as_u64() == Some(1)));
+    let a = audio.get(audio_stream).copied();
+    if v.is_none() && a.is_none() {
+      return Err(anyhow!("no audio or video stream"));
     }
-  } else {
-    // Audio-only file
-    (0, 0, 0.0, "none".to_string())
-  };
 
-  Ok(Probe {
-    duration,
-    width,
-    height,
-    fps,
-    audio_rate: a["sample_rate"]
+    // Pixel aspect and display rotation
+    let (sar, rotation) = match v {
+      Some(v) => {
+        let sar = v["sample_aspect_ratio"]
+          .as_str()
+          .and_then(|r| r.split_once(':'))
+          .and_then(|(n, d)| Some((n.parse::<f64>().ok()?, d.parse::<f64>().ok()?)))
+          .filter(|(n, d)| *n > 0.0 && *d > 0.0)
+          .map(|(n, d)| n / d)
+          .unwrap_or(1.0);
+        (sar, stream_rotation(v))
+      }
+      None => (1.0, 0),
+    };
+
+    // Colour metadata; "unknown"/"reserved" are treated as absent
+    let color = |key: &str| {
+      v.and_then(|v| v[key].as_str())
+        .filter(|c| !matches!(*c, "unknown" | "reserved" | "unspecified"))
+        .map(|c| c.to_string())
+    };
+    let color_transfer = color("color_transfer");
+    let is_hdr = color_transfer.as_deref().is_some_and(|t| HDR_TRANSFERS.contains(&t));
+
+    // Handle video stream (if present)
+    let (width, height, fps, v_codec) = if let Some(v) = v {
+      let fps = v["r_frame_rate"].as_str().and_then(parse_rate).unwrap_or(30.0);
+
+      // Get width and height - if they're not present or are 0, treat as audio-only
+      let w = v["width"].as_u64().unwrap_or(0) as u32;
+      let h = v["height"].as_u64().unwrap_or(0) as u32;
+
+      if w == 0 || h == 0 {
+        (0, 0, 0.0, "none".to_string())
+      } else {
+        (
+          w,
+          h,
+          fps,
+          v["codec_name"].as_str().unwrap_or("h264").to_string()
+        )
+      }
+    } else {
+      // Audio-only file
+      (0, 0, 0.0, "none".to_string())
+    };
+
+    // VFR: the base rate (r_frame_rate) and the real average disagree. Still images and
+    // streams without an average can't be judged
+    let avg_fps = v.filter(|_| width > 0)
+      .and_then(|v| v["avg_frame_rate"].as_str())
+      .and_then(parse_rate)
+      .unwrap_or(fps);
+    let is_vfr = width > 0 && fps > 0.0 && (fps - avg_fps).abs() / fps > VFR_TOLERANCE;
+
+    // Cameras write the recording start on the container, some only on the streams
+    let creation_time = fmt["tags"]["creation_time"]
       .as_str()
-      .unwrap_or("48000")
-      .parse()
-      .unwrap_or(48000),
-    audio_channels: a["channels"].as_u64().unwrap_or(2) as u8,
-    v_codec,
-    a_codec: a["codec_name"].as_str().unwrap_or("aac").to_string(),
-    container,
-  })
+      .or_else(|| streams.iter().find_map(|s| s["tags"]["creation_time"].as_str()))
+      .map(|t| t.to_string());
+
+    // Video-only files have no audio to describe
+    let (audio_rate, audio_channels, a_codec) = match a {
+      Some(a) => (
+        a["sample_rate"].as_str().unwrap_or("48000").parse().unwrap_or(48000),
+        a["channels"].as_u64().unwrap_or(2) as u8,
+        a["codec_name"].as_str().unwrap_or("aac").to_string(),
+      ),
+      None => (0, 0, "none".to_string()),
+    };
+
+    Ok(Probe {
+      duration: self.duration,
+      width,
+      height,
+      fps,
+      audio_rate,
+      audio_channels,
+      v_codec,
+      a_codec,
+      container: self.container.clone(),
+      sar,
+      rotation,
+      color_transfer,
+      color_primaries: color("color_primaries"),
+      color_space: color("color_space"),
+      is_hdr,
+      avg_fps,
+      is_vfr,
+      creation_time,
+      audio_stream,
+    })
+  }
+}
+
+/// Audio stream to play, by position among the audio streams, given whether each is marked
+/// default: the first marked one, else the first. Files with a commentary track often mark
+/// the main mix default without putting it first
+pub fn default_audio_index(defaults: impl IntoIterator<Item = bool>) -> usize {
+  defaults.into_iter().position(|default| default).unwrap_or(0)
+}
+
+/// Audio stream of `input` the app decodes when no clip picks one: the one the file marks
+/// default (see `default_audio_index`). Probes the file; callers that know the clip pass its
+/// `Clip::audio_stream` instead, since clips over the same file can play different streams
+pub fn default_audio_stream(input: &str) -> usize {
+  audio_streams(input).ok().map(|streams| default_audio_index(streams.iter().map(|s| s.default))).unwrap_or(0)
+}
+
+/// The first video and the default audio stream of `input`, see `ProbeFull::probe`
+pub fn ffprobe(input: &str) -> Result<Probe> {
+  ffprobe_full(input)?.probe()
 }
 
 /// --- Utilities ---------------------------------------------------------------------
@@ -124,90 +404,516 @@ pub fn ffmpeg_exists() -> bool {
     && Command::new("ffprobe").arg("-version").output().is_ok()
 }
 
-/// Clamp/sort/merge cut ranges; discard invalid or tiny (< 1ms) after clamping.
-fn normalize_cuts(mut cuts: Vec<Cut>, duration: f64) -> Vec<Cut> {
-  if duration <= 0.0 {
-    return vec![];
-  }
-  for (s, e) in cuts.iter_mut() {
-    // normalize order
-    if *e < *s {
-      std::mem::swap(s, e);
-    }
-    // clamp to [0, duration]
-    *s = s.max(0.0);
-    *e = e.min(duration);
-  }
-  // drop invalid / degenerate
-  cuts.retain(|(s, e)| *e > *s + 0.001);
-
-  // sort + merge overlaps
-  cuts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-  let mut merged: Vec<Cut> = Vec::new();
-  for (s, e) in cuts {
-    if let Some((_ms, me)) = merged.last_mut() {
-      if s <= *me + 0.005 {
-        *me = me.max(e);
-      } else {
-        merged.push((s, e));
-      }
-    } else {
-      merged.push((s, e));
-    }
+/// --- Hardware decode -----------------------------------------------------------------
+
+/// Decoder hwaccels we know how to use, in order of preference per platform.
+#[cfg(target_os = "macos")]
+const HWACCEL_PREFERENCE: &[&str] = &["videotoolbox"];
+#[cfg(target_os = "windows")]
+const HWACCEL_PREFERENCE: &[&str] = &["d3d11va", "cuda"];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const HWACCEL_PREFERENCE: &[&str] = &["vaapi", "cuda"];
+
+/// stderr fragments ffmpeg prints when a hwaccel can't be initialized.
+const HWACCEL_ERROR_MARKERS: &[&str] = &[
+  "hwaccel initialisation returned error",
+  "failed setup for format",
+  "device creation failed",
+  "no device available for decoder",
+  "failed to initialise vaapi",
+  "cannot load libcuda",
+  "hardware device setup failed",
+  "error creating a mfx session",
+];
+
+/// Which decode path an ffmpeg invocation ended up using.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecodePathRecord {
+  pub operation: String,         // "thumbnail" | "proxy" | "streaming_preview"
+  pub hwaccel: Option<String>,   // None = software
+  pub fell_back: bool,           // Hardware init failed and we retried in software
+  pub timestamp: String,         // ISO 8601
+}
+
+static HWACCELS: OnceLock<Vec<String>> = OnceLock::new();
+static ENCODERS: OnceLock<Vec<String>> = OnceLock::new();
+static VERSION: OnceLock<Option<String>> = OnceLock::new();
+static LAST_DECODE_PATH: Mutex<Option<DecodePathRecord>> = Mutex::new(None);
+
+/// Hwaccels reported by `ffmpeg -hwaccels` (cached for the process lifetime).
+pub fn detect_hwaccels() -> Vec<String> {
+  HWACCELS
+    .get_or_init(|| {
+      let Ok(out) = Command::new("ffmpeg").args(["-hide_banner", "-hwaccels"]).output() else {
+        return vec![];
+      };
+      String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .skip_while(|l| !l.starts_with("Hardware acceleration methods"))
+        .skip(1)
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+    })
+    .clone()
+}
+
+/// Encoders ffmpeg was built with, from `ffmpeg -encoders` (cached for the process lifetime).
+/// Hardware ones are listed whether or not the machine has the hardware, see detect_hw_encoders
+pub fn detect_encoders() -> Vec<String> {
+  ENCODERS
+    .get_or_init(|| {
+      let Ok(out) = Command::new("ffmpeg").args(["-hide_banner", "-encoders"]).output() else {
+        return vec![];
+      };
+      String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .skip_while(|l| !l.trim_start().starts_with("------"))
+        .skip(1)
+        .filter_map(|l| l.split_whitespace().nth(1).map(|name| name.to_string()))
+        .collect()
+    })
+    .clone()
+}
+
+/// The version ffmpeg reports, e.g. "6.1.1" or "N-113406-g0ad5c3" for a git build
+/// (cached for the process lifetime)
+pub fn ffmpeg_version() -> Option<String> {
+  VERSION
+    .get_or_init(|| {
+      let out = Command::new("ffmpeg").arg("-version").output().ok()?;
+      let stdout = String::from_utf8_lossy(&out.stdout);
+      let line = stdout.lines().next()?;
+      line.strip_prefix("ffmpeg version ")?.split_whitespace().next().map(|v| v.to_string())
+    })
+    .clone()
+}
+
+/// The hwaccel decode path to try first, unless software decode is forced in settings.
+pub fn preferred_hwaccel() -> Option<String> {
+  let forced_software = longterm_storage::get_settings()
+    .map(|s| s.force_software_decode)
+    .unwrap_or(false);
+  if forced_software {
+    return None;
   }
-  merged
+  let available = detect_hwaccels();
+  HWACCEL_PREFERENCE
+    .iter()
+    .find(|h| available.iter().any(|a| a == *h))
+    .map(|h| h.to_string())
 }
 
+/// Input options that enable hardware decoding; goes before `-i`.
+pub fn hwaccel_args(hwaccel: Option<&str>) -> Vec<String> {
+  match hwaccel {
+    Some(h) => vec!["-hwaccel".to_string(), h.to_string()],
+    None => vec![],
+  }
+}
 
-/// Convert cut ranges into kept segments across [0, duration].
-fn to_kept_segments(cuts: &[Cut], duration: f64) -> Vec<Cut> {
-  if duration <= 0.0 {
-    return vec![];
+pub fn is_hwaccel_error(stderr: &str) -> bool {
+  let lower = stderr.to_lowercase();
+  HWACCEL_ERROR_MARKERS.iter().any(|m| lower.contains(m))
+}
+
+pub fn record_decode_path(operation: &str, hwaccel: Option<&str>, fell_back: bool) {
+  if let Ok(mut last) = LAST_DECODE_PATH.lock() {
+    *last = Some(DecodePathRecord {
+      operation: operation.to_string(),
+      hwaccel: hwaccel.map(|h| h.to_string()),
+      fell_back,
+      timestamp: chrono::Utc::now().to_rfc3339(),
+    });
+  }
+}
+
+pub fn last_decode_path() -> Option<DecodePathRecord> {
+  LAST_DECODE_PATH.lock().ok().and_then(|last| last.clone())
+}
+
+/// Decode capabilities for the troubleshooting report.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SystemReport {
+  pub ffmpeg_available: bool,
+  pub hwaccels: Vec<String>,
+  pub preferred_hwaccel: Option<String>,
+  pub force_software_decode: bool,
+  pub last_decode_path: Option<DecodePathRecord>,
+}
+
+pub fn system_report() -> SystemReport {
+  SystemReport {
+    ffmpeg_available: ffmpeg_exists(),
+    hwaccels: detect_hwaccels(),
+    preferred_hwaccel: preferred_hwaccel(),
+    force_software_decode: longterm_storage::get_settings()
+      .map(|s| s.force_software_decode)
+      .unwrap_or(false),
+    last_decode_path: last_decode_path(),
+  }
+}
+
+/// Run an ffmpeg command with hardware decode, retrying once in software if the
+/// hwaccel fails to initialize. `build` receives the input options to put before `-i`.
+fn output_with_hwaccel_fallback<F>(operation: &str, build: F) -> Result<std::process::Output>
+where
+  F: Fn(&[String]) -> Command,
+{
+  output_with_hwaccel_fallback_lines(operation, build, None)
+}
+
+/// `output_with_hwaccel_fallback`, passing each stdout line to `on_line` (see
+/// jobs::output_cancellable), e.g. to follow `-progress pipe:1`
+fn output_with_hwaccel_fallback_lines<F>(
+  operation: &str,
+  build: F,
+  mut on_line: Option<&mut dyn FnMut(&str)>,
+) -> Result<std::process::Output>
+where
+  F: Fn(&[String]) -> Command,
+{
+  let hwaccel = preferred_hwaccel();
+  let output = jobs::output_cancellable(&mut build(&hwaccel_args(hwaccel.as_deref())), on_line.as_deref_mut())
+    .with_context(|| format!("failed to spawn ffmpeg for {}", operation))?;
+
+  if hwaccel.is_some() && !output.status.success() && is_hwaccel_error(&String::from_utf8_lossy(&output.stderr)) {
+    log::warn!("{}: hardware decode ({:?}) failed, retrying in software", operation, hwaccel);
+    let output = jobs::output_cancellable(&mut build(&[]), on_line)
+      .with_context(|| format!("failed to spawn ffmpeg for {}", operation))?;
+    record_decode_path(operation, None, true);
+    return Ok(output);
+  }
+
+  record_decode_path(operation, hwaccel.as_deref(), false);
+  Ok(output)
+}
+
+/// --- Hardware encode -----------------------------------------------------------------
+
+/// H.264 encoder used unless a hardware one is asked for, and when one fails
+pub const SOFTWARE_ENCODER: &str = "libx264";
+/// Hardware H.264 encoders we know how to drive
+const HW_ENCODERS: &[&str] = &["h264_videotoolbox", "h264_nvenc", "h264_qsv", "h264_vaapi"];
+/// Render node VAAPI encodes go through
+const VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+static HW_ENCODERS_AVAILABLE: OnceLock<Vec<String>> = OnceLock::new();
+
+/// How an H.264 encode trades speed for quality
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodePreset {
+  Preview, // Proxies and timeline previews
+  Export,
+}
+
+/// Which encoder an encode ended up using
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncoderUsed {
+  pub encoder: String,
+  pub fell_back: bool, // The requested hardware encoder failed and libx264 finished the job
+}
+
+/// A file written by an encode, and the encoder that wrote it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncodedFile {
+  pub path: String,
+  pub encoder: Option<EncoderUsed>, // None for audio-only output, and for a proxy reused from the cache
+}
+
+/// Global options a hardware encoder needs; go before the first `-i`
+pub(crate) fn encoder_device_args(encoder: &str) -> Vec<&'static str> {
+  match encoder {
+    "h264_vaapi" => vec!["-vaapi_device", VAAPI_DEVICE],
+    _ => vec![],
+  }
+}
+
+/// Filter moving frames to the encoder's device, appended to the video filter chain
+pub(crate) fn encoder_upload_filter(encoder: &str) -> Option<&'static str> {
+  match encoder {
+    "h264_vaapi" => Some("format=nv12,hwupload"),
+    _ => None,
+  }
+}
+
+/// Codec options for `encoder`, tuned to roughly match libx264 at the same preset
+pub(crate) fn h264_encoder_args(encoder: &str, preset: EncodePreset) -> Vec<&'static str> {
+  let preview = preset == EncodePreset::Preview;
+  match encoder {
+    "h264_videotoolbox" => vec!["-c:v", "h264_videotoolbox", "-q:v", if preview { "45" } else { "65" }, "-pix_fmt", "yuv420p"],
+    "h264_nvenc" => vec![
+      "-c:v", "h264_nvenc", "-preset", if preview { "p1" } else { "p5" },
+      "-rc", "vbr", "-cq", if preview { "28" } else { "21" }, "-b:v", "0", "-pix_fmt", "yuv420p",
+    ],
+    "h264_qsv" => vec![
+      "-c:v", "h264_qsv", "-preset", if preview { "veryfast" } else { "medium" },
+      "-global_quality", if preview { "28" } else { "21" }, "-pix_fmt", "nv12",
+    ],
+    "h264_vaapi" => vec!["-c:v", "h264_vaapi", "-qp", if preview { "28" } else { "21" }],
+    _ => match preset {
+      EncodePreset::Preview => vec!["-c:v", "libx264", "-preset", "ultrafast", "-crf", "28", "-pix_fmt", "yuv420p"],
+      EncodePreset::Export => VideoCodec::H264.args().to_vec(),
+    },
   }
-  if cuts.is_empty() {
-    return vec![(0.0, duration)];
+}
+
+/// Whether `encoder` can encode here: a short test encode, since ffmpeg lists hardware
+/// encoders it was built with whether or not the machine has the hardware
+fn hw_encoder_works(encoder: &str) -> bool {
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error"])
+    .args(encoder_device_args(encoder))
+    .args(["-f", "lavfi", "-i", "color=black:size=256x256:rate=30:duration=0.2"]);
+  if let Some(upload) = encoder_upload_filter(encoder) {
+    cmd.args(["-vf", upload]);
   }
-  let mut kept: Vec<Cut> = Vec::new();
-  let mut t = 0.0;
-  for (s, e) in cuts {
-    if *s > t {
-      kept.push((t, *s));
+  cmd.args(h264_encoder_args(encoder, EncodePreset::Preview)).args(["-f", "null", "-"]);
+  cmd.output().map(|out| out.status.success()).unwrap_or(false)
+}
+
+/// Hardware H.264 encoders that pass a test encode (cached for the process lifetime)
+pub fn detect_hw_encoders() -> Vec<String> {
+  HW_ENCODERS_AVAILABLE
+    .get_or_init(|| {
+      HW_ENCODERS
+        .iter()
+        .filter(|encoder| hw_encoder_works(encoder))
+        .map(|encoder| encoder.to_string())
+        .collect()
+    })
+    .clone()
+}
+
+/// Encoders the UI can offer: libx264 first, then the working hardware ones
+pub fn list_available_encoders() -> Vec<String> {
+  let mut encoders = vec![SOFTWARE_ENCODER.to_string()];
+  encoders.extend(detect_hw_encoders());
+  encoders
+}
+
+/// Reject encoder names we don't know how to drive
+pub fn validate_encoder(encoder: Option<&str>) -> Result<()> {
+  match encoder {
+    Some(encoder) if encoder != SOFTWARE_ENCODER && !HW_ENCODERS.contains(&encoder) => {
+      Err(anyhow!("unknown encoder {}; expected {} or one of {}", encoder, SOFTWARE_ENCODER, HW_ENCODERS.join(", ")))
     }
-    t = *e;
+    _ => Ok(()),
+  }
+}
+
+/// Run an encode with `encoder` (default libx264). If a hardware encoder fails for any reason
+/// other than cancellation, the encode is run again with libx264. `run` receives the encoder
+/// to use
+fn output_with_encoder_fallback<F>(operation: &str, encoder: Option<&str>, mut run: F) -> Result<(std::process::Output, EncoderUsed)>
+where
+  F: FnMut(&str) -> Result<std::process::Output>,
+{
+  let encoder = encoder.unwrap_or(SOFTWARE_ENCODER);
+  let output = run(encoder)?;
+  if output.status.success() || encoder == SOFTWARE_ENCODER || jobs::current_job_cancelled() {
+    return Ok((output, EncoderUsed { encoder: encoder.to_string(), fell_back: false }));
   }
-  if t < duration {
-    kept.push((t, duration));
+  log::warn!(
+    "{}: {} failed, retrying with {}: {}",
+    operation,
+    encoder,
+    SOFTWARE_ENCODER,
+    String::from_utf8_lossy(&output.stderr).trim()
+  );
+  let output = run(SOFTWARE_ENCODER)?;
+  Ok((output, EncoderUsed { encoder: SOFTWARE_ENCODER.to_string(), fell_back: true }))
+}
+
+/// Whether the first video stream of `input` has a keyframe within `tolerance` seconds of `time`
+pub fn is_near_keyframe(input: &str, time: f64, tolerance: f64) -> Result<bool> {
+  let window = format!("{}%{}", (time - tolerance).max(0.0), time + tolerance);
+  let output = Command::new("ffprobe")
+    .args([
+      "-v",
+      "error",
+      "-select_streams",
+      "v:0",
+      "-skip_frame",
+      "nokey",
+      "-show_entries",
+      "frame=pts_time",
+      "-of",
+      "csv=p=0",
+      "-read_intervals",
+      &window,
+      input,
+    ])
+    .output()
+    .with_context(|| "failed to run ffprobe")?;
+  if !output.status.success() {
+    return Err(anyhow!("ffprobe keyframe scan failed: {}", String::from_utf8_lossy(&output.stderr)));
+  }
+  // read_intervals seeks to the keyframe before the window, so earlier frames show up too
+  Ok(String::from_utf8_lossy(&output.stdout)
+    .lines()
+    .filter_map(|line| line.trim().trim_end_matches(',').parse::<f64>().ok())
+    .any(|pts| (pts - time).abs() <= tolerance))
+}
+
+/// Run an ffmpeg command, calling `on_progress` with the completed fraction of `duration`
+/// (output seconds) as ffmpeg reports it. `cmd` must pass `-progress pipe:1 -nostats`,
+/// so stdout can't carry media.
+pub(crate) fn output_with_progress(
+  cmd: &mut Command,
+  duration: f64,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<std::process::Output> {
+  // Run through the job registry so cancelling the job kills ffmpeg
+  jobs::output_cancellable(cmd, Some(&mut |line: &str| {
+    if let Some(fraction) = progress_fraction(line, duration) {
+      on_progress(fraction);
+    }
+  }))
+  .with_context(|| "failed to run ffmpeg")
+}
+
+/// Completed fraction of `duration` in a `-progress` line, None for other lines
+fn progress_fraction(line: &str, duration: f64) -> Option<f64> {
+  // -progress reports out_time_us (older builds misname it out_time_ms, also in microseconds)
+  let micros = line
+    .strip_prefix("out_time_us=")
+    .or_else(|| line.strip_prefix("out_time_ms="))
+    .and_then(|v| v.trim().parse::<f64>().ok())?;
+  (duration > 0.0).then(|| (micros / 1_000_000.0 / duration).clamp(0.0, 1.0))
+}
+
+/// Clamp/sort/merge cut ranges; discard invalid or tiny (< 1ms) after clamping.
+/// Cuts within 5ms of each other are joined.
+pub(crate) fn normalize_cuts(cuts: Vec<Cut>, duration: f64) -> Vec<Cut> {
+  intervals::normalize(cuts, duration, 0.001, 0.005)
+}
+
+/// Convert cut ranges into kept segments across [0, duration].
+pub(crate) fn to_kept_segments(cuts: &[Cut], duration: f64) -> Vec<Cut> {
+  intervals::complement(cuts, duration)
+}
+
+/// Audio filter placing the signal at `pan` (-100 left .. 100 right), or None for center.
+/// The input is first converted to stereo, so mono sources are duplicated to both channels
+/// and then balanced. The louder side stays at unity gain; the other is attenuated linearly
+pub fn pan_filter(pan: i8) -> Option<String> {
+  if pan == 0 {
+    return None;
   }
-  kept
+  let balance = f64::from(pan.clamp(-100, 100)) / 100.0;
+  let left = (1.0 - balance).min(1.0);
+  let right = (1.0 + balance).min(1.0);
+  Some(format!("aformat=channel_layouts=stereo,pan=stereo|c0={:.2}*c0|c1={:.2}*c1", left, right))
 }
 
 /// Build a filter_complex string that trims video/audio to `kept` segments and concats them.
-fn build_filter_complex(kept: &[Cut]) -> String {
-  // labels [v0],[a0].. concat to [outv][outa]
+/// `video_filter` (e.g. a crop) is applied to the joined video.
+/// Without `has_video` only the audio is trimmed and concatenated (to [outa]), for audio-only
+/// projects. The audio comes from the input's `audio_stream`th audio stream
+fn build_filter_complex(
+  kept: &[Cut],
+  has_video: bool,
+  has_audio: bool,
+  audio_stream: usize,
+  video_filter: Option<&str>,
+  audio_filter: Option<&str>,
+) -> String {
+  // labels [v0],[a0].. concat to [outv][outa]; a source without audio gets no [outa]
   let mut filter = String::new();
   let mut v_labels = Vec::with_capacity(kept.len());
   let mut a_labels = Vec::with_capacity(kept.len());
 
   for (i, (s, e)) in kept.iter().enumerate() {
-    filter.push_str(&format!(
-      "[0:v]trim=start={}:end={},setpts=PTS-STARTPTS[v{idx}];\
-       [0:a]atrim=start={}:end={},asetpts=PTS-STARTPTS,aresample=async=1:first_pts=0[a{idx}];",
-      s, e, s, e, idx = i
-    ));
-    v_labels.push(format!("[v{}]", i));
-    a_labels.push(format!("[a{}]", i));
+    if has_video {
+      filter.push_str(&format!("[0:v]trim=start={}:end={},setpts=PTS-STARTPTS[v{}];", s, e, i));
+      v_labels.push(format!("[v{}]", i));
+    }
+    if has_audio {
+      filter.push_str(&format!(
+        "[0:a:{}]atrim=start={}:end={},asetpts=PTS-STARTPTS,aresample=async=1:first_pts=0[a{}];",
+        audio_stream, s, e, i
+      ));
+      a_labels.push(format!("[a{}]", i));
+    }
   }
+  let concat_v = match (has_video, video_filter.is_some()) {
+    (false, _) => "",
+    (true, true) => "[catv]",
+    (true, false) => "[outv]",
+  };
+  let concat_a = match (has_audio, audio_filter.is_some()) {
+    (false, _) => "",
+    (true, true) => "[cata]",
+    (true, false) => "[outa]",
+  };
   filter.push_str(&format!(
-    "{}{}concat=n={}:v=1:a=1[outv][outa]",
+    "{}{}concat=n={}:v={}:a={}{}{}",
     v_labels.join(""),
     a_labels.join(""),
-    kept.len()
+    kept.len(),
+    u8::from(has_video),
+    u8::from(has_audio),
+    concat_v,
+    concat_a
   ));
+  if let Some(vf) = video_filter.filter(|_| has_video) {
+    filter.push_str(&format!(";[catv]{}[outv]", vf));
+  }
+  if let Some(af) = audio_filter.filter(|_| has_audio) {
+    filter.push_str(&format!(";[cata]{}[outa]", af));
+  }
   filter
 }
 
+/// Length of the fade at each end of an export when the project fades its timeline ends
+const TIMELINE_END_FADE: f64 = 1.0;
+
+/// Video and audio filters fading an output of `duration` seconds in from black/silence and
+/// out to it, see ProjectSettings::fade_timeline_ends. Short outputs fade over half each
+fn timeline_end_fades(duration: f64) -> (String, String) {
+  let d = TIMELINE_END_FADE.min(duration / 2.0);
+  let out = (duration - d).max(0.0);
+  (
+    format!("fade=t=in:st=0:d={},fade=t=out:st={}:d={}", d, out, d),
+    format!("afade=t=in:st=0:d={},afade=t=out:st={}:d={}", d, out, d),
+  )
+}
+
+/// The whole output's video and audio filters followed by the timeline end fades. They go last,
+/// after the audio settings, so loudness normalization doesn't undo the fade
+fn with_timeline_end_fades(
+  video_filter: Option<String>,
+  audio_filter: Option<String>,
+  duration: f64,
+) -> (Option<String>, Option<String>) {
+  let (video_fade, audio_fade) = timeline_end_fades(duration);
+  (chain_filters(video_filter, Some(video_fade)), chain_filters(audio_filter, Some(audio_fade)))
+}
+
+/// `a` followed by `b` in one filter chain
+pub(crate) fn chain_filters(a: Option<String>, b: Option<String>) -> Option<String> {
+  match (a, b) {
+    (Some(a), Some(b)) => Some(format!("{},{}", a, b)),
+    (a, b) => a.or(b),
+  }
+}
+
+/// Size of the waveform picture used as the video of audio-only exports
+const WAVEFORM_VIDEO_SIZE: (u32, u32) = (1280, 720);
+
+/// Write the waveform picture of `input`'s `audio_stream`th audio stream next to `output` for a
+/// waveform-video export; the caller removes it
+fn write_waveform_still(input: &str, audio_stream: usize, output: &Path) -> Result<PathBuf> {
+  let (width, height) = WAVEFORM_VIDEO_SIZE;
+  let png = waveform_image(input, Some(audio_stream), width, height)?;
+  let still = output.with_extension("waveform.png");
+  fs::write(&still, png).with_context(|| format!("failed to write {:?}", still))?;
+  Ok(still)
+}
+
 /// Create a sibling path `.../name.tmp.ext` for atomic writes.
-fn temp_output_path(output: &Path) -> PathBuf {
+pub(crate) fn temp_output_path(output: &Path) -> PathBuf {
   let parent = output.parent().unwrap_or_else(|| Path::new("."));
   let stem = output
     .file_stem()
@@ -219,231 +925,2415 @@ fn temp_output_path(output: &Path) -> PathBuf {
 
 /// --- Export with cuts ----------------------------------------------------------------
 
-/// Export a new file with the specified `ranges_to_cut` removed.
-/// Uses filter_complex trim/concat (re-encodes to H.264/AAC).
-pub fn export_with_cuts(input: &str, output: &str, ranges_to_cut: &[(f64, f64)]) -> Result<()> {
-  if !ffmpeg_exists() {
-    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+  #[default]
+  Mp4,
+  Mov,
+  Mkv,
+  Webm,
+  M4a, // Audio only; the video codec is ignored
+}
+
+impl Container {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Container::Mp4 => "mp4",
+      Container::Mov => "mov",
+      Container::Mkv => "mkv",
+      Container::Webm => "webm",
+      Container::M4a => "m4a",
+    }
   }
 
-  // If nothing to cut → copy as-is (fast).
-  if ranges_to_cut.is_empty() {
-    fs::copy(input, output)
-      .with_context(|| format!("failed to copy {} -> {}", input, output))?;
-    return Ok(());
+  fn muxer(&self) -> &'static str {
+    match self {
+      Container::Mp4 => "mp4",
+      Container::Mov => "mov",
+      Container::Mkv => "matroska",
+      Container::Webm => "webm",
+      Container::M4a => "ipod",
+    }
   }
 
-  let probe = ffprobe(input).context("ffprobe failed")?;
-  let duration = probe.duration;
+  /// Only the ISO BMFF containers have a moov atom to move
+  fn supports_faststart(&self) -> bool {
+    matches!(self, Container::Mp4 | Container::Mov | Container::M4a)
+  }
 
-  // Normalize requested cuts.
-  let normalized = normalize_cuts(ranges_to_cut.to_vec(), duration);
-  if normalized.is_empty() {
-    // All cuts invalid/degenerate → just copy.
-    fs::copy(input, output)
-      .with_context(|| format!("failed to copy {} -> {}", input, output))?;
-    return Ok(());
+  pub fn has_video(&self) -> bool {
+    *self != Container::M4a
   }
 
-  // Convert to kept segments.
-  let kept = to_kept_segments(&normalized, duration);
-  if kept.is_empty() {
-    return Err(anyhow!("All content would be cut out (no kept segments)."));
+  /// Whether streams in these codecs (ffprobe names, empty for no stream) can be copied into
+  /// this container without re-encoding
+  fn holds_codecs(&self, v_codec: &str, a_codec: &str) -> bool {
+    let pcm = a_codec.starts_with("pcm_");
+    let (video_ok, audio_ok) = match self {
+      Container::Mp4 => (
+        matches!(v_codec, "h264" | "hevc" | "av1" | "vp9" | "mpeg4"),
+        matches!(a_codec, "aac" | "mp3" | "opus" | "flac" | "ac3" | "eac3" | "alac"),
+      ),
+      Container::Mov => (
+        matches!(v_codec, "h264" | "hevc" | "prores" | "mpeg4" | "mjpeg"),
+        matches!(a_codec, "aac" | "mp3" | "alac" | "ac3") || pcm,
+      ),
+      Container::Mkv => (true, true),
+      Container::Webm => (matches!(v_codec, "vp8" | "vp9" | "av1"), matches!(a_codec, "opus" | "vorbis")),
+      Container::M4a => (true, matches!(a_codec, "aac" | "alac")),
+    };
+    (video_ok || v_codec.is_empty() || !self.has_video()) && (audio_ok || a_codec.is_empty())
   }
 
-  let filter_complex = build_filter_complex(&kept);
-  let tmp = temp_output_path(Path::new(output));
+  /// Whether a file ffprobe reports as `format_name` (e.g. "mov,mp4,m4a,3gp,3g2,mj2") already
+  /// is this container, so copying the file whole gives a valid output
+  fn is_format(&self, format_name: &str) -> bool {
+    let name = match self {
+      Container::Mp4 | Container::Mov | Container::M4a => "mov",
+      Container::Mkv => "matroska",
+      Container::Webm => "webm",
+    };
+    format_name.split(',').any(|f| f == name)
+  }
+}
 
-  // Encode. You can switch codecs/presets as needed.
-  let status = Command::new("ffmpeg")
-    .args([
-      "-v",
-      "error",
-      "-i",
-      input,
-      "-filter_complex",
-      &filter_complex,
-      "-map",
-      "[outv]",
-      "-map",
-      "[outa]",
-      "-c:v",
-      "libx264",
-      "-preset",
-      "medium",
-      "-crf",
-      "20",
-      "-pix_fmt",
-      "yuv420p",
-      "-c:a",
-      "aac",
-      "-b:a",
-      "192k",
-      "-movflags",
-      "+faststart",
-      "-y",
-      tmp.to_string_lossy().as_ref(),
-    ])
-    .status()
-    .with_context(|| "failed to spawn ffmpeg for export")?;
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+  #[default]
+  H264,
+  H265,
+  Vp9,
+  Av1,
+  ProRes,
+}
 
-  if !status.success() {
-    // Cleanup partial temp
-    let _ = fs::remove_file(&tmp);
-    return Err(anyhow!("ffmpeg export failed (status {:?})", status.code()));
+impl VideoCodec {
+  fn args(&self) -> &'static [&'static str] {
+    match self {
+      VideoCodec::H264 => &["-c:v", "libx264", "-preset", "medium", "-crf", "20", "-pix_fmt", "yuv420p"],
+      VideoCodec::H265 => &["-c:v", "libx265", "-preset", "medium", "-crf", "22", "-pix_fmt", "yuv420p", "-tag:v", "hvc1"],
+      VideoCodec::Vp9 => &["-c:v", "libvpx-vp9", "-crf", "32", "-b:v", "0", "-row-mt", "1", "-pix_fmt", "yuv420p"],
+      VideoCodec::Av1 => &["-c:v", "libsvtav1", "-preset", "8", "-crf", "32", "-pix_fmt", "yuv420p"],
+      VideoCodec::ProRes => &["-c:v", "prores_ks", "-profile:v", "3", "-pix_fmt", "yuv422p10le"],
+    }
   }
 
-  // Atomic replace.
-  fs::rename(&tmp, output).with_context(|| "failed to move tmp output into place")?;
-  Ok(())
+  /// Short description for the export fingerprint
+  fn describe(&self) -> &'static str {
+    match self {
+      VideoCodec::H264 => "libx264 medium crf20 yuv420p",
+      VideoCodec::H265 => "libx265 medium crf22 yuv420p",
+      VideoCodec::Vp9 => "libvpx-vp9 crf32 yuv420p",
+      VideoCodec::Av1 => "libsvtav1 p8 crf32 yuv420p",
+      VideoCodec::ProRes => "prores_ks hq yuv422p10le",
+    }
+  }
 }
 
-/// --- Preview Proxy -------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+  #[default]
+  Aac,
+  Opus,
+  Flac,
+  Pcm,
+}
 
-/// Make a small H.264/AAC proxy mp4 for reliable WebView playback.
-/// Returns the output path. If `max_w` is `Some`, downscales width, preserving AR.
-pub fn make_preview_proxy(input: &str, max_w: Option<u32>) -> Result<String> {
-  if !ffmpeg_exists() {
-    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+impl AudioCodec {
+  fn args(&self) -> &'static [&'static str] {
+    match self {
+      AudioCodec::Aac => &["-c:a", "aac", "-b:a", "192k"],
+      AudioCodec::Opus => &["-c:a", "libopus", "-b:a", "160k"],
+      AudioCodec::Flac => &["-c:a", "flac"],
+      AudioCodec::Pcm => &["-c:a", "pcm_s24le"],
+    }
   }
 
-  let input_path = Path::new(input);
-  let stem = input_path
-    .file_stem()
-    .ok_or_else(|| anyhow!("Invalid input file path"))?
-    .to_string_lossy();
+  fn describe(&self) -> &'static str {
+    match self {
+      AudioCodec::Aac => "aac 192k",
+      AudioCodec::Opus => "opus 160k",
+      AudioCodec::Flac => "flac",
+      AudioCodec::Pcm => "pcm_s24le",
+    }
+  }
+}
 
-  // Use Downloads directory for better Tauri compatibility
-  let downloads_dir = dirs::download_dir().unwrap_or_else(|| std::env::temp_dir());
-  let out_path = downloads_dir.join(format!("{}_proxy.mp4", stem));
-  let out_str = out_path.to_string_lossy().to_string();
+/// Video quality target overriding the codec's default CRF
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateControl {
+  Crf(u8),         // Constant quality; lower is better
+  Bitrate(String), // Average bitrate in ffmpeg notation, e.g. "8M" or "2500k"
+}
 
-  // scale filter if requested (960 width by default is a good dev choice)
-  let scale = max_w.unwrap_or(960);
-  let vf = format!("scale='min({scale},iw)':-2");
+/// libx264/libx265 speed presets, fastest first
+const X264_PRESETS: &[&str] = &[
+  "ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow", "placebo",
+];
 
-  let status = Command::new("ffmpeg")
-    .args([
-      "-v",
-      "error",
-      "-i",
-      input,
-      "-vf",
-      &vf,
-      "-c:v",
-      "libx264",
-      "-preset",
-      "ultrafast",
-      "-crf",
-      "28",
-      "-pix_fmt",
-      "yuv420p",
-      "-c:a",
-      "aac",
-      "-b:a",
-      "96k",
-      "-movflags",
-      "+faststart",
-      "-y",
-      &out_str,
-    ])
-    .status()
-    .with_context(|| "failed to spawn ffmpeg for proxy")?;
+/// Whether `rate` is a bitrate ffmpeg accepts: digits with an optional k or M suffix
+fn is_bitrate(rate: &str) -> bool {
+  let digits = rate.strip_suffix(['k', 'K', 'M']).unwrap_or(rate);
+  digits.parse::<f64>().is_ok_and(|n| n > 0.0)
+}
 
-  if !status.success() {
-    return Err(anyhow!(
-      "ffmpeg proxy creation failed (status {:?})",
-      status.code()
-    ));
+/// Set the value following `flag` in `args`, appending both if the flag isn't there
+fn set_arg(args: &mut Vec<String>, flag: &str, value: &str) {
+  match args.iter().position(|a| a == flag) {
+    Some(i) if i + 1 < args.len() => args[i + 1] = value.to_string(),
+    _ => args.extend([flag.to_string(), value.to_string()]),
   }
+}
 
-  Ok(out_str)
+/// Remove `flag` and its value from `args`
+fn remove_arg(args: &mut Vec<String>, flag: &str) {
+  if let Some(i) = args.iter().position(|a| a == flag) {
+    args.drain(i..(i + 2).min(args.len()));
+  }
 }
 
-/// --- Thumbnail Generation ------------------------------------------------------------
+/// What an export does with HDR (PQ/HLG) input
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HdrMode {
+  #[default]
+  Auto,         // Tone-map to SDR unless the project targets HDR
+  ForceTonemap, // Always tone-map to SDR
+  Passthrough,  // Keep the source transfer; HDR input stays HDR
+}
 
-/// Generate video thumbnails at regular intervals for timeline scrubbing.
-/// Returns a vector of base64-encoded thumbnail images.
-/// For audio files, returns an empty vector.
-pub fn generate_thumbnails(input: &str, count: usize, width: u32) -> Result<Vec<String>> {
-  if !ffmpeg_exists() {
-    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
-  }
+/// Output format for cut-list exports. The default (H.264/AAC in MP4 with faststart)
+/// matches what exports have always produced.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ExportSettings {
+  #[serde(default)]
+  pub container: Container,
+  #[serde(default)]
+  pub video_codec: VideoCodec,
+  #[serde(default)]
+  pub audio_codec: AudioCodec,
+  #[serde(default)]
+  pub faststart: Option<bool>, // None: on for mp4/mov, off otherwise
+  #[serde(default)]
+  pub pan: Option<i8>, // Stereo balance -100..100; None: the pan of the project track playing the input
+  #[serde(default)]
+  pub hdr_mode: HdrMode,
+  #[serde(default)]
+  pub track_effects: Option<Vec<project_file::Effect>>, // None: the effects of the project track playing the input
+  #[serde(default)]
+  pub waveform_video: bool, // Audio-only projects: add a static waveform picture as the video instead of exporting audio only
+  #[serde(default)]
+  pub encoder: Option<String>, // H.264 encoder, e.g. "h264_nvenc" (see list_available_encoders); None: libx264
+  #[serde(default)]
+  pub rate_control: Option<RateControl>, // None: the codec's default CRF
+  #[serde(default)]
+  pub encoder_preset: Option<String>, // Speed preset: x264/x265 names, or 0-13 for AV1; None: the codec's default
+  #[serde(default)]
+  pub max_height: Option<u32>, // Taller video is scaled down to this, keeping the aspect ratio
+  #[serde(default)]
+  pub audio_bitrate: Option<String>, // e.g. "320k"; AAC and Opus only; None: the codec's default
+  #[serde(default)]
+  pub normalize_loudness: Option<LoudnessTarget>, // Two-pass EBU R128 normalization of the exported audio
+  #[serde(default)]
+  pub allow_upscale: bool, // Scale shorter video up to max_height and render timelines above the sources' frame rate; see upscale_check
+  #[serde(default)]
+  pub audio_stream: Option<usize>, // Audio stream of the input to export (counting audio streams only), e.g. the clip's pick; None: the file's default
+}
 
-  let probe = ffprobe(input).context("ffprobe failed")?;
-  let duration = probe.duration;
-  
+/// Frame rates within this fraction of the fastest source aren't above it (29.97 vs 30)
+const FPS_TOLERANCE: f64 = 0.01;
+
+/// Tallest picture and fastest frame rate among an export's video sources
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceLimits {
+  pub height: u32,
+  pub fps: f64,
+}
+
+impl SourceLimits {
+  /// Limits of the video among `probes`; None when none of them has a picture
+  pub fn of<'a>(probes: impl IntoIterator<Item = &'a Probe>) -> Option<Self> {
+    probes
+      .into_iter()
+      .filter(|p| p.width > 0 && p.height > 0)
+      .map(|p| SourceLimits { height: display_size(p).1, fps: p.fps })
+      .reduce(|a, b| SourceLimits { height: a.height.max(b.height), fps: a.fps.max(b.fps) })
+  }
+
+  /// Whether `fps` is faster than every source
+  pub fn fps_above(&self, fps: f64) -> bool {
+    self.fps > 0.0 && fps > self.fps * (1.0 + FPS_TOLERANCE)
+  }
+}
+
+/// What an export asking for more than its sources have did about it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpscaleDecision {
+  Clamped,  // Kept to the sources' height and frame rate
+  Upscaled, // Scaled up, as `allow_upscale` asked
+}
+
+impl ExportSettings {
+  pub fn faststart(&self) -> bool {
+    self.faststart.unwrap_or(self.container.supports_faststart())
+  }
+
+  pub fn pan(&self) -> i8 {
+    self.pan.unwrap_or(0)
+  }
+
+  pub fn track_effects(&self) -> &[project_file::Effect] {
+    self.track_effects.as_deref().unwrap_or_default()
+  }
+
+  /// The hardware encoder asked for, if any
+  pub fn hw_encoder(&self) -> Option<&str> {
+    self.encoder.as_deref().filter(|e| *e != SOFTWARE_ENCODER)
+  }
+
+  /// ffmpeg encoders an export with these settings runs, video first
+  pub fn encoder_names(&self) -> Vec<&str> {
+    let video = match (self.video_codec, self.hw_encoder()) {
+      (VideoCodec::H264, Some(encoder)) => encoder,
+      (codec, _) => codec.args()[1],
+    };
+    vec![video, self.audio_codec.args()[1]]
+  }
+
+  /// Audio filter for exports: the track effects (see audio_effects for the chain order), then pan
+  pub fn audio_filter(&self) -> Result<Option<String>> {
+    let mut chain = audio_effects::audio_chain(0.0, self.track_effects(), None)?;
+    chain.extend(pan_filter(self.pan()));
+    Ok((!chain.is_empty()).then(|| chain.join(",")))
+  }
+
+  /// Tone-mapping filter for `probe`'s video, if these settings convert it to SDR
+  pub fn tonemap_filter(&self, probe: &Probe) -> Option<String> {
+    match self.hdr_mode {
+      HdrMode::Passthrough => None,
+      HdrMode::Auto | HdrMode::ForceTonemap => probe.tonemap_filter(),
+    }
+  }
+
+  /// Scale filter applying `max_height`, if set. Shorter video is only scaled up to it with
+  /// `allow_upscale`
+  pub fn scale_filter(&self) -> Option<String> {
+    self.max_height.map(|height| match self.allow_upscale {
+      true => format!("scale=-2:{}", height),
+      false => format!("scale=-2:'min({},ih)'", height),
+    })
+  }
+
+  /// Whether exporting sources within `limits`, rendered at `render_fps` (None: the sources'
+  /// own rate), asks for more than they have: a `max_height` taller than every source, or a
+  /// frame rate above all of them. Returns what the export does about it, clamp or upscale
+  /// per `allow_upscale`, with a warning for the user; None when nothing is above the sources
+  pub fn upscale_check(&self, limits: &SourceLimits, render_fps: Option<f64>) -> Option<(UpscaleDecision, String)> {
+    let mut above = Vec::new();
+    if let Some(height) = self.max_height.filter(|h| *h > limits.height) {
+      above.push((format!("{}p", height), format!("{}p", limits.height)));
+    }
+    if let Some(fps) = render_fps.filter(|f| limits.fps_above(*f)) {
+      above.push((format!("{} fps", fps), format!("{} fps", limits.fps)));
+    }
+    if above.is_empty() {
+      return None;
+    }
+    let (asked, have): (Vec<String>, Vec<String>) = above.into_iter().unzip();
+    let (asked, have) = (asked.join(" at "), have.join(" at "));
+    Some(match self.allow_upscale {
+      true => (UpscaleDecision::Upscaled, format!("{} is above every source ({}); the export is upscaled, as allowed", asked, have)),
+      false => (
+        UpscaleDecision::Clamped,
+        format!("{} is above every source ({}); the export stays at {} unless upscaling is allowed", asked, have, have),
+      ),
+    })
+  }
+
+  /// Reject combinations ffmpeg would fail on (or silently write unplayable files for),
+  /// and an `output` whose extension doesn't match the container.
+  pub fn validate(&self, output: &str) -> Result<()> {
+    self.validate_format()?;
+    let container = self.container.extension();
+    let extension = Path::new(output)
+      .extension()
+      .and_then(|e| e.to_str())
+      .map(|e| e.to_lowercase())
+      .unwrap_or_default();
+    if extension != container {
+      return Err(anyhow!("output extension '.{}' doesn't match the {} container", extension, container));
+    }
+    Ok(())
+  }
+
+  /// `validate` without an output file, e.g. for presets
+  pub fn validate_format(&self) -> Result<()> {
+    let (video_ok, audio_ok) = match self.container {
+      Container::Mp4 => (
+        matches!(self.video_codec, VideoCodec::H264 | VideoCodec::H265 | VideoCodec::Av1),
+        matches!(self.audio_codec, AudioCodec::Aac | AudioCodec::Opus | AudioCodec::Flac),
+      ),
+      Container::Mov => (
+        matches!(self.video_codec, VideoCodec::H264 | VideoCodec::H265 | VideoCodec::ProRes),
+        matches!(self.audio_codec, AudioCodec::Aac | AudioCodec::Pcm),
+      ),
+      Container::Mkv => (true, true),
+      Container::Webm => (
+        matches!(self.video_codec, VideoCodec::Vp9 | VideoCodec::Av1),
+        matches!(self.audio_codec, AudioCodec::Opus),
+      ),
+      Container::M4a => (true, matches!(self.audio_codec, AudioCodec::Aac)),
+    };
+    let container = self.container.extension();
+    if !video_ok {
+      return Err(anyhow!("video codec {:?} can't be stored in {}", self.video_codec, container));
+    }
+    if !audio_ok {
+      return Err(anyhow!("audio codec {:?} can't be stored in {}", self.audio_codec, container));
+    }
+    if self.faststart == Some(true) && !self.container.supports_faststart() {
+      return Err(anyhow!("faststart only applies to mp4 and mov, not {}", container));
+    }
+    if !(-100..=100).contains(&self.pan()) {
+      return Err(anyhow!("pan {} is outside -100..100", self.pan()));
+    }
+    for effect in self.track_effects() {
+      audio_effects::validate(effect)?;
+    }
+    validate_encoder(self.encoder.as_deref())?;
+    if self.hw_encoder().is_some() && self.video_codec != VideoCodec::H264 {
+      return Err(anyhow!("hardware encoders only write H.264, not {:?}", self.video_codec));
+    }
+    if self.hw_encoder().is_some() && (self.rate_control.is_some() || self.encoder_preset.is_some()) {
+      return Err(anyhow!("hardware encoders use their own quality settings; drop the CRF, bitrate and preset"));
+    }
+    if self.waveform_video && !self.container.has_video() {
+      return Err(anyhow!("a waveform video can't be stored in {}", container));
+    }
+    self.validate_quality()?;
+    if let Some(rate) = &self.audio_bitrate {
+      if !matches!(self.audio_codec, AudioCodec::Aac | AudioCodec::Opus) {
+        return Err(anyhow!("{:?} is lossless and takes no bitrate", self.audio_codec));
+      }
+      if !is_bitrate(rate) {
+        return Err(anyhow!("audio bitrate '{}' isn't a number with an optional k or M suffix", rate));
+      }
+    }
+    if let Some(height) = self.max_height {
+      if height < 16 || height % 2 != 0 {
+        return Err(anyhow!("maximum height {} must be even and at least 16", height));
+      }
+    }
+    if let Some(target) = &self.normalize_loudness {
+      target.validate()?;
+    }
+    Ok(())
+  }
+
+  /// Rate control and speed preset against what the video codec understands
+  fn validate_quality(&self) -> Result<()> {
+    let codec = self.video_codec;
+    match &self.rate_control {
+      Some(_) if codec == VideoCodec::ProRes => {
+        return Err(anyhow!("ProRes quality is set by its profile, not a CRF or bitrate"));
+      }
+      Some(RateControl::Crf(crf)) => {
+        let max = if matches!(codec, VideoCodec::H264 | VideoCodec::H265) { 51 } else { 63 };
+        if *crf > max {
+          return Err(anyhow!("CRF {} is outside 0..{} for {:?}", crf, max, codec));
+        }
+      }
+      Some(RateControl::Bitrate(rate)) if !is_bitrate(rate) => {
+        return Err(anyhow!("video bitrate '{}' isn't a number with an optional k or M suffix", rate));
+      }
+      _ => {}
+    }
+    let Some(preset) = &self.encoder_preset else {
+      return Ok(());
+    };
+    let known = match codec {
+      VideoCodec::H264 | VideoCodec::H265 => X264_PRESETS.contains(&preset.as_str()),
+      VideoCodec::Av1 => preset.parse::<u8>().is_ok_and(|p| p <= 13),
+      VideoCodec::Vp9 | VideoCodec::ProRes => {
+        return Err(anyhow!("{:?} has no speed presets", codec));
+      }
+    };
+    if !known {
+      return Err(anyhow!("unknown {:?} preset '{}'", codec, preset));
+    }
+    Ok(())
+  }
+
+  /// Part of the export fingerprint. Default settings give the same string as before
+  /// settings existed, so older sidecars stay valid.
+  pub fn describe(&self) -> String {
+    format!(
+      "{} / {} / {}{}{}{}",
+      self.video_codec.describe(),
+      self.audio_codec.describe(),
+      self.container.extension(),
+      if self.faststart() { " +faststart" } else { "" },
+      match self.pan() {
+        0 => String::new(),
+        pan => format!(" pan {}", pan),
+      },
+      match self.track_effects() {
+        [] => String::new(),
+        effects => format!(" fx {}", serde_json::to_string(effects).unwrap_or_default()),
+      }
+    ) + if self.waveform_video { " +waveform" } else { "" }
+      + &self.hw_encoder().map(|e| format!(" enc {}", e)).unwrap_or_default()
+      + &match &self.rate_control {
+        Some(RateControl::Crf(crf)) => format!(" crf {}", crf),
+        Some(RateControl::Bitrate(rate)) => format!(" vbr {}", rate),
+        None => String::new(),
+      }
+      + &self.encoder_preset.as_ref().map(|p| format!(" preset {}", p)).unwrap_or_default()
+      + &self.max_height.map(|h| format!(" max {}p", h)).unwrap_or_default()
+      + &self.audio_bitrate.as_ref().map(|r| format!(" audio {}", r)).unwrap_or_default()
+      + &self
+        .normalize_loudness
+        .as_ref()
+        .map(|t| format!(" loudnorm I{} TP{} LRA{}", t.integrated, t.true_peak, t.lra))
+        .unwrap_or_default()
+      + if self.allow_upscale { " +upscale" } else { "" }
+      + &self.audio_stream.map(|i| format!(" audio stream {}", i)).unwrap_or_default()
+  }
+
+  /// Copying is only a valid shortcut when the default format was asked for, it can hold the
+  /// source's codecs, and nothing (tone-mapping, pan, track effects, a non-default audio stream)
+  /// has to touch the decoded media
+  pub(crate) fn allows_stream_copy(&self, probe: &Probe) -> bool {
+    self.container.holds_codecs(&probe.v_codec, &probe.a_codec)
+      && self.tonemap_filter(probe).is_none()
+      && self.pan() == 0
+      && self.track_effects().is_empty()
+      && self.audio_stream.map_or(true, |i| i == probe.audio_stream)
+      && ExportSettings { pan: None, hdr_mode: HdrMode::default(), track_effects: None, encoder: None, allow_upscale: false, audio_stream: None, ..self.clone() }
+        == ExportSettings::default()
+  }
+
+  /// Video codec arguments with the rate control and speed preset applied
+  pub(crate) fn video_args(&self) -> Vec<String> {
+    let mut args: Vec<String> = self.video_codec.args().iter().map(|a| a.to_string()).collect();
+    if let Some(preset) = &self.encoder_preset {
+      set_arg(&mut args, "-preset", preset);
+    }
+    match &self.rate_control {
+      Some(RateControl::Crf(crf)) => set_arg(&mut args, "-crf", &crf.to_string()),
+      Some(RateControl::Bitrate(rate)) => {
+        remove_arg(&mut args, "-crf");
+        set_arg(&mut args, "-b:v", rate);
+      }
+      None => {}
+    }
+    args
+  }
+
+  pub(crate) fn audio_args(&self) -> Vec<String> {
+    let mut args: Vec<String> = self.audio_codec.args().iter().map(|a| a.to_string()).collect();
+    if let Some(rate) = &self.audio_bitrate {
+      set_arg(&mut args, "-b:a", rate);
+    }
+    args
+  }
+
+  pub(crate) fn muxer_args(&self) -> Vec<&'static str> {
+    let mut args = vec!["-f", self.container.muxer()];
+    if self.faststart() {
+      args.extend(["-movflags", "+faststart"]);
+    }
+    args
+  }
+
+  /// Matroska is written progressively and stays readable if cut short, so MKV exports,
+  /// re-exports over an earlier one included, are written in place. Not when `output` is one
+  /// of the `inputs`: ffmpeg would truncate the file it is reading, so that goes through a temp
+  /// file and rename
+  fn writes_in_place(&self, output: &Path, inputs: &[&Path]) -> bool {
+    let same_file = |input: &Path| input == output || fs::canonicalize(input).ok().is_some_and(|i| fs::canonicalize(output).ok() == Some(i));
+    self.container == Container::Mkv && !inputs.iter().any(|&input| same_file(input))
+  }
+}
+
+/// Named export settings. Built-in presets come from `builtin_export_presets`; custom ones
+/// are stored in the project settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportPreset {
+  pub id: String,
+  pub name: String,
+  #[serde(default)]
+  pub built_in: bool,
+  pub settings: ExportSettings,
+}
+
+/// Presets shipped with the app, in menu order
+pub fn builtin_export_presets() -> Vec<ExportPreset> {
+  let preset = |id: &str, name: &str, settings: ExportSettings| ExportPreset {
+    id: id.to_string(),
+    name: name.to_string(),
+    built_in: true,
+    settings,
+  };
+  vec![
+    preset("youtube_1080p", "YouTube 1080p", ExportSettings {
+      rate_control: Some(RateControl::Crf(18)),
+      encoder_preset: Some("slow".to_string()),
+      max_height: Some(1080),
+      audio_bitrate: Some("320k".to_string()),
+      ..ExportSettings::default()
+    }),
+    preset("web_720p", "Web 720p", ExportSettings {
+      rate_control: Some(RateControl::Crf(23)),
+      max_height: Some(720),
+      audio_bitrate: Some("128k".to_string()),
+      ..ExportSettings::default()
+    }),
+    preset("archive_prores", "Archive ProRes", ExportSettings {
+      container: Container::Mov,
+      video_codec: VideoCodec::ProRes,
+      audio_codec: AudioCodec::Pcm,
+      ..ExportSettings::default()
+    }),
+    preset("audio_only", "Audio only", ExportSettings {
+      container: Container::M4a,
+      audio_bitrate: Some("256k".to_string()),
+      ..ExportSettings::default()
+    }),
+  ]
+}
+
+/// What `export_with_cuts` did, and with which video encoder
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportResult {
+  pub outcome: ExportOutcome,
+  pub encoder: Option<EncoderUsed>, // None when nothing was encoded or the input was copied
+  #[serde(default)]
+  pub upscale: Option<UpscaleDecision>, // Set when `max_height` was above the input, see upscale_check
+}
+
+/// Result of an export request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ExportOutcome {
+  Encoded,
+  UpToDate, // Existing output matched the request fingerprint; nothing was encoded
+}
+
+/// Progress of an export, sent to the UI as "export-progress". The last event for a job has
+/// `done` set, and `error` (with ffmpeg's stderr) if the export failed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportProgress {
+  pub job_id: String,
+  pub percent: f64,
+  pub eta_seconds: Option<f64>, // None until enough has been encoded to estimate
+  pub done: bool,
+  pub outcome: Option<ExportOutcome>,
+  pub error: Option<String>,
+}
+
+/// Fingerprint of a cut-list export, plus the source fingerprints it was built from.
+pub fn cut_export_fingerprint(
+  input: &str,
+  ranges_to_cut: &[(f64, f64)],
+  settings: &ExportSettings,
+  reframe_aspect: Option<f64>,
+) -> Result<(String, Vec<export_sidecar::SourceFingerprint>)> {
+  let sources = vec![export_sidecar::fingerprint_source(input)?];
+  let settings = export_summary(input, settings, reframe_aspect);
+  let fingerprint = export_sidecar::fingerprint_request(&sources, &ranges_to_cut, &settings)?;
+  Ok((fingerprint, sources))
+}
+
+/// Everything besides the cuts that changes a cut-list export of `input`, in words
+fn export_summary(input: &str, settings: &ExportSettings, reframe_aspect: Option<f64>) -> String {
+  // Only a conversion that actually happens changes the output, so the HDR mode counts
+  // when the input is HDR and not otherwise
+  let probe = ffprobe(input).ok();
+  let tonemap = probe.as_ref().and_then(|probe| settings.tonemap_filter(probe)).is_some();
+  let mut settings = settings.describe();
+  if tonemap {
+    settings.push_str(" / tonemap bt709");
+  }
+  if let Some(aspect) = reframe_aspect {
+    settings.push_str(&format!(" / reframe {}", aspect));
+  }
+  if probe.is_some_and(|probe| probe.is_audio_only()) {
+    settings.push_str(" / audio only");
+  }
+  if project_file::fades_timeline_ends() {
+    settings.push_str(&format!(" / fade ends {}s", TIMELINE_END_FADE));
+  }
+  settings
+}
+
+/// Export a new file with the specified `ranges_to_cut` removed, optionally center-cropped to
+/// `reframe_aspect` (width / height) using the same math as the preview guides.
+/// Skips encoding when `output` already holds the result of an identical request, unless `force` is set.
+/// `settings` are validated before anything runs. `on_progress` receives the encoded fraction (0..1).
+/// A hardware encoder in `settings.encoder` that fails is replaced by libx264; the result says so.
+pub fn export_with_cuts(
+  input: &str,
+  output: &str,
+  ranges_to_cut: &[(f64, f64)],
+  settings: &ExportSettings,
+  reframe_aspect: Option<f64>,
+  force: bool,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<ExportResult> {
+  settings.validate(output)?;
+  if !ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+
+  let (fingerprint, sources) = cut_export_fingerprint(input, ranges_to_cut, settings, reframe_aspect)?;
+  if !force && export_sidecar::export_status(Path::new(output), &fingerprint).up_to_date {
+    // Reused from before the setting was on, say
+    if !export_sidecar::edits_path(Path::new(output)).exists() {
+      record_cut_export_edits(input, output, ranges_to_cut, settings, reframe_aspect, &fingerprint);
+    }
+    return Ok(ExportResult { outcome: ExportOutcome::UpToDate, encoder: None, upscale: None });
+  }
+
+  let limits = ffprobe(input).ok().and_then(|probe| SourceLimits::of([&probe]));
+  let upscale = limits.and_then(|limits| settings.upscale_check(&limits, None)).map(|(decision, _)| decision);
+  let encoder = encode_with_cuts(input, output, ranges_to_cut, settings, reframe_aspect, on_progress)?;
+  export_sidecar::write_sidecar(Path::new(output), &fingerprint, sources, upscale, encode_profile::profile_for(settings))?;
+  record_cut_export_edits(input, output, ranges_to_cut, settings, reframe_aspect, &fingerprint);
+  Ok(ExportResult { outcome: ExportOutcome::Encoded, encoder, upscale })
+}
+
+/// `record_export_edits` for a cut-list export of `input`
+fn record_cut_export_edits(
+  input: &str,
+  output: &str,
+  ranges_to_cut: &[Cut],
+  settings: &ExportSettings,
+  reframe_aspect: Option<f64>,
+  fingerprint: &str,
+) {
+  record_export_edits(output, &[input.to_string()], Some(fingerprint), |duration| {
+    let cuts = normalize_cuts(ranges_to_cut.to_vec(), duration);
+    let kept = KeptRange::in_sequence(0, &to_kept_segments(&cuts, duration));
+    (cuts, kept, Some(settings.clone()), export_summary(input, settings, reframe_aspect))
+  });
+}
+
+/// What an export removed from its sources and kept, for `record_export_edits`: the cuts of a
+/// single-source export, the kept stretches in output order, the settings and their summary
+type ExportLayout = (Vec<Cut>, Vec<KeptRange>, Option<ExportSettings>, String);
+
+/// Write `<output>.edits.json` (see export_sidecar::ExportEdits) when the export_edits_sidecar
+/// setting is on. `layout` gets the total duration of the sources. The request is fingerprinted
+/// like the `.gebo.json` sidecar unless `fingerprint` is that sidecar's. Failures are only
+/// logged: provenance is a convenience, and the export itself has succeeded
+pub(crate) fn record_export_edits(
+  output: &str,
+  inputs: &[String],
+  fingerprint: Option<&str>,
+  layout: impl FnOnce(f64) -> ExportLayout,
+) {
+  if !longterm_storage::get_settings().map(|s| s.export_edits_sidecar).unwrap_or(false) {
+    return;
+  }
+  let written = (|| -> Result<()> {
+    let sources = inputs.iter().map(|i| export_sidecar::fingerprint_source(i)).collect::<Result<Vec<_>>>()?;
+    let probes = inputs.iter().map(|i| ffprobe(i)).collect::<Result<Vec<_>>>()?;
+    let source_duration = probes.iter().map(|p| p.duration).sum();
+    let (cuts, kept, settings, summary) = layout(source_duration);
+    let fingerprint = match fingerprint {
+      Some(fingerprint) => fingerprint.to_string(),
+      None => export_sidecar::fingerprint_request(&sources, &kept, &settings)?,
+    };
+    let output_duration = ffprobe(output).context("ffprobe of the export failed")?.duration;
+    export_sidecar::write_edits(
+      Path::new(output),
+      &fingerprint,
+      sources,
+      source_duration,
+      &cuts,
+      kept,
+      output_duration,
+      settings.as_ref(),
+      summary,
+      probes.iter().all(Probe::is_audio_only),
+    )
+  })();
+  if let Err(e) = written {
+    log::warn!("Failed to write export edits for {}: {:#}", output, e);
+  }
+}
+
+/// Uses filter_complex trim/concat (re-encodes with `settings`). Returns the video encoder
+/// used, or None when the input was copied as is
+fn encode_with_cuts(
+  input: &str,
+  output: &str,
+  ranges_to_cut: &[(f64, f64)],
+  settings: &ExportSettings,
+  reframe_aspect: Option<f64>,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<Option<EncoderUsed>> {
+  let probe = ffprobe(input).context("ffprobe failed")?;
+  let duration = probe.duration;
+  let tonemap = settings.tonemap_filter(&probe);
+  let audio_stream = settings.audio_stream.unwrap_or(probe.audio_stream);
+
+  // Audio files never touch their cover art, and their audio always goes through the
+  // pure-audio graph rather than a container copy
+  let audio_only = probe.is_audio_only();
+  let fade_ends = project_file::fades_timeline_ends();
+  // Copies take the whole file, so it has to be in the target container already
+  let can_copy = reframe_aspect.is_none()
+    && settings.allows_stream_copy(&probe)
+    && settings.container.is_format(&probe.container)
+    && !audio_only
+    && !fade_ends;
+
+  // If nothing to cut → copy as-is (fast).
+  if ranges_to_cut.is_empty() && can_copy {
+    fs::copy(input, output)
+      .with_context(|| format!("failed to copy {} -> {}", input, output))?;
+    return Ok(None);
+  }
+
+  // Normalize requested cuts.
+  let normalized = normalize_cuts(ranges_to_cut.to_vec(), duration);
+  if normalized.is_empty() && can_copy {
+    // All cuts invalid/degenerate → just copy.
+    fs::copy(input, output)
+      .with_context(|| format!("failed to copy {} -> {}", input, output))?;
+    return Ok(None);
+  }
+
+  // Convert to kept segments.
+  let kept = to_kept_segments(&normalized, duration);
+  if kept.is_empty() {
+    return Err(anyhow!("All content would be cut out (no kept segments)."));
+  }
+
+  // Tone-map before cropping so the crop works on the converted frame
+  let crop = reframe_aspect.map(|aspect| guides::reframe_crop_filter(&probe, aspect));
+  let mut video_filter = chain_filters(chain_filters(tonemap, crop), settings.scale_filter());
+  let mut audio_filter = settings.audio_filter()?;
+  if let Some(target) = settings.normalize_loudness.as_ref().filter(|_| probe.audio_rate > 0) {
+    // Measure what the export will contain: the kept audio after the settings' processing
+    let measure = chain_filters(audio_filter.clone(), Some(target.measure_filter()));
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-nostats", "-i", input])
+      .args(["-filter_complex", &build_filter_complex(&kept, false, true, audio_stream, None, measure.as_deref())])
+      .args(["-map", "[outa]", "-f", "null", "-"]);
+    let stats = run_loudness_pass(&mut cmd)?;
+    audio_filter = chain_filters(audio_filter, target.apply_filter(&stats, probe.audio_rate));
+  }
+  let output_duration: f64 = kept.iter().map(|(s, e)| e - s).sum();
+  if fade_ends {
+    (video_filter, audio_filter) = with_timeline_end_fades(video_filter, audio_filter, output_duration);
+  }
+  // The waveform video, when asked for, is a still picture looped as a second input
+  let keep_video = !audio_only && settings.container.has_video() && probe.width > 0;
+  let waveform = match audio_only && settings.waveform_video {
+    true => Some(write_waveform_still(input, audio_stream, Path::new(output))?),
+    false => None,
+  };
+  let has_video = keep_video || waveform.is_some();
+  // Screen recordings often have no audio; their exports have none either
+  let has_audio = probe.audio_rate > 0;
+  if !has_video && !has_audio {
+    return Err(anyhow!("{} has no audio, and {} keeps only audio", input, settings.container.extension()));
+  }
+  let output_path = Path::new(output);
+  let in_place = settings.writes_in_place(output_path, &[Path::new(input)]);
+  let target = if in_place { output_path.to_path_buf() } else { temp_output_path(output_path) };
+
+  // Encode with the requested codecs and container. A hardware H.264 encoder that fails is
+  // replaced by libx264, which needs its frames in system memory
+  let encoder = settings.hw_encoder().filter(|_| has_video);
+  let result = output_with_encoder_fallback("export", encoder, |encoder| {
+    let upload = encoder_upload_filter(encoder).map(str::to_string);
+    let video_filter = chain_filters(video_filter.clone(), upload.clone());
+    let mut filter_complex =
+      build_filter_complex(&kept, keep_video, has_audio, audio_stream, video_filter.as_deref(), audio_filter.as_deref());
+    if waveform.is_some() {
+      let still_filter = chain_filters(Some("format=yuv420p".to_string()), upload).unwrap_or_default();
+      filter_complex.push_str(&format!(";[1:v]{}[outv]", still_filter));
+    }
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1"]);
+    if has_video {
+      cmd.args(encoder_device_args(encoder));
+    }
+    cmd.args(["-i", input]);
+    if let Some(still) = &waveform {
+      cmd.args(["-loop", "1", "-framerate", "1", "-i"]).arg(still);
+    }
+    cmd.args(["-filter_complex", &filter_complex]);
+    if has_video {
+      match settings.hw_encoder() {
+        Some(_) => cmd.args(["-map", "[outv]"]).args(h264_encoder_args(encoder, EncodePreset::Export)),
+        None => cmd.args(["-map", "[outv]"]).args(settings.video_args()),
+      };
+    }
+    if has_audio {
+      cmd.args(["-map", "[outa]"]).args(settings.audio_args());
+    }
+    cmd.args(settings.muxer_args());
+    if waveform.is_some() {
+      cmd.arg("-shortest");
+    }
+    cmd.arg("-y").arg(&target);
+    output_with_progress(&mut cmd, output_duration, on_progress).with_context(|| "ffmpeg export failed to run")
+  });
+  if let Some(still) = &waveform {
+    let _ = fs::remove_file(still);
+  }
+  let (result, encoder_used) = result?;
+
+  if !result.status.success() {
+    // Cleanup partial output
+    let _ = fs::remove_file(&target);
+    return Err(anyhow!(
+      "ffmpeg export failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+
+  if !in_place {
+    // Atomic replace.
+    fs::rename(&target, output).with_context(|| "failed to move tmp output into place")?;
+  }
+  Ok(Some(encoder_used))
+}
+
+/// --- Loudness ------------------------------------------------------------------------
+
+/// Loudness an export is normalized to; the defaults suit streaming platforms
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoudnessTarget {
+  #[serde(default = "default_integrated_loudness")]
+  pub integrated: f64, // LUFS
+  #[serde(default = "default_true_peak")]
+  pub true_peak: f64, // dBTP
+  #[serde(default = "default_loudness_range")]
+  pub lra: f64, // LU
+}
+
+fn default_integrated_loudness() -> f64 {
+  -14.0
+}
+
+fn default_true_peak() -> f64 {
+  -1.0
+}
+
+fn default_loudness_range() -> f64 {
+  11.0
+}
+
+impl Default for LoudnessTarget {
+  fn default() -> Self {
+    Self { integrated: default_integrated_loudness(), true_peak: default_true_peak(), lra: default_loudness_range() }
+  }
+}
+
+/// Loudness of some audio as measured by ffmpeg's loudnorm filter
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoudnessStats {
+  pub integrated: f64, // LUFS; -inf for silence
+  pub true_peak: f64,  // dBTP
+  pub lra: f64,        // LU
+  pub threshold: f64,  // Gating threshold, LUFS
+  pub target_offset: f64,
+}
+
+impl LoudnessTarget {
+  /// Reject targets outside what loudnorm accepts
+  fn validate(&self) -> Result<()> {
+    if !(-70.0..=-5.0).contains(&self.integrated) {
+      return Err(anyhow!("target loudness {} LUFS is outside -70..-5", self.integrated));
+    }
+    if !(-9.0..=0.0).contains(&self.true_peak) {
+      return Err(anyhow!("target true peak {} dBTP is outside -9..0", self.true_peak));
+    }
+    if !(1.0..=20.0).contains(&self.lra) {
+      return Err(anyhow!("target loudness range {} LU is outside 1..20", self.lra));
+    }
+    Ok(())
+  }
+
+  /// First pass: measure only, printing the stats as JSON on stderr
+  fn measure_filter(&self) -> String {
+    format!("loudnorm=I={}:TP={}:LRA={}:print_format=json", self.integrated, self.true_peak, self.lra)
+  }
+
+  /// Second pass: normalize using `stats` from the first, then resample back to
+  /// `sample_rate` (loudnorm works at 192 kHz). None for silent audio, which has no loudness
+  fn apply_filter(&self, stats: &LoudnessStats, sample_rate: u32) -> Option<String> {
+    if !stats.integrated.is_finite() || !stats.true_peak.is_finite() {
+      return None;
+    }
+    Some(format!(
+      "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true,aresample={}",
+      self.integrated,
+      self.true_peak,
+      self.lra,
+      stats.integrated,
+      stats.true_peak,
+      stats.lra,
+      stats.threshold,
+      stats.target_offset,
+      if sample_rate > 0 { sample_rate } else { RENDER_SAMPLE_RATE }
+    ))
+  }
+}
+
+/// Stats from the JSON block loudnorm prints at the end of stderr. Its values are strings
+fn parse_loudnorm(stderr: &str) -> Result<LoudnessStats> {
+  let start = stderr.rfind('{').ok_or_else(|| anyhow!("loudnorm printed no measurements"))?;
+  let end = stderr[start..].find('}').map(|i| start + i + 1).ok_or_else(|| anyhow!("loudnorm output is cut short"))?;
+  let json: serde_json::Value = serde_json::from_str(&stderr[start..end]).context("loudnorm output is not JSON")?;
+  let field = |name: &str| -> Result<f64> {
+    json
+      .get(name)
+      .and_then(|v| v.as_str())
+      .and_then(|v| v.trim().parse::<f64>().ok())
+      .ok_or_else(|| anyhow!("loudnorm output has no {}", name))
+  };
+  Ok(LoudnessStats {
+    integrated: field("input_i")?,
+    true_peak: field("input_tp")?,
+    lra: field("input_lra")?,
+    threshold: field("input_thresh")?,
+    target_offset: field("target_offset")?,
+  })
+}
+
+/// Run a measuring ffmpeg command (its output ends in a loudnorm `measure_filter`)
+fn run_loudness_pass(cmd: &mut Command) -> Result<LoudnessStats> {
+  let output = jobs::output_cancellable(cmd, None).with_context(|| "ffmpeg loudness measurement failed to run")?;
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  if !output.status.success() {
+    return Err(anyhow!("ffmpeg loudness measurement failed (status {:?}): {}", output.status.code(), stderr.trim()));
+  }
+  parse_loudnorm(&stderr)
+}
+
+/// Integrated loudness, true peak and range of audio stream `audio_stream` of `path` (default:
+/// the one the file marks default). None when the file has no audio
+pub fn measure_loudness(path: &str, audio_stream: Option<usize>) -> Result<Option<LoudnessStats>> {
+  let probe = ffprobe(path).context("ffprobe failed")?;
+  if probe.audio_rate == 0 {
+    return Ok(None);
+  }
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-hide_banner", "-nostats", "-i", path, "-map", &format!("0:a:{}", audio_stream.unwrap_or(probe.audio_stream))])
+    .args(["-af", &LoudnessTarget::default().measure_filter(), "-f", "null", "-"]);
+  run_loudness_pass(&mut cmd).map(Some)
+}
+
+/// --- Project render ------------------------------------------------------------------
+
+/// Frame size of timeline renders when the video track has nothing to take it from
+const DEFAULT_RENDER_SIZE: (u32, u32) = (1920, 1080);
+/// Sample rate every timeline audio stream is resampled to before mixing
+const RENDER_SAMPLE_RATE: u32 = 48000;
+
+/// Probe of `clip`, from the project file when it has one
+fn clip_probe(clip: &project_file::Clip) -> Result<Probe> {
+  match &clip.latest_probe {
+    Some(probe) => Ok(probe.clone()),
+    None => ffprobe(&clip.path.to_string_lossy()).with_context(|| format!("ffprobe of {:?} failed", clip.path)),
+  }
+}
+
+/// Displayed size of `probe`'s video (rotation and pixel aspect applied), rounded to even numbers
+fn display_size(probe: &Probe) -> (u32, u32) {
+  let width = (f64::from(probe.width) * if probe.sar > 0.0 { probe.sar } else { 1.0 }).round() as u32;
+  let (width, height) = match probe.rotation {
+    90 | 270 => (probe.height, width),
+    _ => (width, probe.height),
+  };
+  (width / 2 * 2, height / 2 * 2)
+}
+
+/// Inputs of a timeline render, one per clip, in `-i` order
+struct RenderInputs<'a> {
+  clips: Vec<&'a project_file::Clip>,
+}
+
+impl<'a> RenderInputs<'a> {
+  /// Input index of `clip_id`, adding the clip on first use
+  fn index(&mut self, project: &'a project_file::ProjectFile, clip_id: &str) -> Result<usize> {
+    if let Some(i) = self.clips.iter().position(|c| c.id == clip_id) {
+      return Ok(i);
+    }
+    let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+    self.clips.push(clip);
+    Ok(self.clips.len() - 1)
+  }
+}
+
+/// Video pieces of `track` back to back, gaps filled with black, concatenated to [catv]
+fn timeline_video_graph<'a>(
+  project: &'a project_file::ProjectFile,
+  track: &'a project_file::Track,
+  inputs: &mut RenderInputs<'a>,
+  settings: &ExportSettings,
+  (width, height): (u32, u32),
+  fps: f64,
+  duration: f64,
+) -> Result<Vec<String>> {
+  let mut graph = Vec::new();
+  let mut labels = Vec::new();
+  let mut cursor = 0.0;
+  let black = |graph: &mut Vec<String>, labels: &mut Vec<String>, length: f64| {
+    let label = format!("[gap{}]", labels.len());
+    graph.push(format!("color=c=black:s={}x{}:r={}:d={},format=yuv420p,setsar=1{}", width, height, fps, length, label));
+    labels.push(label);
+  };
+  for (segment, (start, end)) in track.segments.iter().zip(track.timeline_spans()) {
+    if start - cursor > SNAP_EPSILON {
+      black(&mut graph, &mut labels, start - cursor);
+    }
+    let length = end - start;
+    let clip = project.clips_map.get(&segment.clip_id).ok_or_else(|| anyhow!("clip not found: {}", segment.clip_id))?;
+    let probe = clip_probe(clip)?;
+    if clip.r#type != project_file::ClipType::Image && probe.is_audio_only() {
+      // Nothing to show; the audio graph still plays it
+      black(&mut graph, &mut labels, length);
+      cursor = end;
+      continue;
+    }
+    let index = inputs.index(project, &segment.clip_id)?;
+    let mut video = vec![
+      format!("trim=start={}:end={}", segment.start, segment.end),
+      format!("setpts=(PTS-STARTPTS)/{}", segment.speed),
+    ];
+    video.extend(settings.tonemap_filter(&probe));
+    for effect in &segment.effects {
+      video.push(crate::bake::effect_filter(effect)?);
+    }
+    video.extend(segment.video_fade_filters(length));
+    // Every piece is letterboxed to the same frame so concat accepts them
+    video.push(format!("scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2", w = width, h = height));
+    video.push("setsar=1".to_string());
+    video.push(format!("fps={}", fps));
+    video.push("tpad=stop_mode=clone:stop=-1".to_string());
+    video.push(format!("trim=duration={}", length));
+    video.push("format=yuv420p".to_string());
+    let label = format!("[seg{}]", labels.len());
+    graph.push(format!("[{}:v]{}{}", index, video.join(","), label));
+    labels.push(label);
+    cursor = end;
+  }
+  if duration - cursor > SNAP_EPSILON {
+    black(&mut graph, &mut labels, duration - cursor);
+  }
+  graph.push(format!("{}concat=n={}:v=1:a=0[catv]", labels.join(""), labels.len()));
+  Ok(graph)
+}
+
+/// Audio of every segment of `tracks` placed at its timeline position, mixed to [mixa]
+fn timeline_audio_graph<'a>(
+  project: &'a project_file::ProjectFile,
+  tracks: &[&'a project_file::Track],
+  inputs: &mut RenderInputs<'a>,
+  duration: f64,
+) -> Result<Vec<String>> {
+  let mut graph = Vec::new();
+  let mut labels = Vec::new();
+  for track in tracks {
+    for (segment, (start, end)) in track.segments.iter().zip(track.timeline_spans()) {
+      let index = inputs.index(project, &segment.clip_id)?;
+      let clip = inputs.clips[index];
+      if clip.r#type == project_file::ClipType::Image || clip_probe(clip)?.audio_rate == 0 {
+        continue;
+      }
+      let length = end - start;
+      let mut audio = vec![
+        format!("atrim=start={}:end={}", segment.start, segment.end),
+        "asetpts=PTS-STARTPTS".to_string(),
+      ];
+      audio.extend(crate::bake::atempo_chain(segment.speed));
+      audio.extend(audio_effects::audio_chain(segment.gain, &track.track_effects, Some(track.volume))?);
+      audio.extend(segment.audio_fade_filters(length));
+      audio.extend(pan_filter(track.pan));
+      audio.push(format!("aresample={},aformat=sample_fmts=fltp:channel_layouts=stereo", RENDER_SAMPLE_RATE));
+      audio.push(format!("atrim=duration={}", length));
+      audio.push(format!("adelay={}:all=1", (start * 1000.0).round() as u64));
+      let label = format!("[a{}]", labels.len());
+      graph.push(format!("[{}:a:{}]{}{}", index, clip.audio_stream(), audio.join(","), label));
+      labels.push(label);
+    }
+  }
+  // Silence under everything, so gaps and a timeline without audio still fill `duration`
+  graph.push(format!("anullsrc=r={}:cl=stereo,atrim=duration={}[silence]", RENDER_SAMPLE_RATE, duration));
+  graph.push(format!(
+    "[silence]{}amix=inputs={}:duration=first:normalize=0[mixa]",
+    labels.join(""),
+    labels.len() + 1
+  ));
+  Ok(graph)
+}
+
+/// Render the timeline of `project`, with its accepted cuts taken out (see
+/// ProjectFile::with_cuts_applied), to `output`: the first enabled video track, with black
+/// where it has gaps or audio-only clips, and the audio of every enabled, unmuted track (the video track's own
+/// included) mixed with each track's volume, effects and pan. Segments keep their speed, gain,
+/// fades and effects. Audio-only projects and containers render the mix alone. Video renders at
+/// ProjectFile::export_fps. Returns the video encoder used; a failing hardware encoder in
+/// `settings.encoder` is replaced by libx264
+pub fn render_project(
+  project: &project_file::ProjectFile,
+  output: &str,
+  settings: &ExportSettings,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<Option<EncoderUsed>> {
+  settings.validate(output)?;
+  if !ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+  let project = &project.with_cuts_applied();
+  let duration = project.timeline_duration();
   if duration <= 0.0 {
-    return Err(anyhow!("Invalid media duration"));
+    return Err(anyhow!("the timeline is empty"));
+  }
+
+  let tracks = project.ordered_tracks();
+  let video_track = tracks
+    .iter()
+    .copied()
+    .find(|t| t.enabled && t.r#type == project_file::TrackType::Video && !t.segments.is_empty())
+    .filter(|_| !project.is_audio_only() && settings.container.has_video());
+  let audio_tracks: Vec<&project_file::Track> = tracks
+    .iter()
+    .copied()
+    .filter(|t| t.enabled && !t.muted)
+    .filter(|t| t.r#type == project_file::TrackType::Audio || video_track.is_some_and(|v| v.id == t.id))
+    .collect();
+
+  let fps = project.export_fps(settings);
+  let mut inputs = RenderInputs { clips: Vec::new() };
+  let mut graph = Vec::new();
+  if let Some(track) = video_track {
+    let size = track
+      .segments
+      .iter()
+      .filter_map(|s| project.clips_map.get(&s.clip_id))
+      .find_map(|c| clip_probe(c).ok().filter(|p| p.width > 0 && p.height > 0))
+      .map(|p| display_size(&p))
+      .unwrap_or(DEFAULT_RENDER_SIZE);
+    graph.extend(timeline_video_graph(project, track, &mut inputs, settings, size, fps, duration)?);
+  }
+  let audio_graph = timeline_audio_graph(project, &audio_tracks, &mut inputs, duration)?;
+  graph.extend(audio_graph.iter().cloned());
+
+  let mut video_filter = settings.scale_filter();
+  let mut audio_filter = settings.audio_filter()?;
+  // More than the silence bed means some segment has audio to normalize
+  if let Some(target) = settings.normalize_loudness.as_ref().filter(|_| audio_graph.len() > 2) {
+    let measure = chain_filters(audio_filter.clone(), Some(target.measure_filter())).unwrap_or_default();
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-nostats"]);
+    for clip in &inputs.clips {
+      if clip.r#type == project_file::ClipType::Image {
+        cmd.args(["-loop", "1", "-framerate", &fps.to_string()]);
+      }
+      cmd.arg("-i").arg(&clip.path);
+    }
+    let measure_graph = format!("{};[mixa]{}[outa]", audio_graph.join(";"), measure);
+    cmd.args(["-filter_complex", &measure_graph, "-map", "[outa]", "-f", "null", "-"]);
+    let stats = run_loudness_pass(&mut cmd)?;
+    audio_filter = chain_filters(audio_filter, target.apply_filter(&stats, RENDER_SAMPLE_RATE));
+  }
+  if project.settings.fade_timeline_ends {
+    (video_filter, audio_filter) = with_timeline_end_fades(video_filter, audio_filter, duration);
+  }
+  graph.push(format!("[mixa]{}[outa]", audio_filter.unwrap_or_else(|| "anull".to_string())));
+
+  let output_path = Path::new(output);
+  let sources: Vec<&Path> = inputs.clips.iter().map(|clip| clip.path.as_path()).collect();
+  let in_place = settings.writes_in_place(output_path, &sources);
+  let target = if in_place { output_path.to_path_buf() } else { temp_output_path(output_path) };
+  let encoder = settings.hw_encoder().filter(|_| video_track.is_some());
+  let result = output_with_encoder_fallback("timeline render", encoder, |encoder| {
+    let mut graph = graph.clone();
+    if video_track.is_some() {
+      let filter = chain_filters(video_filter.clone(), encoder_upload_filter(encoder).map(str::to_string));
+      graph.push(format!("[catv]{}[outv]", filter.unwrap_or_else(|| "null".to_string())));
+    }
+
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1"]);
+    if video_track.is_some() {
+      cmd.args(encoder_device_args(encoder));
+    }
+    for clip in &inputs.clips {
+      if clip.r#type == project_file::ClipType::Image {
+        cmd.args(["-loop", "1", "-framerate", &fps.to_string()]);
+      }
+      cmd.arg("-i").arg(&clip.path);
+    }
+    cmd.args(["-filter_complex", &graph.join(";")]);
+    if video_track.is_some() {
+      match settings.hw_encoder() {
+        Some(_) => cmd.args(["-map", "[outv]"]).args(h264_encoder_args(encoder, EncodePreset::Export)),
+        None => cmd.args(["-map", "[outv]"]).args(settings.video_args()),
+      };
+    }
+    cmd.args(["-map", "[outa]"])
+      .args(settings.audio_args())
+      .args(["-t", &duration.to_string()])
+      .args(settings.muxer_args())
+      .arg("-y")
+      .arg(&target);
+    output_with_progress(&mut cmd, duration, on_progress).with_context(|| "ffmpeg timeline render failed to run")
+  });
+  let (result, encoder_used) = result?;
+
+  if !result.status.success() {
+    let _ = fs::remove_file(&target);
+    return Err(anyhow!(
+      "ffmpeg timeline render failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+  if !in_place {
+    fs::rename(&target, output).with_context(|| "failed to move tmp output into place")?;
+  }
+  if let Some(track) = video_track.or_else(|| audio_tracks.iter().copied().find(|t| !t.segments.is_empty())) {
+    let (sources, kept) = timeline_kept_ranges(project, track);
+    record_export_edits(output, &sources, None, |_| {
+      (Vec::new(), kept, Some(settings.clone()), format!("timeline render / {}", settings.describe()))
+    });
+  }
+  Ok(video_track.map(|_| encoder_used))
+}
+
+/// The distinct clip files `track` plays, and each of its segments as a range of one of them
+/// placed at its timeline start
+fn timeline_kept_ranges(project: &project_file::ProjectFile, track: &project_file::Track) -> (Vec<String>, Vec<KeptRange>) {
+  let mut sources: Vec<String> = Vec::new();
+  let mut kept = Vec::new();
+  for (segment, (output_start, _)) in track.segments.iter().zip(track.timeline_spans()) {
+    let Some(clip) = project.clips_map.get(&segment.clip_id) else { continue };
+    let path = clip.path.to_string_lossy().to_string();
+    let source = match sources.iter().position(|s| *s == path) {
+      Some(source) => source,
+      None => {
+        sources.push(path);
+        sources.len() - 1
+      }
+    };
+    kept.push(KeptRange { start: segment.start, end: segment.end, output_start, source });
+  }
+  (sources, kept)
+}
+
+/// --- Lossless cut export -------------------------------------------------------------
+
+/// Boundaries closer than this are the same point
+const SNAP_EPSILON: f64 = 0.001;
+
+/// Where a kept segment of a lossless export really starts and ends in the source
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LosslessSegment {
+  pub requested: Cut,
+  pub snapped: Cut,    // Source range that ends up in the output
+  pub reencoded: bool, // Snapping collapsed it to nothing, so it was re-encoded at the requested times
+}
+
+/// Result of `export_with_cuts_lossless`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LosslessExport {
+  pub segments: Vec<LosslessSegment>,
+  pub max_drift: f64, // Largest distance between a requested and an actual boundary, seconds
+  pub output_duration: f64,
+}
+
+/// Timestamps of every keyframe in the first video stream of `input`, ascending
+pub fn keyframe_times(input: &str) -> Result<Vec<f64>> {
+  let output = Command::new("ffprobe")
+    .args(["-v", "error", "-select_streams", "v:0", "-skip_frame", "nokey", "-show_frames"])
+    .args(["-show_entries", "frame=pts_time", "-of", "csv=p=0", input])
+    .output()
+    .with_context(|| "failed to run ffprobe")?;
+  if !output.status.success() {
+    return Err(anyhow!("ffprobe keyframe scan failed: {}", String::from_utf8_lossy(&output.stderr)));
+  }
+  let mut times: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+    .lines()
+    .filter_map(|line| line.trim().trim_end_matches(',').parse::<f64>().ok())
+    .collect();
+  times.sort_by(f64::total_cmp);
+  Ok(times)
+}
+
+/// Move both ends of each `kept` segment to the nearest keyframe. Audio-only sources (no
+/// `keyframes`) can be cut anywhere and keep their times. A segment snapping to nothing keeps
+/// its requested times and is marked for re-encoding; none overlap the one before. A copied
+/// segment always starts on a keyframe, as stream copy can't start anywhere else, so one that
+/// would start inside the segment before moves on to the next keyframe
+fn snap_to_keyframes(kept: &[Cut], keyframes: &[f64], duration: f64) -> Vec<LosslessSegment> {
+  let nearest = |t: f64| {
+    keyframes
+      .iter()
+      .copied()
+      .min_by(|a, b| (a - t).abs().total_cmp(&(b - t).abs()))
+      .unwrap_or(t)
+  };
+  let first_after = |t: f64| match keyframes.is_empty() {
+    true => t,
+    false => keyframes.iter().copied().find(|k| *k >= t - SNAP_EPSILON).unwrap_or(duration),
+  };
+  let mut previous_end = 0.0_f64;
+  let mut segments = Vec::new();
+  for &(start, end) in kept {
+    let snapped_start = match nearest(start) {
+      _ if start <= SNAP_EPSILON => 0.0,
+      snapped if snapped < previous_end - SNAP_EPSILON => first_after(previous_end),
+      snapped => snapped,
+    };
+    let snapped_end = if end >= duration - SNAP_EPSILON { duration } else { nearest(end) };
+    let segment = if snapped_end - snapped_start > SNAP_EPSILON {
+      LosslessSegment { requested: (start, end), snapped: (snapped_start, snapped_end), reencoded: false }
+    } else {
+      let start = start.max(previous_end);
+      if end - start <= SNAP_EPSILON {
+        continue; // Already covered by the previous segment's snapped end
+      }
+      LosslessSegment { requested: (start, end), snapped: (start, end), reencoded: true }
+    };
+    previous_end = segment.snapped.1;
+    segments.push(segment);
+  }
+  segments
+}
+
+/// Encoder writing `codec` (an ffprobe codec name), so a re-encoded piece can be joined to
+/// stream-copied ones
+fn matching_encoder(codec: &str) -> Option<&'static str> {
+  match codec {
+    "h264" => Some("libx264"),
+    "hevc" => Some("libx265"),
+    "vp9" => Some("libvpx-vp9"),
+    "av1" => Some("libsvtav1"),
+    "prores" => Some("prores_ks"),
+    "aac" => Some("aac"),
+    "opus" => Some("libopus"),
+    "flac" => Some("flac"),
+    "mp3" => Some("libmp3lame"),
+    _ => None,
+  }
+}
+
+/// Profile option of `encoder` for the profile ffprobe names `profile`, when it has one
+fn encoder_profile(encoder: &str, profile: &str) -> Option<&'static str> {
+  match (encoder, profile) {
+    ("libx264", "Baseline" | "Constrained Baseline") => Some("baseline"),
+    ("libx264", "Main") => Some("main"),
+    ("libx264", "High") => Some("high"),
+    ("libx264", "High 10") => Some("high10"),
+    ("libx264", "High 4:2:2") => Some("high422"),
+    ("libx264", "High 4:4:4 Predictive") => Some("high444"),
+    ("libx265", "Main") => Some("main"),
+    ("libx265", "Main 10") => Some("main10"),
+    ("libx265", "Main Still Picture") => Some("mainstillpicture"),
+    ("prores_ks", "Proxy") => Some("proxy"),
+    ("prores_ks", "LT") => Some("lt"),
+    ("prores_ks", "Standard") => Some("standard"),
+    ("prores_ks", "HQ") => Some("hq"),
+    ("prores_ks", "4444") => Some("4444"),
+    ("prores_ks", "4444 XQ") => Some("4444xq"),
+    _ => None,
+  }
+}
+
+/// Encoder arguments re-encoding ffprobe stream `stream` (its JSON) so the result joins the
+/// stream-copied parts of a `container` file (an extension): same codec, profile, level and
+/// pixel format for video, with the same frame rate and time base; same rate and channels for
+/// audio
+fn matching_stream_args(stream: &serde_json::Value, container: &str) -> Result<Vec<String>> {
+  let kind = stream["codec_type"].as_str().unwrap_or_default();
+  let codec = stream["codec_name"].as_str().unwrap_or("unknown");
+  let encoder =
+    matching_encoder(codec).ok_or_else(|| anyhow!("can't re-encode {} {} to match the copied segments", codec, kind))?;
+  let mut args: Vec<String> = Vec::new();
+  let mut push = |option: &str, value: String| args.extend([option.to_string(), value]);
+  if kind == "audio" {
+    push("-c:a", encoder.to_string());
+    if let Some(rate) = json_number::<u32>(&stream["sample_rate"]) {
+      push("-ar", rate.to_string());
+    }
+    if let Some(channels) = stream["channels"].as_u64() {
+      push("-ac", channels.to_string());
+    }
+    return Ok(args);
+  }
+
+  push("-c:v", encoder.to_string());
+  if let Some(pix_fmt) = stream["pix_fmt"].as_str() {
+    push("-pix_fmt", pix_fmt.to_string());
+  }
+  if let Some(profile) = stream["profile"].as_str().and_then(|p| encoder_profile(encoder, p)) {
+    push("-profile:v", profile.to_string());
+  }
+  // ffprobe gives H.264 levels times 10 and HEVC ones times 30, e.g. 41 and 123 for 4.1
+  match (codec, stream["level"].as_i64().filter(|l| *l > 0)) {
+    ("h264", Some(level)) => push("-level:v", format!("{:.1}", level as f64 / 10.0)),
+    ("hevc", Some(level)) => push("-x265-params", format!("level-idc={:.1}", level as f64 / 30.0)),
+    _ => {}
+  }
+  if let Some(rate) = stream["r_frame_rate"].as_str().filter(|r| !r.starts_with('0')) {
+    push("-r", rate.to_string());
+  }
+  let timescale = stream["time_base"].as_str().and_then(|t| t.strip_prefix("1/")).and_then(|t| t.parse::<u32>().ok());
+  if let (Some(timescale), "mp4" | "mov" | "m4v") = (timescale, container.to_lowercase().as_str()) {
+    push("-video_track_timescale", timescale.to_string());
+  }
+  Ok(args)
+}
+
+/// Write `segment` of `input` to `part` with its `audio_stream`th audio stream, stream-copied
+/// or (when marked) re-encoded to match the source's streams as `full` describes them
+fn write_lossless_part(
+  input: &str,
+  full: &ProbeFull,
+  audio_stream: usize,
+  segment: &LosslessSegment,
+  part: &Path,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<()> {
+  let (start, end) = segment.snapped;
+  let audio_map = format!("0:a:{}?", audio_stream);
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1"]);
+  if segment.reencoded {
+    cmd.args(["-ss", &start.to_string(), "-i", input, "-t", &(end - start).to_string()]);
+    cmd.args(["-map", "0:v:0?", "-map", &audio_map]);
+    let container = part.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let streams = full.json["streams"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let video = streams.iter().find(|s| s["codec_type"] == "video" && s["disposition"]["attached_pic"].as_u64() != Some(1));
+    let audio = streams.iter().filter(|s| s["codec_type"] == "audio").nth(audio_stream);
+    for stream in video.into_iter().chain(audio) {
+      cmd.args(matching_stream_args(stream, container)?);
+    }
+  } else {
+    // Input seeking with stream copy starts at the keyframe at or before -ss; nudge past the
+    // keyframe so rounding in its printed timestamp can't land on the one before
+    let seek = if start > 0.0 { start + SNAP_EPSILON } else { 0.0 };
+    cmd.args(["-ss", &seek.to_string(), "-i", input, "-t", &(end - start).to_string()]);
+    cmd.args(["-map", "0:v:0?", "-map", &audio_map, "-c", "copy"]);
+  }
+  cmd.args(["-avoid_negative_ts", "make_zero", "-y"]).arg(part);
+
+  let result = output_with_progress(&mut cmd, end - start, on_progress)
+    .with_context(|| "ffmpeg segment export failed to run")?;
+  if !result.status.success() {
+    return Err(anyhow!(
+      "ffmpeg failed on segment {:.3}-{:.3} (status {:?}): {}",
+      start,
+      end,
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+  Ok(())
+}
+
+/// Join `parts` into `target` with the concat demuxer, without re-encoding
+fn concat_parts(parts: &[PathBuf], list: &Path, target: &Path) -> Result<()> {
+  let entries: String = parts
+    .iter()
+    .map(|p| format!("file '{}'\n", p.to_string_lossy().replace('\'', "'\\''")))
+    .collect();
+  fs::write(list, entries).with_context(|| format!("failed to write {:?}", list))?;
+
+  let faststart = matches!(
+    target.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(),
+    Some("mp4" | "mov" | "m4a")
+  );
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-f", "concat", "-safe", "0", "-i"]).arg(list);
+  cmd.args(["-map", "0", "-c", "copy"]);
+  if faststart {
+    cmd.args(["-movflags", "+faststart"]);
+  }
+  cmd.arg("-y").arg(target);
+  let result = jobs::output_cancellable(&mut cmd, None).with_context(|| "ffmpeg concat failed to run")?;
+  if !result.status.success() {
+    return Err(anyhow!(
+      "ffmpeg concat failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+  Ok(())
+}
+
+/// Export `input` with `ranges_to_cut` removed without re-encoding: each kept segment is
+/// snapped to the nearest keyframes, stream-copied and joined with the concat demuxer, keeping
+/// the source's container format (taken from `output`'s extension). Segments that snapping
+/// would collapse are re-encoded at their requested times instead. Returns where every
+/// segment actually landed so the UI can show how far the cuts moved. `audio_stream` picks the
+/// audio stream to keep (default: the one the file marks default).
+/// `on_progress` receives the finished fraction (0..1).
+pub fn export_with_cuts_lossless(
+  input: &str,
+  output: &str,
+  ranges_to_cut: &[Cut],
+  audio_stream: Option<usize>,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<LosslessExport> {
+  if !ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+  let full = ffprobe_full(input).context("ffprobe failed")?;
+  let probe = full.probe()?;
+  let audio_stream = audio_stream.unwrap_or(probe.audio_stream);
+  let duration = probe.duration;
+  let cuts = normalize_cuts(ranges_to_cut.to_vec(), duration);
+  let kept = to_kept_segments(&cuts, duration);
+  if kept.is_empty() {
+    return Err(anyhow!("All content would be cut out (no kept segments)."));
+  }
+
+  let keyframes = match probe.width > 0 && probe.height > 0 {
+    true => keyframe_times(input)?,
+    false => Vec::new(),
+  };
+  if probe.width > 0 && keyframes.is_empty() {
+    return Err(anyhow!("no keyframes found in {}", input));
+  }
+  let segments = snap_to_keyframes(&kept, &keyframes, duration);
+  let output_duration: f64 = segments.iter().map(|s| s.snapped.1 - s.snapped.0).sum();
+  let max_drift = segments
+    .iter()
+    .flat_map(|s| [(s.snapped.0 - s.requested.0).abs(), (s.snapped.1 - s.requested.1).abs()])
+    .fold(0.0, f64::max);
+
+  let output_path = Path::new(output);
+  let target = temp_output_path(output_path);
+  let ext = output_path.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+  let parts: Vec<PathBuf> = (0..segments.len())
+    .map(|i| target.with_extension(format!("part{}.{}", i, ext)))
+    .collect();
+  let list = target.with_extension("concat.txt");
+
+  let result = (|| -> Result<()> {
+    let mut done = 0.0;
+    for (segment, part) in segments.iter().zip(&parts) {
+      let length = segment.snapped.1 - segment.snapped.0;
+      write_lossless_part(input, &full, audio_stream, segment, part, &mut |fraction| {
+        on_progress(0.95 * (done + fraction * length) / output_duration.max(f64::EPSILON))
+      })?;
+      done += length;
+    }
+    concat_parts(&parts, &list, &target)
+  })();
+  for part in &parts {
+    let _ = fs::remove_file(part);
+  }
+  let _ = fs::remove_file(&list);
+  if let Err(e) = result {
+    let _ = fs::remove_file(&target);
+    return Err(e);
+  }
+
+  fs::rename(&target, output).with_context(|| "failed to move tmp output into place")?;
+  // What was actually copied, which is the snapped ranges rather than the requested ones
+  let snapped: Vec<Cut> = segments.iter().map(|s| s.snapped).collect();
+  record_export_edits(output, &[input.to_string()], None, |_| {
+    let cuts = intervals::complement(&snapped, duration);
+    (cuts, KeptRange::in_sequence(0, &snapped), None, "lossless stream copy".to_string())
+  });
+  on_progress(1.0);
+  Ok(LosslessExport { segments, max_drift, output_duration })
+}
+
+/// --- Animated export -----------------------------------------------------------------
+
+/// Format of `export_animated`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimatedFormat {
+  Gif,
+  Webp,
+}
+
+impl AnimatedFormat {
+  pub fn extension(self) -> &'static str {
+    match self {
+      AnimatedFormat::Gif => "gif",
+      AnimatedFormat::Webp => "webp",
+    }
+  }
+}
+
+/// Defaults for `export_animated` when the caller doesn't pick
+pub const DEFAULT_ANIMATED_WIDTH: u32 = 480;
+pub const DEFAULT_ANIMATED_FPS: f64 = 15.0;
+/// GIF frame delays are whole hundredths of a second, so higher rates play back wrong
+const MAX_ANIMATED_FPS: f64 = 50.0;
+
+/// Result of `export_animated`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnimatedExport {
+  pub path: String,
+  pub format: AnimatedFormat,
+  pub duration: f64,
+  pub size_bytes: u64, // GIFs grow quickly; the UI warns about large ones
+}
+
+/// Check an `export_animated` request without encoding anything. Returns the probed duration
+/// of `input`
+pub fn validate_animated(
+  input: &str,
+  output: &str,
+  start: f64,
+  end: f64,
+  width: u32,
+  fps: f64,
+  format: AnimatedFormat,
+) -> Result<f64> {
+  let extension = Path::new(output)
+    .extension()
+    .and_then(|e| e.to_str())
+    .map(|e| e.to_lowercase())
+    .unwrap_or_default();
+  if extension != format.extension() {
+    return Err(anyhow!("output extension '.{}' doesn't match the {} format", extension, format.extension()));
+  }
+  if !(16..=3840).contains(&width) {
+    return Err(anyhow!("width {} is outside 16..3840", width));
+  }
+  if !(fps > 0.0 && fps <= MAX_ANIMATED_FPS) {
+    return Err(anyhow!("frame rate {} is outside 0..{}", fps, MAX_ANIMATED_FPS));
+  }
+  if !(start >= 0.0 && end > start) {
+    return Err(anyhow!("invalid range {:.3}..{:.3}: end must be after start", start, end));
+  }
+  let probe = ffprobe(input).with_context(|| format!("ffprobe failed for {}", input))?;
+  if probe.width == 0 || probe.height == 0 {
+    return Err(anyhow!("{} has no video", input));
+  }
+  // Probed durations are rounded to the millisecond
+  if end > probe.duration + 0.001 {
+    return Err(anyhow!("range {:.3}..{:.3} runs past the end of {} ({:.3}s)", start, end, input, probe.duration));
+  }
+  Ok(probe.duration)
+}
+
+/// Encode `start..end` of `input` as a looping GIF or animated WebP, `width` pixels wide (never
+/// upscaled) at `fps`. GIFs take two passes: one builds a palette from the range, the second
+/// maps the frames onto it with dithering, so gradients don't band the way the default
+/// palette makes them
+#[allow(clippy::too_many_arguments)]
+pub fn export_animated(
+  input: &str,
+  output: &str,
+  start: f64,
+  end: f64,
+  width: u32,
+  fps: f64,
+  format: AnimatedFormat,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<AnimatedExport> {
+  if !ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+  validate_animated(input, output, start, end, width, fps, format)?;
+
+  let duration = end - start;
+  let frames = format!("fps={},scale='min({},iw)':-2:flags=lanczos{}", fps, width, preview_color(input));
+  let range = ["-ss".to_string(), start.to_string(), "-t".to_string(), duration.to_string()];
+  let target = temp_output_path(Path::new(output));
+  let palette = Path::new(output).with_extension("palette.png");
+
+  let result = match format {
+    AnimatedFormat::Gif => {
+      let mut cmd = Command::new("ffmpeg");
+      cmd.args(["-v", "error"])
+        .args(&range)
+        .args(["-i", input, "-vf", &format!("{},palettegen=stats_mode=diff", frames), "-y"])
+        .arg(&palette);
+      // palettegen only writes at the end, so this pass has no progress to report
+      let pass = jobs::output_cancellable(&mut cmd, None).with_context(|| "ffmpeg palette pass failed to run")?;
+      if !pass.status.success() {
+        let _ = fs::remove_file(&palette);
+        return Err(anyhow!(
+          "ffmpeg palette pass failed (status {:?}): {}",
+          pass.status.code(),
+          String::from_utf8_lossy(&pass.stderr)
+        ));
+      }
+      on_progress(0.1);
+
+      let filter = format!("[0:v]{}[x];[x][1:v]paletteuse=dither=sierra2_4a:diff_mode=rectangle", frames);
+      let mut cmd = Command::new("ffmpeg");
+      cmd.args(["-v", "error", "-progress", "pipe:1", "-nostats"])
+        .args(&range)
+        .args(["-i", input, "-i"])
+        .arg(&palette)
+        .args(["-filter_complex", &filter, "-an", "-loop", "0", "-f", "gif", "-y"])
+        .arg(&target);
+      let result = output_with_progress(&mut cmd, duration, &mut |fraction| on_progress(0.1 + 0.9 * fraction));
+      let _ = fs::remove_file(&palette);
+      result?
+    }
+    AnimatedFormat::Webp => {
+      let mut cmd = Command::new("ffmpeg");
+      cmd.args(["-v", "error", "-progress", "pipe:1", "-nostats"])
+        .args(&range)
+        .args(["-i", input, "-vf", &frames, "-an", "-c:v", "libwebp"])
+        .args(["-loop", "0", "-lossless", "0", "-q:v", "75", "-f", "webp", "-y"])
+        .arg(&target);
+      output_with_progress(&mut cmd, duration, on_progress)?
+    }
+  };
+
+  if !result.status.success() {
+    let _ = fs::remove_file(&target);
+    return Err(anyhow!(
+      "ffmpeg {} export failed (status {:?}): {}",
+      format.extension(),
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+  fs::rename(&target, output).with_context(|| "failed to move tmp output into place")?;
+  let size_bytes = fs::metadata(output).with_context(|| format!("failed to stat {}", output))?.len();
+  Ok(AnimatedExport { path: output.to_string(), format, duration, size_bytes })
+}
+
+/// --- Preview Proxy -------------------------------------------------------------------
+
+/// Proxy width when none is asked for
+pub const PROXY_WIDTH: u32 = 960;
+
+/// Directory proxies are cached in: `proxies` in the app's cache dir (see work_dirs.rs)
+fn proxy_dir() -> Result<PathBuf> {
+  let dir = work_dirs::path(WorkDir::Cache)?.join("proxies");
+  fs::create_dir_all(&dir).with_context(|| format!("Failed to create proxy cache at {:?}", dir))?;
+  Ok(dir)
+}
+
+/// Where `make_preview_proxy` writes the proxy of `input` at width `max_w` (default
+/// `PROXY_WIDTH`) with audio stream `audio_stream`: `<stem>_<key>_<mtime>.mp4`. The key hashes
+/// the absolute source path, the width and the audio stream when one is picked, so same-named
+/// sources don't share a proxy; the modification time gives an edited source a new one, and
+/// lets `prune_replaced_proxies` find the old one
+pub fn proxy_path(input: &str, max_w: Option<u32>, audio_stream: Option<usize>) -> Result<PathBuf> {
+  let source = fs::canonicalize(input).with_context(|| format!("failed to read {:?}", input))?;
+  let modified = fs::metadata(&source)?
+    .modified()
+    .ok()
+    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  let mut hasher = Sha256::new();
+  hasher.update(source.to_string_lossy().as_bytes());
+  hasher.update(max_w.unwrap_or(PROXY_WIDTH).to_le_bytes());
+  if let Some(audio_stream) = audio_stream {
+    hasher.update((audio_stream as u64).to_le_bytes());
+  }
+  let stem = source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let key = format!("{:x}", hasher.finalize());
+  Ok(proxy_dir()?.join(format!("{}_{}_{:x}.mp4", stem, &key[..16], modified)))
+}
+
+/// Temporary file a proxy encode writes before it is renamed to `proxy`. Unique per encode, so
+/// two jobs making the same proxy don't write into each other's file
+fn proxy_temp_path(proxy: &Path) -> PathBuf {
+  proxy.with_extension(format!("{}.tmp.mp4", uuid::Uuid::new_v4()))
+}
+
+/// Delete proxies of the same source, width and stream as `current` made before the source last
+/// changed; nothing would use them again. Other encodes' temporary files are left alone
+fn prune_replaced_proxies(current: &Path) {
+  let (Some(dir), Some(name)) = (current.parent(), current.file_name().and_then(|n| n.to_str())) else {
+    return;
+  };
+  let Some((prefix, _)) = name.rsplit_once('_') else {
+    return;
+  };
+  let prefix = format!("{}_", prefix);
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for path in entries.flatten().map(|entry| entry.path()) {
+    let Some(other) = path.file_name().and_then(|n| n.to_str()) else {
+      continue;
+    };
+    if other != name && other.starts_with(&prefix) && !other.contains(".tmp.") {
+      media_protocol::revoke_path(&path);
+      if let Err(e) = fs::remove_file(&path) {
+        log::warn!("Failed to remove replaced proxy {:?}: {}", path, e);
+      }
+    }
+  }
+}
+
+/// Whether the proxy at `proxy` was written after `input` last changed
+fn proxy_is_fresh(proxy: &Path, input: &str) -> bool {
+  let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+  match (modified(proxy), modified(Path::new(input))) {
+    (Some(proxy), Some(source)) => proxy >= source,
+    _ => false,
+  }
+}
+
+/// Progress of a proxy encode, sent to the UI as "proxy-progress". The last event for an
+/// input has `done` set
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProxyProgress {
+  pub input: String,
+  pub job_id: Option<String>,
+  pub percent: f64,
+  pub done: bool,
+  pub cached: bool, // An up-to-date proxy was already cached, nothing was encoded
+}
+
+/// Make a small H.264/AAC proxy mp4 for reliable WebView playback, or reuse the cached one
+/// when it is newer than `input`. Returns the output path, which is also registered with the
+/// media protocol. `max_w` caps the width (default `PROXY_WIDTH`), preserving AR.
+/// `audio_stream` is the clip's audio stream (default: the one the file marks default).
+/// `encoder` picks a hardware H.264 encoder (default libx264); if it fails, libx264 redoes the proxy.
+/// `on_progress` receives the finished fraction (0..1) of an encode.
+pub fn make_preview_proxy(
+  input: &str,
+  max_w: Option<u32>,
+  audio_stream: Option<usize>,
+  encoder: Option<&str>,
+  on_progress: &mut dyn FnMut(f64),
+) -> Result<EncodedFile> {
+  if !ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+
+  let out_path = proxy_path(input, max_w, audio_stream)?;
+  let out_str = out_path.to_string_lossy().to_string();
+  if proxy_is_fresh(&out_path, input) {
+    // Keep an existing token: a player may be streaming the proxy through it
+    if media_protocol::token_for_path(&out_path).is_none() {
+      media_protocol::register(&out_path, MediaScope::Session);
+    }
+    return Ok(EncodedFile { path: out_str, encoder: None });
+  }
+  // Encode beside the proxy and rename when done, so a cancelled or crashed encode never
+  // leaves a partial file that looks cached
+  let temp = proxy_temp_path(&out_path);
+  let temp_str = temp.to_string_lossy().to_string();
+  let probe = ffprobe(input).ok();
+  let duration = probe.as_ref().map(|p| p.duration).unwrap_or(0.0);
+  let audio_map = format!("0:a:{}?", audio_stream.or(probe.as_ref().map(|p| p.audio_stream)).unwrap_or_else(|| default_audio_stream(input)));
+
+  let vf = format!("scale='min({},iw)':-2", max_w.unwrap_or(PROXY_WIDTH));
+
+  let (output, encoder) = output_with_encoder_fallback("proxy", encoder, |encoder| {
+    let vf = chain_filters(Some(vf.clone()), encoder_upload_filter(encoder).map(str::to_string)).unwrap_or_default();
+    output_with_hwaccel_fallback_lines(
+      "proxy",
+      |hwaccel| {
+        let mut cmd = Command::new("ffmpeg");
+        cmd.args(["-v", "error", "-progress", "pipe:1", "-nostats"])
+          .args(encoder_device_args(encoder))
+          .args(hwaccel)
+          .args(["-i", input, "-map", "0:v:0?", "-map", &audio_map, "-vf", &vf])
+          .args(h264_encoder_args(encoder, EncodePreset::Preview))
+          .args(["-c:a", "aac", "-b:a", "96k", "-movflags", "+faststart", "-y", &temp_str]);
+        cmd
+      },
+      Some(&mut |line: &str| {
+        if let Some(fraction) = progress_fraction(line, duration) {
+          on_progress(fraction);
+        }
+      }),
+    )
+  })?;
+
+  if !output.status.success() {
+    let _ = fs::remove_file(&temp);
+    return Err(anyhow!(
+      "ffmpeg proxy creation failed (status {:?}): {}",
+      output.status.code(),
+      String::from_utf8_lossy(&output.stderr)
+    ));
+  }
+  // The file is about to be replaced; old URLs must not serve it
+  media_protocol::revoke_path(&out_path);
+  fs::rename(&temp, &out_path).with_context(|| format!("failed to move the proxy to {:?}", out_path))?;
+  prune_replaced_proxies(&out_path);
+
+  media_protocol::register(&out_path, MediaScope::Session);
+  Ok(EncodedFile { path: out_str, encoder: Some(encoder) })
+}
+
+/// Total size of the cached proxies, bytes
+pub fn proxy_cache_size() -> Result<u64> {
+  let dir = proxy_dir()?;
+  let mut size = 0;
+  for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {:?}", dir))? {
+    let metadata = entry?.metadata()?;
+    if metadata.is_file() {
+      size += metadata.len();
+    }
+  }
+  Ok(size)
+}
+
+/// Delete every cached proxy, revoking their media URLs. Returns the bytes freed
+pub fn clear_proxy_cache() -> Result<u64> {
+  let dir = proxy_dir()?;
+  let mut freed = 0;
+  for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {:?}", dir))? {
+    let path = entry?.path();
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    if !path.is_file() {
+      continue;
+    }
+    media_protocol::revoke_path(&path);
+    if fs::remove_file(&path).is_ok() {
+      freed += size;
+    }
+  }
+  Ok(freed)
+}
+
+/// --- Signal Detection ----------------------------------------------------------------
+
+/// Parse `<key>: <seconds>` start/end pairs from ffmpeg detector log output.
+/// A start without an end runs to `duration`.
+fn parse_detector_ranges(stderr: &str, start_key: &str, end_key: &str, duration: f64) -> Vec<Cut> {
+  let value_after = |line: &str, key: &str| -> Option<f64> {
+    let rest = &line[line.find(key)? + key.len()..];
+    rest.trim_start_matches([':', ' ']).split_whitespace().next()?.parse().ok()
+  };
+  let mut ranges = Vec::new();
+  let mut open: Option<f64> = None;
+  for line in stderr.lines() {
+    if let Some(start) = value_after(line, start_key) {
+      open = Some(start.max(0.0));
+    } else if let Some(end) = value_after(line, end_key) {
+      if let Some(start) = open.take() {
+        ranges.push((start, end.min(duration)));
+      }
+    }
+  }
+  if let Some(start) = open {
+    ranges.push((start, duration));
+  }
+  ranges.retain(|(s, e)| e > s);
+  ranges
+}
+
+/// Ranges where the audio stays below `noise_db` (e.g. -35.0) for at least `min_duration` seconds.
+pub fn detect_silences(input: &str, noise_db: f64, min_duration: f64) -> Result<Vec<Cut>> {
+  let duration = ffprobe(input).context("ffprobe failed")?.duration;
+  silences_in(input, noise_db, min_duration, duration)
+}
+
+/// `detect_silences` for a file already probed to last `duration` seconds
+fn silences_in(input: &str, noise_db: f64, min_duration: f64, duration: f64) -> Result<Vec<Cut>> {
+  let output = Command::new("ffmpeg")
+    .args([
+      "-hide_banner",
+      "-nostats",
+      "-i",
+      input,
+      "-vn",
+      "-af",
+      &format!("silencedetect=noise={}dB:d={}", noise_db, min_duration),
+      "-f",
+      "null",
+      "-",
+    ])
+    .output()
+    .with_context(|| "failed to spawn ffmpeg for silence detection")?;
+  if !output.status.success() {
+    return Err(anyhow!("ffmpeg silence detection failed: {}", String::from_utf8_lossy(&output.stderr)));
+  }
+  let log = String::from_utf8_lossy(&output.stderr);
+  Ok(parse_detector_ranges(&log, "silence_start", "silence_end", duration))
+}
+
+/// Silences of at least `min_duration` seconds below `noise_db`, including any at the very
+/// start or end. Files without audio, and thresholds longer than the file, give no ranges
+/// rather than an error.
+pub fn detect_silence(path: &str, min_duration: f64, noise_db: f64) -> Result<Vec<Cut>> {
+  if !min_duration.is_finite() || min_duration <= 0.0 {
+    return Err(anyhow!("minimum silence must be positive, got {}", min_duration));
+  }
+  if project_file::is_image_path(Path::new(path)) || audio_streams(path)?.is_empty() {
+    return Ok(vec![]);
   }
+  let duration = ffprobe(path).context("ffprobe failed")?.duration;
+  if min_duration > duration {
+    return Ok(vec![]);
+  }
+  silences_in(path, noise_db, min_duration, duration)
+}
 
-  // Check if this is a video file (has video stream)
+/// Ranges where the picture doesn't change for at least `min_duration` seconds.
+/// Decodes at a low frame rate and size, which is plenty to spot a static screen.
+pub fn detect_freezes(input: &str, min_duration: f64) -> Result<Vec<Cut>> {
+  let probe = ffprobe(input).context("ffprobe failed")?;
   if probe.width == 0 || probe.height == 0 {
-    // Audio-only file, return empty thumbnails
     return Ok(vec![]);
   }
+  let filter = format!("fps=5,scale=320:-2,freezedetect=n=-60dB:d={}", min_duration);
+  let output = output_with_hwaccel_fallback("freeze detection", |hwaccel| {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-nostats"])
+      .args(hwaccel)
+      .args(["-i", input, "-an", "-vf", &filter, "-f", "null", "-"]);
+    cmd
+  })?;
+  if !output.status.success() {
+    return Err(anyhow!("ffmpeg freeze detection failed: {}", String::from_utf8_lossy(&output.stderr)));
+  }
+  let log = String::from_utf8_lossy(&output.stderr);
+  Ok(parse_detector_ranges(&log, "freeze_start", "freeze_end", probe.duration))
+}
 
-  let mut thumbnails = Vec::new();
-  let interval = duration / (count as f64);
+/// Times (seconds) where the picture changes by more than `threshold` (0..1 scene score).
+/// Audio-only files have no scene changes.
+pub fn detect_scene_changes(input: &str, threshold: f64) -> Result<Vec<f64>> {
+  let probe = ffprobe(input).context("ffprobe failed")?;
+  if probe.width == 0 || probe.height == 0 {
+    return Ok(vec![]);
+  }
+  let filter = format!("fps=5,scale=320:-2,select='gt(scene\\,{})',showinfo", threshold);
+  let output = output_with_hwaccel_fallback("scene detection", |hwaccel| {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-nostats"])
+      .args(hwaccel)
+      .args(["-i", input, "-an", "-vf", &filter, "-f", "null", "-"]);
+    cmd
+  })?;
+  if !output.status.success() {
+    return Err(anyhow!("ffmpeg scene detection failed: {}", String::from_utf8_lossy(&output.stderr)));
+  }
+  let log = String::from_utf8_lossy(&output.stderr);
+  Ok(
+    log
+      .lines()
+      .filter(|line| line.contains("Parsed_showinfo"))
+      .filter_map(|line| {
+        let rest = &line[line.find("pts_time:")? + "pts_time:".len()..];
+        rest.split_whitespace().next()?.parse().ok()
+      })
+      .collect(),
+  )
+}
+
+/// --- Thumbnail Generation ------------------------------------------------------------
+
+/// Generate video thumbnails at regular intervals for timeline scrubbing.
+/// Returns a vector of base64-encoded thumbnail images.
+/// For audio files, returns an empty vector; in an audio-only project, the file's poster
+/// (album art or waveform picture) instead.
+pub fn generate_thumbnails(input: &str, count: usize, width: u32) -> Result<Vec<String>> {
+  if !ffmpeg_exists() {
+    return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+  }
+
+  let probe = ffprobe(input).context("ffprobe failed")?;
+  let duration = probe.duration;
   
-  for i in 0..count {
-    let timestamp = (i as f64) * interval;
-    
-    // Generate thumbnail using ffmpeg
-    let output = Command::new("ffmpeg")
+  if duration <= 0.0 {
+    return Err(anyhow!("Invalid media duration"));
+  }
+
+  // Audio files show album art or a waveform picture as the poster
+  if probe.is_audio_only() {
+    return Ok(thumbnail_cache::poster(input)?.into_iter().collect());
+  }
+
+  if count == 0 {
+    return Ok(vec![]);
+  }
+  let interval = duration / (count as f64);
+  let color = preview_color(input);
+
+  // One pass for all of them: the fps filter keeps a frame every `interval` seconds from the
+  // start (0, interval, 2 * interval, ...), which ffmpeg writes back to back as PNGs. Reading
+  // stops half an interval past the last one, so the rest of the file is never decoded
+  let read = thumbnail_read_duration(duration, count);
+  let output = output_with_hwaccel_fallback("thumbnail", |hwaccel| {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error"])
+      .args(hwaccel)
       .args([
-        "-v", "error",
-        "-ss", &timestamp.to_string(),
+        "-t", &read.to_string(),
         "-i", input,
-        "-vframes", "1",
-        "-vf", &format!("scale={}:-1", width),
+        "-an",
+        "-vf", &format!("fps={},scale={}:-1{}", 1.0 / interval, width, color),
+        "-frames:v", &count.to_string(),
         "-f", "image2pipe",
         "-vcodec", "png",
         "-"
-      ])
-      .output()
-      .with_context(|| format!("failed to spawn ffmpeg for thumbnail at {}", timestamp))?;
+      ]);
+    cmd
+  })?;
+
+  if !output.status.success() {
+    return Err(anyhow!(
+      "ffmpeg thumbnail generation failed: {}",
+      String::from_utf8_lossy(&output.stderr)
+    ));
+  }
+
+  let thumbnails: Vec<String> = split_png_stream(&output.stdout)
+    .into_iter()
+    .map(|png| base64::engine::general_purpose::STANDARD.encode(png))
+    .collect();
+  if thumbnails.len() < count {
+    log::warn!("Got {} of {} thumbnails for {}", thumbnails.len(), count, input);
+  }
+  Ok(thumbnails)
+}
+
+/// Seconds of the input `generate_thumbnails` reads for `count` thumbnails of a `duration`
+/// file: up to half an interval past the last one, which is enough for the fps filter to pick
+/// it, and never past the probed duration
+fn thumbnail_read_duration(duration: f64, count: usize) -> f64 {
+  let interval = duration / count as f64;
+  ((count as f64 - 0.5) * interval).min(duration)
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Split PNGs written back to back (image2pipe) into single images by walking their chunks up
+/// to each IEND, since the signature bytes can also occur inside compressed image data. A
+/// truncated last image is dropped
+fn split_png_stream(data: &[u8]) -> Vec<&[u8]> {
+  let mut images = Vec::new();
+  let mut start = 0;
+  while data[start..].starts_with(PNG_SIGNATURE) {
+    let mut at = start + PNG_SIGNATURE.len();
+    let end = loop {
+      // Chunk: 4-byte big-endian length, 4-byte type, data, 4-byte CRC
+      let Some(header) = data.get(at..at + 8) else { break None };
+      let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+      let next = at + 12 + length;
+      if next > data.len() {
+        break None;
+      }
+      if header[4..8] == *b"IEND" {
+        break Some(next);
+      }
+      at = next;
+    };
+    let Some(end) = end else { break };
+    images.push(&data[start..end]);
+    start = end;
+  }
+  images
+}
+
+/// PNG of the whole waveform of `input`'s `audio_stream`th audio stream (default: the one the
+/// file marks default), `width` x `height`
+pub fn waveform_image(input: &str, audio_stream: Option<usize>, width: u32, height: u32) -> Result<Vec<u8>> {
+  let output = Command::new("ffmpeg")
+    .args([
+      "-v", "error",
+      "-i", input,
+      "-filter_complex", &format!(
+        "[0:a:{}]aformat=channel_layouts=mono,showwavespic=s={}x{}:colors=0x4f9dff",
+        audio_stream.unwrap_or_else(|| default_audio_stream(input)), width, height
+      ),
+      "-frames:v", "1",
+      "-f", "image2pipe",
+      "-vcodec", "png",
+      "-"
+    ])
+    .output()
+    .with_context(|| "failed to spawn ffmpeg for waveform image")?;
+
+  if !output.status.success() || output.stdout.is_empty() {
+    return Err(anyhow!(
+      "ffmpeg waveform image failed: {}",
+      String::from_utf8_lossy(&output.stderr)
+    ));
+  }
+  Ok(output.stdout)
+}
+
+/// --- Still Frames --------------------------------------------------------------------
+
+/// Image format of `extract_frame`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StillFormat {
+  Png,
+  Jpeg,
+}
+
+/// A single frame from `extract_frame`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StillFrame {
+  pub image: String, // Base64
+  pub format: StillFormat,
+  pub timestamp: f64, // Where the frame was taken, after clamping to the last frame
+}
+
+/// Why a frame couldn't be extracted. Serialized as `{"kind": "no_video_stream"}` or
+/// `{"kind": "failed", "message": ...}` so the frontend can hide frame actions for audio
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum FrameError {
+  NoVideoStream,
+  Failed(String),
+}
+
+impl std::fmt::Display for FrameError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      FrameError::NoVideoStream => write!(f, "no video stream"),
+      FrameError::Failed(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<anyhow::Error> for FrameError {
+  fn from(e: anyhow::Error) -> Self {
+    match e.downcast::<FrameError>() {
+      Ok(e) => e,
+      Err(e) => FrameError::Failed(format!("{:#}", e)),
+    }
+  }
+}
+
+/// With `accurate`, input seeking stops this far before the frame and output seeking decodes
+/// the rest, so the frame is exact without decoding from the start of the file
+const ACCURATE_PREROLL: f64 = 5.0;
 
-    if !output.status.success() {
-      return Err(anyhow!(
-        "ffmpeg thumbnail generation failed at {}: {}",
-        timestamp,
-        String::from_utf8_lossy(&output.stderr)
-      ));
+/// The frame of `path` at `timestamp` seconds as a base64 image, at most `width` pixels wide
+/// (full resolution when None). Timestamps past the end give the last frame. `accurate` decodes
+/// up to the exact frame (what the player shows at that playhead) instead of taking the nearest
+/// keyframe-aligned one, which is faster
+pub fn extract_frame(
+  path: &str,
+  timestamp: f64,
+  width: Option<u32>,
+  format: StillFormat,
+  accurate: bool,
+) -> Result<StillFrame, FrameError> {
+  if !ffmpeg_exists() {
+    return Err(FrameError::Failed("ffmpeg/ffprobe not found on PATH".to_string()));
+  }
+  let probe = ffprobe(path).context("ffprobe failed")?;
+  if probe.width == 0 || probe.height == 0 {
+    return Err(FrameError::NoVideoStream);
+  }
+  let frame_duration = if probe.fps > 0.0 { 1.0 / probe.fps } else { 0.04 };
+  let timestamp = timestamp.clamp(0.0, (probe.duration - frame_duration).max(0.0));
+
+  let (input_seek, output_seek) = match accurate {
+    true => {
+      let input_seek = (timestamp - ACCURATE_PREROLL).max(0.0);
+      (input_seek, Some(timestamp - input_seek))
+    }
+    false => (timestamp, None),
+  };
+  let scale = width.map(|w| format!("scale='min({},iw)':-2", w.max(16)));
+  // Matches the player: preview LUT or tone-mapping, as for thumbnails
+  let filter = chain_filters(scale, preview_color(path).strip_prefix(',').map(str::to_string));
+  let codec: &[&str] = match format {
+    StillFormat::Png => &["-vcodec", "png"],
+    StillFormat::Jpeg => &["-vcodec", "mjpeg", "-q:v", "2"],
+  };
+
+  let output = output_with_hwaccel_fallback("still frame", |hwaccel| {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error"])
+      .args(hwaccel)
+      .args(["-ss", &input_seek.to_string(), "-i", path]);
+    if let Some(seek) = output_seek {
+      cmd.args(["-ss", &seek.to_string()]);
+    }
+    if let Some(filter) = &filter {
+      cmd.args(["-vf", filter]);
     }
+    cmd.args(["-an", "-frames:v", "1", "-f", "image2pipe"]).args(codec).arg("-");
+    cmd
+  })?;
 
-    // Convert to base64
-    let base64 = base64::engine::general_purpose::STANDARD.encode(&output.stdout);
-    thumbnails.push(base64);
+  if !output.status.success() || output.stdout.is_empty() {
+    return Err(FrameError::Failed(format!(
+      "ffmpeg failed to extract the frame at {:.3}s (status {:?}): {}",
+      timestamp,
+      output.status.code(),
+      String::from_utf8_lossy(&output.stderr)
+    )));
   }
-
-  Ok(thumbnails)
+  Ok(StillFrame {
+    image: base64::engine::general_purpose::STANDARD.encode(&output.stdout),
+    format,
+    timestamp,
+  })
 }
 
 /// --- Album Art Extraction -------------------------------------------------------------
 
+/// Index of the stream holding `input`'s cover art: the first attached picture, else a video
+/// stream of a single frame, which some taggers write instead. None without either; the
+/// frames of an actual video aren't cover art
+fn album_art_stream(input: &str) -> Result<Option<u64>> {
+  let out = Command::new("ffprobe")
+    .args([
+      "-v", "error",
+      "-select_streams", "v",
+      "-show_entries", "stream=index,nb_frames:stream_disposition=attached_pic",
+      "-print_format", "json",
+      input,
+    ])
+    .output()
+    .with_context(|| "failed to spawn ffprobe")?;
+  if !out.status.success() {
+    return Err(anyhow!("ffprobe failed: {}", String::from_utf8_lossy(&out.stderr)));
+  }
+  let json: serde_json::Value = serde_json::from_slice(&out.stdout).with_context(|| "invalid ffprobe JSON")?;
+  let streams = json["streams"].as_array().cloned().unwrap_or_default();
+  let attached = streams.iter().find(|s| s["disposition"]["attached_pic"].as_u64() == Some(1));
+  let single_frame = || streams.iter().find(|s| s["nb_frames"].as_str() == Some("1"));
+  Ok(attached.or_else(single_frame).and_then(|s| s["index"].as_u64()))
+}
+
 /// Extract album art from audio file and return as base64-encoded PNG.
 /// Returns None if no album art is found.
 pub fn extract_album_art(input: &str) -> Result<Option<String>> {
   if !ffmpeg_exists() {
     return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
   }
+  let Some(stream) = album_art_stream(input)? else {
+    return Ok(None);
+  };
 
-  // Try to extract album art using ffmpeg
   let output = Command::new("ffmpeg")
     .args([
       "-v", "error",
       "-i", input,
-      "-an",  // Disable audio
+      "-map", &format!("0:{}", stream),
       "-c:v", "png",  // Convert to PNG
       "-f", "image2pipe",
-      "-vframes", "1",
+      "-frames:v", "1",
       "-"
     ])
     .output()
     .with_context(|| "failed to spawn ffmpeg for album art extraction")?;
 
-  // If ffmpeg failed or returned no data, there's no album art
+  // If ffmpeg failed or returned no data, there's no usable album art
   if !output.status.success() || output.stdout.is_empty() {
     return Ok(None);
   }
@@ -453,6 +3343,141 @@ pub fn extract_album_art(input: &str) -> Result<Option<String>> {
   Ok(Some(base64))
 }
 
+/// Tags and album art of a music file, for media tiles
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AudioMetadata {
+  pub title: Option<String>,
+  pub artist: Option<String>,
+  pub album: Option<String>,
+  pub duration: f64,
+  pub art: Option<String>, // Media URL of the PNG, None if the file has no embedded art
+}
+
+/// Format tag by name. Tag keys differ in case between containers (ID3 vs Vorbis comments)
+fn format_tag(format: &serde_json::Value, name: &str) -> Option<String> {
+  format["tags"]
+    .as_object()?
+    .iter()
+    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+    .and_then(|(_, value)| value.as_str())
+    .map(|value| value.trim().to_string())
+    .filter(|value| !value.is_empty())
+}
+
+/// Read title/artist/album from the format tags. Album art is left empty; see
+/// `thumbnail_cache::audio_metadata`
+pub fn audio_tags(input: &str) -> Result<AudioMetadata> {
+  let out = Command::new("ffprobe")
+    .args(["-v", "error", "-print_format", "json", "-show_format", input])
+    .output()
+    .with_context(|| "failed to spawn ffprobe")?;
+  if !out.status.success() {
+    return Err(anyhow!("ffprobe failed: {}", String::from_utf8_lossy(&out.stderr)));
+  }
+  let json: serde_json::Value = serde_json::from_slice(&out.stdout).with_context(|| "invalid ffprobe JSON")?;
+  let format = &json["format"];
+  Ok(AudioMetadata {
+    title: format_tag(format, "title"),
+    artist: format_tag(format, "artist").or_else(|| format_tag(format, "album_artist")),
+    album: format_tag(format, "album"),
+    duration: format["duration"].as_str().and_then(|d| d.parse().ok()).unwrap_or(0.0),
+    art: None,
+  })
+}
+
+/// One audio stream of a file, as far as decoding it needs to know
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AudioStreamInfo {
+  pub sample_rate: u32,
+  pub channels: u8,
+  #[serde(default)]
+  pub default: bool, // Marked as the stream to play
+}
+
+/// Audio streams of `input` in file order. Unlike `ffprobe`, a file without audio is not an
+/// error (the list is empty) and video streams aren't looked at
+pub fn audio_streams(input: &str) -> Result<Vec<AudioStreamInfo>> {
+  let out = Command::new("ffprobe")
+    .args([
+      "-v", "error",
+      "-select_streams", "a",
+      "-show_entries", "stream=sample_rate,channels:stream_disposition=default",
+      "-print_format", "json",
+      input,
+    ])
+    .output()
+    .with_context(|| "failed to spawn ffprobe")?;
+  if !out.status.success() {
+    return Err(anyhow!("ffprobe failed: {}", String::from_utf8_lossy(&out.stderr)));
+  }
+  let json: serde_json::Value = serde_json::from_slice(&out.stdout).with_context(|| "invalid ffprobe JSON")?;
+  Ok(json["streams"]
+    .as_array()
+    .map(|streams| {
+      streams
+        .iter()
+        .map(|s| AudioStreamInfo {
+          sample_rate: s["sample_rate"].as_str().and_then(|r| r.parse().ok()).unwrap_or(48000),
+          channels: s["channels"].as_u64().unwrap_or(2) as u8,
+          default: s["disposition"]["default"].as_u64() == Some(1),
+        })
+        .collect()
+    })
+    .unwrap_or_default())
+}
+
+/// --- Drawtext ------------------------------------------------------------------------
+
+/// Text longer than this is passed to drawtext in a file rather than in the filter string
+const DRAWTEXT_INLINE_MAX: usize = 200;
+
+/// Escape `value` as one filter option value inside a filtergraph. ffmpeg unescapes twice:
+/// the graph parser first (where `[],;` end the filter and `\'` escape), then the option
+/// parser (where `:` ends the value). The value is quoted for the second, with each `'`
+/// closed, escaped and reopened, and the result escaped for the first
+pub(crate) fn escape_filter_value(value: &str) -> String {
+  let quoted = format!("'{}'", value.replace('\'', "'\\''"));
+  let mut escaped = String::with_capacity(quoted.len() + 8);
+  for c in quoted.chars() {
+    if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// Text options for a drawtext filter that shows some user text exactly as typed. Expansion
+/// is off, so `%` needs no escaping. Long and multi-line text goes through a temp file, which
+/// is deleted when this is dropped: keep it until ffmpeg has run
+pub(crate) struct DrawtextText {
+  pub options: String, // "text=...:expansion=none" or "textfile=...:expansion=none"
+  file: Option<PathBuf>,
+}
+
+impl Drop for DrawtextText {
+  fn drop(&mut self) {
+    if let Some(file) = &self.file {
+      let _ = fs::remove_file(file);
+    }
+  }
+}
+
+/// Drawtext options showing `text`; see DrawtextText
+pub(crate) fn drawtext_text(text: &str) -> Result<DrawtextText> {
+  // drawtext draws a stray glyph for a carriage return
+  let text = text.replace("\r\n", "\n").replace('\r', "\n");
+  if text.len() <= DRAWTEXT_INLINE_MAX && !text.contains('\n') {
+    return Ok(DrawtextText { options: format!("text={}:expansion=none", escape_filter_value(&text)), file: None });
+  }
+  let file = std::env::temp_dir().join(format!("gebo_drawtext_{}.txt", uuid::Uuid::new_v4()));
+  fs::write(&file, &text).with_context(|| format!("failed to write drawtext text to {:?}", file))?;
+  Ok(DrawtextText {
+    options: format!("textfile={}:expansion=none", escape_filter_value(&file.to_string_lossy())),
+    file: Some(file),
+  })
+}
+
 /// --- Timeline Preview Generation -------------------------------------------------------
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -461,6 +3486,117 @@ pub struct TimelineClip {
   pub start_time: f64,  // Start time within the source media
   pub end_time: f64,    // End time within the source media
   pub offset: f64,      // Position on the timeline
+  #[serde(default)]
+  pub pan: i8,          // Pan of the clip's track
+  #[serde(default)]
+  pub audio_stream: Option<usize>, // The clip's audio stream (see Clip::audio_stream); None: the file's default
+}
+
+/// lut3d filter applying the .cube file at `path`
+pub(crate) fn lut3d_filter(path: &str) -> String {
+  let escaped = path.replace('\\', "/").replace('\'', "'\\''").replace(':', "\\:");
+  format!("lut3d=file='{}'", escaped)
+}
+
+/// Colour conversion for thumbnails and previews of the file at `path`, as ",<filters>":
+/// the clip's preview LUT when the project sets one (log footage), else the tonemap chain for
+/// an HDR file so previews are always SDR, else "" (also for files that can't be probed).
+/// Applied after scaling so the conversion runs on preview-sized frames. Exports never use it;
+/// their grading comes from segment effects.
+pub(crate) fn preview_color(path: &str) -> String {
+  if let Some(lut) = project_file::preview_lut_for_path(Path::new(path)) {
+    return format!(",{}", lut3d_filter(&lut.to_string_lossy()));
+  }
+  ffprobe(path)
+    .ok()
+    .and_then(|probe| probe.tonemap_filter())
+    .map(|f| format!(",{}", f))
+    .unwrap_or_default()
+}
+
+/// Audio filter for previews of `path`: the effects of the project track that plays it (see
+/// audio_effects for the chain order), then `pan`. Invalid effects are logged and skipped so
+/// the preview still plays
+pub(crate) fn preview_audio(path: &str, pan: i8) -> Option<String> {
+  let effects = project_file::track_effects_for_path(Path::new(path));
+  let mut chain = audio_effects::audio_chain(0.0, &effects, None).unwrap_or_else(|e| {
+    log::warn!("Track effects for {} skipped in preview: {}", path, e);
+    Vec::new()
+  });
+  chain.extend(pan_filter(pan));
+  (!chain.is_empty()).then(|| chain.join(","))
+}
+
+/// Audio of timeline preview clip `index` (input `index`) as [a<index>]: its trimmed audio,
+/// or silence as long as the clip when the file has none, so the concat gets audio from every
+/// clip
+fn preview_clip_audio(index: usize, clip: &TimelineClip) -> String {
+  let probe = ffprobe(&clip.media_path).ok();
+  let has_audio = probe.as_ref().map(|p| p.audio_rate > 0).unwrap_or(true);
+  if has_audio {
+    format!(
+      "[{}:a:{}]atrim=start={}:end={},asetpts=PTS-STARTPTS,aresample=async=1:first_pts=0{}[a{}]; ",
+      index,
+      clip.audio_stream.or(probe.as_ref().map(|p| p.audio_stream)).unwrap_or(0),
+      clip.start_time,
+      clip.end_time,
+      preview_audio(&clip.media_path, clip.pan).map(|f| format!(",{}", f)).unwrap_or_default(),
+      index
+    )
+  } else {
+    format!(
+      "anullsrc=r={}:cl=stereo,atrim=duration={}[a{}]; ",
+      RENDER_SAMPLE_RATE,
+      clip.end_time - clip.start_time,
+      index
+    )
+  }
+}
+
+/// Timeline preview for audio-only projects: the clips' audio trimmed and concatenated into an
+/// AAC-only MP4 at `out_str`, with no video stream to build or wait for
+fn audio_timeline_preview(clips: &[TimelineClip], out_str: &str, bitrate: &str) -> Result<()> {
+  let mut filter = String::new();
+  let mut labels = String::new();
+  for (i, clip) in clips.iter().enumerate() {
+    filter.push_str(&format!(
+      "[{}:a:{}]atrim=start={}:end={},asetpts=PTS-STARTPTS,aresample=async=1:first_pts=0{}[a{}]; ",
+      i, clip.audio_stream.unwrap_or_else(|| default_audio_stream(&clip.media_path)), clip.start_time, clip.end_time, preview_audio(&clip.media_path, clip.pan).map(|f| format!(",{}", f)).unwrap_or_default(), i
+    ));
+    labels.push_str(&format!("[a{}]", i));
+  }
+  filter.push_str(&format!("{}concat=n={}:v=0:a=1[outa]", labels, clips.len()));
+
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error"]);
+  for clip in clips {
+    cmd.args(["-i", &clip.media_path]);
+  }
+  cmd.args([
+    "-filter_complex", &filter,
+    "-map", "[outa]",
+    "-c:a", "aac",
+    "-b:a", bitrate,
+    "-movflags", "+faststart",
+    "-y",
+    out_str,
+  ]);
+
+  let output = cmd
+    .output()
+    .with_context(|| "failed to spawn ffmpeg for audio timeline preview")?;
+  if !output.status.success() {
+    return Err(anyhow!(
+      "ffmpeg audio timeline preview creation failed: {}",
+      String::from_utf8_lossy(&output.stderr)
+    ));
+  }
+  Ok(())
+}
+
+/// Whether none of `clips` has video to show, so a preview is pure audio
+fn all_audio(clips: &[TimelineClip]) -> bool {
+  clips.iter().all(|c| ffprobe(&c.media_path).is_ok_and(|probe| probe.is_audio_only()))
 }
 
 /// Generate a preview video from a timeline composition
@@ -469,7 +3605,8 @@ pub fn generate_timeline_preview(
   clips: &[TimelineClip],
   output_width: u32,
   _total_duration: f64,
-) -> Result<String> {
+  encoder: Option<&str>,
+) -> Result<EncodedFile> {
   if !ffmpeg_exists() {
     return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
   }
@@ -478,19 +3615,27 @@ pub fn generate_timeline_preview(
     return Err(anyhow!("No clips provided for timeline preview"));
   }
 
-  // Use Downloads directory for preview storage
-  let downloads_dir = dirs::download_dir().unwrap_or_else(|| std::env::temp_dir());
+  // Downloads unless it isn't writable, see work_dirs.rs
+  let previews_dir = work_dirs::path(WorkDir::Previews)?;
   let timestamp = std::time::SystemTime::now()
     .duration_since(std::time::UNIX_EPOCH)
     .unwrap()
     .as_secs();
-  let out_path = downloads_dir.join(format!("timeline_preview_{}.mp4", timestamp));
+  let out_path = previews_dir.join(format!("timeline_preview_{}.mp4", timestamp));
   let out_str = out_path.to_string_lossy().to_string();
+  // The file is about to be rewritten; old URLs must not serve it half-written
+  media_protocol::revoke_path(&out_path);
 
   // Sort clips by offset
   let mut sorted_clips = clips.to_vec();
   sorted_clips.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap());
 
+  if all_audio(&sorted_clips) {
+    audio_timeline_preview(&sorted_clips, &out_str, "96k")?;
+    media_protocol::register(Path::new(&out_str), MediaScope::Project);
+    return Ok(EncodedFile { path: out_str, encoder: None });
+  }
+
   // Build filter_complex for concatenating clips
   let mut filter = String::new();
   let mut stream_labels = Vec::new();
@@ -500,11 +3645,10 @@ pub fn generate_timeline_preview(
     
     // Trim and scale each clip
     filter.push_str(&format!(
-      "[{}:v]trim=start={}:end={},setpts=PTS-STARTPTS,scale='min({},iw)':-2[v{}]; \
-       [{}:a]atrim=start={}:end={},asetpts=PTS-STARTPTS,aresample=async=1:first_pts=0[a{}]; ",
-      i, clip.start_time, clip.end_time, output_width, i,
-      i, clip.start_time, clip.end_time, i
+      "[{}:v]trim=start={}:end={},setpts=PTS-STARTPTS,scale='min({},iw)':-2{}[v{}]; ",
+      i, clip.start_time, clip.end_time, output_width, preview_color(&clip.media_path), i
     ));
+    filter.push_str(&preview_clip_audio(i, clip));
     
     // Concat expects streams in pairs: [v0][a0][v1][a1]...
     stream_labels.push(format!("[v{}][a{}]", i, i));
@@ -512,58 +3656,44 @@ pub fn generate_timeline_preview(
 
   // Concatenate all clips - join the paired labels
   filter.push_str(&format!(
-    "{}concat=n={}:v=1:a=1[outv][outa]",
+    "{}concat=n={}:v=1:a=1",
     stream_labels.join(""),
     sorted_clips.len()
   ));
 
-  // Build ffmpeg command with multiple inputs
-  let mut cmd = Command::new("ffmpeg");
-  cmd.args(["-v", "error"]);
-  
-  // Add all input files
-  for clip in &sorted_clips {
-    cmd.args(["-i", &clip.media_path]);
-  }
+  let (output, encoder) = output_with_encoder_fallback("timeline_preview", encoder, |encoder| {
+    // Hardware encoders that read from device memory get the frames uploaded after the concat
+    let filter = match encoder_upload_filter(encoder) {
+      Some(upload) => format!("{}[catv][outa];[catv]{}[outv]", filter, upload),
+      None => format!("{}[outv][outa]", filter),
+    };
 
-  // Add filter and output settings
-  cmd.args([
-    "-filter_complex",
-    &filter,
-    "-map",
-    "[outv]",
-    "-map",
-    "[outa]",
-    "-c:v",
-    "libx264",
-    "-preset",
-    "ultrafast",  // Fast encoding for preview
-    "-crf",
-    "28",  // Lower quality for faster preview
-    "-pix_fmt",
-    "yuv420p",
-    "-c:a",
-    "aac",
-    "-b:a",
-    "96k",
-    "-movflags",
-    "+faststart",
-    "-y",
-    &out_str,
-  ]);
+    // Build ffmpeg command with multiple inputs
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error"]).args(encoder_device_args(encoder));
 
-  let status = cmd
-    .status()
-    .with_context(|| "failed to spawn ffmpeg for timeline preview")?;
+    // Add all input files
+    for clip in &sorted_clips {
+      cmd.args(["-i", &clip.media_path]);
+    }
 
-  if !status.success() {
+    // Add filter and output settings; the preview preset is fast and low quality
+    cmd.args(["-filter_complex", &filter, "-map", "[outv]", "-map", "[outa]"])
+      .args(h264_encoder_args(encoder, EncodePreset::Preview))
+      .args(["-c:a", "aac", "-b:a", "96k", "-movflags", "+faststart", "-y", &out_str]);
+    jobs::output_cancellable(&mut cmd, None).with_context(|| "failed to spawn ffmpeg for timeline preview")
+  })?;
+
+  if !output.status.success() {
     return Err(anyhow!(
-      "ffmpeg timeline preview creation failed (status {:?})",
-      status.code()
+      "ffmpeg timeline preview creation failed (status {:?}): {}",
+      output.status.code(),
+      String::from_utf8_lossy(&output.stderr)
     ));
   }
 
-  Ok(out_str)
+  media_protocol::register(Path::new(&out_str), MediaScope::Project);
+  Ok(EncodedFile { path: out_str, encoder: Some(encoder) })
 }
 
 /// Generate a fast preview with dynamic resolution based on player dimensions
@@ -585,23 +3715,33 @@ pub fn generate_adaptive_timeline_preview(
   // Aim for slightly higher than player size to avoid pixelation
   let target_width = (player_width as f32 * 1.2).min(1280.0) as u32;
 
-  // Use Downloads directory for preview storage
-  let downloads_dir = dirs::download_dir().unwrap_or_else(|| std::env::temp_dir());
+  // Downloads unless it isn't writable, see work_dirs.rs
+  let previews_dir = work_dirs::path(WorkDir::Previews)?;
   let timestamp = std::time::SystemTime::now()
     .duration_since(std::time::UNIX_EPOCH)
     .unwrap()
     .as_secs();
-  let out_path = downloads_dir.join(format!("timeline_preview_{}.mp4", timestamp));
+  let out_path = previews_dir.join(format!("timeline_preview_{}.mp4", timestamp));
   let out_str = out_path.to_string_lossy().to_string();
+  // The file is about to be rewritten; old URLs must not serve it half-written
+  media_protocol::revoke_path(&out_path);
 
   // Sort clips by offset
   let mut sorted_clips = clips.to_vec();
   sorted_clips.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap());
 
+  if all_audio(&sorted_clips) {
+    audio_timeline_preview(&sorted_clips, &out_str, "128k")?;
+    media_protocol::register(Path::new(&out_str), MediaScope::Project);
+    return Ok(out_str);
+  }
+
   // For single clip, use simpler approach
   if sorted_clips.len() == 1 {
     let clip = &sorted_clips[0];
     let clip_duration = clip.end_time - clip.start_time;
+    let audio_filter: Vec<String> =
+      preview_audio(&clip.media_path, clip.pan).map(|f| vec!["-af".to_string(), f]).unwrap_or_default();
     
     let output = Command::new("ffmpeg")
       .args([
@@ -609,7 +3749,10 @@ pub fn generate_adaptive_timeline_preview(
         "-ss", &clip.start_time.to_string(),
         "-t", &clip_duration.to_string(),
         "-i", &clip.media_path,
-        "-vf", &format!("scale='min({},iw)':-2", target_width),
+        "-vf", &format!("scale='min({},iw)':-2{}", target_width, preview_color(&clip.media_path)),
+      ])
+      .args(&audio_filter)
+      .args([
         "-c:v", "libx264",
         "-preset", "ultrafast",
         "-crf", "26",  // Slightly better quality for single clip
@@ -629,6 +3772,7 @@ pub fn generate_adaptive_timeline_preview(
       return Err(anyhow!("ffmpeg preview creation failed: {}", stderr));
     }
 
+    media_protocol::register(Path::new(&out_str), MediaScope::Project);
     return Ok(out_str);
   }
 
@@ -641,11 +3785,10 @@ pub fn generate_adaptive_timeline_preview(
     
     // Trim, scale, and prepare each clip
     filter.push_str(&format!(
-      "[{}:v]trim=start={}:end={},setpts=PTS-STARTPTS,scale='min({},iw)':-2,fps=30[v{}]; \
-       [{}:a]atrim=start={}:end={},asetpts=PTS-STARTPTS,aresample=async=1:first_pts=0[a{}]; ",
-      i, clip.start_time, clip.end_time, target_width, i,
-      i, clip.start_time, clip.end_time, i
+      "[{}:v]trim=start={}:end={},setpts=PTS-STARTPTS,scale='min({},iw)':-2{},fps=30[v{}]; ",
+      i, clip.start_time, clip.end_time, target_width, preview_color(&clip.media_path), i
     ));
+    filter.push_str(&preview_clip_audio(i, clip));
     
     // Concat expects streams in pairs: [v0][a0][v1][a1]...
     stream_labels.push(format!("[v{}][a{}]", i, i));
@@ -697,5 +3840,618 @@ pub fn generate_adaptive_timeline_preview(
     ));
   }
 
+  media_protocol::register(Path::new(&out_str), MediaScope::Project);
   Ok(out_str)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn copied_segment_after_a_reencoded_one_starts_on_the_next_keyframe() {
+    // The first segment collapses (no keyframe inside 2..2.5) and is re-encoded up to 2.5; the
+    // second one's nearest keyframe, 2, lies inside it
+    let segments = snap_to_keyframes(&[(2.1, 2.5), (2.6, 9.0)], &[0.0, 2.0, 6.0, 10.0], 12.0);
+    assert_eq!(segments.len(), 2);
+    assert!(segments[0].reencoded);
+    assert_eq!(segments[0].snapped, (2.1, 2.5));
+    assert!(!segments[1].reencoded);
+    assert_eq!(segments[1].snapped, (6.0, 10.0));
+  }
+
+  #[test]
+  fn segments_never_overlap() {
+    let keyframes = [0.0, 1.0, 4.0, 5.0, 9.0];
+    let segments = snap_to_keyframes(&[(0.0, 1.2), (1.3, 1.6), (1.7, 4.6), (4.7, 12.0)], &keyframes, 12.0);
+    for pair in segments.windows(2) {
+      assert!(pair[1].snapped.0 >= pair[0].snapped.1 - SNAP_EPSILON, "{:?}", segments);
+    }
+    for segment in segments.iter().filter(|s| !s.reencoded && s.snapped.0 > 0.0) {
+      assert!(keyframes.contains(&segment.snapped.0), "{:?}", segment);
+    }
+  }
+
+  #[test]
+  fn audio_only_sources_keep_their_times() {
+    let segments = snap_to_keyframes(&[(0.0, 3.3), (5.5, 8.0)], &[], 8.0);
+    assert_eq!(segments.iter().map(|s| s.snapped).collect::<Vec<_>>(), vec![(0.0, 3.3), (5.5, 8.0)]);
+    assert!(segments.iter().all(|s| !s.reencoded));
+  }
+
+  #[test]
+  fn reencoded_h264_matches_the_source_stream() {
+    let stream = serde_json::json!({
+      "codec_type": "video",
+      "codec_name": "h264",
+      "profile": "High",
+      "level": 41,
+      "pix_fmt": "yuv420p",
+      "r_frame_rate": "30000/1001",
+      "time_base": "1/30000",
+    });
+    let args = matching_stream_args(&stream, "mp4").unwrap();
+    assert_eq!(
+      args.join(" "),
+      "-c:v libx264 -pix_fmt yuv420p -profile:v high -level:v 4.1 -r 30000/1001 -video_track_timescale 30000"
+    );
+    // Only ISO BMFF files take a track timescale
+    assert!(!matching_stream_args(&stream, "mkv").unwrap().contains(&"-video_track_timescale".to_string()));
+  }
+
+  #[test]
+  fn reencoded_hevc_keeps_its_10_bit_profile_and_level() {
+    let stream = serde_json::json!({
+      "codec_type": "video",
+      "codec_name": "hevc",
+      "profile": "Main 10",
+      "level": 123,
+      "pix_fmt": "yuv420p10le",
+      "r_frame_rate": "25/1",
+      "time_base": "1/1000",
+    });
+    let args = matching_stream_args(&stream, "mkv").unwrap().join(" ");
+    assert_eq!(args, "-c:v libx265 -pix_fmt yuv420p10le -profile:v main10 -x265-params level-idc=4.1 -r 25/1");
+  }
+
+  #[test]
+  fn reencoded_audio_keeps_rate_and_channels() {
+    let stream = serde_json::json!({
+      "codec_type": "audio",
+      "codec_name": "aac",
+      "profile": "LC",
+      "sample_rate": "44100",
+      "channels": 1,
+    });
+    assert_eq!(matching_stream_args(&stream, "mp4").unwrap().join(" "), "-c:a aac -ar 44100 -ac 1");
+  }
+
+  #[test]
+  fn codecs_without_a_matching_encoder_are_refused() {
+    let stream = serde_json::json!({ "codec_type": "video", "codec_name": "mpeg2video" });
+    assert!(matching_stream_args(&stream, "mp4").is_err());
+  }
+
+  fn probe(width: u32, height: u32) -> serde_json::Value {
+    serde_json::json!({
+      "duration": 10.0, "width": width, "height": height, "fps": 30.0, "audio_rate": 48000, "audio_channels": 2,
+      "v_codec": if width > 0 { "h264" } else { "" }, "a_codec": "aac", "container": "mp4",
+    })
+  }
+
+  #[test]
+  fn audio_only_clips_show_black_on_the_video_track() {
+    let segment = |id: &str, clip: &str, end: f64| serde_json::json!({ "id": id, "clip_id": clip, "start": 0.0, "end": end });
+    let project: project_file::ProjectFile = serde_json::from_value(serde_json::json!({
+      "title": "Test",
+      "clips_map": {
+        "clip_v": { "id": "clip_v", "path": "/media/v.mp4", "latest_probe": probe(1920, 1080), "type": "Video" },
+        "clip_a": { "id": "clip_a", "path": "/media/a.m4a", "latest_probe": probe(0, 0), "type": "Audio" },
+      },
+      "tracks_map": { "main": {
+        "id": "main", "name": "Main", "type": "Video", "enabled": true, "muted": false, "volume": 100, "order": 0,
+        "segments": [segment("s0", "clip_v", 2.0), segment("s1", "clip_a", 3.0), segment("s2", "clip_v", 2.0)],
+      } },
+    }))
+    .unwrap();
+    let track = &project.tracks_map["main"];
+    let mut inputs = RenderInputs { clips: Vec::new() };
+    let graph = timeline_video_graph(&project, track, &mut inputs, &ExportSettings::default(), (1920, 1080), 30.0, 7.0).unwrap();
+
+    assert_eq!(inputs.clips.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec!["clip_v"]);
+    assert_eq!(graph.iter().filter(|g| g.starts_with("[0:v]")).count(), 2);
+    assert!(!graph.iter().any(|g| g.starts_with("[1:v]")));
+    assert!(graph.iter().any(|g| g.starts_with("color=c=black") && g.contains(":d=3,") && g.ends_with("[gap1]")));
+    assert_eq!(graph.last().unwrap(), "[seg0][gap1][seg2]concat=n=3:v=1:a=0[catv]");
+  }
+
+  #[test]
+  fn a_non_default_audio_stream_is_never_stream_copied() {
+    let mut source: Probe = serde_json::from_value(probe(1920, 1080)).unwrap();
+    source.audio_stream = 1;
+    assert!(ExportSettings::default().allows_stream_copy(&source));
+    assert!(ExportSettings { audio_stream: Some(1), ..ExportSettings::default() }.allows_stream_copy(&source));
+    assert!(!ExportSettings { audio_stream: Some(0), ..ExportSettings::default() }.allows_stream_copy(&source));
+    assert!(ExportSettings { audio_stream: Some(0), ..ExportSettings::default() }.describe().ends_with(" audio stream 0"));
+  }
+
+  #[test]
+  fn pan_keeps_the_louder_side_at_unity() {
+    assert_eq!(pan_filter(-100).unwrap(), "aformat=channel_layouts=stereo,pan=stereo|c0=1.00*c0|c1=0.00*c1");
+    assert_eq!(pan_filter(0), None);
+    assert_eq!(pan_filter(30).unwrap(), "aformat=channel_layouts=stereo,pan=stereo|c0=0.70*c0|c1=1.00*c1");
+    // Out-of-range values from an older file are clamped rather than inverting a channel
+    assert_eq!(pan_filter(-128), pan_filter(-100));
+  }
+
+  #[test]
+  fn mono_sources_are_upmixed_before_panning() {
+    // pan only sees c1 once aformat has duplicated the mono channel
+    let filter = pan_filter(30).unwrap();
+    assert!(filter.find("aformat=channel_layouts=stereo").unwrap() < filter.find("pan=").unwrap());
+  }
+
+  #[test]
+  fn export_pan_comes_after_the_track_effects() {
+    let effects: Vec<project_file::Effect> =
+      serde_json::from_value(serde_json::json!([{ "type": "highpass", "parameters": { "frequency": 80.0 } }])).unwrap();
+    let settings = ExportSettings { pan: Some(30), track_effects: Some(effects), ..ExportSettings::default() };
+    let filter = settings.audio_filter().unwrap().unwrap();
+    assert!(filter.starts_with("highpass"), "{}", filter);
+    assert!(filter.ends_with(&pan_filter(30).unwrap()), "{}", filter);
+    assert_eq!(ExportSettings::default().audio_filter().unwrap(), None);
+  }
+
+  #[test]
+  fn codecs_the_container_cannot_hold_are_never_stream_copied() {
+    let source = |v_codec: &str, a_codec: &str| -> Probe {
+      let mut probe: Probe = serde_json::from_value(probe(1920, 1080)).unwrap();
+      probe.v_codec = v_codec.to_string();
+      probe.a_codec = a_codec.to_string();
+      probe
+    };
+    let settings = ExportSettings::default();
+    assert!(settings.allows_stream_copy(&source("h264", "aac")));
+    assert!(settings.allows_stream_copy(&source("hevc", "")));
+    assert!(!settings.allows_stream_copy(&source("prores", "aac")));
+    assert!(!settings.allows_stream_copy(&source("h264", "pcm_s24le")));
+    assert!(!settings.allows_stream_copy(&source("vp8", "vorbis")));
+
+    assert!(Container::Mov.holds_codecs("prores", "pcm_s24le"));
+    assert!(Container::Mkv.holds_codecs("vp8", "vorbis"));
+    assert!(!Container::Webm.holds_codecs("h264", "opus"));
+    assert!(Container::M4a.holds_codecs("mjpeg", "aac"));
+  }
+
+  #[test]
+  fn only_files_already_in_the_container_are_copied_whole() {
+    assert!(Container::Mp4.is_format("mov,mp4,m4a,3gp,3g2,mj2"));
+    assert!(!Container::Mp4.is_format("matroska,webm"));
+    assert!(Container::Mkv.is_format("matroska,webm"));
+    assert!(!Container::Mkv.is_format("mov,mp4,m4a,3gp,3g2,mj2"));
+  }
+
+  /// Matroska clip in `dir` whose first audio stream is a silent commentary track and whose
+  /// second, marked default, is a tone
+  fn two_audio_fixture(dir: &Path) -> String {
+    let input = dir.join("two-audio.mkv");
+    let result = Command::new("ffmpeg")
+      .args(["-v", "error", "-y"])
+      .args(["-f", "lavfi", "-i", "testsrc2=size=160x90:rate=30:duration=2"])
+      .args(["-f", "lavfi", "-i", "anullsrc=r=48000:cl=stereo:d=2"])
+      .args(["-f", "lavfi", "-i", "sine=frequency=440:sample_rate=48000:duration=2"])
+      .args(["-map", "0:v", "-map", "1:a", "-map", "2:a", "-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac"])
+      .args(["-disposition:a:0", "comment", "-disposition:a:1", "default", "-default_mode", "passthrough"])
+      .arg(&input)
+      .output()
+      .unwrap();
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    input.to_string_lossy().to_string()
+  }
+
+  fn audible(path: &str) -> bool {
+    crate::waveform::pcm_peaks(path, 0).unwrap().iter().any(|p| *p != 0)
+  }
+
+  #[test]
+  fn the_default_audio_stream_is_probed_and_exported_unless_the_clip_picks_another() {
+    if !ffmpeg_exists() {
+      eprintln!("ffmpeg not on PATH; skipping");
+      return;
+    }
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = two_audio_fixture(&dir);
+
+    let full = ffprobe_full(&input).unwrap();
+    let audio: Vec<&StreamInfo> = full.streams.iter().filter(|s| s.codec_type == "audio").collect();
+    assert_eq!(audio.len(), 2);
+    assert!(audio[0].dispositions.iter().any(|d| d == "comment"));
+    assert!(audio[1].dispositions.iter().any(|d| d == "default"));
+    assert_eq!(full.probe().unwrap().audio_stream, 1);
+    assert_eq!(default_audio_stream(&input), 1);
+    assert!(crate::waveform::pcm_peaks(&input, 1).unwrap().iter().any(|p| *p != 0));
+    assert!(!crate::waveform::pcm_peaks(&input, 0).unwrap().iter().any(|p| *p != 0));
+
+    // Only the tone is audible, so a silent export took the commentary stream and vice versa
+    let default_out = dir.join("default.mp4").to_string_lossy().to_string();
+    export_with_cuts(&input, &default_out, &[(0.5, 1.0)], &ExportSettings::default(), None, true, &mut |_| {}).unwrap();
+    assert!(audible(&default_out));
+    let commentary = ExportSettings { audio_stream: Some(0), ..ExportSettings::default() };
+    let commentary_out = dir.join("commentary.mp4").to_string_lossy().to_string();
+    export_with_cuts(&input, &commentary_out, &[(0.5, 1.0)], &commentary, None, true, &mut |_| {}).unwrap();
+    assert!(!audible(&commentary_out));
+
+    let lossless_out = dir.join("lossless.mkv").to_string_lossy().to_string();
+    export_with_cuts_lossless(&input, &lossless_out, &[], Some(0), &mut |_| {}).unwrap();
+    assert!(!audible(&lossless_out));
+
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  /// 2 s Matroska clip in `dir` with a test picture, a tone, or both
+  fn av_fixture(dir: &Path, name: &str, video: bool, audio: bool) -> String {
+    let input = dir.join(format!("{}.mkv", name));
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-v", "error", "-y"]);
+    if video {
+      cmd.args(["-f", "lavfi", "-i", "testsrc2=size=160x90:rate=30:duration=2"]);
+    }
+    if audio {
+      cmd.args(["-f", "lavfi", "-i", "sine=frequency=440:sample_rate=48000:duration=2"]);
+    }
+    cmd.args(["-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac"]);
+    let result = cmd.arg(&input).output().unwrap();
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    input.to_string_lossy().to_string()
+  }
+
+  #[test]
+  fn video_only_audio_only_and_normal_files_are_probed_and_exported() {
+    if !ffmpeg_exists() {
+      eprintln!("ffmpeg not on PATH; skipping");
+      return;
+    }
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for (name, video, audio) in [("video-only", true, false), ("audio-only", false, true), ("normal", true, true)] {
+      let input = av_fixture(&dir, name, video, audio);
+      let probe = ffprobe(&input).unwrap();
+      assert_eq!(probe.width > 0, video, "{}", name);
+      assert_eq!(probe.audio_channels > 0, audio, "{}", name);
+      assert!((probe.duration - 2.0).abs() < 0.1, "{}: {}s", name, probe.duration);
+
+      let output = dir.join(format!("{}-export.mp4", name)).to_string_lossy().to_string();
+      export_with_cuts(&input, &output, &[(0.5, 1.0)], &ExportSettings::default(), None, true, &mut |_| {}).unwrap();
+      let exported = ffprobe(&output).unwrap();
+      assert_eq!(exported.width > 0, video, "{}", name);
+      assert_eq!(exported.audio_channels > 0, audio, "{}", name);
+      assert!((exported.duration - 1.5).abs() < 0.15, "{}: {}s", name, exported.duration);
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  /// A PNG as image2pipe writes it: the signature, a data chunk holding `data`, then IEND
+  fn png(data: &[u8]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(b"IDAT");
+    png.extend_from_slice(data);
+    png.extend_from_slice(&[0; 4]);
+    png.extend_from_slice(&[0, 0, 0, 0]);
+    png.extend_from_slice(b"IEND");
+    png.extend_from_slice(&[0; 4]);
+    png
+  }
+
+  #[test]
+  fn png_streams_split_on_iend_not_on_signatures_inside_the_data() {
+    let first = png(&[PNG_SIGNATURE, b"IEND"].concat());
+    let second = png(b"second");
+    let mut stream = [first.clone(), second.clone()].concat();
+    assert_eq!(split_png_stream(&stream), vec![first.as_slice(), second.as_slice()]);
+
+    // A truncated last image is dropped
+    stream.extend_from_slice(&png(b"third")[..20]);
+    assert_eq!(split_png_stream(&stream).len(), 2);
+    assert!(split_png_stream(b"not a png").is_empty());
+  }
+
+  #[test]
+  fn thumbnails_read_only_up_to_the_last_one() {
+    assert_eq!(thumbnail_read_duration(100.0, 10), 95.0);
+    assert_eq!(thumbnail_read_duration(10.0, 1), 5.0);
+    assert!(thumbnail_read_duration(7.0, 50) < 7.0);
+  }
+
+  /// Full path of `program` on PATH
+  #[cfg(unix)]
+  fn on_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?).map(|dir| dir.join(program)).find(|p| p.is_file())
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn all_thumbnails_come_from_one_ffmpeg_process() {
+    use std::os::unix::fs::PermissionsExt;
+    let Some(real) = on_path("ffmpeg").filter(|_| ffmpeg_exists()) else {
+      eprintln!("ffmpeg not on PATH; skipping");
+      return;
+    };
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("long.mp4");
+    let result = Command::new(&real)
+      .args(["-v", "error", "-y", "-f", "lavfi", "-i", "testsrc2=size=160x90:rate=30:duration=20"])
+      .args(["-c:v", "libx264", "-g", "30", "-pix_fmt", "yuv420p"])
+      .arg(&input)
+      .output()
+      .unwrap();
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+    // An ffmpeg ahead on PATH that logs each run, then runs the real one
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let log = dir.join("runs.log");
+    let wrapper = bin.join("ffmpeg");
+    fs::write(&wrapper, format!("#!/bin/sh\necho \"$*\" >> '{}'\nexec '{}' \"$@\"\n", log.display(), real.display())).unwrap();
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::var_os("PATH").unwrap();
+    let wrapped = std::env::join_paths(std::iter::once(bin.clone()).chain(std::env::split_paths(&path))).unwrap();
+    std::env::set_var("PATH", &wrapped);
+    let started = std::time::Instant::now();
+    let thumbnails = generate_thumbnails(&input.to_string_lossy(), 50, 64);
+    let elapsed = started.elapsed();
+    std::env::set_var("PATH", &path);
+
+    let thumbnails = thumbnails.unwrap();
+    assert_eq!(thumbnails.len(), 50);
+    let runs = fs::read_to_string(&log).unwrap_or_default();
+    let input_runs = runs.lines().filter(|line| line.contains("long.mp4")).count();
+    assert_eq!(input_runs, 1, "{}", runs);
+    eprintln!("50 thumbnails in {:?}", elapsed);
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  /// ffmpeg's av_get_token: read up to a top-level `term` character, taking the character after
+  /// a `\` literally and everything between `'`s literally, and dropping leading and unescaped
+  /// trailing whitespace. Returns the token and what follows it
+  fn get_token<'a>(buf: &'a str, term: &str) -> (String, &'a str) {
+    const WHITESPACE: &[char] = &[' ', '\n', '\t', '\r'];
+    let mut chars = buf.trim_start_matches(WHITESPACE).char_indices().peekable();
+    let rest = buf.trim_start_matches(WHITESPACE);
+    let (mut out, mut end) = (String::new(), 0);
+    let mut stop = rest.len();
+    while let Some((at, c)) = chars.next() {
+      if term.contains(c) {
+        stop = at;
+        break;
+      }
+      match c {
+        '\\' if chars.peek().is_some() => {
+          out.push(chars.next().unwrap().1);
+          end = out.len();
+        }
+        '\'' => {
+          for (_, c) in chars.by_ref() {
+            if c == '\'' {
+              end = out.len();
+              break;
+            }
+            out.push(c);
+          }
+        }
+        _ => out.push(c),
+      }
+    }
+    while out.len() > end && out.ends_with(WHITESPACE) {
+      out.pop();
+    }
+    (out, &rest[stop..])
+  }
+
+  /// Options of the first filter in `graph` ("name=options..."), unescaped the way ffmpeg's
+  /// graph parser and then its option parser do
+  fn parse_filter_options(graph: &str) -> (Vec<(String, String)>, String) {
+    let (_, args) = graph.split_once('=').unwrap();
+    let (args, rest) = get_token(args, "[],;");
+    let mut options = Vec::new();
+    let mut remaining = args.as_str();
+    while !remaining.is_empty() {
+      let (key, value) = remaining.split_once('=').unwrap();
+      let (value, after) = get_token(value, ":");
+      options.push((key.to_string(), value));
+      remaining = after.strip_prefix(':').unwrap_or(after);
+    }
+    (options, rest.to_string())
+  }
+
+  /// Deterministic strings over the characters ffmpeg's parsers treat specially, plus
+  /// whitespace and multi-byte text
+  fn arbitrary_strings(count: usize) -> Vec<String> {
+    let alphabet: Vec<char> = "\\'[],;:=%{}\" \ta9é日🎬".chars().collect();
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next = move || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      state
+    };
+    (0..count)
+      .map(|_| {
+        let len = (next() % 24) as usize;
+        (0..len).map(|_| alphabet[(next() % alphabet.len() as u64) as usize]).collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn escaped_filter_values_unescape_to_the_original() {
+    for value in arbitrary_strings(5000) {
+      let graph = format!("drawtext=text={}:expansion=none[out];[out]null", escape_filter_value(&value));
+      let (options, rest) = parse_filter_options(&graph);
+      assert_eq!(
+        options,
+        vec![("text".to_string(), value.clone()), ("expansion".to_string(), "none".to_string())],
+        "{:?} escaped as {:?}",
+        value,
+        graph
+      );
+      assert_eq!(rest, "[out];[out]null", "{:?}", value);
+    }
+  }
+
+  #[test]
+  fn drawtext_text_shows_the_text_as_typed() {
+    for text in arbitrary_strings(500) {
+      let drawtext = drawtext_text(&text).unwrap();
+      let (options, _) = parse_filter_options(&format!("drawtext={}", drawtext.options));
+      assert_eq!(options, vec![("text".to_string(), text.clone()), ("expansion".to_string(), "none".to_string())]);
+    }
+
+    // Long and multi-line text goes through a file, with carriage returns as line breaks
+    let long = format!("{}\r\nsecond: 'line'", "x".repeat(DRAWTEXT_INLINE_MAX));
+    let drawtext = drawtext_text(&long).unwrap();
+    let (options, _) = parse_filter_options(&format!("drawtext={}", drawtext.options));
+    assert_eq!(options[0].0, "textfile");
+    assert_eq!(options[1], ("expansion".to_string(), "none".to_string()));
+    let file = PathBuf::from(&options[0].1);
+    assert_eq!(fs::read_to_string(&file).unwrap(), long.replace("\r\n", "\n"));
+    drop(drawtext);
+    assert!(!file.exists());
+  }
+
+  #[test]
+  fn timeline_end_fades_cover_the_first_and_last_second() {
+    let (video, audio) = timeline_end_fades(10.0);
+    assert_eq!(video, "fade=t=in:st=0:d=1,fade=t=out:st=9:d=1");
+    assert_eq!(audio, "afade=t=in:st=0:d=1,afade=t=out:st=9:d=1");
+    // Short outputs fade over half each
+    assert_eq!(timeline_end_fades(1.0).0, "fade=t=in:st=0:d=0.5,fade=t=out:st=0.5:d=0.5");
+    // After the output's own filters
+    let (video, audio) = with_timeline_end_fades(Some("scale=640:-2".to_string()), None, 10.0);
+    assert_eq!(video.unwrap(), "scale=640:-2,fade=t=in:st=0:d=1,fade=t=out:st=9:d=1");
+    assert_eq!(audio.unwrap(), "afade=t=in:st=0:d=1,afade=t=out:st=9:d=1");
+  }
+
+  #[test]
+  fn timeline_audio_fades_use_each_segments_curve() {
+    let segment = |id: &str, curve: &str| {
+      serde_json::json!({
+        "id": id, "clip_id": "clip_a", "start": 0.0, "end": 2.0, "fade_in": 0.5, "fade_out": 0.25, "fade_curve": curve,
+      })
+    };
+    let project: project_file::ProjectFile = serde_json::from_value(serde_json::json!({
+      "title": "Test",
+      "clips_map": { "clip_a": { "id": "clip_a", "path": "/media/a.m4a", "latest_probe": probe(0, 0), "type": "Audio" } },
+      "tracks_map": { "music": {
+        "id": "music", "name": "Music", "type": "Audio", "enabled": true, "muted": false, "volume": 100, "order": 0,
+        "segments": [segment("s0", "linear"), segment("s1", "exponential"), segment("s2", "s_curve")],
+      } },
+    }))
+    .unwrap();
+    let mut inputs = RenderInputs { clips: Vec::new() };
+    let graph = timeline_audio_graph(&project, &[&project.tracks_map["music"]], &mut inputs, 6.0).unwrap();
+    for (chain, curve) in graph.iter().zip(["tri", "exp", "hsin"]) {
+      assert!(chain.contains(&format!("afade=t=in:st=0:d=0.5:curve={},", curve)), "{}", chain);
+      assert!(chain.contains(&format!("afade=t=out:st=1.75:d=0.25:curve={},", curve)), "{}", chain);
+    }
+  }
+
+  /// Luma (0..255) of the frame at `time` of `path`, averaged over the picture
+  fn luma_at(path: &str, time: f64) -> u8 {
+    let output = Command::new("ffmpeg")
+      .args(["-v", "error", "-ss", &time.to_string(), "-i", path])
+      .args(["-vf", "scale=1:1,format=gray", "-frames:v", "1", "-f", "rawvideo", "-"])
+      .output()
+      .unwrap();
+    output.stdout[0]
+  }
+
+  #[test]
+  fn project_render_fades_the_timeline_ends() {
+    if !ffmpeg_exists() {
+      eprintln!("ffmpeg not on PATH; skipping");
+      return;
+    }
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("white.mp4");
+    let status = Command::new("ffmpeg")
+      .args(["-v", "error", "-f", "lavfi", "-i", "color=c=white:s=320x240:r=25:d=4", "-f", "lavfi", "-i", "sine=frequency=440:duration=4"])
+      .args(["-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac", "-shortest", "-y"])
+      .arg(&input)
+      .status()
+      .unwrap();
+    assert!(status.success());
+
+    let mut project: project_file::ProjectFile = serde_json::from_value(serde_json::json!({
+      "title": "Test",
+      "clips_map": { "clip_v": { "id": "clip_v", "path": input, "type": "Video" } },
+      "tracks_map": { "main": {
+        "id": "main", "name": "Main", "type": "Video", "enabled": true, "muted": false, "volume": 100, "order": 0,
+        "segments": [{ "id": "s0", "clip_id": "clip_v", "start": 0.0, "end": 4.0 }],
+      } },
+    }))
+    .unwrap();
+    project.settings.fade_timeline_ends = true;
+    let output = dir.join("render.mp4").to_string_lossy().to_string();
+    render_project(&project, &output, &ExportSettings::default(), &mut |_| {}).unwrap();
+
+    assert!(luma_at(&output, 0.0) < 40);
+    assert!(luma_at(&output, 2.0) > 200);
+    let peaks = crate::waveform::pcm_peaks(&output, 0).unwrap();
+    let middle = peaks[peaks.len() / 2].unsigned_abs();
+    assert!(peaks[0].unsigned_abs() < middle / 4, "{} vs {}", peaks[0], middle);
+    assert!(peaks[peaks.len() - 1].unsigned_abs() < middle / 4, "{} vs {}", peaks[peaks.len() - 1], middle);
+
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn concurrent_proxy_encodes_write_separate_files() {
+    let proxy = Path::new("/cache/proxies/clip_0123456789abcdef_17f.mp4");
+    let (a, b) = (proxy_temp_path(proxy), proxy_temp_path(proxy));
+    assert_ne!(a, b);
+    assert!(a.starts_with("/cache/proxies") && a.to_string_lossy().ends_with(".tmp.mp4"), "{:?}", a);
+  }
+
+  #[test]
+  fn proxies_of_an_edited_source_are_pruned() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let names = [
+      "clip_0123456789abcdef_17f.mp4", // Made before the source changed
+      "clip_0123456789abcdef_180.mp4", // Current
+      "clip_0123456789abcdef_180.job-2.tmp.mp4", // Another encode still writing
+      "clip_fedcba9876543210_17f.mp4", // Another width of the same source
+    ];
+    for name in names {
+      fs::write(dir.join(name), b"").unwrap();
+    }
+    prune_replaced_proxies(&dir.join(names[1]));
+    let left: Vec<bool> = names.iter().map(|name| dir.join(name).exists()).collect();
+    assert_eq!(left, [false, true, true, true]);
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn mkv_re_exports_are_written_in_place_unless_they_replace_an_input() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let (input, output) = (dir.join("talk.mkv"), dir.join("talk-edit.mkv"));
+    fs::write(&input, b"").unwrap();
+    fs::write(&output, b"").unwrap();
+    let mkv = ExportSettings { container: Container::Mkv, ..ExportSettings::default() };
+
+    assert!(mkv.writes_in_place(&output, &[&input]));
+    assert!(mkv.writes_in_place(&dir.join("new.mkv"), &[&input]));
+    assert!(!mkv.writes_in_place(&input, &[&input]));
+    // The same file under another spelling
+    assert!(!mkv.writes_in_place(&dir.join(".").join("talk.mkv"), &[&input]));
+    assert!(!ExportSettings::default().writes_in_place(&output, &[&input]));
+    let _ = fs::remove_dir_all(&dir);
+  }
+}
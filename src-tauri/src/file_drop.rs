@@ -0,0 +1,154 @@
+//! Files dropped onto a window. Each path is validated and imported here rather than in the
+//! frontend, and the outcome is reported to that window with a single "files-imported" event.
+
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+use crate::editor_windows;
+use crate::jobs::{self, JobContext};
+use crate::longterm_storage;
+use crate::project_file::{self, Clip, ProjectFile};
+
+/// Extensions opened as projects instead of imported as media
+pub const PROJECT_EXTENSIONS: &[&str] = &["gebo", "vid"];
+
+/// Names skipped in dropped folders besides dotfiles
+const SYSTEM_FILES: &[&str] = &["thumbs.db", "desktop.ini", "icon\r"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DropOutcome {
+  Imported,      // Media added to the current project (or returned as a clip without one)
+  ProjectOpened, // Project file loaded
+  Failed,        // Not importable, see `error`
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DroppedFile {
+  pub path: String,
+  pub outcome: DropOutcome,
+  pub clip: Option<Clip>,
+  pub error: Option<String>,
+  #[serde(default)]
+  pub warnings: Vec<String>, // Imported, but likely to cause trouble (e.g. variable frame rate)
+}
+
+/// Payload of the "files-imported" event
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FilesImported {
+  pub files: Vec<DroppedFile>, // In drop order, folders expanded in name order
+  pub project: Option<ProjectFile>, // Current project after the drop, if any
+}
+
+fn is_project_file(path: &Path) -> bool {
+  path.extension()
+    .map(|e| e.to_string_lossy().to_lowercase())
+    .is_some_and(|e| PROJECT_EXTENSIONS.contains(&e.as_str()))
+}
+
+fn is_hidden(path: &Path) -> bool {
+  let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+  name.starts_with('.') || SYSTEM_FILES.contains(&name.as_str())
+}
+
+/// Expand dropped folders one level: their visible files, in name order. Subfolders and
+/// project files inside folders are ignored
+fn expand(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+  let mut expanded = Vec::new();
+  for path in paths {
+    if !path.is_dir() {
+      expanded.push(path);
+      continue;
+    }
+    let mut children: Vec<PathBuf> = match fs::read_dir(&path) {
+      Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+      Err(e) => {
+        log::warn!("Failed to read dropped folder {:?}: {}", path, e);
+        continue;
+      }
+    };
+    children.retain(|p| p.is_file() && !is_hidden(p) && !is_project_file(p));
+    children.sort();
+    expanded.extend(children);
+  }
+  expanded
+}
+
+fn open_project(path: &Path) -> Result<()> {
+  let path = path.to_string_lossy().to_string();
+  project_file::load_project(path.clone())?;
+  if let Err(e) = longterm_storage::add_recent_project(path.clone()) {
+    log::warn!("Failed to add {} to recent projects: {}", path, e);
+  }
+  Ok(())
+}
+
+/// Validate and import one media file. Into the current project when one is loaded
+fn import_file(path: &Path) -> Result<Clip> {
+  let clip = project_file::import_media(path)?;
+  if project_file::has_project() {
+    return project_file::add_clip(clip);
+  }
+  Ok(clip)
+}
+
+/// Process a drop. A project file is opened first so media dropped with it lands in it;
+/// only one project can be opened per drop. Cancelling `job` fails the files not imported yet
+pub fn import_dropped(paths: Vec<PathBuf>, job: &JobContext) -> FilesImported {
+  let (projects, media): (Vec<PathBuf>, Vec<PathBuf>) = paths.into_iter().partition(|p| is_project_file(p));
+  let mut files = Vec::new();
+
+  for (i, path) in projects.iter().enumerate() {
+    let result = if i == 0 { open_project(path) } else { Err(anyhow!("only one project can be opened at a time")) };
+    files.push(DroppedFile {
+      path: path.to_string_lossy().to_string(),
+      outcome: if result.is_ok() { DropOutcome::ProjectOpened } else { DropOutcome::Failed },
+      clip: None,
+      error: result.err().map(|e| e.to_string()),
+      warnings: Vec::new(),
+    });
+  }
+
+  let media = expand(media);
+  for (i, path) in media.iter().enumerate() {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    job.progress(i as f64 / media.len() as f64, Some(&format!("Importing {}", name)));
+    let imported = match job.is_cancelled() {
+      true => Err(anyhow!("import cancelled")),
+      false => import_file(path),
+    };
+    let (outcome, clip, error) = match imported {
+      Ok(clip) => (DropOutcome::Imported, Some(clip), None),
+      Err(e) => {
+        log::warn!("Dropped file {:?} was not imported: {}", path, e);
+        (DropOutcome::Failed, None, Some(e.to_string()))
+      }
+    };
+    let warnings = clip.as_ref().and_then(|c| c.latest_probe.as_ref()?.vfr_warning(&name)).into_iter().collect();
+    files.push(DroppedFile { path: path.to_string_lossy().to_string(), outcome, clip, error, warnings });
+  }
+
+  FilesImported { files, project: project_file::get_project().ok().flatten() }
+}
+
+/// Handle a drop on the window `label` as an "import_files" job (probing and hashing are slow,
+/// and hashing a large file can be cancelled) and emit the result to it. An opened project gets
+/// an editor window of its own
+pub fn handle_drop(app: AppHandle, label: String, paths: Vec<PathBuf>) {
+  let params = format!("{} dropped path(s)", paths.len());
+  let emitter = app.clone();
+  jobs::spawn_job(&app, "import_files", params, move |job| {
+    let imported = import_dropped(paths, job);
+    if let Err(e) = emitter.emit_to(label.as_str(), "files-imported", &imported) {
+      log::warn!("Failed to emit files-imported: {}", e);
+    }
+    for file in imported.files.iter().filter(|f| f.outcome == DropOutcome::ProjectOpened) {
+      if let Err(e) = editor_windows::open(&emitter, Some(file.path.as_str())) {
+        log::warn!("Failed to open an editor for {}: {}", file.path, e);
+      }
+    }
+    let count = imported.files.iter().filter(|f| f.outcome == DropOutcome::Imported).count();
+    Ok(serde_json::json!({ "imported": count }))
+  });
+}
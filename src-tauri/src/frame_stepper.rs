@@ -0,0 +1,305 @@
+//! Frame-accurate stepping through a clip for the preview player. Each stepper keeps an ffmpeg
+//! process decoding forward from the current frame into a pipe, so the next frame is usually
+//! already waiting, and a cache of recent frames for stepping back. Stepping back past the cache
+//! decodes a batch of earlier frames at once. Steppers are few, close themselves when idle and
+//! all close with the project.
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ffmpeg;
+use crate::project_file;
+
+/// Steppers open at once; opening another closes the least recently used
+const MAX_STEPPERS: usize = 4;
+/// Steppers unused for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// How often idle steppers are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(15);
+/// Decoded frames kept per stepper
+const CACHE_FRAMES: usize = 120;
+/// Frames decoded at once when stepping back past the cache
+const BACK_BATCH: u64 = 30;
+/// Frames are scaled down to this width unless asked otherwise
+const DEFAULT_WIDTH: u32 = 960;
+/// JPEG quality passed to ffmpeg's mjpeg encoder (2 best .. 31 worst)
+const JPEG_QUALITY: &str = "4";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepDirection {
+  Next,
+  Previous,
+}
+
+/// What `open` returns; frame indices run from 0 to `frame_count - 1`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepperInfo {
+  pub stepper_id: String,
+  pub clip_id: String,
+  pub fps: f64,
+  pub frame_count: u64,
+  pub frame: SteppedFrame, // The frame at the requested start time
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SteppedFrame {
+  pub index: u64,
+  pub time: f64, // Seconds into the clip: index / fps
+  pub jpeg: String, // Base64
+}
+
+/// An ffmpeg process writing frames `next_index..` of a clip as JPEGs to its stdout
+struct Decoder {
+  child: Child,
+  stdout: BufReader<ChildStdout>,
+  next_index: u64,
+}
+
+impl Decoder {
+  /// The next JPEG from the pipe, or None once ffmpeg has no more frames
+  fn read_frame(&mut self) -> Result<Option<Vec<u8>>> {
+    let mut frame = Vec::new();
+    loop {
+      // Entropy-coded data escapes 0xFF, so FF D9 only ever appears as the end-of-image marker
+      let read = self.stdout.read_until(0xD9, &mut frame).context("failed to read from ffmpeg")?;
+      if read == 0 {
+        return Ok(None);
+      }
+      if frame.len() >= 2 && frame[frame.len() - 2] == 0xFF {
+        self.next_index += 1;
+        return Ok(Some(frame));
+      }
+    }
+  }
+}
+
+impl Drop for Decoder {
+  fn drop(&mut self) {
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+  }
+}
+
+struct Stepper {
+  clip_id: String,
+  path: String,
+  fps: f64,
+  frame_count: u64,
+  width: u32,
+  color: String, // Preview LUT or tone-mapping, as for other previews
+  current: u64,
+  cache: BTreeMap<u64, Vec<u8>>,
+  decoder: Option<Decoder>,
+  last_used: Instant,
+}
+
+impl Stepper {
+  /// Start decoding at frame `index`
+  fn spawn_decoder(&self, index: u64) -> Result<Decoder> {
+    // Half a frame early so rounding never skips the frame asked for
+    let seek = ((index as f64 - 0.5) / self.fps).max(0.0);
+    let filter = format!("fps={},scale='min({},iw)':-2{}", self.fps, self.width, self.color);
+    let mut child = Command::new("ffmpeg")
+      .args(["-v", "error", "-ss", &seek.to_string(), "-i", &self.path])
+      .args(["-an", "-vf", &filter, "-f", "image2pipe", "-c:v", "mjpeg", "-q:v", JPEG_QUALITY, "-"])
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()
+      .with_context(|| "failed to spawn ffmpeg for frame stepping")?;
+    let stdout = child.stdout.take().ok_or_else(|| anyhow!("ffmpeg has no stdout"))?;
+    Ok(Decoder { child, stdout: BufReader::new(stdout), next_index: index })
+  }
+
+  /// Keep the cache within `CACHE_FRAMES` by dropping the frames farthest from the current one
+  fn trim_cache(&mut self) {
+    while self.cache.len() > CACHE_FRAMES {
+      let (first, last) = match (self.cache.keys().next(), self.cache.keys().next_back()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return,
+      };
+      let farthest = if self.current.abs_diff(first) > self.current.abs_diff(last) { first } else { last };
+      self.cache.remove(&farthest);
+    }
+  }
+
+  /// Read frames from the decoder into the cache up to and including `index`. Returns false if
+  /// the clip ended first, which also corrects `frame_count`
+  fn decode_through(&mut self, index: u64) -> Result<bool> {
+    let Some(decoder) = self.decoder.as_mut() else {
+      return Ok(false);
+    };
+    while decoder.next_index <= index {
+      let at = decoder.next_index;
+      match decoder.read_frame()? {
+        Some(jpeg) => {
+          self.cache.insert(at, jpeg);
+        }
+        None => {
+          self.decoder = None;
+          self.frame_count = self.frame_count.min(at);
+          return Ok(false);
+        }
+      }
+    }
+    Ok(true)
+  }
+
+  /// JPEG of frame `index`, from the cache or the decoder. Frames behind the decoder are
+  /// decoded in a batch ending at `index`, leaving the decoder ready to continue forward
+  fn frame(&mut self, index: u64) -> Result<Vec<u8>> {
+    if let Some(jpeg) = self.cache.get(&index) {
+      return Ok(jpeg.clone());
+    }
+    let reusable = self.decoder.as_ref().is_some_and(|d| d.next_index <= index && index - d.next_index < BACK_BATCH);
+    if !reusable {
+      let start = match self.current > index {
+        true => index.saturating_sub(BACK_BATCH - 1),
+        false => index,
+      };
+      self.decoder = Some(self.spawn_decoder(start)?);
+    }
+    self.decode_through(index)?;
+    self.trim_cache();
+    self.cache.get(&index).cloned().ok_or_else(|| anyhow!("frame {} is past the end of the clip", index))
+  }
+
+  /// Move to frame `index` (clamped to the clip) and return it
+  fn show(&mut self, index: u64) -> Result<SteppedFrame> {
+    self.last_used = Instant::now();
+    let index = index.min(self.frame_count.saturating_sub(1));
+    let jpeg = match self.frame(index) {
+      Ok(jpeg) => jpeg,
+      // The probe's frame count can overshoot by a frame or two; fall back to the real last one
+      Err(_) if self.frame_count > 0 && index >= self.frame_count => self.frame(self.frame_count - 1)?,
+      Err(e) => return Err(e),
+    };
+    let index = index.min(self.frame_count.saturating_sub(1));
+    self.current = index;
+    Ok(SteppedFrame {
+      index,
+      time: index as f64 / self.fps,
+      jpeg: base64::engine::general_purpose::STANDARD.encode(&jpeg),
+    })
+  }
+}
+
+type Steppers = Mutex<HashMap<String, Arc<Mutex<Stepper>>>>;
+
+fn steppers() -> MutexGuard<'static, HashMap<String, Arc<Mutex<Stepper>>>> {
+  static STEPPERS: OnceLock<Steppers> = OnceLock::new();
+  STEPPERS.get_or_init(|| {
+    start_sweeper();
+    Mutex::new(HashMap::new())
+  })
+  .lock()
+  .unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock_stepper(stepper: &Mutex<Stepper>) -> MutexGuard<'_, Stepper> {
+  stepper.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Close steppers that have been idle for `IDLE_TIMEOUT`, for as long as the app runs
+fn start_sweeper() {
+  thread::spawn(|| loop {
+    thread::sleep(SWEEP_INTERVAL);
+    let mut guard = steppers();
+    // A stepper busy decoding is in use, so only idle ones that can be locked are checked
+    guard.retain(|_, stepper| match stepper.try_lock() {
+      Ok(s) => s.last_used.elapsed() < IDLE_TIMEOUT,
+      Err(_) => true,
+    });
+  });
+}
+
+fn get(stepper_id: &str) -> Result<Arc<Mutex<Stepper>>> {
+  steppers().get(stepper_id).cloned().ok_or_else(|| anyhow!("frame stepper not found or closed: {}", stepper_id))
+}
+
+/// Open a stepper on video clip `clip_id` of the current project, positioned at the frame at
+/// `time` seconds (default 0). Frames are at most `max_width` pixels wide
+pub fn open(clip_id: &str, time: Option<f64>, max_width: Option<u32>) -> Result<StepperInfo> {
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+  let path = clip.path.to_string_lossy().to_string();
+  let probe = ffmpeg::ffprobe(&path).context("ffprobe failed")?;
+  if probe.width == 0 || probe.height == 0 || probe.fps <= 0.0 {
+    return Err(anyhow!("{} has no video to step through", path));
+  }
+
+  let mut stepper = Stepper {
+    clip_id: clip_id.to_string(),
+    color: ffmpeg::preview_color(&path),
+    path,
+    fps: probe.fps,
+    frame_count: ((probe.duration * probe.fps).round() as u64).max(1),
+    width: max_width.unwrap_or(DEFAULT_WIDTH).max(16),
+    current: 0,
+    cache: BTreeMap::new(),
+    decoder: None,
+    last_used: Instant::now(),
+  };
+  let start = (time.unwrap_or(0.0).max(0.0) * stepper.fps).round() as u64;
+  let frame = stepper.show(start)?;
+  let info = StepperInfo {
+    stepper_id: uuid::Uuid::new_v4().to_string(),
+    clip_id: stepper.clip_id.clone(),
+    fps: stepper.fps,
+    frame_count: stepper.frame_count,
+    frame,
+  };
+
+  let mut guard = steppers();
+  while guard.len() >= MAX_STEPPERS {
+    let oldest = guard
+      .iter()
+      .min_by_key(|(_, s)| lock_stepper(s).last_used)
+      .map(|(id, _)| id.clone());
+    let Some(oldest) = oldest else {
+      break;
+    };
+    log::info!("Closing frame stepper {} to stay within {} steppers", oldest, MAX_STEPPERS);
+    guard.remove(&oldest);
+  }
+  guard.insert(info.stepper_id.clone(), Arc::new(Mutex::new(stepper)));
+  Ok(info)
+}
+
+/// Step one frame forward or back. At either end of the clip the end frame is returned again
+pub fn step(stepper_id: &str, direction: StepDirection) -> Result<SteppedFrame> {
+  let stepper = get(stepper_id)?;
+  let mut stepper = lock_stepper(&stepper);
+  let index = match direction {
+    StepDirection::Next => stepper.current + 1,
+    StepDirection::Previous => stepper.current.saturating_sub(1),
+  };
+  stepper.show(index)
+}
+
+/// Jump to the frame at `time` seconds, e.g. after the player moved the playhead
+pub fn seek(stepper_id: &str, time: f64) -> Result<SteppedFrame> {
+  let stepper = get(stepper_id)?;
+  let mut stepper = lock_stepper(&stepper);
+  let index = (time.max(0.0) * stepper.fps).round() as u64;
+  stepper.show(index)
+}
+
+/// Close a stepper, stopping its ffmpeg process. Closing one that's already gone is fine
+pub fn close(stepper_id: &str) {
+  steppers().remove(stepper_id);
+}
+
+/// Close every stepper; the project they step through is going away
+pub fn close_all() {
+  steppers().clear();
+}
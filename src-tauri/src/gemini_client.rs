@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use futures_util::StreamExt;
+use crate::api_quota::{self, ApiPriority};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GeminiRequest {
@@ -53,6 +54,7 @@ pub struct GeminiClient {
     api_key: String,
     base_url: String,
     stream_base_url: String,
+    priority: ApiPriority,
 }
 
 impl GeminiClient {
@@ -61,9 +63,16 @@ impl GeminiClient {
             api_key,
             base_url: "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent".to_string(),
             stream_base_url: "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:streamGenerateContent".to_string(),
+            priority: ApiPriority::Interactive,
         }
     }
 
+    /// Queue this client's requests at `priority` instead of interactive
+    pub fn with_priority(mut self, priority: ApiPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     /// Test the API key and basic connectivity
     pub async fn test_api_key(&self) -> Result<String, String> {
         let test_prompt = "Respond with just the word 'success' to test the API connection.".to_string();
@@ -87,10 +96,7 @@ impl GeminiClient {
 
         let url = format!("{}?key={}", self.base_url, self.api_key);
         
-        let response = client
-            .post(&url)
-            .json(&request)
-            .send()
+        let response = api_quota::send(self.priority, "gemini", || client.post(&url).json(&request))
             .await
             .map_err(|e| format!("Failed to send request: {}", e))?;
 
@@ -137,10 +143,7 @@ impl GeminiClient {
 
         let url = format!("{}?key={}", self.stream_base_url, self.api_key);
         
-        let response = client
-            .post(&url)
-            .json(&request)
-            .send()
+        let response = api_quota::send(self.priority, "gemini (streaming)", || client.post(&url).json(&request))
             .await
             .map_err(|e| format!("Failed to send request: {}", e))?;
 
@@ -222,7 +225,9 @@ Please respond with ONLY a valid JSON object (no additional text, explanations,
       "target_clip_id": null,
       "target_track_id": null,
       "time_range": {{"start": 0.0, "end": 1.0}},
-      "preview_data": null
+      "preview_data": null,
+      "confidence": 0.9,
+      "rationale": "Why this edit is needed, in one sentence"
     }}
   ],
   "has_video_preview": true,
@@ -234,9 +239,10 @@ Please respond with ONLY a valid JSON object (no additional text, explanations,
   ]
 }}
 
-Action types can be: "accept", "reject", "upload_video", "confirm_proceed", "custom"
+Action types can be: "accept", "reject", "upload_video", "confirm_proceed", "export", "custom"
 - Use "upload_video" when user needs to add media but has none
 - Use "accept"/"reject" ONLY after changes have been applied and preview is shown
+- Use "export" when the user asks to export the project. Put the target file in "output" (omit it to use the project's export folder) and state that path in response_content
 
 IMPORTANT WORKFLOW:
 When user requests an edit operation:
@@ -270,6 +276,7 @@ Focus on video editing operations like:
 
 For thinking steps, use status values: "pending", "in_progress", "completed", "error"
 For edit operations, use operation_type values: "cut", "split", "merge", "trim", "add_transition", "add_effect", "add_text", "adjust_audio"
+For every edit operation, set "confidence" (0.0 to 1.0) to how sure you are the user wants that specific edit, and "rationale" to a short reason the user can check
 
 Respond with ONLY the JSON object, no other text."#,
             user_message,
@@ -327,7 +334,9 @@ Please respond with ONLY a valid JSON object (no additional text, explanations,
       "target_clip_id": null,
       "target_track_id": null,
       "time_range": {{"start": 0.0, "end": 1.0}},
-      "preview_data": null
+      "preview_data": null,
+      "confidence": 0.9,
+      "rationale": "Why this edit is needed, in one sentence"
     }}
   ],
   "has_video_preview": true,
@@ -339,9 +348,10 @@ Please respond with ONLY a valid JSON object (no additional text, explanations,
   ]
 }}
 
-Action types can be: "accept", "reject", "upload_video", "confirm_proceed", "custom"
+Action types can be: "accept", "reject", "upload_video", "confirm_proceed", "export", "custom"
 - Use "upload_video" when user needs to add media but has none
 - Use "accept"/"reject" ONLY after changes have been applied and preview is shown
+- Use "export" when the user asks to export the project. Put the target file in "output" (omit it to use the project's export folder) and state that path in response_content
 
 IMPORTANT WORKFLOW:
 When user requests an edit operation:
@@ -375,6 +385,7 @@ Focus on video editing operations like:
 
 For thinking steps, use status values: "pending", "in_progress", "completed", "error"
 For edit operations, use operation_type values: "cut", "split", "merge", "trim", "add_transition", "add_effect", "add_text", "adjust_audio"
+For every edit operation, set "confidence" (0.0 to 1.0) to how sure you are the user wants that specific edit, and "rationale" to a short reason the user can check
 
 Respond with ONLY the JSON object, no other text."#,
             user_message,
@@ -471,6 +482,10 @@ pub struct EditOperation {
     pub target_track_id: Option<String>,
     pub time_range: Option<TimeRange>,
     pub preview_data: Option<serde_json::Value>,
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    #[serde(default)]
+    pub rationale: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -483,5 +498,7 @@ pub struct TimeRange {
 pub struct Action {
     pub action_type: String,
     pub label: String,
+    #[serde(default)]
+    pub output: Option<String>,
 }
 
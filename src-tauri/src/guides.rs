@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use crate::ffmpeg::Probe;
+use crate::project_file::GuideSettings;
+
+/// Axis-aligned rectangle in pixels
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    /// Same center, each side scaled by `percent` / 100
+    fn inset(&self, percent: f64) -> Rect {
+        let scale = percent.clamp(0.0, 100.0) / 100.0;
+        let (width, height) = (self.width * scale, self.height * scale);
+        Rect {
+            x: self.x + (self.width - width) / 2.0,
+            y: self.y + (self.height - height) / 2.0,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AspectOverlay {
+    pub aspect: String, // As configured, e.g. "9:16"
+    pub rect: Rect,
+}
+
+/// Everything the preview canvas draws, in player pixels
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GuideRects {
+    pub video: Rect, // Where the frame sits inside the player (letterboxed)
+    pub action_safe: Option<Rect>,
+    pub title_safe: Option<Rect>,
+    pub aspect_overlays: Vec<AspectOverlay>,
+}
+
+/// Parse "16:9", "9/16", "2.39:1" or "1.85" into width / height
+pub fn parse_aspect(aspect: &str) -> Result<f64> {
+    let aspect_str = aspect.trim();
+    let ratio = match aspect_str.split_once([':', '/', 'x']) {
+        Some((w, h)) => {
+            let (w, h) = (w.trim().parse::<f64>()?, h.trim().parse::<f64>()?);
+            w / h
+        }
+        None => aspect_str.parse::<f64>()?,
+    };
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return Err(anyhow!("invalid aspect ratio '{}'", aspect));
+    }
+    Ok(ratio)
+}
+
+/// Largest rect of `aspect` (width / height) centered inside `frame`.
+/// Shared by the preview guides and the reframe crop so both agree on what is kept
+pub fn aspect_crop(frame: Rect, aspect: f64) -> Rect {
+    let frame_aspect = frame.width / frame.height;
+    let (width, height) = if aspect > frame_aspect {
+        (frame.width, frame.width / aspect)
+    } else {
+        (frame.height * aspect, frame.height)
+    };
+    Rect {
+        x: frame.x + (frame.width - width) / 2.0,
+        y: frame.y + (frame.height - height) / 2.0,
+        width,
+        height,
+    }
+}
+
+/// Frame placement inside a player of the given size, keeping the display aspect
+fn fit_frame(display: (f64, f64), player_width: f64, player_height: f64) -> Rect {
+    let frame = Rect { x: 0.0, y: 0.0, width: player_width, height: player_height };
+    if display.0 <= 0.0 || display.1 <= 0.0 {
+        return frame;
+    }
+    aspect_crop(frame, display.0 / display.1)
+}
+
+/// Guide rectangles for `probe`'s frame shown in a `player_width` x `player_height` player.
+/// Anamorphic and rotated sources are handled through the probe's display size
+pub fn guide_rects(probe: &Probe, settings: &GuideSettings, player_width: f64, player_height: f64) -> Result<GuideRects> {
+    if player_width <= 0.0 || player_height <= 0.0 {
+        return Err(anyhow!("player size must be positive"));
+    }
+    let video = fit_frame(probe.display_size(), player_width, player_height);
+
+    let aspect_overlays = settings.target_aspects.iter()
+        .map(|aspect| Ok(AspectOverlay {
+            aspect: aspect.clone(),
+            rect: aspect_crop(video, parse_aspect(aspect)?),
+        }))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(GuideRects {
+        video,
+        action_safe: settings.show_action_safe.then(|| video.inset(settings.action_safe_percent)),
+        title_safe: settings.show_title_safe.then(|| video.inset(settings.title_safe_percent)),
+        aspect_overlays,
+    })
+}
+
+/// ffmpeg filters that keep the same region `aspect_crop` shows in the preview. ffmpeg
+/// auto-rotates before filtering, and an anamorphic frame is first scaled to square pixels,
+/// so the crop is sized in display pixels exactly like the guide
+pub fn reframe_crop_filter(probe: &Probe, aspect: f64) -> String {
+    // Keep sizes even for yuv420p
+    let even = |v: f64| ((v / 2.0).floor() * 2.0).max(2.0) as u32;
+    let (display_w, display_h) = probe.display_size();
+    let anamorphic = (probe.sar - 1.0).abs() > 1e-6;
+    let (frame_w, frame_h) = match anamorphic {
+        true => (even(display_w.round()) as f64, even(display_h.round()) as f64),
+        false => (display_w, display_h),
+    };
+    let frame = Rect { x: 0.0, y: 0.0, width: frame_w, height: frame_h };
+    let crop = aspect_crop(frame, aspect);
+
+    let width = even(crop.width.min(frame_w));
+    let height = even(crop.height.min(frame_h));
+    let x = ((frame_w - width as f64) / 2.0).max(0.0).round() as u32;
+    let y = ((frame_h - height as f64) / 2.0).max(0.0).round() as u32;
+    let crop = format!("crop={}:{}:{}:{}", width, height, x, y);
+    match anamorphic {
+        true => format!("scale={}:{},setsar=1,{}", frame_w, frame_h, crop),
+        false => crop,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(width: u32, height: u32, sar: f64, rotation: i32) -> Probe {
+        serde_json::from_value(serde_json::json!({
+            "duration": 10.0, "width": width, "height": height, "fps": 25.0, "audio_rate": 48000, "audio_channels": 2,
+            "v_codec": "h264", "a_codec": "aac", "container": "mp4", "sar": sar, "rotation": rotation,
+        }))
+        .unwrap()
+    }
+
+    fn settings(target_aspects: &[&str]) -> GuideSettings {
+        serde_json::from_value(serde_json::json!({
+            "show_action_safe": true,
+            "show_title_safe": true,
+            "target_aspects": target_aspects,
+        }))
+        .unwrap()
+    }
+
+    fn assert_rect(actual: Rect, expected: (f64, f64, f64, f64)) {
+        let actual_tuple = (actual.x, actual.y, actual.width, actual.height);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+        assert!(
+            close(actual.x, expected.0) && close(actual.y, expected.1) && close(actual.width, expected.2) && close(actual.height, expected.3),
+            "{:?} != {:?}",
+            actual_tuple,
+            expected
+        );
+    }
+
+    #[test]
+    fn parses_aspect_notations() {
+        assert_eq!(parse_aspect("16:9").unwrap(), 16.0 / 9.0);
+        assert_eq!(parse_aspect(" 9/16 ").unwrap(), 9.0 / 16.0);
+        assert_eq!(parse_aspect("2.39:1").unwrap(), 2.39);
+        assert_eq!(parse_aspect("1.85").unwrap(), 1.85);
+        assert!(parse_aspect("16:0").is_err());
+        assert!(parse_aspect("wide").is_err());
+    }
+
+    #[test]
+    fn square_pixel_source_is_cropped_directly() {
+        assert_eq!(reframe_crop_filter(&probe(1920, 1080, 1.0, 0), 9.0 / 16.0), "crop=606:1080:657:0");
+        assert_eq!(reframe_crop_filter(&probe(1920, 1080, 1.0, 0), 1.0), "crop=1080:1080:420:0");
+    }
+
+    #[test]
+    fn anamorphic_source_is_squared_before_cropping() {
+        // HDV: 1440x1080 stored, shown 1920x1080
+        let hdv = probe(1440, 1080, 4.0 / 3.0, 0);
+        assert_eq!(reframe_crop_filter(&hdv, 1.0), "scale=1920:1080,setsar=1,crop=1080:1080:420:0");
+        let guides = guide_rects(&hdv, &settings(&["1:1"]), 1920.0, 1080.0).unwrap();
+        assert_rect(guides.video, (0.0, 0.0, 1920.0, 1080.0));
+        assert_rect(guides.aspect_overlays[0].rect, (420.0, 0.0, 1080.0, 1080.0));
+    }
+
+    #[test]
+    fn rotated_source_is_cropped_upright() {
+        // A phone video stored landscape and shown portrait
+        let phone = probe(1920, 1080, 1.0, 90);
+        assert_eq!(reframe_crop_filter(&phone, 1.0), "crop=1080:1080:0:420");
+        let guides = guide_rects(&phone, &settings(&["1:1"]), 1080.0, 1920.0).unwrap();
+        assert_rect(guides.aspect_overlays[0].rect, (0.0, 420.0, 1080.0, 1080.0));
+    }
+
+    #[test]
+    fn rotated_anamorphic_source_is_squared_in_its_shown_orientation() {
+        // 1440x1080 at 4:3 pixels, rotated: shown 1080 wide and 1920 tall
+        let source = probe(1440, 1080, 4.0 / 3.0, 270);
+        let (shown_w, shown_h) = source.display_size();
+        assert!((shown_w - 1080.0).abs() < 1e-6 && (shown_h - 1920.0).abs() < 1e-6);
+        assert_eq!(reframe_crop_filter(&source, 9.0 / 16.0), "scale=1080:1920,setsar=1,crop=1080:1920:0:0");
+        assert_eq!(reframe_crop_filter(&source, 1.0), "scale=1080:1920,setsar=1,crop=1080:1080:0:420");
+        // The guide in a player the size of the frame shows the same region
+        let guides = guide_rects(&source, &settings(&["1:1"]), 1080.0, 1920.0).unwrap();
+        assert_rect(guides.aspect_overlays[0].rect, (0.0, 420.0, 1080.0, 1080.0));
+    }
+
+    #[test]
+    fn safe_areas_are_centered_in_the_letterboxed_frame() {
+        // 4:3 video in a 16:9 player is pillarboxed
+        let guides = guide_rects(&probe(1440, 1080, 1.0, 0), &settings(&[]), 1920.0, 1080.0).unwrap();
+        assert_rect(guides.video, (240.0, 0.0, 1440.0, 1080.0));
+        assert_rect(guides.title_safe.unwrap(), (312.0, 54.0, 1296.0, 972.0));
+        assert_rect(guides.action_safe.unwrap(), (290.4, 37.8, 1339.2, 1004.4));
+        assert!(guide_rects(&probe(1440, 1080, 1.0, 0), &settings(&[]), 0.0, 1080.0).is_err());
+    }
+}
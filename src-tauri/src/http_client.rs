@@ -0,0 +1,187 @@
+//! HTTP for the transcription and analysis providers. Clients time out (connect and per read,
+//! from the settings) so a provider that stops answering fails the job instead of leaving it
+//! "uploading" forever, and every request can be raced against a job's cancellation token.
+//! Failures are told apart as `HttpError`, which `jobs::FailureKind` reports to the UI.
+
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use crate::longterm_storage;
+
+/// Connect timeout without an `http_connect_timeout_secs` setting
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest wait for the next bytes of a response without an `http_read_timeout_secs` setting.
+/// Providers take a while to answer long uploads, so this is generous
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Why a provider request failed
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum HttpError {
+    Timeout(String), // No connection or no data within the timeouts
+    Cancelled, // The job was cancelled
+    Provider(String), // The provider answered with an error status
+    Network(String), // Anything else on the way: DNS, refused connection, bad response body
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Timeout(message) => write!(f, "request timed out: {}", message),
+            HttpError::Cancelled => write!(f, "request cancelled"),
+            HttpError::Provider(message) | HttpError::Network(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            HttpError::Timeout(e.to_string())
+        } else {
+            HttpError::Network(e.to_string())
+        }
+    }
+}
+
+/// Connect and read timeouts from the settings
+pub fn timeouts() -> (Duration, Duration) {
+    let settings = longterm_storage::get_settings().ok();
+    let seconds = |value: Option<u64>, default: Duration| value.filter(|s| *s > 0).map(Duration::from_secs).unwrap_or(default);
+    (
+        seconds(settings.as_ref().and_then(|s| s.http_connect_timeout_secs), DEFAULT_CONNECT_TIMEOUT),
+        seconds(settings.as_ref().and_then(|s| s.http_read_timeout_secs), DEFAULT_READ_TIMEOUT),
+    )
+}
+
+/// A client with the given timeouts
+pub fn client_with_timeouts(connect: Duration, read: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(connect)
+        .read_timeout(read)
+        .build()
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to build an HTTP client with timeouts, using the defaults: {}", e);
+            reqwest::Client::new()
+        })
+}
+
+/// A client with the timeouts from the settings
+pub fn client() -> reqwest::Client {
+    let (connect, read) = timeouts();
+    client_with_timeouts(connect, read)
+}
+
+/// Run `request` (a send, or reading a body) unless `cancel` fires first; a fired token wins
+/// over a request that is also done. Timeouts and transport errors come back as `HttpError`
+pub async fn run<T, F>(cancel: Option<&CancellationToken>, request: F) -> Result<T, HttpError>
+where
+    F: Future<Output = reqwest::Result<T>>,
+{
+    let result = match cancel {
+        Some(token) => tokio::select! {
+            biased;
+            _ = token.cancelled() => return Err(HttpError::Cancelled),
+            result = request => result,
+        },
+        None => request.await,
+    };
+    result.map_err(HttpError::from)
+}
+
+/// Read `response`'s body as text unless `cancel` fires first. Error statuses become
+/// `HttpError::Provider` with the body, e.g. "OpenAI API error (401 Unauthorized): ..."
+pub async fn response_text(response: reqwest::Response, cancel: Option<&CancellationToken>, provider: &str) -> Result<String, HttpError> {
+    let status = response.status();
+    let body = run(cancel, response.text()).await?;
+    if !status.is_success() {
+        return Err(HttpError::Provider(format!("{} error ({}): {}", provider, status, body)));
+    }
+    Ok(body)
+}
+
+/// A local server at `path` that accepts connections and never answers, until the returned
+/// flag is set
+#[cfg(test)]
+pub fn silent_server(path: &str) -> (String, std::sync::Arc<std::sync::atomic::AtomicBool>) {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+    listener.set_nonblocking(true).unwrap();
+    let stop = std::sync::Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    std::thread::spawn(move || {
+        let mut held = Vec::new();
+        while !stopped.load(Ordering::Relaxed) {
+            if let Ok((stream, _)) = listener.accept() {
+                held.push(stream);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    });
+    (url, stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use std::time::Instant;
+
+    #[test]
+    fn a_silent_provider_times_out() {
+        let (url, stop) = silent_server("/");
+        let client = client_with_timeouts(Duration::from_secs(2), Duration::from_millis(500));
+
+        let result = tauri::async_runtime::block_on(run(None, client.get(&url).send()));
+        stop.store(true, Ordering::Relaxed);
+        assert!(matches!(result, Err(HttpError::Timeout(_))), "{:?}", result.err());
+    }
+
+    #[test]
+    fn cancelling_abandons_a_request_in_flight() {
+        let (url, stop) = silent_server("/");
+        let client = client_with_timeouts(Duration::from_secs(2), Duration::from_secs(60));
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                token.cancel();
+            })
+        };
+
+        let started = Instant::now();
+        let result = tauri::async_runtime::block_on(run(Some(&token), client.get(&url).send()));
+        canceller.join().unwrap();
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(result.err(), Some(HttpError::Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn a_fired_token_wins_over_a_ready_request() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = tauri::async_runtime::block_on(run(Some(&token), async { Ok(1) }));
+        assert_eq!(result, Err(HttpError::Cancelled));
+
+        let result = tauri::async_runtime::block_on(run(Some(&CancellationToken::new()), async { Ok(1) }));
+        assert_eq!(result, Ok(1));
+    }
+
+    #[test]
+    fn a_refused_connection_is_a_network_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let client = client_with_timeouts(Duration::from_secs(2), Duration::from_secs(2));
+
+        let result = tauri::async_runtime::block_on(run(None, client.get(&url).send()));
+        assert!(matches!(result, Err(HttpError::Network(_))), "{:?}", result.err());
+    }
+}
@@ -0,0 +1,15 @@
+//! Ids for project entities created in the backend: a kind prefix and a UUIDv7, e.g.
+//! "segment_01920c7a-5b3e-7c4d-9e2f-0a1b2c3d4e5f". v7 ids sort by creation time and
+//! don't repeat across processes.
+
+pub const CLIP: &str = "clip";
+pub const TRACK: &str = "track";
+pub const SEGMENT: &str = "segment";
+pub const MARKER: &str = "marker";
+pub const LIBRARY_ITEM: &str = "library";
+pub const PROFILE: &str = "profile";
+
+/// A fresh id for an entity of kind `prefix`
+pub fn new_id(prefix: &str) -> String {
+  format!("{}_{}", prefix, uuid::Uuid::now_v7())
+}
@@ -0,0 +1,380 @@
+//! Interval helpers on `(start, end)` ranges in seconds, shared by cut normalization, kept-range
+//! computation, the detectors that combine range lists, and mapping times through a cut list.
+//! Everything that turns cuts into kept ranges goes through here so edges agree to the sample.
+
+use crate::ffmpeg::Cut;
+use serde::{Deserialize, Serialize};
+
+/// Sort and merge ranges that overlap or are within `gap` of each other.
+/// Ranges with `end <= start` are dropped.
+pub fn merge(mut ranges: Vec<Cut>, gap: f64) -> Vec<Cut> {
+  ranges.retain(|(s, e)| e > s);
+  ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+  let mut merged: Vec<Cut> = Vec::with_capacity(ranges.len());
+  for (s, e) in ranges {
+    match merged.last_mut() {
+      Some((_, last_end)) if s <= *last_end + gap => *last_end = last_end.max(e),
+      _ => merged.push((s, e)),
+    }
+  }
+  merged
+}
+
+/// Swap reversed ranges, clamp them to [0, `duration`], drop any no longer than `min_len`,
+/// then merge those within `gap` of each other. NaN ranges are dropped.
+pub fn normalize(ranges: Vec<Cut>, duration: f64, min_len: f64, gap: f64) -> Vec<Cut> {
+  if duration <= 0.0 {
+    return vec![];
+  }
+  let ranges = ranges
+    .into_iter()
+    .filter(|(s, e)| !s.is_nan() && !e.is_nan())
+    .map(|(s, e)| (s.min(e).max(0.0), s.max(e).min(duration)))
+    .filter(|(s, e)| *e > *s + min_len)
+    .collect();
+  merge(ranges, gap)
+}
+
+/// Parts of `a` not covered by `b`, sorted and disjoint. Inputs need not be sorted or merged.
+pub fn subtract(a: &[Cut], b: &[Cut]) -> Vec<Cut> {
+  let b = merge(b.to_vec(), 0.0);
+  let mut out = Vec::new();
+  for (start, end) in merge(a.to_vec(), 0.0) {
+    let mut t = start;
+    for (s, e) in b.iter().filter(|(s, e)| *e > start && *s < end) {
+      if *s > t {
+        out.push((t, *s));
+      }
+      t = t.max(*e);
+    }
+    if end > t {
+      out.push((t, end));
+    }
+  }
+  out
+}
+
+/// Parts of [0, `duration`] not covered by `ranges`.
+pub fn complement(ranges: &[Cut], duration: f64) -> Vec<Cut> {
+  if duration <= 0.0 {
+    return vec![];
+  }
+  subtract(&[(0.0, duration)], ranges)
+}
+
+/// Ranges covered by both `a` and `b`. Inputs need not be sorted or merged.
+pub fn intersect(a: &[Cut], b: &[Cut]) -> Vec<Cut> {
+  let (a, b) = (merge(a.to_vec(), 0.0), merge(b.to_vec(), 0.0));
+  let (mut i, mut j) = (0, 0);
+  let mut out = Vec::new();
+  while i < a.len() && j < b.len() {
+    let start = a[i].0.max(b[j].0);
+    let end = a[i].1.min(b[j].1);
+    if end > start {
+      out.push((start, end));
+    }
+    // Advance whichever range ends first
+    if a[i].1 < b[j].1 {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+  out
+}
+
+/// How much of each silence `tighten` keeps, in seconds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TightenSettings {
+  #[serde(default = "default_pad_after_speech")]
+  pub pad_after_speech: f64, // Kept at the start of a silence, where speech stops ("leave 150ms")
+  #[serde(default)]
+  pub pad_before_speech: f64, // Kept at the end of a silence, before speech resumes
+  #[serde(default)]
+  pub min_silence: f64, // Shorter silences are left alone
+}
+
+fn default_pad_after_speech() -> f64 {
+  0.15
+}
+
+impl Default for TightenSettings {
+  fn default() -> Self {
+    Self { pad_after_speech: default_pad_after_speech(), pad_before_speech: 0.0, min_silence: 0.0 }
+  }
+}
+
+/// Cuts that shorten each silence to its padding: [start + pad_after_speech, end - pad_before_speech].
+/// Silences shorter than `min_silence`, or not longer than both pads, are kept whole.
+pub fn tighten(silences: &[Cut], settings: &TightenSettings) -> Vec<Cut> {
+  let after = settings.pad_after_speech.max(0.0);
+  let before = settings.pad_before_speech.max(0.0);
+  let cuts = merge(silences.to_vec(), 0.0)
+    .into_iter()
+    .filter(|(s, e)| e - s >= settings.min_silence)
+    .map(|(s, e)| (s + after, e - before))
+    .collect();
+  merge(cuts, 0.0)
+}
+
+/// True if any range in `ranges` overlaps `range`.
+pub fn overlaps_any(ranges: &[Cut], range: Cut) -> bool {
+  ranges.iter().any(|(s, e)| *s < range.1 && *e > range.0)
+}
+
+/// Source time → output time once `kept` ranges are played back to back, i.e. after a cut list
+/// is applied. Monotonic: a time inside a cut maps to the join that replaced it.
+pub struct Remap {
+  kept: Vec<Cut>,
+  offsets: Vec<f64>, // Output-time start of each kept range
+  pub duration: f64, // Output length
+}
+
+impl Remap {
+  /// `kept` need not be sorted or merged.
+  pub fn new(kept: Vec<Cut>) -> Self {
+    let kept = merge(kept, 0.0);
+    let mut offsets = Vec::with_capacity(kept.len());
+    let mut t = 0.0;
+    for (s, e) in &kept {
+      offsets.push(t);
+      t += e - s;
+    }
+    Self { kept, offsets, duration: t }
+  }
+
+  /// Output times where one kept range ends and the next begins.
+  pub fn joins(&self) -> Vec<f64> {
+    self.offsets.iter().skip(1).copied().collect()
+  }
+
+  pub fn to_output(&self, source: f64) -> f64 {
+    for ((s, e), offset) in self.kept.iter().zip(&self.offsets) {
+      if source < *s {
+        return *offset;
+      }
+      if source <= *e {
+        return offset + (source - s);
+      }
+    }
+    self.duration
+  }
+
+  /// Like `to_output`, but None for a time inside a cut (range ends count as kept).
+  pub fn to_output_kept(&self, source: f64) -> Option<f64> {
+    self.kept
+      .iter()
+      .zip(&self.offsets)
+      .find(|((s, e), _)| source >= *s && source <= *e)
+      .map(|((s, _), offset)| offset + (source - s))
+  }
+
+  /// Output time → source time. A join maps to the start of the range after it, so this
+  /// inverts `to_output` everywhere but the exact end of a kept range.
+  pub fn to_source(&self, output: f64) -> f64 {
+    for ((s, e), offset) in self.kept.iter().zip(&self.offsets) {
+      if output < offset + (e - s) {
+        return s + (output - offset).max(0.0);
+      }
+    }
+    self.kept.last().map_or(0.0, |(_, e)| *e)
+  }
+
+  /// Source ranges that make up output range [start, end]; pieces no longer than `min_len` are dropped.
+  pub fn source_ranges(&self, start: f64, end: f64, min_len: f64) -> Vec<Cut> {
+    self.kept
+      .iter()
+      .zip(&self.offsets)
+      .filter_map(|((s, e), offset)| {
+        let from = start.max(*offset);
+        let to = end.min(offset + (e - s));
+        (to > from + min_len).then(|| (s + (from - offset), s + (to - offset)))
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::rngs::StdRng;
+  use rand::{Rng, SeedableRng};
+
+  /// Up to 8 ranges on a quarter-second grid in [0, 20], some reversed, empty, touching or overlapping
+  fn random_ranges(rng: &mut StdRng) -> Vec<Cut> {
+    (0..rng.gen_range(0..8))
+      .map(|_| {
+        let start = rng.gen_range(0..80) as f64 * 0.25;
+        (start, start + rng.gen_range(-8..24) as f64 * 0.25)
+      })
+      .collect()
+  }
+
+  /// Random (a, b) pairs, the same on every run
+  fn random_pairs() -> Vec<(Vec<Cut>, Vec<Cut>)> {
+    let mut rng = StdRng::seed_from_u64(2232);
+    (0..500).map(|_| (random_ranges(&mut rng), random_ranges(&mut rng))).collect()
+  }
+
+  fn length(ranges: &[Cut]) -> f64 {
+    ranges.iter().map(|(s, e)| e - s).sum()
+  }
+
+  fn covers(ranges: &[Cut], t: f64) -> bool {
+    ranges.iter().any(|(s, e)| e > s && *s <= t && t <= *e)
+  }
+
+  fn assert_disjoint(ranges: &[Cut], gap: f64) {
+    assert!(ranges.iter().all(|(s, e)| e > s), "{:?}", ranges);
+    assert!(ranges.windows(2).all(|w| w[1].0 > w[0].1 + gap), "{:?}", ranges);
+  }
+
+  #[test]
+  fn merged_ranges_are_disjoint_and_cover_the_same_time() {
+    for (a, _) in random_pairs() {
+      let merged = merge(a.clone(), 0.0);
+      assert_disjoint(&merged, 0.0);
+      for step in -4..=120 {
+        let t = step as f64 * 0.125;
+        assert_eq!(covers(&a, t), covers(&merged, t), "{} in {:?} vs {:?}", t, a, merged);
+      }
+      assert_disjoint(&merge(a, 1.0), 1.0);
+    }
+  }
+
+  #[test]
+  fn normalized_ranges_fit_the_duration() {
+    for (a, _) in random_pairs() {
+      let normalized = normalize(a, 12.0, 0.5, 0.25);
+      assert_disjoint(&normalized, 0.25);
+      assert!(normalized.iter().all(|(s, e)| *s >= 0.0 && *e <= 12.0 && e - s > 0.5), "{:?}", normalized);
+    }
+  }
+
+  #[test]
+  fn subtraction_and_intersection_split_the_first_set() {
+    for (a, b) in random_pairs() {
+      let outside = subtract(&a, &b);
+      let inside = intersect(&a, &b);
+      assert_disjoint(&outside, 0.0);
+      assert_disjoint(&inside, 0.0);
+      assert!((length(&outside) + length(&inside) - length(&merge(a.clone(), 0.0))).abs() < 1e-9);
+      assert!(intersect(&outside, &b).is_empty(), "{:?} minus {:?} = {:?}", a, b, outside);
+      assert_eq!(inside, intersect(&b, &a));
+    }
+  }
+
+  #[test]
+  fn the_complement_covers_the_rest_of_the_duration() {
+    for (a, _) in random_pairs() {
+      let rest = complement(&a, 12.0);
+      assert_disjoint(&rest, 0.0);
+      assert!(intersect(&rest, &a).is_empty());
+      assert!((length(&rest) + length(&intersect(&a, &[(0.0, 12.0)])) - 12.0).abs() < 1e-9);
+    }
+  }
+
+  #[test]
+  fn remapping_never_runs_backwards() {
+    for (cuts, _) in random_pairs() {
+      let remap = Remap::new(complement(&cuts, 20.0));
+      let mut last = 0.0;
+      for step in 0..=160 {
+        let output = remap.to_output(step as f64 * 0.125);
+        assert!(output >= last && output <= remap.duration, "{:?}: {} after {}", cuts, output, last);
+        last = output;
+      }
+      assert_eq!(last, remap.duration);
+    }
+  }
+
+  /// Kept 0-2, 5-6 and 8-10 of a 10 s source: cuts 2-5 and 6-8
+  fn remap() -> Remap {
+    Remap::new(complement(&[(2.0, 5.0), (6.0, 8.0)], 10.0))
+  }
+
+  #[test]
+  fn intersections_merge_their_inputs_first() {
+    assert_eq!(intersect(&[(5.0, 8.0), (0.0, 3.0), (2.0, 4.0)], &[(1.0, 6.0)]), vec![(1.0, 4.0), (5.0, 6.0)]);
+    assert_eq!(intersect(&[(0.0, 10.0)], &[(2.0, 3.0), (4.0, 5.0), (9.0, 12.0)]), vec![(2.0, 3.0), (4.0, 5.0), (9.0, 10.0)]);
+    // Ranges that only touch share no time
+    assert!(intersect(&[(0.0, 1.0)], &[(1.0, 2.0)]).is_empty());
+    assert!(intersect(&[], &[(0.0, 1.0)]).is_empty());
+  }
+
+  #[test]
+  fn overlap_ignores_touching_ranges() {
+    let ranges = [(0.0, 1.0), (5.0, 6.0)];
+    assert!(overlaps_any(&ranges, (0.5, 2.0)));
+    assert!(overlaps_any(&ranges, (4.0, 7.0)));
+    assert!(!overlaps_any(&ranges, (1.0, 5.0)));
+  }
+
+  fn pads(after: f64, before: f64, min_silence: f64) -> TightenSettings {
+    TightenSettings { pad_after_speech: after, pad_before_speech: before, min_silence }
+  }
+
+  #[test]
+  fn tightening_cuts_the_excess_and_keeps_the_padding() {
+    // The old math cut [start, start + leave], i.e. the part meant to stay
+    let cuts = tighten(&[(10.0, 13.0)], &TightenSettings::default());
+    assert_eq!(cuts.len(), 1);
+    assert!((cuts[0].0 - 10.15).abs() < 1e-9 && cuts[0].1 == 13.0, "{:?}", cuts);
+
+    assert_eq!(tighten(&[(10.0, 13.0)], &pads(0.25, 0.5, 0.0)), vec![(10.25, 12.5)]);
+  }
+
+  #[test]
+  fn short_silences_are_left_alone() {
+    let settings = pads(0.25, 0.5, 1.0);
+    assert_eq!(tighten(&[(20.0, 20.5), (40.0, 41.0)], &settings), vec![(40.25, 40.5)]);
+    // Not longer than both pads together: nothing left to cut
+    assert!(tighten(&[(60.0, 60.75)], &pads(0.25, 0.5, 0.0)).is_empty());
+  }
+
+  #[test]
+  fn overlapping_silences_are_tightened_as_one() {
+    assert_eq!(tighten(&[(1.0, 4.0), (0.0, 2.0)], &pads(0.25, 0.0, 0.0)), vec![(0.25, 4.0)]);
+  }
+
+  #[test]
+  fn kept_times_survive_a_round_trip() {
+    let remap = remap();
+    assert_eq!(remap.duration, 5.0);
+    for step in 0..=100 {
+      let source = step as f64 * 0.1;
+      let Some(output) = remap.to_output_kept(source) else { continue };
+      assert_eq!(remap.to_output(source), output);
+      // The end of a kept range is also the start of the next one in output time
+      if remap.joins().contains(&output) {
+        continue;
+      }
+      assert!((remap.to_source(output) - source).abs() < 1e-9, "{} -> {} -> {}", source, output, remap.to_source(output));
+    }
+  }
+
+  #[test]
+  fn times_inside_cuts_have_no_output_time() {
+    let remap = remap();
+    assert_eq!(remap.to_output_kept(3.0), None);
+    assert_eq!(remap.to_output_kept(7.5), None);
+    assert_eq!(remap.to_output_kept(10.5), None);
+    assert_eq!(remap.to_output_kept(2.0), Some(2.0));
+    assert_eq!(remap.to_output_kept(5.0), Some(2.0));
+    assert_eq!(remap.to_output_kept(8.5), Some(3.5));
+    // to_output maps them to the join that replaced the cut
+    assert_eq!(remap.to_output(3.0), 2.0);
+    assert_eq!(remap.to_output(7.0), 3.0);
+  }
+
+  #[test]
+  fn output_times_map_back_into_kept_ranges() {
+    let remap = remap();
+    assert_eq!(remap.joins(), vec![2.0, 3.0]);
+    assert_eq!(remap.to_source(2.0), 5.0);
+    assert_eq!(remap.to_source(3.0), 8.0);
+    assert_eq!(remap.to_source(4.5), 9.5);
+    assert_eq!(remap.to_source(7.0), 10.0);
+    assert_eq!(remap.source_ranges(1.0, 4.0, 0.0), vec![(1.0, 2.0), (5.0, 6.0), (8.0, 9.0)]);
+  }
+}
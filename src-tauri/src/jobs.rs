@@ -0,0 +1,854 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio_util::sync::CancellationToken;
+use crate::http_client::HttpError;
+use crate::longterm_storage;
+use crate::post_export;
+use crate::webhook;
+
+/// Finished jobs kept in memory for the UI
+const MAX_FINISHED_JOBS: usize = 100;
+/// Records kept in the on-disk history, oldest dropped first
+const MAX_HISTORY_RECORDS: usize = 500;
+/// Lines kept from the end of a failure message; ffmpeg errors carry its whole stderr
+const ERROR_TAIL_LINES: usize = 40;
+/// How often a paused job checks whether it was resumed or cancelled
+const PAUSE_POLL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Queued, // Waiting for the running export to finish
+    Running,
+    Paused, // Stopped between units of work until resumed; see `pause_job`
+    Completed,
+    Failed,
+    Cancelled,
+    Interrupted, // Was running when the app exited; only found in the history
+}
+
+impl JobStatus {
+    /// Not finished yet
+    pub fn is_active(&self) -> bool {
+        matches!(self, JobStatus::Queued | JobStatus::Running | JobStatus::Paused)
+    }
+}
+
+/// What made a job fail, so the UI can offer a retry for timeouts and point at the provider
+/// for its errors
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Timeout, // A provider stopped answering; see `http_client`
+    Provider, // A provider answered with an error
+    Network,
+    Cancelled, // A provider request was abandoned because the job was cancelled
+    Other,
+}
+
+impl FailureKind {
+    /// Classify by the first `HttpError` in `error`'s chain
+    fn of(error: &anyhow::Error) -> FailureKind {
+        match error.chain().find_map(|cause| cause.downcast_ref::<HttpError>()) {
+            Some(HttpError::Timeout(_)) => FailureKind::Timeout,
+            Some(HttpError::Provider(_)) => FailureKind::Provider,
+            Some(HttpError::Network(_)) => FailureKind::Network,
+            Some(HttpError::Cancelled) => FailureKind::Cancelled,
+            None => FailureKind::Other,
+        }
+    }
+}
+
+/// How a job was started, so a queued or interrupted job can be started again after a restart
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobRequest {
+    pub command: String, // Command that spawned the job, e.g. "export_project"
+    pub args: serde_json::Value, // Its arguments, fully resolved
+    #[serde(default)]
+    pub project: Option<PathBuf>, // Project the job needs loaded, if any
+    #[serde(default)]
+    pub inputs: Vec<String>, // Media files the job reads
+}
+
+/// A long-running backend operation. Every change is emitted as "job-updated"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub kind: String, // e.g. "bake_segment"
+    #[serde(default)]
+    pub params: String, // Short summary of what the job was asked to do
+    pub status: JobStatus,
+    pub progress: f64, // 0.0 - 1.0
+    pub message: Option<String>, // Current step, for display
+    pub result: Option<serde_json::Value>, // Set when completed
+    pub error: Option<String>, // Set when failed; the tail of long (stderr) messages
+    #[serde(default)]
+    pub failure: Option<FailureKind>, // Set when failed
+    #[serde(default)]
+    pub outputs: Vec<String>, // Files written by the job
+    pub created_at: String, // ISO 8601
+    pub finished_at: Option<String>,
+    #[serde(default)]
+    pub request: Option<JobRequest>, // Set for jobs that can be resumed after a restart
+    #[serde(default)]
+    pub note: Option<String>, // What happened to the job across a restart
+    #[serde(default)]
+    pub pausable: bool, // Work runs in units and calls `JobContext::checkpoint` between them
+    #[serde(skip)]
+    cancel: Arc<AtomicBool>, // Set by `cancel_job`; work checks it through `JobContext::is_cancelled`
+    #[serde(skip)]
+    cancel_token: CancellationToken, // Fired by `cancel_job` along with `cancel`, for async work
+    #[serde(skip)]
+    pause: Arc<AtomicBool>, // Set by `pause_job`; honoured at the next `JobContext::checkpoint`
+}
+
+impl Job {
+    /// A running job with a fresh id
+    fn new(kind: &str, params: String, request: Option<JobRequest>) -> Self {
+        Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            params,
+            status: JobStatus::Running,
+            progress: 0.0,
+            message: None,
+            result: None,
+            error: None,
+            failure: None,
+            outputs: Vec::new(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            finished_at: None,
+            request,
+            note: None,
+            pausable: false,
+            cancel: Arc::new(AtomicBool::new(false)),
+            cancel_token: CancellationToken::new(),
+            pause: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+type Work = Box<dyn FnOnce(&JobContext) -> Result<serde_json::Value> + Send>;
+
+/// Exports waiting for the running one, in start order. Lock after `JOBS`
+static QUEUE: Mutex<VecDeque<(JobContext, Work)>> = Mutex::new(VecDeque::new());
+/// Ids of paused exports that were resumed while another export ran. They wait in
+/// `JobContext::checkpoint` and go ahead of `QUEUE`. Lock after `JOBS`
+static RESUMING: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// External processes (ffmpeg) started for a job, by job id, so cancelling can stop them
+/// mid-encode. Each is taken back out to be waited on once its output closes
+static PROCESSES: Mutex<Option<HashMap<String, Child>>> = Mutex::new(None);
+/// Ids whose process `kill_process` stopped, until their `with_job_id` scope ends
+static KILLED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Job the current thread works for; see `with_job_id`
+    static CURRENT_JOB: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Jobs of these kinds run one at a time; the rest wait as `Queued`. Parallel encodes only
+/// compete for the same cores and disk
+fn is_queued_kind(kind: &str) -> bool {
+    kind.starts_with("export")
+}
+
+/// Handed to the job's work function to report progress
+pub struct JobContext {
+    id: String,
+    app: AppHandle,
+    cancel: Arc<AtomicBool>,
+    cancel_token: CancellationToken,
+    pause: Arc<AtomicBool>,
+}
+
+impl JobContext {
+    /// Report progress (clamped to 0..1) and optionally a new status message
+    pub fn progress(&self, fraction: f64, message: Option<&str>) {
+        update_job(&self.app, &self.id, |job| {
+            job.progress = fraction.clamp(0.0, 1.0);
+            if let Some(message) = message {
+                job.message = Some(message.to_string());
+            }
+        });
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// True once the job was cancelled. Work should stop and return an error
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Fires when the job is cancelled. Race awaits against it (see `http_client::run`) so
+    /// a request in flight is dropped rather than waited out
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel_token.clone()
+    }
+
+    /// Record a file the job wrote, for the job history
+    pub fn add_output(&self, path: &str) {
+        update_job(&self.app, &self.id, |job| job.outputs.push(path.to_string()));
+    }
+
+    /// Declare that the work calls `checkpoint` between its units, so `pause_job` accepts it.
+    /// Call before the first unit
+    pub fn set_pausable(&self) {
+        update_job(&self.app, &self.id, |job| job.pausable = true);
+    }
+
+    /// Call between units of work: while the job is paused this waits here, and once it's
+    /// cancelled this fails. A paused export lets the next queued export run meanwhile, and
+    /// when resumed waits as `Queued` for that one to finish
+    pub fn checkpoint(&self) -> Result<()> {
+        if self.pause.load(Ordering::Relaxed) && !self.is_cancelled() {
+            update_job(&self.app, &self.id, |job| {
+                job.status = JobStatus::Paused;
+                job.message = Some("Paused".to_string());
+            });
+            if let Some(job) = get_job(&self.id) {
+                persist(&job);
+                if is_queued_kind(&job.kind) {
+                    start_next();
+                }
+            }
+            while self.pause.load(Ordering::Relaxed) && !self.is_cancelled() {
+                std::thread::sleep(PAUSE_POLL);
+            }
+            if !self.is_cancelled() {
+                self.rejoin_queue();
+            }
+        }
+        if self.is_cancelled() {
+            return Err(anyhow!("cancelled"));
+        }
+        Ok(())
+    }
+
+    /// Mark a resumed job running again. An export waits its turn if another export started
+    /// while it was paused; `start_next` hands it the slot before any export in `QUEUE`
+    fn rejoin_queue(&self) {
+        let record = {
+            let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+            let busy = jobs
+                .iter()
+                .any(|j| j.id != self.id && j.status == JobStatus::Running && is_queued_kind(&j.kind));
+            let Some(job) = jobs.iter_mut().find(|j| j.id == self.id) else {
+                return;
+            };
+            let waiting = busy && is_queued_kind(&job.kind);
+            if waiting {
+                job.status = JobStatus::Queued;
+                job.message = Some("Waiting for the running export".to_string());
+                RESUMING.lock().unwrap_or_else(|e| e.into_inner()).push_back(self.id.clone());
+            } else {
+                job.status = JobStatus::Running;
+                job.message = Some("Resumed".to_string());
+            }
+            job.clone()
+        };
+        persist(&record);
+        if let Err(e) = self.app.emit("job-updated", &record) {
+            log::warn!("Failed to emit job-updated: {}", e);
+        }
+        if record.status != JobStatus::Queued {
+            return;
+        }
+        // `start_next` marks it running once the other export is done
+        while !self.is_cancelled() && get_job(&self.id).is_some_and(|j| j.status == JobStatus::Queued) {
+            std::thread::sleep(PAUSE_POLL);
+        }
+        if let Some(job) = get_job(&self.id).filter(|j| j.status == JobStatus::Running) {
+            persist(&job);
+            if let Err(e) = self.app.emit("job-updated", &job) {
+                log::warn!("Failed to emit job-updated: {}", e);
+            }
+        }
+    }
+}
+
+/// On-disk record of every job, so failures can be inspected after a restart
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct JobHistory {
+    jobs: Vec<Job>, // Oldest first
+}
+
+fn history_path() -> Result<PathBuf> {
+    Ok(longterm_storage::get_lts_directory()?.join("jobs.json"))
+}
+
+fn load_history() -> Result<JobHistory> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(JobHistory::default());
+    }
+    let data = fs::read_to_string(&path).with_context(|| format!("Failed to read job history at {:?}", path))?;
+    serde_json::from_str(&data).with_context(|| "Failed to parse job history")
+}
+
+fn save_history(history: &JobHistory) -> Result<()> {
+    write_history(&history_path()?, history)
+}
+
+/// Write `history` to `path` through a temp file and rename, like a project save, so a crash
+/// mid-write never leaves a truncated history behind
+fn write_history(path: &Path, history: &JobHistory) -> Result<()> {
+    let data = serde_json::to_string_pretty(history).with_context(|| "Failed to serialize job history")?;
+    let temp = path.with_extension("json.tmp");
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(data.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to write job history at {:?}", temp));
+    }
+    fs::rename(&temp, path).with_context(|| format!("Failed to move job history into place at {:?}", path))
+}
+
+/// Serializes history read-modify-write cycles between job threads
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Insert or replace `job` in the history file. Failures are logged; the job itself goes on
+fn persist(job: &Job) {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let result = load_history().and_then(|mut history| {
+        match history.jobs.iter_mut().find(|j| j.id == job.id) {
+            Some(existing) => *existing = job.clone(),
+            None => history.jobs.push(job.clone()),
+        }
+        let excess = history.jobs.len().saturating_sub(MAX_HISTORY_RECORDS);
+        history.jobs.drain(..excess);
+        save_history(&history)
+    });
+    if let Err(e) = result {
+        log::warn!("Failed to persist job {}: {}", job.id, e);
+    }
+}
+
+/// Why `request` can no longer run, if its project or media are gone
+fn restore_problem(request: &JobRequest) -> Option<String> {
+    if let Some(project) = request.project.as_ref().filter(|p| !p.exists()) {
+        return Some(format!("project {:?} no longer exists", project));
+    }
+    request
+        .inputs
+        .iter()
+        .find(|input| !std::path::Path::new(input).exists())
+        .map(|input| format!("media {} no longer exists", input))
+}
+
+/// Settle jobs that were queued or running when the app last exited. Call once at startup.
+/// Running jobs become interrupted; with a request they stay pending and restart from scratch
+/// if resumed. Queued jobs with a request stay pending as they were. Pending jobs whose
+/// project or media are gone fail at restore with the reason
+pub fn recover_interrupted_jobs() -> Result<()> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut history = load_history()?;
+    let mut changed = false;
+    for job in history.jobs.iter_mut().filter(|j| j.status.is_active()) {
+        if let Some(problem) = job.request.as_ref().and_then(restore_problem) {
+            job.status = JobStatus::Failed;
+            job.error = Some(format!("failed at restore: {}", problem));
+            job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+            changed = true;
+            continue;
+        }
+        match (&job.status, &job.request) {
+            (JobStatus::Queued, Some(_)) => {}
+            (JobStatus::Queued, None) => {
+                job.status = JobStatus::Interrupted;
+                job.error = Some("the app exited before the job started".to_string());
+                changed = true;
+            }
+            (_, request) => {
+                job.status = JobStatus::Interrupted;
+                job.error = Some("the app exited while the job was running".to_string());
+                if request.is_some() {
+                    job.note = Some("interrupted mid-run; resuming restarts it from the beginning".to_string());
+                }
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        save_history(&history)?;
+    }
+    Ok(())
+}
+
+/// Jobs from earlier sessions that can be resumed: queued ones that never started and
+/// interrupted ones with a request. Oldest first
+pub fn get_pending_jobs() -> Result<Vec<Job>> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let history = load_history()?;
+    let live: Vec<String> = list_jobs().into_iter().map(|j| j.id).collect();
+    Ok(history
+        .jobs
+        .into_iter()
+        .filter(|j| j.request.is_some() && matches!(j.status, JobStatus::Queued | JobStatus::Interrupted))
+        .filter(|j| !live.contains(&j.id))
+        .collect())
+}
+
+/// The pending job `id` (see `get_pending_jobs`), checked to still be runnable
+pub fn get_pending_job(id: &str) -> Result<Job> {
+    let job = get_pending_jobs()?
+        .into_iter()
+        .find(|j| j.id == id)
+        .ok_or_else(|| anyhow::anyhow!("no pending job {}", id))?;
+    if let Some(problem) = job.request.as_ref().and_then(restore_problem) {
+        close_pending_job(id, JobStatus::Failed, &format!("failed at restore: {}", problem))?;
+        return Err(anyhow::anyhow!("cannot resume job {}: {}", id, problem));
+    }
+    Ok(job)
+}
+
+/// Take the pending job `id` off the pending list, recording `note` (e.g. "resumed as job X")
+pub fn close_pending_job(id: &str, status: JobStatus, note: &str) -> Result<()> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut history = load_history()?;
+    let job = history.jobs.iter_mut().find(|j| j.id == id).ok_or_else(|| anyhow::anyhow!("unknown job {}", id))?;
+    if status == JobStatus::Failed {
+        job.error = Some(note.to_string());
+    } else {
+        job.note = Some(note.to_string());
+    }
+    job.status = status;
+    job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+    save_history(&history)
+}
+
+/// Persisted jobs, newest first, optionally only of `kind`
+pub fn get_job_history(limit: Option<usize>, kind: Option<&str>) -> Result<Vec<Job>> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let history = load_history()?;
+    Ok(history.jobs
+        .into_iter()
+        .rev()
+        .filter(|j| kind.map_or(true, |kind| j.kind == kind))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect())
+}
+
+/// Remove every finished record from the history. Queued and running jobs stay so they can still finish
+pub fn clear_job_history() -> Result<()> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut history = load_history()?;
+    history.jobs.retain(|j| j.status.is_active());
+    save_history(&history)
+}
+
+/// Last `ERROR_TAIL_LINES` lines of an error message
+fn error_tail(message: &str) -> String {
+    let lines: Vec<&str> = message.lines().collect();
+    match lines.len().checked_sub(ERROR_TAIL_LINES) {
+        Some(skipped) if skipped > 0 => format!("… ({} lines omitted)\n{}", skipped, lines[skipped..].join("\n")),
+        _ => message.to_string(),
+    }
+}
+
+/// Apply `change` to a job and emit the updated job
+fn update_job(app: &AppHandle, id: &str, change: impl FnOnce(&mut Job)) {
+    let updated = {
+        let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        match jobs.iter_mut().find(|j| j.id == id) {
+            Some(job) => {
+                change(job);
+                job.clone()
+            }
+            None => return,
+        }
+    };
+    if let Err(e) = app.emit("job-updated", &updated) {
+        log::warn!("Failed to emit job-updated: {}", e);
+    }
+}
+
+/// Drop the oldest finished jobs past the limit. Queued and running jobs are always kept
+fn prune(jobs: &mut Vec<Job>) {
+    let finished = jobs.iter().filter(|j| !j.status.is_active()).count();
+    let mut excess = finished.saturating_sub(MAX_FINISHED_JOBS);
+    jobs.retain(|j| {
+        if excess > 0 && !j.status.is_active() {
+            excess -= 1;
+            return false;
+        }
+        true
+    });
+}
+
+/// Run `work` on a background thread as a tracked job and return its id immediately.
+/// `params` summarizes the request for the job history
+pub fn spawn_job<F>(app: &AppHandle, kind: &str, params: String, work: F) -> String
+where
+    F: FnOnce(&JobContext) -> Result<serde_json::Value> + Send + 'static,
+{
+    spawn_job_with_request(app, kind, params, None, work)
+}
+
+/// `spawn_job` for jobs that record their `request`, so they can be resumed after a restart.
+/// Exports wait as `Queued` while another export runs
+pub fn spawn_job_with_request<F>(app: &AppHandle, kind: &str, params: String, request: Option<JobRequest>, work: F) -> String
+where
+    F: FnOnce(&JobContext) -> Result<serde_json::Value> + Send + 'static,
+{
+    let mut job = Job::new(kind, params, request);
+    let id = job.id.clone();
+    let context = JobContext {
+        id: id.clone(),
+        app: app.clone(),
+        cancel: job.cancel.clone(),
+        cancel_token: job.cancel_token.clone(),
+        pause: job.pause.clone(),
+    };
+    let work: Work = Box::new(work);
+    let start_now = {
+        let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        // A paused export doesn't hold up the others
+        let busy = is_queued_kind(kind)
+            && jobs.iter().any(|j| matches!(j.status, JobStatus::Queued | JobStatus::Running) && is_queued_kind(&j.kind));
+        if busy {
+            job.status = JobStatus::Queued;
+        }
+        jobs.push(job.clone());
+        prune(&mut jobs);
+        // Queued while JOBS is held, so an export finishing meanwhile finds it in `start_next`
+        if busy {
+            QUEUE.lock().unwrap_or_else(|e| e.into_inner()).push_back((context, work));
+            None
+        } else {
+            Some((context, work))
+        }
+    };
+    persist(&job);
+    if let Err(e) = app.emit("job-updated", &job) {
+        log::warn!("Failed to emit job-updated: {}", e);
+    }
+
+    if let Some((context, work)) = start_now {
+        run(context, work);
+    }
+    id
+}
+
+/// Start the next queued export, if any and no other export is running
+fn start_next() {
+    let next = {
+        let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        if jobs.iter().any(|j| j.status == JobStatus::Running && is_queued_kind(&j.kind)) {
+            return;
+        }
+        // A resumed export is already under way, so it goes first; its thread sees the status
+        if let Some(id) = RESUMING.lock().unwrap_or_else(|e| e.into_inner()).pop_front() {
+            if let Some(job) = jobs.iter_mut().find(|j| j.id == id) {
+                job.status = JobStatus::Running;
+                job.message = Some("Resumed".to_string());
+            }
+            return;
+        }
+        let Some((context, work)) = QUEUE.lock().unwrap_or_else(|e| e.into_inner()).pop_front() else {
+            return;
+        };
+        if let Some(job) = jobs.iter_mut().find(|j| j.id == context.id) {
+            job.status = JobStatus::Running;
+        }
+        (context, work)
+    };
+    let (context, work) = next;
+    if let Some(job) = get_job(&context.id) {
+        persist(&job);
+        if let Err(e) = context.app.emit("job-updated", &job) {
+            log::warn!("Failed to emit job-updated: {}", e);
+        }
+    }
+    run(context, work);
+}
+
+fn run(context: JobContext, work: Work) {
+    std::thread::spawn(move || {
+        let outcome = with_job_id(&context.id, || work(&context));
+        update_job(&context.app, &context.id, |job| {
+            job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+            match outcome {
+                Ok(result) => {
+                    job.status = JobStatus::Completed;
+                    job.progress = 1.0;
+                    job.result = Some(result);
+                }
+                Err(_) if context.is_cancelled() => {
+                    job.status = JobStatus::Cancelled;
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(error_tail(&format!("{:#}", e)));
+                    job.failure = Some(FailureKind::of(&e));
+                }
+            }
+        });
+        if let Some(job) = get_job(&context.id) {
+            persist(&job);
+            webhook::job_finished(&job);
+            if is_queued_kind(&job.kind) {
+                start_next();
+            }
+            // Runs after the job is recorded as completed, so its failures can't fail the export
+            if job.status == JobStatus::Completed && job.kind.starts_with("export") {
+                post_export::spawn(&context.app, &job.kind, job.outputs);
+            }
+        }
+    });
+}
+
+/// Ask a running job to stop, killing the process it runs (see `output_cancellable`), or take
+/// a queued one off the queue. Returns false if the job is unknown or already finished
+pub fn cancel_job(id: &str) -> bool {
+    let running = {
+        let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(job) = jobs.iter_mut().find(|j| j.id == id && j.status.is_active()) else {
+            return false;
+        };
+        let mut resuming = RESUMING.lock().unwrap_or_else(|e| e.into_inner());
+        let was_resuming = resuming.iter().any(|r| r == id);
+        resuming.retain(|r| r != id);
+        drop(resuming);
+        // A resumed export waiting its turn still has a thread, which finishes the record
+        if matches!(job.status, JobStatus::Running | JobStatus::Paused) || was_resuming {
+            job.cancel.store(true, Ordering::Relaxed);
+            job.cancel_token.cancel();
+            Ok(())
+        } else {
+            job.status = JobStatus::Cancelled;
+            job.finished_at = Some(chrono::Utc::now().to_rfc3339());
+            let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+            let position = queue.iter().position(|(context, _)| context.id == id);
+            Err(position.and_then(|i| queue.remove(i)).map(|(context, _)| (job.clone(), context.app)))
+        }
+    };
+    // Killed after JOBS is released; a process started after this sees the flag and is killed at once
+    let dequeued = match running {
+        Ok(()) => {
+            kill_process(id);
+            return true;
+        }
+        Err(dequeued) => dequeued,
+    };
+    if let Some((job, app)) = dequeued {
+        persist(&job);
+        if let Err(e) = app.emit("job-updated", &job) {
+            log::warn!("Failed to emit job-updated: {}", e);
+        }
+    }
+    true
+}
+
+/// Ask the running job `id` to pause at its next checkpoint. The current unit of work (an
+/// ffmpeg run, say) finishes first. Jobs that run as a single step, and queued jobs, can't
+/// be paused
+pub fn pause_job(app: &AppHandle, id: &str) -> Result<()> {
+    {
+        let jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        let job = jobs.iter().find(|j| j.id == id && j.status.is_active()).ok_or_else(|| anyhow!("no active job {}", id))?;
+        if job.status == JobStatus::Queued {
+            return Err(anyhow!("job {} is waiting for another export; cancel it instead", id));
+        }
+        if !job.pausable {
+            return Err(anyhow!("{} jobs run as a single step and can't be paused; cancel instead", job.kind));
+        }
+        if job.status == JobStatus::Paused {
+            return Ok(());
+        }
+        job.pause.store(true, Ordering::Relaxed);
+    }
+    update_job(app, id, |job| job.message = Some("Pausing after the current step".to_string()));
+    Ok(())
+}
+
+/// Continue a paused job from its next unit of work, or call off a pause it hasn't reached yet
+pub fn resume_job(app: &AppHandle, id: &str) -> Result<()> {
+    let reached = {
+        let jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+        let job = jobs.iter().find(|j| j.id == id && j.status.is_active()).ok_or_else(|| anyhow!("no active job {}", id))?;
+        if !job.pause.swap(false, Ordering::Relaxed) {
+            return Err(anyhow!("job {} isn't paused", id));
+        }
+        job.status == JobStatus::Paused
+    };
+    // A job that reached its checkpoint marks itself running again when it wakes up
+    if !reached {
+        update_job(app, id, |job| job.message = None);
+    }
+    Ok(())
+}
+
+pub fn get_job(id: &str) -> Option<Job> {
+    let jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+    jobs.iter().find(|j| j.id == id).cloned()
+}
+
+/// All known jobs, oldest first
+pub fn list_jobs() -> Vec<Job> {
+    JOBS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Run `f` on behalf of job `id`: processes it starts through `output_cancellable` can be
+/// stopped with `kill_process(id)`. Jobs run their work this way already; commands that aren't
+/// jobs use it with an id chosen by the caller
+pub fn with_job_id<T>(id: &str, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT_JOB.with(|current| current.replace(Some(id.to_string())));
+    let result = f();
+    CURRENT_JOB.with(|current| *current.borrow_mut() = previous);
+    KILLED.lock().unwrap_or_else(|e| e.into_inner()).retain(|killed| killed != id);
+    result
+}
+
+fn job_cancelled(id: &str) -> bool {
+    JOBS.lock().unwrap_or_else(|e| e.into_inner()).iter().any(|j| j.id == id && j.cancel.load(Ordering::Relaxed))
+}
+
+/// Whether the job the current thread works for was cancelled or had its process killed, so
+/// a failed run shouldn't be retried
+pub fn current_job_cancelled() -> bool {
+    let Some(id) = CURRENT_JOB.with(|current| current.borrow().clone()) else {
+        return false;
+    };
+    job_cancelled(&id) || KILLED.lock().unwrap_or_else(|e| e.into_inner()).contains(&id)
+}
+
+/// Run `cmd` to completion like `Command::output`, registering the process under the current
+/// job (if any) so it can be killed. With `on_line`, stdout is handed over line by line instead
+/// of collected
+pub fn output_cancellable(cmd: &mut Command, mut on_line: Option<&mut dyn FnMut(&str)>) -> Result<Output> {
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| "failed to spawn process")?;
+    let stdout = child.stdout.take();
+    // Read stderr alongside stdout so neither pipe can fill up and stall the process
+    let stderr = child.stderr.take();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_end(&mut buffer);
+        }
+        buffer
+    });
+
+    let job_id = CURRENT_JOB.with(|current| current.borrow().clone());
+    let mut unregistered = None;
+    match &job_id {
+        Some(id) => {
+            let cancelled = job_cancelled(id);
+            if cancelled {
+                let _ = child.kill();
+            }
+            PROCESSES.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert_with(HashMap::new).insert(id.clone(), child);
+        }
+        None => unregistered = Some(child),
+    }
+
+    let mut collected = Vec::new();
+    if let Some(mut stdout) = stdout {
+        match on_line.as_mut() {
+            Some(on_line) => {
+                for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+                    on_line(&line);
+                }
+            }
+            None => {
+                let _ = stdout.read_to_end(&mut collected);
+            }
+        }
+    }
+
+    let registered = job_id.as_ref().and_then(|id| {
+        PROCESSES.lock().unwrap_or_else(|e| e.into_inner()).as_mut().and_then(|processes| processes.remove(id))
+    });
+    let mut child = unregistered.or(registered).context("process record was lost")?;
+    let status = child.wait().with_context(|| "failed to wait for process")?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+    Ok(Output { status, stdout: collected, stderr })
+}
+
+/// Kill the process job `id` is running, if any. Returns false if there was none
+pub fn kill_process(id: &str) -> bool {
+    let mut processes = PROCESSES.lock().unwrap_or_else(|e| e.into_inner());
+    match processes.as_mut().and_then(|processes| processes.get_mut(id)) {
+        Some(child) => {
+            if let Err(e) = child.kill() {
+                log::warn!("Failed to kill process of job {}: {}", id, e);
+            }
+            KILLED.lock().unwrap_or_else(|e| e.into_inner()).push(id.to_string());
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_are_classified_by_their_http_error() {
+        let failed = |error: HttpError| FailureKind::of(&Err::<(), _>(error).context("transcription failed").unwrap_err());
+        assert_eq!(failed(HttpError::Timeout("read".to_string())), FailureKind::Timeout);
+        assert_eq!(failed(HttpError::Provider("401".to_string())), FailureKind::Provider);
+        assert_eq!(failed(HttpError::Network("refused".to_string())), FailureKind::Network);
+        assert_eq!(failed(HttpError::Cancelled), FailureKind::Cancelled);
+        assert_eq!(FailureKind::of(&anyhow::anyhow!("ffmpeg failed")), FailureKind::Other);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cancelling_a_job_kills_its_process() {
+        let job = Job::new("test_sleep", String::new(), None);
+        let id = job.id.clone();
+        JOBS.lock().unwrap().push(job);
+        let worker = {
+            let id = id.clone();
+            std::thread::spawn(move || with_job_id(&id, || output_cancellable(Command::new("sleep").arg("30"), None)))
+        };
+        let pid = loop {
+            let pid = PROCESSES.lock().unwrap().as_ref().and_then(|processes| processes.get(&id)).map(Child::id);
+            if let Some(pid) = pid {
+                break pid;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert!(cancel_job(&id));
+        let output = worker.join().unwrap().unwrap();
+        assert!(!output.status.success());
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists(), "process {} still running", pid);
+        assert!(!PROCESSES.lock().unwrap().as_ref().is_some_and(|processes| processes.contains_key(&id)));
+        JOBS.lock().unwrap().retain(|j| j.id != id);
+    }
+
+    #[test]
+    fn history_is_replaced_whole() {
+        let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("jobs.json");
+        fs::write(&path, "{ \"jobs\": [ truncated").unwrap();
+        write_history(&path, &JobHistory::default()).unwrap();
+        let read: JobHistory = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(read.jobs.is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "temp file left behind");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,207 @@
+//! Numbers and times typed by users, who write decimals with a dot ("2.5") or a comma ("2,5")
+//! and may group thousands with the other one ("1.234,5", "1,234.5"). The separator is worked
+//! out from the token itself where it can be; only a single separator followed by exactly
+//! three digits ("1,234") is genuinely ambiguous, and that's where the locale hint decides.
+//! Times also accept "m:ss" and "h:mm:ss" with either decimal separator, and the "m.ss,f"
+//! clock style used where the dot separates minutes from seconds.
+
+use serde::{Serialize, Deserialize};
+
+/// Regex matching one number token; pass the match to `parse_number`
+pub const NUMBER_PATTERN: &str = r"\d+(?:[.,]\d+)*";
+/// Regex matching one time token; pass the match to `parse_time`
+pub const TIME_PATTERN: &str = r"\d+(?:[.,:]\d+)*";
+
+/// Which character a user's locale writes decimals with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DecimalSeparator {
+  Dot,
+  Comma,
+}
+
+impl DecimalSeparator {
+  fn char(self) -> char {
+    match self {
+      DecimalSeparator::Dot => '.',
+      DecimalSeparator::Comma => ',',
+    }
+  }
+}
+
+/// The decimal separator from the app settings, if the user set one
+pub fn locale_hint() -> Option<DecimalSeparator> {
+  crate::longterm_storage::get_settings().ok().and_then(|s| s.decimal_separator)
+}
+
+/// Whether the groups after the first are all three digits, as thousands groups are
+fn is_grouped(groups: &[&str]) -> bool {
+  groups.len() > 1 && !groups[0].is_empty() && groups[0].len() <= 3 && groups[1..].iter().all(|g| g.len() == 3)
+}
+
+/// Parse a number written with either decimal separator. With both separators present the
+/// last one is the decimal point; a separator that repeats is a thousands separator. A lone
+/// separator before exactly three digits follows `hint`, and is a decimal point without one
+/// (values typed into commands are seconds and percentages, rarely in the thousands)
+pub fn parse_number(token: &str, hint: Option<DecimalSeparator>) -> Option<f64> {
+  let token = token.trim();
+  if token.is_empty() || !token.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',') {
+    return None;
+  }
+  let dots = token.matches('.').count();
+  let commas = token.matches(',').count();
+  let (grouping, decimal) = match (dots, commas) {
+    (0, 0) => return token.parse().ok(),
+    (_, 0) | (0, _) => {
+      let separator = if dots > 0 { '.' } else { ',' };
+      let groups: Vec<&str> = token.split(separator).collect();
+      let ambiguous = groups.len() == 2 && groups[1].len() == 3;
+      let is_decimal = match (groups.len(), ambiguous, hint) {
+        (2, false, _) => true,
+        (2, true, Some(hint)) => hint.char() == separator,
+        (2, true, None) => true,
+        _ => false, // Repeated: "1.234.567"
+      };
+      if is_decimal {
+        (None, separator)
+      } else if is_grouped(&groups) {
+        return groups.concat().parse().ok();
+      } else {
+        return None;
+      }
+    }
+    _ => {
+      let last_dot = token.rfind('.')?;
+      let last_comma = token.rfind(',')?;
+      if last_dot > last_comma { (Some(','), '.') } else { (Some('.'), ',') }
+    }
+  };
+
+  let (whole, fraction) = token.rsplit_once(decimal)?;
+  if fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  let whole = match grouping {
+    Some(grouping) => {
+      let groups: Vec<&str> = whole.split(grouping).collect();
+      if groups.len() > 1 && !is_grouped(&groups) {
+        return None;
+      }
+      groups.concat()
+    }
+    None => whole.to_string(),
+  };
+  if whole.is_empty() || !whole.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  format!("{}.{}", whole, fraction).parse().ok()
+}
+
+/// Minutes and seconds of an "m.ss,f" clock time: a dot before exactly two digits of seconds,
+/// then a comma fraction, e.g. "1.05,5" (65.5 s)
+fn dotted_clock(token: &str) -> Option<f64> {
+  let (clock, fraction) = token.split_once(',')?;
+  let (minutes, seconds) = clock.split_once('.')?;
+  if seconds.len() != 2 || fraction.contains(['.', ',']) {
+    return None;
+  }
+  let minutes: f64 = minutes.parse().ok()?;
+  let seconds: f64 = format!("{}.{}", seconds, fraction).parse().ok()?;
+  (seconds < 60.0).then_some(minutes * 60.0 + seconds)
+}
+
+/// Seconds in a time token: "h:mm:ss", "m:ss" (either decimal separator in the seconds),
+/// "m.ss,f", or a plain number of seconds
+pub fn parse_time(token: &str, hint: Option<DecimalSeparator>) -> Option<f64> {
+  let token = token.trim();
+  if token.contains(':') {
+    let parts: Vec<&str> = token.split(':').collect();
+    if parts.len() > 3 {
+      return None;
+    }
+    let (last, units) = parts.split_last()?;
+    let seconds = parse_number(last, hint)?;
+    if seconds >= 60.0 {
+      return None;
+    }
+    let mut total = 0.0;
+    for unit in units {
+      let value: u32 = unit.parse().ok()?;
+      total = total * 60.0 + f64::from(value);
+    }
+    return Some(total * 60.0 + seconds);
+  }
+  dotted_clock(token).or_else(|| parse_number(token, hint))
+}
+
+/// Both ends of a typed time range. An end written as "m.ss,f" makes the other end's "m.ss"
+/// a clock time too, so "1.05,5 - 1.10" is 65.5 s to 70 s rather than to 1.1 s
+pub fn parse_time_range(start: &str, end: &str, hint: Option<DecimalSeparator>) -> Option<(f64, f64)> {
+  let as_clock = |token: &str| dotted_clock(&format!("{},0", token.trim()));
+  let (start, end) = match (dotted_clock(start.trim()), dotted_clock(end.trim())) {
+    (Some(start), None) => (start, as_clock(end).or_else(|| parse_time(end, hint))?),
+    (None, Some(end)) => (as_clock(start).or_else(|| parse_time(start, hint))?, end),
+    _ => (parse_time(start, hint)?, parse_time(end, hint)?),
+  };
+  Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use DecimalSeparator::{Comma, Dot};
+
+  #[test]
+  fn decimals_parse_with_either_separator() {
+    assert_eq!(parse_number("2,5", None), Some(2.5));
+    assert_eq!(parse_number("2.5", None), Some(2.5));
+    assert_eq!(parse_number("2,5", Some(Dot)), Some(2.5));
+    assert_eq!(parse_number("2.5", Some(Comma)), Some(2.5));
+    assert_eq!(parse_number("42", None), Some(42.0));
+  }
+
+  #[test]
+  fn the_last_of_two_separators_is_the_decimal_point() {
+    assert_eq!(parse_number("1.234,5", None), Some(1234.5));
+    assert_eq!(parse_number("1,234.5", None), Some(1234.5));
+    assert_eq!(parse_number("1.234.567,25", None), Some(1234567.25));
+    assert_eq!(parse_number("1.234.567", None), Some(1234567.0));
+    // Groups after the first must be thousands
+    assert_eq!(parse_number("1.23,5", None), None);
+    assert_eq!(parse_number("1,2,3", None), None);
+  }
+
+  #[test]
+  fn only_a_lone_separator_before_three_digits_follows_the_hint() {
+    assert_eq!(parse_number("1,234", None), Some(1.234));
+    assert_eq!(parse_number("1,234", Some(Comma)), Some(1.234));
+    assert_eq!(parse_number("1,234", Some(Dot)), Some(1234.0));
+    assert_eq!(parse_number("1.234", Some(Comma)), Some(1234.0));
+    assert_eq!(parse_number("1,23", Some(Dot)), Some(1.23));
+  }
+
+  #[test]
+  fn anything_else_is_not_a_number() {
+    for token in ["", " ", "2,", ",5", "2,5a", "-2", "2 5", "1..2"] {
+      assert_eq!(parse_number(token, None), None, "{:?}", token);
+    }
+  }
+
+  #[test]
+  fn times_take_clock_forms_with_either_separator() {
+    assert_eq!(parse_time("2,5", None), Some(2.5));
+    assert_eq!(parse_time("1:05,5", None), Some(65.5));
+    assert_eq!(parse_time("1:05.5", None), Some(65.5));
+    assert_eq!(parse_time("1:02:03,5", None), Some(3723.5));
+    assert_eq!(parse_time("1.05,5", None), Some(65.5));
+    assert_eq!(parse_time("1:60", None), None);
+    assert_eq!(parse_time("1:2:3:4", None), None);
+  }
+
+  #[test]
+  fn a_clock_end_makes_the_other_end_a_clock_too() {
+    assert_eq!(parse_time_range("1.05,5", "1.10", None), Some((65.5, 70.0)));
+    assert_eq!(parse_time_range("1.10", "1.20,5", None), Some((70.0, 80.5)));
+    assert_eq!(parse_time_range("2,5", "4.5", None), Some((2.5, 4.5)));
+  }
+}
@@ -2,11 +2,142 @@ use anyhow::{anyhow, Context, Result};
 use serde::{Serialize, Deserialize};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use crate::encode_profile::EncodeProfile;
+use crate::ffmpeg::UpscaleDecision;
+use crate::locale_numbers::DecimalSeparator;
+use crate::shortcuts;
 extern crate dirs;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct LTSFile {
-    pub recent_projects: Vec<String>
+    pub recent_projects: Vec<String>,
+    #[serde(default)]
+    pub settings: AppSettings,
+    #[serde(default)]
+    pub export_history: Vec<ExportRecord>, // Newest first
+}
+
+/// A finished export, kept so the UI can list past outputs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportRecord {
+    pub kind: String, // "cutlist" | "audiogram"
+    pub output: String,
+    pub sources: Vec<String>,
+    pub created_at: String, // ISO 8601
+    #[serde(default)]
+    pub upscale: Option<UpscaleDecision>, // Set when the export asked for more than its sources had
+    #[serde(default)]
+    pub encode_profile: Option<String>, // Id of the encode profile whose settings the export used
+}
+
+/// App-wide user settings, persisted in the LTS file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AppSettings {
+    #[serde(default)]
+    pub force_software_decode: bool, // Never pass -hwaccel to ffmpeg (troubleshooting)
+    #[serde(default)]
+    pub shortcuts: HashMap<String, String>, // Action id -> accelerator, see shortcuts.rs
+    #[serde(default)]
+    pub gemini_requests_per_minute: Option<u32>, // Shared quota for all Gemini calls, see api_quota.rs
+    #[serde(default)]
+    pub agent_review_threshold: Option<f64>, // Agent edits less confident than this need review (0..1)
+    #[serde(default)]
+    pub agent_max_cut_fraction: Option<f64>, // Agent proposals cutting more of the project than this ask first (0..1)
+    #[serde(default)]
+    pub ingest_sha256: bool, // Also record SHA-256 at import (slower), see media_hash.rs
+    #[serde(default)]
+    pub post_export: PostExportSettings,
+    #[serde(default)]
+    pub webhook: WebhookSettings,
+    #[serde(default)]
+    pub agent_metrics_disabled: bool, // Don't record agent request metrics, see agent_metrics.rs
+    #[serde(default)]
+    pub previews_dir: Option<PathBuf>, // Fallback in use because Downloads isn't writable; set at startup, see work_dirs.rs
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>, // Fallback in use for the thumbnail cache; set at startup, see work_dirs.rs
+    #[serde(default)]
+    pub library_dir: Option<PathBuf>, // Fallback in use for the media library; set at startup, see work_dirs.rs
+    #[serde(default)]
+    pub exports_dir: Option<PathBuf>, // Fallback in use for exports of projects without a folder; set at startup, see work_dirs.rs
+    #[serde(default)]
+    pub export_edits_sidecar: bool, // Write <output>.edits.json describing what each export removed, see export_sidecar.rs
+    #[serde(default)]
+    pub decimal_separator: Option<DecimalSeparator>, // How the user writes decimals; None: worked out from what they type, see locale_numbers.rs
+    #[serde(default)]
+    pub save_view_changes: bool, // Save the project when only the timeline layout changed; otherwise it's saved with the next edit
+    #[serde(default)]
+    pub thumbnail_cache_mb: Option<u64>, // Size cap of the thumbnail cache; None: 500 MB, see thumbnail_cache.rs
+    #[serde(default)]
+    pub http_connect_timeout_secs: Option<u64>, // Transcription and analysis requests; None: 30 s, see http_client.rs
+    #[serde(default)]
+    pub http_read_timeout_secs: Option<u64>, // Longest silence from a provider mid-request; None: 300 s, see http_client.rs
+    #[serde(default)]
+    pub encode_profile: Option<EncodeProfile>, // Team export settings to hold exports to, see encode_profile.rs
+}
+
+/// Endpoint told about finished jobs, see webhook.rs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WebhookSettings {
+    #[serde(default)]
+    pub url: Option<String>, // None or empty: no webhook
+    #[serde(default)]
+    pub secret: Option<String>, // Sent as the X-Gebo-Secret header
+}
+
+/// What happens when an export finishes, see post_export.rs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PostExportSettings {
+    #[serde(default = "default_notify")]
+    pub notify: bool, // System notification
+    #[serde(default)]
+    pub reveal: bool, // Show the output in the file manager
+    #[serde(default)]
+    pub copy_path: bool, // Copy the output path(s) to the clipboard
+    #[serde(default)]
+    pub allow_command: bool, // Opt-in for `command`; only set through set_post_export_command
+    #[serde(default)]
+    pub command: Option<String>, // Shell command run per output, "{output}" is replaced by the quoted path
+}
+
+fn default_notify() -> bool {
+    true
+}
+
+impl Default for PostExportSettings {
+    fn default() -> Self {
+        Self {
+            notify: default_notify(),
+            reveal: false,
+            copy_path: false,
+            allow_command: false,
+            command: None,
+        }
+    }
+}
+
+impl AppSettings {
+    /// These settings as the settings page sent them, with the fields it doesn't own taken from
+    /// `stored`. Those change through commands of their own (shortcuts, the post-export command,
+    /// metrics, the encode profile) or at startup (work directories), so the page's copy of
+    /// them can be stale and would undo the change
+    pub fn merged_into(self, stored: AppSettings) -> AppSettings {
+        AppSettings {
+            shortcuts: stored.shortcuts,
+            post_export: PostExportSettings {
+                allow_command: stored.post_export.allow_command,
+                command: stored.post_export.command,
+                ..self.post_export
+            },
+            agent_metrics_disabled: stored.agent_metrics_disabled,
+            previews_dir: stored.previews_dir,
+            cache_dir: stored.cache_dir,
+            library_dir: stored.library_dir,
+            exports_dir: stored.exports_dir,
+            encode_profile: stored.encode_profile,
+            ..self
+        }
+    }
 }
 
 impl LTSFile {
@@ -21,7 +152,7 @@ impl LTSFile {
 
         // If the file doesn't exist, return an empty LTSFile
         if !lts_file_path.exists() {
-            return Ok(LTSFile { recent_projects: Vec::new() });
+            return Ok(LTSFile::default());
         }
 
         let data = fs::read_to_string(&lts_file_path)
@@ -104,4 +235,158 @@ pub fn get_recent_projects() -> Result<Vec<String>> {
     }
 
     Ok(valid_projects)
-}
\ No newline at end of file
+}
+
+// Settings component of LTSFile
+
+pub fn get_settings() -> Result<AppSettings> {
+    let mut settings = LTSFile::get()?.settings;
+    settings.shortcuts = shortcuts::with_defaults(&settings.shortcuts);
+    Ok(settings)
+}
+
+pub fn set_settings(settings: AppSettings) -> Result<()> {
+    let mut lts_file = LTSFile::get()?;
+    lts_file.settings = settings;
+    lts_file.save()
+}
+
+// Credentials, kept out of the LTS file so settings reads and events never carry them
+
+/// Secrets stored in credentials.json next to the LTS file, readable only by the user
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+struct Credentials {
+    #[serde(default)]
+    gemini_api_key: Option<String>,
+}
+
+fn credentials_path() -> Result<PathBuf> {
+    Ok(get_lts_directory()?.join("credentials.json"))
+}
+
+fn read_credentials() -> Result<Credentials> {
+    let path = credentials_path()?;
+    if !path.exists() {
+        return Ok(Credentials::default());
+    }
+    let data = fs::read_to_string(&path).with_context(|| format!("Failed to read credentials at {:?}", path))?;
+    serde_json::from_str(&data).with_context(|| "Failed to parse credentials JSON data")
+}
+
+fn write_credentials(credentials: &Credentials) -> Result<()> {
+    use std::io::Write;
+    let path = credentials_path()?;
+    let data = serde_json::to_string_pretty(credentials).with_context(|| "Failed to serialize credentials")?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies when the file is created
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to restrict permissions of {:?}", path))?;
+        }
+    }
+    let mut file = options.open(&path).with_context(|| format!("Failed to open credentials at {:?}", path))?;
+    file.write_all(data.as_bytes()).with_context(|| format!("Failed to write credentials at {:?}", path))
+}
+
+pub fn get_gemini_api_key() -> Result<Option<String>> {
+    Ok(read_credentials()?.gemini_api_key)
+}
+
+/// Store the Gemini API key; None removes it
+pub fn set_gemini_api_key(api_key: Option<String>) -> Result<()> {
+    let mut credentials = read_credentials()?;
+    credentials.gemini_api_key = api_key;
+    write_credentials(&credentials)
+}
+
+// Export history component of LTSFile
+
+/// Maximum number of export records kept
+const MAX_EXPORT_HISTORY: usize = 200;
+
+pub fn add_export_record(kind: &str, output: &str, sources: Vec<String>) -> Result<()> {
+    add_export_record_with_upscale(kind, output, sources, None, None)
+}
+
+/// `add_export_record` for an export that clamped or upscaled (see ExportSettings::upscale_check),
+/// or was made with the settings of encode profile `encode_profile`
+pub fn add_export_record_with_upscale(
+    kind: &str,
+    output: &str,
+    sources: Vec<String>,
+    upscale: Option<UpscaleDecision>,
+    encode_profile: Option<String>,
+) -> Result<()> {
+    let mut lts_file = LTSFile::get()?;
+
+    // Re-exporting to the same path replaces the older record
+    lts_file.export_history.retain(|r| r.output != output);
+    lts_file.export_history.insert(0, ExportRecord {
+        kind: kind.to_string(),
+        output: output.to_string(),
+        sources,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        upscale,
+        encode_profile,
+    });
+    lts_file.export_history.truncate(MAX_EXPORT_HISTORY);
+
+    lts_file.save()
+}
+
+pub fn get_export_history() -> Result<Vec<ExportRecord>> {
+    Ok(LTSFile::get()?.export_history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn a_stale_settings_page_only_writes_its_own_fields() {
+        let stale = AppSettings::default();
+        let mut stored = AppSettings::default();
+        stored.shortcuts.insert("mark_in".to_string(), "J".to_string());
+        stored.post_export.allow_command = true;
+        stored.post_export.command = Some("echo {output}".to_string());
+        stored.agent_metrics_disabled = true;
+        stored.cache_dir = Some(PathBuf::from("/tmp/gebo-cache"));
+
+        let mut sent = stale.clone();
+        sent.ingest_sha256 = true;
+        sent.post_export.reveal = true;
+        sent.http_read_timeout_secs = Some(60);
+        let merged = sent.clone().merged_into(stored.clone());
+
+        assert!(merged.ingest_sha256);
+        assert!(merged.post_export.reveal);
+        assert_eq!(merged.http_read_timeout_secs, Some(60));
+        assert_eq!(merged.shortcuts, stored.shortcuts);
+        assert!(merged.post_export.allow_command);
+        assert_eq!(merged.post_export.command, stored.post_export.command);
+        assert!(merged.agent_metrics_disabled);
+        assert_eq!(merged.cache_dir, stored.cache_dir);
+    }
+
+    /// API keys belong in credentials.json (see set_gemini_api_key), never in the source
+    #[test]
+    fn no_google_api_keys_in_the_source() {
+        // Split so this file doesn't match its own pattern
+        let pattern = regex::Regex::new(&format!("{}{}[0-9A-Za-z_-]{{35}}", "AI", "za")).unwrap();
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "rs") {
+                let text = fs::read_to_string(&path).unwrap();
+                assert!(!pattern.is_match(&text), "{:?} contains what looks like a Google API key", path);
+            }
+        }
+    }
+}
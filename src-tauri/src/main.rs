@@ -9,6 +9,54 @@ mod gemini_client;
 mod transcription;
 mod video_analysis;
 mod streaming_encoder;
+mod stream_prefetch;
+mod export_sidecar;
+mod sentence_snap;
+mod timeline_overview;
+mod shortcuts;
+mod audiogram;
+mod guides;
+mod jobs;
+mod bake;
+mod media_library;
+mod api_quota;
+mod export_parts;
+mod intervals;
+mod dead_air;
+mod media_project;
+mod media_protocol;
+mod media_hash;
+mod thumbnail_cache;
+mod file_drop;
+mod cli;
+mod ids;
+mod post_export;
+mod clip_details;
+mod webhook;
+mod audio_effects;
+mod agent_metrics;
+mod conform;
+mod clip_cache;
+mod engagement;
+mod work_dirs;
+mod edit_script;
+mod editor_windows;
+mod self_test;
+mod time_stretch;
+mod clip_prep;
+mod frame_stepper;
+mod locale_numbers;
+mod captions;
+mod sample_project;
+mod project_lock;
+mod chapters;
+mod http_client;
+mod montage;
+mod waveform_cache;
+mod spectrogram;
+mod exit_guard;
+mod encode_profile;
+mod retention;
 
 use crate::transcription::transcribe_media_file;
 use crate::video_analysis::analyze_video_file;
@@ -18,29 +66,696 @@ fn probe_video(path: String) -> Result<ffmpeg::Probe, String> {
   ffmpeg::ffprobe(&path).map_err(|e| e.to_string())
 }
 
+/// Every stream, chapter and container tag of a file, e.g. to pick among audio languages
 #[tauri::command]
-fn audio_peaks(path: String) -> Result<Vec<i16>, String> {
-  waveform::pcm_peaks(&path).map_err(|e| e.to_string())
+fn ffprobe_full(path: String) -> Result<ffmpeg::ProbeFull, String> {
+  ffmpeg::ffprobe_full(&path).map_err(|e| e.to_string())
 }
 
+/// Waveform peaks of `path` (see waveform::pcm_peaks), or one array per channel with
+/// `channels`, from the waveform cache when the file hasn't changed. Without `audio_stream`,
+/// the clip's chosen stream or the file's default one (see ffmpeg::default_audio_stream). Decodes off the command
+/// thread, sending "waveform-progress" to the calling window; closing the window stops the decode
 #[tauri::command]
-fn export_cutlist(input: String, output: String, ranges_to_cut: Vec<(f64, f64)>) -> Result<(), String> {
-  ffmpeg::export_with_cuts(&input, &output, &ranges_to_cut).map_err(|e| e.to_string())
+async fn audio_peaks(
+  window: tauri::Window,
+  path: String,
+  audio_stream: Option<usize>,
+  channels: Option<bool>,
+) -> Result<waveform::Peaks, waveform::WaveformError> {
+  tokio::task::spawn_blocking(move || {
+    let decode = waveform::WindowDecode::new(window.label());
+    let mut last_percent = -1.0;
+    let mut on_progress = |fraction: f64| {
+      let percent = (fraction * 100.0).floor();
+      if percent <= last_percent {
+        return;
+      }
+      last_percent = percent;
+      let progress = waveform::WaveformProgress { path: path.clone(), percent };
+      if let Err(e) = window.emit_to(window.label(), "waveform-progress", &progress) {
+        log::warn!("Failed to emit waveform-progress: {}", e);
+      }
+    };
+    let audio_stream = audio_stream.unwrap_or_else(|| ffmpeg::default_audio_stream(&path));
+    let channels = channels.unwrap_or(false);
+    let peaks = waveform_cache::get_or_compute(&path, audio_stream, channels, || match channels {
+      true => waveform::pcm_channel_peaks_with_progress(&path, audio_stream, &mut on_progress, Some(decode.cancel_flag())),
+      false => waveform::pcm_peaks_with_progress(&path, audio_stream, &mut on_progress, Some(decode.cancel_flag()))
+        .map(|peaks| waveform::ChannelPeaks { channels: 1, peaks: vec![peaks] }),
+    })?;
+    Ok(match channels {
+      true => waveform::Peaks::Channels(peaks),
+      false => waveform::Peaks::Mixed(peaks.peaks.into_iter().next().unwrap_or_default()),
+    })
+  })
+  .await
+  .map_err(|e| waveform::WaveformError::Failed(e.to_string()))?
+  .map_err(waveform::WaveformError::from)
 }
 
+/// (min, max) peak pairs of `path` between `start` and `end` seconds (the whole file when
+/// omitted), one pair per `samples_per_peak` samples at 8 kHz; for zooming into the waveform.
+/// With `rms`, the RMS level of each pair's samples too (see waveform::PeaksWithRms)
 #[tauri::command]
-fn make_preview_proxy(input: String) -> Result<String, String> {
-  ffmpeg::make_preview_proxy(&input, Some(960)).map_err(|e| e.to_string())
+async fn audio_peaks_ex(
+  path: String,
+  samples_per_peak: usize,
+  start: Option<f64>,
+  end: Option<f64>,
+  audio_stream: Option<usize>,
+  rms: Option<bool>,
+) -> Result<waveform::PeakWindows, waveform::WaveformError> {
+  tokio::task::spawn_blocking(move || {
+    let audio_stream = audio_stream.unwrap_or_else(|| ffmpeg::default_audio_stream(&path));
+    match rms.unwrap_or(false) {
+      true => waveform::pcm_peaks_with_rms(&path, audio_stream, samples_per_peak, start, end).map(waveform::PeakWindows::WithRms),
+      false => waveform::pcm_peaks_ex(&path, audio_stream, samples_per_peak, start, end).map(waveform::PeakWindows::Peaks),
+    }
+  })
+  .await
+  .map_err(|e| waveform::WaveformError::Failed(e.to_string()))?
+  .map_err(waveform::WaveformError::from)
 }
 
+/// Bands × time buckets of `range` (clip seconds) of a clip's audio, for a heatmap; see
+/// spectrogram::spectrogram
 #[tauri::command]
-fn read_file_as_base64(path: String) -> Result<String, String> {
-  use std::fs;
-  use base64::Engine;
-  
-  let data = fs::read(&path).map_err(|e| e.to_string())?;
-  let encoded = base64::engine::general_purpose::STANDARD.encode(&data);
-  Ok(encoded)
+async fn get_spectrogram(clip_id: String, range: (f64, f64), bands: usize, resolution: usize) -> Result<spectrogram::Spectrogram, String> {
+  tokio::task::spawn_blocking(move || {
+    let (path, audio_stream, _) = spectrogram::clip_source(&clip_id)?;
+    spectrogram::spectrogram(&path, audio_stream, range, bands, resolution)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+  .map_err(|e| format!("{:#}", e))
+}
+
+/// Constant tones (hum, whine) in `range` of a clip and the track effects removing them
+#[tauri::command]
+async fn suggest_denoise_filter(clip_id: String, range: (f64, f64)) -> Result<spectrogram::DenoiseSuggestion, String> {
+  tokio::task::spawn_blocking(move || {
+    let (path, audio_stream, track_id) = spectrogram::clip_source(&clip_id)?;
+    spectrogram::suggest_denoise(&path, audio_stream, range, track_id)
+  })
+  .await
+  .map_err(|e| e.to_string())?
+  .map_err(|e| format!("{:#}", e))
+}
+
+/// Level of `path` in dBFS per `window_ms` (10 ms to 5 s), see waveform::loudness_profile
+#[tauri::command]
+async fn loudness_profile(path: String, window_ms: u32, audio_stream: Option<usize>) -> Result<Vec<f32>, waveform::WaveformError> {
+  tokio::task::spawn_blocking(move || {
+    let audio_stream = audio_stream.unwrap_or_else(|| ffmpeg::default_audio_stream(&path));
+    waveform::loudness_profile(&path, audio_stream, window_ms)
+  })
+  .await
+  .map_err(|e| waveform::WaveformError::Failed(e.to_string()))?
+  .map_err(waveform::WaveformError::from)
+}
+
+#[tauri::command]
+async fn audio_waveform(path: String, audio_stream: Option<usize>) -> Result<waveform::WaveformData, waveform::WaveformError> {
+  tokio::task::spawn_blocking(move || {
+    let audio_stream = audio_stream.unwrap_or_else(|| ffmpeg::default_audio_stream(&path));
+    waveform::pcm_peaks_with_clipping(&path, audio_stream)
+  })
+  .await
+  .map_err(|e| waveform::WaveformError::Failed(e.to_string()))?
+  .map_err(waveform::WaveformError::from)
+}
+
+#[tauri::command]
+fn get_clip_details(clip_id: String) -> Result<clip_details::ClipDetails, String> {
+  clip_details::get_clip_details(&clip_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn find_clipping(clip_id: String) -> Result<waveform::ClippingReport, String> {
+  tokio::task::spawn_blocking(move || {
+    let clip = project_file::get_clip(&clip_id)?;
+    waveform::find_clipping(&clip_id, &clip.path.to_string_lossy())
+  })
+  .await
+  .map_err(|e| e.to_string())?
+  .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn detect_dead_air(
+  clip_id: String,
+  min_duration: f64,
+  mode: Option<dead_air::DeadAirMode>,
+) -> Result<Vec<dead_air::DeadAirRange>, String> {
+  tokio::task::spawn_blocking(move || {
+    let clip = project_file::get_clip(&clip_id)?;
+    dead_air::detect_dead_air(&clip.path.to_string_lossy(), min_duration, mode.unwrap_or_default())
+  })
+  .await
+  .map_err(|e| e.to_string())?
+  .map_err(|e| e.to_string())
+}
+
+/// Silences of at least `min_duration` seconds below `noise_db` in `path`; see ffmpeg::detect_silence
+#[tauri::command]
+async fn detect_silence(path: String, min_duration: f64, noise_db: f64) -> Result<Vec<(f64, f64)>, String> {
+  tokio::task::spawn_blocking(move || ffmpeg::detect_silence(&path, min_duration, noise_db))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Work directories that fell back from their preferred location this run; see work_dirs.rs
+#[tauri::command]
+fn get_work_dir_fallbacks() -> Vec<work_dirs::DirFallback> {
+  work_dirs::check()
+}
+
+/// `(start, end, score)` of each `window_secs` window of the clip; see engagement.rs
+#[tauri::command]
+async fn score_engagement(clip_id: String, window_secs: f64) -> Result<Vec<(f64, f64, f32)>, String> {
+  tokio::task::spawn_blocking(move || engagement::score_engagement(&clip_id, window_secs))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{:#}", e))
+}
+
+/// Cuts that shorten `silences` to their padding; see intervals::tighten
+#[tauri::command]
+fn tighten_silences(silences: Vec<(f64, f64)>, settings: Option<intervals::TightenSettings>) -> Vec<(f64, f64)> {
+  intervals::tighten(&silences, &settings.unwrap_or_default())
+}
+
+/// Fill an unset export pan and track effects from the project track that plays `input`,
+/// and resolve the HDR mode against the project's dynamic range
+fn with_project_defaults(input: &str, settings: ffmpeg::ExportSettings) -> ffmpeg::ExportSettings {
+  match project_file::get_project().ok().flatten() {
+    Some(project) => project.with_export_defaults(input, settings),
+    None => settings,
+  }
+}
+
+/// Start exporting `input` without `ranges_to_cut` as an "export_cutlist" job and return its id.
+/// Uses `settings`, else the export preset with id `preset`, else the default format.
+/// "export-progress" events report the encode; `cancel_export` stops it
+#[tauri::command]
+fn export_cutlist(
+  app: tauri::AppHandle,
+  input: String,
+  output: String,
+  ranges_to_cut: Vec<(f64, f64)>,
+  settings: Option<ffmpeg::ExportSettings>,
+  preset: Option<String>,
+  reframe_aspect: Option<String>,
+  force: Option<bool>,
+) -> Result<String, String> {
+  let settings = match (settings, preset) {
+    (Some(settings), _) => settings,
+    (None, Some(id)) => project_file::find_export_preset(&id).map_err(|e| e.to_string())?,
+    (None, None) => ffmpeg::ExportSettings::default(),
+  };
+  let settings = with_project_defaults(&input, settings);
+  settings.validate(&output).map_err(|e| e.to_string())?;
+  start_export_cutlist(&app, input, output, ranges_to_cut, settings, reframe_aspect, force.unwrap_or(false))
+}
+
+fn start_export_cutlist(
+  app: &tauri::AppHandle,
+  input: String,
+  output: String,
+  ranges_to_cut: Vec<(f64, f64)>,
+  settings: ffmpeg::ExportSettings,
+  reframe_aspect: Option<String>,
+  force: bool,
+) -> Result<String, String> {
+  let aspect = reframe_aspect.as_deref().map(guides::parse_aspect).transpose().map_err(|e| e.to_string())?;
+  let params = format!("{} -> {} ({} cuts)", input, output, ranges_to_cut.len());
+  let request = jobs::JobRequest {
+    command: "export_cutlist".to_string(),
+    args: serde_json::json!({
+      "input": input,
+      "output": output,
+      "ranges_to_cut": ranges_to_cut,
+      "settings": settings,
+      "reframe_aspect": reframe_aspect,
+      "force": force,
+    }),
+    project: None,
+    inputs: vec![input.clone()],
+  };
+  let progress_app = app.clone();
+  Ok(jobs::spawn_job_with_request(app, "export_cutlist", params, Some(request), move |job| {
+    let started = std::time::Instant::now();
+    let mut last_percent = -1.0;
+    let result = ffmpeg::export_with_cuts(
+      &input,
+      &output,
+      &ranges_to_cut,
+      &settings,
+      aspect,
+      force,
+      &mut |fraction| {
+        job.progress(fraction, None);
+        // ffmpeg reports several times a second; a tenth of a percent is plenty for the UI
+        let percent = (fraction * 1000.0).floor() / 10.0;
+        if percent <= last_percent {
+          return;
+        }
+        last_percent = percent;
+        let elapsed = started.elapsed().as_secs_f64();
+        let eta_seconds = (fraction >= 0.01).then(|| elapsed * (1.0 - fraction) / fraction);
+        emit_export_progress(&progress_app, ffmpeg::ExportProgress {
+          job_id: job.id().to_string(),
+          percent,
+          eta_seconds,
+          done: false,
+          outcome: None,
+          error: None,
+        });
+      },
+    );
+    let error = match &result {
+      Err(_) if job.is_cancelled() => Some("cancelled".to_string()),
+      Err(e) => Some(format!("{:#}", e)),
+      Ok(_) => None,
+    };
+    emit_export_progress(&progress_app, ffmpeg::ExportProgress {
+      job_id: job.id().to_string(),
+      percent: if result.is_ok() { 100.0 } else { 0.0 },
+      eta_seconds: None,
+      done: true,
+      outcome: result.as_ref().ok().map(|r| r.outcome.clone()),
+      error,
+    });
+
+    let result = result?;
+    if result.outcome == ffmpeg::ExportOutcome::Encoded {
+      job.add_output(&output);
+      let profile = encode_profile::profile_for(&settings);
+      if let Err(e) = longterm_storage::add_export_record_with_upscale("cutlist", &output, vec![input.clone()], result.upscale, profile) {
+        log::warn!("Failed to record export: {}", e);
+      }
+    }
+    Ok(serde_json::json!({ "output": output, "outcome": result.outcome, "encoder": result.encoder, "upscale": result.upscale }))
+  }))
+}
+
+/// Cut-list export without re-encoding, as a job (kind "export_lossless"); the job result
+/// holds where each kept segment landed after snapping to keyframes. `audio_stream` is the
+/// clip's audio stream (default: the file's default one)
+#[tauri::command]
+fn export_cutlist_lossless(
+  app: tauri::AppHandle,
+  input: String,
+  output: String,
+  ranges_to_cut: Vec<(f64, f64)>,
+  audio_stream: Option<usize>,
+) -> String {
+  start_export_lossless(&app, input, output, ranges_to_cut, audio_stream)
+}
+
+fn start_export_lossless(
+  app: &tauri::AppHandle,
+  input: String,
+  output: String,
+  ranges_to_cut: Vec<(f64, f64)>,
+  audio_stream: Option<usize>,
+) -> String {
+  let params = format!("{} -> {} ({} cuts, lossless)", input, output, ranges_to_cut.len());
+  let request = jobs::JobRequest {
+    command: "export_cutlist_lossless".to_string(),
+    args: serde_json::json!({ "input": input, "output": output, "ranges_to_cut": ranges_to_cut, "audio_stream": audio_stream }),
+    project: None,
+    inputs: vec![input.clone()],
+  };
+  jobs::spawn_job_with_request(app, "export_lossless", params, Some(request), move |job| {
+    let export = ffmpeg::export_with_cuts_lossless(&input, &output, &ranges_to_cut, audio_stream, &mut |fraction| job.progress(fraction, None))?;
+    job.add_output(&output);
+    if let Err(e) = longterm_storage::add_export_record("cutlist_lossless", &output, vec![input.clone()]) {
+      log::warn!("Failed to record export: {}", e);
+    }
+    Ok(serde_json::json!({ "output": output, "lossless": export }))
+  })
+}
+
+/// Export `start..end` of `input` as a looping GIF or animated WebP, as a job (kind
+/// "export_animated"). The job result includes the file size so the UI can warn about large GIFs
+#[tauri::command]
+fn export_animated(
+  app: tauri::AppHandle,
+  input: String,
+  output: String,
+  start: f64,
+  end: f64,
+  width: Option<u32>,
+  fps: Option<f64>,
+  format: ffmpeg::AnimatedFormat,
+) -> Result<String, String> {
+  let width = width.unwrap_or(ffmpeg::DEFAULT_ANIMATED_WIDTH);
+  let fps = fps.unwrap_or(ffmpeg::DEFAULT_ANIMATED_FPS);
+  ffmpeg::validate_animated(&input, &output, start, end, width, fps, format).map_err(|e| e.to_string())?;
+  Ok(start_export_animated(&app, input, output, (start, end), width, fps, format))
+}
+
+fn start_export_animated(
+  app: &tauri::AppHandle,
+  input: String,
+  output: String,
+  (start, end): (f64, f64),
+  width: u32,
+  fps: f64,
+  format: ffmpeg::AnimatedFormat,
+) -> String {
+  let params = format!("{} [{:.2}..{:.2}] -> {}", input, start, end, output);
+  let request = jobs::JobRequest {
+    command: "export_animated".to_string(),
+    args: serde_json::json!({
+      "input": input,
+      "output": output,
+      "start": start,
+      "end": end,
+      "width": width,
+      "fps": fps,
+      "format": format,
+    }),
+    project: None,
+    inputs: vec![input.clone()],
+  };
+  jobs::spawn_job_with_request(app, "export_animated", params, Some(request), move |job| {
+    let export = ffmpeg::export_animated(&input, &output, start, end, width, fps, format, &mut |fraction| job.progress(fraction, None))?;
+    job.add_output(&output);
+    if let Err(e) = longterm_storage::add_export_record("animated", &output, vec![input.clone()]) {
+      log::warn!("Failed to record export: {}", e);
+    }
+    Ok(serde_json::json!({ "output": output, "animated": export }))
+  })
+}
+
+/// Stop an export or proxy job and kill its ffmpeg process; the partial output is removed.
+/// Cancelling a job that already finished (or never existed) does nothing and returns false
+#[tauri::command]
+fn cancel_export(job_id: String) -> bool {
+  // Proxies run under an id of their own rather than as jobs; only their process can be killed
+  jobs::cancel_job(&job_id) || jobs::kill_process(&job_id)
+}
+
+fn emit_export_progress(app: &tauri::AppHandle, progress: ffmpeg::ExportProgress) {
+  if let Err(e) = app.emit("export-progress", &progress) {
+    log::warn!("Failed to emit export-progress: {}", e);
+  }
+}
+
+/// Export an animated waveform video of `range` of a clip's audio, skipping the clip's
+/// accepted cuts, as an "export_audiogram" job and return its id
+#[tauri::command]
+fn export_audiogram(
+  app: tauri::AppHandle,
+  clip_id: String,
+  range: project_file::TimeRange,
+  style: audiogram::AudiogramStyle,
+  background_image: Option<String>,
+  output: String,
+) -> Result<String, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let clip = project.clips_map.get(&clip_id).ok_or_else(|| format!("clip not found: {}", clip_id))?;
+  let input = clip.path.to_string_lossy().to_string();
+  let cuts = project.clip_time_cuts(&clip_id);
+  Ok(start_export_audiogram(&app, input, clip.audio_stream(), (range.start, range.end), cuts, style, background_image, output))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_export_audiogram(
+  app: &tauri::AppHandle,
+  input: String,
+  audio_stream: usize,
+  range: (f64, f64),
+  cuts: Vec<(f64, f64)>,
+  style: audiogram::AudiogramStyle,
+  background_image: Option<String>,
+  output: String,
+) -> String {
+  let params = format!("{} [{:.2}..{:.2}] -> {}", input, range.0, range.1, output);
+  let request = jobs::JobRequest {
+    command: "export_audiogram".to_string(),
+    args: serde_json::json!({
+      "input": input,
+      "audio_stream": audio_stream,
+      "range": range,
+      "cuts": cuts,
+      "style": style,
+      "background_image": background_image,
+      "output": output,
+    }),
+    project: None,
+    inputs: vec![input.clone()],
+  };
+  jobs::spawn_job_with_request(app, "export_audiogram", params, Some(request), move |job| {
+    audiogram::export_audiogram(&input, audio_stream, range, &cuts, &style, background_image.as_deref(), &output)?;
+    job.add_output(&output);
+    if let Err(e) = longterm_storage::add_export_record("audiogram", &output, vec![input.clone()]) {
+      log::warn!("Failed to record export: {}", e);
+    }
+    Ok(serde_json::json!({ "output": output }))
+  })
+}
+
+#[tauri::command]
+fn export_parts(
+  app: tauri::AppHandle,
+  mut settings: export_parts::PartsExportSettings,
+  split: export_parts::SplitMode,
+) -> Result<String, String> {
+  settings.export = with_project_defaults(&settings.input, settings.export);
+  let markers: Vec<f64> = match split {
+    export_parts::SplitMode::AtMarkers => {
+      let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+      project.markers.iter().map(|m| m.time).collect()
+    }
+    export_parts::SplitMode::ByDuration(_) => Vec::new(),
+  };
+  Ok(start_export_parts(&app, settings, split, markers))
+}
+
+fn start_export_parts(
+  app: &tauri::AppHandle,
+  settings: export_parts::PartsExportSettings,
+  split: export_parts::SplitMode,
+  markers: Vec<f64>,
+) -> String {
+  let params = format!("{} -> {} ({:?})", settings.input, settings.output, split);
+  let request = jobs::JobRequest {
+    command: "export_parts".to_string(),
+    args: serde_json::json!({ "settings": settings, "split": split, "markers": markers }),
+    project: None,
+    inputs: vec![settings.input.clone()],
+  };
+  jobs::spawn_job_with_request(app, "export_parts", params, Some(request), move |job| {
+    let parts = export_parts::export_parts(&settings, &split, &markers, job)?;
+    for part in &parts {
+      job.add_output(&part.path);
+    }
+    for part in parts.iter().filter(|p| p.outcome == ffmpeg::ExportOutcome::Encoded) {
+      if let Err(e) = longterm_storage::add_export_record("part", &part.path, vec![settings.input.clone()]) {
+        log::warn!("Failed to record export: {}", e);
+      }
+    }
+    Ok(serde_json::to_value(parts)?)
+  })
+}
+
+/// Render the loaded project's timeline with its accepted cuts taken out, using the default
+/// preset, as an "export_project" job; returns the job id. `output` defaults to the project's
+/// export folder
+#[tauri::command]
+fn export_project(app: tauri::AppHandle, output: Option<String>) -> Result<String, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let mut settings = ffmpeg::ExportSettings::default();
+  settings.hdr_mode = project.hdr_mode(settings.hdr_mode);
+  start_export_timeline(&app, project, output, settings, "export_project")
+}
+
+/// Render the loaded project's whole timeline (see ffmpeg::render_project) as an
+/// "export_timeline" job and return its id. Uses `settings`, else the export preset with id
+/// `preset`, else the default format; `output` defaults to the project's export folder
+#[tauri::command]
+fn export_timeline(
+  app: tauri::AppHandle,
+  output: Option<String>,
+  settings: Option<ffmpeg::ExportSettings>,
+  preset: Option<String>,
+) -> Result<String, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let mut settings = match (settings, preset) {
+    (Some(settings), _) => settings,
+    (None, Some(id)) => project_file::find_export_preset(&id).map_err(|e| e.to_string())?,
+    (None, None) => ffmpeg::ExportSettings::default(),
+  };
+  settings.hdr_mode = project.hdr_mode(settings.hdr_mode);
+  start_export_timeline(&app, project, output, settings, "export_timeline")
+}
+
+/// Start rendering `project`'s timeline as a `command` job ("export_project" or
+/// "export_timeline", which is also the job kind and the export record's kind)
+fn start_export_timeline(
+  app: &tauri::AppHandle,
+  project: project_file::ProjectFile,
+  output: Option<String>,
+  settings: ffmpeg::ExportSettings,
+  command: &str,
+) -> Result<String, String> {
+  let output = output.unwrap_or_else(|| {
+    project
+      .export_path_in(&project.export_dir(), settings.container.extension())
+      .to_string_lossy()
+      .to_string()
+  });
+  settings.validate(&output).map_err(|e| e.to_string())?;
+  let params = format!("timeline -> {}", output);
+  let mut inputs: Vec<String> = project
+    .tracks_map
+    .values()
+    .flat_map(|t| &t.segments)
+    .filter_map(|s| project.clips_map.get(&s.clip_id))
+    .map(|c| c.path.to_string_lossy().to_string())
+    .collect();
+  inputs.sort();
+  inputs.dedup();
+  // Resuming reads the timeline from the open project, so only saved projects can be resumed
+  let request = project.path.clone().map(|path| jobs::JobRequest {
+    command: command.to_string(),
+    args: serde_json::json!({ "output": output, "settings": settings }),
+    project: Some(path),
+    inputs: inputs.clone(),
+  });
+  let record_kind = command.trim_start_matches("export_").to_string();
+  Ok(jobs::spawn_job_with_request(app, command, params, request, move |job| {
+    if let Some(dir) = std::path::Path::new(&output).parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    job.progress(0.0, Some("Rendering timeline"));
+    let encoder = ffmpeg::render_project(&project, &output, &settings, &mut |fraction| job.progress(fraction, None))?;
+    job.add_output(&output);
+    let upscale = project.upscale_check(&settings).map(|(decision, _)| decision);
+    let profile = encode_profile::profile_for(&settings);
+    if let Err(e) = longterm_storage::add_export_record_with_upscale(&record_kind, &output, inputs, upscale, profile) {
+      log::warn!("Failed to record export: {}", e);
+    }
+    Ok(serde_json::json!({ "output": output, "encoder": encoder, "upscale": upscale }))
+  }))
+}
+
+#[tauri::command]
+fn get_export_history() -> Result<Vec<longterm_storage::ExportRecord>, String> {
+  longterm_storage::get_export_history().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_export_status(
+  input: String,
+  output: String,
+  ranges_to_cut: Vec<(f64, f64)>,
+  settings: Option<ffmpeg::ExportSettings>,
+  reframe_aspect: Option<String>,
+) -> Result<export_sidecar::ExportStatus, String> {
+  let reframe_aspect = reframe_aspect.as_deref().map(guides::parse_aspect).transpose().map_err(|e| e.to_string())?;
+  let settings = with_project_defaults(&input, settings.unwrap_or_default());
+  let (fingerprint, _) = ffmpeg::cut_export_fingerprint(&input, &ranges_to_cut, &settings, reframe_aspect)
+    .map_err(|e| e.to_string())?;
+  Ok(export_sidecar::export_status(std::path::Path::new(&output), &fingerprint))
+}
+
+/// Read the `.edits.json` of an export (or the file itself): its source, settings and removed
+/// ranges, whether the source is unchanged, and a script that rebuilds the cut list
+#[tauri::command]
+fn load_export_sidecar(path: String) -> Result<export_sidecar::LoadedExportEdits, String> {
+  export_sidecar::load_export_sidecar(std::path::Path::new(&path)).map_err(|e| format!("{:#}", e))
+}
+
+/// Make the preview proxy of `input`, with a hardware `encoder` if given (see
+/// list_available_encoders), or reuse the cached one. Progress is sent as "proxy-progress".
+/// `audio_stream` is the clip's audio stream (default: the file's default one).
+/// With `job_id`, `cancel_export(job_id)` stops the encode
+#[tauri::command]
+async fn make_preview_proxy(
+  app: tauri::AppHandle,
+  input: String,
+  audio_stream: Option<usize>,
+  job_id: Option<String>,
+  encoder: Option<String>,
+) -> Result<ffmpeg::EncodedFile, String> {
+  ffmpeg::validate_encoder(encoder.as_deref()).map_err(|e| e.to_string())?;
+  tokio::task::spawn_blocking(move || {
+    let emit = |percent: f64, done: bool, cached: bool| {
+      let progress = ffmpeg::ProxyProgress { input: input.clone(), job_id: job_id.clone(), percent, done, cached };
+      if let Err(e) = app.emit("proxy-progress", &progress) {
+        log::warn!("Failed to emit proxy-progress: {}", e);
+      }
+    };
+    let mut last_percent = -1.0;
+    let mut make = || {
+      ffmpeg::make_preview_proxy(&input, Some(ffmpeg::PROXY_WIDTH), audio_stream, encoder.as_deref(), &mut |fraction| {
+        let percent = (fraction * 1000.0).floor() / 10.0;
+        if percent > last_percent {
+          last_percent = percent;
+          emit(percent, false, false);
+        }
+      })
+    };
+    let result = match &job_id {
+      Some(id) => jobs::with_job_id(id, make),
+      None => make(),
+    };
+    if let Ok(proxy) = &result {
+      emit(100.0, true, proxy.encoder.is_none());
+    }
+    result
+  })
+  .await
+  .map_err(|e| e.to_string())?
+  .map_err(|e| e.to_string())
+}
+
+/// Total size of the cached waveform peaks, bytes
+#[tauri::command]
+fn get_waveform_cache_size() -> Result<u64, String> {
+  waveform_cache::cache_size().map_err(|e| e.to_string())
+}
+
+/// Delete every cached waveform and spectrogram; returns the bytes freed. They are decoded
+/// again when next shown
+#[tauri::command]
+fn clear_waveform_cache() -> Result<u64, String> {
+  waveform_cache::clear().map_err(|e| e.to_string())
+}
+
+/// Total size of the cached preview proxies, bytes
+#[tauri::command]
+fn get_proxy_cache_size() -> Result<u64, String> {
+  ffmpeg::proxy_cache_size().map_err(|e| e.to_string())
+}
+
+/// Delete every cached preview proxy; returns the bytes freed
+#[tauri::command]
+fn clear_proxy_cache() -> Result<u64, String> {
+  ffmpeg::clear_proxy_cache().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_media_url(path: String) -> Option<String> {
+  media_protocol::token_for_path(std::path::Path::new(&path)).map(|token| media_protocol::media_url(&token))
+}
+
+#[tauri::command]
+fn revoke_media_token(token: String) -> bool {
+  media_protocol::revoke(&token)
+}
+
+/// Deprecated: the whole file as base64, for small files only (see media_protocol::read_base64).
+/// Load media through `get_media_url`, or in pieces with `read_file_chunk`
+#[tauri::command]
+fn read_file_as_base64(path: String, max_bytes: Option<u64>) -> Result<String, media_protocol::ReadFileError> {
+  media_protocol::read_base64(std::path::Path::new(&path), max_bytes)
 }
 
 #[tauri::command]
@@ -128,12 +843,46 @@ fn get_file_size(path: String) -> Result<u64, String> {
 
 #[tauri::command]
 fn generate_thumbnails(path: String, count: usize, width: u32) -> Result<Vec<String>, String> {
-  ffmpeg::generate_thumbnails(&path, count, width).map_err(|e| e.to_string())
+  thumbnail_cache::get_or_generate_thumbnails(&path, count, width).map_err(|e| e.to_string())
+}
+
+/// Empty the thumbnail cache; returns the bytes freed
+#[tauri::command]
+fn clear_thumbnail_cache() -> Result<u64, String> {
+  thumbnail_cache::clear().map_err(|e| e.to_string())
 }
 
+/// The frame of `path` at `timestamp` as base64 PNG or JPEG, e.g. for "save frame as image" or
+/// picking a poster frame. `accurate` (default true) matches the playhead exactly
+#[tauri::command]
+async fn extract_frame(
+  path: String,
+  timestamp: f64,
+  width: Option<u32>,
+  format: Option<ffmpeg::StillFormat>,
+  accurate: Option<bool>,
+) -> Result<ffmpeg::StillFrame, ffmpeg::FrameError> {
+  let format = format.unwrap_or(ffmpeg::StillFormat::Png);
+  tokio::task::spawn_blocking(move || ffmpeg::extract_frame(&path, timestamp, width, format, accurate.unwrap_or(true)))
+    .await
+    .map_err(|e| ffmpeg::FrameError::Failed(e.to_string()))?
+}
+
+/// Media URL of the embedded album art of `path`, None when it has none
 #[tauri::command]
 fn extract_album_art(path: String) -> Result<Option<String>, String> {
-  ffmpeg::extract_album_art(&path).map_err(|e| e.to_string())
+  thumbnail_cache::album_art_url(&path).map_err(|e| e.to_string())
+}
+
+/// Media URL of the poster of audio file `path`: its album art, else a waveform picture
+#[tauri::command]
+fn get_poster_url(path: String) -> Result<Option<String>, String> {
+  thumbnail_cache::poster_url(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_audio_metadata(path: String) -> Result<ffmpeg::AudioMetadata, String> {
+  thumbnail_cache::audio_metadata(&path).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -141,8 +890,46 @@ fn generate_timeline_preview(
   clips: Vec<ffmpeg::TimelineClip>,
   output_width: u32,
   total_duration: f64,
-) -> Result<String, String> {
-  ffmpeg::generate_timeline_preview(&clips, output_width, total_duration).map_err(|e| e.to_string())
+  encoder: Option<String>,
+) -> Result<ffmpeg::EncodedFile, String> {
+  ffmpeg::validate_encoder(encoder.as_deref()).map_err(|e| e.to_string())?;
+  ffmpeg::generate_timeline_preview(&clips, output_width, total_duration, encoder.as_deref()).map_err(|e| e.to_string())
+}
+
+/// Video encoders the machine can use: "libx264" plus the hardware H.264 encoders that pass a
+/// test encode. The first call runs the tests and takes a moment
+#[tauri::command]
+async fn list_available_encoders() -> Result<Vec<String>, String> {
+  tokio::task::spawn_blocking(ffmpeg::list_available_encoders).await.map_err(|e| e.to_string())
+}
+
+/// Integrated loudness (LUFS), true peak and loudness range of a file's audio, for showing
+/// before an export normalizes it. `audio_stream` is the clip's audio stream (default: the file's
+/// default one). None when the file has no audio
+#[tauri::command]
+async fn measure_loudness(path: String, audio_stream: Option<usize>) -> Result<Option<ffmpeg::LoudnessStats>, String> {
+  tokio::task::spawn_blocking(move || ffmpeg::measure_loudness(&path, audio_stream))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Built-in export presets ("YouTube 1080p", "Web 720p", "Archive ProRes", "Audio only")
+/// followed by the loaded project's custom ones
+#[tauri::command]
+fn get_export_presets() -> Vec<ffmpeg::ExportPreset> {
+  project_file::export_presets()
+}
+
+/// Store a custom export preset in the project, replacing the one with the same id
+#[tauri::command]
+fn save_export_preset(preset: ffmpeg::ExportPreset) -> Result<ffmpeg::ExportPreset, String> {
+  project_file::save_export_preset(preset).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_export_preset(id: String) -> Result<(), String> {
+  project_file::delete_export_preset(&id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -174,19 +961,27 @@ async fn set_fullscreen(window: tauri::Window, fullscreen: bool) -> Result<(), S
   Ok(())
 }
 
+/// What closing the app now would lose: an unsaved project, undecided agent proposals, active jobs
 #[tauri::command]
-async fn create_editor_window(app: tauri::AppHandle) -> Result<(), String> {
-  let _editor_window = tauri::WebviewWindowBuilder::new(
-    &app,
-    "editor",
-    tauri::WebviewUrl::App("/editor".into())
-  )
-  .title("Video Editor")
-  .fullscreen(false)
-  .build()
-  .map_err(|e| e.to_string())?;
-  
-  Ok(())
+fn get_exit_blockers() -> Vec<exit_guard::ExitBlocker> {
+  exit_guard::exit_blockers()
+}
+
+/// Go ahead with the close or quit announced by "close-requested". Without `force` it only
+/// happens when nothing is in the way any more; what still is gets returned
+#[tauri::command]
+fn confirm_close(app: tauri::AppHandle, force: bool) -> Result<Vec<exit_guard::ExitBlocker>, String> {
+  exit_guard::confirm_close(&app, force).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_editor_window(app: tauri::AppHandle, project_path: Option<String>) -> Result<(), String> {
+  editor_windows::open(&app, project_path.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn close_editor_window(window: tauri::Window) -> Result<(), String> {
+  editor_windows::close(&window).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -206,6 +1001,76 @@ fn new_project(project_file: project_file::ProjectFile) -> Result<project_file::
   project_file::new_project(project_file).map_err(|e| e.to_string())
 }
 
+/// Probes and hashes every file, so it runs off the IPC thread
+#[tauri::command]
+async fn create_project_from_media(
+  paths: Vec<String>,
+  project_path: String,
+  title: String,
+  order: Option<media_project::MediaOrder>,
+) -> Result<media_project::ProjectFromMedia, String> {
+  tokio::task::spawn_blocking(move || {
+    media_project::create_project_from_media(paths, &project_path, &title, order.unwrap_or_default())
+  })
+  .await
+  .map_err(|e| e.to_string())?
+  .map_err(|e| e.to_string())
+}
+
+/// Generate the offline sample media in `dest_dir` and open a sample project built on it
+#[tauri::command]
+async fn create_sample_project(dest_dir: String) -> Result<project_file::ProjectFile, String> {
+  tokio::task::spawn_blocking(move || sample_project::create_sample_project(std::path::Path::new(&dest_dir)))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Apply the edit script at `script_path` to the project at `project_path`, which becomes the
+/// current project. Runs as a job whose result is the script report; returns the job id
+#[tauri::command]
+fn run_edit_script(app: tauri::AppHandle, script_path: String, project_path: String) -> Result<String, String> {
+  Ok(start_edit_script(&app, script_path, project_path))
+}
+
+/// Rebuild the cut list of an old export in the project at `project_path` (created when
+/// missing): the script from its `.edits.json` is written next to it and run as a job
+#[tauri::command]
+fn reproduce_export_edits(app: tauri::AppHandle, path: String, project_path: String) -> Result<String, String> {
+  let loaded = export_sidecar::load_export_sidecar(std::path::Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+  let script_path = format!("{}.script.json", loaded.path.trim_end_matches(".json"));
+  let data = serde_json::to_string_pretty(&loaded.script).map_err(|e| e.to_string())?;
+  std::fs::write(&script_path, data).map_err(|e| format!("failed to write {}: {}", script_path, e))?;
+  Ok(start_edit_script(&app, script_path, project_path))
+}
+
+fn start_edit_script(app: &tauri::AppHandle, script_path: String, project_path: String) -> String {
+  let params = format!("{} -> {}", script_path, project_path);
+  jobs::spawn_job(app, "edit_script", params, move |job| {
+    let report = edit_script::run_edit_script(
+      std::path::Path::new(&script_path),
+      std::path::Path::new(&project_path),
+      &mut |index, total, _| job.progress(index as f64 / total as f64, Some(format!("Step {} of {}", index + 1, total).as_str())),
+    )?;
+    if let Some(error) = &report.error {
+      return Err(anyhow::anyhow!("{} (report: {})", error, report.report_path));
+    }
+    Ok(serde_json::to_value(report)?)
+  })
+}
+
+/// Put the clips on the timeline at their recording start times; see media_project::align_clips_by_timecode
+#[tauri::command]
+fn align_clips_by_timecode(
+  clip_ids: Vec<String>,
+  target_track_ids: Vec<String>,
+  tolerance: Option<f64>,
+  clock_offsets: Option<std::collections::HashMap<String, f64>>,
+) -> Result<media_project::AlignResult, String> {
+  media_project::align_clips_by_timecode(&clip_ids, &target_track_ids, tolerance.unwrap_or(0.0), &clock_offsets.unwrap_or_default())
+    .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn load_project(path: String) -> Result<project_file::ProjectFile, String> {
   project_file::load_project(path).map_err(|e| e.to_string())
@@ -226,11 +1091,614 @@ fn get_project() -> Result<Option<project_file::ProjectFile>, String> {
   project_file::get_project()
 }
 
+/// Backup `which` (1 is the newest) of the project saved at `path`, or the newest readable one.
+/// Nothing is overwritten or loaded
+#[tauri::command]
+fn restore_project_backup(path: String, which: Option<usize>) -> Result<project_file::ProjectFile, String> {
+  project_file::restore_project_backup(path, which).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn single_read_project(path: String) -> Result<project_file::ProjectFile, String> {
   project_file::single_read_project(path).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn undo_project() -> Result<Option<project_file::ProjectFile>, String> {
+  project_file::undo_project().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn repair_duplicate_ids() -> Result<Vec<project_file::RenamedId>, String> {
+  project_file::repair_duplicate_ids().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_track(track_id: String, patch: project_file::TrackPatch) -> Result<project_file::Track, String> {
+  project_file::update_track(&track_id, &patch).map_err(|e| e.to_string())
+}
+
+/// Apply `ops` to the project as one undo step, or none of them; see project_file::transact
+#[tauri::command]
+fn transact(ops: Vec<project_file::ProjectOp>) -> Result<project_file::ProjectFile, project_file::TransactionError> {
+  project_file::transact(&ops)
+}
+
+#[tauri::command]
+fn get_view_state() -> Result<project_file::TimelineViewState, String> {
+  project_file::get_view_state().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_view_state(patch: project_file::ViewStatePatch) -> Result<project_file::TimelineViewState, String> {
+  project_file::set_view_state(&patch).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn batch_update_segments(
+  track_id: String,
+  filter: project_file::SegmentFilter,
+  patch: project_file::SegmentPatch,
+) -> Result<project_file::BatchUpdateResult, String> {
+  project_file::batch_update_segments(&track_id, &filter, &patch).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_clip_transcript(
+  clip_id: String,
+  segments: Vec<transcription::TranscriptSegment>,
+  language: Option<String>,
+) -> Result<(), String> {
+  project_file::set_clip_transcript(&clip_id, segments, language.as_deref()).map_err(|e| e.to_string())
+}
+
+/// Write the timeline's captions to `output` (.srt or .vtt) in output time, one file per
+/// language when clips are in several (`name.en.srt`, `name.es.srt`). Returns the files written
+#[tauri::command]
+fn export_transcript(output: String) -> Result<Vec<String>, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let files = captions::export_transcript(&project, std::path::Path::new(&output)).map_err(|e| e.to_string())?;
+  Ok(files.iter().map(|f| f.to_string_lossy().to_string()).collect())
+}
+
+/// Output time of each timeline time once the accepted cuts are removed; null for times inside a cut
+#[tauri::command]
+fn remap_to_output_time(times: Vec<f64>) -> Result<Vec<Option<f64>>, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  Ok(project.remap_to_output_time(&times))
+}
+
+/// Markers at their positions in the edited output, for the timeline's edited view
+#[tauri::command]
+fn get_output_markers() -> Result<Vec<project_file::Marker>, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  Ok(project.output_markers())
+}
+
+/// Propose up to `target_count` chapters for the edited output, as markers for review. The
+/// first is at 0:00 and each lasts at least 10 seconds; titles come from the LLM when an API
+/// key is set
+#[tauri::command]
+async fn generate_chapters(target_count: usize) -> Result<Vec<chapters::ChapterProposal>, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let (project, positions) = tokio::task::spawn_blocking(move || {
+    let positions = chapters::chapter_positions(&project, target_count);
+    (project, positions)
+  })
+  .await
+  .map_err(|e| e.to_string())?;
+  let positions = positions.map_err(|e| e.to_string())?;
+  Ok(chapters::propose_chapters(&project, positions).await)
+}
+
+/// Find the stretches of the edited output viewers are likely to skip and keep them on the
+/// project; reasons come from the LLM when an API key is set. See retention.rs
+#[tauri::command]
+async fn find_retention_risks() -> Result<Vec<project_file::RiskRegion>, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let candidates = tokio::task::spawn_blocking(move || retention::risk_candidates(&project))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+  let regions = retention::label_regions(candidates).await;
+  project_file::set_retention_risks(regions.clone()).map_err(|e| e.to_string())?;
+  Ok(regions)
+}
+
+/// Markers for the retention risks kept on the project, for review; add the ones to keep with
+/// add_markers
+#[tauri::command]
+fn retention_risk_markers() -> Result<Vec<project_file::Marker>, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  Ok(retention::risk_markers(&project))
+}
+
+/// Cut the best moment of each video clip to the beats of the music clip, on new tracks of
+/// the current project; returns the updated project for preview. See montage.rs
+#[tauri::command]
+async fn build_montage(music_clip_id: String, video_clip_ids: Vec<String>, target_duration: f64) -> Result<project_file::ProjectFile, String> {
+  tokio::task::spawn_blocking(move || montage::build_montage(&music_clip_id, &video_clip_ids, target_duration))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Add reviewed chapter (or any) markers to the project; returns its markers
+#[tauri::command]
+fn add_markers(markers: Vec<project_file::Marker>) -> Result<Vec<project_file::Marker>, String> {
+  project_file::add_markers(markers).map_err(|e| e.to_string())
+}
+
+/// Set (or clear with null, going back to detection) the language a clip is spoken in
+#[tauri::command]
+fn set_clip_language(clip_id: String, language: Option<String>) -> Result<project_file::Clip, String> {
+  project_file::set_clip_language(&clip_id, language.as_deref()).map_err(|e| e.to_string())
+}
+
+/// Set (or clear with null, going back to the file's default stream) the audio stream a clip plays
+#[tauri::command]
+fn set_clip_audio_stream(clip_id: String, audio_stream: Option<usize>) -> Result<project_file::Clip, String> {
+  project_file::set_clip_audio_stream(&clip_id, audio_stream).map_err(|e| e.to_string())
+}
+
+/// Point a clip at a re-edit of its file and move its segments by the offset between the two,
+/// given or found from their audio; see project_file::replace_clip_source
+#[tauri::command]
+async fn replace_clip_source(
+  clip_id: String,
+  new_path: String,
+  offset_adjustment: project_file::OffsetAdjustment,
+) -> Result<project_file::SourceReplacement, String> {
+  tokio::task::spawn_blocking(move || project_file::replace_clip_source(&clip_id, std::path::Path::new(&new_path), offset_adjustment))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{:#}", e))
+}
+
+/// Set (or clear with null) the LUT applied to a clip's thumbnails and previews
+#[tauri::command]
+fn set_clip_preview_lut(clip_id: String, lut: Option<String>) -> Result<project_file::Clip, String> {
+  let lut = lut.filter(|l| !l.trim().is_empty()).map(std::path::PathBuf::from);
+  project_file::set_clip_preview_lut(&clip_id, lut).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn snap_cut_to_sentence(
+  clip_id: String,
+  time: f64,
+  direction: sentence_snap::SnapDirection,
+  max_shift: Option<f64>,
+) -> Result<sentence_snap::SnapResult, String> {
+  let clip = project_file::get_clip(&clip_id).map_err(|e| e.to_string())?;
+  let transcript = clip.transcript.ok_or_else(|| format!("clip {} has no transcript", clip_id))?;
+  let boundaries = sentence_snap::sentence_boundaries(&transcript);
+  Ok(sentence_snap::snap_to_boundary(
+    &boundaries,
+    time,
+    direction,
+    max_shift.unwrap_or(sentence_snap::DEFAULT_MAX_SHIFT),
+  ))
+}
+
+/// Whether every clip in the current project is audio; see ProjectFile::is_audio_only
+#[tauri::command]
+fn is_audio_only_project() -> bool {
+  project_file::is_audio_only()
+}
+
+#[tauri::command]
+fn get_guide_rects(player_width: f64, player_height: f64) -> Result<guides::GuideRects, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let clip = project.primary_video_clip().ok_or_else(|| "project has no video clip".to_string())?;
+  let probe = match &clip.latest_probe {
+    Some(probe) => probe.clone(),
+    None => ffmpeg::ffprobe(&clip.path.to_string_lossy()).map_err(|e| e.to_string())?,
+  };
+  guides::guide_rects(&probe, &project.settings.guides, player_width, player_height).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn render_timeline_overview(width: u32, height: u32) -> Result<String, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  tokio::task::spawn_blocking(move || timeline_overview::render_timeline_overview(&project, width, height))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn bake_segment(
+  app: tauri::AppHandle,
+  track_id: String,
+  segment_id: String,
+  settings: Option<bake::BakeSettings>,
+) -> Result<String, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  project.segment(&track_id, &segment_id).map_err(|e| e.to_string())?;
+  let settings = settings.unwrap_or_default();
+  let params = format!("track {} segment {}", track_id, segment_id);
+  Ok(jobs::spawn_job(&app, "bake_segment", params, move |job| {
+    let baked = bake::bake_segment(&track_id, &segment_id, &settings, job)?;
+    if let Ok(clip) = project_file::get_clip(&baked.clip_id) {
+      job.add_output(&clip.path.to_string_lossy());
+    }
+    Ok(serde_json::to_value(baked)?)
+  }))
+}
+
+/// Export one segment with its effects, speed and audio adjustments. Runs as a job; returns the job id
+#[tauri::command]
+fn export_segment(
+  app: tauri::AppHandle,
+  track_id: String,
+  segment_id: String,
+  settings: Option<ffmpeg::ExportSettings>,
+  output: String,
+) -> Result<String, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let segment = project.segment(&track_id, &segment_id).map_err(|e| e.to_string())?;
+  let clip = project.clips_map.get(&segment.clip_id).ok_or_else(|| format!("clip not found: {}", segment.clip_id))?;
+  let input = clip.path.to_string_lossy().to_string();
+  let settings = with_project_defaults(&input, settings.unwrap_or_default());
+  settings.validate(&output).map_err(|e| e.to_string())?;
+  let params = format!("track {} segment {} -> {}", track_id, segment_id, output);
+  let request = jobs::JobRequest {
+    command: "export_segment".to_string(),
+    args: serde_json::json!({ "track_id": track_id, "segment_id": segment_id, "settings": settings, "output": output }),
+    project: project.path.clone(), // The segment is read from the open project when the job runs
+    inputs: vec![input.clone()],
+  };
+  Ok(jobs::spawn_job_with_request(&app, "export_segment", params, Some(request), move |job| {
+    if let Some(dir) = std::path::Path::new(&output).parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mode = bake::export_segment(&track_id, &segment_id, &settings, &output, job)?;
+    job.add_output(&output);
+    if let Err(e) = longterm_storage::add_export_record("segment", &output, vec![input.clone()]) {
+      log::warn!("Failed to record export: {}", e);
+    }
+    Ok(serde_json::json!({ "output": output, "mode": mode }))
+  }))
+}
+
+/// Re-encode a variable-frame-rate clip to constant frame rate at the project fps. Runs as a job;
+/// returns the job id. With `retarget`, segments playing the clip switch to the conformed one
+#[tauri::command]
+fn conform_vfr(app: tauri::AppHandle, clip_id: String, retarget: Option<bool>) -> Result<String, String> {
+  project_file::get_clip(&clip_id).map_err(|e| e.to_string())?;
+  let params = format!("clip {}", clip_id);
+  Ok(jobs::spawn_job(&app, "conform_vfr", params, move |job| {
+    let clip = conform::conform_vfr(&clip_id, job)?;
+    job.add_output(&clip.path.to_string_lossy());
+    let retargeted = if retarget.unwrap_or(false) { project_file::retarget_clip(&clip_id, &clip.id)? } else { 0 };
+    Ok(serde_json::json!({ "clip": clip, "retargeted_segments": retargeted }))
+  }))
+}
+
+/// Stretch `range` (default: all) of a clip's audio to `target_duration` seconds as a new derived
+/// audio clip. Runs as a job; its result reports the achieved duration
+#[tauri::command]
+fn time_stretch_clip(
+  app: tauri::AppHandle,
+  clip_id: String,
+  range: Option<(f64, f64)>,
+  target_duration: f64,
+  preserve_pitch: Option<bool>,
+) -> Result<String, String> {
+  project_file::get_clip(&clip_id).map_err(|e| e.to_string())?;
+  let params = format!("clip {} to {:.2}s", clip_id, target_duration);
+  Ok(jobs::spawn_job(&app, "time_stretch", params, move |job| {
+    let result = time_stretch::time_stretch_clip(&clip_id, range, target_duration, preserve_pitch.unwrap_or(true), job)?;
+    job.add_output(&result.clip.path.to_string_lossy());
+    Ok(serde_json::to_value(result)?)
+  }))
+}
+
+/// Stretch an audio-track segment to play for `target_duration`, e.g. a voiceover to its video
+/// slot. Runs as a job; `unbake_segment` undoes it
+#[tauri::command]
+fn stretch_segment_to_fit(
+  app: tauri::AppHandle,
+  track_id: String,
+  segment_id: String,
+  target_duration: f64,
+  preserve_pitch: Option<bool>,
+) -> Result<String, String> {
+  let params = format!("segment {} to {:.2}s", segment_id, target_duration);
+  Ok(jobs::spawn_job(&app, "time_stretch", params, move |job| {
+    let (segment, result) =
+      time_stretch::stretch_segment_to_fit(&track_id, &segment_id, target_duration, preserve_pitch.unwrap_or(true), job)?;
+    job.add_output(&result.clip.path.to_string_lossy());
+    Ok(serde_json::json!({ "segment": segment, "stretch": result }))
+  }))
+}
+
+#[tauri::command]
+fn retarget_clip(from_clip_id: String, to_clip_id: String) -> Result<usize, String> {
+  project_file::retarget_clip(&from_clip_id, &to_clip_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn unbake_segment(track_id: String, segment_id: String) -> Result<project_file::Segment, String> {
+  project_file::unbake_segment(&track_id, &segment_id).map_err(|e| e.to_string())
+}
+
+// Jobs
+
+#[tauri::command]
+fn get_job(job_id: String) -> Result<jobs::Job, String> {
+  jobs::get_job(&job_id).ok_or_else(|| format!("job not found: {}", job_id))
+}
+
+#[tauri::command]
+fn list_jobs() -> Vec<jobs::Job> {
+  jobs::list_jobs()
+}
+
+#[tauri::command]
+fn cancel_job(job_id: String) -> bool {
+  jobs::cancel_job(&job_id)
+}
+
+/// Pause a multi-part job (part exports, cache warm-up, media verification) after its current
+/// step. Single-step jobs such as one ffmpeg encode can't be paused and return an error
+#[tauri::command]
+fn pause_job(app: tauri::AppHandle, job_id: String) -> Result<(), String> {
+  jobs::pause_job(&app, &job_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn resume_job(app: tauri::AppHandle, job_id: String) -> Result<(), String> {
+  jobs::resume_job(&app, &job_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_job_history(limit: Option<usize>, kind_filter: Option<String>) -> Result<Vec<jobs::Job>, String> {
+  jobs::get_job_history(limit, kind_filter.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_job_history() -> Result<(), String> {
+  jobs::clear_job_history().map_err(|e| e.to_string())
+}
+
+/// Exports from earlier sessions that were queued or interrupted; see jobs::get_pending_jobs
+#[tauri::command]
+fn get_pending_jobs() -> Result<Vec<jobs::Job>, String> {
+  jobs::get_pending_jobs().map_err(|e| e.to_string())
+}
+
+fn request_arg<T: serde::de::DeserializeOwned>(request: &jobs::JobRequest, name: &str) -> Result<T, String> {
+  let value = request.args.get(name).cloned().unwrap_or(serde_json::Value::Null);
+  serde_json::from_value(value).map_err(|e| format!("{} job has a bad '{}': {}", request.command, name, e))
+}
+
+/// Err unless the project `request` reads when it runs is the loaded one
+fn check_resume_project(request: &jobs::JobRequest) -> Result<(), String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  if request.project.is_some() && project.path != request.project {
+    return Err(format!("open {:?} to resume this export", request.project.clone().unwrap_or_default()));
+  }
+  Ok(())
+}
+
+/// Start a pending job again from the beginning as a new job; returns the new job id. The old
+/// record is closed with a note pointing at the new one
+#[tauri::command]
+fn resume_pending_job(app: tauri::AppHandle, job_id: String) -> Result<String, String> {
+  let job = jobs::get_pending_job(&job_id).map_err(|e| e.to_string())?;
+  let request = job.request.ok_or_else(|| format!("job {} can't be resumed", job_id))?;
+  let new_id = match request.command.as_str() {
+    "export_project" => {
+      if request.project.is_none() {
+        // Recorded before project exports rendered the timeline
+        return Err(format!("job {} was started by an older version; export the project again", job_id));
+      }
+      check_resume_project(&request)?;
+      let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+      start_export_timeline(&app, project, request_arg(&request, "output")?, request_arg(&request, "settings")?, "export_project")?
+    }
+    "export_parts" => start_export_parts(
+      &app,
+      request_arg(&request, "settings")?,
+      request_arg(&request, "split")?,
+      request_arg(&request, "markers")?,
+    ),
+    "export_cutlist" => start_export_cutlist(
+      &app,
+      request_arg(&request, "input")?,
+      request_arg(&request, "output")?,
+      request_arg(&request, "ranges_to_cut")?,
+      request_arg(&request, "settings")?,
+      request_arg(&request, "reframe_aspect")?,
+      request_arg(&request, "force")?,
+    )?,
+    "export_cutlist_lossless" => start_export_lossless(
+      &app,
+      request_arg(&request, "input")?,
+      request_arg(&request, "output")?,
+      request_arg(&request, "ranges_to_cut")?,
+      request_arg(&request, "audio_stream")?,
+    ),
+    "export_animated" => start_export_animated(
+      &app,
+      request_arg(&request, "input")?,
+      request_arg(&request, "output")?,
+      (request_arg(&request, "start")?, request_arg(&request, "end")?),
+      request_arg(&request, "width")?,
+      request_arg(&request, "fps")?,
+      request_arg(&request, "format")?,
+    ),
+    "export_audiogram" => start_export_audiogram(
+      &app,
+      request_arg(&request, "input")?,
+      request_arg(&request, "audio_stream")?,
+      request_arg(&request, "range")?,
+      request_arg(&request, "cuts")?,
+      request_arg(&request, "style")?,
+      request_arg(&request, "background_image")?,
+      request_arg(&request, "output")?,
+    ),
+    "export_timeline" => {
+      check_resume_project(&request)?;
+      export_timeline(app, request_arg(&request, "output")?, request_arg(&request, "settings")?, None)?
+    }
+    "export_segment" => {
+      check_resume_project(&request)?;
+      export_segment(
+        app,
+        request_arg(&request, "track_id")?,
+        request_arg(&request, "segment_id")?,
+        request_arg(&request, "settings")?,
+        request_arg(&request, "output")?,
+      )?
+    }
+    other => return Err(format!("jobs of kind {} can't be resumed", other)),
+  };
+  jobs::close_pending_job(&job_id, jobs::JobStatus::Cancelled, &format!("resumed as job {}", new_id))
+    .map_err(|e| e.to_string())?;
+  Ok(new_id)
+}
+
+/// Drop a pending job without running it
+#[tauri::command]
+fn discard_pending_job(job_id: String) -> Result<(), String> {
+  jobs::get_pending_jobs()
+    .map_err(|e| e.to_string())?
+    .iter()
+    .find(|j| j.id == job_id)
+    .ok_or_else(|| format!("no pending job {}", job_id))?;
+  jobs::close_pending_job(&job_id, jobs::JobStatus::Cancelled, "discarded").map_err(|e| e.to_string())
+}
+
+// Media verification
+
+/// Re-hash the project's media against its ingest hashes. Runs as a job; returns the job id
+#[tauri::command]
+fn verify_project_media(app: tauri::AppHandle, deep: Option<bool>) -> Result<String, String> {
+  if !project_file::has_project() {
+    return Err("no project is currently loaded".to_string());
+  }
+  let deep = deep.unwrap_or(false);
+  let params = if deep { "deep".to_string() } else { "xxh64".to_string() };
+  Ok(jobs::spawn_job(&app, "verify_project_media", params, move |job| {
+    let report = media_hash::verify_project_media(deep, job)?;
+    Ok(serde_json::to_value(report)?)
+  }))
+}
+
+/// Regenerate the stale caches (proxy, waveform, thumbnail) of the given clips, or of every
+/// clip. Runs as a job; returns the job id
+#[tauri::command]
+fn warm_clip_caches(app: tauri::AppHandle, clip_ids: Option<Vec<String>>) -> Result<String, String> {
+  if !project_file::has_project() {
+    return Err("no project is currently loaded".to_string());
+  }
+  let params = match &clip_ids {
+    Some(ids) => format!("{} clips", ids.len()),
+    None => "all clips".to_string(),
+  };
+  Ok(jobs::spawn_job(&app, "warm_clip_caches", params, move |job| {
+    let results = clip_cache::warm_clip_caches(clip_ids.as_deref(), job)?;
+    Ok(serde_json::to_value(results)?)
+  }))
+}
+
+/// Generate what the agent needs from a clip (proxy, waveform, thumbnail, transcript and
+/// optionally an analysis) as one job; see clip_prep.rs. Returns the job id
+#[tauri::command]
+fn prepare_clip(app: tauri::AppHandle, clip_id: String, options: Option<clip_prep::PrepareOptions>) -> Result<String, String> {
+  if !project_file::has_project() {
+    return Err("no project is currently loaded".to_string());
+  }
+  let options = options.unwrap_or_default();
+  let params = format!("clip {}", clip_id);
+  let events = app.clone();
+  Ok(jobs::spawn_job(&app, "prepare_clip", params, move |job| {
+    let report = clip_prep::prepare_clip(&events, &clip_id, &options, job)?;
+    Ok(serde_json::to_value(report)?)
+  }))
+}
+
+#[tauri::command]
+fn get_validation_report(export_settings: Option<ffmpeg::ExportSettings>) -> Result<project_file::ValidationReport, String> {
+  let project = project_file::get_project()?.ok_or_else(|| "no project is currently loaded".to_string())?;
+  let export_settings = export_settings.unwrap_or_default();
+  let mut report = project.validation_report();
+  report.warnings = project.export_warnings(&export_settings);
+  report.warnings.extend(encode_profile::export_warnings(&export_settings));
+  Ok(report)
+}
+
+/// Check the encode profile at `path` and make it the one exports are held to
+#[tauri::command]
+fn import_encode_profile(path: String) -> Result<encode_profile::EncodeProfile, String> {
+  encode_profile::import_encode_profile(std::path::Path::new(&path)).map_err(|e| e.to_string())
+}
+
+/// Write `profile`, or the imported one when None, to `path` for sharing
+#[tauri::command]
+fn export_encode_profile(path: String, profile: Option<encode_profile::EncodeProfile>) -> Result<encode_profile::EncodeProfile, String> {
+  encode_profile::export_encode_profile(std::path::Path::new(&path), profile).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_encode_profile() -> Option<encode_profile::EncodeProfile> {
+  encode_profile::active_profile()
+}
+
+#[tauri::command]
+fn clear_encode_profile() -> Result<(), String> {
+  encode_profile::clear_encode_profile().map_err(|e| e.to_string())
+}
+
+/// What keeps this machine from exporting as the imported encode profile asks
+#[tauri::command]
+fn check_encode_profile() -> Vec<String> {
+  encode_profile::active_profile().map(|profile| encode_profile::environment_warnings(&profile)).unwrap_or_default()
+}
+
+// Media library
+
+/// Hashes and copies the file, so it runs off the IPC thread
+#[tauri::command]
+async fn library_add(path: String, tags: Option<Vec<String>>) -> Result<media_library::LibraryItem, String> {
+  tokio::task::spawn_blocking(move || media_library::library_add(&path, tags.unwrap_or_default()))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn library_list(filter: Option<media_library::LibraryFilter>) -> Result<Vec<media_library::LibraryItem>, String> {
+  media_library::library_list(&filter.unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn library_set_tags(library_id: String, tags: Vec<String>) -> Result<media_library::LibraryItem, String> {
+  media_library::library_set_tags(&library_id, tags).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn library_import_into_project(library_id: String) -> Result<project_file::Clip, String> {
+  media_library::library_import_into_project(&library_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn library_remove(library_id: String, force: Option<bool>) -> Result<media_library::LibraryRemoveResult, String> {
+  media_library::library_remove(&library_id, force.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn library_thumbnail(library_id: String) -> Result<Option<String>, String> {
+  media_library::library_thumbnail(&library_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn library_peaks(library_id: String) -> Result<Option<Vec<i16>>, String> {
+  media_library::library_peaks(&library_id).map_err(|e| e.to_string())
+}
+
 // Longterm storage
 
 #[tauri::command]
@@ -243,6 +1711,90 @@ fn get_recent_projects() -> Result<Vec<String>, String> {
   longterm_storage::get_recent_projects().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_recent_project_locks() -> Result<Vec<project_lock::RecentProjectLock>, String> {
+  project_lock::recent_project_locks().map_err(|e| e.to_string())
+}
+
+/// `confirmed` must be true: the user agreed the other app's unsaved work may be lost
+#[tauri::command]
+fn force_unlock_project(path: String, confirmed: bool) -> Result<project_lock::LockStatus, String> {
+  project_lock::force_unlock(std::path::Path::new(&path), confirmed).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_settings() -> Result<longterm_storage::AppSettings, String> {
+  longterm_storage::get_settings().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_settings(app: tauri::AppHandle, settings: longterm_storage::AppSettings) -> Result<(), String> {
+  // Only the fields the settings page owns are taken from its copy, which may be stale
+  let current = longterm_storage::get_settings().map_err(|e| e.to_string())?;
+  let settings = settings.merged_into(current);
+  longterm_storage::set_settings(settings.clone()).map_err(|e| e.to_string())?;
+  settings_changed(&app, &settings);
+  Ok(())
+}
+
+#[tauri::command]
+async fn set_post_export_command(app: tauri::AppHandle, command: Option<String>) -> Result<bool, String> {
+  let enabled = post_export::set_command(&app, command).map_err(|e| e.to_string())?;
+  if let Ok(settings) = longterm_storage::get_settings() {
+    settings_changed(&app, &settings);
+  }
+  Ok(enabled)
+}
+
+/// POST a sample payload to the configured webhook; returns the HTTP status
+#[tauri::command]
+async fn test_webhook() -> Result<u16, String> {
+  tokio::task::spawn_blocking(webhook::test_webhook)
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{:#}", e))
+}
+
+#[tauri::command]
+fn get_shortcuts() -> Result<std::collections::HashMap<String, String>, String> {
+  shortcuts::get_shortcuts().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_shortcut(
+  app: tauri::AppHandle,
+  action: String,
+  accelerator: String,
+) -> Result<std::collections::HashMap<String, String>, String> {
+  let map = shortcuts::set_shortcut(&action, &accelerator).map_err(|e| e.to_string())?;
+  let settings = longterm_storage::get_settings().map_err(|e| e.to_string())?;
+  settings_changed(&app, &settings);
+  Ok(map)
+}
+
+/// Re-register global shortcuts and tell every window the settings changed
+fn settings_changed(app: &tauri::AppHandle, settings: &longterm_storage::AppSettings) {
+  if let Err(e) = shortcuts::register_global_shortcuts(app, &settings.shortcuts) {
+    log::warn!("Failed to register global shortcuts: {}", e);
+  }
+  if let Err(e) = app.emit("settings-changed", settings.clone()) {
+    log::warn!("Failed to emit settings-changed: {}", e);
+  }
+}
+
+/// Run the media pipeline on a generated sample; see self_test.rs
+#[tauri::command]
+async fn run_self_test() -> Result<self_test::SelfTestReport, String> {
+  tokio::task::spawn_blocking(self_test::run_self_test)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_system_report() -> Result<ffmpeg::SystemReport, String> {
+  Ok(ffmpeg::system_report())
+}
+
 // AI Agent commands
 
 #[tauri::command]
@@ -250,12 +1802,22 @@ async fn process_ai_message(
   user_message: String,
   context: ai_agent::AgentContext,
 ) -> Result<ai_agent::AgentResponse, String> {
-  ai_agent::process_message(user_message, context).await
+  let response = ai_agent::process_message(user_message, context).await?;
+  ai_agent::note_proposal(&response);
+  Ok(response)
+}
+
+/// The edits the cut guard held back for `message_id`, once the user confirmed them
+#[tauri::command]
+async fn confirm_agent_proceed(message_id: String) -> Result<ai_agent::AgentResponse, String> {
+  let response = ai_agent::confirm_proceed(&message_id).await?;
+  ai_agent::note_proposal(&response);
+  Ok(response)
 }
 
 #[tauri::command]
-fn set_gemini_api_key(api_key: String) -> Result<(), String> {
-  ai_agent::set_api_key(api_key)
+async fn set_gemini_api_key(api_key: String) -> Result<(), String> {
+  ai_agent::set_api_key(api_key).await
 }
 
 #[tauri::command]
@@ -283,11 +1845,38 @@ async fn test_gemini_api() -> Result<String, String> {
   client.test_api_key().await
 }
 
+#[tauri::command]
+fn get_api_queue_status() -> api_quota::ApiQueueStatus {
+  api_quota::get_api_queue_status()
+}
+
 #[tauri::command]
 async fn reset_ai_agent() -> Result<(), String> {
   ai_agent::reset_processing_lock().await
 }
 
+/// Per-intent agent request counts over the last `window` seconds (everything kept when None)
+#[tauri::command]
+fn get_agent_metrics(window: Option<u64>) -> Result<agent_metrics::AgentMetrics, String> {
+  agent_metrics::get_metrics(window).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn record_agent_decision(message_id: String, accepted: bool) -> Result<(), String> {
+  ai_agent::settle_proposal(&message_id);
+  agent_metrics::record_decision(Some(&message_id), accepted).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_agent_metrics_enabled(enabled: bool) -> Result<(), String> {
+  agent_metrics::set_enabled(enabled).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_agent_metrics() -> Result<(), String> {
+  agent_metrics::clear().map_err(|e| e.to_string())
+}
+
 // Streaming preview commands
 use tauri::Emitter;
 
@@ -296,9 +1885,11 @@ async fn start_streaming_preview(
   app: tauri::AppHandle,
   clips: Vec<streaming_encoder::StreamingSegment>,
   width: u32,
-) -> Result<(), String> {
+) -> Result<String, String> {
+  let stream_id = stream_prefetch::open_stream(clips.clone(), width);
+  let id = stream_id.clone();
   std::thread::spawn(move || {
-    match streaming_encoder::generate_streaming_preview(clips, width) {
+    match streaming_encoder::generate_streaming_preview(id.clone(), clips, width) {
       Ok((rx, handle)) => {
         // Stream chunks to frontend
         while let Ok(chunk) = rx.recv() {
@@ -321,19 +1912,150 @@ async fn start_streaming_preview(
         let _ = app.emit("preview-error", format!("{}", e));
       }
     }
+    stream_prefetch::close_stream(&id);
   });
   
-  Ok(())
+  Ok(stream_id)
+}
+
+/// Playhead position for a preview stream, so its prefetcher encodes what plays next.
+/// The frontend calls this on a timer until it returns false (stream finished)
+#[tauri::command]
+fn set_playhead_hint(stream_id: String, time: f64, playing: bool) -> bool {
+  stream_prefetch::set_playhead_hint(&stream_id, time, playing)
+}
+
+// Frame stepping commands
+
+/// Open a frame stepper on a video clip at `time` (default 0); the result holds its id and
+/// that frame. At most a few are open at once, and idle ones close themselves
+#[tauri::command]
+async fn open_frame_stepper(clip_id: String, time: Option<f64>, max_width: Option<u32>) -> Result<frame_stepper::StepperInfo, String> {
+  tokio::task::spawn_blocking(move || frame_stepper::open(&clip_id, time, max_width))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// The next or previous frame of a stepper, as base64 JPEG
+#[tauri::command]
+async fn step_frame(stepper_id: String, direction: frame_stepper::StepDirection) -> Result<frame_stepper::SteppedFrame, String> {
+  tokio::task::spawn_blocking(move || frame_stepper::step(&stepper_id, direction))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Move a stepper to the frame at `time`, e.g. after the playhead moved
+#[tauri::command]
+async fn seek_frame_stepper(stepper_id: String, time: f64) -> Result<frame_stepper::SteppedFrame, String> {
+  tokio::task::spawn_blocking(move || frame_stepper::seek(&stepper_id, time))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn close_frame_stepper(stepper_id: String) {
+  frame_stepper::close(&stepper_id);
 }
 
 fn main() {
+  // Support commands (--inspect, --probe) print and exit before any window exists
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if let Some(code) = cli::run(&args) {
+    std::process::exit(code);
+  }
+
   tauri::Builder::default()
     .plugin(tauri_plugin_dialog::init())
+    .plugin(tauri_plugin_notification::init())
+    .plugin(tauri_plugin_clipboard_manager::init())
+    .plugin(
+      tauri_plugin_global_shortcut::Builder::new()
+        .with_handler(shortcuts::handle_global_shortcut)
+        .build(),
+    )
+    .register_uri_scheme_protocol(media_protocol::SCHEME, |_ctx, request| media_protocol::handle_request(&request))
+    .setup(|app| {
+      // Resolve the work directories up front. No page is listening yet, so the UI asks for
+      // the fallbacks with get_work_dir_fallbacks once it has loaded
+      for fallback in work_dirs::check() {
+        log::warn!("Using {:?} for {:?} instead of {:?}: {}", fallback.chosen, fallback.dir, fallback.preferred, fallback.reason);
+      }
+      // Locks left by a crashed session would otherwise block their projects
+      match project_lock::clean_stale_locks() {
+        Ok(recovered) if !recovered.is_empty() => log::info!("Removed stale locks of {:?}", recovered),
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to clean up stale project locks: {}", e),
+      }
+      // Exports queued or interrupted when the app last exited become pending jobs; the home
+      // page offers to resume them (get_pending_jobs) once it has loaded
+      if let Err(e) = jobs::recover_interrupted_jobs() {
+        log::warn!("Failed to check job history for interrupted jobs: {}", e);
+      }
+      match shortcuts::get_shortcuts() {
+        Ok(map) => {
+          if let Err(e) = shortcuts::register_global_shortcuts(app.handle(), &map) {
+            log::warn!("Failed to register global shortcuts: {}", e);
+          }
+        }
+        Err(e) => log::warn!("Failed to load shortcuts: {}", e),
+      }
+      Ok(())
+    })
+    .on_window_event(|window, event| {
+      use tauri::Manager;
+      if let tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) = event {
+        file_drop::handle_drop(window.app_handle().clone(), window.label().to_string(), paths.clone());
+      }
+      if let tauri::WindowEvent::Destroyed = event {
+        waveform::cancel_for_window(window.label());
+      }
+      if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+        if exit_guard::hold_close(window) {
+          api.prevent_close();
+        }
+      }
+      editor_windows::handle_event(window, event);
+    })
     .invoke_handler(tauri::generate_handler![
       probe_video,
+      ffprobe_full,
       audio_peaks,
+      audio_peaks_ex,
+      loudness_profile,
+      get_spectrogram,
+      suggest_denoise_filter,
+      audio_waveform,
+      find_clipping,
+      get_clip_details,
+      detect_dead_air,
+      detect_silence,
+      score_engagement,
+      get_work_dir_fallbacks,
+      tighten_silences,
       export_cutlist,
+      get_export_status,
+      load_export_sidecar,
+      reproduce_export_edits,
+      export_audiogram,
+      export_parts,
+      export_project,
+      export_timeline,
+      get_export_history,
       make_preview_proxy,
+      get_proxy_cache_size,
+      clear_proxy_cache,
+      get_waveform_cache_size,
+      clear_waveform_cache,
+      list_available_encoders,
+      get_export_presets,
+      measure_loudness,
+      save_export_preset,
+      delete_export_preset,
+      get_media_url,
+      revoke_media_token,
       read_file_as_base64,
       download_audio_file,
       copy_to_app_data,
@@ -341,39 +2063,149 @@ fn main() {
       read_file_chunk,
       get_file_size,
       generate_thumbnails,
+      clear_thumbnail_cache,
+      extract_frame,
       extract_album_art,
+      get_poster_url,
+      is_audio_only_project,
+      get_audio_metadata,
       generate_timeline_preview,
       generate_adaptive_timeline_preview,
       resize_window,
       center_window,
       set_fullscreen,
       create_editor_window,
+      close_editor_window,
+      get_exit_blockers,
+      confirm_close,
       focus_main_window,
       // ProjectFile commands
       new_project,
+      create_project_from_media,
+      create_sample_project,
+      align_clips_by_timecode,
+      run_edit_script,
       load_project,
       save_project,
       update_project,
       get_project,
       single_read_project,
+      restore_project_backup,
+      undo_project,
+      batch_update_segments,
+      repair_duplicate_ids,
+      update_track,
+      transact,
+      get_view_state,
+      set_view_state,
+      set_clip_transcript,
+      set_clip_language,
+      set_clip_audio_stream,
+      replace_clip_source,
+      export_transcript,
+      remap_to_output_time,
+      get_output_markers,
+      generate_chapters,
+      find_retention_risks,
+      retention_risk_markers,
+      add_markers,
+      build_montage,
+      set_clip_preview_lut,
+      snap_cut_to_sentence,
+      render_timeline_overview,
+      get_guide_rects,
+      bake_segment,
+      export_segment,
+      unbake_segment,
+      conform_vfr,
+      time_stretch_clip,
+      stretch_segment_to_fit,
+      retarget_clip,
+      // Job commands
+      get_job,
+      list_jobs,
+      cancel_job,
+      pause_job,
+      resume_job,
+      cancel_export,
+      export_cutlist_lossless,
+      export_animated,
+      get_job_history,
+      clear_job_history,
+      get_pending_jobs,
+      resume_pending_job,
+      discard_pending_job,
+      verify_project_media,
+      warm_clip_caches,
+      prepare_clip,
+      get_validation_report,
+      import_encode_profile,
+      export_encode_profile,
+      get_encode_profile,
+      clear_encode_profile,
+      check_encode_profile,
+      // Media library commands
+      library_add,
+      library_list,
+      library_set_tags,
+      library_import_into_project,
+      library_remove,
+      library_thumbnail,
+      library_peaks,
       // Longterm storage commands
       add_recent_project,
       get_recent_projects,
+      get_recent_project_locks,
+      force_unlock_project,
+      get_settings,
+      update_settings,
+      set_post_export_command,
+      test_webhook,
+      get_shortcuts,
+      set_shortcut,
+      get_system_report,
+      run_self_test,
       // AI Agent commands
       process_ai_message,
+      confirm_agent_proceed,
       set_gemini_api_key,
       get_gemini_api_key,
       has_gemini_api_key,
       generate_chat_name,
       test_gemini_api,
       reset_ai_agent,
+      get_agent_metrics,
+      record_agent_decision,
+      set_agent_metrics_enabled,
+      clear_agent_metrics,
+      get_api_queue_status,
       // Transcription commands
       transcribe_media_file,
       // Video analysis commands
       analyze_video_file,
       // Streaming preview commands
-      start_streaming_preview
+      start_streaming_preview,
+      set_playhead_hint,
+      // Frame stepping commands
+      open_frame_stepper,
+      step_frame,
+      seek_frame_stepper,
+      close_frame_stepper
     ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while running tauri application")
+    .run(|app, event| {
+      // Quitting is held back like closing a window; an explicit exit (code set) goes ahead
+      if let tauri::RunEvent::ExitRequested { code: None, api, .. } = &event {
+        if exit_guard::hold_exit(app) {
+          api.prevent_exit();
+        }
+      }
+      // Unlock the open project, so a clean exit isn't taken for a crash next launch
+      if let tauri::RunEvent::Exit = event {
+        if let Err(e) = project_file::close_project() {
+          log::warn!("Failed to close the project on exit: {}", e);
+        }
+      }
+    });
 }
@@ -0,0 +1,382 @@
+//! Ingest checksums: an xxHash64 (and optionally SHA-256) of each clip's file, recorded at
+//! import so later changes to the media can be detected. Also hashes of the decoded video and
+//! audio streams, which cached artifacts are checked against (see clip_cache.rs).
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+use xxhash_rust::xxh64::Xxh64;
+use crate::jobs::{self, JobContext};
+use crate::longterm_storage;
+use crate::project_file::{self, Clip, HashCheck, HashStatus, MediaHashes, StreamHashes, StreamKind};
+
+/// Read size per step; memory use stays at one buffer regardless of file size
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+pub struct FileHashes {
+  pub size: u64,
+  pub xxh64: String,
+  pub sha256: Option<String>,
+}
+
+/// Stream `path` through the hashers. `should_stop` is checked between reads;
+/// `on_bytes` receives the number of bytes hashed so far.
+pub fn hash_file(
+  path: &Path,
+  sha256: bool,
+  should_stop: &dyn Fn() -> bool,
+  on_bytes: &mut dyn FnMut(u64),
+) -> Result<FileHashes> {
+  let mut file = File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+  let mut xxh = Xxh64::new(0);
+  let mut sha = sha256.then(Sha256::new);
+  let mut buffer = vec![0u8; BUFFER_SIZE];
+  let mut size = 0u64;
+  loop {
+    if should_stop() {
+      return Err(anyhow!("hashing cancelled"));
+    }
+    let n = file.read(&mut buffer).with_context(|| format!("failed to read {:?}", path))?;
+    if n == 0 {
+      break;
+    }
+    xxh.update(&buffer[..n]);
+    if let Some(sha) = sha.as_mut() {
+      sha.update(&buffer[..n]);
+    }
+    size += n as u64;
+    on_bytes(size);
+  }
+  Ok(FileHashes {
+    size,
+    xxh64: format!("{:016x}", xxh.digest()),
+    sha256: sha.map(|sha| format!("{:x}", sha.finalize())),
+  })
+}
+
+/// Hashes to record for a newly imported file. SHA-256 is included when enabled in settings.
+/// Stops when the job the current thread works for is cancelled
+pub fn ingest_hashes(path: &Path) -> Result<MediaHashes> {
+  let sha256 = longterm_storage::get_settings().map(|s| s.ingest_sha256).unwrap_or(false);
+  let hashes = hash_file(path, sha256, &jobs::current_job_cancelled, &mut |_| {})?;
+  Ok(MediaHashes {
+    size: hashes.size,
+    xxh64: hashes.xxh64,
+    sha256: hashes.sha256,
+    hashed_at: chrono::Utc::now().to_rfc3339(),
+    last_check: None,
+  })
+}
+
+/// --- Stream hashes -----------------------------------------------------------------
+
+/// murmur3 of the first `kind` stream of `path`, decoded, or None if the file has no such
+/// stream. Decodes the whole stream, so it costs about as much as a transcode
+pub fn stream_hash(path: &Path, kind: StreamKind) -> Result<Option<String>> {
+  // "V" skips attached pictures, so album art isn't taken for a video stream
+  let map = match kind {
+    StreamKind::Video => "0:V:0?",
+    StreamKind::Audio => "0:a:0?",
+  };
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-i"]).arg(path).args(["-map", map, "-f", "hash", "-hash", "murmur3", "-"]);
+  // Decodes the whole stream, so a cancelled job kills it rather than waiting it out
+  let output = jobs::output_cancellable(&mut cmd, None).with_context(|| "failed to spawn ffmpeg for stream hash")?;
+  if jobs::current_job_cancelled() {
+    return Err(anyhow!("cancelled"));
+  }
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  if !output.status.success() {
+    if stderr.contains("does not contain any stream") {
+      return Ok(None);
+    }
+    return Err(anyhow!("ffmpeg stream hash of {:?} failed: {}", path, stderr));
+  }
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let hash = stdout
+    .trim()
+    .strip_prefix("murmur3=")
+    .ok_or_else(|| anyhow!("unexpected ffmpeg hash output: {}", stdout.trim()))?;
+  Ok(Some(hash.to_string()))
+}
+
+/// File size and modification time (Unix seconds) that stream hashes are taken against
+pub fn file_stamp(path: &Path) -> Result<(u64, u64)> {
+  let metadata = std::fs::metadata(path).with_context(|| format!("failed to read {:?}", path))?;
+  let modified = metadata
+    .modified()
+    .ok()
+    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  Ok((metadata.len(), modified))
+}
+
+/// Hashes of every video and audio stream of `path`
+pub fn stream_hashes(path: &Path) -> Result<StreamHashes> {
+  let (size, modified) = file_stamp(path)?;
+  let mut streams = HashMap::new();
+  for kind in [StreamKind::Video, StreamKind::Audio] {
+    if let Some(hash) = stream_hash(path, kind)? {
+      streams.insert(kind, hash);
+    }
+  }
+  Ok(StreamHashes { size, modified, streams })
+}
+
+/// --- Verification ------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MediaVerification {
+  pub clip_id: String,
+  pub path: String,
+  pub status: HashStatus,
+  pub detail: Option<String>, // What differed, or why a check was limited
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MediaVerificationReport {
+  pub deep: bool,
+  pub results: Vec<MediaVerification>,
+  pub mismatched: usize,
+  pub missing: usize,
+  #[serde(default)]
+  pub unreadable: usize,
+}
+
+/// Compare freshly computed hashes against the ingest record
+fn compare(recorded: &MediaHashes, actual: &FileHashes) -> (HashStatus, Option<String>) {
+  if actual.size != recorded.size {
+    return (HashStatus::Mismatch, Some(format!("size changed from {} to {} bytes", recorded.size, actual.size)));
+  }
+  if actual.xxh64 != recorded.xxh64 {
+    return (HashStatus::Mismatch, Some("xxh64 differs from ingest".to_string()));
+  }
+  match (&recorded.sha256, &actual.sha256) {
+    (Some(expected), Some(sha)) if expected != sha => (HashStatus::Mismatch, Some("SHA-256 differs from ingest".to_string())),
+    _ => (HashStatus::Verified, None),
+  }
+}
+
+/// Status of `clip`'s file against its ingest hashes, and what differed. A file that can't be
+/// read is `Unreadable`; only cancelling through `should_stop` is an error
+fn check_clip(
+  clip: &Clip,
+  deep: bool,
+  should_stop: &dyn Fn() -> bool,
+  on_bytes: &mut dyn FnMut(u64),
+) -> Result<(HashStatus, Option<String>)> {
+  let Some(recorded) = &clip.hashes else {
+    return Ok((HashStatus::Unhashed, None));
+  };
+  if !clip.path.is_file() {
+    return Ok((HashStatus::Missing, None));
+  }
+  let compare_sha = deep && recorded.sha256.is_some();
+  match hash_file(&clip.path, compare_sha, should_stop, on_bytes) {
+    Ok(actual) => {
+      let (status, detail) = compare(recorded, &actual);
+      Ok(match (deep, &recorded.sha256, detail) {
+        (true, None, None) => (status, Some("no SHA-256 recorded at import; checked xxh64 only".to_string())),
+        (_, _, detail) => (status, detail),
+      })
+    }
+    Err(_) if should_stop() => Err(anyhow!("media verification cancelled")),
+    Err(e) => {
+      log::warn!("Failed to verify {:?}: {:#}", clip.path, e);
+      Ok((HashStatus::Unreadable, Some(format!("{:#}", e))))
+    }
+  }
+}
+
+/// Re-hash every clip of the current project and compare against its ingest hashes.
+/// xxh64 only unless `deep`, which also compares SHA-256 where one was recorded.
+/// Results are stored on the clips as their `last_check`. A file that can't be read is reported
+/// as unreadable and the run goes on with the next clip. The job can be paused between clips.
+pub fn verify_project_media(deep: bool, job: &JobContext) -> Result<MediaVerificationReport> {
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let mut clips: Vec<_> = project.clips_map.values().cloned().collect();
+  clips.sort_by(|a, b| a.path.cmp(&b.path));
+
+  let total: u64 = clips
+    .iter()
+    .filter(|c| c.hashes.is_some())
+    .filter_map(|c| std::fs::metadata(&c.path).ok())
+    .map(|m| m.len())
+    .sum::<u64>()
+    .max(1);
+  let mut done = 0u64;
+  let mut results = Vec::with_capacity(clips.len());
+  let mut checks = HashMap::new();
+  let checked_at = chrono::Utc::now().to_rfc3339();
+  job.set_pausable();
+
+  for clip in &clips {
+    job.checkpoint().map_err(|_| anyhow!("media verification cancelled"))?;
+    let path = clip.path.to_string_lossy().to_string();
+    let name = clip.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.clone());
+    if clip.hashes.is_some() && clip.path.is_file() {
+      job.progress(done as f64 / total as f64, Some(&format!("Verifying {}", name)));
+    }
+    let mut on_bytes = |bytes: u64| {
+      // Progress every 64 MiB keeps event traffic low on large files
+      if bytes % (64 * BUFFER_SIZE as u64) == 0 {
+        job.progress((done + bytes) as f64 / total as f64, None);
+      }
+    };
+    let (status, detail) = check_clip(clip, deep, &|| job.is_cancelled(), &mut on_bytes)?;
+    if clip.hashes.is_some() {
+      done += std::fs::metadata(&clip.path).map(|m| m.len()).unwrap_or(0);
+    }
+    if clip.hashes.is_some() {
+      checks.insert(clip.id.clone(), HashCheck { status, deep, checked_at: checked_at.clone() });
+    }
+    results.push(MediaVerification { clip_id: clip.id.clone(), path, status, detail });
+  }
+
+  project_file::set_clip_hash_checks(checks)?;
+  Ok(MediaVerificationReport {
+    deep,
+    mismatched: results.iter().filter(|r| r.status == HashStatus::Mismatch).count(),
+    missing: results.iter().filter(|r| r.status == HashStatus::Missing).count(),
+    unreadable: results.iter().filter(|r| r.status == HashStatus::Unreadable).count(),
+    results,
+  })
+}
+
+/// Integrity of an export source, as recorded in the export sidecar
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceHashStatus {
+  pub path: String,
+  pub status: HashStatus,
+  pub xxh64: Option<String>,
+  pub sha256: Option<String>,
+  pub checked_at: Option<String>, // Last verification, if any
+}
+
+/// Ingest hashes and last verification of the current project's clip at `path`
+pub fn source_hash_status(path: &str) -> SourceHashStatus {
+  let clip = project_file::get_project()
+    .ok()
+    .flatten()
+    .and_then(|project| project.clips_at_path(Path::new(path)).first().map(|c| (*c).clone()));
+  let hashes = clip.as_ref().and_then(|c| c.hashes.clone());
+  SourceHashStatus {
+    path: path.to_string(),
+    status: clip.as_ref().map(|c| c.hash_status()).unwrap_or(HashStatus::Unhashed),
+    xxh64: hashes.as_ref().map(|h| h.xxh64.clone()),
+    sha256: hashes.as_ref().and_then(|h| h.sha256.clone()),
+    checked_at: hashes.and_then(|h| h.last_check).map(|c| c.checked_at),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use std::path::PathBuf;
+
+  /// XXH64 and SHA-256 of no bytes
+  const EMPTY_XXH64: &str = "ef46db3751d8e999";
+  const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+  fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn clip(path: &Path, hashes: Option<MediaHashes>) -> Clip {
+    let mut clip: Clip = serde_json::from_value(serde_json::json!({ "id": "clip_a", "path": path, "type": "Video" })).unwrap();
+    clip.hashes = hashes;
+    clip
+  }
+
+  fn recorded(path: &Path, sha256: bool) -> MediaHashes {
+    let hashes = hash_file(path, sha256, &|| false, &mut |_| {}).unwrap();
+    MediaHashes { size: hashes.size, xxh64: hashes.xxh64, sha256: hashes.sha256, hashed_at: String::new(), last_check: None }
+  }
+
+  #[test]
+  fn hashes_stream_through_in_buffer_steps() {
+    let dir = temp_dir();
+    let empty = dir.join("empty.bin");
+    fs::write(&empty, b"").unwrap();
+    let hashes = hash_file(&empty, true, &|| false, &mut |_| {}).unwrap();
+    assert_eq!((hashes.size, hashes.xxh64.as_str(), hashes.sha256.as_deref()), (0, EMPTY_XXH64, Some(EMPTY_SHA256)));
+
+    let large = dir.join("large.bin");
+    fs::write(&large, vec![7u8; BUFFER_SIZE * 2 + 10]).unwrap();
+    let mut reported = Vec::new();
+    let hashes = hash_file(&large, false, &|| false, &mut |bytes| reported.push(bytes)).unwrap();
+    assert_eq!(hashes.size, (BUFFER_SIZE * 2 + 10) as u64);
+    assert_eq!(hashes.sha256, None);
+    assert_eq!(reported.last(), Some(&hashes.size));
+    assert!(reported.len() >= 3);
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn hashing_stops_when_cancelled() {
+    let dir = temp_dir();
+    let large = dir.join("large.bin");
+    fs::write(&large, vec![7u8; BUFFER_SIZE * 3]).unwrap();
+    let read = std::cell::Cell::new(0u64);
+    let result = hash_file(&large, false, &|| read.get() >= BUFFER_SIZE as u64, &mut |bytes| read.set(bytes));
+    assert!(result.unwrap_err().to_string().contains("cancelled"));
+    assert_eq!(read.get(), BUFFER_SIZE as u64);
+    // Outside a job nothing cancels ingest hashing
+    assert!(ingest_hashes(&large).is_ok());
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn each_clip_gets_its_own_status() {
+    let dir = temp_dir();
+    let file = dir.join("clip.mp4");
+    fs::write(&file, b"original").unwrap();
+    let hashes = recorded(&file, true);
+    let check = |clip: &Clip, deep: bool| check_clip(clip, deep, &|| false, &mut |_| {}).unwrap();
+
+    assert_eq!(check(&clip(&file, None), false), (HashStatus::Unhashed, None));
+    assert_eq!(check(&clip(&file, Some(hashes.clone())), true), (HashStatus::Verified, None));
+    assert_eq!(check(&clip(&dir.join("gone.mp4"), Some(hashes.clone())), false), (HashStatus::Missing, None));
+
+    let xxh_only = MediaHashes { sha256: None, ..hashes.clone() };
+    let (status, detail) = check(&clip(&file, Some(xxh_only)), true);
+    assert_eq!(status, HashStatus::Verified);
+    assert!(detail.unwrap().contains("xxh64 only"));
+
+    fs::write(&file, b"re-edit!").unwrap();
+    assert_eq!(
+      check(&clip(&file, Some(hashes.clone())), false),
+      (HashStatus::Mismatch, Some("xxh64 differs from ingest".to_string()))
+    );
+    fs::write(&file, b"longer re-edit").unwrap();
+    assert_eq!(check(&clip(&file, Some(hashes.clone())), false).0, HashStatus::Mismatch);
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn an_unreadable_file_is_reported_not_fatal() {
+    // A regular file whose first read fails: address 0 of this process isn't mapped
+    let unreadable = Path::new("/proc/self/mem");
+    let hashes = MediaHashes { size: 0, xxh64: EMPTY_XXH64.to_string(), sha256: None, hashed_at: String::new(), last_check: None };
+    let (status, detail) = check_clip(&clip(unreadable, Some(hashes)), false, &|| false, &mut |_| {}).unwrap();
+    assert_eq!(status, HashStatus::Unreadable);
+    assert!(detail.unwrap().contains("failed to read"));
+
+    // Unless the run was cancelled, which stops it
+    let hashes = MediaHashes { size: 0, xxh64: EMPTY_XXH64.to_string(), sha256: None, hashed_at: String::new(), last_check: None };
+    assert!(check_clip(&clip(unreadable, Some(hashes)), false, &|| true, &mut |_| {}).is_err());
+  }
+}
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ffmpeg::{self, Probe};
+use crate::ids;
+use crate::longterm_storage;
+use crate::project_file::{self, Clip, ClipType, MediaHashes, ProjectFile};
+use crate::thumbnail_cache;
+use crate::waveform;
+use crate::waveform_cache;
+use crate::work_dirs::{self, WorkDir};
+
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// A media file kept in the global library, shared by every project
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryItem {
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf, // The library's own copy; imported clips point here
+    pub original_path: PathBuf, // Where the file was added from
+    pub r#type: ClipType,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub probe: Option<Probe>, // Cached probe, None for images
+    #[serde(default)]
+    pub hashes: Option<MediaHashes>, // Ingest hashes, copied to clips imported from the library
+    #[serde(default)]
+    pub thumbnail: Option<PathBuf>, // Cached PNG
+    #[serde(default)]
+    pub peaks: Option<PathBuf>, // Cached waveform peaks, see waveform_cache
+    pub added_at: String, // ISO 8601
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct LibraryIndex {
+    items: Vec<LibraryItem>,
+}
+
+/// Narrows `library_list`. Empty fields match everything
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LibraryFilter {
+    #[serde(default)]
+    pub text: Option<String>, // Case-insensitive substring of the name
+    #[serde(default)]
+    pub tags: Vec<String>, // Item must have every tag
+    #[serde(default)]
+    pub r#type: Option<ClipType>,
+}
+
+impl LibraryFilter {
+    fn matches(&self, item: &LibraryItem) -> bool {
+        let text_ok = match self.text.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            Some(text) => item.name.to_lowercase().contains(&text.to_lowercase()),
+            None => true,
+        };
+        let tags_ok = self.tags.iter().all(|tag| item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+        let type_ok = self.r#type.as_ref().map_or(true, |t| *t == item.r#type);
+        text_ok && tags_ok && type_ok
+    }
+}
+
+/// A project with clips pointing at a library item
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectReference {
+    pub project: String, // Project path, or title for an unsaved open project
+    pub segment_count: usize, // Timeline segments playing the item; 0 if only imported
+}
+
+/// Outcome of `library_remove`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryRemoveResult {
+    pub removed: bool, // False when projects reference the item and removal wasn't forced
+    pub referencing_projects: Vec<ProjectReference>,
+}
+
+/// Library folder (under app data unless that isn't writable), created if missing
+fn library_dir() -> Result<PathBuf> {
+    work_dirs::path(WorkDir::Library)
+}
+
+fn index_path() -> Result<PathBuf> {
+    Ok(library_dir()?.join("library.json"))
+}
+
+fn load_index() -> Result<LibraryIndex> {
+    let path = index_path()?;
+    if !path.exists() {
+        return Ok(LibraryIndex::default());
+    }
+    let data = fs::read_to_string(&path).with_context(|| format!("Failed to read library index at {:?}", path))?;
+    serde_json::from_str(&data).with_context(|| "Failed to parse library index")
+}
+
+fn save_index(index: &LibraryIndex) -> Result<()> {
+    let path = index_path()?;
+    let data = serde_json::to_string_pretty(index).with_context(|| "Failed to serialize library index")?;
+    fs::write(&path, data).with_context(|| format!("Failed to write library index at {:?}", path))
+}
+
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags.into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Best-effort thumbnail and peaks cache; a failure only means the UI shows a placeholder
+fn build_cache(item: &mut LibraryItem, cache_dir: &Path) {
+    let path = item.path.to_string_lossy().to_string();
+    if item.r#type == ClipType::Video {
+        let thumbnail = ffmpeg::generate_thumbnails(&path, 1, THUMBNAIL_WIDTH)
+            .and_then(|thumbs| thumbs.into_iter().next().ok_or_else(|| anyhow!("no frame")))
+            .and_then(|b64| Ok(base64::engine::general_purpose::STANDARD.decode(b64)?))
+            .and_then(|png| {
+                let file = cache_dir.join("thumbnail.png");
+                fs::write(&file, png)?;
+                Ok(file)
+            });
+        match thumbnail {
+            Ok(file) => item.thumbnail = Some(file),
+            Err(e) => log::warn!("Library thumbnail failed for {}: {}", item.name, e),
+        }
+    }
+    if item.r#type == ClipType::Audio {
+        let art = thumbnail_cache::album_art(&path)
+            .and_then(|art| art.map(|b64| Ok(base64::engine::general_purpose::STANDARD.decode(b64)?)).transpose())
+            .and_then(|png| match png {
+                Some(png) => {
+                    let file = cache_dir.join("thumbnail.png");
+                    fs::write(&file, png)?;
+                    Ok(Some(file))
+                }
+                None => Ok(None),
+            });
+        match art {
+            Ok(file) => item.thumbnail = file,
+            Err(e) => log::warn!("Library album art failed for {}: {}", item.name, e),
+        }
+    }
+    if item.r#type != ClipType::Image {
+        let audio_stream = ffmpeg::default_audio_stream(&path);
+        let peaks = waveform_cache::mixed_peaks(&path, audio_stream)
+            .and_then(|_| waveform_cache::entry_path(&path, audio_stream, false));
+        match peaks {
+            Ok(file) => item.peaks = Some(file),
+            Err(e) if e.is::<waveform::WaveformError>() => {} // Video without audio: no lane to draw
+            Err(e) => log::warn!("Library peaks failed for {}: {}", item.name, e),
+        }
+    }
+}
+
+/// Copy `path` into the library with `tags`, caching its probe, thumbnail and peaks
+pub fn library_add(path: &str, tags: Vec<String>) -> Result<LibraryItem> {
+    let source = Path::new(path);
+    if !source.is_file() {
+        return Err(anyhow!("File does not exist: {}", path));
+    }
+    let name = source.file_name()
+        .ok_or_else(|| anyhow!("Invalid filename: {}", path))?
+        .to_string_lossy()
+        .to_string();
+    // The copy is byte-identical, so the source's ingest hashes describe it
+    let Clip { r#type, latest_probe: probe, hashes, .. } = project_file::import_media(source)?;
+
+    let id = ids::new_id(ids::LIBRARY_ITEM);
+    let item_dir = library_dir()?.join(&id);
+    fs::create_dir_all(&item_dir).with_context(|| format!("Failed to create {:?}", item_dir))?;
+    let library_path = item_dir.join(&name);
+    fs::copy(source, &library_path).with_context(|| format!("Failed to copy {} into the library", path))?;
+
+    let mut item = LibraryItem {
+        id,
+        name,
+        path: library_path,
+        original_path: source.to_path_buf(),
+        r#type,
+        tags: normalize_tags(tags),
+        probe,
+        hashes,
+        thumbnail: None,
+        peaks: None,
+        added_at: chrono::Utc::now().to_rfc3339(),
+    };
+    build_cache(&mut item, &item_dir);
+
+    let mut index = load_index()?;
+    index.items.push(item.clone());
+    save_index(&index)?;
+    Ok(item)
+}
+
+/// Library items matching `filter`, newest first
+pub fn library_list(filter: &LibraryFilter) -> Result<Vec<LibraryItem>> {
+    let mut items: Vec<LibraryItem> = load_index()?.items.into_iter()
+        .filter(|item| filter.matches(item))
+        .collect();
+    items.reverse();
+    Ok(items)
+}
+
+pub fn library_get(library_id: &str) -> Result<LibraryItem> {
+    load_index()?.items.into_iter()
+        .find(|item| item.id == library_id)
+        .ok_or_else(|| anyhow!("library item not found: {}", library_id))
+}
+
+/// The library item whose copy is at `path`, i.e. the item a clip was imported from
+pub fn library_item_at(path: &Path) -> Result<Option<LibraryItem>> {
+    Ok(load_index()?.items.into_iter().find(|item| item.path == path))
+}
+
+/// Replace an item's tags
+pub fn library_set_tags(library_id: &str, tags: Vec<String>) -> Result<LibraryItem> {
+    let mut index = load_index()?;
+    let item = index.items.iter_mut()
+        .find(|item| item.id == library_id)
+        .ok_or_else(|| anyhow!("library item not found: {}", library_id))?;
+    item.tags = normalize_tags(tags);
+    let updated = item.clone();
+    save_index(&index)?;
+    Ok(updated)
+}
+
+/// Register the item as a clip of the current project. The clip points at the library file
+/// and reuses the cached probe; importing the same item twice returns the existing clip
+pub fn library_import_into_project(library_id: &str) -> Result<Clip> {
+    let item = library_get(library_id)?;
+    if !item.path.is_file() {
+        return Err(anyhow!("library file is missing: {:?}", item.path));
+    }
+    let creation_time = item.probe.as_ref().and_then(|p| p.creation_time.clone());
+    let has_album_art = match item.r#type {
+        ClipType::Audio => project_file::probe_album_art(&item.path),
+        _ => None,
+    };
+    project_file::add_clip(Clip {
+        id: ids::new_id(ids::CLIP),
+        path: item.path,
+        latest_probe: item.probe,
+        r#type: item.r#type,
+        transcript: None,
+        derived_from: None,
+        hashes: item.hashes,
+        preview_lut: None,
+        stream_hashes: None,
+        creation_time,
+        ready_for_ai: None,
+        transcript_language: None,
+        language_override: None,
+        has_album_art,
+        audio_stream_index: None,
+    })
+}
+
+/// Reference to `path` from `project`, if any of its clips point there
+fn project_reference(project: &ProjectFile, name: String, path: &Path) -> Option<ProjectReference> {
+    let clips = project.clips_at_path(path);
+    if clips.is_empty() {
+        return None;
+    }
+    let segment_count = clips.iter().map(|c| project.clip_usage(&c.id)).sum();
+    Some(ProjectReference { project: name, segment_count })
+}
+
+/// Recent projects (and the open one) with a clip pointing at the item's file
+pub fn projects_referencing(item: &LibraryItem) -> Result<Vec<ProjectReference>> {
+    let mut references = Vec::new();
+    let mut open_path = None;
+    if let Some(project) = project_file::get_project().map_err(|e| anyhow!(e))? {
+        let name = project.path.as_ref().map(|p| p.to_string_lossy().to_string()).unwrap_or_else(|| project.title.clone());
+        open_path = Some(name.clone());
+        references.extend(project_reference(&project, name, &item.path));
+    }
+    for path in longterm_storage::get_recent_projects()? {
+        if open_path.as_ref() == Some(&path) {
+            continue;
+        }
+        // Unreadable projects can't be checked; they are skipped rather than blocking removal
+        match project_file::single_read_project(path.clone()) {
+            Ok(project) => references.extend(project_reference(&project, path, &item.path)),
+            Err(e) => log::warn!("Skipping {} while checking library usage: {}", path, e),
+        }
+    }
+    Ok(references)
+}
+
+/// Delete a library item and its cache. When recent projects still reference it, nothing is
+/// removed unless `force` is set; the referencing projects are returned either way
+pub fn library_remove(library_id: &str, force: bool) -> Result<LibraryRemoveResult> {
+    let item = library_get(library_id)?;
+    let referencing_projects = projects_referencing(&item)?;
+    if !referencing_projects.is_empty() && !force {
+        return Ok(LibraryRemoveResult { removed: false, referencing_projects });
+    }
+
+    let mut index = load_index()?;
+    index.items.retain(|i| i.id != library_id);
+    save_index(&index)?;
+    let item_dir = library_dir()?.join(&item.id);
+    if item_dir.exists() {
+        fs::remove_dir_all(&item_dir).with_context(|| format!("Failed to delete {:?}", item_dir))?;
+    }
+    Ok(LibraryRemoveResult { removed: true, referencing_projects })
+}
+
+/// Cached thumbnail as base64 PNG, if one was generated
+pub fn library_thumbnail(library_id: &str) -> Result<Option<String>> {
+    match library_get(library_id)?.thumbnail {
+        Some(path) => {
+            let png = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            Ok(Some(base64::engine::general_purpose::STANDARD.encode(png)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Cached waveform peaks, in the same format as `waveform::pcm_peaks`. Read through
+/// waveform_cache, which decodes them again if the entry is gone or stale
+pub fn library_peaks(library_id: &str) -> Result<Option<Vec<i16>>> {
+    let item = library_get(library_id)?;
+    if item.peaks.is_none() {
+        return Ok(None);
+    }
+    let path = item.path.to_string_lossy().to_string();
+    waveform_cache::mixed_peaks(&path, ffmpeg::default_audio_stream(&path)).map(Some)
+}
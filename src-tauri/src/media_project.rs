@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::ids;
+use crate::longterm_storage;
+use crate::project_file::{self, Clip, ClipType, FadeCurve, ProjectFile, ProjectSettings, Segment, Track, TrackType};
+
+/// Timeline length given to image clips
+pub const DEFAULT_IMAGE_DURATION: f64 = 5.0;
+
+/// Order the files are laid out on the timeline
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum MediaOrder {
+    #[default]
+    Filename,
+    CreationTime, // Oldest first; falls back to modification time where creation time is unavailable
+}
+
+/// A file that could not be added to the new project
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectFromMedia {
+    pub project: ProjectFile,
+    pub failures: Vec<ImportFailure>, // Files that were skipped, in the order given
+}
+
+fn creation_time(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|m| m.created().or_else(|_| m.modified()))
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+fn sort_paths(paths: &mut [PathBuf], order: MediaOrder) {
+    match order {
+        MediaOrder::Filename => paths.sort_by_key(|p| p.file_name().map(|n| n.to_string_lossy().to_lowercase())),
+        MediaOrder::CreationTime => paths.sort_by_cached_key(|p| creation_time(p)),
+    }
+}
+
+pub fn empty_track(name: &str, r#type: TrackType, order: u32) -> Track {
+    Track {
+        id: ids::new_id(ids::TRACK),
+        name: name.to_string(),
+        r#type,
+        enabled: true,
+        muted: false,
+        volume: 100,
+        pan: 0,
+        order,
+        track_effects: Vec::new(),
+        segments: Vec::new(),
+    }
+}
+
+/// Segment covering the whole clip; images get `DEFAULT_IMAGE_DURATION`
+pub fn full_segment(clip: &Clip) -> Result<Segment> {
+    let end = match clip.r#type {
+        ClipType::Image => DEFAULT_IMAGE_DURATION,
+        _ => clip.latest_probe.as_ref().map(|p| p.duration).unwrap_or(0.0),
+    };
+    if end <= 0.0 {
+        return Err(anyhow!("media has no duration"));
+    }
+    Ok(Segment {
+        id: ids::new_id(ids::SEGMENT),
+        clip_id: clip.id.clone(),
+        start: 0.0,
+        end,
+        label: None,
+        gain: 0.0,
+        fade_in: 0.0,
+        fade_out: 0.0,
+        fade_curve: FadeCurve::Linear,
+        video_fade_in: 0.0,
+        video_fade_out: 0.0,
+        speed: 1.0,
+        effects: Vec::new(),
+        baked_from: None,
+        timeline_start: None,
+    })
+}
+
+/// Create a project at `project_path` with every file in `paths` imported and laid end to end:
+/// video and images on a video track, audio on an audio track. Files that fail to import are
+/// reported in `failures` rather than aborting the rest. The project is saved, becomes the
+/// current project and is added to recent projects
+pub fn create_project_from_media(
+    paths: Vec<String>,
+    project_path: &str,
+    title: &str,
+    order: MediaOrder,
+) -> Result<ProjectFromMedia> {
+    if paths.is_empty() {
+        return Err(anyhow!("no media files given"));
+    }
+    let mut paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    sort_paths(&mut paths, order);
+
+    let mut video = empty_track("Video Track 1", TrackType::Video, 0);
+    let mut audio = empty_track("Audio Track 1", TrackType::Audio, 1);
+    let mut clips_map = HashMap::new();
+    let mut failures = Vec::new();
+
+    for path in paths {
+        let imported = project_file::import_media(&path).and_then(|clip| Ok((full_segment(&clip)?, clip)));
+        match imported {
+            Ok((segment, clip)) => {
+                let track = if clip.r#type == ClipType::Audio { &mut audio } else { &mut video };
+                track.segments.push(segment);
+                clips_map.insert(clip.id.clone(), clip);
+            }
+            Err(e) => {
+                log::warn!("Skipping {:?} in new project: {}", path, e);
+                failures.push(ImportFailure { path: path.to_string_lossy().to_string(), error: e.to_string() });
+            }
+        }
+    }
+    if clips_map.is_empty() {
+        return Err(anyhow!("none of the files could be imported: {}", failures[0].error));
+    }
+
+    let title = match title.trim() {
+        "" => Path::new(project_path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+        title => title.to_string(),
+    };
+    let project = ProjectFile {
+        title,
+        version: project_file::PROJECT_VERSION,
+        clips_map,
+        tracks_map: HashMap::from([(video.id.clone(), video), (audio.id.clone(), audio)]),
+        path: Some(PathBuf::from(project_path)),
+        markers: Vec::new(),
+        accepted_cuts: Vec::new(),
+        settings: ProjectSettings::default(),
+        view_state: Default::default(),
+        retention_risks: Vec::new(),
+    };
+    let project = project_file::new_project(project)?;
+    if let Err(e) = longterm_storage::add_recent_project(project_path.to_string()) {
+        log::warn!("Failed to add {} to recent projects: {}", project_path, e);
+    }
+    Ok(ProjectFromMedia { project, failures })
+}
+
+/// --- Timecode alignment --------------------------------------------------------------
+
+/// Where `align_clips_by_timecode` put a clip
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlignedClip {
+    pub clip_id: String,
+    pub track_id: String,
+    pub segment_id: String,
+    pub timeline_start: f64,
+    pub aligned: bool, // False when the clip had no creation time and was placed at the end
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlignResult {
+    pub placed: Vec<AlignedClip>, // In the order the clips were given
+    pub missing_creation_time: Vec<String>, // Ids of clips placed at the end unaligned
+}
+
+/// Recording start of `clip` in seconds since the epoch, corrected by its clock offset
+fn recording_start(clip: &Clip, clock_offsets: &HashMap<String, f64>) -> Option<f64> {
+    let time = chrono::DateTime::parse_from_rfc3339(clip.creation_time.as_deref()?).ok()?;
+    Some(time.timestamp_millis() as f64 / 1000.0 + clock_offsets.get(&clip.id).copied().unwrap_or(0.0))
+}
+
+/// Lay the given clips out by their recording start times so footage from several cameras
+/// lines up: the earliest starts at 0 and every other clip at its start relative to that.
+/// Clip `i` goes on `target_track_ids[i]`, or all on the one track when a single id is given.
+/// `clock_offsets` (seconds, by clip id) correct a camera's clock; starts within `tolerance`
+/// seconds of the previous clip's snap to it. Clips without a creation time are placed after
+/// everything else and reported. A segment can't start before the end of the one before it
+/// on its track, so overlapping clips on one track are pushed later
+pub fn align_clips_by_timecode(
+    clip_ids: &[String],
+    target_track_ids: &[String],
+    tolerance: f64,
+    clock_offsets: &HashMap<String, f64>,
+) -> Result<AlignResult> {
+    if clip_ids.is_empty() {
+        return Err(anyhow!("no clips given"));
+    }
+    if target_track_ids.len() != 1 && target_track_ids.len() != clip_ids.len() {
+        return Err(anyhow!(
+            "expected one target track or one per clip, got {} for {} clips",
+            target_track_ids.len(),
+            clip_ids.len()
+        ));
+    }
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        return Err(anyhow!("tolerance must not be negative"));
+    }
+    let mut project = project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+
+    let mut placements = Vec::new(); // (index, clip, track id, recording start)
+    for (i, clip_id) in clip_ids.iter().enumerate() {
+        let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?.clone();
+        let track_id = target_track_ids.get(i).unwrap_or(&target_track_ids[0]).clone();
+        if !project.tracks_map.contains_key(&track_id) {
+            return Err(anyhow!("track not found: {}", track_id));
+        }
+        let start = recording_start(&clip, clock_offsets);
+        placements.push((i, clip, track_id, start));
+    }
+
+    // Aligned clips in recording order, then the rest in the order given
+    placements.sort_by(|a, b| match (a.3, b.3) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.0.cmp(&b.0),
+    });
+    let earliest = placements.iter().find_map(|p| p.3).unwrap_or(0.0);
+
+    let mut placed = Vec::new();
+    let mut missing_creation_time = Vec::new();
+    let mut previous: Option<f64> = None;
+    let mut end_of_aligned = 0.0_f64;
+    for (index, clip, track_id, start) in placements {
+        let mut segment = full_segment(&clip)?;
+        let wanted = match start {
+            Some(start) => {
+                let mut offset = start - earliest;
+                if let Some(previous) = previous.filter(|p| offset - p <= tolerance) {
+                    offset = previous;
+                }
+                previous = Some(offset);
+                offset
+            }
+            None => {
+                missing_creation_time.push(clip.id.clone());
+                end_of_aligned
+            }
+        };
+        segment.timeline_start = Some(wanted);
+        let track = project.tracks_map.get_mut(&track_id).ok_or_else(|| anyhow!("track not found: {}", track_id))?;
+        track.segments.push(segment.clone());
+        let (timeline_start, timeline_end) = track.timeline_spans().last().copied().unwrap_or((wanted, wanted));
+        if start.is_some() {
+            end_of_aligned = end_of_aligned.max(timeline_end);
+        } else {
+            end_of_aligned = timeline_end;
+        }
+        placed.push((index, AlignedClip {
+            clip_id: clip.id.clone(),
+            track_id,
+            segment_id: segment.id,
+            timeline_start,
+            aligned: start.is_some(),
+        }));
+    }
+
+    project_file::update_project(project)?;
+    placed.sort_by_key(|(index, _)| *index);
+    Ok(AlignResult { placed: placed.into_iter().map(|(_, p)| p).collect(), missing_creation_time })
+}
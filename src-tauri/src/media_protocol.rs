@@ -0,0 +1,296 @@
+//! `gebo-media://` protocol: serves generated media (proxies, previews, baked segments) to the
+//! webview by token. Only registered files can be read, and a token stops working once it
+//! is revoked, expires, or its project is closed.
+
+use base64::Engine;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::http::{header, Request, Response, StatusCode};
+
+pub const SCHEME: &str = "gebo-media";
+
+/// Tokens not served for this long expire. Every request extends the token
+const TOKEN_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Largest body sent per range response; players fetch the rest with further range requests.
+/// Requests without a Range header get the whole file
+const MAX_CHUNK: u64 = 8 * 1024 * 1024;
+
+/// How long a registration lives besides its TTL
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaScope {
+  Session, // Until revoked or expired
+  Project, // Also revoked when the current project is closed or replaced
+}
+
+struct Entry {
+  path: PathBuf,
+  scope: MediaScope,
+  expires_at: Instant,
+}
+
+/// Allow-list of files the protocol may serve, keyed by token
+struct MediaRegistry {
+  entries: HashMap<String, Entry>,
+}
+
+impl MediaRegistry {
+  fn prune(&mut self, now: Instant) {
+    self.entries.retain(|_, entry| entry.expires_at > now);
+  }
+}
+
+static REGISTRY: Mutex<Option<MediaRegistry>> = Mutex::new(None);
+
+fn with_registry<T>(f: impl FnOnce(&mut MediaRegistry) -> T) -> T {
+  let mut guard = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+  let registry = guard.get_or_insert_with(|| MediaRegistry { entries: HashMap::new() });
+  registry.prune(Instant::now());
+  f(registry)
+}
+
+/// URL the webview loads for `token`. Windows and Android serve custom schemes over http
+pub fn media_url(token: &str) -> String {
+  if cfg!(any(windows, target_os = "android")) {
+    format!("http://{}.localhost/{}", SCHEME, token)
+  } else {
+    format!("{}://localhost/{}", SCHEME, token)
+  }
+}
+
+/// Allow `path` to be served and return its token. A path has at most one live token:
+/// registering it again revokes the old one, so URLs to a replaced file stop working
+pub fn register(path: &Path, scope: MediaScope) -> String {
+  let token = uuid::Uuid::new_v4().simple().to_string();
+  with_registry(|registry| {
+    registry.entries.retain(|_, entry| entry.path != path);
+    registry.entries.insert(token.clone(), Entry {
+      path: path.to_path_buf(),
+      scope,
+      expires_at: Instant::now() + TOKEN_TTL,
+    });
+  });
+  token
+}
+
+/// Returns false if the token was unknown or already expired
+pub fn revoke(token: &str) -> bool {
+  with_registry(|registry| registry.entries.remove(token).is_some())
+}
+
+/// Revoke every token for `path`, e.g. before deleting the file
+pub fn revoke_path(path: &Path) {
+  with_registry(|registry| registry.entries.retain(|_, entry| entry.path != path));
+}
+
+/// Revoke everything registered for the current project
+pub fn revoke_project_media() {
+  with_registry(|registry| registry.entries.retain(|_, entry| entry.scope != MediaScope::Project));
+}
+
+/// Live token for a registered path, if any
+pub fn token_for_path(path: &Path) -> Option<String> {
+  with_registry(|registry| {
+    registry.entries.iter().find(|(_, entry)| entry.path == path).map(|(token, _)| token.clone())
+  })
+}
+
+/// URL serving `path`: its live token's, or a new registration's
+pub fn url_for(path: &Path, scope: MediaScope) -> String {
+  let token = token_for_path(path).unwrap_or_else(|| register(path, scope));
+  media_url(&token)
+}
+
+/// Path for a live token, extending its expiry
+fn resolve(token: &str) -> Option<PathBuf> {
+  with_registry(|registry| {
+    let entry = registry.entries.get_mut(token)?;
+    entry.expires_at = Instant::now() + TOKEN_TTL;
+    Some(entry.path.clone())
+  })
+}
+
+fn empty(status: StatusCode) -> Response<Vec<u8>> {
+  Response::builder().status(status).body(Vec::new()).unwrap_or_default()
+}
+
+/// Parse a single "bytes=start-end" range against a file of `size` bytes
+fn parse_range(value: &str, size: u64) -> Option<(u64, u64)> {
+  let spec = value.trim().strip_prefix("bytes=")?;
+  let (start, end) = spec.split_once('-')?;
+  let (start, end) = match (start.trim(), end.trim()) {
+    ("", suffix) => {
+      let suffix: u64 = suffix.parse().ok()?;
+      (size.saturating_sub(suffix), size.checked_sub(1)?)
+    }
+    (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+    (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size.checked_sub(1)?)),
+  };
+  (start <= end).then_some((start, end))
+}
+
+fn read_range(path: &Path, start: u64, len: u64) -> std::io::Result<Vec<u8>> {
+  let mut file = File::open(path)?;
+  file.seek(SeekFrom::Start(start))?;
+  let mut body = Vec::with_capacity(len as usize);
+  file.take(len).read_to_end(&mut body)?;
+  Ok(body)
+}
+
+/// Serve a protocol request. Unknown, revoked and expired tokens (and files that have since
+/// disappeared) get a bare 404 so filesystem errors and paths never reach the webview
+pub fn handle_request(request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+  let token = request.uri().path().trim_start_matches('/');
+  let Some(path) = resolve(token) else {
+    return empty(StatusCode::NOT_FOUND);
+  };
+  let Ok(size) = std::fs::metadata(&path).map(|m| m.len()) else {
+    revoke(token);
+    return empty(StatusCode::NOT_FOUND);
+  };
+
+  let requested = request.headers().get(header::RANGE).and_then(|v| v.to_str().ok());
+  let (start, end, partial) = match requested {
+    Some(value) => match parse_range(value, size) {
+      Some((start, end)) => (start, end.min(start + MAX_CHUNK - 1), true),
+      None => {
+        return Response::builder()
+          .status(StatusCode::RANGE_NOT_SATISFIABLE)
+          .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+          .body(Vec::new())
+          .unwrap_or_default();
+      }
+    },
+    // A 206 only answers a Range request, so a plain fetch gets the whole file
+    None => (0, size.saturating_sub(1), false),
+  };
+
+  let body = if size == 0 { Ok(Vec::new()) } else { read_range(&path, start, end - start + 1) };
+  let Ok(body) = body else {
+    log::warn!("Failed to read registered media {:?}", path);
+    return empty(StatusCode::NOT_FOUND);
+  };
+  let mime = mime_guess::from_path(&path).first_or_octet_stream();
+  let mut response = Response::builder()
+    .header(header::CONTENT_TYPE, mime.essence_str())
+    .header(header::ACCEPT_RANGES, "bytes")
+    .header(header::CONTENT_LENGTH, body.len());
+  response = if partial {
+    response
+      .status(StatusCode::PARTIAL_CONTENT)
+      .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size))
+  } else {
+    response.status(StatusCode::OK)
+  };
+  response.body(body).unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Files `read_base64` reads without an explicit limit
+pub const BASE64_DEFAULT_LIMIT: u64 = 10 * 1024 * 1024;
+/// Largest limit `read_base64` accepts; bigger files go through the protocol
+pub const BASE64_MAX_LIMIT: u64 = 50 * 1024 * 1024;
+
+/// Why a file couldn't be read as base64. Serialized as `{"kind": "too_large", "message": ...}`
+/// or `{"kind": "failed", "message": ...}` so the frontend can switch to a media URL for the former
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum ReadFileError {
+  TooLarge(String),
+  Failed(String),
+}
+
+impl fmt::Display for ReadFileError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ReadFileError::TooLarge(message) | ReadFileError::Failed(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for ReadFileError {}
+
+/// The whole of `path` as base64, refused when it is over `limit` bytes (default
+/// `BASE64_DEFAULT_LIMIT`, at most `BASE64_MAX_LIMIT`). Encoding copies the file into memory
+/// twice over, so media should be loaded by URL instead
+pub fn read_base64(path: &Path, limit: Option<u64>) -> Result<String, ReadFileError> {
+  let limit = limit.unwrap_or(BASE64_DEFAULT_LIMIT).min(BASE64_MAX_LIMIT);
+  let too_large = |size: u64| ReadFileError::TooLarge(format!(
+    "{:?} is {} bytes, over the {} byte limit for base64; load it with get_media_url or read_file_chunk",
+    path, size, limit
+  ));
+  let size = std::fs::metadata(path).map_err(|e| ReadFileError::Failed(format!("failed to read {:?}: {}", path, e)))?.len();
+  if size > limit {
+    return Err(too_large(size));
+  }
+  // The file may grow between the check and the read; read one byte past the limit to notice
+  let mut data = Vec::with_capacity(size as usize);
+  File::open(path)
+    .and_then(|file| file.take(limit + 1).read_to_end(&mut data))
+    .map_err(|e| ReadFileError::Failed(format!("failed to read {:?}: {}", path, e)))?;
+  if data.len() as u64 > limit {
+    return Err(too_large(data.len() as u64));
+  }
+  Ok(base64::engine::general_purpose::STANDARD.encode(&data))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  fn request(token: &str, range: Option<&str>) -> Request<Vec<u8>> {
+    let mut builder = Request::builder().uri(format!("{}://localhost/{}", SCHEME, token));
+    if let Some(range) = range {
+      builder = builder.header(header::RANGE, range);
+    }
+    builder.body(Vec::new()).unwrap()
+  }
+
+  #[test]
+  fn ranges_are_parsed_against_the_file_size() {
+    assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+    assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+    assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+    assert_eq!(parse_range("bytes=-2000", 1000), Some((0, 999)));
+    assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+    assert_eq!(parse_range(" bytes=1-2 ", 1000), Some((1, 2)));
+    assert_eq!(parse_range("bytes=100-50", 1000), None);
+    assert_eq!(parse_range("bytes=0-10", 0), None);
+    assert_eq!(parse_range("items=0-10", 1000), None);
+    assert_eq!(parse_range("bytes=a-b", 1000), None);
+  }
+
+  #[test]
+  fn requests_without_a_range_get_the_whole_file() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("large.bin");
+    let size = MAX_CHUNK as usize + 1024;
+    fs::write(&path, vec![7u8; size]).unwrap();
+    let token = register(&path, MediaScope::Session);
+
+    let response = handle_request(&request(&token, None));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().len(), size);
+    assert!(response.headers().get(header::CONTENT_RANGE).is_none());
+
+    let response = handle_request(&request(&token, Some("bytes=0-")));
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.body().len() as u64, MAX_CHUNK);
+    assert_eq!(
+      response.headers()[header::CONTENT_RANGE],
+      format!("bytes 0-{}/{}", MAX_CHUNK - 1, size).as_str()
+    );
+
+    let response = handle_request(&request(&token, Some(&format!("bytes={}-", size + 10))));
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+    revoke(&token);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,384 @@
+//! Beat-synced montages: "make a montage of these clips to this song". Beats are tracked in
+//! the music's waveform, each video clip contributes its most engaging moment (see
+//! engagement.rs) and the moments are cut to slots between beats, in the order the clips were
+//! given. Everything is worked out locally and deterministically; the result lands on a new
+//! video track with the music on a new audio track, as one undo step.
+
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use crate::engagement;
+use crate::media_project;
+use crate::project_file::{self, ClipType, ProjectFile, TrackType};
+use crate::waveform;
+
+/// Tempo range the beat tracker looks in
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 180.0;
+/// Tempo the tracker leans towards: clicks at 120 BPM also repeat at 60, and without a
+/// preference the slower reading wins as often as not
+const PREFERRED_BPM: f64 = 120.0;
+/// How far a beat may drift from the tempo's prediction towards an onset, as a share of a beat
+const BEAT_TOLERANCE: f64 = 0.125;
+/// Engagement window the best moment of each clip is picked from, seconds
+const MOMENT_SECONDS: f64 = 2.0;
+/// Slowest a clip is played to fill a slot it's too short for. Slower than this and the slot
+/// goes to another clip instead
+pub const MIN_MONTAGE_SPEED: f64 = 0.8;
+
+/// Beats of a piece of music, in seconds from its start
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BeatGrid {
+    pub bpm: f64,
+    pub beats: Vec<f64>,
+}
+
+/// Track the beats in `peaks` (one per `bucket_seconds`, as `waveform::pcm_peaks` gives). The
+/// tempo is the strongest period of the onsets (rises in level), leaning towards
+/// `PREFERRED_BPM`; beats then follow it, each pulled to the strongest onset near where the
+/// tempo puts it so slow drift doesn't add up. None for music too short or too flat to have a beat
+pub fn detect_beats(peaks: &[i16], bucket_seconds: f64) -> Option<BeatGrid> {
+    if bucket_seconds.is_nan() || bucket_seconds <= 0.0 {
+        return None;
+    }
+    let level: Vec<f64> = peaks.iter().map(|p| (*p as f64).abs() / i16::MAX as f64).collect();
+    // onset[i] is the rise into bucket i + 1
+    let onset: Vec<f64> = level.windows(2).map(|w| (w[1] - w[0]).max(0.0)).collect();
+    let min_lag = ((60.0 / MAX_BPM / bucket_seconds).round() as usize).max(1);
+    let max_lag = (60.0 / MIN_BPM / bucket_seconds).round() as usize;
+    if onset.len() < max_lag * 2 || onset.iter().all(|v| *v == 0.0) {
+        return None;
+    }
+
+    // Beat periods rarely come out a whole number of buckets, so each onset is compared with
+    // the strongest of its neighbours one period on
+    let spread: Vec<f64> = (0..onset.len())
+        .map(|i| onset[i.saturating_sub(1)..(i + 2).min(onset.len())].iter().copied().fold(0.0, f64::max))
+        .collect();
+    let mut lag = min_lag;
+    let mut best = f64::MIN;
+    for candidate in min_lag..=max_lag {
+        let correlation = onset.iter().zip(&spread[candidate..]).map(|(a, b)| a * b).sum::<f64>() / (onset.len() - candidate) as f64;
+        let octaves = (60.0 / (candidate as f64 * bucket_seconds) / PREFERRED_BPM).log2();
+        let correlation = correlation * (-0.5 * octaves * octaves).exp();
+        if correlation > best {
+            best = correlation;
+            lag = candidate;
+        }
+    }
+
+    // Phase: the start within the first beat whose tracked beats land on the most onset. A plain
+    // comb of the period drifts off the beats when the period is a bucket off
+    let tolerance = ((lag as f64 * BEAT_TOLERANCE).round() as usize).max(1);
+    let mut indices = Vec::new();
+    let mut best = f64::MIN;
+    for offset in 0..lag {
+        let tracked = track_beats(&onset, offset, lag, tolerance);
+        let strength: f64 = tracked.iter().map(|i| onset[*i]).sum();
+        if strength > best {
+            best = strength;
+            indices = tracked;
+        }
+    }
+    let beats: Vec<f64> = indices.iter().map(|i| (*i + 1) as f64 * bucket_seconds).collect();
+    // From the tracked beats; the period alone is only as fine as a bucket
+    let bpm = match beats.len() {
+        n if n >= 2 => 60.0 * (n - 1) as f64 / (beats[n - 1] - beats[0]),
+        _ => 60.0 / (lag as f64 * bucket_seconds),
+    };
+    Some(BeatGrid { bpm, beats })
+}
+
+/// Onset indices of beats from `start` on, one `lag` apart, each pulled to the strongest onset
+/// within `tolerance` of where the previous one puts it
+fn track_beats(onset: &[f64], start: usize, lag: usize, tolerance: usize) -> Vec<usize> {
+    let mut indices = vec![start];
+    loop {
+        let predicted = indices[indices.len() - 1] + lag;
+        if predicted >= onset.len() {
+            break;
+        }
+        let from = predicted.saturating_sub(tolerance);
+        let to = (predicted + tolerance).min(onset.len() - 1);
+        indices.push((from..=to).fold(predicted, |best, i| if onset[i] > onset[best] { i } else { best }));
+    }
+    indices
+}
+
+/// A video clip offered to the montage and its best moment
+#[derive(Debug, Clone, PartialEq)]
+pub struct MontageSource {
+    pub clip_id: String,
+    pub duration: f64, // Length of the clip, seconds
+    pub moment: (f64, f64), // Most engaging window, clip seconds
+    pub score: f32,
+}
+
+/// One clip's place in the montage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MontagePiece {
+    pub clip_id: String,
+    pub slot_start: f64, // Timeline (music) seconds, on a beat
+    pub slot_end: f64,
+    pub start: f64, // Clip seconds played in the slot
+    pub end: f64,
+    pub speed: f64, // Below 1 when the clip is slowed to fill the slot
+}
+
+/// Whether a clip of `duration` seconds can fill a slot of `length` seconds
+fn fits(duration: f64, length: f64) -> bool {
+    duration >= length * MIN_MONTAGE_SPEED - 1e-9
+}
+
+/// Index of the grid point nearest `time` in `from..=to`; earlier wins ties
+fn nearest(grid: &[f64], time: f64, from: usize, to: usize) -> usize {
+    (from..=to).fold(from, |best, i| if (grid[i] - time).abs() < (grid[best] - time).abs() { i } else { best })
+}
+
+/// Cut `sources` to the beats of the music. The montage runs from the start of the music to
+/// the last beat within `target_duration`; each source gets a slot of whole beats, about
+/// equally long, in the order given. When there are more sources than beats the lowest-scoring
+/// are left out. A source too short for its slot, even slowed to `MIN_MONTAGE_SPEED`, swaps
+/// slots with a later one where both fit; failing that its slot ends on an earlier beat and
+/// the next slot takes the rest (the last one ends the montage early)
+pub fn plan_montage(beats: &[f64], sources: &[MontageSource], target_duration: f64) -> Result<Vec<MontagePiece>> {
+    if sources.is_empty() {
+        return Err(anyhow!("no video clips to make a montage from"));
+    }
+    if !target_duration.is_finite() || target_duration <= 0.0 {
+        return Err(anyhow!("target duration must be positive, got {}", target_duration));
+    }
+    let mut grid = vec![0.0];
+    grid.extend(beats.iter().copied().filter(|b| *b > 1e-6 && *b <= target_duration + 1e-6));
+    if grid.len() < 2 {
+        return Err(anyhow!("the music has no beat within the first {:.1} s", target_duration));
+    }
+
+    let mut sources: Vec<&MontageSource> = sources.iter().collect();
+    let slots = sources.len().min(grid.len() - 1);
+    if slots < sources.len() {
+        let mut ranked: Vec<usize> = (0..sources.len()).collect();
+        ranked.sort_by(|a, b| sources[*b].score.total_cmp(&sources[*a].score).then(a.cmp(b)));
+        let mut kept = ranked[..slots].to_vec();
+        kept.sort();
+        sources = kept.into_iter().map(|i| sources[i]).collect();
+    }
+
+    // Slot boundaries as grid indices, each on the beat nearest an equal split
+    let last = grid.len() - 1;
+    let end = grid[last];
+    let mut bounds = vec![0];
+    for k in 1..slots {
+        let from = bounds[k - 1] + 1;
+        let to = last - (slots - k);
+        bounds.push(nearest(&grid, end * k as f64 / slots as f64, from, to));
+    }
+    bounds.push(last);
+    let mut times: Vec<f64> = bounds.iter().map(|i| grid[*i]).collect();
+
+    for i in 0..slots {
+        let length = times[i + 1] - times[i];
+        if fits(sources[i].duration, length) {
+            continue;
+        }
+        let swap = (i + 1..slots).find(|&j| {
+            fits(sources[j].duration, length) && fits(sources[i].duration, times[j + 1] - times[j])
+        });
+        if let Some(j) = swap {
+            sources.swap(i, j);
+            continue;
+        }
+        // End the slot on the latest beat the clip can fill; off the beat if it can't fill one
+        let longest = sources[i].duration / MIN_MONTAGE_SPEED;
+        let beat = grid.iter().copied().rev().find(|t| *t > times[i] + 1e-6 && *t - times[i] <= longest + 1e-9);
+        times[i + 1] = beat.unwrap_or(times[i] + longest);
+    }
+
+    Ok(sources
+        .iter()
+        .enumerate()
+        .map(|(i, source)| {
+            let (slot_start, slot_end) = (times[i], times[i + 1]);
+            let length = slot_end - slot_start;
+            let (played, speed) = match source.duration >= length {
+                true => (length, 1.0),
+                false => (source.duration, source.duration / length),
+            };
+            // Centered on the moment, kept inside the clip
+            let middle = (source.moment.0 + source.moment.1) / 2.0;
+            let start = (middle - played / 2.0).clamp(0.0, (source.duration - played).max(0.0));
+            MontagePiece { clip_id: source.clip_id.clone(), slot_start, slot_end, start, end: start + played, speed }
+        })
+        .collect())
+}
+
+/// Most engaging window of `clip_id`; the earliest of equals
+fn best_moment(clip_id: &str, duration: f64) -> Result<MontageSource> {
+    let windows = engagement::engagement_windows(clip_id, MOMENT_SECONDS)?;
+    let best = windows
+        .iter()
+        .reduce(|best, w| if w.score > best.score { w } else { best })
+        .ok_or_else(|| anyhow!("clip {} has nothing to score", clip_id))?;
+    Ok(MontageSource { clip_id: clip_id.to_string(), duration, moment: (best.start, best.end), score: best.score })
+}
+
+/// Build a montage of `video_clip_ids` to `music_clip_id` of at most `target_duration` seconds
+/// on new tracks of the current project, and return the project for preview. Undo removes it
+pub fn build_montage(music_clip_id: &str, video_clip_ids: &[String], target_duration: f64) -> Result<ProjectFile> {
+    let project = project_file::get_project()
+        .map_err(anyhow::Error::msg)?
+        .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    let music = project.clips_map.get(music_clip_id).ok_or_else(|| anyhow!("clip not found: {}", music_clip_id))?;
+    if music.latest_probe.as_ref().is_some_and(|p| p.audio_rate == 0) {
+        return Err(anyhow!("the music clip has no audio"));
+    }
+    let peaks = waveform::pcm_peaks(&music.path.to_string_lossy(), music.audio_stream())?;
+    let grid = detect_beats(&peaks, waveform::BUCKET_SECONDS).ok_or_else(|| anyhow!("no beat found in the music"))?;
+    log::info!("Montage: {} beats at {:.0} BPM", grid.beats.len(), grid.bpm);
+
+    let mut sources = Vec::new();
+    for clip_id in video_clip_ids {
+        let clip = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        if clip.r#type != ClipType::Video {
+            return Err(anyhow!("clip {} is not a video", clip_id));
+        }
+        let duration = clip.latest_probe.as_ref().map(|p| p.duration).filter(|d| *d > 0.0)
+            .ok_or_else(|| anyhow!("clip {} has no known duration", clip_id))?;
+        sources.push(best_moment(clip_id, duration)?);
+    }
+    let pieces = plan_montage(&grid.beats, &sources, target_duration)?;
+    let length = pieces.last().map(|p| p.slot_end).unwrap_or(0.0);
+
+    // add_tracks puts them above the existing tracks
+    let mut video = media_project::empty_track("Montage", TrackType::Video, 0);
+    for piece in &pieces {
+        let clip = &project.clips_map[&piece.clip_id];
+        let mut segment = media_project::full_segment(clip)?;
+        segment.start = piece.start;
+        segment.end = piece.end;
+        segment.speed = piece.speed;
+        segment.timeline_start = Some(piece.slot_start);
+        video.segments.push(segment);
+    }
+    let mut audio = media_project::empty_track("Montage music", TrackType::Audio, 0);
+    let mut segment = media_project::full_segment(music)?;
+    segment.end = segment.end.min(length);
+    audio.segments.push(segment);
+    project_file::add_tracks(vec![video, audio])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUCKET: f64 = 0.01;
+
+    /// Quiet peaks with a click every `period` buckets from bucket 10 on
+    fn clicks(period: usize, buckets: usize) -> Vec<i16> {
+        (0..buckets).map(|i| if i >= 10 && (i - 10) % period == 0 { 30000 } else { 1000 }).collect()
+    }
+
+    fn source(clip_id: &str, duration: f64, score: f32) -> MontageSource {
+        MontageSource { clip_id: clip_id.to_string(), duration, moment: (0.0, duration.min(2.0)), score }
+    }
+
+    fn slots(pieces: &[MontagePiece]) -> Vec<(&str, f64, f64)> {
+        pieces.iter().map(|p| (p.clip_id.as_str(), p.slot_start, p.slot_end)).collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn beats_follow_the_clicks() {
+        // 120 BPM: a click every 50 buckets, at 0.1 s, 0.6 s, ...
+        let grid = detect_beats(&clicks(50, 1000), BUCKET).unwrap();
+        assert!((grid.bpm - 120.0).abs() < 0.5, "{}", grid.bpm);
+        assert_eq!(grid.beats.len(), 20);
+        for (i, beat) in grid.beats.iter().enumerate() {
+            assert!((beat - (0.1 + 0.5 * i as f64)).abs() < 0.015, "beat {} at {}", i, beat);
+        }
+    }
+
+    #[test]
+    fn slower_tempos_are_found_too() {
+        let grid = detect_beats(&clicks(100, 1000), BUCKET).unwrap();
+        assert!((grid.bpm - 60.0).abs() < 0.5, "{}", grid.bpm);
+        let grid = detect_beats(&clicks(60, 1200), BUCKET).unwrap();
+        assert!((grid.bpm - 100.0).abs() < 0.5, "{}", grid.bpm);
+    }
+
+    #[test]
+    fn flat_or_short_music_has_no_beat() {
+        assert_eq!(detect_beats(&[1000; 1000], BUCKET), None);
+        assert_eq!(detect_beats(&clicks(50, 150), BUCKET), None);
+        assert_eq!(detect_beats(&clicks(50, 1000), 0.0), None);
+    }
+
+    #[test]
+    fn sources_get_equal_slots_on_beats() {
+        let beats: Vec<f64> = (1..=20).map(|i| i as f64 * 0.5).collect();
+        let sources = [source("a", 10.0, 1.0), source("b", 10.0, 1.0), source("c", 10.0, 1.0), source("d", 10.0, 1.0)];
+        let pieces = plan_montage(&beats, &sources, 8.0).unwrap();
+        assert_eq!(slots(&pieces), vec![("a", 0.0, 2.0), ("b", 2.0, 4.0), ("c", 4.0, 6.0), ("d", 6.0, 8.0)]);
+        for piece in &pieces {
+            assert_eq!(piece.speed, 1.0);
+            // Centered on the moment (0-2 s)
+            assert_eq!((piece.start, piece.end), (0.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn short_clips_slow_down_to_fill_their_slot() {
+        let pieces = plan_montage(&[1.0, 2.0], &[source("a", 1.8, 1.0)], 2.0).unwrap();
+        assert_eq!(slots(&pieces), vec![("a", 0.0, 2.0)]);
+        assert!(close(pieces[0].speed, 0.9));
+        assert_eq!((pieces[0].start, pieces[0].end), (0.0, 1.8));
+
+        // Exactly the slowest speed allowed still fits
+        let pieces = plan_montage(&[1.0, 2.0], &[source("a", 1.6, 1.0)], 2.0).unwrap();
+        assert_eq!(slots(&pieces), vec![("a", 0.0, 2.0)]);
+        assert!(close(pieces[0].speed, MIN_MONTAGE_SPEED));
+    }
+
+    #[test]
+    fn clips_too_short_for_their_slot_swap_with_a_later_one() {
+        // Slots of 3.5 s and 2.5 s; "a" only fills the second, slowed to 0.8
+        let sources = [source("a", 2.0, 1.0), source("b", 10.0, 1.0)];
+        let pieces = plan_montage(&[3.5, 4.0, 5.0, 6.0], &sources, 6.0).unwrap();
+        assert_eq!(slots(&pieces), vec![("b", 0.0, 3.5), ("a", 3.5, 6.0)]);
+        assert_eq!(pieces[0].speed, 1.0);
+        assert!(close(pieces[1].speed, 0.8));
+    }
+
+    #[test]
+    fn clips_that_cant_swap_get_a_shorter_slot() {
+        // "a" can't fill 4 s even slowed down; its slot ends on the last beat it can fill
+        // and "b" takes the rest
+        let beats: Vec<f64> = (1..=8).map(|i| i as f64).collect();
+        let pieces = plan_montage(&beats, &[source("a", 1.0, 1.0), source("b", 10.0, 1.0)], 8.0).unwrap();
+        assert_eq!(slots(&pieces), vec![("a", 0.0, 1.0), ("b", 1.0, 8.0)]);
+        assert_eq!((pieces[0].start, pieces[0].end, pieces[0].speed), (0.0, 1.0, 1.0));
+        assert_eq!((pieces[1].end - pieces[1].start, pieces[1].speed), (7.0, 1.0));
+
+        // With no beat close enough, the last slot ends off the beat and the montage ends early
+        let pieces = plan_montage(&[4.0, 8.0], &[source("a", 2.0, 1.0)], 8.0).unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert!(close(pieces[0].slot_end, 2.5));
+        assert!(close(pieces[0].speed, 0.8));
+    }
+
+    #[test]
+    fn more_clips_than_beats_keeps_the_best_in_order() {
+        let sources = [source("a", 5.0, 0.2), source("b", 5.0, 0.9), source("c", 5.0, 0.5)];
+        let pieces = plan_montage(&[1.0, 2.0], &sources, 2.0).unwrap();
+        assert_eq!(slots(&pieces), vec![("b", 0.0, 1.0), ("c", 1.0, 2.0)]);
+    }
+
+    #[test]
+    fn montages_need_clips_and_a_beat() {
+        assert!(plan_montage(&[1.0], &[], 2.0).is_err());
+        assert!(plan_montage(&[5.0], &[source("a", 5.0, 1.0)], 2.0).is_err());
+        assert!(plan_montage(&[1.0], &[source("a", 5.0, 1.0)], 0.0).is_err());
+    }
+}
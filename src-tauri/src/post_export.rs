@@ -0,0 +1,186 @@
+//! Actions run when an export finishes: a system notification, revealing the output, copying
+//! its path and an opt-in shell command. They run after the export has been recorded as done,
+//! so a failing action is logged and reported but never fails the export.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::process::Command;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_notification::NotificationExt;
+use crate::longterm_storage::{self, PostExportSettings};
+
+/// Replaced by the shell-quoted output path in the post-export command
+pub const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+/// Payload of the "post-export" event
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostExportReport {
+  pub kind: String, // Export kind, e.g. "cutlist" or the job kind
+  pub outputs: Vec<String>,
+  pub failures: Vec<String>, // One line per failed action; empty when everything ran
+}
+
+/// Run the configured actions for a finished export of `outputs` on a background thread
+pub fn spawn(app: &AppHandle, kind: &str, outputs: Vec<String>) {
+  if outputs.is_empty() {
+    return;
+  }
+  let app = app.clone();
+  let kind = kind.to_string();
+  std::thread::spawn(move || {
+    let settings = match longterm_storage::get_settings() {
+      Ok(settings) => settings.post_export,
+      Err(e) => {
+        log::warn!("Post-export actions skipped, settings unreadable: {}", e);
+        return;
+      }
+    };
+    let failures = run(&app, &settings, &outputs);
+    for failure in &failures {
+      log::warn!("Post-export action failed for {}: {}", kind, failure);
+    }
+    let report = PostExportReport { kind, outputs, failures };
+    if let Err(e) = app.emit("post-export", &report) {
+      log::warn!("Failed to emit post-export: {}", e);
+    }
+  });
+}
+
+/// Run each enabled action, collecting failures instead of stopping at the first
+fn run(app: &AppHandle, settings: &PostExportSettings, outputs: &[String]) -> Vec<String> {
+  let mut failures = Vec::new();
+  let mut attempt = |action: &str, result: Result<()>| {
+    if let Err(e) = result {
+      failures.push(format!("{}: {:#}", action, e));
+    }
+  };
+
+  if settings.notify {
+    attempt("notification", notify(app, outputs));
+  }
+  if settings.reveal {
+    attempt("reveal", reveal(&outputs[0]));
+  }
+  if settings.copy_path {
+    let copied = app.clipboard().write_text(outputs.join("\n")).map_err(|e| anyhow!("{}", e));
+    attempt("copy path", copied);
+  }
+  let command = settings.command.as_deref().filter(|c| settings.allow_command && !c.trim().is_empty());
+  if let Some(command) = command {
+    for output in outputs {
+      attempt("command", run_command(command, output));
+    }
+  }
+  failures
+}
+
+fn file_name(path: &str) -> String {
+  Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.to_string())
+}
+
+fn notify(app: &AppHandle, outputs: &[String]) -> Result<()> {
+  let body = match outputs {
+    [output] => file_name(output),
+    [first, ..] => format!("{} files, starting with {}", outputs.len(), file_name(first)),
+    [] => return Ok(()),
+  };
+  app.notification()
+    .builder()
+    .title("Export finished")
+    .body(body)
+    .show()
+    .map_err(|e| anyhow!("{}", e))
+}
+
+/// --- File manager --------------------------------------------------------------------
+
+#[cfg(target_os = "macos")]
+fn reveal(path: &str) -> Result<()> {
+  check(Command::new("open").args(["-R", path]).status(), "open -R")
+}
+
+#[cfg(target_os = "windows")]
+fn reveal(path: &str) -> Result<()> {
+  // Explorer exits with 1 even when it opened the window, so only spawning is checked
+  Command::new("explorer").arg(format!("/select,{}", path)).status().context("failed to start explorer")?;
+  Ok(())
+}
+
+/// No portable way to select a file on Linux; open its folder
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn reveal(path: &str) -> Result<()> {
+  let folder = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+  check(Command::new("xdg-open").arg(folder).status(), "xdg-open")
+}
+
+/// --- Shell command -------------------------------------------------------------------
+
+#[cfg(target_os = "windows")]
+fn shell(command: &str) -> Command {
+  use std::os::windows::process::CommandExt;
+  let mut cmd = Command::new("cmd");
+  cmd.arg("/C").raw_arg(command);
+  cmd
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell(command: &str) -> Command {
+  let mut cmd = Command::new("sh");
+  cmd.args(["-c", command]);
+  cmd
+}
+
+#[cfg(target_os = "windows")]
+fn shell_quote(path: &str) -> String {
+  // Windows paths can't contain double quotes
+  format!("\"{}\"", path)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_quote(path: &str) -> String {
+  format!("'{}'", path.replace('\'', "'\\''"))
+}
+
+/// Run the user's command for one output. The path is also passed as GEBO_OUTPUT
+fn run_command(command: &str, output: &str) -> Result<()> {
+  let command = command.replace(OUTPUT_PLACEHOLDER, &shell_quote(output));
+  check(shell(&command).env("GEBO_OUTPUT", output).status(), &command)
+}
+
+fn check(status: std::io::Result<std::process::ExitStatus>, what: &str) -> Result<()> {
+  let status = status.with_context(|| format!("failed to start `{}`", what))?;
+  if !status.success() {
+    return Err(anyhow!("`{}` exited with status {:?}", what, status.code()));
+  }
+  Ok(())
+}
+
+/// Set the post-export shell command. Enabling a command first asks for confirmation in a
+/// native dialog, since it will run with the user's permissions after every export; `None`
+/// turns it off. Returns whether a command is enabled afterwards
+pub fn set_command(app: &AppHandle, command: Option<String>) -> Result<bool> {
+  let mut settings = longterm_storage::get_settings()?;
+  let command = command.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+  if let Some(command) = &command {
+    let confirmed = app.dialog()
+      .message(format!(
+        "After every export Gebo will run this command in a shell, with {} replaced by the exported file:\n\n{}\n\nOnly continue if you trust it.",
+        OUTPUT_PLACEHOLDER, command
+      ))
+      .title("Run a command after exports?")
+      .kind(MessageDialogKind::Warning)
+      .buttons(MessageDialogButtons::OkCancel)
+      .blocking_show();
+    if !confirmed {
+      return Ok(settings.post_export.allow_command);
+    }
+  }
+  settings.post_export.allow_command = command.is_some();
+  settings.post_export.command = command;
+  let enabled = settings.post_export.allow_command;
+  longterm_storage::set_settings(settings)?;
+  Ok(enabled)
+}
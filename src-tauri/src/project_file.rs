@@ -1,10 +1,28 @@
 use anyhow::{anyhow, Context, Result};
 use serde::{Serialize, Deserialize};
+use std::fmt;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::sync::{Mutex, OnceLock};
-use std::collections::HashMap;
-use crate::ffmpeg::{self, Probe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, HashMap};
+use crate::ai_agent;
+use crate::audio_effects;
+use crate::editor_windows;
+use crate::ffmpeg::{self, ExportSettings, HdrMode, Probe};
+use crate::frame_stepper;
+use crate::ids;
+use crate::intervals::{self, Remap};
+use crate::jobs;
+use crate::media_hash;
+use crate::media_protocol;
+use crate::project_lock;
+use crate::thumbnail_cache;
+use crate::transcription::{self, TranscriptSegment};
+use crate::waveform;
+use crate::work_dirs;
 
 
 // ClipType
@@ -41,6 +59,97 @@ pub struct Clip {
     pub path: PathBuf,
     pub latest_probe: Option<Probe>, // Cached probe of the clip
     pub r#type: ClipType, // Media type
+    #[serde(default)]
+    pub transcript: Option<Vec<TranscriptSegment>>, // Transcript in clip time, if transcribed
+    #[serde(default)]
+    pub derived_from: Option<String>, // Id of the clip this file was rendered from (baked segments)
+    #[serde(default)]
+    pub hashes: Option<MediaHashes>, // Content hashes recorded at import, see media_hash.rs
+    #[serde(default)]
+    pub preview_lut: Option<PathBuf>, // .cube applied to thumbnails and previews only; relative paths are from the project folder
+    #[serde(default)]
+    pub stream_hashes: Option<StreamHashes>, // Per-stream content hashes, see clip_cache.rs
+    #[serde(default)]
+    pub creation_time: Option<String>, // Recording start (ISO 8601) from the file's tags, see media_project::align_clips_by_timecode
+    #[serde(default)]
+    pub ready_for_ai: Option<String>, // When clip_prep::prepare_clip last finished every step it ran (ISO 8601)
+    #[serde(default)]
+    pub transcript_language: Option<String>, // ISO 639 code reported or detected for the transcript
+    #[serde(default)]
+    pub language_override: Option<String>, // Set by the user; wins over transcript_language
+    #[serde(default)]
+    pub has_album_art: Option<bool>, // Whether an audio clip embeds cover art; None until checked. The art itself is extracted into thumbnail_cache on demand, see thumbnail_cache::album_art_url
+    #[serde(default)]
+    pub audio_stream_index: Option<usize>, // Audio stream (among the file's audio streams) chosen by the user; wins over the default one
+}
+
+/// A kind of stream in a media file that cached artifacts can depend on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Video, // Picture only; embedded album art doesn't count
+    Audio,
+}
+
+/// Hashes of a clip's decoded streams. Unlike the file hash they survive a remux, and a
+/// derivative that kept one stream (stabilization keeps the audio) shares that stream's hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StreamHashes {
+    pub size: u64, // File size and modification time the hashes were taken at; either
+    pub modified: u64, // changing means they must be taken again (Unix seconds)
+    pub streams: HashMap<StreamKind, String>, // Streams the file has; murmur3 of the decoded stream
+}
+
+// MediaHashes
+/// Content hashes of a clip's file, recorded when it was imported
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MediaHashes {
+    pub size: u64,
+    pub xxh64: String, // Hex
+    #[serde(default)]
+    pub sha256: Option<String>, // Hex, only if SHA-256 ingest hashing was enabled
+    pub hashed_at: String, // ISO 8601
+    #[serde(default)]
+    pub last_check: Option<HashCheck>,
+}
+
+/// Outcome of the most recent `verify_project_media` run for a clip
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HashCheck {
+    pub status: HashStatus,
+    pub deep: bool, // SHA-256 was compared too
+    pub checked_at: String, // ISO 8601
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum HashStatus {
+    Verified,   // File matches its ingest hashes
+    Mismatch,   // File changed since import
+    Missing,    // File no longer exists
+    Unreadable, // File exists but couldn't be read
+    Unhashed,   // No hashes were recorded at import
+    Unchecked,  // Hashes recorded but never verified
+}
+
+impl Clip {
+    /// Latest known integrity status, without re-hashing
+    pub fn hash_status(&self) -> HashStatus {
+        match &self.hashes {
+            None => HashStatus::Unhashed,
+            Some(hashes) => hashes.last_check.as_ref().map(|c| c.status).unwrap_or(HashStatus::Unchecked),
+        }
+    }
+
+    /// Language the clip is spoken in: the user's choice, else what was detected
+    pub fn language(&self) -> Option<&str> {
+        self.language_override.as_deref().or(self.transcript_language.as_deref())
+    }
+
+    /// Audio stream the clip plays (counting audio streams only): the user's choice, else the
+    /// one its probe found marked default
+    pub fn audio_stream(&self) -> usize {
+        self.audio_stream_index.or(self.latest_probe.as_ref().map(|p| p.audio_stream)).unwrap_or(0)
+    }
 }
 impl Clip {
     /// Verify that the clip's path exists and is a file
@@ -56,29 +165,264 @@ impl Clip {
         if let Some(path_str) = self.path.to_str() {
             self.latest_probe = ffmpeg::ffprobe(path_str).ok();
         }
+        if self.r#type == ClipType::Audio {
+            self.has_album_art = probe_album_art(&self.path);
+        }
+    }
+
+    /// Whether the clip lacks something clips are given at import: the probe, or for audio
+    /// whether it has cover art. True for clips imported by older versions
+    fn needs_backfill(&self) -> bool {
+        match self.r#type {
+            ClipType::Image => false,
+            ClipType::Video => self.latest_probe.is_none(),
+            ClipType::Audio => self.latest_probe.is_none() || self.has_album_art.is_none(),
+        }
+    }
+}
+
+/// Files with these extensions are imported as images and not probed
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "gif", "bmp", "tif", "tiff"];
+
+/// Whether `path` is imported as an image (by extension)
+pub fn is_image_path(path: &Path) -> bool {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    IMAGE_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Whether an audio file has cover art, extracting it into the thumbnail cache on the way.
+/// None when extraction failed (logged), so the next backfill tries again
+pub(crate) fn probe_album_art(path: &Path) -> Option<bool> {
+    thumbnail_cache::album_art_file(path)
+        .inspect_err(|e| log::warn!("Album art extraction failed for {:?}: {}", path, e))
+        .ok()
+        .map(|art| art.is_some())
+}
+
+/// Re-probe the clips of `project` that need it (see Clip::needs_backfill) and whose files are
+/// there. Returns whether any clip changed
+fn backfill_clips(project: &mut ProjectFile) -> bool {
+    let mut changed = false;
+    for clip in project.clips_map.values_mut().filter(|c| c.needs_backfill() && c.verify()) {
+        clip.update_probe();
+        changed = true;
+    }
+    changed
+}
+
+/// Build a clip for a media file, detecting whether it is video, audio or an image.
+/// Video and audio are probed; a file ffprobe can't read is an error. Ingest hashes are
+/// recorded when the file can be read; cancelling the job importing it cancels the import
+pub fn import_media(path: &Path) -> Result<Clip> {
+    if !path.is_file() {
+        return Err(anyhow!("File does not exist: {:?}", path));
     }
+    let (r#type, latest_probe) = if is_image_path(path) {
+        (ClipType::Image, None)
+    } else {
+        let probe = ffmpeg::ffprobe(&path.to_string_lossy())
+            .with_context(|| format!("{:?} is not playable media", path.file_name().unwrap_or_default()))?;
+        if let Some(warning) = probe.vfr_warning(&path.to_string_lossy()) {
+            log::warn!("{}", warning);
+        }
+        let r#type = if probe.width > 0 && probe.height > 0 { ClipType::Video } else { ClipType::Audio };
+        (r#type, Some(probe))
+    };
+    let creation_time = latest_probe.as_ref().and_then(|p| p.creation_time.clone());
+    let has_album_art = if r#type == ClipType::Audio { probe_album_art(path) } else { None };
+    Ok(Clip {
+        id: ids::new_id(ids::CLIP),
+        path: path.to_path_buf(),
+        latest_probe,
+        r#type,
+        transcript: None,
+        derived_from: None,
+        hashes: match media_hash::ingest_hashes(path) {
+            Ok(hashes) => Some(hashes),
+            Err(e) if jobs::current_job_cancelled() => return Err(e),
+            Err(e) => {
+                log::warn!("Failed to hash {:?} at import: {}", path, e);
+                None
+            }
+        },
+        preview_lut: None,
+        stream_hashes: None,
+        creation_time,
+        ready_for_ai: None,
+        transcript_language: None,
+        language_override: None,
+        has_album_art,
+        audio_stream_index: None,
+    })
+}
+
+// Effect
+/// A single ffmpeg-backed effect applied to a segment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Effect {
+    pub r#type: String, // Effect kind, e.g. "lut", "blur"
+    #[serde(default)]
+    pub parameters: HashMap<String, serde_json::Value>,
 }
 
 // Segment
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Segment {
     pub id: String,
 
     pub clip_id: String, // Reference to the Clip by ID
     pub start: f64,     // Start time in seconds within the clip
     pub end: f64,       // End time in seconds within the clip
+
+    #[serde(default)]
+    pub label: Option<String>, // User-facing label, e.g. "intro"
+    #[serde(default)]
+    pub gain: f64,      // Audio gain in dB
+    #[serde(default)]
+    pub fade_in: f64,   // Audio fade in, seconds
+    #[serde(default)]
+    pub fade_out: f64,  // Audio fade out, seconds
+    #[serde(default)]
+    pub fade_curve: FadeCurve, // Shape of both audio fades
+    #[serde(default)]
+    pub video_fade_in: f64,  // Fade from black, seconds
+    #[serde(default)]
+    pub video_fade_out: f64, // Fade to black, seconds
+    #[serde(default = "default_speed")]
+    pub speed: f64,     // Playback speed multiplier
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub baked_from: Option<Box<Segment>>, // The segment as it was before baking, restored by unbake
+    #[serde(default)]
+    pub timeline_start: Option<f64>, // Fixed timeline position in seconds, e.g. from timecode alignment; None plays right after the previous segment
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+/// Gain curve of a segment's audio fades
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    Exponential, // Slow start, for music fading out under speech
+    SCurve,      // Eased at both ends
+}
+
+impl FadeCurve {
+    /// Value of ffmpeg's `afade` curve option
+    pub fn afade_curve(&self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "tri",
+            FadeCurve::Exponential => "exp",
+            FadeCurve::SCurve => "hsin",
+        }
+    }
 }
 
+/// Allowed range for `Segment::speed`
+pub const SEGMENT_SPEED_RANGE: (f64, f64) = (0.25, 4.0);
+/// Allowed range for `Segment::gain` in dB
+pub const SEGMENT_GAIN_RANGE: (f64, f64) = (-60.0, 24.0);
+
 impl Segment {
     /// Verify that the segment is valid. Does not check that clip id is valid
     pub fn verify(&self) -> bool {
-        self.start < self.end
+        self.validation_error().is_none()
+    }
+
+    /// Describe the first problem with this segment, if any
+    pub fn validation_error(&self) -> Option<String> {
+        if self.start.is_nan() || self.end.is_nan() || self.start >= self.end {
+            return Some(format!("start ({}) must be before end ({})", self.start, self.end));
+        }
+        if !(SEGMENT_SPEED_RANGE.0..=SEGMENT_SPEED_RANGE.1).contains(&self.speed) {
+            return Some(format!(
+                "speed {} is outside {}..{}",
+                self.speed, SEGMENT_SPEED_RANGE.0, SEGMENT_SPEED_RANGE.1
+            ));
+        }
+        if !(SEGMENT_GAIN_RANGE.0..=SEGMENT_GAIN_RANGE.1).contains(&self.gain) {
+            return Some(format!(
+                "gain {}dB is outside {}..{}dB",
+                self.gain, SEGMENT_GAIN_RANGE.0, SEGMENT_GAIN_RANGE.1
+            ));
+        }
+        if self.fade_in.is_nan() || self.fade_out.is_nan() || self.fade_in < 0.0 || self.fade_out < 0.0 {
+            return Some("fades must not be negative".to_string());
+        }
+        // Fades apply to the played-back duration, which depends on speed
+        let played = self.duration() / self.speed;
+        if self.fade_in + self.fade_out > played + 1e-6 {
+            return Some(format!(
+                "fades ({}s + {}s) are longer than the segment ({:.3}s)",
+                self.fade_in, self.fade_out, played
+            ));
+        }
+        if self.video_fade_in.is_nan() || self.video_fade_out.is_nan() || self.video_fade_in < 0.0 || self.video_fade_out < 0.0 {
+            return Some("video fades must not be negative".to_string());
+        }
+        if self.video_fade_in + self.video_fade_out > played + 1e-6 {
+            return Some(format!(
+                "video fades ({}s + {}s) are longer than the segment ({:.3}s)",
+                self.video_fade_in, self.video_fade_out, played
+            ));
+        }
+        if let Some(effect) = self.effects.iter().find(|e| e.r#type.trim().is_empty()) {
+            return Some(format!("effect with empty type ({:?})", effect.parameters));
+        }
+        None
     }
 
     /// Get the duration of the segment in seconds
     pub fn duration(&self) -> f64 {
         self.end - self.start
     }
+
+    /// Whether the segment plays its source range as-is: no effects, speed change, gain or fades
+    pub fn is_unmodified(&self) -> bool {
+        self.effects.is_empty()
+            && self.speed == 1.0
+            && self.gain == 0.0
+            && self.fade_in == 0.0
+            && self.fade_out == 0.0
+            && self.video_fade_in == 0.0
+            && self.video_fade_out == 0.0
+    }
+
+    /// Duration on the timeline once speed is applied
+    pub fn effective_duration(&self) -> f64 {
+        self.duration() / self.speed
+    }
+
+    /// `fade` filters for the video fades, over the segment played for `length` seconds
+    pub fn video_fade_filters(&self, length: f64) -> Vec<String> {
+        let mut filters = Vec::new();
+        if self.video_fade_in > 0.0 {
+            filters.push(format!("fade=t=in:st=0:d={}", self.video_fade_in));
+        }
+        if self.video_fade_out > 0.0 {
+            filters.push(format!("fade=t=out:st={}:d={}", (length - self.video_fade_out).max(0.0), self.video_fade_out));
+        }
+        filters
+    }
+
+    /// `afade` filters for the audio fades with the segment's curve, over the segment played
+    /// for `length` seconds
+    pub fn audio_fade_filters(&self, length: f64) -> Vec<String> {
+        let curve = self.fade_curve.afade_curve();
+        let mut filters = Vec::new();
+        if self.fade_in > 0.0 {
+            filters.push(format!("afade=t=in:st=0:d={}:curve={}", self.fade_in, curve));
+        }
+        if self.fade_out > 0.0 {
+            filters.push(format!("afade=t=out:st={}:d={}:curve={}", (length - self.fade_out).max(0.0), self.fade_out, curve));
+        }
+        filters
+    }
 }
 
 // TrackType
@@ -122,7 +466,11 @@ pub struct Track {
     pub enabled: bool,
     pub muted: bool,
     pub volume: u8, // 0-100 for audio tracks, else does not matter
+    #[serde(default)]
+    pub pan: i8, // Stereo balance, -100 (left) to 100 (right); mono sources are upmixed first
     pub order: u32, // Order of the track in the timeline
+    #[serde(default)]
+    pub track_effects: Vec<Effect>, // Audio effects after each segment's own; see audio_effects
 
     pub segments: Vec<Segment>, // Segments in this track. Order matters
 }
@@ -132,24 +480,289 @@ impl Track {
     pub fn verify(&self) -> bool {
         let segments_valid = self.segments.is_empty() || self.segments.iter().all(|seg| seg.verify());
         let volume_valid = self.r#type != TrackType::Audio || (self.volume <= 100);
+        let pan_valid = (-100..=100).contains(&self.pan);
+        let effects_valid = self.track_effects.iter().all(|e| audio_effects::validate(e).is_ok());
         
-        segments_valid && volume_valid
+        segments_valid && volume_valid && pan_valid && effects_valid
+    }
+
+    /// Timeline start/end of each segment. Segments play back to back in order, each taking
+    /// its duration divided by its speed; one with a `timeline_start` waits until then
+    pub fn timeline_spans(&self) -> Vec<(f64, f64)> {
+        let mut cursor = 0.0;
+        self.segments.iter().map(|segment| {
+            let start = segment.timeline_start.map_or(cursor, |t| t.max(cursor));
+            cursor = start;
+            cursor += segment.duration() / segment.speed;
+            (start, cursor)
+        }).collect()
+    }
+
+    /// Total played-back length of the track in seconds
+    pub fn duration(&self) -> f64 {
+        self.timeline_spans().last().map(|(_, end)| *end).unwrap_or(0.0)
+    }
+}
+
+// Marker
+/// A named point on the timeline
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Marker {
+    pub id: String,
+    pub time: f64, // Timeline seconds
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub color: Option<String>, // CSS hex color, e.g. "#f5c518"
+}
+
+/// A stretch of the edited output viewers are likely to skip, see retention.rs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RiskRegion {
+    pub start: f64, // Output seconds, with the cuts accepted when it was found taken out
+    pub end: f64,
+    pub score: f64, // 0 (holds attention) to 1 (likely skipped)
+    pub reason: String,
+}
+
+// TimeRange
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+// ProjectSettings
+/// Per-project settings, stored in the project file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProjectSettings {
+    #[serde(default)]
+    pub guides: GuideSettings,
+    #[serde(default)]
+    pub dynamic_range: DynamicRange, // What exports deliver; see ExportSettings.hdr_mode
+    #[serde(default)]
+    pub default_export_dir: Option<PathBuf>, // Where exports go unless a file is picked; see ProjectFile::export_dir
+    #[serde(default)]
+    pub fps: Option<f64>, // Timeline frame rate; see ProjectFile::fps
+    #[serde(default)]
+    pub fade_timeline_ends: bool, // Exports fade in from black/silence and out to it over the first and last second
+    #[serde(default)]
+    pub export_presets: Vec<ffmpeg::ExportPreset>, // Custom presets; the built-in ones aren't stored
+}
+
+/// Timeline layout kept with the project. It isn't an edit: changing it never adds an undo step
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TimelineViewState {
+    #[serde(default)]
+    pub track_heights: HashMap<String, f64>, // Track id -> height in pixels; tracks not listed use the default
+    #[serde(default)]
+    pub collapsed_tracks: Vec<String>, // Track ids
+    #[serde(default)]
+    pub zoom: Option<f64>, // Pixels per second
+    #[serde(default)]
+    pub scroll: Option<f64>, // Timeline seconds at the left edge
+}
+
+/// View state fields to overwrite. `None` leaves the field unchanged; a track height of
+/// `None` goes back to the default height
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ViewStatePatch {
+    pub track_heights: Option<HashMap<String, Option<f64>>>, // Merged into the existing heights
+    pub collapsed_tracks: Option<Vec<String>>,
+    pub zoom: Option<f64>,
+    pub scroll: Option<f64>,
+}
+
+impl ViewStatePatch {
+    pub fn apply(&self, view: &mut TimelineViewState) {
+        if let Some(heights) = &self.track_heights {
+            for (track_id, height) in heights {
+                match height {
+                    Some(height) => view.track_heights.insert(track_id.clone(), *height),
+                    None => view.track_heights.remove(track_id),
+                };
+            }
+        }
+        if let Some(collapsed) = &self.collapsed_tracks {
+            view.collapsed_tracks = collapsed.clone();
+        }
+        if let Some(zoom) = self.zoom {
+            view.zoom = Some(zoom);
+        }
+        if let Some(scroll) = self.scroll {
+            view.scroll = Some(scroll);
+        }
+    }
+}
+
+impl TimelineViewState {
+    /// Whether the values are usable: heights and zoom positive, scroll not negative
+    pub fn verify(&self) -> bool {
+        self.track_heights.values().all(|h| h.is_finite() && *h > 0.0)
+            && self.zoom.map_or(true, |z| z.is_finite() && z > 0.0)
+            && self.scroll.map_or(true, |s| s.is_finite() && s >= 0.0)
+    }
+
+    /// Drop entries for tracks that no longer exist
+    fn prune(&mut self, tracks: &HashMap<String, Track>) {
+        self.track_heights.retain(|id, _| tracks.contains_key(id));
+        self.collapsed_tracks.retain(|id| tracks.contains_key(id));
+    }
+}
+
+/// Delivery target of a project. With SDR, HDR clips are tone-mapped on export
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DynamicRange {
+    #[default]
+    Sdr,
+    Hdr,
+}
+
+/// Safe-area and aspect-ratio overlays for the preview
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GuideSettings {
+    #[serde(default)]
+    pub show_action_safe: bool,
+    #[serde(default)]
+    pub show_title_safe: bool,
+    #[serde(default = "default_action_safe")]
+    pub action_safe_percent: f64, // Share of frame width/height inside the action-safe area
+    #[serde(default = "default_title_safe")]
+    pub title_safe_percent: f64,
+    #[serde(default)]
+    pub target_aspects: Vec<String>, // Crop overlays, e.g. "9:16", "1:1", "2.39:1"
+}
+
+// SMPTE ST 2046-1 safe areas
+fn default_action_safe() -> f64 {
+    93.0
+}
+
+fn default_title_safe() -> f64 {
+    90.0
+}
+
+impl Default for GuideSettings {
+    fn default() -> Self {
+        Self {
+            show_action_safe: false,
+            show_title_safe: false,
+            action_safe_percent: default_action_safe(),
+            title_safe_percent: default_title_safe(),
+            target_aspects: Vec::new(),
+        }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProjectFile {
     pub title: String,
+    #[serde(default = "current_project_version")]
+    pub version: u32, // Schema version, see PROJECT_VERSION; files from before it existed are version 1
     pub clips_map: HashMap<String, Clip>, // Id to CLIP
     pub tracks_map: HashMap<String, Track>, // Id to Track
     pub path: Option<PathBuf>, // Where the ProjectFile is saved on disk.
     // This is a weird way of doing it but is convenient and its used frequently
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub accepted_cuts: Vec<TimeRange>, // Cuts the user accepted, in timeline seconds
+    #[serde(default)]
+    pub settings: ProjectSettings,
+    #[serde(default)]
+    pub view_state: TimelineViewState, // How the timeline was laid out, so it reopens the same for everyone
+    #[serde(default)]
+    pub retention_risks: Vec<RiskRegion>, // From the last retention risk analysis, see retention.rs
 
     // Add other fields here later, such as metadata, settings, 
     // and info about edits like segments and effects
     // and maybe cache probe info?
 }
 
+/// Schema version of the project files this build writes. Bump it and append a step to
+/// `MIGRATIONS` when a format change needs older files rewritten before they deserialize
+pub const PROJECT_VERSION: u32 = 2;
+
+fn current_project_version() -> u32 {
+    PROJECT_VERSION
+}
+
+/// Upgrades of the raw JSON of a project file, in order: `MIGRATIONS[i]` takes version `i + 1`
+/// to `i + 2`
+const MIGRATIONS: &[fn(&mut serde_json::Value) -> Result<()>] = &[migrate_v1_to_v2];
+
+/// Version 2 adds `version`. Version 1 files could also hold clip and track types in lowercase
+/// ("video"), as `ClipType::from_str` and `TrackType::from_str` accept them; serde only takes
+/// the variant names
+fn migrate_v1_to_v2(project: &mut serde_json::Value) -> Result<()> {
+    let object = project.as_object_mut().context("project file is not a JSON object")?;
+    for key in ["clips_map", "tracks_map"] {
+        let Some(entries) = object.get_mut(key).and_then(|m| m.as_object_mut()) else {
+            continue;
+        };
+        for kind in entries.values_mut().filter_map(|entry| entry.get_mut("type")) {
+            if let Some(name) = kind.as_str().and_then(type_variant_name) {
+                *kind = serde_json::Value::String(name);
+            }
+        }
+    }
+    object.insert("version".to_string(), serde_json::Value::from(2u32));
+    Ok(())
+}
+
+/// Variant name serde expects for a lowercase clip or track type, e.g. "video" -> "Video"
+fn type_variant_name(kind: &str) -> Option<String> {
+    if ClipType::from_str(kind).is_none() && TrackType::from_str(kind).is_none() {
+        return None;
+    }
+    let mut chars = kind.chars();
+    let first = chars.next()?;
+    Some(first.to_uppercase().chain(chars).collect())
+}
+
+/// Bring the raw JSON of a project file up to `PROJECT_VERSION`. Files without a version are
+/// version 1; files from a newer build are refused with a message saying so rather than
+/// whatever serde would make of them
+fn migrate(project: &mut serde_json::Value) -> Result<()> {
+    let version = match project.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| anyhow!("project file has an invalid version: {}", version))?,
+    };
+    if version > PROJECT_VERSION {
+        return Err(anyhow!(
+            "This project was created with a newer version of Gebo (project format {}, this version reads up to {}). Update Gebo to open it.",
+            version, PROJECT_VERSION
+        ));
+    }
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        step(project).with_context(|| format!("failed to upgrade the project from format {}", from + 1))?;
+    }
+    Ok(())
+}
+
+/// Result of `ProjectFile::validation_report`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub issues: Vec<String>, // Human-readable problems; empty when valid
+    pub clips: Vec<ClipReport>,
+    #[serde(default)]
+    pub warnings: Vec<String>, // Export concerns; these don't affect `valid`
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClipReport {
+    pub clip_id: String,
+    pub path: String,
+    pub exists: bool,
+    pub hash_status: HashStatus, // From the last verification; see media_hash.rs
+}
+
 impl ProjectFile { 
     fn verify_segments_in_tracks(&self) -> bool {
         for track in self.tracks_map.values() {
@@ -168,125 +781,787 @@ impl ProjectFile {
         clips_valid && tracks_valid && self.verify_segments_in_tracks()
     }
 
-    /// Load a ProjectFile from a given path
-    pub fn from_path(path: &Path) -> Result<Self> {
-        // Ensure path exists
-        if !path.exists() || !path.is_file() {
-            return Err(anyhow!("project file does not exist or is not a valid file"));
+    /// Everything `verify` checks, spelled out, plus each clip's file and hash status
+    pub fn validation_report(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+        let mut clips: Vec<ClipReport> = self.clips_map.values().map(|clip| {
+            let exists = clip.verify();
+            let hash_status = if exists || clip.hashes.is_none() { clip.hash_status() } else { HashStatus::Missing };
+            if !exists {
+                issues.push(format!("clip {}: file {:?} is missing", clip.id, clip.path));
+            } else if hash_status == HashStatus::Mismatch {
+                issues.push(format!("clip {}: file {:?} changed since import", clip.id, clip.path));
+            }
+            ClipReport {
+                clip_id: clip.id.clone(),
+                path: clip.path.to_string_lossy().to_string(),
+                exists,
+                hash_status,
+            }
+        }).collect();
+        clips.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for track in self.ordered_tracks() {
+            if track.r#type == TrackType::Audio && track.volume > 100 {
+                issues.push(format!("track {}: volume {} is above 100", track.id, track.volume));
+            }
+            if !(-100..=100).contains(&track.pan) {
+                issues.push(format!("track {}: pan {} is outside -100..100", track.id, track.pan));
+            }
+            for effect in &track.track_effects {
+                if let Err(e) = audio_effects::validate(effect) {
+                    issues.push(format!("track {}: {}", track.id, e));
+                }
+            }
+            for segment in &track.segments {
+                if let Some(error) = segment.validation_error() {
+                    issues.push(format!("track {} segment {}: {}", track.id, segment.id, error));
+                }
+                if !self.clips_map.contains_key(&segment.clip_id) {
+                    issues.push(format!("track {} segment {}: unknown clip {}", track.id, segment.id, segment.clip_id));
+                }
+            }
         }
+        ValidationReport { valid: issues.is_empty(), issues, clips, warnings: Vec::new() }
+    }
 
-        // Read file content, set self = deserialized content
-        let content: String = fs::read_to_string(path).with_context(|| "failed to read project file")?;
-        let mut project: Self = serde_json::from_str(&content).with_context(|| "invalid project file format")?;
-        
-        // Mutate self.path to be the provided path so path is always updated
-        project.path = Some(path.to_path_buf());
+    /// Fill an unset export pan and track effects from the track that plays `input`, and
+    /// resolve the HDR mode against the project's dynamic range
+    pub fn with_export_defaults(&self, input: &str, mut settings: ExportSettings) -> ExportSettings {
+        if settings.pan.is_none() {
+            settings.pan = self.track_pan_for_path(Path::new(input));
+        }
+        if settings.track_effects.is_none() {
+            settings.track_effects = self.track_effects_for_path(Path::new(input));
+        }
+        settings.hdr_mode = self.hdr_mode(settings.hdr_mode);
+        settings
+    }
 
-        // Ensure project is valid now
-        if !project.verify() {
-            return Err(anyhow!("project file is invalid."));
+    /// The HDR mode an export actually uses: Auto passes HDR through when the project targets HDR
+    pub fn hdr_mode(&self, requested: HdrMode) -> HdrMode {
+        match (requested, self.settings.dynamic_range) {
+            (HdrMode::Auto, DynamicRange::Hdr) => HdrMode::Passthrough,
+            (mode, _) => mode,
         }
+    }
 
-        Ok(project)
+    /// Ids used by more than one clip, track, segment (across all tracks) or marker,
+    /// as "kind id" in name order. Segment lookups silently hit the first match otherwise
+    pub fn duplicate_ids(&self) -> Vec<String> {
+        let ids = [
+            (ids::CLIP, self.clips_map.values().map(|c| c.id.as_str()).collect::<Vec<_>>()),
+            (ids::TRACK, self.tracks_map.values().map(|t| t.id.as_str()).collect()),
+            (ids::SEGMENT, self.tracks_map.values().flat_map(|t| &t.segments).map(|s| s.id.as_str()).collect()),
+            (ids::MARKER, self.markers.iter().map(|m| m.id.as_str()).collect()),
+        ];
+        let mut duplicates = Vec::new();
+        for (kind, mut ids) in ids {
+            ids.sort_unstable();
+            let mut repeated: Vec<&str> = ids.windows(2).filter(|w| w[0] == w[1]).map(|w| w[0]).collect();
+            repeated.dedup();
+            duplicates.extend(repeated.into_iter().map(|id| format!("{} {}", kind, id)));
+        }
+        duplicates
     }
 
-    /// Save the ProjectFile to its stored path
-    pub fn save(&self) -> Result<()> {
-        // JSONify self
-        let content = serde_json::to_string_pretty(self).with_context(|| "failed to serialize project file")?;
-        // Write to self.path
-        fs::write(self.path.as_ref().context("project file path is not set")?, content).with_context(|| "failed to write project file")?;
-        Ok(())
+    /// Err naming the duplicated ids, if any
+    pub fn check_unique_ids(&self) -> Result<()> {
+        let duplicates = self.duplicate_ids();
+        if duplicates.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!("duplicate ids: {} (repair_duplicate_ids rewrites them)", duplicates.join(", ")))
     }
-}
 
+    /// Concerns about exporting the timeline with `settings`. Passthrough on a timeline
+    /// that mixes SDR and HDR video leaves the SDR parts looking flat next to the HDR ones.
+    /// Uses the cached clip probes.
+    pub fn export_warnings(&self, settings: &ExportSettings) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.hdr_mode(settings.hdr_mode) == HdrMode::Passthrough {
+            let used: Vec<&Probe> = self.tracks_map.values()
+                .flat_map(|t| &t.segments)
+                .filter_map(|s| self.clips_map.get(&s.clip_id))
+                .filter_map(|c| c.latest_probe.as_ref())
+                .filter(|p| p.width > 0)
+                .collect();
+            let hdr = used.iter().filter(|p| p.is_hdr).count();
+            if hdr > 0 && hdr < used.len() {
+                warnings.push(format!(
+                    "HDR passthrough on a timeline mixing SDR and HDR video ({} of {} segments are HDR); SDR segments won't match",
+                    hdr, used.len()
+                ));
+            }
+        }
+        let mut vfr: Vec<&Clip> = self.tracks_map.values()
+            .flat_map(|t| &t.segments)
+            .filter_map(|s| self.clips_map.get(&s.clip_id))
+            .filter(|c| c.latest_probe.as_ref().is_some_and(|p| p.is_vfr))
+            .collect();
+        vfr.sort_by(|a, b| a.id.cmp(&b.id));
+        vfr.dedup_by(|a, b| a.id == b.id);
+        for clip in vfr {
+            let name = clip.path.file_name().unwrap_or_default().to_string_lossy();
+            warnings.extend(clip.latest_probe.as_ref().and_then(|p| p.vfr_warning(&name)));
+        }
+        warnings.extend(self.upscale_check(settings).map(|(_, warning)| warning));
+        warnings
+    }
 
+    /// Pan of the first track (in timeline order) that plays the file at `path`, if any
+    pub fn track_pan_for_path(&self, path: &Path) -> Option<i8> {
+        let clip_ids: Vec<&str> = self.clips_at_path(path).iter().map(|c| c.id.as_str()).collect();
+        self.ordered_tracks()
+            .into_iter()
+            .find(|t| t.segments.iter().any(|s| clip_ids.contains(&s.clip_id.as_str())))
+            .map(|t| t.pan)
+    }
 
-// Global Project State Management
+    /// Audio effects of the first track (in timeline order) that plays the file at `path`, if any
+    pub fn track_effects_for_path(&self, path: &Path) -> Option<Vec<Effect>> {
+        let clip_ids: Vec<&str> = self.clips_at_path(path).iter().map(|c| c.id.as_str()).collect();
+        self.ordered_tracks()
+            .into_iter()
+            .find(|t| t.segments.iter().any(|s| clip_ids.contains(&s.clip_id.as_str())))
+            .map(|t| t.track_effects.clone())
+    }
 
-/// Global project state that handles all project operations
-struct ProjectState {
-    project: ProjectFile,
-}
+    /// Preview LUT of the first clip at `path` that has one, resolved against the project
+    /// folder. A LUT file that doesn't exist is logged and ignored
+    pub fn preview_lut_for_path(&self, path: &Path) -> Option<PathBuf> {
+        let lut = self.clips_at_path(path).into_iter().find_map(|c| c.preview_lut.clone())?;
+        let lut = match self.path.as_ref().and_then(|p| p.parent()) {
+            Some(dir) if lut.is_relative() => dir.join(lut),
+            _ => lut,
+        };
+        if !lut.is_file() {
+            log::warn!("Preview LUT {:?} for {:?} not found; previews are shown without it", lut, path);
+            return None;
+        }
+        Some(lut)
+    }
 
-impl ProjectState {
-    /// Create a new project state
-    fn new(project: ProjectFile) -> Result<Self> {
-        Ok(Self {
-            project,
-        })
+    /// Length of the timeline in seconds: the longest track
+    pub fn timeline_duration(&self) -> f64 {
+        self.tracks_map.values().map(|t| t.duration()).fold(0.0, f64::max)
     }
 
-    /// Load a project from path and create state
-    fn load_from_path(path: String) -> Result<Self> {
-        let path_buf = PathBuf::from(&path);
-        let project = ProjectFile::from_path(&path_buf)?;
-        
-        Ok(Self {
-            project,
-        })
+    /// Timeline time -> time in the edited output, once the accepted cuts are taken out
+    pub fn output_remap(&self) -> Remap {
+        let accepted: Vec<(f64, f64)> = self.accepted_cuts.iter().map(|c| (c.start, c.end)).collect();
+        Remap::new(intervals::complement(&accepted, self.timeline_duration()))
     }
 
-    /// Save the project
-    fn save(&mut self, new_path: Option<String>) -> Result<()> {
-        // Update path if provided
-        if let Some(new_path_str) = new_path {
-            self.project.path = Some(PathBuf::from(new_path_str));
+    /// Output time of each timeline time in `times`; None for times the accepted cuts remove
+    pub fn remap_to_output_time(&self, times: &[f64]) -> Vec<Option<f64>> {
+        let remap = self.output_remap();
+        times.iter().map(|t| remap.to_output_kept(*t)).collect()
+    }
+
+    /// Markers at their output times, for the edited view and chapters. Markers inside accepted
+    /// cuts are left out
+    pub fn output_markers(&self) -> Vec<Marker> {
+        let remap = self.output_remap();
+        let mut markers: Vec<Marker> = self.markers.iter().filter_map(|marker| {
+            let time = remap.to_output_kept(marker.time)?;
+            Some(Marker { time, ..marker.clone() })
+        }).collect();
+        markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+        markers
+    }
+
+    /// Accepted cuts mapped into `clip_id`'s own time, through every segment that uses the clip.
+    /// Sorted; cuts from different segments may overlap
+    pub fn clip_time_cuts(&self, clip_id: &str) -> Vec<(f64, f64)> {
+        let accepted: Vec<(f64, f64)> = self.accepted_cuts.iter().map(|c| (c.start, c.end)).collect();
+        let mut cuts = Vec::new();
+        for track in self.tracks_map.values() {
+            for (segment, span) in track.segments.iter().zip(track.timeline_spans()) {
+                if segment.clip_id != clip_id {
+                    continue;
+                }
+                cuts.extend(intervals::intersect(&[span], &accepted).into_iter().map(|(from, to)| (
+                    segment.start + (from - span.0) * segment.speed,
+                    segment.start + (to - span.0) * segment.speed,
+                )));
+            }
         }
-        
-        // Save the project
-        self.project.save()
+        cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        cuts
     }
 
-    /// Update the project data and save to disk
-    fn update(&mut self, updated_project: ProjectFile) -> Result<()> {
-        self.project = updated_project;
-        
-        // Save changes immediately
-        self.save(None)
+    /// Copy of the project with the accepted cuts taken out of every track, i.e. what the edited
+    /// output plays. Segments are trimmed or split around each cut and pinned to their output
+    /// time, so gaps stay where they were. Fades stay on the ends the cuts leave alone
+    pub fn with_cuts_applied(&self) -> ProjectFile {
+        let accepted: Vec<(f64, f64)> = self.accepted_cuts.iter().map(|c| (c.start, c.end)).collect();
+        let remap = self.output_remap();
+        let mut project = self.clone();
+        project.accepted_cuts.clear();
+        for track in project.tracks_map.values_mut() {
+            let mut segments = Vec::new();
+            for (segment, span) in track.segments.iter().zip(track.timeline_spans()) {
+                let kept = intervals::subtract(&[span], &accepted);
+                for (i, (from, to)) in kept.into_iter().filter(|(from, to)| to - from > 1e-3).enumerate() {
+                    let mut part = segment.clone();
+                    if i > 0 {
+                        part.id = ids::new_id(ids::SEGMENT);
+                    }
+                    part.timeline_start = Some(remap.to_output(from));
+                    if from > span.0 {
+                        part.start = segment.start + (from - span.0) * segment.speed;
+                        part.fade_in = 0.0;
+                        part.video_fade_in = 0.0;
+                    }
+                    if to < span.1 {
+                        part.end = segment.start + (to - span.0) * segment.speed;
+                        part.fade_out = 0.0;
+                        part.video_fade_out = 0.0;
+                    }
+                    segments.push(part);
+                }
+            }
+            track.segments = segments;
+        }
+        project
     }
 
-    /// Get a clone of the project data
-    fn get_project(&self) -> ProjectFile {
-        self.project.clone()
+    /// The clip the preview frames: first segment on the topmost enabled video track,
+    /// falling back to any video clip
+    pub fn primary_video_clip(&self) -> Option<&Clip> {
+        self.ordered_tracks().into_iter()
+            .filter(|t| t.enabled && t.r#type == TrackType::Video)
+            .flat_map(|t| t.segments.iter())
+            .find_map(|segment| self.clips_map.get(&segment.clip_id))
+            .or_else(|| self.clips_map.values().find(|c| c.r#type == ClipType::Video))
     }
-}
 
-// Global singleton state
-static PROJECT_STATE: OnceLock<Mutex<Option<ProjectState>>> = OnceLock::new();
+    /// Every clip is audio (a podcast edit), so previews and exports build pure-audio graphs.
+    /// Derived from the clips, so adding a video clip turns it off again
+    pub fn is_audio_only(&self) -> bool {
+        !self.clips_map.is_empty() && self.clips_map.values().all(|c| c.r#type == ClipType::Audio)
+    }
 
-/// Get the global project state singleton
-fn get_global_state() -> &'static Mutex<Option<ProjectState>> {
-    PROJECT_STATE.get_or_init(|| Mutex::new(None))
-}
+    /// First clip on an enabled audio track, else any audio clip; the main clip of an audio-only project
+    pub fn primary_audio_clip(&self) -> Option<&Clip> {
+        self.ordered_tracks().into_iter()
+            .filter(|t| t.enabled && t.r#type == TrackType::Audio)
+            .flat_map(|t| t.segments.iter())
+            .find_map(|segment| self.clips_map.get(&segment.clip_id))
+            .or_else(|| self.clips_map.values().find(|c| c.r#type == ClipType::Audio))
+    }
 
-// Public API functions
+    /// Frame rate conforms target: `settings.fps`, else the rate of the first constant-frame-rate
+    /// video clip on the timeline, else 30
+    pub fn fps(&self) -> f64 {
+        if let Some(fps) = self.settings.fps.filter(|f| *f > 0.0) {
+            return fps;
+        }
+        self.ordered_tracks().into_iter()
+            .filter(|t| t.r#type == TrackType::Video)
+            .flat_map(|t| t.segments.iter())
+            .filter_map(|s| self.clips_map.get(&s.clip_id)?.latest_probe.as_ref())
+            .find(|p| p.width > 0 && !p.is_vfr && p.fps > 0.0)
+            .map(|p| p.fps)
+            .unwrap_or(30.0)
+    }
 
-/// Create a new project and set it as current (for unsaved projects)
-pub fn new_project(project: ProjectFile) -> Result<ProjectFile> {
-    let state = get_global_state();
-    let mut guard = state.lock().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
-    
-    let mut project_state = ProjectState::new(project)?;
-    
-    // Save the project to disk if it has a path
-    if project_state.project.path.is_some() {
-        project_state.save(None)?;
+    /// Height and frame rate limits of the video clips on the timeline's video tracks
+    pub fn source_limits(&self) -> Option<ffmpeg::SourceLimits> {
+        ffmpeg::SourceLimits::of(
+            self.tracks_map.values()
+                .filter(|t| t.r#type == TrackType::Video)
+                .flat_map(|t| &t.segments)
+                .filter_map(|s| self.clips_map.get(&s.clip_id)?.latest_probe.as_ref()),
+        )
     }
-    
-    let result = project_state.get_project();
-    
-    *guard = Some(project_state);
-    Ok(result)
-}
 
-/// Load a project from a file path and set it as current
-pub fn load_project(path: String) -> Result<ProjectFile> {
-    let state = get_global_state();
-    let mut guard = state.lock().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
-    
-    let project_state = ProjectState::load_from_path(path)?;
+    /// Whether a timeline export with `settings` asks for more than the sources have, and
+    /// what it does about it; see ExportSettings::upscale_check
+    pub fn upscale_check(&self, settings: &ExportSettings) -> Option<(ffmpeg::UpscaleDecision, String)> {
+        settings.upscale_check(&self.source_limits()?, Some(self.fps()))
+    }
+
+    /// Frame rate timeline exports render at: `fps`, but no faster than the fastest video
+    /// source unless `settings.allow_upscale`
+    pub fn export_fps(&self, settings: &ExportSettings) -> f64 {
+        let fps = self.fps();
+        match self.source_limits() {
+            Some(limits) if !settings.allow_upscale && limits.fps_above(fps) => limits.fps,
+            _ => fps,
+        }
+    }
+
+    /// Number of segments, across all tracks, that play `clip_id`
+    pub fn clip_usage(&self, clip_id: &str) -> usize {
+        self.tracks_map.values()
+            .flat_map(|t| t.segments.iter())
+            .filter(|s| s.clip_id == clip_id)
+            .count()
+    }
+
+    /// Clips whose file is `path`. Paths are compared canonicalized when they exist
+    pub fn clips_at_path(&self, path: &Path) -> Vec<&Clip> {
+        let wanted = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.clips_map.values()
+            .filter(|c| fs::canonicalize(&c.path).unwrap_or_else(|_| c.path.clone()) == wanted)
+            .collect()
+    }
+
+    /// Folder next to the project file where rendered media (baked segments) is written
+    pub fn media_dir(&self) -> Result<PathBuf> {
+        let path = self.path.as_ref().ok_or_else(|| anyhow!("save the project before rendering media into it"))?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "project".to_string());
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        Ok(parent.join(format!("{}_media", stem)))
+    }
+
+    /// Folder exports go to unless the user picks a file: `settings.default_export_dir`,
+    /// else the project file's folder, else the exports work dir (Downloads when writable)
+    pub fn export_dir(&self) -> PathBuf {
+        let preferred = self.settings.default_export_dir.clone()
+            .or_else(|| self.path.as_ref().and_then(|path| path.parent()).map(|parent| parent.to_path_buf()));
+        work_dirs::output_dir(preferred)
+    }
+
+    /// `<title>.<extension>` in `dir`, with characters file systems reject replaced
+    pub fn export_path_in(&self, dir: &Path, extension: &str) -> PathBuf {
+        let name: String = self.title.trim().chars()
+            .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+            .collect();
+        let name = if name.is_empty() { "export".to_string() } else { name };
+        dir.join(format!("{}.{}", name, extension))
+    }
+
+    /// Find a segment by track and segment id
+    pub fn segment(&self, track_id: &str, segment_id: &str) -> Result<&Segment> {
+        self.tracks_map.get(track_id)
+            .ok_or_else(|| anyhow!("track not found: {}", track_id))?
+            .segments.iter()
+            .find(|s| s.id == segment_id)
+            .ok_or_else(|| anyhow!("segment {} not found on track {}", segment_id, track_id))
+    }
+
+    /// Tracks in timeline order
+    pub fn ordered_tracks(&self) -> Vec<&Track> {
+        let mut tracks: Vec<&Track> = self.tracks_map.values().collect();
+        tracks.sort_by_key(|t| t.order);
+        tracks
+    }
+
+    /// Load a ProjectFile from a given path
+    pub fn from_path(path: &Path) -> Result<Self> {
+        Self::read(path, path)
+    }
+
+    /// Backup `which` (1 is the newest) of the project saved at `path`, see `save`. The backup
+    /// describes that project, so its path is `path`; the project file itself isn't touched
+    pub fn from_backup(path: &Path, which: usize) -> Result<Self> {
+        if !(1..=MAX_BACKUPS).contains(&which) {
+            return Err(anyhow!("backup {} does not exist; projects keep backups 1 to {}", which, MAX_BACKUPS));
+        }
+        Self::read(&backup_path(path, which), path)
+    }
+
+    /// Load a ProjectFile from a given path without verifying it, so a project with missing
+    /// clips still loads. For tools that report problems through `validation_report`
+    pub fn from_path_unverified(path: &Path) -> Result<Self> {
+        Self::read_unverified(path, path)
+    }
+
+    /// Read the project in `file`, saved at `path`
+    fn read(file: &Path, path: &Path) -> Result<Self> {
+        let project = Self::read_unverified(file, path)?;
+
+        // Ensure project is valid now
+        if !project.verify() {
+            return Err(anyhow!("project file is invalid."));
+        }
+
+        Ok(project)
+    }
+
+    /// Read, upgrade and deserialize the project in `file`, saved at `path`
+    fn read_unverified(file: &Path, path: &Path) -> Result<Self> {
+        // Ensure path exists
+        if !file.exists() || !file.is_file() {
+            return Err(anyhow!("project file does not exist or is not a valid file"));
+        }
+
+        // Read file content, upgrade it to the current format, set self = deserialized content
+        let content: String = fs::read_to_string(file).with_context(|| "failed to read project file")?;
+        let mut raw: serde_json::Value = serde_json::from_str(&content).with_context(|| "invalid project file format")?;
+        migrate(&mut raw)?;
+        let mut project: Self = serde_json::from_value(raw).with_context(|| "invalid project file format")?;
+        
+        // Mutate self.path to be the provided path so path is always updated
+        project.path = Some(path.to_path_buf());
+
+        Ok(project)
+    }
+
+    /// Save the ProjectFile to its stored path. It is written to `{name}.tmp`, synced and renamed
+    /// over the file, so a crash or full disk mid-write leaves the previous version in place.
+    /// That version becomes backup 1 first, older backups move up to `MAX_BACKUPS`, on the
+    /// first save of the session and then at most every `BACKUP_INTERVAL`
+    pub fn save(&self) -> Result<()> {
+        let path = self.path.as_ref().context("project file path is not set")?;
+        self.write_file(path, backup_due(path))
+    }
+
+    /// Write the project to `path` through a temp file, first making the file there the newest
+    /// backup if `backup` is set
+    fn write_file(&self, path: &Path, backup: bool) -> Result<()> {
+        // JSONify self
+        let content = serde_json::to_string_pretty(self).with_context(|| "failed to serialize project file")?;
+        let temp = with_suffix(path, "tmp");
+        let written = fs::File::create(&temp).and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(e).with_context(|| "failed to write project file");
+        }
+        if backup {
+            if let Err(e) = rotate_backups(path) {
+                log::warn!("Failed to back up {:?}: {}", path, e);
+            }
+        }
+        fs::rename(&temp, path).with_context(|| "failed to move the project file into place")?;
+        Ok(())
+    }
+}
+
+/// Backups kept next to a saved project: `.bak1` (the newest) to `.bak3`
+pub const MAX_BACKUPS: usize = 3;
+
+/// Least time between two backups of a project. Saves come on every edit; backing each up would
+/// leave only the last few seconds of work in the backups
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// When each project file was last backed up in this session
+static LAST_BACKUP: Mutex<BTreeMap<PathBuf, Instant>> = Mutex::new(BTreeMap::new());
+
+/// Whether a save to `path` should back up the file it replaces: the first save of the session,
+/// then once per `BACKUP_INTERVAL`. Records the backup when it says so
+fn backup_due(path: &Path) -> bool {
+    let mut last = LAST_BACKUP.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    if last.get(path).is_some_and(|at| now.duration_since(*at) < BACKUP_INTERVAL) {
+        return false;
+    }
+    last.insert(path.to_path_buf(), now);
+    true
+}
+
+/// `path` with `.{suffix}` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Where backup `which` (1 is the newest) of the project at `path` is kept
+pub fn backup_path(path: &Path, which: usize) -> PathBuf {
+    with_suffix(path, &format!("bak{}", which))
+}
+
+/// Make the file at `path` the newest backup, shifting older ones and dropping the oldest. A file
+/// that isn't valid JSON (a save cut short before saves were atomic) isn't worth keeping and
+/// would push out good backups, so the backups are left alone then
+fn rotate_backups(path: &Path) -> Result<()> {
+    let Ok(current) = fs::read(path) else {
+        return Ok(());
+    };
+    if serde_json::from_slice::<serde_json::Value>(&current).is_err() {
+        return Ok(());
+    }
+    for which in (1..MAX_BACKUPS).rev() {
+        let older = backup_path(path, which);
+        if older.exists() {
+            fs::rename(&older, backup_path(path, which + 1)).with_context(|| format!("failed to rotate {:?}", older))?;
+        }
+    }
+    fs::write(backup_path(path, 1), current).with_context(|| "failed to write the backup")?;
+    Ok(())
+}
+
+
+
+// Global Project State Management
+
+/// Maximum number of undo steps kept in memory
+const MAX_UNDO_STEPS: usize = 50;
+
+/// Global project state that handles all project operations
+struct ProjectState {
+    project: ProjectFile,
+    undo_stack: Vec<ProjectFile>, // Previous versions of the project, newest last
+    pending_save: Option<PendingSave>, // Written once the state lock is released, see `mutate`
+}
+
+/// Numbers snapshots in the order they were taken, so a slow write never overwrites a newer one
+static SAVE_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Serializes project writes; holds the generation last written to each path. Per path, so an
+/// older snapshot of one project still writes after a newer one of another
+static SAVE_LOCK: Mutex<BTreeMap<PathBuf, u64>> = Mutex::new(BTreeMap::new());
+
+/// A snapshot of the project to write to disk outside the state lock
+struct PendingSave {
+    generation: u64,
+    project: ProjectFile,
+}
+
+impl PendingSave {
+    /// Must be called with the state lock held, so generations follow the order of changes
+    fn new(project: &ProjectFile) -> Self {
+        Self { generation: SAVE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1, project: project.clone() }
+    }
+
+    /// Write the snapshot, unless a newer one was already written to the same file
+    fn write(self) -> Result<()> {
+        let path = self.project.path.clone().context("project file path is not set")?;
+        let mut written = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if written.get(&path).is_some_and(|last| self.generation < *last) {
+            return Ok(());
+        }
+        self.project.save()?;
+        written.insert(path, self.generation);
+        Ok(())
+    }
+}
+
+impl ProjectState {
+    /// Create a new project state
+    fn new(project: ProjectFile) -> Result<Self> {
+        Ok(Self {
+            project,
+            undo_stack: Vec::new(),
+            pending_save: None,
+        })
+    }
+
+    /// Load a project from path and create state
+    fn load_from_path(path: String) -> Result<Self> {
+        let path_buf = PathBuf::from(&path);
+        let project = ProjectFile::from_path(&path_buf)?;
+        
+        Ok(Self {
+            project,
+            undo_stack: Vec::new(),
+            pending_save: None,
+        })
+    }
+
+    /// Replace the project with a mutated copy as a single undo step, then save if it has a path
+    fn commit(&mut self, mutated: ProjectFile) -> Result<()> {
+        self.check_added_duplicates(&mutated)?;
+        let previous = std::mem::replace(&mut self.project, mutated);
+        self.undo_stack.push(previous);
+        if self.undo_stack.len() > MAX_UNDO_STEPS {
+            self.undo_stack.remove(0);
+        }
+
+        if self.project.path.is_some() {
+            self.save(None)?;
+        }
+        Ok(())
+    }
+
+    /// Revert the last committed mutation. Returns false if there is nothing to undo
+    fn undo(&mut self) -> Result<bool> {
+        let Some(mut previous) = self.undo_stack.pop() else {
+            return Ok(false);
+        };
+        // The layout isn't part of the edit history
+        previous.view_state = std::mem::take(&mut self.project.view_state);
+        self.project = previous;
+
+        if self.project.path.is_some() {
+            self.save(None)?;
+        }
+        Ok(true)
+    }
+
+    /// Replace the view state without an undo step. It is only saved right away when the user
+    /// asked for view changes to be saved; otherwise it goes out with the next save
+    fn set_view_state(&mut self, view_state: TimelineViewState) -> Result<()> {
+        self.project.view_state = view_state;
+        let save_view_changes = crate::longterm_storage::get_settings().map(|s| s.save_view_changes).unwrap_or(false);
+        if save_view_changes && self.project.path.is_some() {
+            self.save(None)?;
+        }
+        Ok(())
+    }
+
+    /// Save the project. The write itself happens after the state lock is released, see `mutate`
+    fn save(&mut self, new_path: Option<String>) -> Result<()> {
+        // Update path if provided
+        if let Some(new_path_str) = new_path {
+            self.project.path = Some(PathBuf::from(new_path_str));
+        }
+        if self.project.path.is_none() {
+            return Err(anyhow!("project file path is not set"));
+        }
+
+        self.pending_save = Some(PendingSave::new(&self.project));
+        Ok(())
+    }
+
+    /// Err naming ids `mutated` duplicates that the current project doesn't. Duplicates already
+    /// in a loaded file are left for repair_duplicate_ids, so it can still be edited and saved
+    fn check_added_duplicates(&self, mutated: &ProjectFile) -> Result<()> {
+        let existing = self.project.duplicate_ids();
+        let added: Vec<String> = mutated.duplicate_ids().into_iter().filter(|d| !existing.contains(d)).collect();
+        if !added.is_empty() {
+            return Err(anyhow!("duplicate ids: {}", added.join(", ")));
+        }
+        Ok(())
+    }
+
+    /// Update the project data and save to disk. Rejected if it adds duplicate ids. The view
+    /// state stays as it was; it only changes through `set_view_state`
+    fn update(&mut self, mut updated_project: ProjectFile) -> Result<()> {
+        self.check_added_duplicates(&updated_project)?;
+        updated_project.view_state = std::mem::take(&mut self.project.view_state);
+        self.project = updated_project;
+        
+        // Save changes immediately
+        self.save(None)
+    }
+
+    /// Get a clone of the project data
+    fn get_project(&self) -> ProjectFile {
+        self.project.clone()
+    }
+}
+
+// Global singleton state. Reads share the lock; nothing holds it while touching the disk
+static PROJECT_STATE: OnceLock<RwLock<Option<ProjectState>>> = OnceLock::new();
+
+/// Get the global project state singleton
+fn get_global_state() -> &'static RwLock<Option<ProjectState>> {
+    PROJECT_STATE.get_or_init(|| RwLock::new(None))
+}
+
+/// Run `f` on the current project with the write lock held. A save it asks for is written
+/// after the lock is released, so reads never wait on the disk; its error is still returned
+fn mutate<T>(f: impl FnOnce(&mut ProjectState) -> Result<T>) -> Result<T> {
+    let (result, pending) = {
+        let state = get_global_state();
+        let mut guard = state.write().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+
+        let project_state = guard.as_mut().ok_or_else(|| anyhow!("no project is currently loaded"))?;
+        let result = f(project_state);
+        (result, project_state.pending_save.take())
+    };
+    if let Some(pending) = pending {
+        pending.write()?;
+    }
+    result
+}
+
+/// Release the lock of the project being replaced by the one at `next`, unless it's the same
+/// file, and revoke its media. A project another editor window still shows keeps its lock and
+/// media, see editor_windows
+fn release_replaced(current: &Option<ProjectState>, next: Option<&Path>) {
+    let current = current.as_ref().and_then(|s| s.project.path.as_deref());
+    // Agent proposals were made for the project going away; reloading the same file keeps them
+    if next.is_none() || current != next {
+        ai_agent::clear_proposals();
+    }
+    if next.is_some() && current.is_some() && current != next && editor_windows::is_open_for(current) {
+        return;
+    }
+    if let Some(path) = current {
+        if Some(path) != next {
+            project_lock::release(path);
+        }
+    }
+    media_protocol::revoke_project_media();
+    frame_stepper::close_all();
+}
+
+/// Err when replacing the loaded project would lose an editor's work: it was never saved and
+/// its editor is open, or `next` is a new unsaved project while editors are open, which
+/// focusing one of them would replace. See editor_windows
+fn check_editor_free(next: Option<&Path>) -> Result<()> {
+    if !editor_windows::any_open() {
+        return Ok(());
+    }
+    if next.is_none() {
+        return Err(anyhow!("close the open editors before creating an unsaved project"));
+    }
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    match guard.as_ref().map(|s| &s.project) {
+        Some(current) if current.path.is_none() && editor_windows::is_open_for(None) => {
+            Err(anyhow!("{:?} is not saved; save or close it before opening another project", current.title))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Public API functions
+
+/// Create a new project and set it as current (for unsaved projects). Refused when it would
+/// replace work an editor holds, see `check_editor_free`
+pub fn new_project(project: ProjectFile) -> Result<ProjectFile> {
+    project.check_unique_ids()?;
+    check_editor_free(project.path.as_deref())?;
+    if let Some(path) = &project.path {
+        project_lock::acquire(path)?;
+    }
+    let mut project_state = ProjectState::new(project)?;
     let result = project_state.get_project();
+    let pending = {
+        let state = get_global_state();
+        let mut guard = state.write().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+
+        release_replaced(&guard, result.path.as_deref());
+
+        // Save the project to disk if it has a path
+        if project_state.project.path.is_some() {
+            project_state.save(None)?;
+        }
+        let pending = project_state.pending_save.take();
+        *guard = Some(project_state);
+        pending
+    };
+    if let Some(pending) = pending {
+        pending.write()?;
+    }
+    Ok(result)
+}
+
+/// Load a project from a file path and set it as current. Refused when it would replace work
+/// an editor holds, see `check_editor_free`
+pub fn load_project(path: String) -> Result<ProjectFile> {
+    let path_buf = PathBuf::from(&path);
+    check_editor_free(Some(&path_buf))?;
+    // Refused while another app has it open, see project_lock
+    let already_ours = project_lock::status(&path_buf) == project_lock::LockStatus::Ours;
+    project_lock::acquire(&path_buf)?;
+    // Read before locking; the current project stays usable meanwhile
+    let mut project_state = ProjectState::load_from_path(path).inspect_err(|_| {
+        if !already_ours {
+            project_lock::release(&path_buf);
+        }
+    })?;
+    // Not an undo step: the clips only gain what import would have given them
+    if backfill_clips(&mut project_state.project) {
+        if let Err(e) = PendingSave::new(&project_state.project).write() {
+            log::warn!("Failed to save backfilled clips of {:?}: {}", path_buf, e);
+        }
+    }
+    let result = project_state.get_project();
+
+    let state = get_global_state();
+    let mut guard = state.write().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+    release_replaced(&guard, Some(&path_buf));
     
     *guard = Some(project_state);
     Ok(result)
@@ -294,44 +1569,1078 @@ pub fn load_project(path: String) -> Result<ProjectFile> {
 
 /// Get the current project, if any
 pub fn get_project() -> Result<Option<ProjectFile>, String> {
-    let state = get_global_state();
-    let guard = state.lock().map_err(|e| format!("failed to lock project state: {}", e))?;
+    read_project(get_global_state())
+}
+
+/// `get_project` on `state`
+fn read_project(state: &RwLock<Option<ProjectState>>) -> Result<Option<ProjectFile>, String> {
+    let guard = state.read().map_err(|e| format!("failed to lock project state: {}", e))?;
     
     Ok(guard.as_ref().map(|s| s.get_project()))
 }
 
+/// Get a clip of the current project by id
+pub fn get_clip(clip_id: &str) -> Result<Clip> {
+    let state = get_global_state();
+    let guard = state.read().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+
+    let project_state = guard.as_ref().ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    project_state.project.clips_map.get(clip_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("clip not found: {}", clip_id))
+}
+
+/// Add a clip to the current project. If a clip with the same file is already registered,
+/// that clip is returned instead of adding a duplicate
+pub fn add_clip(clip: Clip) -> Result<Clip> {
+    mutate(|project_state| {
+        if let Some(existing) = project_state.project.clips_at_path(&clip.path).first() {
+            return Ok((*existing).clone());
+        }
+        let mut project = project_state.get_project();
+        project.clips_map.insert(clip.id.clone(), clip.clone());
+        project_state.commit(project)?;
+        Ok(clip)
+    })
+}
+
+/// Store per-stream hashes on a clip of the current project. They describe the file rather than
+/// an edit, so this adds no undo step
+pub fn set_clip_stream_hashes(clip_id: &str, hashes: StreamHashes) -> Result<()> {
+    mutate(|project_state| {
+        let clip = project_state.project.clips_map.get_mut(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        clip.stream_hashes = Some(hashes);
+        if project_state.project.path.is_some() {
+            project_state.save(None)?;
+        }
+        Ok(())
+    })
+}
+
+/// Store a transcript on a clip of the current project, with the language the provider
+/// reported or, failing that, the one detected from its text
+pub fn set_clip_transcript(clip_id: &str, segments: Vec<TranscriptSegment>, language: Option<&str>) -> Result<()> {
+    let language = language.and_then(transcription::normalize_language).or_else(|| transcription::detect_language(&segments));
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let clip = project.clips_map.get_mut(clip_id)
+            .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        clip.transcript = Some(segments);
+        clip.transcript_language = language;
+        project_state.commit(project)
+    })
+}
+
+/// Add `markers` to the current project, e.g. accepted chapter suggestions. Markers whose id
+/// is taken get a new one. Returns the project's markers
+pub fn add_markers(markers: Vec<Marker>) -> Result<Vec<Marker>> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        for mut marker in markers {
+            if marker.id.is_empty() || project.markers.iter().any(|m| m.id == marker.id) {
+                marker.id = ids::new_id(ids::MARKER);
+            }
+            project.markers.push(marker);
+        }
+        project.markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let markers = project.markers.clone();
+        project_state.commit(project)?;
+        Ok(markers)
+    })
+}
+
+/// Add `tracks` above the current project's tracks, in the order given, as one undo step, e.g.
+/// a generated montage. Every segment must be valid and use a clip of the project. Returns the
+/// updated project
+pub fn add_tracks(tracks: Vec<Track>) -> Result<ProjectFile> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let mut order = project.tracks_map.values().map(|t| t.order + 1).max().unwrap_or(0);
+        for mut track in tracks {
+            for segment in &track.segments {
+                if !project.clips_map.contains_key(&segment.clip_id) {
+                    return Err(anyhow!("clip not found: {}", segment.clip_id));
+                }
+                if let Some(reason) = segment.validation_error() {
+                    return Err(anyhow!("invalid segment in track {}: {}", track.name, reason));
+                }
+            }
+            track.order = order;
+            order += 1;
+            project.tracks_map.insert(track.id.clone(), track);
+        }
+        project_state.commit(project.clone())?;
+        Ok(project)
+    })
+}
+
+/// Set the language of a clip of the current project, overriding detection, or go back to the
+/// detected one (`None`)
+pub fn set_clip_language(clip_id: &str, language: Option<&str>) -> Result<Clip> {
+    let language = match language.map(str::trim).filter(|l| !l.is_empty()) {
+        Some(tag) => Some(transcription::normalize_language(tag).ok_or_else(|| anyhow!("not a language code: {}", tag))?),
+        None => None,
+    };
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let clip = project.clips_map.get_mut(clip_id)
+            .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        clip.language_override = language;
+        let updated = clip.clone();
+        project_state.commit(project)?;
+        Ok(updated)
+    })
+}
+
+/// Mark a clip of the current project as prepared for the agent at `ready_at`, or not (`None`)
+pub fn set_clip_ready_for_ai(clip_id: &str, ready_at: Option<String>) -> Result<()> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let clip = project.clips_map.get_mut(clip_id)
+            .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        clip.ready_for_ai = ready_at;
+        project_state.commit(project)
+    })
+}
+
+/// Set or clear (`None`) the preview LUT of a clip of the current project
+pub fn set_clip_preview_lut(clip_id: &str, lut: Option<PathBuf>) -> Result<Clip> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let clip = project.clips_map.get_mut(clip_id)
+            .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        clip.preview_lut = lut;
+        let updated = clip.clone();
+        project_state.commit(project)?;
+        Ok(updated)
+    })
+}
+
+/// Set or clear (`None`, going back to the file's default stream) the audio stream a clip of
+/// the current project plays. The stream must exist in the file
+pub fn set_clip_audio_stream(clip_id: &str, audio_stream: Option<usize>) -> Result<Clip> {
+    if let Some(index) = audio_stream {
+        let path = get_clip(clip_id)?.path;
+        let count = ffmpeg::audio_streams(&path.to_string_lossy())?.len();
+        if index >= count {
+            return Err(anyhow!("{:?} has {} audio streams, no stream {}", path, count, index));
+        }
+    }
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let clip = project.clips_map.get_mut(clip_id)
+            .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        clip.audio_stream_index = audio_stream;
+        let updated = clip.clone();
+        project_state.commit(project)?;
+        Ok(updated)
+    })
+}
+
+/// How `replace_clip_source` finds where the old file's times are in the new one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetAdjustment {
+    Auto,        // Cross-correlate the audio of both files, see waveform::envelope_offset
+    Manual(f64), // Seconds added to every time of the old file; negative when its head was trimmed
+}
+
+/// A segment `replace_clip_source` moved, or couldn't
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SegmentShift {
+    pub track_id: String,
+    pub segment_id: String,
+    pub before: (f64, f64), // Start and end in the old file
+    pub after: (f64, f64),  // In the new file; `before` when the segment was left as it was
+    pub reason: Option<String>, // Why the segment was flagged
+}
+
+/// What `replace_clip_source` did
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceReplacement {
+    pub clip: Clip,
+    pub offset: f64,                // Seconds added to the clip's times
+    pub correlation: Option<f64>,   // Of the audio at `offset`, with OffsetAdjustment::Auto
+    pub adjusted: Vec<SegmentShift>, // Shifted and still whole
+    pub flagged: Vec<SegmentShift>,  // Clamped to the new file, or left as they were; check these
+}
+
+/// `start..end` of the old file moved by `offset` into a new file `duration` seconds long (no
+/// limit when None), clamped to it. The reason is set when the range was cut, or left as it was
+/// because none of it is in the new file
+fn shift_range(start: f64, end: f64, offset: f64, duration: Option<f64>) -> (f64, f64, Option<String>) {
+    let (shifted_start, shifted_end) = (start + offset, end + offset);
+    let limit = duration.unwrap_or(f64::INFINITY);
+    if shifted_end <= 0.0 || shifted_start >= limit {
+        return (start, end, Some(format!(
+            "{:.2}s..{:.2}s is outside the new source ({:.2}s long); left as it was",
+            shifted_start, shifted_end, limit
+        )));
+    }
+    let (clamped_start, clamped_end) = (shifted_start.max(0.0), shifted_end.min(limit));
+    let lost = (clamped_start - shifted_start) + (shifted_end - clamped_end);
+    if lost > 1e-6 {
+        return (clamped_start, clamped_end, Some(format!("{:.2}s fell outside the new source and was cut", lost)));
+    }
+    (shifted_start, shifted_end, None)
+}
+
+/// Point clip `clip_id` at `new_path`, a re-edit of its file whose content starts earlier or
+/// later, and move every segment playing it by the offset between the two: given, or found by
+/// matching their audio. Segments cut by the new file's length, or entirely outside it, are
+/// flagged; the transcript moves with the clip. The clip keeps its id, so this is one undo step
+pub fn replace_clip_source(clip_id: &str, new_path: &Path, adjustment: OffsetAdjustment) -> Result<SourceReplacement> {
+    let old = get_clip(clip_id)?;
+    let imported = import_media(new_path)?;
+    if imported.r#type != old.r#type {
+        return Err(anyhow!("can't replace {:?} clip {} with {:?} file {:?}", old.r#type, clip_id, imported.r#type, new_path));
+    }
+    // Keep the user's stream choice when the new file has that stream
+    let audio_stream_index = match old.audio_stream_index {
+        Some(index) if index < ffmpeg::audio_streams(&new_path.to_string_lossy())?.len() => Some(index),
+        _ => None,
+    };
+    let replacement = Clip {
+        id: old.id.clone(),
+        transcript: None,
+        derived_from: old.derived_from.clone(),
+        preview_lut: old.preview_lut.clone(),
+        transcript_language: old.transcript_language.clone(),
+        language_override: old.language_override.clone(),
+        audio_stream_index,
+        ..imported
+    };
+    let (offset, correlation) = match adjustment {
+        OffsetAdjustment::Manual(offset) if offset.is_finite() => (offset, None),
+        OffsetAdjustment::Manual(offset) => return Err(anyhow!("invalid offset {}", offset)),
+        OffsetAdjustment::Auto => {
+            let (from, to) = (old.path.to_string_lossy(), replacement.path.to_string_lossy());
+            let found = waveform::envelope_offset((&from, old.audio_stream()), (&to, replacement.audio_stream()))
+                .context("couldn't match the two files automatically; give the offset instead")?;
+            (found.offset, Some(found.correlation))
+        }
+    };
+    let duration = replacement.latest_probe.as_ref().map(|p| p.duration).filter(|d| *d > 0.0);
+
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let clip = project.clips_map.get_mut(clip_id)
+            .ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+        if clip.path != old.path {
+            return Err(anyhow!("clip {} was relinked while its new source was read; try again", clip_id));
+        }
+        let transcript = clip.transcript.take().map(|segments| {
+            segments.into_iter().filter_map(|mut segment| {
+                let (start, end, reason) = shift_range(segment.start, segment.end, offset, duration);
+                if reason.is_some() {
+                    return None; // Speech the new file doesn't have
+                }
+                (segment.start, segment.end) = (start, end);
+                for word in &mut segment.words {
+                    (word.start, word.end) = (word.start + offset, word.end + offset);
+                }
+                Some(segment)
+            }).collect()
+        });
+        *clip = Clip { transcript, ..replacement.clone() };
+        let updated = clip.clone();
+
+        let (mut adjusted, mut flagged) = (Vec::new(), Vec::new());
+        for track in project.tracks_map.values_mut() {
+            // Baked segments keep the original for unbake; it has to match the new file too
+            for original in track.segments.iter_mut().filter_map(|s| s.baked_from.as_deref_mut()) {
+                if original.clip_id == clip_id {
+                    let (start, end, _) = shift_range(original.start, original.end, offset, duration);
+                    (original.start, original.end) = (start, end);
+                }
+            }
+            for segment in track.segments.iter_mut().filter(|s| s.clip_id == clip_id) {
+                let (start, end, mut reason) = shift_range(segment.start, segment.end, offset, duration);
+                let mut moved = Segment { start, end, ..segment.clone() };
+                if let Some(error) = moved.validation_error() {
+                    reason = Some(format!("{}; left as it was", error));
+                    moved = segment.clone();
+                }
+                let shift = SegmentShift {
+                    track_id: track.id.clone(),
+                    segment_id: segment.id.clone(),
+                    before: (segment.start, segment.end),
+                    after: (moved.start, moved.end),
+                    reason,
+                };
+                *segment = moved;
+                if shift.reason.is_some() { flagged.push(shift) } else { adjusted.push(shift) }
+            }
+        }
+        project_state.commit(project)?;
+        Ok(SourceReplacement { clip: updated, offset, correlation, adjusted, flagged })
+    })
+}
+
+/// Track of the current project that plays clip `clip_id` (the first in track order, when several do)
+pub fn track_playing_clip(clip_id: &str) -> Option<String> {
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    let project = &guard.as_ref()?.project;
+    project.ordered_tracks()
+        .into_iter()
+        .find(|t| t.segments.iter().any(|s| s.clip_id == clip_id))
+        .map(|t| t.id.clone())
+}
+
+/// Preview LUT for the file at `path` in the current project, see ProjectFile::preview_lut_for_path
+pub fn preview_lut_for_path(path: &Path) -> Option<PathBuf> {
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    guard.as_ref()?.project.preview_lut_for_path(path)
+}
+
+/// Whether the current project is audio-only, see ProjectFile::is_audio_only. False without a project
+pub fn is_audio_only() -> bool {
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    guard.as_ref().is_some_and(|s| s.project.is_audio_only())
+}
+
+/// Whether the current project fades its exports in and out, see ProjectSettings::fade_timeline_ends
+pub fn fades_timeline_ends() -> bool {
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    guard.as_ref().is_some_and(|s| s.project.settings.fade_timeline_ends)
+}
+
+/// Track effects for the file at `path` in the current project, see ProjectFile::track_effects_for_path
+pub fn track_effects_for_path(path: &Path) -> Vec<Effect> {
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    guard.as_ref().and_then(|s| s.project.track_effects_for_path(path)).unwrap_or_default()
+}
+
+/// Built-in export presets followed by the current project's custom ones
+pub fn export_presets() -> Vec<ffmpeg::ExportPreset> {
+    let state = get_global_state();
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
+    let mut presets = ffmpeg::builtin_export_presets();
+    if let Some(s) = guard.as_ref() {
+        presets.extend(s.project.settings.export_presets.iter().cloned());
+    }
+    presets
+}
+
+/// Settings of the export preset `id`, built-in or custom
+pub fn find_export_preset(id: &str) -> Result<ExportSettings> {
+    export_presets()
+        .into_iter()
+        .find(|p| p.id == id)
+        .map(|p| p.settings)
+        .ok_or_else(|| anyhow!("export preset not found: {}", id))
+}
+
+/// Add a custom export preset to the current project, or replace the one with its id
+pub fn save_export_preset(mut preset: ffmpeg::ExportPreset) -> Result<ffmpeg::ExportPreset> {
+    if ffmpeg::builtin_export_presets().iter().any(|p| p.id == preset.id) {
+        return Err(anyhow!("'{}' is a built-in preset and can't be changed", preset.id));
+    }
+    preset.settings.validate_format()?;
+    preset.built_in = false;
+
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let presets = &mut project.settings.export_presets;
+        match presets.iter_mut().find(|p| p.id == preset.id) {
+            Some(existing) => *existing = preset.clone(),
+            None => presets.push(preset.clone()),
+        }
+        project_state.commit(project)?;
+        Ok(preset)
+    })
+}
+
+/// Remove a custom export preset from the current project
+pub fn delete_export_preset(id: &str) -> Result<()> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let before = project.settings.export_presets.len();
+        project.settings.export_presets.retain(|p| p.id != id);
+        if project.settings.export_presets.len() == before {
+            return Err(anyhow!("custom export preset not found: {}", id));
+        }
+        project_state.commit(project)
+    })
+}
+
+/// Keep the regions of a retention risk analysis on the current project, replacing the last ones.
+/// They're an analysis result rather than an edit, so this adds no undo step
+pub fn set_retention_risks(regions: Vec<RiskRegion>) -> Result<()> {
+    mutate(|project_state| {
+        project_state.project.retention_risks = regions;
+        if project_state.project.path.is_some() {
+            project_state.save(None)?;
+        }
+        Ok(())
+    })
+}
+
+/// Record verification results (clip id -> check) on the current project's clips
+pub fn set_clip_hash_checks(checks: HashMap<String, HashCheck>) -> Result<()> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        for (clip_id, check) in checks {
+            // Clips removed while verification ran are skipped
+            if let Some(hashes) = project.clips_map.get_mut(&clip_id).and_then(|c| c.hashes.as_mut()) {
+                hashes.last_check = Some(check);
+            }
+        }
+        project_state.commit(project)
+    })
+}
+
 /// Save the current project to disk, optionally updating its path
 pub fn save_project(new_path: Option<String>) -> Result<()> {
-    let state = get_global_state();
-    let mut guard = state.lock().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
-    
-    if let Some(project_state) = guard.as_mut() {
-        project_state.save(new_path)
-    } else {
-        Err(anyhow!("no project is currently loaded"))
-    }
+    // Saving under a new name moves the lock to the new file
+    let Some(new_path) = new_path else {
+        return mutate(|project_state| project_state.save(None));
+    };
+    let new_path_buf = PathBuf::from(&new_path);
+    project_lock::acquire(&new_path_buf)?;
+    mutate(|project_state| {
+        let old_path = project_state.project.path.clone();
+        project_state.save(Some(new_path))?;
+        editor_windows::project_moved(old_path.as_deref(), &new_path_buf);
+        if let Some(old_path) = old_path.filter(|p| *p != new_path_buf) {
+            project_lock::release(&old_path);
+        }
+        Ok(())
+    })
+}
+
+/// Replace the current project with `updated` as a single undo step, saved once. The view
+/// state stays as it was
+pub fn commit_project(mut updated: ProjectFile) -> Result<()> {
+    mutate(|project_state| {
+        updated.view_state = project_state.project.view_state.clone();
+        project_state.commit(updated)
+    })
 }
 
-/// Update the current project with new data
+/// Update the current project with new data. Rejected if it duplicates ids the current project
+/// doesn't, or any ids when no project is loaded
 pub fn update_project(updated_project: ProjectFile) -> Result<()> {
-    let state = get_global_state();
-    let mut guard = state.lock().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
-    
-    if let Some(project_state) = guard.as_mut() {
-        project_state.update(updated_project)
-    } else {
-        // If no project exists, create new one
-        let project_state = ProjectState::new(updated_project)?;
-        *guard = Some(project_state);
+    update_state(get_global_state(), updated_project)
+}
+
+/// `update_project` on `state`
+fn update_state(state: &RwLock<Option<ProjectState>>, updated_project: ProjectFile) -> Result<()> {
+    let pending = {
+        let mut guard = state.write().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+
+        if let Some(project_state) = guard.as_mut() {
+            project_state.update(updated_project)?;
+            project_state.pending_save.take()
+        } else {
+            // If no project exists, create new one
+            updated_project.check_unique_ids()?;
+            let project_state = ProjectState::new(updated_project)?;
+            *guard = Some(project_state);
+            None
+        }
+    };
+    if let Some(pending) = pending {
+        pending.write()?;
+    }
+    Ok(())
+}
+
+/// Undo the last mutation made through the backend. Returns the restored project, or None if nothing to undo
+pub fn undo_project() -> Result<Option<ProjectFile>> {
+    mutate(|project_state| {
+        if project_state.undo()? {
+            Ok(Some(project_state.get_project()))
+        } else {
+            Ok(None)
+        }
+    })
+}
+
+/// Selects which segments of a track a batch operation applies to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SegmentFilter {
+    All,
+    Label(String),
+    ClipId(String),
+    DurationRange { min: Option<f64>, max: Option<f64> }, // Inclusive, in clip seconds
+}
+
+impl SegmentFilter {
+    pub fn matches(&self, segment: &Segment) -> bool {
+        match self {
+            SegmentFilter::All => true,
+            SegmentFilter::Label(label) => segment.label.as_deref() == Some(label.as_str()),
+            SegmentFilter::ClipId(clip_id) => &segment.clip_id == clip_id,
+            SegmentFilter::DurationRange { min, max } => {
+                let duration = segment.duration();
+                min.map_or(true, |min| duration >= min) && max.map_or(true, |max| duration <= max)
+            }
+        }
+    }
+}
+
+/// Fields to overwrite on every matched segment. `None` leaves the field unchanged
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SegmentPatch {
+    pub gain: Option<f64>,
+    pub fade_in: Option<f64>,
+    pub fade_out: Option<f64>,
+    pub fade_curve: Option<FadeCurve>,
+    pub video_fade_in: Option<f64>,
+    pub video_fade_out: Option<f64>,
+    pub speed: Option<f64>,
+    pub effects: Option<Vec<Effect>>,
+}
+
+impl SegmentPatch {
+    pub fn apply(&self, segment: &mut Segment) {
+        if let Some(gain) = self.gain {
+            segment.gain = gain;
+        }
+        if let Some(fade_in) = self.fade_in {
+            segment.fade_in = fade_in;
+        }
+        if let Some(fade_out) = self.fade_out {
+            segment.fade_out = fade_out;
+        }
+        if let Some(fade_curve) = self.fade_curve {
+            segment.fade_curve = fade_curve;
+        }
+        if let Some(video_fade_in) = self.video_fade_in {
+            segment.video_fade_in = video_fade_in;
+        }
+        if let Some(video_fade_out) = self.video_fade_out {
+            segment.video_fade_out = video_fade_out;
+        }
+        if let Some(speed) = self.speed {
+            segment.speed = speed;
+        }
+        if let Some(effects) = &self.effects {
+            segment.effects = effects.clone();
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SegmentFailure {
+    pub segment_id: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchUpdateResult {
+    pub applied: bool, // False when any segment failed validation; nothing was changed
+    pub affected_segment_ids: Vec<String>,
+    pub failures: Vec<SegmentFailure>,
+}
+
+/// Apply `patch` to every segment on `track_id` matched by `filter`.
+///
+/// All-or-nothing: the patch is applied to a copy of the project and only committed
+/// (as a single undo step) if every patched segment still validates.
+pub fn batch_update_segments(track_id: &str, filter: &SegmentFilter, patch: &SegmentPatch) -> Result<BatchUpdateResult> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let track = project.tracks_map.get_mut(track_id)
+            .ok_or_else(|| anyhow!("track not found: {}", track_id))?;
+
+        let mut affected_segment_ids = Vec::new();
+        let mut failures = Vec::new();
+        for segment in track.segments.iter_mut().filter(|seg| filter.matches(seg)) {
+            patch.apply(segment);
+            match segment.validation_error() {
+                Some(reason) => failures.push(SegmentFailure { segment_id: segment.id.clone(), reason }),
+                None => affected_segment_ids.push(segment.id.clone()),
+            }
+        }
+
+        if !failures.is_empty() {
+            return Ok(BatchUpdateResult { applied: false, affected_segment_ids: Vec::new(), failures });
+        }
+        if !affected_segment_ids.is_empty() {
+            project_state.commit(project)?;
+        }
+
+        Ok(BatchUpdateResult { applied: true, affected_segment_ids, failures })
+    })
+}
+
+/// Track fields to overwrite. `None` leaves the field unchanged
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TrackPatch {
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub muted: Option<bool>,
+    pub volume: Option<u8>,
+    pub pan: Option<i8>,
+    pub track_effects: Option<Vec<Effect>>,
+    pub preset: Option<String>, // Name of a built-in effects preset; replaces track_effects
+}
+
+impl TrackPatch {
+    /// This patch with `preset` replaced by its effects, after checking every effect. `track_id`
+    /// is for the error messages
+    fn resolved(&self, track_id: &str) -> Result<TrackPatch> {
+        let mut patch = self.clone();
+        if let Some(name) = patch.preset.take() {
+            let effects = audio_effects::preset(&name).ok_or_else(|| {
+                anyhow!("unknown effects preset '{}' (expected one of {})", name, audio_effects::PRESET_NAMES.join(", "))
+            })?;
+            patch.track_effects = Some(effects);
+        }
+        if let Some(effects) = &patch.track_effects {
+            for effect in effects {
+                audio_effects::validate(effect).map_err(|e| anyhow!("track {}: {}", track_id, e))?;
+            }
+        }
+        Ok(patch)
+    }
+
+    pub fn apply(&self, track: &mut Track) {
+        if let Some(name) = &self.name {
+            track.name = name.clone();
+        }
+        if let Some(enabled) = self.enabled {
+            track.enabled = enabled;
+        }
+        if let Some(muted) = self.muted {
+            track.muted = muted;
+        }
+        if let Some(volume) = self.volume {
+            track.volume = volume;
+        }
+        if let Some(pan) = self.pan {
+            track.pan = pan;
+        }
+        if let Some(track_effects) = &self.track_effects {
+            track.track_effects = track_effects.clone();
+        }
+    }
+}
+
+/// Apply `patch` to a track of the current project as one undo step. Nothing changes if the
+/// patched track fails validation
+pub fn update_track(track_id: &str, patch: &TrackPatch) -> Result<Track> {
+    let patch = patch.resolved(track_id)?;
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let track = project.tracks_map.get_mut(track_id)
+            .ok_or_else(|| anyhow!("track not found: {}", track_id))?;
+        patch.apply(track);
+        if !track.verify() {
+            return Err(anyhow!("track {} is invalid after the update (volume 0..100, pan -100..100)", track_id));
+        }
+        let track = track.clone();
+        project_state.commit(project)?;
+        Ok(track)
+    })
+}
+
+/// One granular edit in a `transact` call. Ids left empty on added segments, tracks and
+/// markers are filled in
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ProjectOp {
+    AddSegment { track_id: String, segment: Segment, index: Option<usize> }, // Appended without an index
+    RemoveSegment { track_id: String, segment_id: String },
+    MoveSegment { segment_id: String, to_track_id: String, index: Option<usize>, timeline_start: Option<f64> }, // Keeps its timeline_start unless one is given
+    UpdateSegment { track_id: String, segment_id: String, patch: SegmentPatch },
+    AddTrack { track: Track }, // Placed above the existing tracks
+    RemoveTrack { track_id: String },
+    UpdateTrack { track_id: String, patch: TrackPatch },
+    AddMarker { marker: Marker },
+    RemoveMarker { marker_id: String },
+}
+
+/// Why `transact` changed nothing
+#[derive(Serialize, Debug)]
+pub struct TransactionError {
+    pub op_index: Option<usize>, // The op that failed; None when the result as a whole was rejected (e.g. duplicate ids) or couldn't be saved
+    pub message: String,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.op_index {
+            Some(index) => write!(f, "op {}: {}", index, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// Ids removed so far in a transaction, with the op that removed each, so a later op that
+/// refers to one fails as a conflict rather than as a plain unknown id
+type Removed = HashMap<String, usize>;
+
+fn not_found(kind: &str, id: &str, removed: &Removed) -> anyhow::Error {
+    match removed.get(id) {
+        Some(index) => anyhow!("{} {} was removed by op {} of this transaction", kind, id, index),
+        None => anyhow!("{} not found: {}", kind, id),
+    }
+}
+
+fn op_track<'a>(project: &'a mut ProjectFile, track_id: &str, removed: &Removed) -> Result<&'a mut Track> {
+    project.tracks_map.get_mut(track_id).ok_or_else(|| not_found("track", track_id, removed))
+}
+
+/// Position of `segment_id` on `track`
+fn op_segment(track: &Track, segment_id: &str, removed: &Removed) -> Result<usize> {
+    track.segments.iter().position(|s| s.id == segment_id).ok_or_else(|| match removed.contains_key(segment_id) {
+        true => not_found("segment", segment_id, removed),
+        false => anyhow!("segment {} is not on track {}", segment_id, track.id),
+    })
+}
+
+/// Insert `segment` into `track` at `index` (clamped), or at the end
+fn insert_segment(project: &ProjectFile, track: &mut Track, segment: Segment, index: Option<usize>) -> Result<()> {
+    if !project.clips_map.contains_key(&segment.clip_id) {
+        return Err(anyhow!("clip not found: {}", segment.clip_id));
+    }
+    if let Some(reason) = segment.validation_error() {
+        return Err(anyhow!("segment {}: {}", segment.id, reason));
+    }
+    let index = index.unwrap_or(track.segments.len()).min(track.segments.len());
+    track.segments.insert(index, segment);
+    Ok(())
+}
+
+impl ProjectOp {
+    /// Apply the op to `project`, recording what it removes. `index` is the op's position
+    fn apply(&self, project: &mut ProjectFile, removed: &mut Removed, index: usize) -> Result<()> {
+        match self {
+            ProjectOp::AddSegment { track_id, segment, index: at } => {
+                let mut segment = segment.clone();
+                if segment.id.is_empty() {
+                    segment.id = ids::new_id(ids::SEGMENT);
+                }
+                let mut track = op_track(project, track_id, removed)?.clone();
+                insert_segment(project, &mut track, segment, *at)?;
+                project.tracks_map.insert(track.id.clone(), track);
+            }
+            ProjectOp::RemoveSegment { track_id, segment_id } => {
+                let track = op_track(project, track_id, removed)?;
+                let position = op_segment(track, segment_id, removed)?;
+                track.segments.remove(position);
+                removed.insert(segment_id.clone(), index);
+            }
+            ProjectOp::MoveSegment { segment_id, to_track_id, index: at, timeline_start } => {
+                let from = project.tracks_map.values()
+                    .find(|t| t.segments.iter().any(|s| &s.id == segment_id))
+                    .map(|t| t.id.clone())
+                    .ok_or_else(|| not_found("segment", segment_id, removed))?;
+                let source = op_track(project, &from, removed)?;
+                let position = op_segment(source, segment_id, removed)?;
+                let mut segment = source.segments.remove(position);
+                if timeline_start.is_some() {
+                    segment.timeline_start = *timeline_start;
+                }
+                let mut target = op_track(project, to_track_id, removed)?.clone();
+                insert_segment(project, &mut target, segment, *at)?;
+                project.tracks_map.insert(target.id.clone(), target);
+            }
+            ProjectOp::UpdateSegment { track_id, segment_id, patch } => {
+                let track = op_track(project, track_id, removed)?;
+                let position = op_segment(track, segment_id, removed)?;
+                let segment = &mut track.segments[position];
+                patch.apply(segment);
+                if let Some(reason) = segment.validation_error() {
+                    return Err(anyhow!("segment {}: {}", segment_id, reason));
+                }
+            }
+            ProjectOp::AddTrack { track } => {
+                let mut track = track.clone();
+                if track.id.is_empty() {
+                    track.id = ids::new_id(ids::TRACK);
+                }
+                if project.tracks_map.contains_key(&track.id) {
+                    return Err(anyhow!("track {} already exists", track.id));
+                }
+                for segment in &track.segments {
+                    if !project.clips_map.contains_key(&segment.clip_id) {
+                        return Err(anyhow!("clip not found: {}", segment.clip_id));
+                    }
+                }
+                if !track.verify() {
+                    return Err(anyhow!("track {} is invalid (segments, volume 0..100, pan -100..100, effects)", track.id));
+                }
+                track.order = project.tracks_map.values().map(|t| t.order + 1).max().unwrap_or(0);
+                project.tracks_map.insert(track.id.clone(), track);
+            }
+            ProjectOp::RemoveTrack { track_id } => {
+                let track = project.tracks_map.remove(track_id).ok_or_else(|| not_found("track", track_id, removed))?;
+                for segment in &track.segments {
+                    removed.insert(segment.id.clone(), index);
+                }
+                removed.insert(track.id, index);
+            }
+            ProjectOp::UpdateTrack { track_id, patch } => {
+                let patch = patch.resolved(track_id)?;
+                let track = op_track(project, track_id, removed)?;
+                patch.apply(track);
+                if !track.verify() {
+                    return Err(anyhow!("track {} is invalid after the update (volume 0..100, pan -100..100)", track_id));
+                }
+            }
+            ProjectOp::AddMarker { marker } => {
+                let mut marker = marker.clone();
+                if marker.id.is_empty() {
+                    marker.id = ids::new_id(ids::MARKER);
+                }
+                if project.markers.iter().any(|m| m.id == marker.id) {
+                    return Err(anyhow!("marker {} already exists", marker.id));
+                }
+                project.markers.push(marker);
+                project.markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+            }
+            ProjectOp::RemoveMarker { marker_id } => {
+                let position = project.markers.iter()
+                    .position(|m| &m.id == marker_id)
+                    .ok_or_else(|| not_found("marker", marker_id, removed))?;
+                project.markers.remove(position);
+                removed.insert(marker_id.clone(), index);
+            }
+        }
         Ok(())
     }
 }
 
+/// Apply `ops` in order to a copy of the current project and swap it in only if every op
+/// succeeds: one undo step and one save, e.g. moving a segment to another track as a remove
+/// and an add. The first failing op, including one that refers to something an earlier op
+/// removed, fails the whole transaction. Returns the updated project
+pub fn transact(ops: &[ProjectOp]) -> Result<ProjectFile, TransactionError> {
+    let whole = |e: anyhow::Error| TransactionError { op_index: None, message: format!("{:#}", e) };
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let mut removed = Removed::new();
+        for (index, op) in ops.iter().enumerate() {
+            if let Err(e) = op.apply(&mut project, &mut removed, index) {
+                return Ok(Err(TransactionError { op_index: Some(index), message: format!("{:#}", e) }));
+            }
+        }
+        if ops.is_empty() {
+            return Ok(Ok(project));
+        }
+        project_state.commit(project.clone())?;
+        Ok(Ok(project))
+    })
+    .map_err(whole)?
+}
+
+/// Timeline view state of the current project, without entries for deleted tracks
+pub fn get_view_state() -> Result<TimelineViewState> {
+    let state = get_global_state();
+    let guard = state.read().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+    let project_state = guard.as_ref().ok_or_else(|| anyhow!("no project is currently loaded"))?;
+    let mut view_state = project_state.project.view_state.clone();
+    view_state.prune(&project_state.project.tracks_map);
+    Ok(view_state)
+}
+
+/// Apply `patch` to the timeline view state of the current project. Not an undo step; see
+/// AppSettings::save_view_changes for when it's written
+pub fn set_view_state(patch: &ViewStatePatch) -> Result<TimelineViewState> {
+    mutate(|project_state| {
+        let mut view_state = project_state.project.view_state.clone();
+        patch.apply(&mut view_state);
+        view_state.prune(&project_state.project.tracks_map);
+        if !view_state.verify() {
+            return Err(anyhow!("invalid view state (track heights and zoom must be positive, scroll not negative)"));
+        }
+        project_state.set_view_state(view_state.clone())?;
+        Ok(view_state)
+    })
+}
+
+/// Point `segment_id` at a freshly baked clip, keeping the original segment for unbake.
+/// Fails if the segment changed since `original` was read, so a bake started on stale state
+/// never overwrites newer edits
+pub fn swap_in_baked_segment(track_id: &str, segment_id: &str, original: &Segment, baked_clip: Clip, duration: f64) -> Result<Segment> {
+    let baked = Segment {
+        id: original.id.clone(),
+        clip_id: baked_clip.id.clone(),
+        start: 0.0,
+        end: duration,
+        label: original.label.clone(),
+        gain: 0.0,
+        fade_in: 0.0,
+        fade_out: 0.0,
+        fade_curve: FadeCurve::Linear,
+        video_fade_in: 0.0,
+        video_fade_out: 0.0,
+        speed: 1.0,
+        effects: Vec::new(),
+        baked_from: Some(Box::new(original.clone())),
+        timeline_start: original.timeline_start,
+    };
+    swap_in_segment(track_id, segment_id, original, baked_clip, baked, "baking")
+}
+
+/// Point `segment_id` at a time-stretched render of its audio. Gain, fades and effects still
+/// apply live; only the clip, range and speed change. `unbake_segment` restores the original
+pub fn swap_in_stretched_segment(track_id: &str, segment_id: &str, original: &Segment, stretched_clip: Clip, duration: f64) -> Result<Segment> {
+    let stretched = Segment {
+        clip_id: stretched_clip.id.clone(),
+        start: 0.0,
+        end: duration,
+        speed: 1.0,
+        baked_from: Some(Box::new(original.clone())),
+        ..original.clone()
+    };
+    swap_in_segment(track_id, segment_id, original, stretched_clip, stretched, "stretching")
+}
+
+/// Add `clip` and replace `segment_id` with `replacement` as one undo step, unless the segment
+/// no longer matches `original`. `activity` ("baking") names the render in that error
+fn swap_in_segment(track_id: &str, segment_id: &str, original: &Segment, clip: Clip, replacement: Segment, activity: &str) -> Result<Segment> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        if project.segment(track_id, segment_id)? != original {
+            return Err(anyhow!("segment {} was edited while {}; try again", segment_id, activity));
+        }
+
+        project.clips_map.insert(clip.id.clone(), clip);
+        let track = project.tracks_map.get_mut(track_id)
+            .ok_or_else(|| anyhow!("track not found: {}", track_id))?;
+        if let Some(segment) = track.segments.iter_mut().find(|s| s.id == segment_id) {
+            *segment = replacement.clone();
+        }
+        project_state.commit(project)?;
+        Ok(replacement)
+    })
+}
+
+/// Restore a baked segment to its original clip, effects and speed.
+/// The baked clip is dropped from the project once nothing references it; its file is left on disk
+pub fn unbake_segment(track_id: &str, segment_id: &str) -> Result<Segment> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let track = project.tracks_map.get_mut(track_id)
+            .ok_or_else(|| anyhow!("track not found: {}", track_id))?;
+        let segment = track.segments.iter_mut()
+            .find(|s| s.id == segment_id)
+            .ok_or_else(|| anyhow!("segment {} not found on track {}", segment_id, track_id))?;
+        let original = segment.baked_from.take()
+            .ok_or_else(|| anyhow!("segment {} is not baked", segment_id))?;
+        let baked_clip_id = std::mem::replace(segment, *original).clip_id;
+        let restored = segment.clone();
+
+        let still_used = project.tracks_map.values()
+            .flat_map(|t| t.segments.iter())
+            .any(|s| s.clip_id == baked_clip_id);
+        if !still_used {
+            project.clips_map.remove(&baked_clip_id);
+        }
+        project_state.commit(project)?;
+        Ok(restored)
+    })
+}
+
+/// Point every segment that plays `from_clip_id` at `to_clip_id` as one undo step, e.g. after
+/// conforming a clip. Segment times are kept, so the clips must share a timebase. Returns the
+/// number of segments changed
+pub fn retarget_clip(from_clip_id: &str, to_clip_id: &str) -> Result<usize> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        if !project.clips_map.contains_key(to_clip_id) {
+            return Err(anyhow!("clip not found: {}", to_clip_id));
+        }
+        let mut changed = 0;
+        for segment in project.tracks_map.values_mut().flat_map(|t| t.segments.iter_mut()) {
+            if segment.clip_id == from_clip_id {
+                segment.clip_id = to_clip_id.to_string();
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            project_state.commit(project)?;
+        }
+        Ok(changed)
+    })
+}
+
+/// An id rewritten by `repair_duplicate_ids`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RenamedId {
+    pub kind: String, // "clip", "track", "segment" or "marker"
+    pub old_id: String,
+    pub new_id: String,
+}
+
+/// Give a fresh id to every duplicate in `items`, keeping the first occurrence
+fn rename_duplicates<'a>(kind: &str, items: impl Iterator<Item = &'a mut String>, renamed: &mut Vec<RenamedId>) {
+    let mut seen = std::collections::HashSet::new();
+    for id in items {
+        if !seen.insert(id.clone()) {
+            let new_id = ids::new_id(kind);
+            renamed.push(RenamedId { kind: kind.to_string(), old_id: std::mem::replace(id, new_id.clone()), new_id });
+        }
+    }
+}
+
+/// Give a fresh id to every clip or track that shares its id with another, re-keying it in
+/// `map`. The entry stored under the shared id (else the first key in name order) keeps it.
+/// Returns (old key, new id) for each moved entry
+fn rekey_duplicates<T>(kind: &str, map: &mut HashMap<String, T>, id_of: fn(&mut T) -> &mut String, renamed: &mut Vec<RenamedId>) -> Vec<(String, String)> {
+    let mut entries: Vec<(String, String)> = map.iter_mut().map(|(key, v)| (key.clone(), id_of(v).clone())).collect();
+    entries.sort();
+    let mut moved = Vec::new();
+    for (key, id) in &entries {
+        let group: Vec<&String> = entries.iter().filter(|(_, other)| other == id).map(|(k, _)| k).collect();
+        let keeper = group.iter().find(|k| **k == id).unwrap_or(&group[0]);
+        if group.len() < 2 || *keeper == key {
+            continue;
+        }
+        let Some(mut value) = map.remove(key) else { continue };
+        let new_id = ids::new_id(kind);
+        *id_of(&mut value) = new_id.clone();
+        renamed.push(RenamedId { kind: kind.to_string(), old_id: id.clone(), new_id: new_id.clone() });
+        moved.push((key.clone(), new_id.clone()));
+        map.insert(new_id, value);
+    }
+    moved
+}
+
+/// Rewrite duplicated clip, track, segment and marker ids in the current project as one undo step.
+/// The first occurrence keeps its id; segments that pointed at a renamed clip follow it
+pub fn repair_duplicate_ids() -> Result<Vec<RenamedId>> {
+    mutate(|project_state| {
+        let mut project = project_state.get_project();
+        let mut renamed = Vec::new();
+
+        let moved_clips = rekey_duplicates(ids::CLIP, &mut project.clips_map, |c| &mut c.id, &mut renamed);
+        rekey_duplicates(ids::TRACK, &mut project.tracks_map, |t| &mut t.id, &mut renamed);
+        let mut tracks: Vec<&mut Track> = project.tracks_map.values_mut().collect();
+        tracks.sort_by(|a, b| (a.order, &a.id).cmp(&(b.order, &b.id)));
+        for segment in tracks.iter_mut().flat_map(|t| t.segments.iter_mut()) {
+            if let Some((_, new_id)) = moved_clips.iter().find(|(old_key, _)| *old_key == segment.clip_id) {
+                segment.clip_id = new_id.clone();
+            }
+        }
+        rename_duplicates(ids::SEGMENT, tracks.into_iter().flat_map(|t| t.segments.iter_mut()).map(|s| &mut s.id), &mut renamed);
+        rename_duplicates(ids::MARKER, project.markers.iter_mut().map(|m| &mut m.id), &mut renamed);
+
+        if !renamed.is_empty() {
+            project_state.commit(project)?;
+        }
+        Ok(renamed)
+    })
+}
+
 /// Close the current project
 pub fn close_project() -> Result<()> {
     let state = get_global_state();
-    let mut guard = state.lock().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
+    let mut guard = state.write().map_err(|e| anyhow!("failed to lock project state: {}", e))?;
     
+    release_replaced(&guard, None);
     *guard = None;  // Drops project state
     Ok(())
 }
@@ -339,11 +2648,29 @@ pub fn close_project() -> Result<()> {
 /// Check if a project is currently loaded
 pub fn has_project() -> bool {
     let state = get_global_state();
-    let guard = state.lock().unwrap_or_else(|e| e.into_inner());
+    let guard = state.read().unwrap_or_else(|e| e.into_inner());
     guard.is_some()
 }
 
 
+/// Backup `which` of the project saved at `path`, or the newest one that reads when None. Only
+/// returned; the project file and the loaded project stay as they are
+pub fn restore_project_backup(path: String, which: Option<usize>) -> Result<ProjectFile> {
+    let path_buf = PathBuf::from(&path);
+    if let Some(which) = which {
+        return ProjectFile::from_backup(&path_buf, which);
+    }
+    let mut last_error = anyhow!("{:?} has no backups", path_buf);
+    for which in 1..=MAX_BACKUPS {
+        match ProjectFile::from_backup(&path_buf, which) {
+            Ok(project) => return Ok(project),
+            Err(e) if backup_path(&path_buf, which).exists() => last_error = e.context(format!("backup {} is unusable", which)),
+            Err(_) => {}
+        }
+    }
+    Err(last_error)
+}
+
 /// Single read of a project file without affecting global state
 pub fn single_read_project(path: String) -> Result<ProjectFile> {
     let path_buf = PathBuf::from(&path);
@@ -356,3 +2683,395 @@ pub fn single_read_project(path: String) -> Result<ProjectFile> {
 // ProjectState contains all functionality directly without unnecessary wrapper classes
 // Use new_project() for creating unsaved projects, load_project() for loading from disk
 // File operations are handled directly without exclusive locking to avoid timing issues
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty project saved at `path`
+    fn project(path: &str) -> ProjectFile {
+        serde_json::from_value(serde_json::json!({
+            "title": "Test",
+            "clips_map": {},
+            "tracks_map": {},
+            "path": path,
+        }))
+        .unwrap()
+    }
+
+    /// Fresh directory under the system temp dir
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn saved_title(path: &Path) -> String {
+        serde_json::from_str::<ProjectFile>(&fs::read_to_string(path).unwrap()).unwrap().title
+    }
+
+    /// One video track "main" playing `segments` (clip start, end, speed) of clip "clip_a" back to back
+    fn timeline(segments: &[(f64, f64, f64)], cuts: &[(f64, f64)]) -> ProjectFile {
+        let segments: Vec<serde_json::Value> = segments.iter().enumerate().map(|(i, (start, end, speed))| serde_json::json!({
+            "id": format!("segment_{}", i),
+            "clip_id": "clip_a",
+            "start": start,
+            "end": end,
+            "speed": speed,
+            "fade_in": 0.5,
+            "fade_out": 0.5,
+        })).collect();
+        let cuts: Vec<serde_json::Value> = cuts.iter().map(|(start, end)| serde_json::json!({ "start": start, "end": end })).collect();
+        serde_json::from_value(serde_json::json!({
+            "title": "Test",
+            "clips_map": {},
+            "tracks_map": { "main": {
+                "id": "main", "name": "Main", "type": "Video", "enabled": true, "muted": false,
+                "volume": 100, "order": 0, "segments": segments,
+            } },
+            "accepted_cuts": cuts,
+        }))
+        .unwrap()
+    }
+
+    fn pieces(project: &ProjectFile) -> Vec<(f64, f64, f64)> {
+        let track = &project.tracks_map["main"];
+        track.segments.iter().zip(track.timeline_spans()).map(|(s, (start, _))| (s.start, s.end, start)).collect()
+    }
+
+    #[test]
+    fn cuts_split_and_trim_segments() {
+        // Segments play 0-4 and 4-10; the cuts take out 1-2 and 3-5
+        let project = timeline(&[(10.0, 14.0, 1.0), (20.0, 26.0, 1.0)], &[(1.0, 2.0), (3.0, 5.0)]).with_cuts_applied();
+        assert_eq!(pieces(&project), vec![(10.0, 11.0, 0.0), (12.0, 13.0, 1.0), (21.0, 26.0, 2.0)]);
+        assert!(project.accepted_cuts.is_empty());
+        assert_eq!(project.timeline_duration(), 7.0);
+
+        let segments = &project.tracks_map["main"].segments;
+        assert_eq!(segments[0].id, "segment_0");
+        assert_ne!(segments[1].id, "segment_0");
+        assert_eq!((segments[0].fade_in, segments[0].fade_out), (0.5, 0.0));
+        assert_eq!((segments[1].fade_in, segments[1].fade_out), (0.0, 0.0));
+        assert_eq!((segments[2].fade_in, segments[2].fade_out), (0.0, 0.5));
+    }
+
+    #[test]
+    fn cuts_follow_segment_speed() {
+        // Twice as fast: 8 s of clip play in 4 s of timeline, so cutting timeline 1-2 removes clip 2-4
+        let project = timeline(&[(0.0, 8.0, 2.0)], &[(1.0, 2.0)]).with_cuts_applied();
+        assert_eq!(pieces(&project), vec![(0.0, 2.0, 0.0), (4.0, 8.0, 1.0)]);
+    }
+
+    #[test]
+    fn segments_inside_a_cut_are_dropped() {
+        let project = timeline(&[(0.0, 2.0, 1.0), (0.0, 3.0, 1.0)], &[(1.5, 5.5)]).with_cuts_applied();
+        assert_eq!(pieces(&project), vec![(0.0, 1.5, 0.0)]);
+        let uncut = timeline(&[(0.0, 2.0, 1.0)], &[]).with_cuts_applied();
+        assert_eq!(pieces(&uncut), vec![(0.0, 2.0, 0.0)]);
+    }
+
+    fn clip(path: &str, r#type: &str, extra: serde_json::Value) -> Clip {
+        let mut clip = serde_json::json!({ "id": "clip_a", "path": path, "latest_probe": null, "type": r#type });
+        clip.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(clip).unwrap()
+    }
+
+    #[test]
+    fn clips_from_older_versions_need_a_backfill() {
+        // Written before clips recorded cover art; the old cache path is ignored
+        let legacy = clip("/media/song.mp3", "Audio", serde_json::json!({ "album_art": "/cache/ab12.png" }));
+        assert_eq!(legacy.has_album_art, None);
+        assert!(legacy.needs_backfill());
+
+        let probe = serde_json::json!({
+            "duration": 1.0, "width": 0, "height": 0, "fps": 0.0, "audio_rate": 48000, "audio_channels": 2,
+            "v_codec": "", "a_codec": "mp3", "container": "mp3",
+        });
+        let checked = clip("/media/song.mp3", "Audio", serde_json::json!({ "latest_probe": probe, "has_album_art": false }));
+        assert!(!checked.needs_backfill());
+        assert!(!clip("/media/still.png", "Image", serde_json::json!({})).needs_backfill());
+        assert!(clip("/media/take.mp4", "Video", serde_json::json!({})).needs_backfill());
+    }
+
+    #[test]
+    fn missing_files_are_not_backfilled() {
+        let mut project = project("/tmp/backfill.gebo");
+        let missing = clip("/nonexistent/gebo-test/song.mp3", "Audio", serde_json::json!({}));
+        project.clips_map.insert(missing.id.clone(), missing);
+        assert!(!backfill_clips(&mut project));
+        assert_eq!(project.clips_map["clip_a"].has_album_art, None);
+    }
+
+    #[test]
+    fn update_keeps_the_view_state() {
+        let mut loaded = project("/tmp/view_state.gebo");
+        loaded.view_state.zoom = Some(80.0);
+        loaded.view_state.collapsed_tracks = vec!["track_a".to_string()];
+        let mut state = ProjectState::new(loaded.clone()).unwrap();
+
+        let mut updated = project("/tmp/view_state.gebo");
+        updated.title = "Renamed".to_string();
+        state.update(updated).unwrap();
+
+        assert_eq!(state.project.title, "Renamed");
+        assert_eq!(state.project.view_state, loaded.view_state);
+        assert_eq!(state.pending_save.map(|pending| pending.project.view_state), Some(loaded.view_state));
+    }
+
+    #[test]
+    fn stale_snapshot_is_not_written() {
+        let dir = temp_dir();
+        let path = dir.join("stale.gebo");
+        let mut older = project(&path.to_string_lossy());
+        older.title = "older".to_string();
+        let older = PendingSave::new(&older);
+        let mut newer = project(&path.to_string_lossy());
+        newer.title = "newer".to_string();
+        let newer = PendingSave::new(&newer);
+
+        newer.write().unwrap();
+        older.write().unwrap();
+        assert_eq!(saved_title(&path), "newer");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_of_other_projects_still_write() {
+        let dir = temp_dir();
+        let a = dir.join("a.gebo");
+        let b = dir.join("b.gebo");
+        // b's snapshot is older than a's, but nothing newer was written to b
+        let b_save = PendingSave::new(&project(&b.to_string_lossy()));
+        let a_save = PendingSave::new(&project(&a.to_string_lossy()));
+
+        a_save.write().unwrap();
+        b_save.write().unwrap();
+        assert!(a.exists());
+        assert!(b.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn readers_never_see_a_partial_save() {
+        let dir = temp_dir();
+        let path = dir.join("stress.gebo");
+        PendingSave::new(&project(&path.to_string_lossy())).write().unwrap();
+
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                let done = done.clone();
+                std::thread::spawn(move || loop {
+                    let content = fs::read_to_string(&path).unwrap();
+                    serde_json::from_str::<ProjectFile>(&content).unwrap();
+                    if done.load(Ordering::SeqCst) {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        // Snapshots taken in order but written from several threads in any order
+        let snapshots: Vec<PendingSave> = (0..200)
+            .map(|i| {
+                let mut snapshot = project(&path.to_string_lossy());
+                snapshot.title = format!("save {}", i);
+                PendingSave::new(&snapshot)
+            })
+            .collect();
+        let mut writers: Vec<Vec<PendingSave>> = (0..4).map(|_| Vec::new()).collect();
+        for (i, snapshot) in snapshots.into_iter().enumerate() {
+            writers[i % 4].push(snapshot);
+        }
+        let writers: Vec<_> = writers
+            .into_iter()
+            .map(|saves| std::thread::spawn(move || saves.into_iter().rev().try_for_each(PendingSave::write)))
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(saved_title(&path), "save 199");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_go_ahead_while_a_save_is_being_written() {
+        let dir = temp_dir();
+        let path = dir.join("blocked.gebo");
+        // A state of its own, so the loaded project of the app is left alone
+        let state: &'static RwLock<Option<ProjectState>> = Box::leak(Box::new(RwLock::new(None)));
+        let titled = |title: &str| {
+            let mut project = project(&path.to_string_lossy());
+            project.title = title.to_string();
+            project
+        };
+        // No project is loaded yet, so this installs it without saving
+        update_state(state, titled("before")).unwrap();
+
+        // Every save waits on SAVE_LOCK, so the update below is stuck writing its save
+        let saves = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let updater = {
+            let updated = titled("after");
+            std::thread::spawn(move || update_state(state, updated))
+        };
+        let (sender, reads) = std::sync::mpsc::channel();
+        std::thread::spawn(move || loop {
+            let title = read_project(state).unwrap().unwrap().title;
+            if title == "after" {
+                let _ = sender.send(title);
+                break;
+            }
+        });
+        // Only fails if reads wait for the save, which can't finish while SAVE_LOCK is held
+        let read = reads.recv_timeout(Duration::from_secs(30));
+        assert!(!path.exists(), "the save went through while SAVE_LOCK was held");
+        drop(saves);
+        assert_eq!(read.expect("get_project waited on a save").as_str(), "after");
+
+        updater.join().unwrap().unwrap();
+        assert_eq!(saved_title(&path), "after");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_project_recovers_from_its_backup() {
+        let dir = temp_dir();
+        let path = dir.join("backups.gebo");
+        let path_str = path.to_string_lossy().to_string();
+        let mut saved = project(&path_str);
+        for title in ["first", "second", "third"] {
+            saved.title = title.to_string();
+            saved.write_file(&path, true).unwrap();
+        }
+        // A crash mid-write before saves were atomic
+        let content = fs::read(&path).unwrap();
+        fs::write(&path, &content[..content.len() / 2]).unwrap();
+        assert!(ProjectFile::from_path(&path).is_err());
+
+        let restored = restore_project_backup(path_str.clone(), None).unwrap();
+        assert_eq!(restored.title, "second");
+        assert_eq!(restored.path.as_deref(), Some(path.as_path()));
+        assert_eq!(fs::read(&path).unwrap().len(), content.len() / 2, "restoring changed the project file");
+
+        // The broken file isn't worth a backup slot
+        saved.title = "fourth".to_string();
+        saved.write_file(&path, true).unwrap();
+        assert_eq!(ProjectFile::from_path(&path).unwrap().title, "fourth");
+        assert_eq!(restore_project_backup(path_str.clone(), Some(1)).unwrap().title, "second");
+        assert_eq!(restore_project_backup(path_str, Some(2)).unwrap().title, "first");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_back_up_once_per_interval() {
+        let dir = temp_dir();
+        let path = dir.join("throttled.gebo");
+        let mut saved = project(&path.to_string_lossy());
+        for title in ["first", "second", "third"] {
+            saved.title = title.to_string();
+            saved.save().unwrap();
+        }
+        // Only the second save found a file to back up, and only the first save was due
+        assert!(!backup_path(&path, 1).exists());
+        assert_eq!(saved_title(&path), "third");
+
+        LAST_BACKUP.lock().unwrap().insert(path.clone(), Instant::now() - BACKUP_INTERVAL);
+        saved.title = "fourth".to_string();
+        saved.save().unwrap();
+        assert_eq!(saved_title(&backup_path(&path, 1)), "third");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Raw JSON of `tests/fixtures/{name}`, with clip paths made absolute so they resolve
+    /// wherever the test runs
+    fn fixture(name: &str) -> serde_json::Value {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut raw: serde_json::Value = serde_json::from_str(&fs::read_to_string(root.join("tests/fixtures").join(name)).unwrap()).unwrap();
+        for clip in raw["clips_map"].as_object_mut().unwrap().values_mut() {
+            let path = root.join(clip["path"].as_str().unwrap());
+            clip["path"] = serde_json::Value::from(path.to_string_lossy().to_string());
+        }
+        raw
+    }
+
+    /// Write `raw` as a project file in `dir`
+    fn write_fixture(dir: &Path, name: &str, raw: &serde_json::Value) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, serde_json::to_string_pretty(raw).unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn format_1_migrates_to_the_format_2_fixture() {
+        let mut v1 = fixture("project_v1.gebo");
+        migrate(&mut v1).unwrap();
+        assert_eq!(v1, fixture("project_v2.gebo"));
+
+        // The current format is left alone
+        let mut v2 = fixture("project_v2.gebo");
+        migrate(&mut v2).unwrap();
+        assert_eq!(v2, fixture("project_v2.gebo"));
+    }
+
+    #[test]
+    fn a_migrated_project_survives_a_save_and_reload() {
+        let dir = temp_dir();
+        let project = ProjectFile::from_path(&write_fixture(&dir, "v1.gebo", &fixture("project_v1.gebo"))).unwrap();
+        assert_eq!(project.version, PROJECT_VERSION);
+        assert_eq!(project.clips_map["clip_a"].r#type, ClipType::Video);
+        assert_eq!(project.tracks_map["track_v"].r#type, TrackType::Video);
+        assert_eq!(project.tracks_map["track_v"].segments.len(), 1);
+
+        let mut saved = project.clone();
+        saved.path = Some(dir.join("v2.gebo"));
+        saved.save().unwrap();
+        let reloaded = ProjectFile::from_path(&dir.join("v2.gebo")).unwrap();
+        assert_eq!(serde_json::to_value(&reloaded).unwrap(), serde_json::to_value(&saved).unwrap());
+
+        // Loading the format 2 fixture gives the same project as migrating format 1
+        let v2 = ProjectFile::from_path(&write_fixture(&dir, "v1.gebo", &fixture("project_v2.gebo"))).unwrap();
+        assert_eq!(serde_json::to_value(&v2).unwrap(), serde_json::to_value(&project).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_project_from_a_newer_format_is_refused() {
+        let dir = temp_dir();
+        let mut raw = fixture("project_v2.gebo");
+        raw["version"] = serde_json::Value::from(PROJECT_VERSION + 1);
+        let error = ProjectFile::from_path(&write_fixture(&dir, "future.gebo", &raw)).unwrap_err().to_string();
+        assert!(error.contains("newer version of Gebo"), "{}", error);
+        assert!(error.contains(&format!("project format {}", PROJECT_VERSION + 1)), "{}", error);
+
+        raw["version"] = serde_json::Value::from(0);
+        let error = ProjectFile::from_path(&write_fixture(&dir, "zero.gebo", &raw)).unwrap_err().to_string();
+        assert!(error.contains("invalid version"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn markers_and_times_move_to_output_time() {
+        // One 10 s segment; the cuts take out 2-5 and 6-8
+        let mut project = timeline(&[(0.0, 10.0, 1.0)], &[(2.0, 5.0), (6.0, 8.0)]);
+        project.markers = [(1.0, "kept"), (3.0, "cut"), (9.0, "late"), (5.5, "middle")]
+            .into_iter()
+            .map(|(time, label)| Marker { id: label.to_string(), time, label: label.to_string(), color: None })
+            .collect();
+
+        let markers: Vec<(String, f64)> = project.output_markers().into_iter().map(|m| (m.label, m.time)).collect();
+        assert_eq!(markers, vec![("kept".to_string(), 1.0), ("middle".to_string(), 2.5), ("late".to_string(), 4.0)]);
+        assert_eq!(project.remap_to_output_time(&[0.0, 3.0, 5.5, 7.0, 10.0]), vec![Some(0.0), None, Some(2.5), None, Some(5.0)]);
+
+        // Back to timeline time for everything the cuts keep
+        let remap = project.output_remap();
+        for (time, output) in [1.0, 5.5, 9.0].into_iter().zip(project.remap_to_output_time(&[1.0, 5.5, 9.0])) {
+            assert_eq!(remap.to_source(output.unwrap()), time);
+        }
+    }
+}
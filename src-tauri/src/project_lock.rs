@@ -0,0 +1,205 @@
+//! Advisory locks on project files, so the same project isn't edited from two places at once
+//! (another copy of the app, or another machine on a shared drive). Opening a project writes
+//! `<project>.lock` next to it, naming the host and process holding it; closing removes it. A
+//! crash leaves the file behind: a lock whose process no longer runs on this machine is stale,
+//! and stale locks of recent projects are cleaned up at startup. Locks held on other machines
+//! can't be checked, so only the user can clear those, with `force_unlock`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use crate::longterm_storage;
+
+/// Who holds a lock; the contents of the lock file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockHolder {
+    pub host: String,
+    pub pid: u32,
+    pub acquired_at: String, // ISO 8601
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LockStatus {
+    Unlocked,
+    Ours, // Held by this app, which has the project open
+    Held { holder: LockHolder }, // Held by another running app, on this machine or another
+    Stale { holder: Option<LockHolder> }, // Its process is gone; None when the file is unreadable
+}
+
+/// A recent project and its lock, for the Home page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecentProjectLock {
+    pub path: String,
+    pub lock: LockStatus,
+    pub recovered: bool, // A stale lock was removed at startup: the project's last session crashed
+}
+
+/// Projects whose stale lock `clean_stale_locks` removed this session
+static RECOVERED: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+fn lock_path(project: &Path) -> PathBuf {
+    let mut name = project.as_os_str().to_os_string();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Name of this machine, as written into locks
+fn host_name() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        std::env::var("COMPUTERNAME")
+            .ok()
+            .or_else(|| {
+                let output = Command::new("hostname").output().ok()?;
+                output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            })
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    })
+}
+
+/// Whether process `pid` is running on this machine. When that can't be told it's assumed
+/// to be, so a lock is never taken for stale by mistake
+fn process_running(pid: u32) -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        let output = Command::new("tasklist").args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"]).output();
+        match output {
+            Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid)),
+            _ => true,
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        match Command::new("kill").args(["-0", &pid.to_string()]).output() {
+            // A process of another user can't be signalled, but it is running
+            Ok(output) => output.status.success() || String::from_utf8_lossy(&output.stderr).contains("not permitted"),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Lock status of the project file at `project`
+pub fn status(project: &Path) -> LockStatus {
+    let content = match fs::read_to_string(lock_path(project)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return LockStatus::Unlocked,
+        Err(_) => return LockStatus::Stale { holder: None },
+    };
+    let Ok(holder) = serde_json::from_str::<LockHolder>(&content) else {
+        return LockStatus::Stale { holder: None };
+    };
+    if holder.host != host_name() {
+        LockStatus::Held { holder }
+    } else if holder.pid == std::process::id() {
+        LockStatus::Ours
+    } else if process_running(holder.pid) {
+        LockStatus::Held { holder }
+    } else {
+        LockStatus::Stale { holder: Some(holder) }
+    }
+}
+
+/// Lock `project` for this app. A project held elsewhere is an error; a stale lock is taken
+/// over. A lock that can't be written (say, in a read-only folder) is only logged, and the
+/// project opens unlocked
+pub fn acquire(project: &Path) -> Result<()> {
+    match status(project) {
+        LockStatus::Ours => return Ok(()),
+        LockStatus::Held { holder } => {
+            return Err(anyhow!(
+                "{:?} is open in Gebo on {} (process {}) since {}; close it there, or force unlock it from the Home page",
+                project.file_name().unwrap_or_default(),
+                holder.host,
+                holder.pid,
+                holder.acquired_at
+            ));
+        }
+        LockStatus::Stale { .. } => {
+            log::info!("Taking over the stale lock of {:?}", project);
+            let _ = fs::remove_file(lock_path(project));
+        }
+        LockStatus::Unlocked => {}
+    }
+    let holder = LockHolder {
+        host: host_name().to_string(),
+        pid: std::process::id(),
+        acquired_at: chrono::Utc::now().to_rfc3339(),
+    };
+    // create_new, so of two apps opening the project at once only one gets the lock
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(lock_path(project))
+        .and_then(|mut file| file.write_all(&serde_json::to_vec_pretty(&holder)?));
+    match written {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(anyhow!("{:?} was just opened elsewhere", project)),
+        Err(e) => {
+            log::warn!("Failed to lock {:?}, opening it unlocked: {}", project, e);
+            Ok(())
+        }
+    }
+}
+
+/// Remove this app's lock on `project`. Locks held by others are left alone
+pub fn release(project: &Path) {
+    if status(project) == LockStatus::Ours {
+        if let Err(e) = fs::remove_file(lock_path(project)) {
+            log::warn!("Failed to remove the lock of {:?}: {}", project, e);
+        }
+    }
+}
+
+/// Remove the lock on `project` whoever holds it, e.g. one left on a shared drive by a
+/// machine that crashed. Needs `confirmed`, since the other app may still be editing; a
+/// project this app has open is closed instead. Returns the status it had
+pub fn force_unlock(project: &Path, confirmed: bool) -> Result<LockStatus> {
+    let previous = status(project);
+    match previous {
+        LockStatus::Unlocked => return Ok(previous),
+        LockStatus::Ours => return Err(anyhow!("{:?} is open in this window; close it to unlock it", project)),
+        _ if !confirmed => return Err(anyhow!("force unlocking {:?} needs confirmation", project)),
+        _ => {}
+    }
+    fs::remove_file(lock_path(project)).with_context(|| format!("failed to remove the lock of {:?}", project))?;
+    log::info!("Force unlocked {:?} (was {:?})", project, previous);
+    Ok(previous)
+}
+
+/// Remove the stale locks of recent projects, left by sessions that crashed. Run at startup;
+/// returns the projects recovered, which `recent_project_locks` then flags
+pub fn clean_stale_locks() -> Result<Vec<String>> {
+    let mut recovered = Vec::new();
+    for path in longterm_storage::get_recent_projects()? {
+        if !matches!(status(Path::new(&path)), LockStatus::Stale { .. }) {
+            continue;
+        }
+        match fs::remove_file(lock_path(Path::new(&path))) {
+            Ok(()) => recovered.push(path),
+            Err(e) => log::warn!("Failed to remove the stale lock of {}: {}", path, e),
+        }
+    }
+    let mut guard = RECOVERED.lock().unwrap_or_else(|e| e.into_inner());
+    guard.get_or_insert_with(HashSet::new).extend(recovered.iter().map(PathBuf::from));
+    Ok(recovered)
+}
+
+/// Recent projects, newest first, with their lock status
+pub fn recent_project_locks() -> Result<Vec<RecentProjectLock>> {
+    let guard = RECOVERED.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(longterm_storage::get_recent_projects()?
+        .into_iter()
+        .map(|path| RecentProjectLock {
+            lock: status(Path::new(&path)),
+            recovered: guard.as_ref().is_some_and(|r| r.contains(Path::new(&path))),
+            path,
+        })
+        .collect())
+}
@@ -0,0 +1,357 @@
+//! Retention risk: the stretches of the edited video viewers are likely to skip, for an overlay
+//! before publishing. Every `CELL_SECONDS` of output is scored locally from the engagement of
+//! what plays there (engagement.rs), how long the speaker has gone on without a pause and how
+//! much of what's said repeats earlier phrases. The riskiest runs of cells are offered to the
+//! LLM, which picks `REGION_COUNT` of them and says why each would lose viewers; without an API
+//! key the local ranking is used with generic reasons. Regions are in output time (accepted
+//! cuts taken out) so the overlay lines up with what ships; they are kept on the project and
+//! can be turned into timeline markers.
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use crate::api_quota::ApiPriority;
+use crate::captions::{self, Cue};
+use crate::engagement::{self, EngagementWindow};
+use crate::gemini_client::GeminiClient;
+use crate::ids;
+use crate::intervals::Remap;
+use crate::longterm_storage;
+use crate::project_file::{ClipType, Marker, ProjectFile, RiskRegion, TrackType};
+
+/// Resolution of the local scores in output seconds; also the engagement window
+const CELL_SECONDS: f64 = 5.0;
+/// Regions an analysis reports
+pub const REGION_COUNT: usize = 5;
+/// Local candidates the LLM picks the regions from
+const CANDIDATE_COUNT: usize = 10;
+/// Cells at or above this risk can be part of a region
+const MIN_RISK: f64 = 0.5;
+/// Speech without a pause longer than this counts as a monologue, seconds
+const MONOLOGUE_SECONDS: f64 = 45.0;
+/// A gap between cues at least this long ends a run of speech, seconds
+const PAUSE_SECONDS: f64 = 1.0;
+/// Words in a phrase compared for repetition
+const PHRASE_WORDS: usize = 3;
+/// A phrase needs a word at least this long to count ("of the", "and a" repeat everywhere)
+const MIN_KEYWORD_LEN: usize = 4;
+/// Share of repeated phrases from which a stretch "repeats earlier points"
+const REPETITIVE_SHARE: f64 = 0.3;
+/// Transcript sent to the LLM per candidate, characters
+const CONTEXT_CHARS: usize = 600;
+/// Color of retention risk markers
+const RISK_COLOR: &str = "#e63946";
+
+/// Weight of each signal in a cell's risk. Signals the project doesn't have (no transcript)
+/// are left out and the rest re-weighted
+const ENGAGEMENT_WEIGHT: f64 = 0.6;
+const MONOLOGUE_WEIGHT: f64 = 0.2;
+const REPETITION_WEIGHT: f64 = 0.2;
+
+/// Local signals for `CELL_SECONDS` of output
+#[derive(Debug, Clone)]
+struct Cell {
+    start: f64,
+    end: f64,
+    engagement: Option<f64>, // Highest engagement of what plays here (0..1); None where nothing was scored
+    monologue: Option<f64>, // Seconds of unbroken speech by the end of the cell; None without a transcript
+    repetition: Option<f64>, // Share of the phrases said here that were said before; None without speech
+    risk: f64,
+}
+
+impl Cell {
+    /// Weighted risk of the signals the cell has, 0 without any
+    fn score(&self) -> f64 {
+        let monologue = self.monologue.map(|m| ((m - MONOLOGUE_SECONDS) / MONOLOGUE_SECONDS).clamp(0.0, 1.0));
+        let features = [
+            (self.engagement.map(|e| 1.0 - e), ENGAGEMENT_WEIGHT),
+            (monologue, MONOLOGUE_WEIGHT),
+            (self.repetition, REPETITION_WEIGHT),
+        ];
+        let weight: f64 = features.iter().filter(|(v, _)| v.is_some()).map(|(_, w)| w).sum();
+        let total: f64 = features.iter().filter_map(|(v, w)| v.map(|v| v * w)).sum();
+        if weight > 0.0 { total / weight } else { 0.0 }
+    }
+}
+
+/// Highest engagement in each cell, from the clips the enabled tracks play there: video for
+/// the picture, unmuted audio for the sound. Clips that can't be scored are left out
+fn engagement_by_cell(project: &ProjectFile, remap: &Remap, cells: usize) -> Vec<Option<f64>> {
+    let mut by_cell: Vec<Option<f64>> = vec![None; cells];
+    let mut scored: HashMap<String, Option<Vec<EngagementWindow>>> = HashMap::new();
+    let tracks = project.tracks_map.values().filter(|t| {
+        t.enabled && (t.r#type == TrackType::Video || (t.r#type == TrackType::Audio && !t.muted))
+    });
+    for track in tracks {
+        for (segment, (timeline_start, _)) in track.segments.iter().zip(track.timeline_spans()) {
+            if project.clips_map.get(&segment.clip_id).map_or(true, |c| c.r#type == ClipType::Image) {
+                continue;
+            }
+            let windows = scored.entry(segment.clip_id.clone()).or_insert_with(|| {
+                engagement::engagement_windows(&segment.clip_id, CELL_SECONDS)
+                    .inspect_err(|e| log::warn!("Engagement scoring failed for {}: {}", segment.clip_id, e))
+                    .ok()
+            });
+            let to_output = |t: f64| remap.to_output(timeline_start + (t - segment.start) / segment.speed);
+            for window in windows.iter().flatten() {
+                let (from, to) = (window.start.max(segment.start), window.end.min(segment.end));
+                let (start, end) = (to_output(from), to_output(to));
+                if to <= from || end <= start {
+                    continue; // Outside the segment, or cut
+                }
+                let last = ((end / CELL_SECONDS).ceil() as usize).min(cells);
+                let first = ((start / CELL_SECONDS) as usize).min(last);
+                let score = f64::from(window.score);
+                for cell in &mut by_cell[first..last] {
+                    *cell = Some(cell.map_or(score, |s| s.max(score)));
+                }
+            }
+        }
+    }
+    by_cell
+}
+
+/// Runs of speech in `cues` (output seconds) with no pause of `PAUSE_SECONDS` or more
+fn speech_runs(cues: &[Cue]) -> Vec<(f64, f64)> {
+    let mut runs: Vec<(f64, f64)> = Vec::new();
+    for cue in cues {
+        match runs.last_mut() {
+            Some(run) if cue.start - run.1 < PAUSE_SECONDS => run.1 = run.1.max(cue.end),
+            _ => runs.push((cue.start, cue.end)),
+        }
+    }
+    runs
+}
+
+/// Lowercased words of `text` without punctuation
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// For each cell, how many of the phrases said in it were said earlier in the video, and how
+/// many were said. A phrase counts at the time its first word is said, spread evenly over its cue
+fn phrase_counts(cues: &[Cue], cells: usize) -> Vec<(usize, usize)> {
+    let mut counts = vec![(0, 0); cells];
+    let mut seen = HashSet::new();
+    for cue in cues {
+        let words = words(&cue.text);
+        let step = (cue.end - cue.start).max(0.0) / words.len().max(1) as f64;
+        for (i, phrase) in words.windows(PHRASE_WORDS).enumerate() {
+            if phrase.iter().all(|w| w.chars().count() < MIN_KEYWORD_LEN) {
+                continue;
+            }
+            let cell = (((cue.start + i as f64 * step) / CELL_SECONDS) as usize).min(cells.saturating_sub(1));
+            let Some((repeated, said)) = counts.get_mut(cell) else { continue };
+            *said += 1;
+            if !seen.insert(phrase.join(" ")) {
+                *repeated += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Score every cell of the output. Runs engagement scoring (decoding, scene detection) for
+/// clips without cached scores
+fn score_cells(project: &ProjectFile, remap: &Remap, cues: &[Cue]) -> Result<Vec<Cell>> {
+    let count = (remap.duration / CELL_SECONDS).ceil() as usize;
+    let engagement = engagement_by_cell(project, remap, count);
+    if cues.is_empty() && engagement.iter().all(Option::is_none) {
+        return Err(anyhow!("nothing to score: no clip on the timeline could be scored and nothing is transcribed"));
+    }
+    let runs = speech_runs(cues);
+    let phrases = phrase_counts(cues, count);
+    Ok((0..count)
+        .map(|index| {
+            let start = index as f64 * CELL_SECONDS;
+            let end = (start + CELL_SECONDS).min(remap.duration);
+            let monologue = (!cues.is_empty()).then(|| {
+                runs.iter()
+                    .filter(|(from, to)| *from < end && *to > start)
+                    .map(|(from, to)| to.min(end) - from)
+                    .fold(0.0, f64::max)
+            });
+            let (repeated, said) = phrases[index];
+            let mut cell = Cell {
+                start,
+                end,
+                engagement: engagement[index],
+                monologue,
+                repetition: (said > 0).then(|| repeated as f64 / said as f64),
+                risk: 0.0,
+            };
+            cell.risk = cell.score();
+            cell
+        })
+        .collect())
+}
+
+/// Why `cells` risk losing viewers, from the local signals alone
+fn local_reason(cells: &[Cell]) -> String {
+    let mean = |signal: fn(&Cell) -> Option<f64>| {
+        let values: Vec<f64> = cells.iter().filter_map(signal).collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    let mut reasons = Vec::new();
+    if mean(|c| c.engagement).is_some_and(|e| e < 0.4) {
+        reasons.push("little is happening in the picture or the sound".to_string());
+    }
+    let longest = cells.iter().filter_map(|c| c.monologue).fold(0.0, f64::max);
+    if longest > MONOLOGUE_SECONDS {
+        reasons.push(format!("{:.0}s of talking without a pause", longest));
+    }
+    if mean(|c| c.repetition).is_some_and(|r| r >= REPETITIVE_SHARE) {
+        reasons.push("mostly repeats earlier points".to_string());
+    }
+    if reasons.is_empty() {
+        reasons.push("less is going on than in the rest of the video".to_string());
+    }
+    let reason = reasons.join(", ");
+    let mut chars = reason.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+/// A region before the LLM has seen it
+pub struct RiskCandidate {
+    region: RiskRegion, // With the local reason
+    transcript: String, // What's said in the region, for the LLM
+}
+
+/// What's said in output range [start, end), cut to `CONTEXT_CHARS`
+fn transcript_between(cues: &[Cue], start: f64, end: f64) -> String {
+    let text = cues.iter().filter(|c| c.end > start && c.start < end).map(|c| c.text.as_str()).collect::<Vec<_>>().join(" ");
+    text.chars().take(CONTEXT_CHARS).collect()
+}
+
+/// The `CANDIDATE_COUNT` riskiest runs of cells at `MIN_RISK` or more, riskiest first, scored
+/// by their mean risk. Runs engagement scoring, so call it off the async runtime
+pub fn risk_candidates(project: &ProjectFile) -> Result<Vec<RiskCandidate>> {
+    let remap = project.output_remap();
+    if remap.duration <= 0.0 {
+        return Err(anyhow!("the timeline is empty"));
+    }
+    let cues = captions::output_cues(project);
+    let cells = score_cells(project, &remap, &cues)?;
+
+    let mut runs: Vec<&[Cell]> = cells.chunk_by(|a, b| (a.risk >= MIN_RISK) == (b.risk >= MIN_RISK)).collect();
+    runs.retain(|run| run[0].risk >= MIN_RISK);
+    let mut candidates: Vec<RiskCandidate> = runs
+        .into_iter()
+        .map(|run| {
+            let (start, end) = (run[0].start, run[run.len() - 1].end);
+            RiskCandidate {
+                region: RiskRegion {
+                    start,
+                    end,
+                    score: run.iter().map(|c| c.risk).sum::<f64>() / run.len() as f64,
+                    reason: local_reason(run),
+                },
+                transcript: transcript_between(&cues, start, end),
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.region.score.total_cmp(&a.region.score).then((b.region.end - b.region.start).total_cmp(&(a.region.end - a.region.start)))
+    });
+    candidates.truncate(CANDIDATE_COUNT);
+    Ok(candidates)
+}
+
+/// One pick of the LLM
+#[derive(Deserialize)]
+struct LabeledRegion {
+    region: usize, // 1-based index into the candidates
+    reason: String,
+}
+
+/// Indices (0-based) of the candidates the LLM thinks viewers most likely skip, riskiest first,
+/// with its reasons. None if there is no API key or the answer can't be used
+async fn llm_labels(candidates: &[RiskCandidate]) -> Option<Vec<(usize, String)>> {
+    let api_key = match longterm_storage::get_gemini_api_key() {
+        Ok(Some(key)) => key,
+        _ => return None,
+    };
+    let regions: Vec<String> = candidates
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| {
+            let region = &candidate.region;
+            let text = if candidate.transcript.is_empty() { "(no speech)" } else { candidate.transcript.as_str() };
+            format!(
+                "Region {} ({:.0}s to {:.0}s, local risk {:.2}: {}): \"{}\"",
+                index + 1, region.start, region.end, region.score, region.reason, text
+            )
+        })
+        .collect();
+    let count = REGION_COUNT.min(candidates.len());
+    let prompt = format!(
+        "These are stretches of a video that local analysis flagged as slow (low activity in \
+         the picture and sound, long monologues, repeated points), with what is said in each:\n\n{}\n\n\
+         Pick the {} regions viewers are most likely to skip and say why in one sentence of at \
+         most 15 words, speaking to the editor. Answer with only a JSON array of {} objects \
+         {{\"region\": <number>, \"reason\": <string>}}, riskiest first.",
+        regions.join("\n"),
+        count,
+        count
+    );
+    let client = GeminiClient::new(api_key).with_priority(ApiPriority::Background);
+    let response = match client.generate_content(prompt).await {
+        Ok(response) => response,
+        Err(e) => {
+            log::warn!("Retention risk labelling failed, using local reasons: {}", e);
+            return None;
+        }
+    };
+    let json = &response[response.find('[')?..=response.rfind(']')?];
+    let labels: Vec<LabeledRegion> = serde_json::from_str(json).ok()?;
+    let mut picked: Vec<(usize, String)> = Vec::new();
+    for label in labels {
+        // A region number that wasn't offered is skipped, not taken for a failed answer
+        let Some(index) = label.region.checked_sub(1).filter(|i| *i < candidates.len()) else {
+            continue;
+        };
+        let reason = label.reason.trim().trim_matches('"').to_string();
+        if !reason.is_empty() && picked.iter().all(|(i, _)| *i != index) {
+            picked.push((index, reason));
+        }
+    }
+    if picked.is_empty() {
+        log::warn!("Retention risk labelling picked none of {} regions", candidates.len());
+        return None;
+    }
+    Some(picked)
+}
+
+/// The `REGION_COUNT` regions to report, in output order: the LLM's picks with its reasons,
+/// topped up from the local ranking when it picked fewer; the local ranking alone without it
+pub async fn label_regions(candidates: Vec<RiskCandidate>) -> Vec<RiskRegion> {
+    let labels = llm_labels(&candidates).await.unwrap_or_default();
+    let mut picked: Vec<RiskRegion> = labels
+        .iter()
+        .take(REGION_COUNT)
+        .map(|(index, reason)| RiskRegion { reason: reason.clone(), ..candidates[*index].region.clone() })
+        .collect();
+    let rest = candidates.iter().enumerate().filter(|(index, _)| labels.iter().all(|(i, _)| i != index));
+    picked.extend(rest.map(|(_, candidate)| candidate.region.clone()).take(REGION_COUNT.saturating_sub(picked.len())));
+    picked.sort_by(|a, b| a.start.total_cmp(&b.start));
+    picked
+}
+
+/// Timeline markers at the start of each retention risk kept on `project`, for review. Their
+/// output times are placed with the current cuts
+pub fn risk_markers(project: &ProjectFile) -> Vec<Marker> {
+    let remap = project.output_remap();
+    project
+        .retention_risks
+        .iter()
+        .map(|region| Marker {
+            id: ids::new_id(ids::MARKER),
+            time: remap.to_source(region.start),
+            label: format!("Skip risk {:.0}% ({:.0}s): {}", region.score * 100.0, region.end - region.start, region.reason),
+            color: Some(RISK_COLOR.to_string()),
+        })
+        .collect()
+}
@@ -0,0 +1,166 @@
+//! A sample project for first launch, so there is something to try the editor and the agent
+//! on. The media is synthesized with ffmpeg's lavfi sources, without network access, and
+//! encoded bit-exactly with fixed ids and a fixed transcript. Every sample has the same
+//! content at the same timestamps, so tutorials and bug reports can refer to them.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::ffmpeg;
+use crate::longterm_storage;
+use crate::media_project::{empty_track, full_segment};
+use crate::project_file::{self, Marker, ProjectFile, ProjectSettings, TrackType};
+use crate::transcription::TranscriptSegment;
+
+/// Length of the sample video, seconds. Its three scenes are a third of it each
+const SAMPLE_SECONDS: f64 = 30.0;
+const SCENE_SECONDS: f64 = 10.0;
+/// Where the sample's voice track goes quiet, for dead-air detection to find
+const SILENCE: (f64, f64) = (14.0, 17.0);
+
+const PROJECT_NAME: &str = "Gebo Sample.gebo";
+const VIDEO_NAME: &str = "gebo-sample.mp4";
+const MUSIC_NAME: &str = "gebo-sample-music.m4a";
+
+/// Encoder flags that keep version strings and timestamps out of the files
+const BITEXACT: &[&str] = &["-fflags", "+bitexact", "-flags:v", "+bitexact", "-flags:a", "+bitexact", "-map_metadata", "-1"];
+
+/// (start, end, text) of the canned transcript; nothing is said during `SILENCE`
+const TRANSCRIPT: &[(f64, f64, &str)] = &[
+    (0.5, 4.0, "Welcome to Gebo, the AI video editor."),
+    (4.5, 8.5, "This sample project was generated on your computer, no internet needed."),
+    (10.5, 13.5, "Try asking the assistant to remove the silence."),
+    (17.5, 21.0, "It will find the quiet gap in the middle of this clip."),
+    (22.0, 26.0, "You can also cut scenes, add markers and export the result."),
+    (26.5, 29.5, "Have fun editing!"),
+];
+
+fn run_ffmpeg(cmd: &mut Command, what: &str) -> Result<()> {
+    let output = cmd.output().with_context(|| "failed to spawn ffmpeg")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed to generate the sample {} (status {:?}): {}",
+            what,
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Three test-pattern scenes with a pulsing tone standing in for a voice, silent during `SILENCE`
+fn synthesize_video(output: &Path) -> Result<()> {
+    let scene = |source: &str| format!("{}=size=1280x720:rate=30:duration={}", source, SCENE_SECONDS);
+    let filter = format!(
+        "[0:v][1:v][2:v]concat=n=3:v=1:a=0,format=yuv420p[v];\
+         [3:a]tremolo=f=4:d=0.6,volume=0.5,volume=enable='between(t,{},{})':volume=0[a]",
+        SILENCE.0, SILENCE.1
+    );
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(["-v", "error", "-y"])
+            .args(["-f", "lavfi", "-i", &scene("testsrc2")])
+            .args(["-f", "lavfi", "-i", &scene("smptehdbars")])
+            .args(["-f", "lavfi", "-i", &scene("testsrc")])
+            .args(["-f", "lavfi", "-i", &format!("sine=frequency=440:sample_rate=48000:duration={}", SAMPLE_SECONDS)])
+            .args(["-filter_complex", &filter, "-map", "[v]", "-map", "[a]"])
+            .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-threads", "1"])
+            .args(["-c:a", "aac", "-b:a", "128k", "-movflags", "+faststart"])
+            .args(BITEXACT)
+            .arg(output),
+        "video",
+    )
+}
+
+/// A quiet two-note chord, the sample's "music" bed
+fn synthesize_music(output: &Path) -> Result<()> {
+    let note = |frequency: u32| format!("sine=frequency={}:sample_rate=48000:duration={}", frequency, SAMPLE_SECONDS);
+    run_ffmpeg(
+        Command::new("ffmpeg")
+            .args(["-v", "error", "-y"])
+            .args(["-f", "lavfi", "-i", &note(220)])
+            .args(["-f", "lavfi", "-i", &note(330)])
+            .args(["-filter_complex", "[0:a][1:a]amix=inputs=2,volume=0.4,afade=t=in:d=2,afade=t=out:st=27:d=3[a]"])
+            .args(["-map", "[a]", "-c:a", "aac", "-b:a", "128k"])
+            .args(BITEXACT)
+            .arg(output),
+        "music",
+    )
+}
+
+fn marker(id: &str, time: f64, label: &str, color: &str) -> Marker {
+    Marker { id: id.to_string(), time, label: label.to_string(), color: Some(color.to_string()) }
+}
+
+/// Generate the sample media in `dest_dir` (created if missing; earlier samples there are
+/// overwritten) and build a project around it: the video cut into its three scenes on one
+/// track, the music bed on another, scene markers and an English transcript. The project is
+/// saved, becomes the current project and is added to recent projects
+pub fn create_sample_project(dest_dir: &Path) -> Result<ProjectFile> {
+    if !ffmpeg::ffmpeg_exists() {
+        return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
+    }
+    fs::create_dir_all(dest_dir).with_context(|| format!("failed to create {:?}", dest_dir))?;
+    let video_path = dest_dir.join(VIDEO_NAME);
+    let music_path = dest_dir.join(MUSIC_NAME);
+    synthesize_video(&video_path)?;
+    synthesize_music(&music_path)?;
+
+    let mut video_clip = project_file::import_media(&video_path)?;
+    video_clip.id = "clip_sample_video".to_string();
+    video_clip.transcript = Some(TRANSCRIPT.iter().enumerate().map(|(index, (start, end, text))| TranscriptSegment {
+        id: format!("seg_{}", index),
+        start: *start,
+        end: *end,
+        text: text.to_string(),
+        confidence: Some(1.0),
+        words: Vec::new(),
+    }).collect());
+    video_clip.transcript_language = Some("en".to_string());
+    let mut music_clip = project_file::import_media(&music_path)?;
+    music_clip.id = "clip_sample_music".to_string();
+
+    let mut video = empty_track("Video Track 1", TrackType::Video, 0);
+    video.id = "track_sample_video".to_string();
+    let scenes = ["Test pattern", "Color bars", "Grid"];
+    for (index, label) in scenes.iter().enumerate() {
+        let mut segment = full_segment(&video_clip)?;
+        segment.id = format!("segment_sample_scene_{}", index + 1);
+        segment.start = index as f64 * SCENE_SECONDS;
+        segment.end = (index + 1) as f64 * SCENE_SECONDS;
+        segment.label = Some(label.to_string());
+        video.segments.push(segment);
+    }
+    let mut music = empty_track("Music", TrackType::Audio, 1);
+    music.id = "track_sample_music".to_string();
+    music.volume = 40;
+    let mut bed = full_segment(&music_clip)?;
+    bed.id = "segment_sample_music".to_string();
+    music.segments.push(bed);
+
+    let project_path = dest_dir.join(PROJECT_NAME);
+    let project = ProjectFile {
+        title: "Gebo Sample".to_string(),
+        version: project_file::PROJECT_VERSION,
+        clips_map: HashMap::from([(video_clip.id.clone(), video_clip), (music_clip.id.clone(), music_clip)]),
+        tracks_map: HashMap::from([(video.id.clone(), video), (music.id.clone(), music)]),
+        path: Some(PathBuf::from(&project_path)),
+        markers: vec![
+            marker("marker_sample_bars", SCENE_SECONDS, "Color bars", "#4f9dff"),
+            marker("marker_sample_silence", SILENCE.0, "Silence", "#f5c518"),
+            marker("marker_sample_grid", 2.0 * SCENE_SECONDS, "Grid", "#4f9dff"),
+        ],
+        accepted_cuts: Vec::new(),
+        settings: ProjectSettings::default(),
+        view_state: Default::default(),
+        retention_risks: Vec::new(),
+    };
+    let project = project_file::new_project(project)?;
+    let project_path = project_path.to_string_lossy().to_string();
+    if let Err(e) = longterm_storage::add_recent_project(project_path.clone()) {
+        log::warn!("Failed to add {} to recent projects: {}", project_path, e);
+    }
+    Ok(project)
+}
@@ -0,0 +1,296 @@
+//! Self-test of the media pipeline: synthesize a short clip with ffmpeg and run it through the
+//! same probe, waveform, thumbnail, proxy and export code real projects use. Broken ffmpeg
+//! builds, missing encoders and unwritable directories show up here instead of mid-edit. A
+//! project in the first file format checks the migrations.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+use crate::ffmpeg;
+use crate::media_protocol;
+use crate::project_file::{self, ClipType, ProjectFile, TrackType};
+use crate::waveform;
+use crate::work_dirs::{self, WorkDir};
+
+/// Length of the generated clip, seconds
+const SAMPLE_SECONDS: f64 = 2.0;
+/// Range cut out of the sample by the export step
+const SAMPLE_CUT: (f64, f64) = (0.5, 1.0);
+/// Name of the generated clip; also names its preview proxy
+const SAMPLE_NAME: &str = "gebo-self-test.mp4";
+/// Titles the drawtext step draws: the characters ffmpeg's filter parsers treat specially,
+/// expansion sequences, emoji and line breaks. All printable ASCII, short and long enough to
+/// go through a text file, is added to these
+const DRAWTEXT_SAMPLES: &[&str] = &[
+  "It's 10:30 - 50% off [today], really; \"quoted\" \\back\\slash",
+  "%{pts} %{localtime} %% \\% \\\\:\\' ''' ::: ,,, ;;; [[ ]] ==",
+  "Emoji 🎬🎉👩‍👩‍👧 and accents: café, naïve, 日本語",
+  "First line\nSecond line: 'quoted'\r\nThird line",
+];
+
+/// A project as format 1 wrote it: no version, lowercase types. Its clip is pointed at a file
+/// that exists before loading
+const PROJECT_V1_FIXTURE: &str = include_str!("../tests/fixtures/project_v1.gebo");
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+  Passed,
+  Failed,
+  Skipped, // An earlier step the check depends on failed
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SelfTestStep {
+  pub name: String,
+  pub status: StepStatus,
+  pub duration_ms: u64,
+  pub detail: Option<String>, // What the step produced, or why it was skipped
+  pub error: Option<String>,  // Failure message, including ffmpeg's stderr where there is one
+}
+
+/// Report of `run_self_test`, meant to be pasted into support tickets as is
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SelfTestReport {
+  pub passed: bool,
+  pub ran_at: String, // ISO 8601
+  pub ffmpeg_version: Option<String>,
+  pub system: ffmpeg::SystemReport,
+  pub steps: Vec<SelfTestStep>,
+}
+
+/// First line of `ffmpeg -version`
+fn ffmpeg_version() -> Option<String> {
+  let output = Command::new("ffmpeg").arg("-version").output().ok()?;
+  String::from_utf8_lossy(&output.stdout).lines().next().map(|line| line.trim().to_string())
+}
+
+/// Write a `SAMPLE_SECONDS` test pattern with a tone to `output`
+fn synthesize_sample(output: &Path) -> Result<String> {
+  synthesize(output, true, true)
+}
+
+/// Write a `SAMPLE_SECONDS` clip with a test pattern, a tone, or both
+fn synthesize(output: &Path, video: bool, audio: bool) -> Result<String> {
+  let duration = SAMPLE_SECONDS.to_string();
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-y"]);
+  if video {
+    cmd.args(["-f", "lavfi", "-i", &format!("testsrc2=size=640x360:rate=30:duration={}", duration)])
+      .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"]);
+  }
+  if audio {
+    cmd.args(["-f", "lavfi", "-i", &format!("sine=frequency=440:sample_rate=48000:duration={}", duration)])
+      .args(["-c:a", "aac"]);
+  }
+  let result = cmd
+    .arg("-shortest")
+    .arg(output)
+    .output()
+    .with_context(|| "failed to spawn ffmpeg")?;
+  if !result.status.success() {
+    return Err(anyhow!(
+      "ffmpeg failed to generate the sample (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr).trim()
+    ));
+  }
+  let content = match (video, audio) {
+    (true, true) => "test pattern with a 440 Hz tone",
+    (true, false) => "test pattern without audio",
+    _ => "440 Hz tone without video",
+  };
+  Ok(format!("{} s {}", SAMPLE_SECONDS, content))
+}
+
+/// Runs steps in order, skipping those after a failed step they depend on
+struct Runner {
+  steps: Vec<SelfTestStep>,
+}
+
+impl Runner {
+  /// Run `check` as step `name` unless `requires` failed or was skipped. Returns whether it passed
+  fn step(&mut self, name: &str, requires: &[&str], check: impl FnOnce() -> Result<String>) -> bool {
+    let blocked = self.steps.iter().find(|s| requires.contains(&s.name.as_str()) && s.status != StepStatus::Passed);
+    if let Some(blocked) = blocked {
+      let detail = format!("needs {}, which did not pass", blocked.name);
+      self.steps.push(SelfTestStep { name: name.to_string(), status: StepStatus::Skipped, duration_ms: 0, detail: Some(detail), error: None });
+      return false;
+    }
+    let started = Instant::now();
+    let result = check();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let passed = result.is_ok();
+    let (detail, error) = match result {
+      Ok(detail) => (Some(detail), None),
+      Err(e) => (None, Some(format!("{:#}", e))),
+    };
+    let status = if passed { StepStatus::Passed } else { StepStatus::Failed };
+    self.steps.push(SelfTestStep { name: name.to_string(), status, duration_ms, detail, error });
+    passed
+  }
+}
+
+/// Load `PROJECT_V1_FIXTURE` as the current format, check that a save and reload doesn't change
+/// it, and that a project from a newer format is refused with a message saying so
+fn check_project_migration(dir: &Path, clip: &Path) -> Result<String> {
+  let v1 = dir.join("gebo-self-test-v1.gebo");
+  let mut raw: serde_json::Value = serde_json::from_str(PROJECT_V1_FIXTURE)?;
+  raw["clips_map"]["clip_a"]["path"] = serde_json::Value::from(clip.to_string_lossy().to_string());
+  fs::write(&v1, serde_json::to_string(&raw)?)?;
+  let project = ProjectFile::from_path(&v1)?;
+  let clip_type = project.clips_map.get("clip_a").map(|c| c.r#type.clone());
+  let track_type = project.tracks_map.get("track_v").map(|t| t.r#type.clone());
+  if project.version != project_file::PROJECT_VERSION || clip_type != Some(ClipType::Video) || track_type != Some(TrackType::Video) {
+    return Err(anyhow!("format 1 loaded as version {} with clip {:?} and track {:?}", project.version, clip_type, track_type));
+  }
+
+  let mut saved = project.clone();
+  saved.path = Some(dir.join("gebo-self-test-v2.gebo"));
+  saved.save()?;
+  let reloaded = ProjectFile::from_path(saved.path.as_deref().unwrap_or(&v1))?;
+  if serde_json::to_value(&reloaded)? != serde_json::to_value(&saved)? {
+    return Err(anyhow!("the migrated project changed when saved and loaded again"));
+  }
+
+  let future = dir.join("gebo-self-test-future.gebo");
+  let mut raw = serde_json::to_value(&saved)?;
+  raw["version"] = serde_json::Value::from(project_file::PROJECT_VERSION + 1);
+  fs::write(&future, serde_json::to_string(&raw)?)?;
+  match ProjectFile::from_path(&future) {
+    Err(e) if e.to_string().contains("newer version of Gebo") => {}
+    Err(e) => return Err(anyhow!("a project from a newer format failed with: {}", e)),
+    Ok(_) => return Err(anyhow!("a project from a newer format loaded")),
+  }
+  Ok(format!("format 1 migrated to {}, format {} refused", project_file::PROJECT_VERSION, project_file::PROJECT_VERSION + 1))
+}
+
+/// Generate a sample clip in the cache directory and run it through the pipeline. Never fails
+/// as a whole: every problem is reported on its step
+pub fn run_self_test() -> SelfTestReport {
+  let ran_at = chrono::Utc::now().to_rfc3339();
+  let mut runner = Runner { steps: Vec::new() };
+  let mut dir = None;
+
+  runner.step("work_dirs", &[], || {
+    let cache = work_dirs::path(WorkDir::Cache)?;
+    let previews = work_dirs::path(WorkDir::Previews)?;
+    let test_dir = cache.join("self-test");
+    fs::create_dir_all(&test_dir).with_context(|| format!("cannot create {:?}", test_dir))?;
+    dir = Some(test_dir);
+    Ok(format!("cache {:?}, previews {:?}", cache, previews))
+  });
+  let dir = dir.unwrap_or_else(|| std::env::temp_dir().join("gebo-self-test"));
+  let sample = dir.join(SAMPLE_NAME);
+  let sample_str = sample.to_string_lossy().to_string();
+  let cut_output = dir.join("gebo-self-test-cut.mp4");
+
+  runner.step("synthesize", &["work_dirs"], || synthesize_sample(&sample));
+  runner.step("probe", &["synthesize"], || {
+    let probe = ffmpeg::ffprobe(&sample_str)?;
+    if (probe.duration - SAMPLE_SECONDS).abs() > 0.2 || probe.width != 640 || probe.audio_rate == 0 {
+      return Err(anyhow!(
+        "unexpected probe result: {:.2} s, {}x{}, audio {} Hz",
+        probe.duration, probe.width, probe.height, probe.audio_rate
+      ));
+    }
+    Ok(format!("{:.2} s, {}x{}, {:.0} fps, audio {} Hz", probe.duration, probe.width, probe.height, probe.fps, probe.audio_rate))
+  });
+  runner.step("waveform", &["synthesize"], || {
+    let peaks = waveform::pcm_peaks(&sample_str, 0)?;
+    if !peaks.iter().any(|p| *p != 0) {
+      return Err(anyhow!("waveform of the test tone is silent ({} peaks)", peaks.len()));
+    }
+    Ok(format!("{} peaks", peaks.len()))
+  });
+  runner.step("thumbnails", &["synthesize"], || {
+    let thumbnails = ffmpeg::generate_thumbnails(&sample_str, 2, 160)?;
+    if thumbnails.is_empty() {
+      return Err(anyhow!("no thumbnails were generated"));
+    }
+    Ok(format!("{} thumbnails", thumbnails.len()))
+  });
+  runner.step("proxy", &["synthesize"], || {
+    let proxy = ffmpeg::make_preview_proxy(&sample_str, Some(320), None, None, &mut |_| {})?.path;
+    let size = fs::metadata(&proxy).with_context(|| format!("proxy {:?} is missing", proxy))?.len();
+    Ok(format!("{} ({} bytes)", proxy, size))
+  });
+  runner.step("cut_export", &["synthesize"], || {
+    let outcome = ffmpeg::export_with_cuts(
+      &sample_str,
+      &cut_output.to_string_lossy(),
+      &[SAMPLE_CUT],
+      &ffmpeg::ExportSettings::default(),
+      None,
+      true,
+      &mut |_| {},
+    )?
+    .outcome;
+    let expected = SAMPLE_SECONDS - (SAMPLE_CUT.1 - SAMPLE_CUT.0);
+    let duration = ffmpeg::ffprobe(&cut_output.to_string_lossy())?.duration;
+    if (duration - expected).abs() > 0.2 {
+      return Err(anyhow!("export is {:.2} s long, expected {:.2} s", duration, expected));
+    }
+    Ok(format!("{:?}, {:.2} s", outcome, duration))
+  });
+  // Screen recordings have no audio and music no video; both must probe and export
+  for (name, file, video, audio) in [
+    ("video_only", "gebo-self-test-video-only.mp4", true, false),
+    ("audio_only", "gebo-self-test-audio-only.m4a", false, true),
+  ] {
+    let input = dir.join(file);
+    let input_str = input.to_string_lossy().to_string();
+    let cut = dir.join(format!("gebo-self-test-{}-cut.mp4", name));
+    runner.step(name, &["work_dirs"], || {
+      synthesize(&input, video, audio)?;
+      let probe = ffmpeg::ffprobe(&input_str)?;
+      if (probe.width > 0) != video || (probe.audio_rate > 0) != audio {
+        return Err(anyhow!("probe sees {}x{} video and {} Hz audio", probe.width, probe.height, probe.audio_rate));
+      }
+      ffmpeg::export_with_cuts(&input_str, &cut.to_string_lossy(), &[SAMPLE_CUT], &ffmpeg::ExportSettings::default(), None, true, &mut |_| {})?;
+      let exported = ffmpeg::ffprobe(&cut.to_string_lossy())?;
+      let expected = SAMPLE_SECONDS - (SAMPLE_CUT.1 - SAMPLE_CUT.0);
+      if (exported.duration - expected).abs() > 0.2 || (exported.width > 0) != video || (exported.audio_rate > 0) != audio {
+        return Err(anyhow!(
+          "export is {:.2} s with {}x{} video and {} Hz audio, expected {:.2} s",
+          exported.duration, exported.width, exported.height, exported.audio_rate, expected
+        ));
+      }
+      Ok(format!("v_codec {}, a_codec {}, exported {:.2} s", probe.v_codec, probe.a_codec, exported.duration))
+    });
+  }
+  runner.step("project_migration", &["synthesize"], || check_project_migration(&dir, &sample));
+  runner.step("drawtext", &[], || {
+    let printable: String = (0x20u8..0x7f).map(char::from).collect();
+    let long = printable.repeat(4);
+    let samples: Vec<&str> = DRAWTEXT_SAMPLES.iter().copied().chain([printable.as_str(), long.as_str()]).collect();
+    for text in &samples {
+      let drawtext = ffmpeg::drawtext_text(text)?;
+      let source = format!("color=c=black:s=320x180:d=0.1,drawtext={}:fontcolor=white:fontsize=12", drawtext.options);
+      let result = Command::new("ffmpeg")
+        .args(["-v", "error", "-f", "lavfi", "-i", &source, "-frames:v", "1", "-f", "null", "-"])
+        .output()
+        .with_context(|| "failed to spawn ffmpeg")?;
+      if !result.status.success() {
+        return Err(anyhow!("drawtext rejected {:?}: {}", text, String::from_utf8_lossy(&result.stderr).trim()));
+      }
+    }
+    Ok(format!("{} titles drawn", samples.len()))
+  });
+  runner.step("cleanup", &[], || {
+    if let Ok(proxy) = ffmpeg::proxy_path(&sample_str, Some(320), None) {
+      media_protocol::revoke_path(&proxy);
+      let _ = fs::remove_file(&proxy);
+    }
+    if dir.exists() {
+      fs::remove_dir_all(&dir).with_context(|| format!("failed to remove {:?}", dir))?;
+    }
+    Ok(format!("removed {:?}", dir))
+  });
+
+  let passed = runner.steps.iter().all(|s| s.status == StepStatus::Passed);
+  SelfTestReport { passed, ran_at, ffmpeg_version: ffmpeg_version(), system: ffmpeg::system_report(), steps: runner.steps }
+}
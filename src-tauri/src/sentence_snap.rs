@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use crate::transcription::TranscriptSegment;
+
+/// Default limit on how far a cut may be moved to reach a sentence boundary, in seconds
+pub const DEFAULT_MAX_SHIFT: f64 = 1.5;
+
+/// Tokens ending in '.' that do not end a sentence. Compared lowercase, without surrounding quotes
+const ABBREVIATIONS: &[&str] = &[
+    "dr.", "mr.", "mrs.", "ms.", "prof.", "sr.", "jr.", "st.", "mt.", "vs.", "etc.",
+    "e.g.", "i.e.", "cf.", "approx.", "inc.", "ltd.", "co.", "corp.", "no.", "fig.",
+    "vol.", "dept.", "est.", "jan.", "feb.", "mar.", "apr.", "jun.", "jul.", "aug.",
+    "sep.", "sept.", "oct.", "nov.", "dec.", "a.m.", "p.m.", "u.s.", "u.k.",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SnapDirection {
+    Backward,
+    Forward,
+    Nearest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapResult {
+    pub time: f64,     // Snapped time, or the original time if nothing was in reach
+    pub snapped: bool,
+    pub shift: f64,    // time - requested time
+}
+
+/// Whether a transcript token ends a sentence
+fn ends_sentence(token: &str) -> bool {
+    let trimmed = token.trim_end_matches(['"', '\'', ')', ']', '”', '’']);
+    if trimmed.ends_with('?') || trimmed.ends_with('!') || trimmed.ends_with('…') {
+        return true;
+    }
+    if !trimmed.ends_with('.') {
+        return false;
+    }
+
+    let lower = trimmed
+        .trim_start_matches(['"', '\'', '(', '[', '“', '‘'])
+        .to_lowercase();
+    if ABBREVIATIONS.contains(&lower.as_str()) {
+        return false;
+    }
+    // Single-letter initials such as "J."
+    let letters = lower.trim_end_matches('.');
+    !(letters.chars().count() == 1 && letters.chars().all(char::is_alphabetic))
+}
+
+/// Lowercase alphanumerics only, for matching provider words against transcript text
+fn normalize(token: &str) -> String {
+    token.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// Sentence-end flags for each word of a segment. Providers strip punctuation from word
+/// timings, so punctuation is taken from the matching token of the segment text
+fn word_sentence_ends(segment: &TranscriptSegment) -> Vec<bool> {
+    let tokens: Vec<&str> = segment.text.split_whitespace().collect();
+    let mut next_token = 0;
+    segment.words.iter().map(|word| {
+        let target = normalize(&word.word);
+        // Punctuation on the word itself wins if the provider kept it
+        if ends_sentence(word.word.trim()) {
+            return true;
+        }
+        // Look a few tokens ahead to tolerate small differences between words and text
+        for offset in 0..3 {
+            if let Some(token) = tokens.get(next_token + offset) {
+                if normalize(token) == target {
+                    next_token += offset + 1;
+                    return ends_sentence(token);
+                }
+            }
+        }
+        false
+    }).collect()
+}
+
+/// Times (seconds, transcript time) at which a sentence starts or ends, sorted.
+/// Boundaries between sentences sit in the middle of the pause between them.
+pub fn sentence_boundaries(segments: &[TranscriptSegment]) -> Vec<f64> {
+    // (end of sentence, start of whatever follows) pairs, plus the very first start
+    let mut ends: Vec<f64> = Vec::new();
+    let mut starts: Vec<f64> = Vec::new();
+    let mut first_start: Option<f64> = None;
+    let mut after_sentence_end = false;
+
+    for segment in segments {
+        if !segment.words.is_empty() {
+            for (word, is_end) in segment.words.iter().zip(word_sentence_ends(segment)) {
+                first_start.get_or_insert(word.start);
+                if after_sentence_end {
+                    starts.push(word.start);
+                }
+                after_sentence_end = is_end;
+                if is_end {
+                    ends.push(word.end);
+                }
+            }
+            continue;
+        }
+
+        // No word timings: place interior sentence ends proportionally by character offset
+        let text = segment.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        first_start.get_or_insert(segment.start);
+        if after_sentence_end {
+            starts.push(segment.start);
+        }
+        after_sentence_end = false;
+        let chars = text.chars().count().max(1) as f64;
+        let mut offset = 0;
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        for (i, token) in tokens.iter().enumerate() {
+            offset += token.chars().count() + 1;
+            if !ends_sentence(token) {
+                continue;
+            }
+            if i + 1 == tokens.len() {
+                ends.push(segment.end);
+                after_sentence_end = true;
+            } else {
+                let t = segment.start + (segment.end - segment.start) * (offset as f64 / chars).min(1.0);
+                ends.push(t);
+                starts.push(t);
+            }
+        }
+    }
+
+    let mut boundaries: Vec<f64> = first_start.into_iter().collect();
+    for (i, end) in ends.iter().enumerate() {
+        // Pair each end with the first start at or after it
+        let next_start = starts.iter().copied().find(|s| *s >= *end);
+        match next_start {
+            Some(start) => boundaries.push((end + start) / 2.0),
+            None if i + 1 == ends.len() => boundaries.push(*end),
+            None => {}
+        }
+    }
+    boundaries.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    boundaries.dedup_by(|a, b| (*a - *b).abs() < 1e-6);
+    boundaries
+}
+
+/// Move `time` to a sentence boundary in `direction`, at most `max_shift` seconds away
+pub fn snap_to_boundary(boundaries: &[f64], time: f64, direction: SnapDirection, max_shift: f64) -> SnapResult {
+    let candidate = boundaries.iter().copied().filter(|b| match direction {
+        SnapDirection::Backward => *b <= time,
+        SnapDirection::Forward => *b >= time,
+        SnapDirection::Nearest => true,
+    }).min_by(|a, b| {
+        (a - time).abs().partial_cmp(&(b - time).abs()).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    match candidate {
+        Some(b) if (b - time).abs() <= max_shift => SnapResult { time: b, snapped: true, shift: b - time },
+        _ => SnapResult { time, snapped: false, shift: 0.0 },
+    }
+}
+
+/// Snap both ends of a cut range so whole sentences are removed. Returns None if snapping
+/// would collapse the range, in which case the caller should keep the original
+pub fn snap_range(boundaries: &[f64], start: f64, end: f64, max_shift: f64) -> Option<(f64, f64)> {
+    let s = snap_to_boundary(boundaries, start, SnapDirection::Nearest, max_shift);
+    let e = snap_to_boundary(boundaries, end, SnapDirection::Nearest, max_shift);
+    if e.time - s.time > 0.001 {
+        Some((s.time, e.time))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcription::TranscriptWord;
+
+    fn segment(start: f64, end: f64, text: &str, words: &[(&str, f64, f64)]) -> TranscriptSegment {
+        TranscriptSegment {
+            id: uuid::Uuid::new_v4().to_string(),
+            start,
+            end,
+            text: text.to_string(),
+            confidence: None,
+            words: words.iter().map(|&(word, start, end)| TranscriptWord { word: word.to_string(), start, end }).collect(),
+        }
+    }
+
+    #[test]
+    fn abbreviations_and_initials_do_not_end_sentences() {
+        for token in ["Dr.", "e.g.", "(Mr.", "etc.", "J.", "p.m."] {
+            assert!(!ends_sentence(token), "{}", token);
+        }
+        for token in ["end.", "why?", "stop!", "done.\"", "(really.)", "well…"] {
+            assert!(ends_sentence(token), "{}", token);
+        }
+    }
+
+    #[test]
+    fn boundaries_sit_in_the_pause_between_sentences() {
+        let segments = [segment(0.0, 2.6, "Hello there. Dr. Smith agrees.", &[
+            ("hello", 0.0, 0.4),
+            ("there", 0.5, 1.0),
+            ("Dr", 1.4, 1.6),
+            ("Smith", 1.7, 2.0),
+            ("agrees", 2.1, 2.6),
+        ])];
+        assert_eq!(sentence_boundaries(&segments), vec![0.0, 1.2, 2.6]);
+    }
+
+    #[test]
+    fn segments_without_word_timings_are_split_by_characters() {
+        let segments = [segment(0.0, 10.0, "One two. Three four.", &[])];
+        assert_eq!(sentence_boundaries(&segments), vec![0.0, 4.5, 10.0]);
+    }
+
+    #[test]
+    fn snapping_respects_direction_and_max_shift() {
+        let boundaries = [0.0, 1.2, 2.6];
+        let forward = snap_to_boundary(&boundaries, 1.0, SnapDirection::Forward, DEFAULT_MAX_SHIFT);
+        assert!(forward.snapped);
+        assert_eq!(forward.time, 1.2);
+        assert_eq!(snap_to_boundary(&boundaries, 1.0, SnapDirection::Backward, DEFAULT_MAX_SHIFT).time, 0.0);
+        assert_eq!(snap_to_boundary(&boundaries, 2.0, SnapDirection::Nearest, DEFAULT_MAX_SHIFT).time, 2.6);
+
+        let out_of_reach = snap_to_boundary(&boundaries, 1.0, SnapDirection::Nearest, 0.1);
+        assert!(!out_of_reach.snapped);
+        assert_eq!((out_of_reach.time, out_of_reach.shift), (1.0, 0.0));
+    }
+
+    #[test]
+    fn ranges_that_would_collapse_are_not_snapped() {
+        let boundaries = [0.0, 1.2, 2.6];
+        assert_eq!(snap_range(&boundaries, 0.1, 2.4, DEFAULT_MAX_SHIFT), Some((0.0, 2.6)));
+        assert_eq!(snap_range(&boundaries, 1.1, 1.3, DEFAULT_MAX_SHIFT), None);
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+use crate::longterm_storage;
+
+/// Default keyboard shortcuts, action id → accelerator. An empty accelerator means unbound.
+/// Actions prefixed with "global." are registered with the OS and fire even when Gebo is not focused
+pub const DEFAULT_SHORTCUTS: &[(&str, &str)] = &[
+    ("command_palette", "CmdOrCtrl+K"),
+    ("toggle_play", "Space"),
+    ("mark_in", "I"),
+    ("mark_out", "O"),
+    ("undo", "CmdOrCtrl+Z"),
+    ("redo", "CmdOrCtrl+Shift+Z"),
+    ("seek_back", "Left"),
+    ("seek_forward", "Right"),
+    ("seek_back_long", "Shift+Left"),
+    ("seek_forward_long", "Shift+Right"),
+    ("frame_back", "Comma"),
+    ("frame_forward", "Period"),
+    ("go_to_start", "Home"),
+    ("go_to_end", "End"),
+    ("global.focus_app", "CmdOrCtrl+Alt+G"),
+    ("global.toggle_recording", ""),
+];
+
+/// Modifiers in canonical order
+const MODIFIERS: &[(&str, &[&str])] = &[
+    ("CmdOrCtrl", &["cmdorctrl", "commandorcontrol", "cmdorcontrol", "commandorctrl"]),
+    ("Ctrl", &["ctrl", "control"]),
+    ("Alt", &["alt", "option"]),
+    ("Shift", &["shift"]),
+    ("Super", &["super", "cmd", "command", "meta"]),
+];
+
+/// Named keys and their accepted spellings
+const NAMED_KEYS: &[(&str, &[&str])] = &[
+    ("Space", &["space"]),
+    ("Enter", &["enter", "return"]),
+    ("Escape", &["escape", "esc"]),
+    ("Tab", &["tab"]),
+    ("Backspace", &["backspace"]),
+    ("Delete", &["delete", "del"]),
+    ("Insert", &["insert"]),
+    ("Home", &["home"]),
+    ("End", &["end"]),
+    ("PageUp", &["pageup"]),
+    ("PageDown", &["pagedown"]),
+    ("Up", &["up", "arrowup"]),
+    ("Down", &["down", "arrowdown"]),
+    ("Left", &["left", "arrowleft"]),
+    ("Right", &["right", "arrowright"]),
+    ("Comma", &["comma", ","]),
+    ("Period", &["period", "."]),
+    ("Slash", &["slash", "/"]),
+    ("Backslash", &["backslash", "\\"]),
+    ("Semicolon", &["semicolon", ";"]),
+    ("Quote", &["quote", "'"]),
+    ("Backquote", &["backquote", "`"]),
+    ("Minus", &["minus", "-"]),
+    ("Equal", &["equal", "="]),
+    ("BracketLeft", &["bracketleft", "["]),
+    ("BracketRight", &["bracketright", "]"]),
+];
+
+/// Global shortcuts currently registered with the OS, and the action each one triggers
+static REGISTERED: Mutex<Vec<(Shortcut, String)>> = Mutex::new(Vec::new());
+
+pub fn is_global_action(action: &str) -> bool {
+    action.starts_with("global.")
+}
+
+fn canonical_key(key: &str) -> Option<String> {
+    let lower = key.to_lowercase();
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, spellings)| spellings.contains(&lower.as_str())) {
+        return Some(name.to_string());
+    }
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            return Some(c.to_ascii_uppercase().to_string());
+        }
+    }
+    // Function keys F1-F24
+    let number = lower.strip_prefix('f')?.parse::<u8>().ok()?;
+    (1..=24).contains(&number).then(|| format!("F{}", number))
+}
+
+/// Parse an accelerator such as "ctrl+shift+k" into canonical form ("Ctrl+Shift+K").
+/// Returns (canonical, has_modifier)
+pub fn normalize_accelerator(accel: &str) -> Result<(String, bool)> {
+    // "Ctrl++" can't be split unambiguously; the plus/equals key is spelled "Equal"
+    let parts: Vec<&str> = accel.split('+').map(|p| p.trim()).collect();
+    if parts.iter().any(|p| p.is_empty()) {
+        return Err(anyhow!("malformed accelerator '{}'", accel));
+    }
+    let (key, modifiers) = parts.split_last().ok_or_else(|| anyhow!("empty accelerator"))?;
+
+    let mut present = [false; MODIFIERS.len()];
+    for modifier in modifiers {
+        let lower = modifier.to_lowercase();
+        let index = MODIFIERS.iter()
+            .position(|(_, spellings)| spellings.contains(&lower.as_str()))
+            .ok_or_else(|| anyhow!("unknown modifier '{}' in '{}'", modifier, accel))?;
+        if present[index] {
+            return Err(anyhow!("modifier '{}' repeated in '{}'", modifier, accel));
+        }
+        present[index] = true;
+    }
+    let key = canonical_key(key).ok_or_else(|| anyhow!("unknown key '{}' in '{}'", key, accel))?;
+
+    let mut canonical: Vec<String> = MODIFIERS.iter().zip(present)
+        .filter(|(_, on)| *on)
+        .map(|((name, _), _)| name.to_string())
+        .collect();
+    let has_modifier = !canonical.is_empty();
+    canonical.push(key);
+    Ok((canonical.join("+"), has_modifier))
+}
+
+/// Key used to detect conflicts: CmdOrCtrl is resolved for the current platform
+fn conflict_key(canonical: &str) -> String {
+    let resolved = if cfg!(target_os = "macos") { "Super" } else { "Ctrl" };
+    let mut parts: Vec<&str> = canonical.split('+')
+        .map(|p| if p == "CmdOrCtrl" { resolved } else { p })
+        .collect();
+    let key = parts.pop().unwrap_or_default();
+    parts.sort_unstable();
+    parts.dedup();
+    parts.push(key);
+    parts.join("+")
+}
+
+/// Fill in defaults for actions the stored map doesn't mention, and drop unknown actions
+pub fn with_defaults(stored: &HashMap<String, String>) -> HashMap<String, String> {
+    DEFAULT_SHORTCUTS.iter()
+        .map(|(action, default)| {
+            let accel = stored.get(*action).cloned().unwrap_or_else(|| default.to_string());
+            (action.to_string(), accel)
+        })
+        .collect()
+}
+
+/// Validate and canonicalize a full shortcut map.
+/// Rejects unknown actions, malformed accelerators, global shortcuts without a modifier,
+/// and two actions sharing one accelerator
+pub fn validate_shortcuts(shortcuts: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    let mut canonical_map = HashMap::new();
+    let mut owners: HashMap<String, &str> = HashMap::new();
+
+    // Sorted so the error names the same pair every time
+    let mut actions: Vec<&String> = shortcuts.keys().collect();
+    actions.sort();
+    for action in actions {
+        if !DEFAULT_SHORTCUTS.iter().any(|(known, _)| known == action) {
+            return Err(anyhow!("unknown shortcut action '{}'", action));
+        }
+        let accel = shortcuts[action].trim();
+        if accel.is_empty() {
+            canonical_map.insert(action.clone(), String::new());
+            continue;
+        }
+
+        let (canonical, has_modifier) = normalize_accelerator(accel)?;
+        if is_global_action(action) && !has_modifier {
+            return Err(anyhow!("global shortcut for '{}' needs at least one modifier", action));
+        }
+        if let Some(other) = owners.insert(conflict_key(&canonical), action) {
+            return Err(anyhow!("'{}' for '{}' is already bound to '{}'", canonical, action, other));
+        }
+        canonical_map.insert(action.clone(), canonical);
+    }
+    Ok(canonical_map)
+}
+
+/// Current shortcut map, with defaults for anything not customized
+pub fn get_shortcuts() -> Result<HashMap<String, String>> {
+    Ok(longterm_storage::get_settings()?.shortcuts)
+}
+
+/// Bind `action` to `accel` (empty to unbind) and persist. Returns the new map
+pub fn set_shortcut(action: &str, accel: &str) -> Result<HashMap<String, String>> {
+    let mut settings = longterm_storage::get_settings()?;
+    if !settings.shortcuts.contains_key(action) {
+        return Err(anyhow!("unknown shortcut action '{}'", action));
+    }
+    settings.shortcuts.insert(action.to_string(), accel.to_string());
+    settings.shortcuts = validate_shortcuts(&settings.shortcuts)?;
+    longterm_storage::set_settings(settings.clone())?;
+    Ok(settings.shortcuts)
+}
+
+/// Replace the OS-level registrations with the global actions of `shortcuts`.
+/// A shortcut another app already owns is logged and skipped rather than failing the rest
+pub fn register_global_shortcuts(app: &AppHandle, shortcuts: &HashMap<String, String>) -> Result<()> {
+    let global_shortcut = app.global_shortcut();
+    global_shortcut.unregister_all().map_err(|e| anyhow!("failed to unregister shortcuts: {}", e))?;
+
+    let mut registered = REGISTERED.lock().map_err(|e| anyhow!("failed to lock shortcut registry: {}", e))?;
+    registered.clear();
+    for (action, accel) in shortcuts {
+        if !is_global_action(action) || accel.is_empty() {
+            continue;
+        }
+        let shortcut = match Shortcut::from_str(accel) {
+            Ok(shortcut) => shortcut,
+            Err(e) => {
+                log::warn!("Cannot register global shortcut {} for {}: {}", accel, action, e);
+                continue;
+            }
+        };
+        match global_shortcut.register(shortcut) {
+            Ok(()) => registered.push((shortcut, action.clone())),
+            Err(e) => log::warn!("Cannot register global shortcut {} for {}: {}", accel, action, e),
+        }
+    }
+    Ok(())
+}
+
+/// Global shortcut plugin handler: emits "global-shortcut" with the action id
+pub fn handle_global_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    let action = match REGISTERED.lock() {
+        Ok(registered) => registered.iter().find(|(s, _)| s == shortcut).map(|(_, a)| a.clone()),
+        Err(_) => None,
+    };
+    let Some(action) = action else {
+        return;
+    };
+
+    if action == "global.focus_app" {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+    }
+    if let Err(e) = app.emit("global-shortcut", action) {
+        log::warn!("Failed to emit global-shortcut: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(action, accel)| (action.to_string(), accel.to_string())).collect()
+    }
+
+    #[test]
+    fn accelerators_are_canonicalized() {
+        assert_eq!(normalize_accelerator("shift+ctrl+k").unwrap(), ("Ctrl+Shift+K".to_string(), true));
+        assert_eq!(normalize_accelerator(" Command + option + f12 ").unwrap(), ("Alt+Super+F12".to_string(), true));
+        assert_eq!(normalize_accelerator("esc").unwrap(), ("Escape".to_string(), false));
+        assert_eq!(normalize_accelerator("CmdOrCtrl+,").unwrap(), ("CmdOrCtrl+Comma".to_string(), true));
+    }
+
+    #[test]
+    fn malformed_accelerators_are_rejected() {
+        for accel in ["", "Ctrl+", "+K", "Ctrl++", "Hyper+K", "Ctrl+Ctrl+K", "Control+Ctrl+K", "Ctrl+F25", "Ctrl+Foo", "K+Ctrl"] {
+            assert!(normalize_accelerator(accel).is_err(), "{:?} was accepted", accel);
+        }
+    }
+
+    #[test]
+    fn the_defaults_are_valid() {
+        let defaults = with_defaults(&HashMap::new());
+        assert_eq!(validate_shortcuts(&defaults).unwrap(), defaults);
+    }
+
+    #[test]
+    fn two_actions_cannot_share_an_accelerator() {
+        let error = validate_shortcuts(&map(&[("mark_in", "I"), ("mark_out", "i")])).unwrap_err();
+        assert!(error.to_string().contains("already bound"), "{}", error);
+
+        let error = validate_shortcuts(&map(&[("undo", "Ctrl+Shift+Z"), ("redo", "shift+control+z")])).unwrap_err();
+        assert!(error.to_string().contains("already bound"), "{}", error);
+
+        // Unbound actions don't conflict with each other
+        assert!(validate_shortcuts(&map(&[("mark_in", ""), ("mark_out", "")])).is_ok());
+    }
+
+    #[test]
+    fn cmd_or_ctrl_conflicts_with_the_platform_modifier() {
+        let platform = if cfg!(target_os = "macos") { "Super+X" } else { "Ctrl+X" };
+        assert_eq!(conflict_key("CmdOrCtrl+X"), platform);
+        let error = validate_shortcuts(&map(&[("undo", "CmdOrCtrl+X"), ("redo", platform)])).unwrap_err();
+        assert!(error.to_string().contains("already bound"), "{}", error);
+
+        let other = if cfg!(target_os = "macos") { "Ctrl+X" } else { "Super+X" };
+        assert!(validate_shortcuts(&map(&[("undo", "CmdOrCtrl+X"), ("redo", other)])).is_ok());
+    }
+
+    #[test]
+    fn global_shortcuts_need_a_modifier() {
+        let error = validate_shortcuts(&map(&[("global.focus_app", "G")])).unwrap_err();
+        assert!(error.to_string().contains("needs at least one modifier"), "{}", error);
+        assert!(validate_shortcuts(&map(&[("global.focus_app", "Alt+G")])).is_ok());
+        // Local actions may use a bare key
+        assert!(validate_shortcuts(&map(&[("mark_in", "G")])).is_ok());
+    }
+
+    #[test]
+    fn unknown_actions_are_rejected() {
+        assert!(validate_shortcuts(&map(&[("launch_rockets", "Ctrl+R")])).is_err());
+    }
+}
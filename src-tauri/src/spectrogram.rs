@@ -0,0 +1,444 @@
+//! Spectral view of a clip's audio, for finding what a waveform can't show: mains hum, a
+//! whining fan, rumble. `spectrogram` decodes a range to mono 16 kHz, runs a Hann-windowed FFT
+//! over overlapping frames and folds the magnitudes into log-spaced bands × time buckets,
+//! cached beside the waveforms (see waveform_cache). `suggest_denoise` looks for tones that
+//! stay put for most of the range and proposes the track effects that remove them.
+
+use anyhow::{anyhow, Context, Result};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::project_file::{self, Effect};
+use crate::waveform;
+use crate::waveform_cache;
+
+/// Rate the audio is analysed at; hum and its harmonics sit far below its 8 kHz Nyquist
+const SAMPLE_RATE: u32 = 16000;
+/// Samples per FFT frame: 3.9 Hz bins, fine enough to tell 50 Hz hum from 60 Hz
+const FFT_SIZE: usize = 4096;
+/// Lowest frequency shown or suggested, Hz
+const MIN_FREQUENCY: f64 = 20.0;
+/// Levels at or below this are drawn as the coldest color, dBFS
+pub const FLOOR_DB: f32 = -120.0;
+/// Allowed band and time bucket counts
+pub const BANDS_RANGE: (usize, usize) = (8, 512);
+pub const RESOLUTION_RANGE: (usize, usize) = (1, 4096);
+/// Bump when the layout of a cached spectrogram changes
+const CACHE_VERSION: u32 = 1;
+
+/// Bands × time buckets of magnitudes, for a heatmap
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Spectrogram {
+  pub start: f64, // Clip seconds
+  pub end: f64,
+  pub bucket_seconds: f64, // Width of a time bucket
+  pub band_edges: Vec<f64>, // Hz, one more than there are bands, lowest first
+  pub magnitudes: Vec<Vec<u8>>, // One row per band, lowest first; 0 is FLOOR_DB and 255 is 0 dBFS
+}
+
+/// A tone found in most frames of the analysed range
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TonalPeak {
+  pub frequency: f64, // Hz
+  pub prominence_db: f64, // Above the spectrum around it
+  pub presence: f64, // Share of frames it was found in, 0..1
+}
+
+/// Result of `suggest_denoise`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DenoiseSuggestion {
+  pub peaks: Vec<TonalPeak>, // Strongest first
+  pub effects: Vec<Effect>, // Track effects removing them; empty when nothing stands out
+  pub track_id: Option<String>, // Track playing the clip, for applying `effects` with update_track
+  pub summary: String,
+}
+
+/// Cache entry: the spectrogram and the stamp of the source it was made from
+#[derive(Serialize, Deserialize)]
+struct CachedSpectrogram {
+  version: u32,
+  stamp: (u64, u64),
+  spectrogram: Spectrogram,
+}
+
+fn check_range((start, end): (f64, f64)) -> Result<()> {
+  if !start.is_finite() || !end.is_finite() || start < 0.0 || end <= start {
+    return Err(anyhow!("invalid range {}..{}", start, end));
+  }
+  Ok(())
+}
+
+/// Hann window of `FFT_SIZE` and its sum, which scales magnitudes back to amplitudes
+fn hann() -> (Vec<f32>, f32) {
+  let window: Vec<f32> = (0..FFT_SIZE)
+    .map(|i| (0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / FFT_SIZE as f64).cos()) as f32)
+    .collect();
+  let sum = window.iter().sum();
+  (window, sum)
+}
+
+/// Decode `start..end` of `path` and call `on_frame` with the amplitude spectrum (bins
+/// 0..=FFT_SIZE/2, where a full-scale sine reads 1.0) of each frame, `hop` samples apart
+fn analyse(
+  path: &str,
+  audio_stream: usize,
+  (start, end): (f64, f64),
+  hop: usize,
+  on_frame: &mut dyn FnMut(usize, &[f32]),
+) -> Result<usize> {
+  let mut child = Command::new("ffmpeg")
+    .args(waveform::decode_args(path, audio_stream, Some(start), Some(end)))
+    .args(["-ac", "1", "-ar", &SAMPLE_RATE.to_string(), "-f", "f32le", "-"])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .with_context(|| "failed to spawn ffmpeg for the spectrogram")?;
+  let mut stdout = child.stdout.take().ok_or_else(|| anyhow!("failed to capture stdout"))?;
+  let mut stderr = child.stderr.take().ok_or_else(|| anyhow!("failed to capture stderr"))?;
+  let stderr = std::thread::spawn(move || {
+    let mut text = String::new();
+    let _ = stderr.read_to_string(&mut text);
+    text
+  });
+
+  let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+  let (window, window_sum) = hann();
+  let mut samples: Vec<f32> = Vec::with_capacity(FFT_SIZE * 2);
+  let mut frame = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+  let mut spectrum = vec![0.0f32; FFT_SIZE / 2 + 1];
+  let mut frames = 0usize;
+  let mut buf = vec![0u8; 64 * 1024];
+  let mut pending = 0usize; // Bytes of a sample split across reads
+  loop {
+    let n = stdout.read(&mut buf[pending..])?;
+    if n == 0 {
+      break;
+    }
+    let available = pending + n;
+    let whole = available - available % 4;
+    samples.extend(buf[..whole].chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+    buf.copy_within(whole..available, 0);
+    pending = available - whole;
+
+    let mut offset = 0;
+    while samples.len() - offset >= FFT_SIZE {
+      for (i, value) in frame.iter_mut().enumerate() {
+        *value = Complex::new(samples[offset + i] * window[i], 0.0);
+      }
+      fft.process(&mut frame);
+      for (bin, amplitude) in spectrum.iter_mut().enumerate() {
+        *amplitude = frame[bin].norm() * 2.0 / window_sum;
+      }
+      on_frame(frames, &spectrum);
+      frames += 1;
+      offset += hop;
+    }
+    samples.drain(..offset.min(samples.len()));
+  }
+  let status = child.wait()?;
+  let stderr = stderr.join().unwrap_or_default();
+  if !status.success() {
+    return Err(anyhow!("ffmpeg spectrogram decode failed (status {:?}): {}", status.code(), stderr.trim()));
+  }
+  if frames == 0 {
+    return Err(anyhow!("{}..{}s is shorter than one analysis frame ({:.2}s)", start, end, FFT_SIZE as f64 / f64::from(SAMPLE_RATE)));
+  }
+  Ok(frames)
+}
+
+fn bin_frequency(bin: f64) -> f64 {
+  bin * f64::from(SAMPLE_RATE) / FFT_SIZE as f64
+}
+
+fn to_db(amplitude: f32) -> f32 {
+  if amplitude <= 0.0 {
+    return FLOOR_DB;
+  }
+  (20.0 * amplitude.log10()).max(FLOOR_DB)
+}
+
+/// `bands` + 1 log-spaced edges from `MIN_FREQUENCY` to the Nyquist frequency
+fn band_edges(bands: usize) -> Vec<f64> {
+  let nyquist = f64::from(SAMPLE_RATE) / 2.0;
+  let ratio = (nyquist / MIN_FREQUENCY).powf(1.0 / bands as f64);
+  (0..=bands).map(|i| MIN_FREQUENCY * ratio.powi(i as i32)).collect()
+}
+
+/// FFT bins averaged into each band: those whose center lies in it, or the nearest one for
+/// bands narrower than a bin
+fn band_bins(edges: &[f64]) -> Vec<(usize, usize)> {
+  let bin_width = bin_frequency(1.0);
+  let last = FFT_SIZE / 2;
+  edges
+    .windows(2)
+    .map(|edge| {
+      let first = (edge[0] / bin_width).ceil() as usize;
+      let end = ((edge[1] / bin_width).ceil() as usize).min(last + 1);
+      if first < end {
+        (first, end)
+      } else {
+        let nearest = (((edge[0] + edge[1]) / 2.0 / bin_width).round() as usize).min(last);
+        (nearest, nearest + 1)
+      }
+    })
+    .collect()
+}
+
+/// Fold per-frame amplitude spectra (frame `i` starting `i * hop` samples into the range) into
+/// `bands` × `resolution` buckets of mean power. Buckets no frame starts in repeat the one before
+struct Accumulator {
+  bins: Vec<(usize, usize)>,
+  power: Vec<Vec<f64>>, // [band][bucket]
+  counts: Vec<u32>, // Frames per bucket
+  frames_per_bucket: f64,
+}
+
+impl Accumulator {
+  fn new(edges: &[f64], resolution: usize, frames_per_bucket: f64) -> Self {
+    let bins = band_bins(edges);
+    Accumulator { power: vec![vec![0.0; resolution]; bins.len()], bins, counts: vec![0; resolution], frames_per_bucket }
+  }
+
+  fn add(&mut self, frame: usize, spectrum: &[f32]) {
+    let bucket = ((frame as f64 / self.frames_per_bucket) as usize).min(self.counts.len() - 1);
+    self.counts[bucket] += 1;
+    for (band, &(first, end)) in self.bins.iter().enumerate() {
+      let power: f64 = spectrum[first..end].iter().map(|a| f64::from(*a) * f64::from(*a)).sum();
+      self.power[band][bucket] += power / (end - first) as f64;
+    }
+  }
+
+  fn magnitudes(self) -> Vec<Vec<u8>> {
+    let counts = self.counts;
+    self.power
+      .into_iter()
+      .map(|row| {
+        let mut last = 0u8;
+        row
+          .iter()
+          .zip(&counts)
+          .map(|(power, count)| {
+            if *count > 0 {
+              let db = to_db((power / f64::from(*count)).sqrt() as f32);
+              last = ((db - FLOOR_DB) / -FLOOR_DB * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+            last
+          })
+          .collect()
+      })
+      .collect()
+  }
+}
+
+/// Spectrogram of `range` (clip seconds) of `path`'s `audio_stream`: `bands` log-spaced bands
+/// × `resolution` time buckets, from the cache when the file hasn't changed
+pub fn spectrogram(path: &str, audio_stream: usize, range: (f64, f64), bands: usize, resolution: usize) -> Result<Spectrogram> {
+  check_range(range)?;
+  if !(BANDS_RANGE.0..=BANDS_RANGE.1).contains(&bands) {
+    return Err(anyhow!("bands must be {} to {}, got {}", BANDS_RANGE.0, BANDS_RANGE.1, bands));
+  }
+  if !(RESOLUTION_RANGE.0..=RESOLUTION_RANGE.1).contains(&resolution) {
+    return Err(anyhow!("resolution must be {} to {} buckets, got {}", RESOLUTION_RANGE.0, RESOLUTION_RANGE.1, resolution));
+  }
+  let stamp = waveform_cache::source_stamp(Path::new(path))?;
+  let entry = cache_entry(path, audio_stream, range, bands, resolution)?;
+  let cached = fs::read(&entry).ok().and_then(|data| serde_json::from_slice::<CachedSpectrogram>(&data).ok());
+  if let Some(cached) = cached.filter(|c| c.version == CACHE_VERSION && c.stamp == stamp) {
+    return Ok(cached.spectrogram);
+  }
+
+  let range_samples = (range.1 - range.0) * f64::from(SAMPLE_RATE);
+  // At least one frame per bucket when the range allows it, and never sparser than half overlap
+  let hop = ((range_samples / resolution as f64) as usize).clamp(128, FFT_SIZE / 2);
+  let edges = band_edges(bands);
+  let mut accumulator = Accumulator::new(&edges, resolution, (range_samples / hop as f64 / resolution as f64).max(1e-9));
+  analyse(path, audio_stream, range, hop, &mut |frame, spectrum| accumulator.add(frame, spectrum))?;
+  let spectrogram = Spectrogram {
+    start: range.0,
+    end: range.1,
+    bucket_seconds: (range.1 - range.0) / resolution as f64,
+    band_edges: edges,
+    magnitudes: accumulator.magnitudes(),
+  };
+
+  let cached = CachedSpectrogram { version: CACHE_VERSION, stamp, spectrogram };
+  let temp = entry.with_extension("tmp");
+  let written = serde_json::to_vec(&cached)
+    .map_err(std::io::Error::from)
+    .and_then(|data| fs::write(&temp, data))
+    .and_then(|()| fs::rename(&temp, &entry));
+  if let Err(e) = written {
+    let _ = fs::remove_file(&temp);
+    log::warn!("Failed to cache the spectrogram of {}: {}", path, e);
+  }
+  Ok(cached.spectrogram)
+}
+
+/// Entry for one request, in the waveform cache so its size and clearing cover spectrograms too
+fn cache_entry(path: &str, audio_stream: usize, range: (f64, f64), bands: usize, resolution: usize) -> Result<PathBuf> {
+  let source = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+  let mut hasher = Sha256::new();
+  hasher.update(source.to_string_lossy().as_bytes());
+  hasher.update((audio_stream as u64).to_le_bytes());
+  hasher.update(range.0.to_le_bytes());
+  hasher.update(range.1.to_le_bytes());
+  hasher.update((bands as u64).to_le_bytes());
+  hasher.update((resolution as u64).to_le_bytes());
+  let hash = format!("{:x}", hasher.finalize());
+  Ok(waveform_cache::cache_dir()?.join(format!("{}.spectrogram.json", &hash[..32])))
+}
+
+/// Highest frequency a constant tone is looked for at, Hz: hum harmonics and fan whine
+const MAX_TONE_FREQUENCY: f64 = 4000.0;
+/// A bin counts as a tone in a frame when it tops the spectrum around it by this much, dB
+const TONE_PROMINENCE_DB: f32 = 10.0;
+/// Bins on each side compared against, skipping the closest `TONE_GAP` (the tone's own spread)
+const TONE_NEIGHBORHOOD: usize = 16;
+const TONE_GAP: usize = 2;
+/// Share of frames a tone must be found in to count as constant rather than speech or music
+const MIN_PRESENCE: f64 = 0.6;
+/// Tones quieter than this are left alone, dBFS
+const MIN_TONE_DB: f32 = -75.0;
+/// Most tones a suggestion deals with
+const MAX_TONES: usize = 5;
+/// Tones below this are rumble, removed with a high-pass rather than a notch, Hz
+const RUMBLE_BELOW: f64 = 45.0;
+
+/// Mean level (dB) of the bins around `bin`, leaving out the tone itself
+fn neighborhood_db(spectrum_db: &[f32], bin: usize) -> f32 {
+  let low = bin.saturating_sub(TONE_NEIGHBORHOOD);
+  let high = (bin + TONE_NEIGHBORHOOD).min(spectrum_db.len() - 1);
+  let around: Vec<f32> = (low..=high).filter(|b| b.abs_diff(bin) > TONE_GAP).map(|b| spectrum_db[b]).collect();
+  if around.is_empty() {
+    return FLOOR_DB;
+  }
+  around.iter().sum::<f32>() / around.len() as f32
+}
+
+/// Per-bin tone counts and summed levels over the frames of a range, see `tonal_peaks`
+struct ToneCounter {
+  counts: Vec<u32>,
+  level_db: Vec<f64>,
+  frames: u32,
+}
+
+impl ToneCounter {
+  fn new() -> Self {
+    ToneCounter { counts: vec![0; FFT_SIZE / 2 + 1], level_db: vec![0.0; FFT_SIZE / 2 + 1], frames: 0 }
+  }
+
+  fn add(&mut self, spectrum: &[f32]) {
+    let db: Vec<f32> = spectrum.iter().map(|a| to_db(*a)).collect();
+    for (level, bin_db) in self.level_db.iter_mut().zip(&db) {
+      *level += f64::from(*bin_db);
+    }
+    let first = (MIN_FREQUENCY / bin_frequency(1.0)).ceil() as usize;
+    let last = ((MAX_TONE_FREQUENCY / bin_frequency(1.0)) as usize).min(db.len() - 2);
+    for bin in first.max(1)..=last {
+      let local_max = db[bin] >= db[bin - 1] && db[bin] >= db[bin + 1];
+      if local_max && db[bin] >= MIN_TONE_DB && db[bin] - neighborhood_db(&db, bin) >= TONE_PROMINENCE_DB {
+        self.counts[bin] += 1;
+      }
+    }
+    self.frames += 1;
+  }
+
+  /// Tones present in at least `MIN_PRESENCE` of the frames, strongest first. Neighboring
+  /// bins are one tone; its frequency is refined between bins from the mean spectrum
+  fn peaks(&self) -> Vec<TonalPeak> {
+    if self.frames == 0 {
+      return Vec::new();
+    }
+    let frames = f64::from(self.frames);
+    let mean_db: Vec<f32> = self.level_db.iter().map(|sum| (sum / frames) as f32).collect();
+    let presence = |bin: usize| f64::from(self.counts[bin]) / frames;
+    let mut peaks = Vec::new();
+    let mut bin = 1;
+    while bin < self.counts.len() - 1 {
+      if presence(bin) < MIN_PRESENCE {
+        bin += 1;
+        continue;
+      }
+      // A tone between two bins qualifies in both; the run is one tone
+      let mut best = bin;
+      while bin + 1 < self.counts.len() - 1 && presence(bin + 1) >= MIN_PRESENCE {
+        bin += 1;
+        if mean_db[bin] > mean_db[best] {
+          best = bin;
+        }
+      }
+      let (left, center, right) = (f64::from(mean_db[best - 1]), f64::from(mean_db[best]), f64::from(mean_db[best + 1]));
+      let curvature = left - 2.0 * center + right;
+      let offset = if curvature < 0.0 { (0.5 * (left - right) / curvature).clamp(-0.5, 0.5) } else { 0.0 };
+      peaks.push(TonalPeak {
+        frequency: bin_frequency(best as f64 + offset),
+        prominence_db: f64::from(mean_db[best] - neighborhood_db(&mean_db, best)),
+        presence: presence(best),
+      });
+      bin += 1;
+    }
+    peaks.sort_by(|a, b| b.prominence_db.total_cmp(&a.prominence_db));
+    peaks.truncate(MAX_TONES);
+    peaks
+  }
+}
+
+fn effect(r#type: &str, parameters: serde_json::Value) -> Effect {
+  let parameters: HashMap<String, serde_json::Value> = serde_json::from_value(parameters).unwrap_or_default();
+  Effect { r#type: r#type.to_string(), parameters }
+}
+
+/// Track effects removing `peaks`: a high-pass above the lowest rumble, and a notch
+/// (`anequalizer`) per tone above that
+fn denoise_effects(peaks: &[TonalPeak]) -> Vec<Effect> {
+  let mut effects = Vec::new();
+  if let Some(rumble) = peaks.iter().filter(|p| p.frequency < RUMBLE_BELOW).map(|p| p.frequency).reduce(f64::max) {
+    let cutoff = ((rumble * 1.5 / 5.0).round() * 5.0).clamp(20.0, 120.0);
+    effects.push(effect("highpass", json!({ "frequency": cutoff })));
+  }
+  let mut tones: Vec<&TonalPeak> = peaks.iter().filter(|p| p.frequency >= RUMBLE_BELOW).collect();
+  tones.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+  for tone in tones {
+    let frequency = (tone.frequency * 10.0).round() / 10.0;
+    let width = (tone.frequency / 15.0).clamp(2.0, 50.0).round();
+    // Cut about as deep as the tone stands out, so speech around it keeps its level
+    let gain = -(tone.prominence_db.round()).clamp(6.0, 40.0);
+    effects.push(effect("anequalizer", json!({ "frequency": frequency, "width": width, "gain": gain })));
+  }
+  effects
+}
+
+/// Constant tones in `range` of `path`'s `audio_stream` and the track effects that would
+/// remove them. `track_id` is passed through for the caller to apply them
+pub fn suggest_denoise(path: &str, audio_stream: usize, range: (f64, f64), track_id: Option<String>) -> Result<DenoiseSuggestion> {
+  check_range(range)?;
+  let mut counter = ToneCounter::new();
+  analyse(path, audio_stream, range, FFT_SIZE / 2, &mut |_, spectrum| counter.add(spectrum))?;
+  let peaks = counter.peaks();
+  let effects = denoise_effects(&peaks);
+  let summary = match peaks.first() {
+    None => "No constant hum or tone found".to_string(),
+    Some(_) => {
+      let tones: Vec<String> = peaks.iter().map(|p| format!("{:.0} Hz ({:.0} dB)", p.frequency, p.prominence_db)).collect();
+      format!("Constant tones at {}", tones.join(", "))
+    }
+  };
+  Ok(DenoiseSuggestion { peaks, effects, track_id, summary })
+}
+
+/// Path, audio stream and playing track of clip `clip_id` of the current project, for the commands
+pub fn clip_source(clip_id: &str) -> Result<(String, usize, Option<String>)> {
+  let clip = project_file::get_clip(clip_id)?;
+  if clip.latest_probe.as_ref().is_some_and(|p| p.audio_rate == 0) {
+    return Err(anyhow!("clip {} has no audio", clip_id));
+  }
+  let track_id = project_file::track_playing_clip(clip_id);
+  Ok((clip.path.to_string_lossy().to_string(), clip.audio_stream(), track_id))
+}
@@ -0,0 +1,263 @@
+//! Prefetcher for the streaming preview: encodes the segments just ahead of the playhead in
+//! the background so playback doesn't stall at segment boundaries. The frontend reports
+//! the playhead with `set_playhead_hint`; the stream pulls finished segments with
+//! `take_segment` instead of encoding them itself.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use crate::streaming_encoder::{self, StreamingSegment};
+
+/// Seconds of timeline encoded ahead of the playhead
+pub const LOOKAHEAD_SECONDS: f64 = 10.0;
+/// Prefetched data kept per stream (base64 chunk bytes)
+const MAX_CACHE_BYTES: usize = 256 * 1024 * 1024;
+/// Segments encoded at the same time per stream
+const MAX_PARALLEL_ENCODES: usize = 2;
+
+struct CachedSegment {
+  chunks: Vec<String>, // Base64 fMP4 chunks, as emitted to the frontend
+  bytes: usize,
+  complete: bool,
+  failed: bool,
+  cancel: Arc<AtomicBool>,
+}
+
+struct PreviewStream {
+  segments: Vec<StreamingSegment>,
+  width: u32,
+  cache: HashMap<usize, CachedSegment>, // Segment index -> prefetched data
+  taken: Vec<usize>, // Segments the stream already consumed; never prefetched again
+  playhead: Option<f64>,
+  reverse: bool, // Playhead moved backwards while playing
+}
+
+impl PreviewStream {
+  /// Timeline span of each segment
+  fn span(&self, index: usize) -> (f64, f64) {
+    let segment = &self.segments[index];
+    (segment.timeline_offset, segment.timeline_offset + (segment.end_time - segment.start_time))
+  }
+
+  /// Segments overlapping the lookahead window, nearest to the playhead first
+  fn window(&self) -> Vec<usize> {
+    let Some(playhead) = self.playhead else {
+      return Vec::new();
+    };
+    let (from, to) = if self.reverse {
+      (playhead - LOOKAHEAD_SECONDS, playhead)
+    } else {
+      (playhead, playhead + LOOKAHEAD_SECONDS)
+    };
+    let mut indices: Vec<usize> = (0..self.segments.len())
+      .filter(|i| {
+        let (start, end) = self.span(*i);
+        start < to && end > from
+      })
+      .collect();
+    if self.reverse {
+      indices.reverse();
+    }
+    indices
+  }
+
+  fn cached_bytes(&self) -> usize {
+    self.cache.values().map(|c| c.bytes).sum()
+  }
+
+  fn evict(&mut self, index: usize) {
+    if let Some(cached) = self.cache.remove(&index) {
+      cached.cancel.store(true, Ordering::Relaxed);
+    }
+  }
+}
+
+struct Streams {
+  streams: Mutex<HashMap<String, PreviewStream>>,
+  changed: Condvar, // Signalled whenever a cached segment progresses, finishes or is evicted
+}
+
+fn streams() -> &'static Streams {
+  static STREAMS: OnceLock<Streams> = OnceLock::new();
+  STREAMS.get_or_init(|| Streams { streams: Mutex::new(HashMap::new()), changed: Condvar::new() })
+}
+
+fn lock() -> MutexGuard<'static, HashMap<String, PreviewStream>> {
+  streams().streams.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Register a stream's segments for prefetching and return its id
+pub fn open_stream(segments: Vec<StreamingSegment>, width: u32) -> String {
+  let id = uuid::Uuid::new_v4().to_string();
+  lock().insert(id.clone(), PreviewStream {
+    segments,
+    width,
+    cache: HashMap::new(),
+    taken: Vec::new(),
+    playhead: None,
+    reverse: false,
+  });
+  id
+}
+
+/// Stop all prefetching for a stream and drop its cache
+pub fn close_stream(stream_id: &str) {
+  if let Some(mut stream) = lock().remove(stream_id) {
+    let indices: Vec<usize> = stream.cache.keys().copied().collect();
+    for index in indices {
+      stream.evict(index);
+    }
+  }
+  streams().changed.notify_all();
+}
+
+/// Record where the playhead is. Segments that fell out of the lookahead window (skipped or
+/// already played) are evicted and their encodes cancelled; upcoming ones start encoding.
+/// Returns false once the stream has finished or closed, so callers can stop hinting
+pub fn set_playhead_hint(stream_id: &str, time: f64, playing: bool) -> bool {
+  let mut guard = lock();
+  let Some(stream) = guard.get_mut(stream_id) else {
+    return false;
+  };
+  if playing {
+    if let Some(previous) = stream.playhead {
+      // Small backwards jitter from the player isn't a direction change
+      if (time - previous).abs() > 0.05 {
+        stream.reverse = time < previous;
+      }
+    }
+  } else {
+    stream.reverse = false;
+  }
+  stream.playhead = Some(time.max(0.0));
+
+  let window = stream.window();
+  let stale: Vec<usize> = stream.cache.keys().copied().filter(|i| !window.contains(i)).collect();
+  for index in stale {
+    stream.evict(index);
+  }
+  schedule(stream_id, stream);
+  drop(guard);
+  streams().changed.notify_all();
+  true
+}
+
+/// Start encodes for window segments that aren't cached yet, within the parallel and byte budgets
+fn schedule(stream_id: &str, stream: &mut PreviewStream) {
+  for index in stream.window() {
+    let running = stream.cache.values().filter(|c| !c.complete && !c.failed).count();
+    if running >= MAX_PARALLEL_ENCODES || stream.cached_bytes() >= MAX_CACHE_BYTES {
+      break;
+    }
+    if stream.cache.contains_key(&index) || stream.taken.contains(&index) {
+      continue;
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    stream.cache.insert(index, CachedSegment {
+      chunks: Vec::new(),
+      bytes: 0,
+      complete: false,
+      failed: false,
+      cancel: cancel.clone(),
+    });
+    let segment = stream.segments[index].clone();
+    let width = stream.width;
+    let stream_id = stream_id.to_string();
+    thread::spawn(move || prefetch_segment(&stream_id, index, &segment, width, cancel));
+  }
+}
+
+/// Encode one segment into the cache. Stops early if evicted or over budget
+fn prefetch_segment(stream_id: &str, index: usize, segment: &StreamingSegment, width: u32, cancel: Arc<AtomicBool>) {
+  let result = streaming_encoder::encode_segment_streaming(
+    &segment.media_path,
+    segment.start_time,
+    segment.end_time,
+    width,
+    segment.pan,
+  );
+  let (rx, handle) = match result {
+    Ok(started) => started,
+    Err(e) => {
+      log::warn!("Prefetch of segment {} failed to start: {}", index, e);
+      finish(stream_id, index, &cancel, true);
+      return;
+    }
+  };
+
+  for chunk in rx.iter() {
+    if cancel.load(Ordering::Relaxed) {
+      // Dropping the receiver stops ffmpeg
+      return;
+    }
+    let mut guard = lock();
+    let Some(stream) = guard.get_mut(stream_id) else {
+      return;
+    };
+    if stream.cached_bytes() + chunk.len() > MAX_CACHE_BYTES {
+      log::warn!("Prefetch budget exceeded, dropping segment {}", index);
+      stream.evict(index);
+      drop(guard);
+      streams().changed.notify_all();
+      return;
+    }
+    let Some(cached) = stream.cache.get_mut(&index) else {
+      return;
+    };
+    cached.bytes += chunk.len();
+    cached.chunks.push(chunk);
+  }
+
+  let failed = match handle.join() {
+    Ok(Ok(())) => false,
+    Ok(Err(e)) => {
+      log::warn!("Prefetch of segment {} failed: {}", index, e);
+      true
+    }
+    Err(_) => true,
+  };
+  finish(stream_id, index, &cancel, failed || cancel.load(Ordering::Relaxed));
+}
+
+fn finish(stream_id: &str, index: usize, cancel: &Arc<AtomicBool>, failed: bool) {
+  let mut guard = lock();
+  if let Some(stream) = guard.get_mut(stream_id) {
+    // The entry may have been evicted and re-queued since this encode started
+    if let Some(cached) = stream.cache.get_mut(&index).filter(|c| Arc::ptr_eq(&c.cancel, cancel)) {
+      cached.complete = !failed;
+      cached.failed = failed;
+    }
+    // A slot is free; keep filling the window
+    schedule(stream_id, stream);
+  }
+  drop(guard);
+  streams().changed.notify_all();
+}
+
+/// Take a prefetched segment for the stream. Waits if it is still encoding; returns None if
+/// it isn't prefetched (or failed), in which case the caller encodes it itself
+pub fn take_segment(stream_id: &str, index: usize) -> Option<Vec<String>> {
+  let mut guard = lock();
+  loop {
+    let stream = guard.get_mut(stream_id)?;
+    match stream.cache.get(&index) {
+      None => {
+        stream.taken.push(index);
+        return None;
+      }
+      Some(cached) if cached.complete => {
+        stream.taken.push(index);
+        return stream.cache.remove(&index).map(|c| c.chunks);
+      }
+      Some(cached) if cached.failed => {
+        stream.taken.push(index);
+        stream.cache.remove(&index);
+        return None;
+      }
+      Some(_) => {
+        guard = streams().changed.wait(guard).unwrap_or_else(|e| e.into_inner());
+      }
+    }
+  }
+}
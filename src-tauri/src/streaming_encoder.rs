@@ -1,10 +1,12 @@
 use anyhow::{anyhow, Context, Result};
 use serde::{Serialize, Deserialize};
 use std::io::{BufReader, Read};
-use std::process::{Command, Stdio};
-use std::sync::mpsc::{channel, Receiver};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 use base64::Engine;
+use crate::ffmpeg;
+use crate::stream_prefetch;
 
 /// Check if ffmpeg exists
 fn ffmpeg_exists() -> bool {
@@ -18,6 +20,8 @@ pub struct StreamingSegment {
   pub start_time: f64,
   pub end_time: f64,
   pub timeline_offset: f64,
+  #[serde(default)]
+  pub pan: i8, // Pan of the segment's track
 }
 
 /// Encode a segment to fragmented MP4 and return base64 chunks as they're produced
@@ -26,6 +30,7 @@ pub fn encode_segment_streaming(
   start_time: f64,
   end_time: f64,
   width: u32,
+  pan: i8,
 ) -> Result<(Receiver<String>, thread::JoinHandle<Result<()>>)> {
   if !ffmpeg_exists() {
     return Err(anyhow!("ffmpeg/ffprobe not found on PATH"));
@@ -43,72 +48,23 @@ pub fn encode_segment_streaming(
   
   // Spawn encoding thread
   let handle = thread::spawn(move || -> Result<()> {
-    let mut child = Command::new("ffmpeg")
-      .args([
-        "-v", "error",
-        "-ss", &start_time.to_string(),
-        "-t", &duration.to_string(),
-        "-i", &media_path,
-        "-vf", &format!("scale='min({},iw)':-2", width),
-        "-c:v", "libx264",
-        "-preset", "ultrafast",
-        "-tune", "zerolatency",  // Optimize for low latency streaming
-        "-crf", "26",
-        "-g", "15",  // Keyframe every 15 frames for better seeking
-        "-pix_fmt", "yuv420p",
-        "-c:a", "aac",
-        "-b:a", "128k",
-        // Fragmented MP4 for streaming (compatible with MSE)
-        "-movflags", "frag_keyframe+empty_moov+default_base_moof",
-        "-frag_duration", "500000", // 500ms fragments
-        "-f", "mp4",
-        "pipe:1", // Output to stdout
-      ])
-      .stdout(Stdio::piped())
-      .stderr(Stdio::piped())
-      .spawn()
-      .with_context(|| "failed to spawn ffmpeg for streaming")?;
-
-    let stdout = child.stdout.take().ok_or_else(|| anyhow!("failed to capture stdout"))?;
-    let mut reader = BufReader::new(stdout);
-    
-    // Stream chunks as they're produced
-    let mut buffer = vec![0u8; 64 * 1024]; // 64KB chunks
-    let mut chunk_count = 0;
-    
-    loop {
-      match reader.read(&mut buffer) {
-        Ok(0) => {
-          // EOF
-          eprintln!("Streaming complete, sent {} chunks", chunk_count);
-          break;
-        }
-        Ok(n) => {
-          // Encode chunk to base64 and send
-          let chunk = buffer[..n].to_vec();
-          let base64_chunk = base64::engine::general_purpose::STANDARD.encode(&chunk);
-          
-          if tx.send(base64_chunk).is_err() {
-            // Receiver dropped, stop encoding
-            eprintln!("Receiver dropped, stopping encoding");
-            let _ = child.kill();
-            break;
-          }
-          
-          chunk_count += 1;
-          if chunk_count % 10 == 0 {
-            eprintln!("Streamed {} chunks...", chunk_count);
-          }
-        }
-        Err(e) => {
-          eprintln!("Error reading ffmpeg output: {}", e);
-          break;
-        }
-      }
-    }
-
-    let output = child.wait_with_output()
-      .with_context(|| "failed to wait for ffmpeg")?;
+    let hwaccel = ffmpeg::preferred_hwaccel();
+    let (chunk_count, output) =
+      stream_fragments(&media_path, start_time, duration, width, pan, &ffmpeg::hwaccel_args(hwaccel.as_deref()), &tx)?;
+
+    // Hardware init fails before any output; retry in software only if nothing was sent yet
+    let output = if hwaccel.is_some()
+      && chunk_count == 0
+      && !output.status.success()
+      && ffmpeg::is_hwaccel_error(&String::from_utf8_lossy(&output.stderr))
+    {
+      eprintln!("Hardware decode ({:?}) failed, retrying in software", hwaccel);
+      ffmpeg::record_decode_path("streaming_preview", None, true);
+      stream_fragments(&media_path, start_time, duration, width, pan, &[], &tx)?.1
+    } else {
+      ffmpeg::record_decode_path("streaming_preview", hwaccel.as_deref(), false);
+      output
+    };
 
     if !output.status.success() {
       let stderr = String::from_utf8_lossy(&output.stderr);
@@ -123,8 +79,106 @@ pub fn encode_segment_streaming(
   Ok((rx, handle))
 }
 
-/// Generate streaming preview for multiple segments
+/// Run one ffmpeg fragmented-MP4 encode, forwarding base64 chunks to `tx`.
+/// Returns the number of chunks sent and the finished process output.
+fn stream_fragments(
+  media_path: &str,
+  start_time: f64,
+  duration: f64,
+  width: u32,
+  pan: i8,
+  hwaccel: &[String],
+  tx: &Sender<String>,
+) -> Result<(usize, Output)> {
+  let audio_filter: Vec<String> =
+    ffmpeg::preview_audio(media_path, pan).map(|f| vec!["-af".to_string(), f]).unwrap_or_default();
+  // Audio files stream just the audio; there's no video to decode or scale
+  let audio_only = ffmpeg::ffprobe(media_path).is_ok_and(|probe| probe.is_audio_only());
+  let video_args: Vec<String> = if audio_only {
+    vec!["-vn".to_string()]
+  } else {
+    [
+      "-vf", &format!("scale='min({},iw)':-2{}", width, ffmpeg::preview_color(media_path)),
+      "-c:v", "libx264",
+      "-preset", "ultrafast",
+      "-tune", "zerolatency",  // Optimize for low latency streaming
+      "-crf", "26",
+      "-g", "15",  // Keyframe every 15 frames for better seeking
+      "-pix_fmt", "yuv420p",
+    ].iter().map(|a| a.to_string()).collect()
+  };
+  let hwaccel: &[String] = if audio_only { &[] } else { hwaccel };
+  let mut child = Command::new("ffmpeg")
+    .args(["-v", "error"])
+    .args(hwaccel)
+    .args([
+      "-ss", &start_time.to_string(),
+      "-t", &duration.to_string(),
+      "-i", media_path,
+    ])
+    .args(&video_args)
+    .args(&audio_filter)
+    .args([
+      "-c:a", "aac",
+      "-b:a", "128k",
+      // Fragmented MP4 for streaming (compatible with MSE)
+      "-movflags", "frag_keyframe+empty_moov+default_base_moof",
+      "-frag_duration", "500000", // 500ms fragments
+      "-f", "mp4",
+      "pipe:1", // Output to stdout
+    ])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .with_context(|| "failed to spawn ffmpeg for streaming")?;
+
+  let stdout = child.stdout.take().ok_or_else(|| anyhow!("failed to capture stdout"))?;
+  let mut reader = BufReader::new(stdout);
+  
+  // Stream chunks as they're produced
+  let mut buffer = vec![0u8; 64 * 1024]; // 64KB chunks
+  let mut chunk_count = 0;
+  
+  loop {
+    match reader.read(&mut buffer) {
+      Ok(0) => {
+        // EOF
+        eprintln!("Streaming complete, sent {} chunks", chunk_count);
+        break;
+      }
+      Ok(n) => {
+        // Encode chunk to base64 and send
+        let chunk = buffer[..n].to_vec();
+        let base64_chunk = base64::engine::general_purpose::STANDARD.encode(&chunk);
+        
+        if tx.send(base64_chunk).is_err() {
+          // Receiver dropped, stop encoding
+          eprintln!("Receiver dropped, stopping encoding");
+          let _ = child.kill();
+          break;
+        }
+        
+        chunk_count += 1;
+        if chunk_count % 10 == 0 {
+          eprintln!("Streamed {} chunks...", chunk_count);
+        }
+      }
+      Err(e) => {
+        eprintln!("Error reading ffmpeg output: {}", e);
+        break;
+      }
+    }
+  }
+
+  let output = child.wait_with_output()
+    .with_context(|| "failed to wait for ffmpeg")?;
+  Ok((chunk_count, output))
+}
+
+/// Generate streaming preview for multiple segments.
+/// Segments the prefetcher already encoded for `stream_id` are sent from its cache.
 pub fn generate_streaming_preview(
+  stream_id: String,
   segments: Vec<StreamingSegment>,
   width: u32,
 ) -> Result<(Receiver<String>, thread::JoinHandle<Result<()>>)> {
@@ -136,6 +190,17 @@ pub fn generate_streaming_preview(
   
   let handle = thread::spawn(move || -> Result<()> {
     for (i, segment) in segments.iter().enumerate() {
+      if let Some(chunks) = stream_prefetch::take_segment(&stream_id, i) {
+        eprintln!("Segment {}/{} served from prefetch ({} chunks)", i + 1, segments.len(), chunks.len());
+        for chunk in chunks {
+          if tx.send(chunk).is_err() {
+            eprintln!("Receiver dropped, stopping multi-segment encoding");
+            return Ok(());
+          }
+        }
+        continue;
+      }
+
       eprintln!("Encoding segment {}/{}: {}s to {}s", 
         i + 1, segments.len(), segment.start_time, segment.end_time);
       
@@ -144,6 +209,7 @@ pub fn generate_streaming_preview(
         segment.start_time,
         segment.end_time,
         width,
+        segment.pan,
       )?;
 
       // Forward chunks from this segment
@@ -0,0 +1,210 @@
+//! Disk cache for media tile images (album art, waveform posters) and timeline thumbnail strips,
+//! keyed by the source file's path, size and modification time so an edited file gets a fresh
+//! entry. Hits refresh an entry's modification time, and the cache is trimmed to
+//! `AppSettings::thumbnail_cache_mb` by removing the least recently used entries.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::ffmpeg::{self, AudioMetadata};
+use crate::longterm_storage;
+use crate::media_protocol::{self, MediaScope};
+use crate::project_file;
+use crate::work_dirs::{self, WorkDir};
+
+fn cache_dir() -> Result<PathBuf> {
+  let dir = work_dirs::path(WorkDir::Cache)?.join("thumbnails");
+  fs::create_dir_all(&dir).with_context(|| format!("Failed to create thumbnail cache at {:?}", dir))?;
+  Ok(dir)
+}
+
+/// Cache size cap without a `thumbnail_cache_mb` setting
+const DEFAULT_CAP_MB: u64 = 500;
+
+/// Mark a cache entry as just used, so trimming keeps it
+fn touch(entry: &Path) {
+  if let Ok(file) = fs::File::options().write(true).open(entry) {
+    let _ = file.set_modified(SystemTime::now());
+  }
+}
+
+/// Remove the least recently used entries until the cache fits its size cap
+fn trim(dir: &Path) {
+  let cap = longterm_storage::get_settings().ok().and_then(|s| s.thumbnail_cache_mb).unwrap_or(DEFAULT_CAP_MB) * 1024 * 1024;
+  let Ok(entries) = fs::read_dir(dir) else { return };
+  let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+    .filter_map(|e| e.ok())
+    .filter_map(|e| {
+      let metadata = e.metadata().ok().filter(|m| m.is_file())?;
+      Some((metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len(), e.path()))
+    })
+    .collect();
+  let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+  if total <= cap {
+    return;
+  }
+  files.sort_by_key(|(used, _, _)| *used);
+  for (_, size, path) in files {
+    if total <= cap {
+      break;
+    }
+    if fs::remove_file(&path).is_ok() {
+      total -= size;
+    }
+  }
+}
+
+/// Delete every cached image and thumbnail strip. Returns the bytes freed
+pub fn clear() -> Result<u64> {
+  let dir = cache_dir()?;
+  let mut freed = 0;
+  for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {:?}", dir))? {
+    let entry = entry?;
+    let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+    if entry.path().is_file() && fs::remove_file(entry.path()).is_ok() {
+      freed += size;
+    }
+  }
+  Ok(freed)
+}
+
+fn cache_key(source: &Path, kind: &str) -> Result<String> {
+  let metadata = fs::metadata(source).with_context(|| format!("failed to read {:?}", source))?;
+  let modified = metadata.modified()
+    .ok()
+    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  let path = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+  let mut hasher = Sha256::new();
+  hasher.update(path.to_string_lossy().as_bytes());
+  hasher.update(metadata.len().to_le_bytes());
+  hasher.update(modified.to_le_bytes());
+  hasher.update(kind.as_bytes());
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Cached PNG of `kind` for `source`, generating it with `generate` on a miss.
+/// A `None` result is cached too (as an empty marker) so files without an image
+/// aren't re-processed every time
+fn get_or_generate(source: &Path, kind: &str, generate: impl FnOnce() -> Result<Option<Vec<u8>>>) -> Result<Option<Vec<u8>>> {
+  let key = cache_key(source, kind)?;
+  let dir = cache_dir()?;
+  let image = dir.join(format!("{}.png", key));
+  let marker = dir.join(format!("{}.none", key));
+  if image.is_file() {
+    touch(&image);
+    return Ok(Some(fs::read(&image)?));
+  }
+  if marker.is_file() {
+    return Ok(None);
+  }
+
+  let generated = generate()?;
+  let written = match &generated {
+    Some(png) => fs::write(&image, png),
+    None => fs::write(&marker, []),
+  };
+  match written {
+    Ok(()) => trim(&dir),
+    Err(e) => log::warn!("Failed to cache {} for {:?}: {}", kind, source, e),
+  }
+  Ok(generated)
+}
+
+/// `count` thumbnails of `path`, `width` wide, as base64 PNGs (see ffmpeg::generate_thumbnails),
+/// from the cache when possible. A hit only reads one file. Audio files give their poster
+pub fn get_or_generate_thumbnails(path: &str, count: usize, width: u32) -> Result<Vec<String>> {
+  // A preview LUT changes how the frames look, so it is part of the key
+  let lut = project_file::preview_lut_for_path(Path::new(path)).map(|l| l.to_string_lossy().to_string()).unwrap_or_default();
+  let key = cache_key(Path::new(path), &format!("thumbnails:{}:{}:{}", count, width, lut))?;
+  let dir = cache_dir()?;
+  let strip = dir.join(format!("{}.json", key));
+  if let Some(thumbnails) = fs::read(&strip).ok().and_then(|json| serde_json::from_slice(&json).ok()) {
+    touch(&strip);
+    return Ok(thumbnails);
+  }
+
+  let thumbnails = ffmpeg::generate_thumbnails(path, count, width)?;
+  // Fewer than asked usually means ffmpeg stopped early; don't keep that
+  if thumbnails.len() == count {
+    match serde_json::to_vec(&thumbnails).map_err(anyhow::Error::from).and_then(|json| Ok(fs::write(&strip, json)?)) {
+      Ok(()) => trim(&dir),
+      Err(e) => log::warn!("Failed to cache thumbnails for {}: {}", path, e),
+    }
+  }
+  Ok(thumbnails)
+}
+
+/// Path of the cached PNG of `kind` for `source`, without generating anything.
+/// None on a miss, or when the cache recorded that the file has no such image
+fn cached_image(source: &Path, kind: &str) -> Option<PathBuf> {
+  let image = cache_dir().ok()?.join(format!("{}.png", cache_key(source, kind).ok()?));
+  image.is_file().then_some(image)
+}
+
+/// Embedded album art as base64 PNG, from the cache when possible
+pub fn album_art(path: &str) -> Result<Option<String>> {
+  let engine = base64::engine::general_purpose::STANDARD;
+  let art = get_or_generate(Path::new(path), "album_art", || {
+    ffmpeg::extract_album_art(path)?
+      .map(|b64| engine.decode(b64).context("album art is not valid base64"))
+      .transpose()
+  })?;
+  Ok(art.map(|png| engine.encode(png)))
+}
+
+/// Cached album art PNG of `path`, if `album_art` already extracted it
+pub fn cached_album_art(path: &Path) -> Option<PathBuf> {
+  cached_image(path, "album_art")
+}
+
+/// Album art of `path` extracted into the cache, for clips to point at. None when it has none
+pub fn album_art_file(path: &Path) -> Result<Option<PathBuf>> {
+  album_art(&path.to_string_lossy())?;
+  Ok(cached_album_art(path))
+}
+
+/// Media URL of the cached album art of `path`, extracting it on a miss. The webview loads
+/// it by URL rather than as base64 through IPC
+pub fn album_art_url(path: &str) -> Result<Option<String>> {
+  Ok(album_art_file(Path::new(path))?.map(|art| media_protocol::url_for(&art, MediaScope::Session)))
+}
+
+/// Size of generated waveform posters; the UI scales them like any thumbnail
+const WAVEFORM_POSTER_SIZE: (u32, u32) = (640, 360);
+
+/// Poster of an audio file as base64 PNG: its album art, else a waveform picture.
+/// Audio-only projects use it wherever a video would show a frame
+pub fn poster(path: &str) -> Result<Option<String>> {
+  if let Some(art) = album_art(path)? {
+    return Ok(Some(art));
+  }
+  let (width, height) = WAVEFORM_POSTER_SIZE;
+  let waveform = get_or_generate(Path::new(path), "waveform", || ffmpeg::waveform_image(path, None, width, height).map(Some))?;
+  Ok(waveform.map(|png| base64::engine::general_purpose::STANDARD.encode(png)))
+}
+
+/// Cached poster PNG of `path`, if `poster` or `album_art` already made one
+pub fn cached_poster(path: &Path) -> Option<PathBuf> {
+  cached_album_art(path).or_else(|| cached_image(path, "waveform"))
+}
+
+/// Media URL of the cached poster of `path` (see `poster`), generating it on a miss
+pub fn poster_url(path: &str) -> Result<Option<String>> {
+  poster(path)?;
+  Ok(cached_poster(Path::new(path)).map(|poster| media_protocol::url_for(&poster, MediaScope::Session)))
+}
+
+/// Tags and album art URL of a music file. A file without art still returns its tags
+pub fn audio_metadata(path: &str) -> Result<AudioMetadata> {
+  let mut metadata = ffmpeg::audio_tags(path)?;
+  metadata.art = album_art_url(path).unwrap_or_else(|e| {
+    log::warn!("Album art extraction failed for {}: {}", path, e);
+    None
+  });
+  Ok(metadata)
+}
@@ -0,0 +1,278 @@
+//! Time-stretch audio to a target length, e.g. to fit a 34 s voiceover into a 30 s slot.
+//! Pitch is kept with ffmpeg's rubberband filter when the build has it, else with chained
+//! atempo; without pitch preservation the audio is resampled like a tape played faster.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::process::Command;
+use std::sync::OnceLock;
+use crate::bake::{self, BakeSettings};
+use crate::ffmpeg;
+use crate::ids;
+use crate::jobs::JobContext;
+use crate::media_hash;
+use crate::media_protocol::{self, MediaScope};
+use crate::project_file::{self, Clip, ClipType, ProjectFile, Segment, TrackType};
+use crate::transcription::TranscriptSegment;
+
+/// Stretches beyond these tempos sound broken however they're rendered
+const MIN_TEMPO: f64 = 0.5;
+const MAX_TEMPO: f64 = 2.0;
+/// Voice and video lengths closer than this are considered to fit
+const FIT_TOLERANCE: f64 = 0.25;
+/// Pairs whose lengths differ by more than this ratio are probably not meant to match
+const MAX_FIT_RATIO: f64 = 1.35;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StretchMethod {
+  Rubberband, // Pitch kept, best quality
+  Atempo,     // Pitch kept, ffmpeg's built-in filter
+  Resample,   // Pitch follows the speed
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StretchResult {
+  pub clip: Clip,
+  pub tempo: f64, // Source length / target length; above 1 speeds up
+  pub method: StretchMethod,
+  pub requested_duration: f64,
+  pub achieved_duration: f64, // Length of the rendered file
+}
+
+/// Whether this ffmpeg build has the rubberband filter (checked once per run)
+fn has_rubberband() -> bool {
+  static AVAILABLE: OnceLock<bool> = OnceLock::new();
+  *AVAILABLE.get_or_init(|| {
+    Command::new("ffmpeg")
+      .args(["-hide_banner", "-filters"])
+      .output()
+      .map(|out| String::from_utf8_lossy(&out.stdout).lines().any(|l| l.split_whitespace().nth(1) == Some("rubberband")))
+      .unwrap_or(false)
+  })
+}
+
+/// Audio filter changing the tempo of `sample_rate` audio by `tempo`
+fn stretch_filter(tempo: f64, preserve_pitch: bool, sample_rate: u32) -> (String, StretchMethod) {
+  if !preserve_pitch {
+    let rate = (sample_rate as f64 * tempo).round();
+    return (format!("asetrate={},aresample={}", rate, sample_rate), StretchMethod::Resample);
+  }
+  if has_rubberband() {
+    return (format!("rubberband=tempo={}", tempo), StretchMethod::Rubberband);
+  }
+  (bake::atempo_chain(tempo).join(","), StretchMethod::Atempo)
+}
+
+/// `transcript` lines inside `range`, moved to start at 0 and scaled by `1 / tempo`
+fn stretch_transcript(transcript: &[TranscriptSegment], range: (f64, f64), tempo: f64) -> Vec<TranscriptSegment> {
+  let map = |t: f64| ((t - range.0) / tempo).max(0.0);
+  transcript
+    .iter()
+    .filter(|s| s.end > range.0 && s.start < range.1)
+    .map(|s| {
+      let mut s = s.clone();
+      s.start = map(s.start);
+      s.end = map(s.end.min(range.1));
+      s.words.retain(|w| w.start >= range.0 && w.start < range.1);
+      for word in &mut s.words {
+        word.start = map(word.start);
+        word.end = map(word.end.min(range.1));
+      }
+      s
+    })
+    .collect()
+}
+
+/// Render `range` of `source` stretched to `target_duration` into the project's media folder
+/// and return it as a new audio clip (not yet added to the project)
+fn render(
+  project: &ProjectFile,
+  source: &Clip,
+  range: (f64, f64),
+  target_duration: f64,
+  preserve_pitch: bool,
+  job: &JobContext,
+) -> Result<StretchResult> {
+  let (start, end) = range;
+  if !target_duration.is_finite() || target_duration <= 0.0 || end <= start {
+    return Err(anyhow!("need a positive range and target duration"));
+  }
+  let tempo = (end - start) / target_duration;
+  if !(MIN_TEMPO..=MAX_TEMPO).contains(&tempo) {
+    return Err(anyhow!(
+      "fitting {:.2}s into {:.2}s needs a {:.2}x stretch; only {}x to {}x sounds acceptable",
+      end - start, target_duration, tempo, MIN_TEMPO, MAX_TEMPO
+    ));
+  }
+
+  let input = source.path.to_string_lossy().to_string();
+  let probe = ffmpeg::ffprobe(&input).context("ffprobe failed")?;
+  if probe.audio_rate == 0 {
+    return Err(anyhow!("{} has no audio", input));
+  }
+  let (filter, method) = stretch_filter(tempo, preserve_pitch, probe.audio_rate);
+
+  let media_dir = project.media_dir()?;
+  fs::create_dir_all(&media_dir).with_context(|| format!("failed to create {:?}", media_dir))?;
+  let clip_id = ids::new_id(ids::CLIP);
+  let stem = source.path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+  let output = media_dir.join(format!("stretched_{}_{}.m4a", stem, &clip_id[clip_id.len() - 8..]));
+  let tmp = ffmpeg::temp_output_path(&output);
+
+  job.progress(0.0, Some(format!("Stretching {:.2}s to {:.2}s", end - start, target_duration).as_str()));
+  let settings = BakeSettings::default();
+  let mut cmd = Command::new("ffmpeg");
+  cmd.args(["-v", "error", "-nostats", "-progress", "pipe:1"])
+    .args(["-ss", &start.to_string(), "-t", &(end - start).to_string(), "-i", &input])
+    .args(["-map", &format!("0:a:{}", source.audio_stream_index.unwrap_or(probe.audio_stream))])
+    .args(["-vn", "-af", &filter, "-c:a", "aac", "-b:a", &settings.audio_bitrate, "-f", "mp4", "-y"])
+    .arg(&tmp);
+  let result = ffmpeg::output_with_progress(&mut cmd, target_duration, &mut |fraction| job.progress(fraction * 0.95, None))
+    .with_context(|| "ffmpeg time stretch failed to run")?;
+  if !result.status.success() {
+    let _ = fs::remove_file(&tmp);
+    return Err(anyhow!(
+      "ffmpeg time stretch failed (status {:?}): {}",
+      result.status.code(),
+      String::from_utf8_lossy(&result.stderr)
+    ));
+  }
+  fs::rename(&tmp, &output).with_context(|| "failed to move tmp output into place")?;
+
+  job.progress(0.95, Some("Verifying output"));
+  let stretched_probe = ffmpeg::ffprobe(&output.to_string_lossy()).context("ffprobe of stretched output failed")?;
+  let achieved_duration = stretched_probe.duration;
+  if (achieved_duration - target_duration).abs() > 0.05 {
+    log::info!("Stretched {} to {:.3}s (asked for {:.3}s)", input, achieved_duration, target_duration);
+  }
+
+  let clip = Clip {
+    id: clip_id,
+    path: output.clone(),
+    latest_probe: Some(stretched_probe),
+    r#type: ClipType::Audio,
+    transcript: source.transcript.as_deref().map(|t| stretch_transcript(t, range, tempo)),
+    derived_from: Some(source.id.clone()),
+    hashes: media_hash::ingest_hashes(&output).ok(),
+    preview_lut: None,
+    stream_hashes: None,
+    creation_time: None,
+    ready_for_ai: None,
+    transcript_language: source.transcript_language.clone(),
+    language_override: source.language_override.clone(),
+    has_album_art: source.has_album_art,
+    audio_stream_index: None, // The output has only the stream the source played
+  };
+  Ok(StretchResult { clip, tempo, method, requested_duration: target_duration, achieved_duration })
+}
+
+/// Stretch `range` (default: all) of clip `clip_id` to `target_duration` seconds and add the
+/// result as an audio clip derived from it
+pub fn time_stretch_clip(
+  clip_id: &str,
+  range: Option<(f64, f64)>,
+  target_duration: f64,
+  preserve_pitch: bool,
+  job: &JobContext,
+) -> Result<StretchResult> {
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let source = project.clips_map.get(clip_id).ok_or_else(|| anyhow!("clip not found: {}", clip_id))?;
+  let range = match range {
+    Some(range) => range,
+    None => {
+      let probe = ffmpeg::ffprobe(&source.path.to_string_lossy()).context("ffprobe failed")?;
+      (0.0, probe.duration)
+    }
+  };
+
+  let mut result = render(&project, source, range, target_duration, preserve_pitch, job)?;
+  let output = result.clip.path.clone();
+  result.clip = project_file::add_clip(result.clip).inspect_err(|_| {
+    let _ = fs::remove_file(&output);
+  })?;
+  media_protocol::register(&output, MediaScope::Project);
+  Ok(result)
+}
+
+/// Stretch the audio of a segment on an audio track so it plays for `target_duration`, and
+/// point the segment at the result. `project_file::unbake_segment` restores the original
+pub fn stretch_segment_to_fit(
+  track_id: &str,
+  segment_id: &str,
+  target_duration: f64,
+  preserve_pitch: bool,
+  job: &JobContext,
+) -> Result<(Segment, StretchResult)> {
+  let project = project_file::get_project()
+    .map_err(|e| anyhow!(e))?
+    .ok_or_else(|| anyhow!("no project is currently loaded"))?;
+  let track = project.tracks_map.get(track_id).ok_or_else(|| anyhow!("track not found: {}", track_id))?;
+  if track.r#type != TrackType::Audio {
+    return Err(anyhow!("only segments on audio tracks can be stretched to fit"));
+  }
+  let segment = project.segment(track_id, segment_id)?.clone();
+  let source = project
+    .clips_map
+    .get(&segment.clip_id)
+    .ok_or_else(|| anyhow!("clip not found: {}", segment.clip_id))?;
+
+  let result = render(&project, source, (segment.start, segment.end), target_duration, preserve_pitch, job)?;
+  let output = result.clip.path.clone();
+  // Timed by the request rather than the probe, so the segments after it land where expected
+  let stretched = project_file::swap_in_stretched_segment(track_id, segment_id, &segment, result.clip.clone(), target_duration)
+    .inspect_err(|_| {
+      let _ = fs::remove_file(&output);
+    })?;
+  media_protocol::register(&output, MediaScope::Project);
+  Ok((stretched, result))
+}
+
+/// A voice segment whose length doesn't match the video segment playing under it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FitMismatch {
+  pub track_id: String,
+  pub segment_id: String,
+  pub label: Option<String>,
+  pub voice_duration: f64,
+  pub slot_duration: f64, // Length of the overlapped video segment
+  pub timeline_start: f64,
+}
+
+/// Segments on audio tracks paired with a video segment (the one they overlap most) whose
+/// lengths differ by more than `FIT_TOLERANCE` but not so much they're clearly unrelated
+pub fn find_fit_mismatches(project: &ProjectFile) -> Vec<FitMismatch> {
+  let video_spans: Vec<(f64, f64)> = project
+    .ordered_tracks()
+    .into_iter()
+    .filter(|t| t.r#type == TrackType::Video && t.enabled)
+    .flat_map(|t| t.timeline_spans())
+    .collect();
+
+  let mut mismatches = Vec::new();
+  for track in project.ordered_tracks().into_iter().filter(|t| t.r#type == TrackType::Audio && t.enabled && !t.muted) {
+    for (segment, (start, end)) in track.segments.iter().zip(track.timeline_spans()) {
+      let overlap = |span: &(f64, f64)| (end.min(span.1) - start.max(span.0)).max(0.0);
+      let Some(slot) = video_spans.iter().filter(|span| overlap(span) > 0.0).max_by(|a, b| overlap(a).total_cmp(&overlap(b))) else {
+        continue;
+      };
+      let voice = end - start;
+      let slot_duration = slot.1 - slot.0;
+      let ratio = voice.max(slot_duration) / voice.min(slot_duration).max(f64::EPSILON);
+      if (voice - slot_duration).abs() > FIT_TOLERANCE && ratio <= MAX_FIT_RATIO {
+        mismatches.push(FitMismatch {
+          track_id: track.id.clone(),
+          segment_id: segment.id.clone(),
+          label: segment.label.clone(),
+          voice_duration: voice,
+          slot_duration,
+          timeline_start: start,
+        });
+      }
+    }
+  }
+  mismatches
+}
@@ -0,0 +1,232 @@
+//! Rasterizes the timeline to a PNG without the webview, for sharing edit state.
+//! Draws a time ruler, one lane per track (ordered), segments, accepted cuts and markers.
+//! There is no bundled font, so the image carries no text.
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use image::{ImageFormat, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_polygon_mut};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
+use std::io::Cursor;
+use crate::project_file::{ProjectFile, Track, TrackType};
+
+const MAX_DIMENSION: u32 = 8192;
+const RULER_HEIGHT: u32 = 18;
+const LANE_GAP: u32 = 4;
+/// Major ticks are at least this many pixels apart.
+const MIN_TICK_PX: f64 = 80.0;
+/// Candidate major tick spacings in seconds, smallest first.
+const TICK_STEPS: &[f64] = &[
+  0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0,
+];
+
+const BACKGROUND: Rgba<u8> = Rgba([24, 24, 27, 255]);
+const RULER_BACKGROUND: Rgba<u8> = Rgba([39, 39, 42, 255]);
+const LANE_BACKGROUND: Rgba<u8> = Rgba([32, 32, 36, 255]);
+const TICK_MAJOR: Rgba<u8> = Rgba([161, 161, 170, 255]);
+const TICK_MINOR: Rgba<u8> = Rgba([82, 82, 91, 255]);
+const GRID_LINE: Rgba<u8> = Rgba([45, 45, 50, 255]);
+const CUT_OVERLAY: Rgba<u8> = Rgba([239, 68, 68, 115]);
+const MARKER_DEFAULT: Rgba<u8> = Rgba([250, 204, 21, 255]);
+/// Segments with a label get a color from this palette, picked by hashing the label.
+const LABEL_PALETTE: &[Rgba<u8>] = &[
+  Rgba([236, 72, 153, 255]),
+  Rgba([249, 115, 22, 255]),
+  Rgba([20, 184, 166, 255]),
+  Rgba([132, 204, 22, 255]),
+  Rgba([99, 102, 241, 255]),
+  Rgba([6, 182, 212, 255]),
+  Rgba([244, 63, 94, 255]),
+  Rgba([217, 70, 239, 255]),
+];
+
+fn track_color(track_type: &TrackType) -> Rgba<u8> {
+  match track_type {
+    TrackType::Video => Rgba([59, 130, 246, 255]),
+    TrackType::Audio => Rgba([34, 197, 94, 255]),
+    TrackType::Text => Rgba([234, 179, 8, 255]),
+    TrackType::Effect => Rgba([168, 85, 247, 255]),
+  }
+}
+
+fn label_color(label: &str) -> Rgba<u8> {
+  // FNV-1a, so colors are stable across runs and platforms
+  let hash = label.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+  LABEL_PALETTE[(hash % LABEL_PALETTE.len() as u64) as usize]
+}
+
+fn dim(color: Rgba<u8>) -> Rgba<u8> {
+  let [r, g, b, a] = color.0;
+  Rgba([r / 3 + 20, g / 3 + 20, b / 3 + 20, a])
+}
+
+/// "#rrggbb" → color; anything else → None.
+fn parse_hex_color(hex: &str) -> Option<Rgba<u8>> {
+  let hex = hex.trim().strip_prefix('#')?;
+  if hex.len() != 6 {
+    return None;
+  }
+  let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+  Some(Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+/// Alpha-blend a color over a rectangle (imageproc's fills overwrite instead of blending).
+fn blend_rect(img: &mut RgbaImage, x0: u32, y0: u32, x1: u32, y1: u32, color: Rgba<u8>) {
+  let alpha = color.0[3] as u32;
+  for y in y0..y1.min(img.height()) {
+    for x in x0..x1.min(img.width()) {
+      let px = img.get_pixel_mut(x, y);
+      for c in 0..3 {
+        px.0[c] = ((color.0[c] as u32 * alpha + px.0[c] as u32 * (255 - alpha)) / 255) as u8;
+      }
+    }
+  }
+}
+
+/// Pick major/minor tick spacing so ticks stay readable from seconds-long to multi-hour timelines.
+fn tick_spacing(duration: f64, width: u32) -> (f64, f64) {
+  let px_per_second = width as f64 / duration;
+  let major = TICK_STEPS
+    .iter()
+    .copied()
+    .find(|step| step * px_per_second >= MIN_TICK_PX)
+    .unwrap_or_else(|| (duration / 4.0 / 3600.0).ceil() * 3600.0);
+  // Quarter-hour style steps divide into thirds, everything else into fifths
+  let divisions = if major == 15.0 || major == 900.0 { 3.0 } else { 5.0 };
+  (major, major / divisions)
+}
+
+struct Canvas {
+  img: RgbaImage,
+  duration: f64,
+}
+
+impl Canvas {
+  fn x(&self, time: f64) -> i32 {
+    let w = (self.img.width() - 1) as f64;
+    ((time / self.duration).clamp(0.0, 1.0) * w).round() as i32
+  }
+
+  fn fill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Rgba<u8>) {
+    let (w, h) = ((x1 - x0).max(1) as u32, (y1 - y0).max(1) as u32);
+    draw_filled_rect_mut(&mut self.img, Rect::at(x0, y0).of_size(w, h), color);
+  }
+
+  fn vline(&mut self, x: i32, y0: u32, y1: u32, color: Rgba<u8>) {
+    draw_line_segment_mut(&mut self.img, (x as f32, y0 as f32), (x as f32, y1 as f32), color);
+  }
+
+  fn draw_ruler(&mut self) {
+    let (width, height) = self.img.dimensions();
+    self.fill(0, 0, width as i32, RULER_HEIGHT as i32, RULER_BACKGROUND);
+
+    let (major, minor) = tick_spacing(self.duration, width);
+    let minor_px = minor * width as f64 / self.duration;
+    if minor_px >= 6.0 {
+      let mut t = 0.0;
+      while t <= self.duration {
+        let x = self.x(t);
+        self.vline(x, RULER_HEIGHT - 5, RULER_HEIGHT - 1, TICK_MINOR);
+        t += minor;
+      }
+    }
+    let mut t = 0.0;
+    while t <= self.duration {
+      let x = self.x(t);
+      self.vline(x, RULER_HEIGHT, height - 1, GRID_LINE);
+      self.vline(x, 2, RULER_HEIGHT - 1, TICK_MAJOR);
+      t += major;
+    }
+  }
+
+  fn draw_track(&mut self, track: &Track, y0: u32, y1: u32) {
+    let width = self.img.width() as i32;
+    self.fill(0, y0 as i32, width, y1 as i32, LANE_BACKGROUND);
+
+    let inactive = !track.enabled || (track.r#type == TrackType::Audio && track.muted);
+    for (segment, (start, end)) in track.segments.iter().zip(track.timeline_spans()) {
+      let color = match &segment.label {
+        Some(label) if !label.trim().is_empty() => label_color(label),
+        _ => track_color(&track.r#type),
+      };
+      let color = if inactive { dim(color) } else { color };
+      // Leave a 1px seam between back-to-back segments
+      let (x0, x1) = (self.x(start), self.x(end) - 1);
+      self.fill(x0, y0 as i32 + 2, x1.max(x0 + 1), y1 as i32 - 2, color);
+    }
+  }
+
+  fn draw_cuts(&mut self, project: &ProjectFile) {
+    let height = self.img.height();
+    for cut in &project.accepted_cuts {
+      let (x0, x1) = (self.x(cut.start), self.x(cut.end));
+      if x1 < x0 {
+        continue;
+      }
+      blend_rect(&mut self.img, x0 as u32, RULER_HEIGHT, (x1 + 1) as u32, height, CUT_OVERLAY);
+      let solid = Rgba([CUT_OVERLAY.0[0], CUT_OVERLAY.0[1], CUT_OVERLAY.0[2], 255]);
+      self.fill(x0, RULER_HEIGHT as i32 - 3, x1 + 1, RULER_HEIGHT as i32, solid);
+    }
+  }
+
+  fn draw_markers(&mut self, project: &ProjectFile) {
+    let height = self.img.height();
+    for marker in &project.markers {
+      let color = marker.color.as_deref().and_then(parse_hex_color).unwrap_or(MARKER_DEFAULT);
+      let x = self.x(marker.time);
+      self.vline(x, RULER_HEIGHT, height - 1, color);
+      // Downward flag in the ruler
+      let flag = [Point::new(x - 4, 4), Point::new(x + 4, 4), Point::new(x, RULER_HEIGHT as i32 - 2)];
+      draw_polygon_mut(&mut self.img, &flag, color);
+    }
+  }
+}
+
+/// Render the project timeline to a PNG and return it base64 encoded.
+pub fn render_timeline_overview(project: &ProjectFile, width: u32, height: u32) -> Result<String> {
+  if !(64..=MAX_DIMENSION).contains(&width) || !(RULER_HEIGHT * 2..=MAX_DIMENSION).contains(&height) {
+    return Err(anyhow!(
+      "overview size {}x{} is outside 64x{}..{}x{}",
+      width, height, RULER_HEIGHT * 2, MAX_DIMENSION, MAX_DIMENSION
+    ));
+  }
+
+  // Everything drawn must fit, including markers or cuts past the last segment
+  let duration = project
+    .markers
+    .iter()
+    .map(|m| m.time)
+    .chain(project.accepted_cuts.iter().map(|c| c.end))
+    .fold(project.timeline_duration(), f64::max);
+  let duration = if duration.is_finite() && duration > 0.0 { duration } else { 10.0 };
+
+  let mut canvas = Canvas {
+    img: RgbaImage::from_pixel(width, height, BACKGROUND),
+    duration,
+  };
+  canvas.draw_ruler();
+
+  let tracks = project.ordered_tracks();
+  if !tracks.is_empty() {
+    let lanes_height = height - RULER_HEIGHT;
+    let lane_height = (lanes_height / tracks.len() as u32).max(1);
+    for (i, track) in tracks.iter().enumerate() {
+      let y0 = RULER_HEIGHT + i as u32 * lane_height + LANE_GAP / 2;
+      let y1 = (RULER_HEIGHT + (i as u32 + 1) * lane_height).saturating_sub(LANE_GAP / 2);
+      if y1 > y0 {
+        canvas.draw_track(track, y0, y1);
+      }
+    }
+  }
+
+  canvas.draw_cuts(project);
+  canvas.draw_markers(project);
+
+  let mut png = Vec::new();
+  canvas
+    .img
+    .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+    .context("failed to encode timeline overview")?;
+  Ok(base64::engine::general_purpose::STANDARD.encode(&png))
+}
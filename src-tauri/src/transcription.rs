@@ -4,6 +4,15 @@ use tokio::fs;
 use anyhow::Result;
 use reqwest::multipart;
 use mime_guess;
+use tokio_util::sync::CancellationToken;
+use crate::http_client;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptWord {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TranscriptSegment {
@@ -12,6 +21,8 @@ pub struct TranscriptSegment {
     pub end: f64,
     pub text: String,
     pub confidence: Option<f64>,
+    #[serde(default)]
+    pub words: Vec<TranscriptWord>, // Word timings, when the provider returns them
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,20 +30,92 @@ pub struct TranscriptionResult {
     pub segments: Vec<TranscriptSegment>,
     pub status: String, // "completed" | "failed"
     pub error: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>, // ISO 639-1 code, when the provider detected it
+}
+
+/// Language names as transcription providers report them, with their ISO 639-1 codes
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("english", "en"), ("spanish", "es"), ("french", "fr"), ("german", "de"), ("portuguese", "pt"),
+    ("italian", "it"), ("dutch", "nl"), ("polish", "pl"), ("swedish", "sv"), ("turkish", "tr"),
+    ("russian", "ru"), ("ukrainian", "uk"), ("arabic", "ar"), ("hindi", "hi"), ("japanese", "ja"),
+    ("korean", "ko"), ("chinese", "zh"),
+];
+
+/// A language tag as a lowercase ISO 639 code: "en", "en-US", "EN" and "English" all give
+/// "en". None if it doesn't look like a language
+pub fn normalize_language(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    if let Some((_, code)) = LANGUAGE_NAMES.iter().find(|(name, _)| *name == tag) {
+        return Some(code.to_string());
+    }
+    let primary = tag.split(['-', '_']).next().unwrap_or_default();
+    ((2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_lowercase())).then(|| primary.to_string())
+}
+
+/// Very common short words of each language the text pass can tell apart
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "you", "that", "it", "of", "to", "this", "what", "with", "have", "was", "are"]),
+    ("es", &["el", "la", "los", "las", "que", "y", "es", "en", "de", "un", "una", "por", "pero", "muy", "está", "con"]),
+    ("fr", &["le", "la", "les", "et", "est", "que", "un", "une", "des", "pas", "je", "vous", "c'est", "avec", "pour"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ich", "ein", "eine", "mit", "sie", "auf", "auch", "wir"]),
+    ("pt", &["o", "os", "as", "que", "e", "é", "um", "uma", "não", "com", "para", "mas", "você", "muito", "isso"]),
+    ("it", &["il", "lo", "gli", "che", "e", "è", "un", "una", "non", "per", "con", "sono", "anche", "questo"]),
+];
+
+/// Language of a transcript from its words, for providers that don't report one. Counts
+/// common words of each language and takes the clear winner; None when there's too little
+/// text or two languages score about the same
+pub fn detect_language(segments: &[TranscriptSegment]) -> Option<String> {
+    let text = segments.iter().map(|s| s.text.to_lowercase()).collect::<Vec<_>>().join(" ");
+    let words: Vec<&str> = text
+        .split(|c: char| !(c.is_alphabetic() || c == '\''))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut scores: Vec<(&str, usize)> = STOPWORDS
+        .iter()
+        .map(|(code, stopwords)| (*code, words.iter().filter(|w| stopwords.contains(w)).count()))
+        .collect();
+    scores.sort_by(|a, b| b.1.cmp(&a.1));
+    let (code, best) = scores[0];
+    let runner_up = scores[1].1;
+    // Shared words ("que", "e", "la") make close scores meaningless
+    (best >= 5 && best as f64 >= runner_up as f64 * 1.5).then(|| code.to_string())
 }
 
+/// OpenAI's transcription endpoint
+const OPENAI_TRANSCRIPTIONS_URL: &str = "https://api.openai.com/v1/audio/transcriptions";
+
 /// Transcription service that can use multiple providers
 pub struct TranscriptionService {
     client: reqwest::Client,
+    cancel: Option<CancellationToken>,
+    openai_url: String,
 }
 
 impl TranscriptionService {
     pub fn new() -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: http_client::client(),
+            cancel: None,
+            openai_url: OPENAI_TRANSCRIPTIONS_URL.to_string(),
         }
     }
 
+    /// Send OpenAI requests to `url` with `client`, e.g. a local server in tests
+    #[cfg(test)]
+    pub fn with_openai_endpoint(mut self, url: &str, client: reqwest::Client) -> Self {
+        self.openai_url = url.to_string();
+        self.client = client;
+        self
+    }
+
+    /// Abandon requests (uploads included) once `cancel` fires
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
     /// Transcribe a video/audio file using Whisper.cc API
     pub async fn transcribe_with_whisper_cc(&self, file_path: &str) -> Result<TranscriptionResult> {
         log::info!("Starting transcription with Whisper.cc for: {}", file_path);
@@ -64,20 +147,16 @@ impl TranscriptionService {
 
         // Make request to Whisper.cc
         // Note: This is a placeholder URL - you'll need to replace with actual Whisper.cc API endpoint
-        let response = self.client
+        let cancel = self.cancel.as_ref();
+        let request = self.client
             .post("https://api.whisper.cc/v1/transcribe") // Replace with actual endpoint
             .header("Authorization", "Bearer YOUR_API_KEY") // Replace with actual API key
-            .multipart(form)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Whisper.cc API error: {}", error_text));
-        }
+            .multipart(form);
+        let response = http_client::run(cancel, request.send()).await?;
+        let body = http_client::response_text(response, cancel, "Whisper.cc API").await?;
 
         // Parse response
-        let whisper_response: WhisperCCResponse = response.json().await?;
+        let whisper_response: WhisperCCResponse = serde_json::from_str(&body)?;
         
         // Convert to our format
         let segments = whisper_response.segments.into_iter().enumerate().map(|(index, segment)| {
@@ -87,6 +166,7 @@ impl TranscriptionService {
                 end: segment.end,
                 text: segment.text,
                 confidence: segment.confidence,
+                words: Vec::new(),
             }
         }).collect();
 
@@ -94,6 +174,7 @@ impl TranscriptionService {
             segments,
             status: "completed".to_string(),
             error: None,
+            language: None,
         })
     }
 
@@ -125,32 +206,37 @@ impl TranscriptionService {
                 .mime_str(&mime_type)?)
             .text("model", "whisper-1")
             .text("response_format", "verbose_json")
-            .text("timestamp_granularities[]", "segment");
+            .text("timestamp_granularities[]", "segment")
+            .text("timestamp_granularities[]", "word");
 
         // Make request to OpenAI API
-        let response = self.client
-            .post("https://api.openai.com/v1/audio/transcriptions")
+        let cancel = self.cancel.as_ref();
+        let request = self.client
+            .post(&self.openai_url)
             .header("Authorization", format!("Bearer {}", api_key))
-            .multipart(form)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("OpenAI API error: {}", error_text));
-        }
+            .multipart(form);
+        let response = http_client::run(cancel, request.send()).await?;
+        let body = http_client::response_text(response, cancel, "OpenAI API").await?;
 
         // Parse response
-        let openai_response: OpenAIWhisperResponse = response.json().await?;
+        let openai_response: OpenAIWhisperResponse = serde_json::from_str(&body)?;
+        let language = openai_response.language.as_deref().and_then(normalize_language);
         
-        // Convert to our format
+        // Convert to our format. Words come back as one flat list; attach them to the
+        // segment they start in
+        let mut words = openai_response.words.into_iter().peekable();
         let segments = openai_response.segments.into_iter().enumerate().map(|(index, segment)| {
+            let mut segment_words = Vec::new();
+            while let Some(word) = words.next_if(|w| w.start < segment.end) {
+                segment_words.push(TranscriptWord { word: word.word, start: word.start, end: word.end });
+            }
             TranscriptSegment {
                 id: format!("seg_{}", index),
                 start: segment.start,
                 end: segment.end,
                 text: segment.text,
                 confidence: None, // OpenAI doesn't provide confidence scores in this format
+                words: segment_words,
             }
         }).collect();
 
@@ -158,6 +244,7 @@ impl TranscriptionService {
             segments,
             status: "completed".to_string(),
             error: None,
+            language,
         })
     }
 
@@ -189,6 +276,7 @@ impl TranscriptionService {
                 end: end_time,
                 text: mock_texts[text_index].to_string(),
                 confidence: Some(0.95),
+                words: Vec::new(),
             });
 
             current_time = end_time;
@@ -199,6 +287,7 @@ impl TranscriptionService {
             segments,
             status: "completed".to_string(),
             error: None,
+            language: None,
         })
     }
 }
@@ -220,6 +309,18 @@ struct WhisperCCSegment {
 #[derive(Debug, Deserialize)]
 struct OpenAIWhisperResponse {
     segments: Vec<OpenAISegment>,
+    #[serde(default)]
+    language: Option<String>, // Detected language as a name, e.g. "english"
+
+    #[serde(default)]
+    words: Vec<OpenAIWord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIWord {
+    word: String,
+    start: f64,
+    end: f64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -237,9 +338,10 @@ pub async fn transcribe_media_file(
     _use_mock: Option<bool>
 ) -> Result<TranscriptionResult, String> {
     let service = TranscriptionService::new();
+    let started_at = chrono::Utc::now().to_rfc3339();
     
     // Try OpenAI Whisper if API key is provided
-    if let Some(key) = api_key {
+    let result = if let Some(key) = api_key {
         service.transcribe_with_openai_whisper(&file_path, &key).await
             .map_err(|e| {
                 log::error!("OpenAI Whisper failed: {}", e);
@@ -247,5 +349,7 @@ pub async fn transcribe_media_file(
             })
     } else {
         Err("No API key provided for transcription".to_string())
-    }
+    };
+    crate::webhook::task_finished("transcription", &started_at, Vec::new(), result.as_ref().err().cloned());
+    result
 }
@@ -4,6 +4,9 @@ use tokio::fs;
 use anyhow::Result;
 use mime_guess;
 use base64::{Engine as _, engine::general_purpose};
+use tokio_util::sync::CancellationToken;
+use crate::api_quota::{self, ApiPriority};
+use crate::http_client;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoAnalysisResult {
@@ -56,18 +59,39 @@ pub struct TranscriptSegment {
     pub confidence: Option<f64>,
 }
 
+/// Gemini's multimodal endpoint; the API key goes in the query
+const GEMINI_GENERATE_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-pro:generateContent";
+
 /// Video analysis service using Gemini API
 pub struct VideoAnalysisService {
     client: reqwest::Client,
+    cancel: Option<CancellationToken>,
+    gemini_url: String,
 }
 
 impl VideoAnalysisService {
     pub fn new() -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: http_client::client(),
+            cancel: None,
+            gemini_url: GEMINI_GENERATE_URL.to_string(),
         }
     }
 
+    /// Send Gemini requests to `url` with `client`, e.g. a local server in tests
+    #[cfg(test)]
+    pub fn with_gemini_endpoint(mut self, url: &str, client: reqwest::Client) -> Self {
+        self.gemini_url = url.to_string();
+        self.client = client;
+        self
+    }
+
+    /// Abandon requests (including the wait for an API slot) once `cancel` fires
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
     /// Analyze a video using Gemini 1.5 Pro multimodal capabilities
     pub async fn analyze_video_with_gemini(&self, file_path: &str, api_key: &str) -> Result<VideoAnalysisResult> {
         log::info!("Starting video analysis with Gemini for: {}", file_path);
@@ -79,7 +103,7 @@ impl VideoAnalysisService {
 
         // Read file and encode as base64
         let file_data = fs::read(file_path).await?;
-        let _file_name = Path::new(file_path)
+        let file_name = Path::new(file_path)
             .file_name()
             .and_then(|name| name.to_str())
             .unwrap_or("video.mp4");
@@ -118,20 +142,20 @@ impl VideoAnalysisService {
         });
 
         // Make request to Gemini API
-        let response = self.client
-            .post(&format!("https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-pro:generateContent?key={}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Gemini API error: {}", error_text));
-        }
+        let url = format!("{}?key={}", self.gemini_url, api_key);
+        let label = format!("video analysis: {}", file_name);
+        let cancel = self.cancel.as_ref();
+        let response = http_client::run(cancel, api_quota::send(ApiPriority::Analysis, &label, || {
+            self.client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .json(&request_body)
+        }))
+        .await?;
+        let body = http_client::response_text(response, cancel, "Gemini API").await?;
 
         // Parse response
-        let gemini_response: GeminiResponse = response.json().await?;
+        let gemini_response: GeminiResponse = serde_json::from_str(&body)?;
         
         // Extract the text content from Gemini's response
         let content = gemini_response.candidates
@@ -427,9 +451,10 @@ pub async fn analyze_video_file(
     _duration: Option<f64>
 ) -> Result<VideoAnalysisResult, String> {
     let service = VideoAnalysisService::new();
+    let started_at = chrono::Utc::now().to_rfc3339();
     
     // Try Gemini video analysis if API key is provided
-    if let Some(key) = api_key {
+    let result = if let Some(key) = api_key {
         service.analyze_video_with_gemini(&file_path, &key).await
             .map_err(|e| {
                 log::error!("Gemini video analysis failed: {}", e);
@@ -437,5 +462,75 @@ pub async fn analyze_video_file(
             })
     } else {
         Err("No API key provided for video analysis".to_string())
+    };
+    crate::webhook::task_finished("analysis", &started_at, Vec::new(), result.as_ref().err().cloned());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::HttpError;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    /// A small video file in a fresh temp dir. The upload reads it in place and makes no copy
+    fn temp_video() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.mp4");
+        std::fs::write(&path, [0u8; 4096]).unwrap();
+        path
+    }
+
+    /// Files left next to the video after an upload
+    fn leftovers(video: &Path) -> Vec<std::path::PathBuf> {
+        std::fs::read_dir(video.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path != video)
+            .collect()
+    }
+
+    fn http_error(error: &anyhow::Error) -> Option<&HttpError> {
+        error.chain().find_map(|cause| cause.downcast_ref::<HttpError>())
+    }
+
+    #[test]
+    fn the_upload_times_out_on_a_silent_provider() {
+        let (url, stop) = http_client::silent_server("/v1beta/models/gemini-1.5-pro:generateContent");
+        let video = temp_video();
+        let client = http_client::client_with_timeouts(Duration::from_secs(2), Duration::from_secs(1));
+        let service = VideoAnalysisService::new().with_gemini_endpoint(&url, client);
+
+        let error = tauri::async_runtime::block_on(service.analyze_video_with_gemini(&video.to_string_lossy(), "key")).unwrap_err();
+        stop.store(true, Ordering::Relaxed);
+        assert!(matches!(http_error(&error), Some(HttpError::Timeout(_))), "{:#}", error);
+        assert!(video.exists());
+        assert!(leftovers(&video).is_empty());
+        let _ = std::fs::remove_dir_all(video.parent().unwrap());
+    }
+
+    #[test]
+    fn the_upload_stops_when_cancelled() {
+        let (url, stop) = http_client::silent_server("/v1beta/models/gemini-1.5-pro:generateContent");
+        let video = temp_video();
+        let client = http_client::client_with_timeouts(Duration::from_secs(2), Duration::from_secs(60));
+        let token = CancellationToken::new();
+        let service = VideoAnalysisService::new().with_gemini_endpoint(&url, client).with_cancel(token.clone());
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            token.cancel();
+        });
+
+        let started = Instant::now();
+        let error = tauri::async_runtime::block_on(service.analyze_video_with_gemini(&video.to_string_lossy(), "key")).unwrap_err();
+        canceller.join().unwrap();
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(http_error(&error), Some(&HttpError::Cancelled), "{:#}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(video.exists());
+        assert!(leftovers(&video).is_empty());
+        let _ = std::fs::remove_dir_all(video.parent().unwrap());
     }
 }
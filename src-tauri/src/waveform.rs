@@ -1,22 +1,696 @@
-use std::{io::Read, process::Command};
+use std::{fmt, io::Read, path::Path, process::{Command, Stdio}, thread, time::Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Context};
+use serde::{Serialize, Deserialize};
+use crate::ffmpeg::{self, AudioStreamInfo};
+use crate::project_file;
+
+/// Audio decoders are single-threaded; more threads only add startup cost per file
+const DECODE_THREADS: &str = "1";
+
+/// Why a waveform couldn't be produced. Serialized as `{"kind": "no_audio_stream"}` or
+/// `{"kind": "failed", "message": ...}` so the frontend can draw an empty lane for the former
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum WaveformError {
+  NoAudioStream, // Images, and video files without audio
+  Failed(String),
+}
+
+impl fmt::Display for WaveformError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      WaveformError::NoAudioStream => write!(f, "no audio stream"),
+      WaveformError::Failed(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for WaveformError {}
+
+impl From<anyhow::Error> for WaveformError {
+  fn from(e: anyhow::Error) -> Self {
+    match e.downcast::<WaveformError>() {
+      Ok(e) => e,
+      Err(e) => WaveformError::Failed(format!("{:#}", e)),
+    }
+  }
+}
+
+/// The `audio_stream`th audio stream of `path`. Images are rejected without running ffprobe
+fn audio_stream(path: &str, audio_stream: usize) -> anyhow::Result<AudioStreamInfo> {
+  if project_file::is_image_path(Path::new(path)) {
+    return Err(WaveformError::NoAudioStream.into());
+  }
+  let streams = ffmpeg::audio_streams(path)?;
+  if streams.is_empty() {
+    return Err(WaveformError::NoAudioStream.into());
+  }
+  let count = streams.len();
+  streams
+    .into_iter()
+    .nth(audio_stream)
+    .ok_or_else(|| anyhow!("audio stream {} not found ({} in file)", audio_stream, count))
+}
+
+/// Decode only the chosen audio stream: `-vn` plus an explicit map keep ffmpeg from opening
+/// the video decoder, which dominated the time for 4K sources. `start`/`end` (seconds) limit
+/// the decode to a window; the seek goes before `-i` so only the window is decoded
+pub(crate) fn decode_args(path: &str, audio_stream: usize, start: Option<f64>, end: Option<f64>) -> Vec<String> {
+  let mut args: Vec<String> = ["-v", "error", "-threads", DECODE_THREADS].iter().map(|a| a.to_string()).collect();
+  if let Some(start) = start.filter(|s| *s > 0.0) {
+    args.extend(["-ss".to_string(), start.to_string()]);
+  }
+  args.extend(["-i".to_string(), path.to_string()]);
+  if let Some(end) = end {
+    args.extend(["-t".to_string(), (end - start.unwrap_or(0.0).max(0.0)).to_string()]);
+  }
+  args.extend(["-map".to_string(), format!("0:a:{}", audio_stream), "-vn".to_string()]);
+  args
+}
+
+fn log_decode_speed(path: &str, audio_seconds: f64, started: Instant) {
+  let elapsed = started.elapsed().as_secs_f64();
+  log::info!(
+    "Waveform of {}: {:.1}s of audio in {:.2}s ({:.0}x realtime)",
+    path,
+    audio_seconds,
+    elapsed,
+    audio_seconds / elapsed.max(1e-3)
+  );
+}
+
+/// Rate peaks are computed at: 8 kHz, mono unless each channel is asked for
+pub(crate) const PEAK_SAMPLE_RATE: u32 = 8000;
+/// Samples per peak of `pcm_peaks`, ~80 peaks per second
+pub(crate) const DEFAULT_SAMPLES_PER_PEAK: usize = 100;
+
+/// Absolute peaks of the whole file, one per `DEFAULT_SAMPLES_PER_PEAK` samples at 8 kHz
+/// (see `BUCKET_SECONDS`)
+pub fn pcm_peaks(path: &str, audio_stream: usize) -> anyhow::Result<Vec<i16>> {
+  pcm_peaks_with_progress(path, audio_stream, &mut |_| {}, None)
+}
+
+/// `pcm_peaks`, calling `on_progress` with the decoded fraction (0..1) of the file as it
+/// goes. Setting `cancel` stops ffmpeg and fails with "cancelled"
+pub fn pcm_peaks_with_progress(
+  path: &str,
+  audio_stream: usize,
+  on_progress: &mut dyn FnMut(f64),
+  cancel: Option<&AtomicBool>,
+) -> anyhow::Result<Vec<i16>> {
+  Ok(decode_peaks(path, audio_stream, 1, DEFAULT_SAMPLES_PER_PEAK, None, None, false, on_progress, cancel)?
+    .peaks
+    .into_iter()
+    .next()
+    .unwrap_or_default()
+    .into_iter()
+    .map(absolute_peak)
+    .collect())
+}
+
+fn absolute_peak((min, max): (i16, i16)) -> i16 {
+  min.saturating_abs().max(max.saturating_abs())
+}
+
+/// Peaks of each channel of an audio stream, for drawing a lane per channel: a lav mic on
+/// one side only is invisible once mixed down
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelPeaks {
+  pub channels: usize, // From the probe; 1 for mono sources
+  pub peaks: Vec<Vec<i16>>, // One array per channel in stream order, each like `pcm_peaks`
+}
+
+/// `pcm_peaks_with_progress` without the downmix: one peak array per channel of the stream
+pub fn pcm_channel_peaks_with_progress(
+  path: &str,
+  audio_stream: usize,
+  on_progress: &mut dyn FnMut(f64),
+  cancel: Option<&AtomicBool>,
+) -> anyhow::Result<ChannelPeaks> {
+  let channels = usize::from(self::audio_stream(path, audio_stream)?.channels).max(1);
+  let peaks = decode_peaks(path, audio_stream, channels, DEFAULT_SAMPLES_PER_PEAK, None, None, false, on_progress, cancel)?
+    .peaks
+    .into_iter()
+    .map(|lane| lane.into_iter().map(absolute_peak).collect())
+    .collect();
+  Ok(ChannelPeaks { channels, peaks })
+}
+
+/// Peaks as `audio_peaks` returns them: mixed down, or per channel when asked. Untagged, so the
+/// mixed-down form is still a bare array
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum Peaks {
+  Mixed(Vec<i16>),
+  Channels(ChannelPeaks),
+}
+
+/// (min, max) sample pairs of the audio between `start` and `end` seconds (the whole file
+/// when None), one pair per `samples_per_peak` samples of mono 8 kHz audio, so peak `i` starts
+/// at `start + i * samples_per_peak / 8000` seconds. A trailing partial peak is dropped. The
+/// editor asks for a short window at a finer resolution when zooming in, without decoding
+/// the rest of the file
+pub fn pcm_peaks_ex(
+  path: &str,
+  audio_stream: usize,
+  samples_per_peak: usize,
+  start: Option<f64>,
+  end: Option<f64>,
+) -> anyhow::Result<Vec<(i16, i16)>> {
+  Ok(decode_peaks(path, audio_stream, 1, samples_per_peak, start, end, false, &mut |_| {}, None)?.peaks.into_iter().next().unwrap_or_default())
+}
+
+/// `pcm_peaks_ex` plus the RMS level of each peak's samples (0..1 of full scale), from the
+/// same decode. Peaks show the loudest sample; RMS tells quiet speech from silence
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeaksWithRms {
+  pub peaks: Vec<(i16, i16)>,
+  pub rms: Vec<f32>, // One per peak
+}
+
+/// `pcm_peaks_ex` with the RMS of every peak window, see `PeaksWithRms`
+pub fn pcm_peaks_with_rms(
+  path: &str,
+  audio_stream: usize,
+  samples_per_peak: usize,
+  start: Option<f64>,
+  end: Option<f64>,
+) -> anyhow::Result<PeaksWithRms> {
+  let decoded = decode_peaks(path, audio_stream, 1, samples_per_peak, start, end, true, &mut |_| {}, None)?;
+  Ok(PeaksWithRms {
+    peaks: decoded.peaks.into_iter().next().unwrap_or_default(),
+    rms: decoded.rms.into_iter().next().unwrap_or_default(),
+  })
+}
+
+/// What `audio_peaks_ex` returns: peaks alone, or with RMS when asked. Untagged, so the
+/// peaks-only form is still a bare array
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum PeakWindows {
+  Peaks(Vec<(i16, i16)>),
+  WithRms(PeaksWithRms),
+}
+
+/// Shortest and longest windows `loudness_profile` accepts, milliseconds
+pub const LOUDNESS_WINDOW_MS: (u32, u32) = (10, 5000);
+/// Level reported for digital silence, dBFS: the floor of 16-bit audio
+pub const SILENCE_DBFS: f32 = -96.0;
+
+/// Level of the whole file in dBFS (RMS, `SILENCE_DBFS` at the quietest), one value per
+/// `window_ms` of audio, so silence can be found from this profile without another decode.
+/// A trailing partial window is dropped
+pub fn loudness_profile(path: &str, audio_stream: usize, window_ms: u32) -> anyhow::Result<Vec<f32>> {
+  let (shortest, longest) = LOUDNESS_WINDOW_MS;
+  if !(shortest..=longest).contains(&window_ms) {
+    return Err(anyhow!("loudness window must be {} to {} ms, got {} ms", shortest, longest, window_ms));
+  }
+  let samples_per_window = (u64::from(window_ms) * u64::from(PEAK_SAMPLE_RATE) / 1000) as usize;
+  let rms = decode_peaks(path, audio_stream, 1, samples_per_window, None, None, true, &mut |_| {}, None)?.rms;
+  Ok(rms.into_iter().next().unwrap_or_default().into_iter().map(rms_dbfs).collect())
+}
+
+/// RMS level (0..1 of full scale) in dBFS, no lower than `SILENCE_DBFS`
+fn rms_dbfs(rms: f32) -> f32 {
+  if rms <= 0.0 {
+    return SILENCE_DBFS;
+  }
+  (20.0 * rms.log10()).max(SILENCE_DBFS)
+}
+
+/// Envelope resolution `envelope_offset` matches at, milliseconds
+const ENVELOPE_WINDOW_MS: u32 = 100;
+/// Largest shift `envelope_offset` looks for, seconds either way
+const MAX_ENVELOPE_OFFSET: f64 = 300.0;
+/// Least audio both envelopes must share at a shift for it to count, seconds
+const MIN_ENVELOPE_OVERLAP: f64 = 10.0;
+/// Quietest level the envelopes keep, dBFS; below it room tone and digital silence look alike
+const ENVELOPE_FLOOR_DBFS: f32 = -60.0;
+/// Correlation below which the envelopes aren't taken to be the same recording
+pub const MIN_ENVELOPE_CORRELATION: f64 = 0.6;
+
+/// Where the audio of one file turns up in another
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeMatch {
+  pub offset: f64,      // Seconds added to a time in the first file to find it in the second
+  pub correlation: f64, // Of the two envelopes at that offset, -1..1
+}
+
+/// Offset between two versions of a recording (a re-edit with a trimmed or padded head), by
+/// cross-correlating their loudness envelopes. Err when nothing within `MAX_ENVELOPE_OFFSET`
+/// correlates at `MIN_ENVELOPE_CORRELATION` or better
+pub fn envelope_offset(from: (&str, usize), to: (&str, usize)) -> anyhow::Result<EnvelopeMatch> {
+  let envelope = |(path, audio_stream): (&str, usize)| -> anyhow::Result<Vec<f64>> {
+    Ok(loudness_profile(path, audio_stream, ENVELOPE_WINDOW_MS)
+      .with_context(|| format!("failed to read the audio envelope of {}", path))?
+      .into_iter()
+      .map(|level| f64::from(level.max(ENVELOPE_FLOOR_DBFS)))
+      .collect())
+  };
+  let (a, b) = (envelope(from)?, envelope(to)?);
+  let window = f64::from(ENVELOPE_WINDOW_MS) / 1000.0;
+  let max_lag = (MAX_ENVELOPE_OFFSET / window) as isize;
+  let min_overlap = ((MIN_ENVELOPE_OVERLAP / window) as usize).min(a.len().min(b.len())).max(2);
+  let scores: Vec<(isize, f64)> = (-max_lag..=max_lag)
+    .filter_map(|lag| envelope_correlation(&a, &b, lag, min_overlap).map(|r| (lag, r)))
+    .collect();
+  let best = scores
+    .iter()
+    .enumerate()
+    .max_by(|x, y| x.1 .1.total_cmp(&y.1 .1))
+    .map(|(i, _)| i)
+    .ok_or_else(|| anyhow!("the files share less than {}s of audio", MIN_ENVELOPE_OVERLAP))?;
+  let (lag, correlation) = scores[best];
+  if correlation < MIN_ENVELOPE_CORRELATION {
+    return Err(anyhow!(
+      "the audio of the two files doesn't line up (best correlation {:.2}, at {:+.1}s)",
+      correlation,
+      lag as f64 * window
+    ));
+  }
+  // A parabola through the neighbouring scores places the peak between windows
+  let refine = match (best.checked_sub(1).and_then(|i| scores.get(i)), scores.get(best + 1)) {
+    (Some(&(before_lag, before)), Some(&(after_lag, after))) if before_lag == lag - 1 && after_lag == lag + 1 => {
+      let curvature = before - 2.0 * correlation + after;
+      if curvature < 0.0 { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) } else { 0.0 }
+    }
+    _ => 0.0,
+  };
+  Ok(EnvelopeMatch { offset: (lag as f64 + refine) * window, correlation })
+}
+
+/// Pearson correlation of `a[i]` with `b[i + lag]` over the windows both have, None when they
+/// share fewer than `min_overlap` or either side is flat there
+fn envelope_correlation(a: &[f64], b: &[f64], lag: isize, min_overlap: usize) -> Option<f64> {
+  let first = (-lag).max(0) as usize; // First index into `a`
+  let end = (a.len() as isize).min(b.len() as isize - lag);
+  if end - (first as isize) < min_overlap as isize {
+    return None;
+  }
+  let a = &a[first..end as usize];
+  let b = &b[(first as isize + lag) as usize..(end + lag) as usize];
+  let n = a.len() as f64;
+  let (mean_a, mean_b) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+  let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+  for (x, y) in a.iter().zip(b) {
+    let (dx, dy) = (x - mean_a, y - mean_b);
+    covariance += dx * dy;
+    variance_a += dx * dx;
+    variance_b += dy * dy;
+  }
+  (variance_a > 0.0 && variance_b > 0.0).then(|| covariance / (variance_a * variance_b).sqrt())
+}
+
+/// Peaks (and RMS levels, when asked for) of a decode, one list per channel
+struct DecodedPeaks {
+  peaks: Vec<Vec<(i16, i16)>>,
+  rms: Vec<Vec<f32>>, // Empty lists without RMS
+}
+
+/// Decode to 8 kHz with `channels` channels (1 mixes down) and fold the samples into peaks,
+/// one list per channel, as they arrive from ffmpeg's pipe, so long files are never held in
+/// memory. With `with_rms` the RMS of each peak's samples is kept too. See `pcm_peaks_ex` and
+/// `pcm_peaks_with_progress`
+#[allow(clippy::too_many_arguments)]
+fn decode_peaks(
+  path: &str,
+  audio_stream: usize,
+  channels: usize,
+  samples_per_peak: usize,
+  start: Option<f64>,
+  end: Option<f64>,
+  with_rms: bool,
+  on_progress: &mut dyn FnMut(f64),
+  cancel: Option<&AtomicBool>,
+) -> anyhow::Result<DecodedPeaks> {
+  if samples_per_peak == 0 || channels == 0 {
+    return Err(anyhow!("samples_per_peak and channels must be at least 1"));
+  }
+  if start.is_some_and(|s| !s.is_finite() || s < 0.0) || end.is_some_and(|e| !e.is_finite() || e <= 0.0) {
+    return Err(anyhow!("invalid peak window {:?}..{:?}", start, end));
+  }
+  if let (Some(start), Some(end)) = (start, end) {
+    if end <= start {
+      return Err(anyhow!("peak window ends at {}s, before it starts at {}s", end, start));
+    }
+  }
+  self::audio_stream(path, audio_stream)?;
+  // Samples expected, for progress; without a known length progress isn't reported
+  let seconds = match end {
+    Some(end) => end - start.unwrap_or(0.0),
+    None => ffmpeg::ffprobe(path).map(|p| p.duration - start.unwrap_or(0.0)).unwrap_or(0.0),
+  };
+  let expected = seconds.max(0.0) * f64::from(PEAK_SAMPLE_RATE);
+
+  let started = Instant::now();
+  // Convert to 8kHz 16-bit PCM, channels interleaved, and stream to stdout
+  let mut child = Command::new("ffmpeg")
+    .args(decode_args(path, audio_stream, start, end))
+    .args(["-ac", &channels.to_string(), "-ar", &PEAK_SAMPLE_RATE.to_string(), "-f", "s16le", "-"])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .with_context(|| "failed to spawn ffmpeg for the waveform")?;
+  let mut stdout = child.stdout.take().ok_or_else(|| anyhow!("failed to capture stdout"))?;
+  // Drained on its own thread so a chatty ffmpeg can't fill the pipe and stall the decode
+  let mut stderr = child.stderr.take().ok_or_else(|| anyhow!("failed to capture stderr"))?;
+  let stderr = thread::spawn(move || {
+    let mut text = String::new();
+    let _ = stderr.read_to_string(&mut text);
+    text
+  });
+
+  let mut peaks = vec![Vec::new(); channels];
+  let mut rms = vec![Vec::new(); channels];
+  let (mut min, mut max) = (vec![i16::MAX; channels], vec![i16::MIN; channels]);
+  let mut squares = vec![0.0f64; channels]; // Sum of squared samples of the current peak
+  let (mut channel, mut count) = (0usize, 0usize); // Position in the current frame and peak
+  let mut decoded = 0usize; // Frames
+  let mut buf = vec![0u8; 64 * 1024];
+  let mut pending = 0usize; // A byte of a sample split across reads
+  loop {
+    if cancel.is_some_and(|c| c.load(Ordering::Relaxed)) {
+      let _ = child.kill();
+      let _ = child.wait();
+      return Err(anyhow!("cancelled"));
+    }
+    let n = stdout.read(&mut buf[pending..])?;
+    if n == 0 {
+      break;
+    }
+    let available = pending + n;
+    let whole = available - available % 2;
+    for sample in buf[..whole].chunks_exact(2) {
+      let v = i16::from_le_bytes([sample[0], sample[1]]);
+      min[channel] = min[channel].min(v);
+      max[channel] = max[channel].max(v);
+      if with_rms {
+        squares[channel] += f64::from(v) * f64::from(v);
+      }
+      channel += 1;
+      if channel < channels {
+        continue;
+      }
+      channel = 0;
+      decoded += 1;
+      count += 1;
+      if count == samples_per_peak {
+        for (c, lane) in peaks.iter_mut().enumerate() {
+          lane.push((min[c], max[c]));
+        }
+        if with_rms {
+          for (c, lane) in rms.iter_mut().enumerate() {
+            lane.push(((squares[c] / samples_per_peak as f64).sqrt() / 32768.0) as f32);
+          }
+          squares.fill(0.0);
+        }
+        min.fill(i16::MAX);
+        max.fill(i16::MIN);
+        count = 0;
+      }
+    }
+    buf.copy_within(whole..available, 0);
+    pending = available - whole;
+    if expected > 0.0 {
+      on_progress((decoded as f64 / expected).min(1.0));
+    }
+  }
+  let status = child.wait()?;
+  let stderr = stderr.join().unwrap_or_default();
+  // A failed decode still writes what it got; that short waveform would look real
+  if !status.success() {
+    return Err(anyhow!("ffmpeg waveform decode failed (status {:?}): {}", status.code(), stderr.trim()));
+  }
+  log_decode_speed(path, decoded as f64 / f64::from(PEAK_SAMPLE_RATE), started);
+  Ok(DecodedPeaks { peaks, rms })
+}
+
+/// --- Progress and cancellation -------------------------------------------------------
+
+/// Progress of a waveform decode, sent to the requesting window as "waveform-progress"
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaveformProgress {
+  pub path: String,
+  pub percent: f64,
+}
+
+/// Decodes in flight, by the label of the window that asked for them
+static IN_FLIGHT: Mutex<Vec<(String, Arc<AtomicBool>)>> = Mutex::new(Vec::new());
+
+/// A decode for window `label`, cancelled when the window goes away. Unregisters on drop
+pub struct WindowDecode {
+  label: String,
+  cancel: Arc<AtomicBool>,
+}
+
+impl WindowDecode {
+  pub fn new(label: &str) -> Self {
+    let cancel = Arc::new(AtomicBool::new(false));
+    IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner()).push((label.to_string(), cancel.clone()));
+    Self { label: label.to_string(), cancel }
+  }
+
+  pub fn cancel_flag(&self) -> &AtomicBool {
+    &self.cancel
+  }
+}
+
+impl Drop for WindowDecode {
+  fn drop(&mut self) {
+    IN_FLIGHT
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .retain(|(label, cancel)| !(label == &self.label && Arc::ptr_eq(cancel, &self.cancel)));
+  }
+}
+
+/// Stop every decode window `label` asked for; its results have nowhere to go
+pub fn cancel_for_window(label: &str) {
+  for (_, cancel) in IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner()).iter().filter(|(l, _)| l == label) {
+    cancel.store(true, Ordering::Relaxed);
+  }
+}
+
+/// --- Clipping detection --------------------------------------------------------------
+
+/// Samples at or beyond this magnitude are treated as hitting full scale.
+const CLIP_LEVEL: i32 = i16::MAX as i32;
+/// Same bucket width as `pcm_peaks` (100 samples at 8 kHz).
+pub const BUCKET_SECONDS: f64 = DEFAULT_SAMPLES_PER_PEAK as f64 / PEAK_SAMPLE_RATE as f64;
+/// Clipped runs closer than this are reported as one region.
+const REGION_MERGE_GAP: f64 = 0.25;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ClippingSummary {
+  pub clipped_samples: u64,
+  pub worst_run_ms: f64, // Longest run of consecutive clipped frames
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClippedRegion {
+  pub start: f64, // Seconds
+  pub end: f64,
+}
+
+/// Peaks plus clipping info. `clipped_buckets` indexes into `peaks`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaveformData {
+  pub peaks: Vec<i16>,
+  pub clipped_buckets: Vec<usize>,
+  pub clipping_summary: ClippingSummary,
+  pub clipped_regions: Vec<ClippedRegion>,
+}
+
+/// Fold decoded frames into peaks and clipping stats without holding the whole file in memory.
+struct ClipScanner {
+  sample_rate: f64,
+  channels: usize,
+  frames_per_bucket: usize,
+
+  frame_index: u64,
+  bucket_frames: usize,
+  bucket_peak: i32,
+  bucket_clipped: bool,
+  run_start: Option<u64>,
+
+  data: WaveformData,
+  worst_run_frames: u64,
+}
+
+impl ClipScanner {
+  fn new(sample_rate: u32, channels: u8) -> Self {
+    let sample_rate = sample_rate.max(1) as f64;
+    Self {
+      sample_rate,
+      channels: channels.max(1) as usize,
+      frames_per_bucket: ((sample_rate * BUCKET_SECONDS).round() as usize).max(1),
+      frame_index: 0,
+      bucket_frames: 0,
+      bucket_peak: 0,
+      bucket_clipped: false,
+      run_start: None,
+      data: WaveformData {
+        peaks: Vec::new(),
+        clipped_buckets: Vec::new(),
+        clipping_summary: ClippingSummary::default(),
+        clipped_regions: Vec::new(),
+      },
+      worst_run_frames: 0,
+    }
+  }
+
+  /// Feed interleaved samples for whole frames.
+  fn push_frames(&mut self, samples: &[i16]) {
+    for frame in samples.chunks_exact(self.channels) {
+      let mut frame_peak = 0;
+      let mut frame_clipped = false;
+      for s in frame {
+        let v = (*s as i32).abs();
+        if v >= CLIP_LEVEL {
+          frame_clipped = true;
+          self.data.clipping_summary.clipped_samples += 1;
+        }
+        frame_peak = frame_peak.max(v);
+      }
+
+      if frame_clipped {
+        self.run_start.get_or_insert(self.frame_index);
+        self.bucket_clipped = true;
+      } else {
+        self.end_run();
+      }
+
+      self.bucket_peak = self.bucket_peak.max(frame_peak);
+      self.bucket_frames += 1;
+      if self.bucket_frames == self.frames_per_bucket {
+        self.flush_bucket();
+      }
+      self.frame_index += 1;
+    }
+  }
+
+  fn end_run(&mut self) {
+    let Some(start) = self.run_start.take() else {
+      return;
+    };
+    let run_frames = self.frame_index - start;
+    self.worst_run_frames = self.worst_run_frames.max(run_frames);
+
+    let start = start as f64 / self.sample_rate;
+    let end = self.frame_index as f64 / self.sample_rate;
+    match self.data.clipped_regions.last_mut() {
+      Some(last) if start - last.end <= REGION_MERGE_GAP => last.end = end,
+      _ => self.data.clipped_regions.push(ClippedRegion { start, end }),
+    }
+  }
+
+  fn flush_bucket(&mut self) {
+    if self.bucket_clipped {
+      self.data.clipped_buckets.push(self.data.peaks.len());
+    }
+    self.data.peaks.push(self.bucket_peak.min(i16::MAX as i32) as i16);
+    self.bucket_frames = 0;
+    self.bucket_peak = 0;
+    self.bucket_clipped = false;
+  }
+
+  fn finish(mut self) -> WaveformData {
+    self.end_run();
+    // Match `pcm_peaks`: a trailing partial bucket is dropped, but a clipped one is kept
+    if self.bucket_clipped {
+      self.flush_bucket();
+    }
+    self.data.clipping_summary.worst_run_ms = self.worst_run_frames as f64 / self.sample_rate * 1000.0;
+    self.data
+  }
+}
+
+/// Compute peaks and detect clipping. Decodes at the source's own sample rate and channel
+/// count so single clipped samples are not hidden by resampling or downmixing.
+pub fn pcm_peaks_with_clipping(path: &str, audio_stream: usize) -> anyhow::Result<WaveformData> {
+  let stream = self::audio_stream(path, audio_stream)?;
+  let mut scanner = ClipScanner::new(stream.sample_rate, stream.channels);
+  let started = Instant::now();
 
-pub fn pcm_peaks(path: &str) -> anyhow::Result<Vec<i16>> {
-  // Convert to mono 8kHz 16-bit PCM and stream to stdout
   let mut child = Command::new("ffmpeg")
-    .args(["-v","error","-i", path, "-ac","1","-ar","8000","-f","s16le","-"])
+    .args(decode_args(path, audio_stream, None, None))
+    .args(["-f", "s16le", "-acodec", "pcm_s16le", "-"])
     .stdout(std::process::Stdio::piped())
-    .spawn()?;
-  let mut buf = vec![];
-  child.stdout.as_mut().unwrap().read_to_end(&mut buf)?;
-  // Downsample to coarse peaks: one value per ~100 samples
-  let mut peaks = vec![];
-  for chunk in buf.chunks_exact(2*100) {
-    let mut maxv: i16 = 0;
-    for s in chunk.chunks_exact(2) {
-      let v = i16::from_le_bytes([s[0], s[1]]).abs();
-      if v > maxv { maxv = v; }
-    }
-    peaks.push(maxv);
-  }
-  Ok(peaks)
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .with_context(|| "failed to spawn ffmpeg for clipping scan")?;
+
+  let mut stdout = child.stdout.take().ok_or_else(|| anyhow!("failed to capture stdout"))?;
+  let frame_bytes = 2 * scanner.channels;
+  let mut buf = vec![0u8; 64 * 1024 * frame_bytes];
+  let mut pending = 0usize; // Bytes of an incomplete frame carried to the next read
+  let mut samples = Vec::with_capacity(buf.len() / 2);
+  loop {
+    let n = stdout.read(&mut buf[pending..])?;
+    if n == 0 {
+      break;
+    }
+    let available = pending + n;
+    let whole = available - available % frame_bytes;
+    samples.clear();
+    samples.extend(buf[..whole].chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])));
+    scanner.push_frames(&samples);
+    buf.copy_within(whole..available, 0);
+    pending = available - whole;
+  }
+
+  let output = child.wait_with_output().with_context(|| "failed to wait for ffmpeg")?;
+  if !output.status.success() {
+    return Err(anyhow!(
+      "ffmpeg clipping scan failed: {}",
+      String::from_utf8_lossy(&output.stderr)
+    ));
+  }
+
+  log_decode_speed(path, scanner.frame_index as f64 / scanner.sample_rate, started);
+  Ok(scanner.finish())
+}
+
+/// Format seconds as m:ss for user-facing messages.
+fn format_timestamp(seconds: f64) -> String {
+  let total = seconds.max(0.0).round() as u64;
+  format!("{}:{:02}", total / 60, total % 60)
+}
+
+/// Human-readable warning such as "source audio clips at 0:42–0:44", or None if nothing clips.
+pub fn describe_clipping(regions: &[ClippedRegion]) -> Option<String> {
+  if regions.is_empty() {
+    return None;
+  }
+  let ranges: Vec<String> = regions
+    .iter()
+    .take(5)
+    .map(|r| {
+      let (start, end) = (format_timestamp(r.start), format_timestamp(r.end));
+      if start == end { start } else { format!("{}–{}", start, end) }
+    })
+    .collect();
+  let more = if regions.len() > 5 { format!(" (+{} more)", regions.len() - 5) } else { String::new() };
+  Some(format!("source audio clips at {}{}", ranges.join(", "), more))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClippingReport {
+  pub clip_id: String,
+  pub clipping_summary: ClippingSummary,
+  pub clipped_regions: Vec<ClippedRegion>,
+  pub warning: Option<String>,
+}
+
+/// Scan a clip's source audio for clipping.
+pub fn find_clipping(clip_id: &str, path: &str) -> anyhow::Result<ClippingReport> {
+  let data = pcm_peaks_with_clipping(path, 0)?;
+  Ok(ClippingReport {
+    clip_id: clip_id.to_string(),
+    warning: describe_clipping(&data.clipped_regions),
+    clipping_summary: data.clipping_summary,
+    clipped_regions: data.clipped_regions,
+  })
 }
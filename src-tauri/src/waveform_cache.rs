@@ -0,0 +1,225 @@
+//! Disk cache for waveform peaks, so opening a project doesn't decode every clip again. One
+//! `.peaks` file per source, audio stream and layout (mixed down or per channel) under
+//! `<cache>/waveforms`, named by a hash of those; the file records the size and modification
+//! time of the source it was made from, and an entry whose source has changed since is a miss
+//! and gets overwritten. Spectrograms (see spectrogram.rs) are cached in the same folder, so
+//! `cache_size` and `clear` cover them too.
+//!
+//! File layout, little-endian: the magic `GEBOPEAK`, format version (u16), channel count
+//! (u16), sample rate (u32), samples per peak (u32), source size (u64), source modification
+//! time in ns (u64), peaks per channel (u64), then each channel's peaks as i16s in turn.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::waveform::{self, ChannelPeaks};
+use crate::work_dirs::{self, WorkDir};
+
+const MAGIC: &[u8; 8] = b"GEBOPEAK";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 8 + 2 + 2 + 4 + 4 + 8 + 8 + 8;
+
+pub(crate) fn cache_dir() -> Result<PathBuf> {
+  let dir = work_dirs::path(WorkDir::Cache)?.join("waveforms");
+  fs::create_dir_all(&dir).with_context(|| format!("Failed to create waveform cache at {:?}", dir))?;
+  Ok(dir)
+}
+
+/// Size and modification time (ns) of `source`; a change in either makes its entries stale
+pub(crate) fn source_stamp(source: &Path) -> Result<(u64, u64)> {
+  let metadata = fs::metadata(source).with_context(|| format!("failed to read {:?}", source))?;
+  let modified = metadata.modified()
+    .ok()
+    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    .map(|d| d.as_nanos() as u64)
+    .unwrap_or(0);
+  Ok((metadata.len(), modified))
+}
+
+/// Entry of `path`'s `audio_stream`, mixed down or per channel. The name doesn't depend on the
+/// file's state, so a changed source overwrites its stale entry instead of leaving it behind.
+/// Also where clip_cache keeps a clip's waveform artifact
+pub(crate) fn entry_path(path: &str, audio_stream: usize, channels: bool) -> Result<PathBuf> {
+  let source = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+  let mut hasher = Sha256::new();
+  hasher.update(source.to_string_lossy().as_bytes());
+  hasher.update((audio_stream as u64).to_le_bytes());
+  hasher.update([channels as u8]);
+  hasher.update(waveform::PEAK_SAMPLE_RATE.to_le_bytes());
+  hasher.update((waveform::DEFAULT_SAMPLES_PER_PEAK as u64).to_le_bytes());
+  let hash = format!("{:x}", hasher.finalize());
+  Ok(cache_dir()?.join(format!("{}.peaks", &hash[..32])))
+}
+
+fn encode(peaks: &ChannelPeaks, stamp: (u64, u64)) -> Vec<u8> {
+  let per_channel = peaks.peaks.first().map_or(0, |lane| lane.len());
+  let mut data = Vec::with_capacity(HEADER_LEN + peaks.peaks.len() * per_channel * 2);
+  data.extend_from_slice(MAGIC);
+  data.extend_from_slice(&VERSION.to_le_bytes());
+  data.extend_from_slice(&(peaks.peaks.len() as u16).to_le_bytes());
+  data.extend_from_slice(&waveform::PEAK_SAMPLE_RATE.to_le_bytes());
+  data.extend_from_slice(&(waveform::DEFAULT_SAMPLES_PER_PEAK as u32).to_le_bytes());
+  data.extend_from_slice(&stamp.0.to_le_bytes());
+  data.extend_from_slice(&stamp.1.to_le_bytes());
+  data.extend_from_slice(&(per_channel as u64).to_le_bytes());
+  for lane in &peaks.peaks {
+    for peak in lane.iter().take(per_channel) {
+      data.extend_from_slice(&peak.to_le_bytes());
+    }
+  }
+  data
+}
+
+/// Peaks in `data` if it is a complete entry made with today's parameters from a source
+/// stamped `stamp`
+fn decode(data: &[u8], stamp: (u64, u64)) -> Option<ChannelPeaks> {
+  decode_any(data).filter(|(_, written)| *written == stamp).map(|(peaks, _)| peaks)
+}
+
+/// Peaks in `data` and the stamp of the source they were made from, if it is a complete entry
+/// made with today's parameters
+fn decode_any(data: &[u8]) -> Option<(ChannelPeaks, (u64, u64))> {
+  let header = data.get(..HEADER_LEN)?;
+  let u16_at = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+  let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap_or_default());
+  let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap_or_default());
+  if &header[..8] != MAGIC || u16_at(8) != VERSION {
+    return None;
+  }
+  let channels = usize::from(u16_at(10));
+  if u32_at(12) != waveform::PEAK_SAMPLE_RATE || u32_at(16) as usize != waveform::DEFAULT_SAMPLES_PER_PEAK {
+    return None;
+  }
+  let stamp = (u64_at(20), u64_at(28));
+  let per_channel = usize::try_from(u64_at(36)).ok()?;
+  let body = &data[HEADER_LEN..];
+  if channels == 0 || body.len() != channels.checked_mul(per_channel)?.checked_mul(2)? {
+    return None;
+  }
+  if per_channel == 0 {
+    return Some((ChannelPeaks { channels, peaks: vec![Vec::new(); channels] }, stamp));
+  }
+  let peaks = body
+    .chunks_exact(per_channel * 2)
+    .map(|lane| lane.chunks_exact(2).map(|p| i16::from_le_bytes([p[0], p[1]])).collect())
+    .collect();
+  Some((ChannelPeaks { channels, peaks }, stamp))
+}
+
+/// Cached peaks of `path` (see `waveform::pcm_peaks`, or `pcm_channel_peaks_with_progress`
+/// with `channels`), decoding them with `compute` on a miss and caching the result. A failed
+/// write is logged; the peaks are still returned
+pub fn get_or_compute(
+  path: &str,
+  audio_stream: usize,
+  channels: bool,
+  compute: impl FnOnce() -> Result<ChannelPeaks>,
+) -> Result<ChannelPeaks> {
+  let stamp = source_stamp(Path::new(path))?;
+  let entry = entry_path(path, audio_stream, channels)?;
+  if let Some(peaks) = fs::read(&entry).ok().and_then(|data| decode(&data, stamp)) {
+    return Ok(peaks);
+  }
+
+  let peaks = compute()?;
+  if let Err(e) = write_entry(&entry, &peaks, stamp) {
+    log::warn!("Failed to cache the waveform of {}: {}", path, e);
+  }
+  Ok(peaks)
+}
+
+/// Write an entry aside and rename it into place, so a reader never sees half an entry
+fn write_entry(entry: &Path, peaks: &ChannelPeaks, stamp: (u64, u64)) -> Result<()> {
+  let temp = entry.with_extension("peaks.tmp");
+  let written = fs::write(&temp, encode(peaks, stamp)).and_then(|()| fs::rename(&temp, entry));
+  if written.is_err() {
+    let _ = fs::remove_file(&temp);
+  }
+  written.with_context(|| format!("failed to write {:?}", entry))
+}
+
+/// Cached mixed-down peaks of `path`'s `audio_stream` (see `waveform::pcm_peaks`), decoding
+/// them on a miss
+pub fn mixed_peaks(path: &str, audio_stream: usize) -> Result<Vec<i16>> {
+  let peaks = get_or_compute(path, audio_stream, false, || {
+    waveform::pcm_peaks(path, audio_stream).map(|peaks| ChannelPeaks { channels: 1, peaks: vec![peaks] })
+  })?;
+  Ok(peaks.peaks.into_iter().next().unwrap_or_default())
+}
+
+/// Copy the entry at `from` to `to` as made from `source`, for a file whose audio is known to
+/// be the same as the one `from` was made from (see clip_cache). Errs if `from` isn't a
+/// complete entry
+pub(crate) fn copy_entry(from: &Path, to: &Path, source: &Path) -> Result<()> {
+  let data = fs::read(from).with_context(|| format!("failed to read {:?}", from))?;
+  let (peaks, _) = decode_any(&data).with_context(|| format!("{:?} is not a waveform cache entry", from))?;
+  write_entry(to, &peaks, source_stamp(source)?)
+}
+
+/// Total size of the cached waveforms and spectrograms, bytes
+pub fn cache_size() -> Result<u64> {
+  let dir = cache_dir()?;
+  let mut size = 0;
+  for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {:?}", dir))? {
+    let metadata = entry?.metadata()?;
+    if metadata.is_file() {
+      size += metadata.len();
+    }
+  }
+  Ok(size)
+}
+
+/// Delete every cached waveform and spectrogram. Returns the bytes freed
+pub fn clear() -> Result<u64> {
+  let dir = cache_dir()?;
+  let mut freed = 0;
+  for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {:?}", dir))? {
+    let entry = entry?;
+    let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+    if entry.path().is_file() && fs::remove_file(entry.path()).is_ok() {
+      freed += size;
+    }
+  }
+  Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn peaks() -> ChannelPeaks {
+    ChannelPeaks { channels: 2, peaks: vec![vec![1, -2, 300], vec![-4, 5, -600]] }
+  }
+
+  #[test]
+  fn entries_only_decode_for_the_stamp_they_were_made_with() {
+    let data = encode(&peaks(), (10, 20));
+    assert_eq!(decode(&data, (10, 20)).map(|p| p.peaks), Some(peaks().peaks));
+    assert!(decode(&data, (10, 21)).is_none());
+    assert!(decode(&data, (11, 20)).is_none());
+    assert!(decode(&data[..data.len() - 1], (10, 20)).is_none());
+  }
+
+  #[test]
+  fn a_copied_entry_is_stamped_with_its_new_source() {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let (source, derived) = (dir.join("source.wav"), dir.join("derived.wav"));
+    fs::write(&source, b"source").unwrap();
+    fs::write(&derived, b"a longer derived file").unwrap();
+    let (from, to) = (dir.join("from.peaks"), dir.join("to.peaks"));
+    write_entry(&from, &peaks(), source_stamp(&source).unwrap()).unwrap();
+
+    copy_entry(&from, &to, &derived).unwrap();
+    let data = fs::read(&to).unwrap();
+    assert!(decode(&data, source_stamp(&source).unwrap()).is_none());
+    assert_eq!(decode(&data, source_stamp(&derived).unwrap()).map(|p| p.peaks), Some(peaks().peaks));
+    assert!(!dir.join("to.peaks.tmp").exists());
+
+    fs::write(&from, b"not an entry").unwrap();
+    assert!(copy_entry(&from, &to, &derived).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
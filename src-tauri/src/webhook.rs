@@ -0,0 +1,123 @@
+//! Optional webhook for automating around Gebo: when a job (export, transcription, analysis)
+//! completes or fails, a JSON payload is POSTed to the URL in settings with the shared secret
+//! in a header. Delivery runs on a background thread and is retried on network failure; it
+//! never affects the job's outcome.
+
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+use crate::jobs::{Job, JobStatus};
+use crate::longterm_storage::{self, WebhookSettings};
+use crate::project_file;
+
+/// Header carrying `WebhookSettings.secret`
+pub const SECRET_HEADER: &str = "X-Gebo-Secret";
+/// Attempts per delivery, including the first
+const ATTEMPTS: u32 = 3;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Body of every webhook request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookPayload {
+  pub event: String, // "job.completed" | "job.failed" | "test"
+  pub kind: String, // Job kind, e.g. "export_project" or "transcription"
+  pub status: String, // "completed" | "failed"
+  pub outputs: Vec<String>,
+  pub started_at: String, // ISO 8601
+  pub finished_at: String, // ISO 8601
+  pub duration_ms: Option<i64>,
+  pub project_title: Option<String>,
+  pub error: Option<String>,
+}
+
+impl WebhookPayload {
+  fn new(kind: &str, started_at: &str, finished_at: &str, outputs: Vec<String>, error: Option<String>) -> Self {
+    let duration_ms = chrono::DateTime::parse_from_rfc3339(finished_at).ok()
+      .zip(chrono::DateTime::parse_from_rfc3339(started_at).ok())
+      .map(|(finished, started)| (finished - started).num_milliseconds());
+    let failed = error.is_some();
+    Self {
+      event: if failed { "job.failed" } else { "job.completed" }.to_string(),
+      kind: kind.to_string(),
+      status: if failed { "failed" } else { "completed" }.to_string(),
+      outputs,
+      started_at: started_at.to_string(),
+      finished_at: finished_at.to_string(),
+      duration_ms,
+      project_title: project_file::get_project().ok().flatten().map(|p| p.title),
+      error,
+    }
+  }
+}
+
+fn configured() -> Option<WebhookSettings> {
+  let settings = longterm_storage::get_settings().ok()?.webhook;
+  settings.url.as_deref().is_some_and(|url| !url.trim().is_empty()).then_some(settings)
+}
+
+/// Report a finished job. Cancelled jobs aren't reported
+pub fn job_finished(job: &Job) {
+  let error = match job.status {
+    JobStatus::Completed => None,
+    JobStatus::Failed => Some(job.error.clone().unwrap_or_default()),
+    _ => return,
+  };
+  let finished_at = job.finished_at.clone().unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+  spawn(WebhookPayload::new(&job.kind, &job.created_at, &finished_at, job.outputs.clone(), error));
+}
+
+/// Report work that doesn't run as a job (transcription, analysis, direct exports)
+/// that started at `started_at` and just finished
+pub fn task_finished(kind: &str, started_at: &str, outputs: Vec<String>, error: Option<String>) {
+  let finished_at = chrono::Utc::now().to_rfc3339();
+  spawn(WebhookPayload::new(kind, started_at, &finished_at, outputs, error));
+}
+
+fn spawn(payload: WebhookPayload) {
+  let Some(settings) = configured() else {
+    return;
+  };
+  std::thread::spawn(move || {
+    if let Err(e) = deliver(&settings, &payload) {
+      log::warn!("Webhook for {} {} failed: {:#}", payload.kind, payload.status, e);
+    }
+  });
+}
+
+/// POST `payload`, retrying network failures and 5xx responses with a growing delay.
+/// Returns the HTTP status of the accepted request
+fn deliver(settings: &WebhookSettings, payload: &WebhookPayload) -> Result<u16> {
+  let url = settings.url.as_deref().map(str::trim).filter(|u| !u.is_empty())
+    .ok_or_else(|| anyhow!("no webhook URL is configured"))?;
+  let client = reqwest::blocking::Client::builder().timeout(TIMEOUT).build()?;
+  let mut last_error = anyhow!("webhook was not sent");
+  for attempt in 1..=ATTEMPTS {
+    let mut request = client.post(url).json(payload);
+    if let Some(secret) = settings.secret.as_deref().filter(|s| !s.is_empty()) {
+      request = request.header(SECRET_HEADER, secret);
+    }
+    match request.send() {
+      Ok(response) if response.status().is_success() => return Ok(response.status().as_u16()),
+      // A 4xx won't change on retry
+      Ok(response) if response.status().is_client_error() => {
+        return Err(anyhow!("{} answered {}", url, response.status()));
+      }
+      Ok(response) => last_error = anyhow!("{} answered {}", url, response.status()),
+      Err(e) => last_error = anyhow!("request to {} failed: {}", url, e),
+    }
+    if attempt < ATTEMPTS {
+      std::thread::sleep(Duration::from_secs(2u64.pow(attempt - 1)));
+    }
+  }
+  Err(last_error)
+}
+
+/// Send a sample payload to the configured URL and wait for the answer, so users can check
+/// their endpoint. Returns the HTTP status
+pub fn test_webhook() -> Result<u16> {
+  let settings = longterm_storage::get_settings()?.webhook;
+  let now = chrono::Utc::now().to_rfc3339();
+  let mut payload = WebhookPayload::new("export_project", &now, &now, vec!["/path/to/export.mp4".to_string()], None);
+  payload.event = "test".to_string();
+  deliver(&settings, &payload)
+}
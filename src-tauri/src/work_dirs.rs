@@ -0,0 +1,282 @@
+//! Directories the app writes generated media to. Each has a preferred location and falls back,
+//! in order, to the user cache dir, the user data dir and the temp dir when that isn't writable
+//! (locked-down machines often refuse writes to Downloads). The choice is made once per run,
+//! recorded in settings, and reported to the UI the first time a fallback is used.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crate::longterm_storage;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkDir {
+  Previews, // Timeline previews; preferred: Downloads
+  Cache,    // Thumbnail, preview proxy and waveform caches; preferred: <data dir>/gebo
+  Library,  // Media library copies and index; preferred: <data dir>/gebo/library
+  Exports,  // Exports of projects without a folder of their own; preferred: Downloads
+}
+
+impl WorkDir {
+  const ALL: [WorkDir; 4] = [WorkDir::Previews, WorkDir::Cache, WorkDir::Library, WorkDir::Exports];
+
+  fn preferred(&self) -> Option<PathBuf> {
+    match self {
+      WorkDir::Previews | WorkDir::Exports => dirs::download_dir(),
+      WorkDir::Cache => dirs::data_dir().map(|d| d.join("gebo")),
+      WorkDir::Library => dirs::data_dir().map(|d| d.join("gebo").join("library")),
+    }
+  }
+
+  /// Subfolder used under a fallback base, so fallbacks don't mix with other files
+  fn subdir(&self) -> &'static str {
+    match self {
+      WorkDir::Previews => "previews",
+      WorkDir::Cache => "cache",
+      WorkDir::Library => "library",
+      WorkDir::Exports => "exports",
+    }
+  }
+
+  /// Candidates in order: preferred, then the cache, data and temp dirs
+  fn candidates(&self) -> Vec<PathBuf> {
+    let fallbacks = [dirs::cache_dir(), dirs::data_dir(), Some(std::env::temp_dir())]
+      .into_iter()
+      .flatten()
+      .map(|base| base.join("gebo").join(self.subdir()));
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for dir in self.preferred().into_iter().chain(fallbacks) {
+      if !candidates.contains(&dir) {
+        candidates.push(dir);
+      }
+    }
+    candidates
+  }
+
+  /// The fallback recorded in settings for this directory
+  fn recorded<'a>(&self, settings: &'a mut longterm_storage::Settings) -> &'a mut Option<PathBuf> {
+    match self {
+      WorkDir::Previews => &mut settings.previews_dir,
+      WorkDir::Cache => &mut settings.cache_dir,
+      WorkDir::Library => &mut settings.library_dir,
+      WorkDir::Exports => &mut settings.exports_dir,
+    }
+  }
+}
+
+/// Folders the user can name as an export destination ("export it to my desktop")
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserFolder {
+  Desktop,
+  Downloads,
+}
+
+impl UserFolder {
+  fn location(&self) -> Option<PathBuf> {
+    match self {
+      UserFolder::Desktop => dirs::desktop_dir(),
+      UserFolder::Downloads => dirs::download_dir(),
+    }
+  }
+}
+
+/// A directory that replaced the preferred one, for the UI's startup warning
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirFallback {
+  pub dir: WorkDir,
+  pub preferred: Option<PathBuf>,
+  pub chosen: PathBuf,
+  pub reason: String, // Why the preferred location was rejected
+}
+
+struct Resolved {
+  dirs: Vec<(WorkDir, PathBuf)>,
+  fallbacks: Vec<DirFallback>, // Fallbacks not recorded in settings before this run
+}
+
+static RESOLVED: OnceLock<Resolved> = OnceLock::new();
+
+/// Create `dir` and write and remove a probe file in it
+fn check_writable(dir: &Path) -> Result<()> {
+  fs::create_dir_all(dir).with_context(|| format!("cannot create {:?}", dir))?;
+  let probe = dir.join(".gebo-write-test");
+  fs::write(&probe, b"ok").with_context(|| format!("cannot write to {:?}", dir))?;
+  let _ = fs::remove_file(&probe);
+  Ok(())
+}
+
+/// First writable candidate of `dir`, and why the preferred one was skipped if it was
+fn choose(dir: WorkDir) -> (PathBuf, Option<String>) {
+  let mut reason = None;
+  for (i, candidate) in dir.candidates().into_iter().enumerate() {
+    match check_writable(&candidate) {
+      Ok(()) => return (candidate, reason),
+      Err(e) => {
+        log::warn!("{:?} directory {:?} is not usable: {:#}", dir, candidate, e);
+        if i == 0 {
+          reason = Some(format!("{:#}", e));
+        }
+      }
+    }
+  }
+  // Nothing writable; keep the temp dir so errors name a sensible path
+  (std::env::temp_dir(), reason.or_else(|| Some("no writable directory found".to_string())))
+}
+
+/// Record `chosen` for `dir` in `recorded`: the fallback in use, or None for the preferred
+/// location. Returns the fallback to warn about, when it wasn't in use before
+fn record(
+  dir: WorkDir,
+  preferred: Option<PathBuf>,
+  chosen: &Path,
+  reason: Option<String>,
+  recorded: &mut Option<PathBuf>,
+) -> Option<DirFallback> {
+  let fell_back = preferred.as_deref() != Some(chosen);
+  let warning = (fell_back && recorded.as_deref() != Some(chosen)).then(|| DirFallback {
+    dir,
+    preferred,
+    chosen: chosen.to_path_buf(),
+    reason: reason.unwrap_or_else(|| "not available on this system".to_string()),
+  });
+  *recorded = fell_back.then(|| chosen.to_path_buf());
+  warning
+}
+
+fn resolve() -> Resolved {
+  let mut settings = longterm_storage::get_settings().ok();
+  let mut changed = false;
+  let mut dirs = Vec::new();
+  let mut fallbacks = Vec::new();
+  for dir in WorkDir::ALL {
+    let (chosen, reason) = choose(dir);
+    let mut unrecorded = None;
+    let recorded = match settings.as_mut() {
+      Some(settings) => dir.recorded(settings),
+      None => &mut unrecorded,
+    };
+    let before = recorded.clone();
+    fallbacks.extend(record(dir, dir.preferred(), &chosen, reason, recorded));
+    changed |= *recorded != before;
+    dirs.push((dir, chosen));
+  }
+  // Only written when a fallback started or stopped being used, not on every startup
+  if let Some(settings) = settings.filter(|_| changed) {
+    if let Err(e) = longterm_storage::set_settings(settings) {
+      log::warn!("Failed to record work directories: {}", e);
+    }
+  }
+  Resolved { dirs, fallbacks }
+}
+
+/// Check the work directories (once per run) and return the fallbacks that weren't in use
+/// before, for the startup warning. Later calls return the same list
+pub fn check() -> Vec<DirFallback> {
+  RESOLVED.get_or_init(resolve).fallbacks.clone()
+}
+
+/// Where files of `dir` go. Path producers use this rather than `dirs::`
+pub fn path(dir: WorkDir) -> Result<PathBuf> {
+  let resolved = RESOLVED.get_or_init(resolve);
+  let path = resolved
+    .dirs
+    .iter()
+    .find(|(d, _)| *d == dir)
+    .map(|(_, path)| path)
+    .ok_or_else(|| anyhow!("{:?} directory was not resolved", dir))?;
+  fs::create_dir_all(path).map_err(|e| anyhow!("{:?} directory {:?} is not writable: {}", dir, path, e))?;
+  Ok(path.clone())
+}
+
+/// `preferred` when files can be written there, else the exports directory. For output
+/// folders the user configured or named, which may be read-only or gone
+pub fn output_dir(preferred: Option<PathBuf>) -> PathBuf {
+  if let Some(dir) = preferred {
+    match check_writable(&dir) {
+      Ok(()) => return dir,
+      Err(e) => log::warn!("Output folder {:?} is not usable, exporting to the exports folder: {:#}", dir, e),
+    }
+  }
+  path(WorkDir::Exports).unwrap_or_else(|e| {
+    log::warn!("{:#}", e);
+    std::env::temp_dir()
+  })
+}
+
+/// `folder` when files can be written there, else the exports directory; see `output_dir`
+pub fn user_folder(folder: UserFolder) -> PathBuf {
+  output_dir(folder.location())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gebo-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn candidates_start_with_the_preferred_location_and_end_in_temp() {
+    for dir in WorkDir::ALL {
+      let candidates = dir.candidates();
+      if let Some(preferred) = dir.preferred() {
+        assert_eq!(candidates[0], preferred);
+      }
+      assert_eq!(candidates.last().unwrap(), &std::env::temp_dir().join("gebo").join(dir.subdir()));
+      let unique: std::collections::HashSet<&PathBuf> = candidates.iter().collect();
+      assert_eq!(unique.len(), candidates.len(), "{:?}", candidates);
+    }
+  }
+
+  #[test]
+  fn a_path_under_a_file_is_not_writable() {
+    let dir = temp_dir();
+    assert!(check_writable(&dir.join("new").join("nested")).is_ok());
+    fs::write(dir.join("file"), b"").unwrap();
+    assert!(check_writable(&dir.join("file").join("sub")).is_err());
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn unwritable_output_folders_fall_back() {
+    let dir = temp_dir();
+    assert_eq!(output_dir(Some(dir.clone())), dir);
+    fs::write(dir.join("file"), b"").unwrap();
+    let blocked = dir.join("file").join("exports");
+    let fallback = output_dir(Some(blocked.clone()));
+    assert_ne!(fallback, blocked);
+    assert!(check_writable(&fallback).is_ok());
+    let _ = fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn fallbacks_are_recorded_and_reported_once() {
+    let preferred = Some(PathBuf::from("/home/user/Downloads"));
+    let fallback = PathBuf::from("/home/user/.cache/gebo/previews");
+
+    // The preferred location in use: nothing to record or report
+    let mut recorded = None;
+    assert!(record(WorkDir::Previews, preferred.clone(), preferred.as_deref().unwrap(), None, &mut recorded).is_none());
+    assert_eq!(recorded, None);
+
+    // A new fallback is recorded and reported
+    let warning = record(WorkDir::Previews, preferred.clone(), &fallback, Some("read-only".to_string()), &mut recorded);
+    let warning = warning.unwrap();
+    assert_eq!((warning.chosen.as_path(), warning.reason.as_str()), (fallback.as_path(), "read-only"));
+    assert_eq!(recorded.as_ref(), Some(&fallback));
+
+    // The next startup finds it already recorded: same settings, no warning
+    let before = recorded.clone();
+    assert!(record(WorkDir::Previews, preferred.clone(), &fallback, Some("read-only".to_string()), &mut recorded).is_none());
+    assert_eq!(recorded, before);
+
+    // Once the preferred location works again the fallback is forgotten
+    record(WorkDir::Previews, preferred.clone(), preferred.as_deref().unwrap(), None, &mut recorded);
+    assert_eq!(recorded, None);
+  }
+}
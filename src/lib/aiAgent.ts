@@ -12,6 +12,25 @@ import type {
   MediaFile
 } from '../types';
 
+/**
+ * Convert a backend edit operation to frontend format
+ */
+const convertEditOperation = (edit: any): EditOperation => ({
+  id: edit.id,
+  type: edit.operation_type as EditOperation['type'],
+  description: edit.description,
+  parameters: edit.parameters,
+  targetClipId: edit.target_clip_id,
+  targetTrackId: edit.target_track_id,
+  timeRange: edit.time_range ? {
+    start: edit.time_range.start,
+    end: edit.time_range.end,
+  } : undefined,
+  previewData: edit.preview_data,
+  confidence: edit.confidence ?? undefined,
+  rationale: edit.rationale ?? undefined,
+});
+
 export class AIAgent {
   private isProcessing = false;
   private currentStream: ReadableStreamDefaultReader<Uint8Array> | null = null;
@@ -464,14 +483,16 @@ export class AIAgent {
     // Generate mock tighten operations
     const mockSilences = this.generateMockSilences(context.currentProject.duration, threshold);
     
+    // Keep the first leaveMs of each silence and cut the rest
     mockSilences.forEach((silence, index) => {
-      const newEnd = silence.start + (leaveMs / 1000);
+      const cutStart = silence.start + (leaveMs / 1000);
+      if (cutStart >= silence.end) return;
       operations.push({
         id: `tighten_${index}`,
         type: "trim",
-        description: `Tighten silence from ${silence.start.toFixed(2)}s to ${newEnd.toFixed(2)}s`,
+        description: `Tighten silence ${silence.start.toFixed(2)}s-${silence.end.toFixed(2)}s: cut ${cutStart.toFixed(2)}s to ${silence.end.toFixed(2)}s`,
         parameters: { threshold, leaveMs, originalRange: silence },
-        timeRange: { start: silence.start, end: newEnd }
+        timeRange: { start: cutStart, end: silence.end }
       });
     });
     
@@ -533,6 +554,9 @@ export class AIAgent {
         media_files: context.currentProject.mediaFiles,
         accepted_cuts: context.currentProject.acceptedCuts,
         preview_cuts: context.currentProject.previewCuts,
+        playhead: context.playhead ?? 0,
+        selection: context.selection ?? null,
+        selected_segment_ids: context.selectedSegmentIds ?? [],
       },
       user_intent: context.userIntent,
       conversation_history: context.conversationHistory,
@@ -555,19 +579,8 @@ export class AIAgent {
         timestamp: new Date(step.timestamp),
         duration: step.duration,
       })),
-      finalEdits: response.final_edits.map((edit: any) => ({
-        id: edit.id,
-        type: edit.operation_type as EditOperation['type'],
-        description: edit.description,
-        parameters: edit.parameters,
-        targetClipId: edit.target_clip_id,
-        targetTrackId: edit.target_track_id,
-        timeRange: edit.time_range ? {
-          start: edit.time_range.start,
-          end: edit.time_range.end,
-        } : undefined,
-        previewData: edit.preview_data,
-      })),
+      finalEdits: response.final_edits.map(convertEditOperation),
+      needsReview: (response.needs_review ?? []).map(convertEditOperation),
       hasVideoPreview: response.has_video_preview,
       videoPreview: response.video_preview ? {
         src: response.video_preview.src,
@@ -578,15 +591,31 @@ export class AIAgent {
         label: response.video_preview.label,
       } : undefined,
       actions: response.actions?.map((action: any) => ({
-        type: action.action_type as "accept" | "reject" | "custom" | "upload_video" | "upload_media" | "confirm_proceed",
+        type: action.action_type as "accept" | "reject" | "custom" | "upload_video" | "upload_media" | "confirm_proceed" | "export" | "conform_vfr" | "stretch_to_fit" | "prepare_clip",
         label: action.label,
-        onClick: () => {
+        output: action.output ?? undefined,
+        clipId: action.clip_id ?? undefined,
+        stretch: action.stretch ? {
+          trackId: action.stretch.track_id,
+          segmentId: action.stretch.segment_id,
+          targetDuration: action.stretch.target_duration,
+        } : undefined,
+        // Jobs run through ChatMessage's handlers so their progress reaches the chat
+        onClick: ["export", "conform_vfr", "stretch_to_fit", "prepare_clip", "confirm_proceed"].includes(action.action_type) ? undefined : () => {
           console.log(`Action clicked: ${action.action_type}`);
         },
       })),
     };
   }
 
+  /**
+   * Fetch the edits the backend held back because they cut too much of the project
+   */
+  async confirmProceed(messageId: string): Promise<AgentResponse> {
+    const response = await invoke('confirm_agent_proceed', { messageId }) as any;
+    return this.convertResponseFromTauriFormat(response);
+  }
+
   /**
    * Utility delay function
    */
@@ -631,3 +660,37 @@ export class AIAgent {
 export const aiAgent = new AIAgent();
 
 
+
+// Local-only agent request metrics for the diagnostics page
+export type IntentMetrics = {
+  intent: string;
+  requests: number;
+  local: number; // Handled by a local command parser
+  llm: number;
+  parse_failures: number;
+  errors: number;
+  validation_failures: number; // Requests with proposed edits that had to be clamped
+  accepted: number;
+  rejected: number;
+  avg_latency_ms: number;
+};
+export type AgentMetrics = {
+  enabled: boolean;
+  requests: number;
+  intents: IntentMetrics[]; // Most failures first
+};
+// `window` is in seconds; omit it for everything still kept
+export async function getAgentMetrics(window?: number): Promise<AgentMetrics> {
+  return await invoke('get_agent_metrics', { window: window ?? null }) as AgentMetrics;
+}
+// `messageId` is the backend id of the response whose proposal was decided
+export async function recordAgentDecision(messageId: string, accepted: boolean): Promise<void> {
+  await invoke('record_agent_decision', { messageId, accepted });
+}
+// Disabling also deletes what was collected
+export async function setAgentMetricsEnabled(enabled: boolean): Promise<void> {
+  await invoke('set_agent_metrics_enabled', { enabled });
+}
+export async function clearAgentMetrics(): Promise<void> {
+  await invoke('clear_agent_metrics');
+}
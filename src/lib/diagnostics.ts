@@ -28,6 +28,38 @@ export async function testFFmpeg(): Promise<{
   }
 }
 
+export type SelfTestStep = {
+  name: string;
+  status: "passed" | "failed" | "skipped";
+  duration_ms: number;
+  detail: string | null;
+  error: string | null; // Includes ffmpeg's stderr
+};
+
+export type SelfTestReport = {
+  passed: boolean;
+  ran_at: string;
+  ffmpeg_version: string | null;
+  system: unknown;
+  steps: SelfTestStep[];
+};
+
+/**
+ * Run the whole media pipeline (probe, waveform, thumbnails, proxy, export) on a generated clip
+ */
+export async function runSelfTest(): Promise<SelfTestReport> {
+  return await invoke("run_self_test") as SelfTestReport;
+}
+
+/**
+ * One line per step, for showing the report to the user
+ */
+export function summarizeSelfTest(report: SelfTestReport): string {
+  return report.steps
+    .map(step => `${step.status === "passed" ? "✓" : step.status === "failed" ? "✗" : "–"} ${step.name} (${step.duration_ms} ms)${step.error ? `: ${step.error}` : ""}`)
+    .join("\n");
+}
+
 /**
  * Diagnose preview generation issues
  */
@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 export type Probe = { 
   duration: number; 
@@ -10,23 +11,352 @@ export type Probe = {
   v_codec: string;
   a_codec: string;
   container: string;
+  avg_fps?: number;
+  is_vfr?: boolean; // Variable frame rate; trims drift until the clip is conformed
+  audio_stream?: number; // Audio stream (counting audio streams only) the audio fields describe: the default one
 };
 
 export async function probeVideo(path: string): Promise<Probe> {
   return await invoke("probe_video", { path }) as Probe;
 }
+
+export type StreamInfo = {
+  index: number;
+  codec_type: string; // "video", "audio", "subtitle", "data" or "attachment"
+  codec: string;
+  language: string | null; // e.g. "eng"
+  title: string | null;
+  channel_layout: string | null; // Audio only, e.g. "5.1(side)"
+  bit_rate: number | null;
+  rotation: number | null; // Video only: 0/90/180/270
+  attached_pic: boolean; // Cover art, not a playable video
+  dispositions?: string[]; // Flags set on the stream: "default" (plays by default), "comment" (commentary), "forced", ...
+};
+export type Chapter = { start: number; end: number; title: string | null };
+export type ProbeFull = {
+  duration: number;
+  container: string;
+  bit_rate: number | null;
+  streams: StreamInfo[];
+  chapters: Chapter[];
+  tags: Record<string, string>; // Container tags
+};
+// Every stream, chapter and container tag, e.g. to choose among audio languages
+export async function ffprobeFull(path: string): Promise<ProbeFull> {
+  return await invoke("ffprobe_full", { path }) as ProbeFull;
+}
+// [start, end] silences of at least minDuration seconds below noiseDb (e.g. -35); empty for files without audio
+export async function detectSilence(path: string, minDuration: number, noiseDb: number): Promise<[number, number][]> {
+  return await invoke("detect_silence", { path, minDuration, noiseDb }) as [number, number][];
+}
 export async function audioPeaks(path: string): Promise<number[]> {
   return await invoke("audio_peaks", { path }) as number[];
 }
-export async function exportCutlist(input: string, output: string, ranges: {start:number; end:number}[]) {
+// One peak array per channel (a single one for mono), for drawing stacked lanes
+export type ChannelPeaks = { channels: number; peaks: number[][] };
+export async function audioChannelPeaks(path: string): Promise<ChannelPeaks> {
+  return await invoke("audio_peaks", { path, channels: true }) as ChannelPeaks;
+}
+// Sent to the window that called audioPeaks while the file decodes
+export type WaveformProgress = { path: string; percent: number };
+export function onWaveformProgress(handler: (progress: WaveformProgress) => void): Promise<UnlistenFn> {
+  return listen<WaveformProgress>("waveform-progress", event => handler(event.payload));
+}
+// [min, max] pairs, one per samplesPerPeak samples at 8 kHz, of start..end seconds (whole file
+// when omitted). Ask for a short window at a finer resolution when zooming in
+export async function audioPeaksEx(path: string, samplesPerPeak: number, start?: number, end?: number): Promise<[number, number][]> {
+  return await invoke("audio_peaks_ex", { path, samplesPerPeak, start, end }) as [number, number][];
+}
+// audioPeaksEx plus the RMS level (0..1 of full scale) of each pair's samples, from the same
+// decode; RMS tells quiet speech from silence where peaks can't
+export type PeaksWithRms = { peaks: [number, number][]; rms: number[] };
+export async function audioPeaksWithRms(path: string, samplesPerPeak: number, start?: number, end?: number): Promise<PeaksWithRms> {
+  return await invoke("audio_peaks_ex", { path, samplesPerPeak, start, end, rms: true }) as PeaksWithRms;
+}
+// Level in dBFS (-96 for digital silence) per windowMs of the whole file; windowMs must be
+// 10 to 5000
+export async function loudnessProfile(path: string, windowMs: number): Promise<number[]> {
+  return await invoke("loudness_profile", { path, windowMs }) as number[];
+}
+// Waveform commands reject with { kind: "no_audio_stream" } for images and silent video
+export function isNoAudioStream(error: unknown): boolean {
+  return (error as { kind?: string } | null)?.kind === "no_audio_stream";
+}
+// Progress of an export as emitted in "export-progress" events; the last one has done set
+export type ExportProgress = {
+  job_id: string;
+  percent: number; // 0..100
+  eta_seconds: number | null;
+  done: boolean;
+  outcome: "Encoded" | "UpToDate" | null;
+  error: string | null; // Includes ffmpeg's stderr when the export failed
+};
+// Output format of an export; unset fields keep the defaults (H.264 CRF 20 + AAC 192k in mp4)
+export type ExportSettings = {
+  container?: "mp4" | "mov" | "mkv" | "webm" | "m4a";
+  video_codec?: "h264" | "h265" | "vp9" | "av1" | "prores";
+  audio_codec?: "aac" | "opus" | "flac" | "pcm";
+  faststart?: boolean | null;
+  pan?: number | null;
+  hdr_mode?: "auto" | "force_tonemap" | "passthrough";
+  waveform_video?: boolean;
+  encoder?: string | null;
+  rate_control?: { crf: number } | { bitrate: string } | null;
+  encoder_preset?: string | null; // x264/x265 speed preset, or 0-13 for AV1
+  max_height?: number | null;
+  audio_bitrate?: string | null;
+  normalize_loudness?: LoudnessTarget | null; // Two-pass EBU R128; skipped for files without audio
+  allow_upscale?: boolean; // Scale up to max_height / render above the sources' fps; otherwise clamped
+  audio_stream?: number | null; // The clip's audio stream (see Clip.audio_stream_index); null: the file's default
+};
+// What an export asking for more than its sources have did; see the validation report's warnings
+export type UpscaleDecision = "clamped" | "upscaled";
+// Defaults: -14 LUFS integrated, -1 dBTP true peak, 11 LU range
+export type LoudnessTarget = { integrated?: number; true_peak?: number; lra?: number };
+export type LoudnessStats = { integrated: number | null; true_peak: number | null; lra: number; threshold: number; target_offset: number };
+// Loudness of a file's audio (the clip's `audioStream`, else the file's default), or null when it
+// has none; integrated and true_peak are null for silence
+export async function measureLoudness(path: string, audioStream?: number | null): Promise<LoudnessStats | null> {
+  return await invoke("measure_loudness", { path, audioStream }) as LoudnessStats | null;
+}
+export type ExportPreset = { id: string; name: string; built_in: boolean; settings: ExportSettings };
+// Built-in presets followed by the project's custom ones
+export async function getExportPresets(): Promise<ExportPreset[]> {
+  return await invoke("get_export_presets") as ExportPreset[];
+}
+// Stores a custom preset in the project file, replacing the one with the same id
+export async function saveExportPreset(preset: ExportPreset): Promise<ExportPreset> {
+  return await invoke("save_export_preset", { preset }) as ExportPreset;
+}
+export async function deleteExportPreset(id: string): Promise<void> {
+  await invoke("delete_export_preset", { id });
+}
+// Team export settings, shared as a checksummed JSON file. Exports made with its settings record its id
+// in their sidecar and history entry; the validation report warns about exports that differ from it
+export type EncodeProfile = {
+  format?: string; // "gebo-encode-profile"; filled in on export
+  version?: number;
+  id?: string; // Generated on export when empty
+  name: string;
+  preset: ExportPreset;
+  min_ffmpeg_version?: string | null; // e.g. "6.1"
+  hdr_mode?: "auto" | "force_tonemap" | "passthrough";
+  loudness_target?: LoudnessTarget | null;
+  checksum?: string;
+};
+// Rejects profiles that were edited by hand or damaged (checksum mismatch)
+export async function importEncodeProfile(path: string): Promise<EncodeProfile> {
+  return await invoke("import_encode_profile", { path }) as EncodeProfile;
+}
+// Writes `profile`, or the imported one when omitted, for sharing with the team
+export async function exportEncodeProfile(path: string, profile?: EncodeProfile): Promise<EncodeProfile> {
+  return await invoke("export_encode_profile", { path, profile: profile ?? null }) as EncodeProfile;
+}
+export async function getEncodeProfile(): Promise<EncodeProfile | null> {
+  return await invoke("get_encode_profile") as EncodeProfile | null;
+}
+export async function clearEncodeProfile(): Promise<void> {
+  await invoke("clear_encode_profile");
+}
+// What keeps this machine from exporting as the profile asks: ffmpeg too old, encoders missing
+export async function checkEncodeProfile(): Promise<string[]> {
+  return await invoke("check_encode_profile") as string[];
+}
+// Starts the export as a job and resolves to its id; see onExportProgress and cancelExport.
+// `format` is either explicit settings or the id of an export preset
+export async function exportCutlist(input: string, output: string, ranges: {start:number; end:number}[], format?: ExportSettings | string): Promise<string> {
+  const pairs = ranges.map(r => [r.start, r.end]);
+  const settings = typeof format === "object" ? format : null;
+  const preset = typeof format === "string" ? format : null;
+  return await invoke("export_cutlist", { input, output, rangesToCut: pairs, settings, preset }) as string;
+}
+// Cut-list export without re-encoding: kept segments snap to the nearest keyframes, so each
+// boundary may move by up to a GOP. Resolves to the job id; the job result lists the actual times
+export type LosslessSegment = {
+  requested: [number, number];
+  snapped: [number, number];
+  reencoded: boolean; // Snapping collapsed it, so it was re-encoded at the requested times
+};
+export type LosslessExport = { segments: LosslessSegment[]; max_drift: number; output_duration: number };
+export async function exportCutlistLossless(input: string, output: string, ranges: {start:number; end:number}[], audioStream?: number | null): Promise<string> {
   const pairs = ranges.map(r => [r.start, r.end]);
-  await invoke("export_cutlist", { input, output, rangesToCut: pairs });
+  return await invoke("export_cutlist_lossless", { input, output, rangesToCut: pairs, audioStream }) as string;
+}
+export type AnimatedFormat = "gif" | "webp";
+
+// Job result of exportAnimated, under "animated"
+export type AnimatedExport = {
+  path: string;
+  format: AnimatedFormat;
+  duration: number;
+  size_bytes: number; // GIFs get large quickly; worth a warning past a few MB
+};
+
+// Export start..end of input as a looping GIF or animated WebP; returns the job id.
+// width defaults to 480 (never upscaled), fps to 15
+export async function exportAnimated(
+  input: string,
+  output: string,
+  start: number,
+  end: number,
+  format: AnimatedFormat,
+  width?: number,
+  fps?: number,
+): Promise<string> {
+  return await invoke("export_animated", { input, output, start, end, width, fps, format }) as string;
+}
+// What an export was made from and removed, read from its <output>.edits.json (written when the
+// export_edits_sidecar setting is on)
+export type LoadedExportEdits = {
+  path: string;
+  edits: {
+    exported_at: string;
+    app_version: string;
+    output: string;
+    source_duration: number;
+    sources: { path: string; size: number; content_hash: string }[];
+    removed: { start: number; end: number; duration: number }[];
+    removed_seconds: number;
+    kept: { start: number; end: number; output_start: number; source?: number }[];
+    settings_summary: string;
+  };
+  source_status: ("unchanged" | "changed" | "missing")[];
+  script: unknown; // Edit script rebuilding the cut list
+};
+export async function loadExportSidecar(path: string): Promise<LoadedExportEdits> {
+  return await invoke("load_export_sidecar", { path }) as LoadedExportEdits;
+}
+// Rebuilds an old export's cut list in the project at projectPath; resolves to the job id
+export async function reproduceExportEdits(path: string, projectPath: string): Promise<string> {
+  return await invoke("reproduce_export_edits", { path, projectPath }) as string;
+}
+// Stops an export (or a proxy started with a job id) and kills ffmpeg; false if it had already finished
+export async function cancelExport(jobId: string): Promise<boolean> {
+  return await invoke("cancel_export", { jobId }) as boolean;
+}
+export function onExportProgress(handler: (progress: ExportProgress) => void): Promise<UnlistenFn> {
+  return listen<ExportProgress>("export-progress", event => handler(event.payload));
+}
+// Backend job as emitted in "job-updated" events
+export type Job = {
+  id: string;
+  kind: string;
+  params: string; // Short summary of what the job was asked to do
+  status: "Queued" | "Running" | "Paused" | "Completed" | "Failed" | "Cancelled" | "Interrupted";
+  progress: number; // 0..1
+  message: string | null;
+  error: string | null;
+  failure?: "timeout" | "provider" | "network" | "cancelled" | "other" | null; // Set when failed
+  outputs: string[];
+  note?: string | null; // What happened to the job across a restart
+  pausable?: boolean; // Multi-part jobs that pauseJob accepts
+};
+// Pauses a pausable job after its current step; rejects for single-step jobs
+export async function pauseJob(jobId: string): Promise<void> {
+  await invoke("pause_job", { jobId });
+}
+export async function resumeJob(jobId: string): Promise<void> {
+  await invoke("resume_job", { jobId });
+}
+// Exports queued or interrupted when the app last exited; the home page offers to resume them on launch
+export async function getPendingJobs(): Promise<Job[]> {
+  return await invoke("get_pending_jobs") as Job[];
 }
-export async function makePreviewProxy(path: string): Promise<string> {
-  return await invoke("make_preview_proxy", { input: path }) as string;
+// Restarts a pending job from the beginning; resolves to the new job id
+export async function resumePendingJob(jobId: string): Promise<string> {
+  return await invoke("resume_pending_job", { jobId }) as string;
 }
-export async function readFileAsBase64(path: string): Promise<string> {
-  return await invoke("read_file_as_base64", { path }) as string;
+export async function discardPendingJob(jobId: string): Promise<void> {
+  await invoke("discard_pending_job", { jobId });
+}
+// Renders the project's timeline with its accepted cuts and the default preset; resolves to the job id
+export async function exportProject(output?: string): Promise<string> {
+  return await invoke("export_project", { output: output ?? null }) as string;
+}
+// Renders the whole timeline (video track with black gaps, every unmuted audio track mixed);
+// resolves to the job id. `format` is either explicit settings or the id of an export preset
+export async function exportTimeline(output?: string, format?: ExportSettings | string): Promise<string> {
+  const settings = typeof format === "object" ? format : null;
+  const preset = typeof format === "string" ? format : null;
+  return await invoke("export_timeline", { output: output ?? null, settings, preset }) as string;
+}
+// Exports one segment with its effects and audio adjustments (stream-copied when unmodified); resolves to the job id
+export async function exportSegment(trackId: string, segmentId: string, output: string): Promise<string> {
+  return await invoke("export_segment", { trackId, segmentId, output }) as string;
+}
+// Re-encodes a VFR clip to constant frame rate at the project fps; resolves to the job id.
+// With `retarget`, segments playing the clip switch to the conformed copy
+export async function conformVfr(clipId: string, retarget = false): Promise<string> {
+  return await invoke("conform_vfr", { clipId, retarget }) as string;
+}
+// Stretches `range` (default: all) of a clip's audio to `targetDuration` as a new clip; resolves to
+// the job id. The job result reports the achieved duration
+export async function timeStretchClip(clipId: string, targetDuration: number, range?: [number, number], preservePitch = true): Promise<string> {
+  return await invoke("time_stretch_clip", { clipId, range, targetDuration, preservePitch }) as string;
+}
+// Stretches an audio-track segment to play for `targetDuration`; resolves to the job id
+export async function stretchSegmentToFit(trackId: string, segmentId: string, targetDuration: number, preservePitch = true): Promise<string> {
+  return await invoke("stretch_segment_to_fit", { trackId, segmentId, targetDuration, preservePitch }) as string;
+}
+export type PrepareOptions = { proxy?: boolean; waveform?: boolean; thumbnails?: boolean; transcript?: boolean; analysis?: boolean };
+export type PrepareStep = "proxy" | "waveform" | "thumbnails" | "audio" | "transcript" | "analysis";
+export type PrepareStepReport = { step: PrepareStep; state: "pending" | "running" | "done" | "skipped" | "failed"; detail: string | null; error: string | null };
+export type PrepareProgress = { job_id: string; clip_id: string; steps: PrepareStepReport[]; percent: number; done: boolean };
+// Generates a clip's proxy, waveform, thumbnails and transcript (plus the Gemini analysis when
+// asked for) in one pausable job; resolves to the job id
+export async function prepareClip(clipId: string, options?: PrepareOptions): Promise<string> {
+  return await invoke("prepare_clip", { clipId, options: options ?? null }) as string;
+}
+// Per-step state of running prepareClip jobs
+export function onClipPrepareProgress(handler: (progress: PrepareProgress) => void): Promise<UnlistenFn> {
+  return listen<PrepareProgress>("clip-prepare-progress", event => handler(event.payload));
+}
+// Which video encoder wrote a file; fell_back is set when the requested hardware encoder
+// failed and libx264 redid the encode
+export type EncoderUsed = { encoder: string; fell_back: boolean };
+export type EncodedFile = { path: string; encoder: EncoderUsed | null };
+// "libx264" plus the hardware H.264 encoders that work on this machine
+export async function listAvailableEncoders(): Promise<string[]> {
+  return await invoke("list_available_encoders") as string[];
+}
+// With `jobId`, the encode can be stopped with cancelExport(jobId). `audioStream` is the clip's
+// audio stream; the file's default one when unset
+export async function makePreviewProxy(path: string, jobId?: string, encoder?: string, audioStream?: number | null): Promise<EncodedFile> {
+  return await invoke("make_preview_proxy", { input: path, audioStream, jobId, encoder }) as EncodedFile;
+}
+// Sent while a proxy encodes; the last event for an input has done set
+export type ProxyProgress = {
+  input: string;
+  job_id: string | null;
+  percent: number;
+  done: boolean;
+  cached: boolean; // An up-to-date proxy was reused, nothing was encoded
+};
+export function onProxyProgress(handler: (progress: ProxyProgress) => void): Promise<UnlistenFn> {
+  return listen<ProxyProgress>("proxy-progress", event => handler(event.payload));
+}
+// Bytes used by cached preview proxies
+export async function getProxyCacheSize(): Promise<number> {
+  return await invoke("get_proxy_cache_size") as number;
+}
+// Delete every cached preview proxy; returns the bytes freed
+export async function clearProxyCache(): Promise<number> {
+  return await invoke("clear_proxy_cache") as number;
+}
+// Bytes used by cached waveform peaks and spectrograms
+export async function getWaveformCacheSize(): Promise<number> {
+  return await invoke("get_waveform_cache_size") as number;
+}
+// Delete every cached waveform and spectrogram; they are decoded again when next shown. Returns the bytes freed
+export async function clearWaveformCache(): Promise<number> {
+  return await invoke("clear_waveform_cache") as number;
+}
+export type ReadFileError =
+  | { kind: "too_large"; message: string } // Over maxBytes (default 10 MB, at most 50 MB)
+  | { kind: "failed"; message: string };
+/** @deprecated Load media by URL with getMediaUrl, or in pieces with readFileChunk */
+export async function readFileAsBase64(path: string, maxBytes?: number): Promise<string> {
+  return await invoke("read_file_as_base64", { path, maxBytes }) as string;
 }
 export async function copyToAppData(path: string): Promise<string> {
   return await invoke("copy_to_app_data", { path }) as string;
@@ -38,31 +368,82 @@ export async function getFileSize(path: string): Promise<number> {
   return await invoke("get_file_size", { path }) as number;
 }
 
+// Cached on disk by file, count and width; unchanged files don't run ffmpeg again
 export async function generateThumbnails(path: string, count: number, width: number): Promise<string[]> {
   return await invoke("generate_thumbnails", { path, count, width }) as string[];
 }
 
+// Empty the thumbnail cache (capped by the thumbnail_cache_mb setting, 500 MB by default); resolves to the bytes freed
+export async function clearThumbnailCache(): Promise<number> {
+  return await invoke("clear_thumbnail_cache") as number;
+}
+
+export type StillFrame = {
+  image: string; // Base64
+  format: "png" | "jpeg";
+  timestamp: number; // Where the frame was taken; past-the-end times give the last frame
+};
+
+// The frame at timestamp, at most width wide (full size when omitted). accurate (default) matches
+// the playhead exactly. Rejects with { kind: "no_video_stream" } for audio, see isNoVideoStream
+export async function extractFrame(
+  path: string,
+  timestamp: number,
+  options: { width?: number; format?: "png" | "jpeg"; accurate?: boolean } = {},
+): Promise<StillFrame> {
+  return await invoke("extract_frame", { path, timestamp, ...options }) as StillFrame;
+}
+
+export function isNoVideoStream(error: unknown): boolean {
+  return (error as { kind?: string } | null)?.kind === "no_video_stream";
+}
+
+// Media URL of the embedded album art, null when there is none
 export async function extractAlbumArt(path: string): Promise<string | null> {
   return await invoke("extract_album_art", { path }) as string | null;
 }
+// Media URL of an audio file's poster: its album art, else a waveform picture
+export async function getPosterUrl(path: string): Promise<string | null> {
+  return await invoke("get_poster_url", { path }) as string | null;
+}
+// True when every clip is audio: previews are audio-only and thumbnails are album art or waveforms
+export async function isAudioOnlyProject(): Promise<boolean> {
+  return await invoke("is_audio_only_project") as boolean;
+}
+
+export type AudioMetadata = {
+  title: string | null;
+  artist: string | null;
+  album: string | null;
+  duration: number;
+  art: string | null; // Media URL of the PNG
+};
+
+export async function getAudioMetadata(path: string): Promise<AudioMetadata> {
+  return await invoke("get_audio_metadata", { path }) as AudioMetadata;
+}
 
 export type TimelineClip = {
   media_path: string;
   start_time: number;
   end_time: number;
   offset: number;
+  pan?: number; // Pan of the clip's track, -100..100
+  audio_stream?: number | null; // The clip's audio stream; null: the file's default
 };
 
 export async function generateTimelinePreview(
   clips: TimelineClip[],
   outputWidth: number,
-  totalDuration: number
-): Promise<string> {
+  totalDuration: number,
+  encoder?: string
+): Promise<EncodedFile> {
   return await invoke("generate_timeline_preview", {
     clips,
     outputWidth,
     totalDuration,
-  }) as string;
+    encoder,
+  }) as EncodedFile;
 }
 
 export async function generateAdaptiveTimelinePreview(
@@ -78,3 +459,31 @@ export async function generateAdaptiveTimelinePreview(
     totalDuration,
   }) as string;
 }
+
+// A work directory that replaced its unwritable preferred location (e.g. Downloads)
+export type DirFallback = {
+  dir: "previews" | "cache" | "library" | "exports";
+  preferred: string | null;
+  chosen: string;
+  reason: string;
+};
+// Fallbacks that started being used this run; the home page warns about them on launch
+export async function getWorkDirFallbacks(): Promise<DirFallback[]> {
+  return await invoke("get_work_dir_fallbacks") as DirFallback[];
+}
+// Frame-accurate stepping for the preview player. A stepper keeps a decoder open on the clip,
+// so stepping forward or back is fast; close it when done (idle ones close themselves)
+export type SteppedFrame = { index: number; time: number; jpeg: string }; // jpeg is base64
+export type StepperInfo = { stepper_id: string; clip_id: string; fps: number; frame_count: number; frame: SteppedFrame };
+export async function openFrameStepper(clipId: string, time?: number, maxWidth?: number): Promise<StepperInfo> {
+  return await invoke("open_frame_stepper", { clipId, time: time ?? null, maxWidth: maxWidth ?? null }) as StepperInfo;
+}
+export async function stepFrame(stepperId: string, direction: "next" | "previous"): Promise<SteppedFrame> {
+  return await invoke("step_frame", { stepperId, direction }) as SteppedFrame;
+}
+export async function seekFrameStepper(stepperId: string, time: number): Promise<SteppedFrame> {
+  return await invoke("seek_frame_stepper", { stepperId, time }) as SteppedFrame;
+}
+export async function closeFrameStepper(stepperId: string): Promise<void> {
+  await invoke("close_frame_stepper", { stepperId });
+}
@@ -8,3 +8,31 @@ export async function addRecentProject(path: string): Promise<void> {
 export async function getRecentProjects(): Promise<string[]> {
     return await invoke("get_recent_projects") as string[];
 }
+
+export interface LockHolder {
+    host: string,
+    pid: number,
+    acquired_at: string, // ISO 8601
+}
+
+export type LockStatus =
+    | { state: "unlocked" }
+    | { state: "ours" } // Open in this app
+    | { state: "held", holder: LockHolder } // Open in another app, here or on another machine
+    | { state: "stale", holder: LockHolder | null }; // Left by an app that's no longer running
+
+export interface RecentProjectLock {
+    path: string,
+    lock: LockStatus,
+    recovered: boolean, // Its stale lock was removed at startup: the last session crashed
+}
+
+/** Recent projects with their lock status, for "in use" / "recovered" badges */
+export async function getRecentProjectLocks(): Promise<RecentProjectLock[]> {
+    return await invoke("get_recent_project_locks") as RecentProjectLock[];
+}
+
+/** Remove a project's lock whoever holds it. Ask the user first and pass `confirmed: true` */
+export async function forceUnlockProject(path: string, confirmed: boolean): Promise<LockStatus> {
+    return await invoke("force_unlock_project", { path, confirmed }) as LockStatus;
+}
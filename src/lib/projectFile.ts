@@ -1,4 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
+import { type UnlistenFn } from "@tauri-apps/api/event";
+import { getCurrentWindow } from "@tauri-apps/api/window";
 import { useState } from "react";
 import { type Probe } from "./ffmpeg";
 export interface Clip {
@@ -6,6 +8,12 @@ export interface Clip {
     path: string, // PathBuf
     latest_probe?: Probe, // Optional cached probe data
     type: "Video" | "Audio" | "Image", // Media type
+    preview_lut?: string | null, // .cube for thumbnails and previews only, relative to the project folder
+    creation_time?: string | null, // Recording start (ISO 8601) from the file's tags
+    transcript_language?: string | null, // ISO 639 code reported or detected for the transcript
+    language_override?: string | null, // Set with setClipLanguage; wins over transcript_language
+    has_album_art?: boolean | null, // Whether an audio clip embeds cover art; null until checked. extractAlbumArt gets it
+    audio_stream_index?: number | null, // Set with setClipAudioStream; wins over the file's default audio stream
 }
 
 export interface Segment {
@@ -14,6 +22,12 @@ export interface Segment {
     clip_id: string, // Reference to the Clip by ID
     start: number,     // Start time in seconds within the clip
     end: number,       // End time in seconds within the clip
+    timeline_start?: number | null, // Fixed timeline position; unset plays right after the previous segment
+}
+
+export interface Effect {
+    type: string,
+    parameters?: Record<string, unknown>,
 }
 
 export type TrackType = "Video" | "Audio" | "Text" | "Effect";
@@ -24,7 +38,9 @@ export interface Track {
     enabled: boolean,
     muted: boolean,
     volume: number, // 0-100 for audio tracks, else does not matter
+    pan?: number, // Stereo balance, -100 (left) to 100 (right)
     order: number, // Order of the track in the timeline
+    track_effects?: Effect[], // acompressor, agate, highpass, anequalizer, deesser; after each segment's own processing
 
     segments: Segment[], // Segments in this track. Order matters
 }
@@ -32,9 +48,12 @@ export interface Track {
 
 export interface ProjectFile {
     title: string;
+    version?: number; // Schema version; older files are upgraded when loaded, newer ones refused
     clips_map: Map<string, Clip>;
     tracks_map: Map<string, Track>;
     path: string;
+    view_state?: TimelineViewState;
+    retention_risks?: RiskRegion[]; // From the last findRetentionRisks
 }
 
 export async function loadProject(path: string): Promise<ProjectFile> {
@@ -57,6 +76,337 @@ export async function newProject(project: ProjectFile): Promise<ProjectFile> {
     return await invoke("new_project", { projectFile: project }) as ProjectFile;
 }
 
+// Generate the sample media offline in destDir and open the sample project (also added to recent projects).
+// Same content at the same timestamps every time
+export async function createSampleProject(destDir: string): Promise<ProjectFile> {
+    return await invoke("create_sample_project", { destDir }) as ProjectFile;
+}
+
+// Backup `which` (1 is the newest, up to 3) of the project saved at `path`, or the newest readable one.
+// Only returned: neither the project file nor the open project changes
+export async function restoreProjectBackup(path: string, which?: number): Promise<ProjectFile> {
+    return await invoke("restore_project_backup", { path, which: which ?? null }) as ProjectFile;
+}
+
 export async function singleReadProject(path: string): Promise<ProjectFile> {
     return await invoke("single_read_project", { path }) as ProjectFile;
-}
\ No newline at end of file
+}
+
+export interface DroppedFile {
+    path: string;
+    outcome: "Imported" | "ProjectOpened" | "Failed";
+    clip: Clip | null;
+    error: string | null;
+    warnings?: string[]; // Imported, but likely to cause trouble (e.g. variable frame rate)
+}
+
+export interface FilesImported {
+    files: DroppedFile[];
+    project: ProjectFile | null; // Current project after the drop
+}
+
+// Files dropped on this window are validated and imported by the backend, which reports here
+export async function onFilesImported(callback: (result: FilesImported) => void): Promise<UnlistenFn> {
+    return await getCurrentWindow().listen<FilesImported>("files-imported", (event) => callback(event.payload));
+}
+
+// This editor's project was loaded again after another editor's was in use
+export async function onProjectActivated(callback: (project: ProjectFile) => void): Promise<UnlistenFn> {
+    return await getCurrentWindow().listen<ProjectFile>("project-activated", (event) => callback(event.payload));
+}
+
+export interface TrackPatch {
+    name?: string;
+    enabled?: boolean;
+    muted?: boolean;
+    volume?: number;
+    pan?: number;
+    track_effects?: Effect[];
+    preset?: "Podcast voice" | "Clean up room noise"; // Replaces track_effects
+}
+
+export async function updateTrack(trackId: string, patch: TrackPatch): Promise<Track> {
+    return await invoke("update_track", { trackId, patch }) as Track;
+}
+
+// Magnitudes of a clip's audio for a heatmap: magnitudes[band][bucket], 0 is -120 dBFS and 255 is 0 dBFS
+export interface Spectrogram {
+    start: number; // Clip seconds
+    end: number;
+    bucket_seconds: number;
+    band_edges: number[]; // Hz, one more than there are bands, lowest first
+    magnitudes: number[][]; // One row per band, lowest first
+}
+
+// bands (8..512, log-spaced from 20 Hz to 8 kHz) × resolution (1..4096) time buckets of
+// range [start, end] of a clip's audio; cached with the waveforms
+export async function getSpectrogram(clipId: string, range: [number, number], bands: number, resolution: number): Promise<Spectrogram> {
+    return await invoke("get_spectrogram", { clipId, range, bands, resolution }) as Spectrogram;
+}
+
+export interface TonalPeak {
+    frequency: number; // Hz
+    prominence_db: number; // Above the spectrum around it
+    presence: number; // Share of the range it was found in, 0..1
+}
+
+export interface DenoiseSuggestion {
+    peaks: TonalPeak[]; // Strongest first
+    effects: Effect[]; // highpass / anequalizer track effects removing them; empty when nothing stands out
+    track_id: string | null; // Track playing the clip; apply with updateTrack(track_id, { track_effects: [...current, ...effects] })
+    summary: string;
+}
+
+// Constant tones (mains hum, whine, rumble) in range [start, end] of a clip, with the track
+// effects that would remove them
+export async function suggestDenoiseFilter(clipId: string, range: [number, number]): Promise<DenoiseSuggestion> {
+    return await invoke("suggest_denoise_filter", { clipId, range }) as DenoiseSuggestion;
+}
+
+// Segment fields to overwrite; unset ones are left alone
+export interface SegmentPatch {
+    gain?: number;
+    fade_in?: number;
+    fade_out?: number;
+    fade_curve?: "linear" | "exponential" | "s_curve";
+    video_fade_in?: number;
+    video_fade_out?: number;
+    speed?: number;
+    effects?: Effect[];
+}
+
+// One edit in a transact call. Empty ids on added segments, tracks and markers are filled in
+export type ProjectOp =
+    | { op: "add_segment"; track_id: string; segment: Segment; index?: number | null } // Appended without an index
+    | { op: "remove_segment"; track_id: string; segment_id: string }
+    | { op: "move_segment"; segment_id: string; to_track_id: string; index?: number | null; timeline_start?: number | null }
+    | { op: "update_segment"; track_id: string; segment_id: string; patch: SegmentPatch }
+    | { op: "add_track"; track: Track }
+    | { op: "remove_track"; track_id: string }
+    | { op: "update_track"; track_id: string; patch: TrackPatch }
+    | { op: "add_marker"; marker: Marker }
+    | { op: "remove_marker"; marker_id: string };
+
+// Rejection of transact: the failing op, or null when the result as a whole was rejected
+export interface TransactionError {
+    op_index: number | null;
+    message: string;
+}
+
+// Apply ops in order as one undo step and one save, or none of them if any fails (rejects
+// with a TransactionError). Resolves to the updated project
+export async function transact(ops: ProjectOp[]): Promise<ProjectFile> {
+    return await invoke("transact", { ops }) as ProjectFile;
+}
+
+// Timeline layout stored in the project, so it reopens the same for everyone
+export interface TimelineViewState {
+    track_heights: Record<string, number>; // Track id -> height in pixels
+    collapsed_tracks: string[]; // Track ids
+    zoom: number | null; // Pixels per second
+    scroll: number | null; // Timeline seconds at the left edge
+}
+
+export interface ViewStatePatch {
+    track_heights?: Record<string, number | null>; // Merged in; null goes back to the default height
+    collapsed_tracks?: string[];
+    zoom?: number;
+    scroll?: number;
+}
+
+export async function getViewState(): Promise<TimelineViewState> {
+    return await invoke("get_view_state") as TimelineViewState;
+}
+
+// Not an undo step. Saved right away only with the save_view_changes setting, otherwise with the next edit
+export async function setViewState(patch: ViewStatePatch): Promise<TimelineViewState> {
+    return await invoke("set_view_state", { patch }) as TimelineViewState;
+}
+
+// Set (or clear with null) the LUT applied to a clip's thumbnails and previews, e.g. for log footage
+export async function setClipPreviewLut(clipId: string, lut: string | null): Promise<Clip> {
+    return await invoke("set_clip_preview_lut", { clipId, lut }) as Clip;
+}
+
+export interface AlignedClip {
+    clip_id: string;
+    track_id: string;
+    segment_id: string;
+    timeline_start: number;
+    aligned: boolean; // False when the clip had no creation time and was placed at the end
+}
+
+export interface AlignResult {
+    placed: AlignedClip[];
+    missing_creation_time: string[]; // Clip ids
+}
+
+// Place clips at their recording start times (earliest at 0), one target track per clip or one for all.
+// `clockOffsets` (seconds, by clip id) correct camera clocks; starts within `tolerance` seconds snap together
+export async function alignClipsByTimecode(
+    clipIds: string[],
+    targetTrackIds: string[],
+    tolerance?: number,
+    clockOffsets?: Record<string, number>,
+): Promise<AlignResult> {
+    return await invoke("align_clips_by_timecode", {
+        clipIds,
+        targetTrackIds,
+        tolerance: tolerance ?? null,
+        clockOffsets: clockOffsets ?? null,
+    }) as AlignResult;
+}
+
+export interface RenamedId {
+    kind: "clip" | "track" | "segment" | "marker";
+    old_id: string;
+    new_id: string;
+}
+
+// Rewrite duplicated ids in the current project; segments follow renamed clips
+export async function repairDuplicateIds(): Promise<RenamedId[]> {
+    return await invoke("repair_duplicate_ids") as RenamedId[];
+}
+
+export interface Artifact {
+    path: string;
+    stale: boolean; // A source stream it depends on changed
+    depends_on: ("video" | "audio")[];
+    source_clip: string | null; // Clip it was generated from, when reused from the clip this one derives from
+}
+
+export interface ClipDetails {
+    clip_id: string;
+    path: string;
+    type: "Video" | "Audio" | "Image";
+    exists: boolean;
+    probe: Probe | null;
+    proxy: Artifact | null;
+    waveform: Artifact | null;
+    thumbnail: Artifact | null;
+    transcript_segments: number | null; // null until transcribed
+    ready_for_ai: string | null; // When prepareClip last finished for it
+    language: string | null; // ISO 639 code: the override, else the detected language
+    language_overridden: boolean;
+    usage_count: number;
+    derived_from: string | null;
+    hashes: unknown | null;
+    hash_status: "Verified" | "Mismatch" | "Missing" | "Unreadable" | "Unhashed" | "Unchecked";
+}
+
+// Everything the media panel shows about a clip, read from caches without generating anything
+export async function getClipDetails(clipId: string): Promise<ClipDetails> {
+    return await invoke("get_clip_details", { clipId }) as ClipDetails;
+}
+
+// Set the language a clip is spoken in ("es", "en-US", "Spanish"), or null to go back to detection
+export async function setClipLanguage(clipId: string, language: string | null): Promise<Clip> {
+    return await invoke("set_clip_language", { clipId, language }) as Clip;
+}
+
+// Pick the audio stream a clip plays (counting audio streams only, as in ProbeFull.streams
+// filtered to "audio"), or null to go back to the one the file marks default
+export async function setClipAudioStream(clipId: string, audioStream: number | null): Promise<Clip> {
+    return await invoke("set_clip_audio_stream", { clipId, audioStream }) as Clip;
+}
+
+// "auto" matches the audio of both files; { manual: seconds } is added to every time of the old
+// file (negative when its head was trimmed)
+export type OffsetAdjustment = "auto" | { manual: number };
+
+export interface SegmentShift {
+    track_id: string;
+    segment_id: string;
+    before: [number, number]; // Start and end in the old file
+    after: [number, number]; // In the new file; before when left as it was
+    reason: string | null; // Why the segment was flagged
+}
+
+export interface SourceReplacement {
+    clip: Clip;
+    offset: number;
+    correlation: number | null; // Of the audio at offset, with "auto"
+    adjusted: SegmentShift[];
+    flagged: SegmentShift[]; // Cut to the new file's length, or left as they were
+}
+
+// Point a clip at a re-edit of its file and move every segment playing it by the offset between
+// the two, as one undo step
+export async function replaceClipSource(clipId: string, newPath: string, offsetAdjustment: OffsetAdjustment): Promise<SourceReplacement> {
+    return await invoke("replace_clip_source", { clipId, newPath, offsetAdjustment }) as SourceReplacement;
+}
+
+// Write the timeline's captions as .srt or .vtt. Clips in several languages give one file per
+// language (name.en.srt, name.es.srt); resolves to the files written
+export async function exportTranscript(output: string): Promise<string[]> {
+    return await invoke("export_transcript", { output }) as string[];
+}
+
+// Output time (after the accepted cuts) of each timeline time; null for times inside a cut
+export async function remapToOutputTime(times: number[]): Promise<(number | null)[]> {
+    return await invoke("remap_to_output_time", { times }) as (number | null)[];
+}
+
+export interface Marker {
+    id: string;
+    time: number; // Timeline seconds (output seconds from getOutputMarkers)
+    label: string;
+    color: string | null; // CSS hex color
+}
+
+// Markers at their output times for the edited view; markers inside accepted cuts are left out
+export async function getOutputMarkers(): Promise<Marker[]> {
+    return await invoke("get_output_markers") as Marker[];
+}
+
+export interface ChapterProposal {
+    marker: Marker; // Timeline time, ready for addMarkers
+    output_time: number; // Where the chapter starts in the edited output
+}
+
+// Propose up to targetCount chapters of the edited output for review: the first at 0:00, each at
+// least 10 seconds long. Titles come from the LLM when an API key is set
+export async function generateChapters(targetCount: number): Promise<ChapterProposal[]> {
+    return await invoke("generate_chapters", { targetCount }) as ChapterProposal[];
+}
+
+// A stretch of the edited output viewers are likely to skip
+export interface RiskRegion {
+    start: number; // Output seconds, with the cuts accepted when it was found taken out
+    end: number;
+    score: number; // 0 (holds attention) to 1 (likely skipped)
+    reason: string;
+}
+
+// Find the five stretches of the edited output viewers are most likely to skip, and keep them on
+// the project. Works without an API key; the reasons are then generic
+export async function findRetentionRisks(): Promise<RiskRegion[]> {
+    return await invoke("find_retention_risks") as RiskRegion[];
+}
+
+// Markers at the retention risks kept on the project, ready for addMarkers
+export async function retentionRiskMarkers(): Promise<Marker[]> {
+    return await invoke("retention_risk_markers") as Marker[];
+}
+
+// Add markers (e.g. accepted chapters) to the project; resolves to all of its markers
+export async function addMarkers(markers: Marker[]): Promise<Marker[]> {
+    return await invoke("add_markers", { markers }) as Marker[];
+}
+
+// Cut the most engaging moment of each video clip to the beats of the music clip, in the
+// order given, on new "Montage" tracks (one undo step); resolves to the updated project
+export async function buildMontage(musicClipId: string, videoClipIds: string[], targetDuration: number): Promise<ProjectFile> {
+    return await invoke("build_montage", { musicClipId, videoClipIds, targetDuration }) as ProjectFile;
+}
+
+// Regenerates only the stale proxy/waveform/thumbnail caches of the clips (all when omitted); resolves to the job id
+export async function warmClipCaches(clipIds?: string[]): Promise<string> {
+    return await invoke("warm_clip_caches", { clipIds: clipIds ?? null }) as string;
+}
+
+// Apply a JSON edit script to a project (created if missing), which becomes the current project.
+// Runs as a job whose result is the report, also written next to the script; resolves to the job id
+export async function runEditScript(scriptPath: string, projectPath: string): Promise<string> {
+    return await invoke("run_edit_script", { scriptPath, projectPath }) as string;
+}
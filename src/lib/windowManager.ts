@@ -67,11 +67,50 @@ export async function returnToHomeSize(): Promise<void> {
   await centerWindow();
 }
 
-export async function openEditorWindow(): Promise<void> {
+/** Open an editor on the loaded project, or focus the one already open on it. Each project gets an editor window of its own */
+export async function openEditorWindow(projectPath?: string): Promise<void> {
   try {
-    await invoke('create_editor_window');
+    await invoke('create_editor_window', { projectPath });
   } catch (error) {
     console.error('Failed to create editor window:', error);
     throw error;
   }
 }
+
+/** Close an editor window without the unsaved-project check, once the user has decided */
+export async function closeEditorWindow(): Promise<void> {
+  await invoke('close_editor_window');
+}
+
+/** What closing a window or quitting would lose; each code gets its own wording in the dialog */
+export type ExitBlockerCode = 'unsaved_project' | 'pending_proposal' | 'active_job';
+
+export interface ExitBlocker {
+  code: ExitBlockerCode;
+  message: string;
+  ids: string[]; // Message ids of the proposals, ids of the jobs
+}
+
+/** Payload of "close-requested"; `window` is null when the whole app is quitting */
+export interface CloseRequest {
+  window: string | null;
+  blockers: ExitBlocker[];
+}
+
+/** What quitting now would lose */
+export async function getExitBlockers(): Promise<ExitBlocker[]> {
+  return await invoke('get_exit_blockers') as ExitBlocker[];
+}
+
+/** Go ahead with the close announced by "close-requested". Without `force` nothing happens while
+ * something is still in the way, and that is returned instead */
+export async function confirmClose(force: boolean): Promise<ExitBlocker[]> {
+  return await invoke('confirm_close', { force }) as ExitBlocker[];
+}
+
+/** Ask whether to close anyway, listing what would be lost */
+export function confirmLosing(blockers: ExitBlocker[]): boolean {
+  if (blockers.length === 0) return true;
+  const lost = blockers.map(blocker => `- ${blocker.message}`).join('\n');
+  return window.confirm(`Closing now will lose:\n${lost}\n\nClose anyway?`);
+}
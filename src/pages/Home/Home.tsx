@@ -1,7 +1,11 @@
 import { useState, useEffect } from 'react';
-import { Plus, FolderOpen, Film } from 'lucide-react';
+import { Plus, FolderOpen, Film, Music, Stethoscope } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/core';
+import { discardPendingJob, getAudioMetadata, getPendingJobs, getWorkDirFallbacks, resumePendingJob, type DirFallback, type Job } from '../../lib/ffmpeg';
+import { runSelfTest, summarizeSelfTest } from '../../lib/diagnostics';
 import { open, save } from '@tauri-apps/plugin-dialog';
+import { getCurrentWindow } from '@tauri-apps/api/window';
+import { confirmClose, confirmLosing, type CloseRequest } from '../../lib/windowManager';
 
 // Advanced glassmorphism styles
 const glassStyles = `
@@ -312,7 +316,7 @@ interface LoadingScreenProps {
 
 interface RecentProjectProps {
   projectPath: string;
-  onLoadingStart: (title: string) => void;
+  onLoadingStart: (title: string, path: string) => void;
   onProjectOpened: () => void;
 }
 
@@ -392,9 +396,10 @@ const addRecentProject = async (path: string): Promise<void> => {
   }
 };
 
-const openEditorWindow = async (): Promise<void> => {
+const openEditorWindow = async (projectPath?: string): Promise<void> => {
   try {
-    await invoke('create_editor_window');
+    // Focuses the project's editor if it is already open
+    await invoke('create_editor_window', { projectPath });
   } catch (error) {
     console.error('Failed to open editor window:', error);
     throw error;
@@ -500,6 +505,8 @@ const LoadingScreen: React.FC<LoadingScreenProps> = ({ onComplete, projectTitle
 const RecentProject: React.FC<RecentProjectProps> = ({ projectPath, onLoadingStart, onProjectOpened }) => {
   const projectName = projectPath.split('/').pop()?.replace('.vid', '') || 'Untitled Project';
   const [thumbnail, setThumbnail] = useState<string>('');
+  const [albumArt, setAlbumArt] = useState<string | null>(null);
+  const [isMusic, setIsMusic] = useState(false);
   
   useEffect(() => {
     // Generate gradient based on project name
@@ -511,9 +518,28 @@ const RecentProject: React.FC<RecentProjectProps> = ({ projectPath, onLoadingSta
     const gradient = gradients[projectName.length % gradients.length];
     setThumbnail(gradient);
   }, [projectName]);
+
+  // Music-only projects show the album art of their first track
+  useEffect(() => {
+    let cancelled = false;
+    (async () => {
+      try {
+        const project = await invoke('single_read_project', { path: projectPath }) as ProjectFile;
+        const clips = Object.values(project.clips_map);
+        const audio = clips.find(clip => clip.type === 'Audio');
+        if (!audio || clips.some(clip => clip.type !== 'Audio')) return;
+        if (!cancelled) setIsMusic(true);
+        const metadata = await getAudioMetadata(audio.path);
+        if (!cancelled && metadata.art) setAlbumArt(metadata.art);
+      } catch {
+        // Missing or unreadable projects keep the plain tile
+      }
+    })();
+    return () => { cancelled = true; };
+  }, [projectPath]);
   
   const handleClick = async () => {
-    onLoadingStart(projectName);
+    onLoadingStart(projectName, projectPath);
     try {
       await loadProject(projectPath);
       await addRecentProject(projectPath);
@@ -531,9 +557,17 @@ const RecentProject: React.FC<RecentProjectProps> = ({ projectPath, onLoadingSta
         className="group cursor-pointer w-full"
       >
         <div className={`w-full aspect-video bg-gradient-to-br ${thumbnail} glass-thumbnail rounded-lg mb-2 flex items-center justify-center overflow-hidden relative`}>
-          <div className="relative z-20 w-10 h-10 glass-card backdrop-blur-2xl rounded-xl flex items-center justify-center group-hover:scale-110 transition-all duration-500">
-            <Film className="w-4 h-4 text-white/90 group-hover:text-white transition-colors duration-300 relative z-10" />
-          </div>
+          {albumArt ? (
+            <img src={albumArt} alt={projectName} className="absolute inset-0 w-full h-full object-cover" />
+          ) : (
+            <div className="relative z-20 w-10 h-10 glass-card backdrop-blur-2xl rounded-xl flex items-center justify-center group-hover:scale-110 transition-all duration-500">
+              {isMusic ? (
+                <Music className="w-4 h-4 text-white/90 group-hover:text-white transition-colors duration-300 relative z-10" />
+              ) : (
+                <Film className="w-4 h-4 text-white/90 group-hover:text-white transition-colors duration-300 relative z-10" />
+              )}
+            </div>
+          )}
         </div>
         
         <div className="px-1">
@@ -549,10 +583,52 @@ const RecentProject: React.FC<RecentProjectProps> = ({ projectPath, onLoadingSta
   );
 };
 
+const describeFallback = (fallback: DirFallback) =>
+  `- ${fallback.dir}: ${fallback.preferred ?? 'no default folder'} can't be used (${fallback.reason}); using ${fallback.chosen}`;
+
+const describePendingJob = (job: Job) => `- ${job.kind}: ${job.params}${job.note ? ` (${job.note})` : ''}`;
+
+const warnAboutWorkDirs = async () => {
+  try {
+    const fallbacks = await getWorkDirFallbacks();
+    if (fallbacks.length === 0) return;
+    alert(`Some folders couldn't be written, so Gebo is using others instead:\n\n${fallbacks.map(describeFallback).join('\n')}`);
+  } catch (error) {
+    console.error('Failed to check work directories:', error);
+  }
+};
+
+// Exports left queued or interrupted by the last session: resumed, or discarded if the user declines
+const offerPendingJobs = async () => {
+  try {
+    const pending = await getPendingJobs();
+    if (pending.length === 0) return;
+    const resume = confirm(`These exports didn't finish when Gebo last closed:\n\n${pending.map(describePendingJob).join('\n')}\n\nResume them now? Cancel discards them.`);
+    const failed: string[] = [];
+    for (const job of pending) {
+      try {
+        if (resume) await resumePendingJob(job.id);
+        else await discardPendingJob(job.id);
+      } catch (error) {
+        failed.push(`- ${job.kind}: ${error}`);
+      }
+    }
+    // Jobs that couldn't be resumed stay pending and are offered again next launch
+    if (failed.length > 0) alert(`Some exports couldn't be resumed:\n\n${failed.join('\n')}`);
+  } catch (error) {
+    console.error('Failed to load pending jobs:', error);
+  }
+};
+
+// Startup checks run once per launch, not every time the home page mounts
+let startupChecksDone = false;
+
 export default function Home() {
   const [modalOpen, setModalOpen] = useState(false);
   const [isLoading, setIsLoading] = useState(false);
   const [loadingProjectTitle, setLoadingProjectTitle] = useState<string | undefined>();
+  const [loadingProjectPath, setLoadingProjectPath] = useState<string | undefined>();
+  const [isRunningDiagnostics, setIsRunningDiagnostics] = useState(false);
   const [recentProjects, setRecentProjects] = useState<string[]>([]);
 
   useEffect(() => {
@@ -562,6 +638,21 @@ export default function Home() {
     return () => window.removeEventListener('focus', handleFocus);
   }, []);
 
+  // Asked for once the page has loaded: anything the backend emitted during startup was missed
+  useEffect(() => {
+    if (startupChecksDone) return;
+    startupChecksDone = true;
+    warnAboutWorkDirs().then(offerPendingJobs);
+  }, []);
+
+  // Quitting while jobs are running is held back by the backend until the user confirms
+  useEffect(() => {
+    const unlisten = getCurrentWindow().listen<CloseRequest>('close-requested', async (event) => {
+      if (confirmLosing(event.payload.blockers)) await confirmClose(true);
+    });
+    return () => { unlisten.then(fn => fn()); };
+  }, []);
+
   // Debug: Log when recentProjects state changes
   useEffect(() => {
     console.log('Recent projects state updated:', recentProjects);
@@ -605,6 +696,7 @@ export default function Home() {
 
     setIsLoading(true);
     setLoadingProjectTitle('Opening Project...');
+    setLoadingProjectPath(path);
 
     try {
       const projectFile = await loadProject(path);
@@ -618,9 +710,10 @@ export default function Home() {
     }
   };
 
-  const handleRecentProjectLoading = (title: string) => {
+  const handleRecentProjectLoading = (title: string, path: string) => {
     setIsLoading(true);
     setLoadingProjectTitle(title);
+    setLoadingProjectPath(path);
   };
 
   const createFlow = async (values: { [key: string]: string | number | boolean }) => {
@@ -635,6 +728,7 @@ export default function Home() {
     };
     setIsLoading(true);
     setLoadingProjectTitle(projectFile.title);
+    setLoadingProjectPath(path);
 
     try {
       await newProject(projectFile);
@@ -647,14 +741,28 @@ export default function Home() {
     }
   };
 
+  const runDiagnostics = async () => {
+    setIsRunningDiagnostics(true);
+    try {
+      const report = await runSelfTest();
+      // The full report goes on the clipboard so it can be pasted into a support ticket
+      await navigator.clipboard.writeText(JSON.stringify(report, null, 2)).catch(() => {});
+      alert(`${report.passed ? 'All checks passed' : 'Some checks failed'} (report copied to clipboard)\n\n${summarizeSelfTest(report)}`);
+    } catch (error) {
+      console.error('Failed to run diagnostics:', error);
+    }
+    setIsRunningDiagnostics(false);
+  };
+
   const handleLoadingComplete = async () => {
     try {
-      await openEditorWindow();
+      await openEditorWindow(loadingProjectPath);
     } catch (error) {
       console.error('Failed to open editor window:', error);
     }
     setIsLoading(false);
     setLoadingProjectTitle(undefined);
+    setLoadingProjectPath(undefined);
   };
 
   return (
@@ -691,6 +799,15 @@ export default function Home() {
                 <FolderOpen className="w-3.5 h-3.5 mr-1.5 relative z-10" />
                 <span className="relative z-10">Open project</span>
               </button>
+
+              <button 
+                onClick={runDiagnostics}
+                disabled={isRunningDiagnostics}
+                className="glass-3d-button px-4 py-2 text-white/90 font-medium text-sm relative disabled:opacity-50"
+              >
+                <Stethoscope className="w-3.5 h-3.5 mr-1.5 relative z-10" />
+                <span className="relative z-10">{isRunningDiagnostics ? 'Running diagnostics...' : 'Run diagnostics'}</span>
+              </button>
             </div>
           </div>
 
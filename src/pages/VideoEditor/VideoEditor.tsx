@@ -33,6 +33,9 @@ import { DeveloperOverlay } from "./components/DeveloperOverlay";
 import Modal from "../../components/Modal";
 import { ApiKeyManager } from "../../components/ApiKeyManager";
 import { getCurrentWindow } from '@tauri-apps/api/window';
+import { confirmClose, confirmLosing, type CloseRequest } from '../../lib/windowManager';
+import { saveProject } from '../../lib/projectFile';
+import { saveProjectPicker } from '../Home/utils/fileUtils';
 import { invoke } from '@tauri-apps/api/core';
 import { useProjectFile } from './hooks/useProjectFileManager';
 
@@ -129,6 +132,7 @@ export default function VideoEditor() {
   
   // Track current playback time to determine if we should show video or black screen
   const [currentPlaybackTime, setCurrentPlaybackTime] = useState(0);
+  const [selectedClipId, setSelectedClipId] = useState<string | null>(null);
   
   // Calculate actual content duration from clips (for export)
   const actualContentDuration = useMemo(() => {
@@ -220,6 +224,7 @@ export default function VideoEditor() {
   } = useTimelinePreview({
     clips,
     mediaFiles,
+    tracks,
     playerWidth: 1280,
     playerHeight: 720,
     debounceMs: 500, // Reduced from 1500ms to 500ms for faster response
@@ -517,8 +522,8 @@ export default function VideoEditor() {
     try {
       // For now, use the basic exportCutlist function
       // In a real implementation, you would modify FFmpeg parameters based on options
-      await exportCutlist(filePath, savePath, acceptedCuts);
-      log(`Exported to ${savePath} with options: ${JSON.stringify(options)}`);
+      const jobId = await exportCutlist(filePath, savePath, acceptedCuts);
+      log(`Exporting to ${savePath} (job ${jobId}) with options: ${JSON.stringify(options)}`);
     } catch (error) {
       console.error('Export failed:', error);
       throw error;
@@ -561,6 +566,27 @@ export default function VideoEditor() {
     await window.close();
   };
 
+  // Closing with unsaved work, undecided agent edits or running jobs is held back by the backend
+  // until the user decides what to do with them
+  useEffect(() => {
+    const unlisten = getCurrentWindow().listen<CloseRequest>('close-requested', async (event) => {
+      const blockers = event.payload.blockers;
+      if (blockers.some(b => b.code === 'unsaved_project') && window.confirm('This project has not been saved. Save it before closing?')) {
+        const path = await saveProjectPicker();
+        if (!path) return;
+        try {
+          await saveProject(path);
+        } catch (error) {
+          console.error('Failed to save project:', error);
+          return;
+        }
+      }
+      if (!confirmLosing(blockers.filter(b => b.code !== 'unsaved_project'))) return;
+      await confirmClose(true);
+    });
+    return () => { unlisten.then(fn => fn()); };
+  }, []);
+
   // Keyboard shortcuts
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
@@ -785,6 +811,11 @@ export default function VideoEditor() {
       acceptedCuts,
       previewCuts,
     },
+    playhead: currentPlaybackTime,
+    selection: inOutPoints.in !== null && inOutPoints.out !== null && inOutPoints.out > inOutPoints.in
+      ? { start: inOutPoints.in, end: inOutPoints.out }
+      : null,
+    selectedSegmentIds: selectedClipId ? [selectedClipId] : [],
     userIntent: "", // Will be set by the agent based on user input
     conversationHistory: [], // Could be populated from chat history if needed
   };
@@ -1252,6 +1283,7 @@ export default function VideoEditor() {
               onDeleteClip={deleteClip}
               onSplitClip={splitClip}
               onResizeClip={resizeClip}
+              onSelectedClipChange={setSelectedClipId}
               onUndo={undo}
               onRedo={redo}
               historyIndex={historyIndex}
@@ -30,6 +30,7 @@ interface AdvancedTimelineProps {
   onDeleteClip?: (clipId: string) => void;
   onSplitClip?: (clipId: string, splitTime: number) => void;
   onResizeClip?: (clipId: string, newStartTime: number, newEndTime: number) => void;
+  onSelectedClipChange?: (clipId: string | null) => void;
   // Undo/Redo props
   onUndo?: () => void;
   onRedo?: () => void;
@@ -47,7 +48,7 @@ export interface AdvancedTimelineHandle {
 }
 
 export const AdvancedTimeline = forwardRef<AdvancedTimelineHandle, AdvancedTimelineProps>(({
-  peaks, duration, accepted, preview, filePath, tracks, clips, mediaFiles, width, height, onSeek, onAddCut, onRemoveCut, onUpdateTrack, onMarkIn, onClearAllCuts, onDropMedia, onDeleteClip, onSplitClip, onResizeClip, onUndo, onRedo, historyIndex, editHistoryLength,
+  peaks, duration, accepted, preview, filePath, tracks, clips, mediaFiles, width, height, onSeek, onAddCut, onRemoveCut, onUpdateTrack, onMarkIn, onClearAllCuts, onDropMedia, onDeleteClip, onSplitClip, onResizeClip, onSelectedClipChange, onUndo, onRedo, historyIndex, editHistoryLength,
 }, ref) => {
   // Sort tracks by order to ensure proper positioning
   const sortedTracks = [...tracks].sort((a, b) => a.order - b.order);
@@ -1952,6 +1953,11 @@ export const AdvancedTimeline = forwardRef<AdvancedTimelineHandle, AdvancedTimel
     }
   }, [clips, selectedClipId]);
 
+  // Let the editor know what is selected (used as agent context)
+  useEffect(() => {
+    onSelectedClipChange?.(selectedClipId);
+  }, [selectedClipId, onSelectedClipChange]);
+
   // Note: Removed pan constraint to allow infinite panning
 
   // Effect to handle global mouse up for scrollbar interaction
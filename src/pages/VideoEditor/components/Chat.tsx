@@ -1,7 +1,9 @@
 import React, { useState, useRef, useEffect } from "react";
 import { Send } from "lucide-react";
 import { ChatMessage } from "./ChatMessage";
+import { listen } from "@tauri-apps/api/event";
 import { aiAgent } from "../../../lib/aiAgent";
+import { conformVfr, exportProject, prepareClip, stretchSegmentToFit, type Job } from "../../../lib/ffmpeg";
 import type { ChatMessage as ChatMessageType, Range, AgentContext, StreamingToken, AgentResponse, ThinkingStep, EditOperation } from "../../../types";
 
 interface ChatProps {
@@ -339,6 +341,111 @@ export function Chat({
     }
   };
 
+  // Run an agent-offered job and stream its progress into a status message
+  const runJobWithStatus = async (
+    name: string, // "Export", "Conform"
+    start: () => Promise<string>, // Resolves to the job id
+    target: string, // Appended to progress lines, e.g. " to `out.mp4`"
+    fallbackOutput?: string,
+  ) => {
+    const statusId = `${name.toLowerCase()}_${Date.now()}`;
+    const setStatus = (content: string, status: ChatMessageType["status"]) => {
+      const currentMessages = currentMessagesRef.current;
+      const existing = currentMessages.find(m => m.id === statusId);
+      const message: ChatMessageType = {
+        id: statusId,
+        type: "assistant",
+        content,
+        timestamp: existing?.timestamp ?? new Date(),
+        isStreaming: status === "streaming",
+        status,
+      };
+      onUpdateMessages(existing
+        ? currentMessages.map(m => m.id === statusId ? message : m)
+        : [...currentMessages, message]);
+    };
+
+    setStatus(`Starting ${name.toLowerCase()}${target}…`, "streaming");
+    let jobId: string | null = null;
+    const early: Job[] = []; // Updates that arrived before the job id was known
+    const unlisten = await listen<Job>("job-updated", (event) => {
+      const job = event.payload;
+      if (jobId === null) {
+        early.push(job);
+        return;
+      }
+      if (job.id === jobId) report(job);
+    });
+    const report = (job: Job) => {
+      const path = job.outputs[0] ?? fallbackOutput;
+      switch (job.status) {
+        case "Queued":
+          setStatus(`${name}${target} queued behind the running export…`, "streaming");
+          return;
+        case "Running":
+          setStatus(`${job.message ?? name}${target}… ${Math.round(job.progress * 100)}%`, "streaming");
+          return;
+        case "Paused":
+          setStatus(`${name}${target} paused at ${Math.round(job.progress * 100)}%`, "streaming");
+          return;
+        case "Completed":
+          setStatus(`${name} finished: \`${path}\``, "completed");
+          break;
+        case "Cancelled":
+          setStatus(`${name} cancelled.`, "completed");
+          break;
+        default:
+          setStatus(`${name} failed: ${job.error ?? "unknown error"}`, "error");
+      }
+      unlisten();
+    };
+
+    try {
+      jobId = await start();
+      const latest = early.filter(job => job.id === jobId).pop();
+      if (latest) report(latest);
+    } catch (error) {
+      unlisten();
+      setStatus(`${name} failed: ${typeof error === "string" ? error : String(error)}`, "error");
+    }
+  };
+
+  const handleExport = (output?: string) =>
+    runJobWithStatus("Export", () => exportProject(output), output ? ` to \`${output}\`` : "", output);
+
+  // Conformed copies replace the VFR clip on the timeline so the drift goes away
+  const handleConformVfr = (clipId: string) =>
+    runJobWithStatus("Conform", () => conformVfr(clipId, true), "");
+
+  // The stretched voice replaces the segment's audio; unbaking the segment restores it
+  const handleStretchToFit = (trackId: string, segmentId: string, targetDuration: number) =>
+    runJobWithStatus("Stretch", () => stretchSegmentToFit(trackId, segmentId, targetDuration), ` to ${targetDuration.toFixed(1)}s`);
+
+  // Prepared clips have a transcript the agent can answer from when asked again
+  const handlePrepareClip = (clipId: string) =>
+    runJobWithStatus("Prepare", () => prepareClip(clipId), "");
+
+  // Edits the agent held back because they cut a large share of the project replace the question
+  const handleConfirmProceed = async (messageId: string) => {
+    try {
+      const response = await aiAgent.confirmProceed(messageId);
+      onUpdateMessages(currentMessagesRef.current.map((m: ChatMessageType) => m.messageId !== messageId ? m : {
+        ...m,
+        content: response.content,
+        finalEdits: response.finalEdits,
+        needsReview: response.needsReview,
+        hasVideoPreview: response.hasVideoPreview,
+        videoPreview: response.videoPreview,
+        actions: response.actions,
+      }));
+      if (response.finalEdits.length > 0 && onApplyEditOperations) {
+        onApplyEditOperations(response.finalEdits);
+      }
+    } catch (error) {
+      console.error("Failed to load the held-back edits:", error);
+    }
+  };
+
   const handleKeyDown = (e: React.KeyboardEvent) => {
     if (e.key === "Enter" && !e.shiftKey) {
       e.preventDefault();
@@ -463,6 +570,11 @@ export function Chat({
               onUploadMedia={onUploadMedia}
               onAcceptPlan={onAcceptPlan}
               onRejectPlan={onRejectPlan}
+              onExport={handleExport}
+              onConformVfr={handleConformVfr}
+              onStretchToFit={handleStretchToFit}
+              onPrepareClip={handlePrepareClip}
+              onConfirmProceed={handleConfirmProceed}
             />
           </div>
         ))}
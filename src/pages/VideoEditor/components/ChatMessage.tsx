@@ -1,6 +1,7 @@
 import { useState, useRef, useEffect } from "react";
 import { Check, X, Circle, Play, Scissors, Plus, Trash2, SlidersHorizontal, Type, Music } from "lucide-react";
 import { Player } from "./Player";
+import { recordAgentDecision } from "../../../lib/aiAgent";
 import type { ChatMessage as ChatMessageType, ThinkingStep, EditOperation } from "../../../types";
 
 interface ChatMessageProps {
@@ -8,9 +9,14 @@ interface ChatMessageProps {
   onUploadMedia?: () => Promise<any>;
   onAcceptPlan?: () => void;
   onRejectPlan?: () => void;
+  onExport?: (output?: string) => void;
+  onConformVfr?: (clipId: string) => void;
+  onStretchToFit?: (trackId: string, segmentId: string, targetDuration: number) => void;
+  onPrepareClip?: (clipId: string) => void;
+  onConfirmProceed?: (messageId: string) => void;
 }
 
-export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan }: ChatMessageProps) {
+export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan, onExport, onConformVfr, onStretchToFit, onPrepareClip, onConfirmProceed }: ChatMessageProps) {
   const [hasAutoPlayed, setHasAutoPlayed] = useState(false);
   const playerRef = useRef<any>(null);
 
@@ -85,7 +91,7 @@ export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan
     );
   };
 
-  const renderEditOperations = (edits: EditOperation[]) => {
+  const renderEditOperations = (edits: EditOperation[], title = "Proposed Edit Operations") => {
     if (!edits || edits.length === 0) return null;
 
     const getIcon = (type: EditOperation['type']) => {
@@ -114,7 +120,7 @@ export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan
         <div className="absolute inset-0 bg-gradient-to-br from-white/5 to-transparent pointer-events-none"></div>
         <h4 className="text-sm font-semibold text-editor-text-primary mb-4 flex items-center gap-2 relative z-10">
           <Scissors className="w-4 h-4 text-editor-status-warning" />
-          Proposed Edit Operations
+          {title}
         </h4>
         <div className="space-y-3 relative z-10">
           {edits.map((edit) => (
@@ -132,6 +138,16 @@ export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan
                     Time: {edit.timeRange.start.toFixed(2)}s - {edit.timeRange.end.toFixed(2)}s
                   </p>
                 )}
+                {edit.rationale && (
+                  <p className="text-xs text-editor-text-secondary mt-1">
+                    Why: {edit.rationale}
+                  </p>
+                )}
+                {edit.confidence !== undefined && (
+                  <p className="text-xs text-editor-text-muted mt-1">
+                    Confidence: {Math.round(edit.confidence * 100)}%
+                  </p>
+                )}
                 {edit.targetClipId && (
                   <p className="text-xs text-editor-text-secondary mt-1">
                     Target Clip: {edit.targetClipId}
@@ -163,6 +179,12 @@ export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan
         {message.actions.map((action, index) => {
           // Determine the actual onClick handler based on action type
           const handleClick = () => {
+            if ((action.type === 'accept' || action.type === 'reject') && message.messageId) {
+              recordAgentDecision(message.messageId, action.type === 'accept').catch(error => {
+                console.warn("Failed to record agent decision:", error);
+              });
+            }
+
             // Try the action's onClick first (for legacy support)
             if (action.onClick && typeof action.onClick === 'function') {
               action.onClick();
@@ -185,9 +207,27 @@ export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan
                   });
                 }
                 break;
+              case 'export':
+                if (onExport) onExport(action.output);
+                break;
+              case 'conform_vfr':
+                if (onConformVfr && action.clipId) onConformVfr(action.clipId);
+                break;
+              case 'stretch_to_fit':
+                if (onStretchToFit && action.stretch) {
+                  onStretchToFit(action.stretch.trackId, action.stretch.segmentId, action.stretch.targetDuration);
+                }
+                break;
+              case 'prepare_clip':
+                if (onPrepareClip && action.clipId) onPrepareClip(action.clipId);
+                break;
               case 'confirm_proceed':
-                // For confirm_proceed, we don't need a handler - user should type their response in chat
-                console.log('User should respond yes/no in chat for confirmation');
+                // Agent proposals held back by the cut guard carry a message id; others are answered in chat
+                if (onConfirmProceed && message.messageId) {
+                  onConfirmProceed(message.messageId);
+                } else {
+                  console.log('User should respond yes/no in chat for confirmation');
+                }
                 break;
               case 'custom':
                 // Check if the label suggests an upload action
@@ -313,6 +353,7 @@ export function ChatMessage({ message, onUploadMedia, onAcceptPlan, onRejectPlan
 
         {/* Edit Operations */}
         {message.finalEdits && message.finalEdits.length > 0 && renderEditOperations(message.finalEdits)}
+        {message.needsReview && message.needsReview.length > 0 && renderEditOperations(message.needsReview, "Needs Review (not applied by Accept All)")}
         
         {/* Agent Actions - Show what the agent is doing */}
         {message.hasVideoPreview && message.videoPreview && (
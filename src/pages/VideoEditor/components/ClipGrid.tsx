@@ -2,6 +2,7 @@ import { useCallback, useState, useMemo, useEffect } from "react";
 import { Plus, Video, Music, X, Image } from "lucide-react";
 import type { Clip } from "../../../lib/projectFile";
 import { useFileHandling } from "../hooks/useFileHandling";
+import { getAudioMetadata, type AudioMetadata } from "../../../lib/ffmpeg";
 
 interface ClipGridProps {
   clips: Clip[];
@@ -19,6 +20,27 @@ export function ClipGrid({ clips, onAddClips, onRemoveClip, onDragStart }: ClipG
   // Store thumbnails for clips (simple cache)
   const [clipThumbnails, setClipThumbnails] = useState<Map<string, string>>(new Map());
   const [thumbnailsBeingGenerated, setThumbnailsBeingGenerated] = useState<Set<string>>(new Set());
+  const [audioMetadata, setAudioMetadata] = useState<Map<string, AudioMetadata>>(new Map());
+
+  // Album art and tags for music clips; art is cached by the backend
+  useEffect(() => {
+    const audioClips = clips.filter(clip => clip.type === 'Audio' && !audioMetadata.has(clip.id));
+    if (audioClips.length === 0) return;
+
+    (async () => {
+      for (const clip of audioClips) {
+        try {
+          const metadata = await getAudioMetadata(clip.path);
+          setAudioMetadata(prev => new Map(prev).set(clip.id, metadata));
+          if (metadata.art) {
+            setClipThumbnails(prev => new Map(prev).set(clip.id, metadata.art));
+          }
+        } catch (error) {
+          console.error("Failed to read audio metadata for:", clip.path, error);
+        }
+      }
+    })();
+  }, [clips.map(c => c.id).join(',')]);
 
   // Generate thumbnails for clips that don't have them yet
   useEffect(() => {
@@ -121,8 +143,12 @@ export function ClipGrid({ clips, onAddClips, onRemoveClip, onDragStart }: ClipG
   }, []);
 
   const getClipDisplayName = useCallback((clip: Clip) => {
+    const metadata = audioMetadata.get(clip.id);
+    if (metadata?.title) {
+      return metadata.artist ? `${metadata.artist} – ${metadata.title}` : metadata.title;
+    }
     return clip.path.split(/[/\\]/).pop() || clip.path;
-  }, []);
+  }, [audioMetadata]);
 
   // Memoize the entire clips grid to prevent unnecessary re-renders
   const clipsGrid = useMemo(() => {
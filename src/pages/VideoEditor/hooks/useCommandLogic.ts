@@ -1,5 +1,6 @@
 import { useState } from "react";
 import type { Range } from "../types";
+import type { TightenPadding } from "./useWaveformLogic";
 
 export function useCommandLogic(probe: any, log: (m: string) => void) {
   const [showCommandDialog, setShowCommandDialog] = useState(false);
@@ -15,21 +16,31 @@ export function useCommandLogic(probe: any, log: (m: string) => void) {
     setCommandInput: (value: string) => void,
     setShowCommandDialog: (show: boolean) => void,
     setPreviewCuts: (cuts: Range[]) => void,
-    tightenSilences: (min: number, leave?: number) => Range[],
+    tightenSilences: (min: number, padding?: TightenPadding) => Promise<Range[]>,
     detectSilences: (min: number) => Range[]
   ) => {
     const q = commandInput.trim().toLowerCase();
     setShowCommandDialog(false);
     if (!q || !probe) return;
 
-    // tighten silence > 2 leave 150ms
-    const t = q.match(/tighten\s+silence(?:s)?\s*[>=>]\s*(\d+(?:\.\d+)?)\s*(?:leave\s*(\d+)\s*ms)?/i);
+    // tighten silence > 2 leave 150ms [lead 50ms] [min 3s]
+    const t = q.match(/tighten\s+silence(?:s)?\s*[>=>]\s*(\d+(?:\.\d+)?)/i);
     if (t) {
       const min = parseFloat(t[1]);
-      const leave = t[2] ? parseInt(t[2], 10) : 150;
-      const ranges = tightenSilences(min, leave);
-      setPreviewCuts(ranges);
-      log(`Preview: tighten silence > ${min}s leave ${leave}ms → ${ranges.length} cuts`);
+      const ms = (keyword: string) => {
+        const m = q.match(new RegExp(`${keyword}\\D{0,20}?(\\d+(?:\\.\\d+)?)\\s*(ms|s)\\b`, "i"));
+        return m ? parseFloat(m[1]) * (m[2] === "s" ? 1000 : 1) : undefined;
+      };
+      const leave = ms("leave") ?? ms("keep") ?? 150;
+      const lead = ms("lead") ?? 0;
+      const minSilenceMs = ms("min");
+      const minSilence = minSilenceMs !== undefined ? minSilenceMs / 1000 : undefined;
+      tightenSilences(min, { leaveMs: leave, leadMs: lead, minSilence })
+        .then(ranges => {
+          setPreviewCuts(ranges);
+          log(`Preview: tighten silence > ${min}s leave ${leave}ms lead ${lead}ms → ${ranges.length} cuts`);
+        })
+        .catch(error => log(`Tighten failed: ${error}`));
       return;
     }
 
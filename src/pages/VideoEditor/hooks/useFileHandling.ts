@@ -1,7 +1,7 @@
 import { useState } from "react";
 import { open } from "@tauri-apps/plugin-dialog";
 import { convertFileSrc } from "@tauri-apps/api/core";
-import { audioPeaks, makePreviewProxy, probeVideo, readFileChunk, getFileSize, generateThumbnails, extractAlbumArt, type Probe } from "../../../lib/ffmpeg";
+import { audioPeaks, isNoAudioStream, makePreviewProxy, probeVideo, readFileChunk, getFileSize, generateThumbnails, extractAlbumArt, type Probe } from "../../../lib/ffmpeg";
 import { transcriptionService } from "../../../lib/transcription";
 import { videoAnalysisService } from "../../../lib/videoAnalysis";
 import type { MediaFile } from "../../../types";
@@ -94,8 +94,12 @@ export function useFileHandling() {
         setPeaks(pk.map(v => Math.max(0, Math.min(32767, v))));
         log(`Peaks: ${pk.length}`);
       } catch (e: any) {
-        log(`Audio peaks failed: ${e?.toString?.() || e}`);
-        log(`Peaks error details: ${JSON.stringify(e)}`);
+        if (isNoAudioStream(e)) {
+          log(`No audio stream in ${sel}`);
+        } else {
+          log(`Audio peaks failed: ${e?.toString?.() || e}`);
+          log(`Peaks error details: ${JSON.stringify(e)}`);
+        }
         setPeaks([]);
       }
 
@@ -104,8 +108,9 @@ export function useFileHandling() {
         try {
           log(`Creating preview proxy for: ${sel}`);
           const prox = await makePreviewProxy(sel);
+          if (prox.encoder?.fell_back) log(`Hardware encoder failed, proxy was made with ${prox.encoder.encoder}`);
           log(`Proxy created, creating blob URL for proxy...`);
-          const proxyBlobUrl = await createBlobFromFile(prox);
+          const proxyBlobUrl = await createBlobFromFile(prox.path);
           setPreviewUrl(proxyBlobUrl);
           log(`Using preview proxy blob URL (H.264/AAC): ${proxyBlobUrl}`);
         } catch (e: any) {
@@ -300,7 +305,11 @@ export function useFileHandling() {
         peaks = audioPeaksData.map(v => Math.max(0, Math.min(32767, v)));
         log(`Audio peaks for ${filePath}: ${peaks.length}`);
       } catch (e: any) {
-        log(`Audio peaks failed for ${filePath}: ${e?.toString?.() || e}`);
+        if (isNoAudioStream(e)) {
+          log(`No audio stream in ${filePath}`);
+        } else {
+          log(`Audio peaks failed for ${filePath}: ${e?.toString?.() || e}`);
+        }
       }
 
       // Generate thumbnails (single for preview, multiple for filmstrip)
@@ -326,17 +335,9 @@ export function useFileHandling() {
           // Audio file - try to extract album art
           log(`Attempting to extract album art for ${filePath}...`);
           try {
-            const albumArtBase64 = await extractAlbumArt(filePath);
-            if (albumArtBase64) {
-              // Convert base64 to blob URL for preview
-              const byteCharacters = atob(albumArtBase64);
-              const byteNumbers = new Array(byteCharacters.length);
-              for (let i = 0; i < byteCharacters.length; i++) {
-                byteNumbers[i] = byteCharacters.charCodeAt(i);
-              }
-              const byteArray = new Uint8Array(byteNumbers);
-              const blob = new Blob([byteArray], { type: 'image/png' });
-              thumbnailUrl = URL.createObjectURL(blob);
+            const albumArtUrl = await extractAlbumArt(filePath);
+            if (albumArtUrl) {
+              thumbnailUrl = albumArtUrl;
               log(`✅ Album art extracted for ${filePath}`);
             } else {
               log(`No album art found for ${filePath}`);
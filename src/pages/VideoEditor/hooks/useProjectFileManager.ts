@@ -1,5 +1,5 @@
 import { useState, useEffect } from 'react';
-import { type ProjectFile, type Track, type Clip, getProject, updateProject, type Segment } from '../../../lib/projectFile';
+import { type ProjectFile, type Track, type Clip, getProject, updateProject, onFilesImported, onProjectActivated, type Segment } from '../../../lib/projectFile';
 import { probeVideo, type Probe } from '../../../lib/ffmpeg';
 
 export class ProjectFileManager {
@@ -75,6 +75,17 @@ export class ProjectFileManager {
     async initialize(): Promise<void> {
         // Fetch project from rust backend on init
         await this.refetchProject();
+        // Dropped files are imported in the backend; a dropped project opens in an editor of its own
+        await onFilesImported((result) => {
+            result.files
+                .filter(file => file.outcome === "Failed")
+                .forEach(file => console.warn(`Dropped file not imported: ${file.path}: ${file.error}`));
+            if (!result.files.some(file => file.outcome === "ProjectOpened")) {
+                this.refetchProject();
+            }
+        });
+        // Focusing this editor loads its project again when another editor's was in use
+        await onProjectActivated(() => this.refetchProject());
     }
 
     async setProject(project: ProjectFile | null): Promise<void> {
@@ -1,9 +1,11 @@
 import { useState, useEffect, useRef, useCallback, type RefObject } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
-import type { Clip, MediaFile } from '../../../types';
+import type { Clip, MediaFile, Track } from '../../../types';
 import type { ExtendedStreamingPlayerHandle } from '../../../components/StreamingVideoPlayer';
 
+
+const NO_TRACKS: Track[] = [];
 interface StreamingPreviewState {
   isStreaming: boolean;
   error: string | null;
@@ -15,6 +17,7 @@ interface StreamingPreviewState {
 interface UseStreamingPreviewOptions {
   clips: Clip[];
   mediaFiles: MediaFile[];
+  tracks?: Track[]; // For per-track audio pan
   playerRef: RefObject<ExtendedStreamingPlayerHandle | null>;
   enabled?: boolean;
 }
@@ -24,6 +27,7 @@ interface StreamingSegment {
   start_time: number;
   end_time: number;
   timeline_offset: number;
+  pan: number;
 }
 
 /**
@@ -33,6 +37,7 @@ interface StreamingSegment {
 export function useStreamingPreview({
   clips,
   mediaFiles,
+  tracks = NO_TRACKS,
   playerRef,
   enabled = true,
 }: UseStreamingPreviewOptions) {
@@ -48,11 +53,15 @@ export function useStreamingPreview({
   const unlistenChunkRef = useRef<(() => void) | null>(null);
   const unlistenCompleteRef = useRef<(() => void) | null>(null);
   const unlistenErrorRef = useRef<(() => void) | null>(null);
+  const streamIdRef = useRef<string | null>(null);
+  // Read through a ref so the memoized converters below see the latest pans
+  const tracksRef = useRef(tracks);
+  tracksRef.current = tracks;
 
   // Generate hash for clips to detect changes
   const generateClipsHash = useCallback((clips: Clip[]): string => {
     return clips
-      .map(c => `${c.mediaFileId}_${c.startTime}_${c.endTime}_${c.offset}`)
+      .map(c => `${c.mediaFileId}_${c.startTime}_${c.endTime}_${c.offset}_${tracksRef.current.find(t => t.id === c.trackId)?.pan ?? 0}`)
       .join('|');
   }, []);
 
@@ -70,6 +79,7 @@ export function useStreamingPreview({
           start_time: clip.startTime,
           end_time: clip.endTime,
           timeline_offset: clip.offset,
+          pan: tracksRef.current.find(t => t.id === clip.trackId)?.pan ?? 0,
         };
       })
       .sort((a, b) => a.timeline_offset - b.timeline_offset);
@@ -232,7 +242,7 @@ export function useStreamingPreview({
       unlistenErrorRef.current = unlistenError;
 
       // Start streaming from backend
-      await invoke('start_streaming_preview', {
+      streamIdRef.current = await invoke<string>('start_streaming_preview', {
         clips: segments,
         width: 1280,
       });
@@ -276,6 +286,28 @@ export function useStreamingPreview({
     return () => clearTimeout(timer);
   }, [clips, mediaFiles, enabled, generateClipsHash, playerRef]);
 
+  // Report the playhead so the backend prefetches the segments about to play
+  useEffect(() => {
+    if (!enabled) return;
+    const timer = setInterval(async () => {
+      const streamId = streamIdRef.current;
+      const player = playerRef.current;
+      if (!streamId || !player) return;
+      try {
+        const open = await invoke<boolean>('set_playhead_hint', {
+          streamId,
+          time: player.currentTime(),
+          playing: player.isPlaying(),
+        });
+        // Stream finished encoding; nothing left to prefetch
+        if (!open && streamIdRef.current === streamId) streamIdRef.current = null;
+      } catch (error) {
+        console.warn('Failed to send playhead hint:', error);
+      }
+    }, 500);
+    return () => clearInterval(timer);
+  }, [enabled, playerRef]);
+
   // Cleanup listeners on unmount
   useEffect(() => {
     return () => {
@@ -1,8 +1,10 @@
 import { useState, useEffect, useRef, useCallback } from 'react';
 import { readFileChunk, getFileSize } from '../../../lib/ffmpeg';
 import { generateAdaptiveTimelinePreview, type TimelineClip } from '../../../lib/ffmpeg';
-import type { Clip, MediaFile } from '../../../types';
+import type { Clip, MediaFile, Track } from '../../../types';
 
+
+const NO_TRACKS: Track[] = [];
 interface PreviewState {
   previewUrl: string | null;
   isGenerating: boolean;
@@ -13,6 +15,7 @@ interface PreviewState {
 interface UseTimelinePreviewOptions {
   clips: Clip[];
   mediaFiles: MediaFile[];
+  tracks?: Track[]; // For per-track audio pan
   playerWidth?: number;
   playerHeight?: number;
   debounceMs?: number;
@@ -26,6 +29,7 @@ interface UseTimelinePreviewOptions {
 export function useTimelinePreview({
   clips,
   mediaFiles,
+  tracks = NO_TRACKS,
   playerWidth = 1280,
   playerHeight = 720,
   debounceMs = 500,
@@ -41,13 +45,16 @@ export function useTimelinePreview({
   const generationTimeoutRef = useRef<NodeJS.Timeout | null>(null);
   const currentGenerationRef = useRef<string | null>(null);
   const cacheRef = useRef<Map<string, string>>(new Map());
+  // Read through a ref so the memoized converters below see the latest pans
+  const tracksRef = useRef(tracks);
+  tracksRef.current = tracks;
 
   // Generate a cache key from the current timeline state
   const generateCacheKey = useCallback((clips: Clip[]): string => {
     if (clips.length === 0) return 'empty';
     
     return clips
-      .map(c => `${c.mediaFileId}_${c.startTime}_${c.endTime}_${c.offset}`)
+      .map(c => `${c.mediaFileId}_${c.startTime}_${c.endTime}_${c.offset}_${tracksRef.current.find(t => t.id === c.trackId)?.pan ?? 0}`)
       .sort()
       .join('|');
   }, []);
@@ -76,6 +83,7 @@ export function useTimelinePreview({
           start_time: clip.startTime,
           end_time: clip.endTime,
           offset: clip.offset,
+          pan: tracksRef.current.find(t => t.id === clip.trackId)?.pan ?? 0,
         };
       })
       .sort((a, b) => a.offset - b.offset);
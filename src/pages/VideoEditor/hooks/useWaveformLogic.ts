@@ -1,5 +1,13 @@
+import { invoke } from "@tauri-apps/api/core";
 import type { Range } from "../../../types";
 
+// Silence kept around speech when tightening; see intervals::TightenSettings
+export type TightenPadding = {
+  leaveMs?: number; // Kept after speech stops (default 150)
+  leadMs?: number; // Kept before speech resumes (default 0)
+  minSilence?: number; // Seconds; shorter silences are left alone (default: the detection minimum)
+};
+
 export function useWaveformLogic(probe: any, peaks: number[]) {
   const mergeRanges = (rs: Range[], eps = 0.01) => {
     if (!rs.length) return rs;
@@ -35,14 +43,18 @@ export function useWaveformLogic(probe: any, peaks: number[]) {
     return mergeRanges(out);
   };
 
-  const tightenSilences = (minSeconds: number, leaveMs = 150): Range[] => {
+  // Cut math lives in the backend (intervals::tighten) so the agent and this command agree
+  const tightenSilences = async (minSeconds: number, padding: TightenPadding = {}): Promise<Range[]> => {
     const spans = detectSilences(minSeconds);
-    const pad = leaveMs / 1000;
-    const cuts = spans.map(r => ({
-      start: Math.min(r.end - pad, r.start + pad),
-      end: Math.max(r.start + pad, r.end - pad),
-    })).filter(c => c.end - c.start > 0.001);
-    return mergeRanges(cuts);
+    const cuts = await invoke("tighten_silences", {
+      silences: spans.map(r => [r.start, r.end]),
+      settings: {
+        pad_after_speech: (padding.leaveMs ?? 150) / 1000,
+        pad_before_speech: (padding.leadMs ?? 0) / 1000,
+        min_silence: padding.minSilence ?? minSeconds,
+      },
+    }) as [number, number][];
+    return mergeRanges(cuts.map(([start, end]) => ({ start, end })));
   };
 
   return {
@@ -54,12 +54,17 @@ export type ChatMessage = {
   isStreaming?: boolean;
   thinkingSteps?: ThinkingStep[];
   finalEdits?: EditOperation[];
+  needsReview?: EditOperation[];
   status?: "thinking" | "streaming" | "completed" | "error";
+  messageId?: string; // Backend id of the agent response, set on completion
 };
 
 export type ChatAction = {
-  type: "accept" | "reject" | "custom" | "upload_video" | "upload_media" | "confirm_proceed";
+  type: "accept" | "reject" | "custom" | "upload_video" | "upload_media" | "confirm_proceed" | "export" | "conform_vfr" | "stretch_to_fit" | "prepare_clip";
   label: string;
+  output?: string; // Target file of an "export" action
+  clipId?: string; // Clip of a "conform_vfr" or "prepare_clip" action
+  stretch?: { trackId: string; segmentId: string; targetDuration: number }; // Segment of a "stretch_to_fit" action
   onClick?: () => void;
 };
 
@@ -146,6 +151,7 @@ export type Track = {
   enabled: boolean;
   muted: boolean;
   volume: number;
+  pan?: number; // -100 (left) to 100 (right)
   order: number;
 };
 
@@ -187,6 +193,8 @@ export type EditOperation = {
   targetTrackId?: string;
   timeRange?: Range;
   previewData?: any;
+  confidence?: number; // 0..1
+  rationale?: string;
 };
 
 export type AgentResponse = {
@@ -194,6 +202,7 @@ export type AgentResponse = {
   content: string;
   thinkingSteps: ThinkingStep[];
   finalEdits: EditOperation[];
+  needsReview: EditOperation[]; // Below the review threshold; not applied by accept-all
   hasVideoPreview: boolean;
   videoPreview?: {
     src: string;
@@ -219,6 +228,10 @@ export type AgentContext = {
     acceptedCuts: Range[];
     previewCuts: Range[];
   };
+  // Editor state so "this", "here" and "the selected clip" can be resolved
+  playhead?: number;
+  selection?: Range | null;
+  selectedSegmentIds?: string[];
   userIntent: string;
   conversationHistory: ChatMessage[];
 };
\ No newline at end of file